@@ -1,6 +1,14 @@
+use std::{fmt::Display, path::PathBuf};
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::{
+    delta::Signatures,
+    error::ErrorLocation,
+    node::{Facts, ResourceState},
+};
+
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ActionId(Uuid);
 
@@ -16,9 +24,10 @@ pub fn new() -> Self {
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize, strum_macros::IntoStaticStr)]
 pub enum ActionMessage {
     NodeStartFailed {
+        kind: NodeStartFailedKind,
         reason: String,
     },
     ActionStarted {
@@ -33,9 +42,106 @@ pub enum ActionMessage {
         id: ActionId,
         success: bool,
     },
+    /// The action didn't run because of the actions before it,
+    /// e.g. an action failed, or nothing failed for a rescue action
+    ActionSkipped {
+        id: ActionId,
+    },
+    /// The change the action would make in a dry run,
+    /// or None if nothing would change
+    ActionDryRun {
+        id: ActionId,
+        change: Option<String>,
+    },
+    /// How many bytes of the action were sent to the node out of the total,
+    /// which is only sent for the large actions, e.g. copying large files
+    ActionTransfer {
+        id: ActionId,
+        sent: u64,
+        total: u64,
+    },
+    /// The structured result of the action as JSON, e.g. the state of the path
+    /// from `stat`, which is kept with the outcome of the action in the run history
+    ActionValue {
+        id: ActionId,
+        value: String,
+    },
+    /// A chunk of the file that a `fetch` action reads on the host, which tiron
+    /// writes to the local file until the result of the action
+    ActionFetch {
+        id: ActionId,
+        data: Vec<u8>,
+    },
     NodeShutdown {
         success: bool,
     },
+    /// The facts of the host, which is the first message from the node
+    NodeFacts {
+        facts: Facts,
+    },
+    /// The result of locking the host, with the error if it can't be locked,
+    /// e.g. it's locked by someone else
+    NodeLocked {
+        error: Option<String>,
+    },
+    /// The signatures of the files that were asked for,
+    /// with None for the files that can't be read
+    NodeSignatures {
+        signatures: Vec<Option<Signatures>>,
+    },
+    /// Whether each of the artifacts that were asked for is in the cache of the node
+    NodeCached {
+        cached: Vec<bool>,
+    },
+    /// The states of the resources that were asked for, in the same order
+    NodeFingerprint {
+        states: Vec<ResourceState>,
+    },
+    /// The action failed in the debug strategy of the run, and the node waits
+    /// for `NodeMessage::Debug` to know what to do with it
+    ActionDebug {
+        id: ActionId,
+    },
+    /// The node panicked, with the action it was running, which is the last message
+    /// from the node before it exits
+    NodePanicked {
+        id: Option<ActionId>,
+        message: String,
+        backtrace: String,
+    },
+}
+
+/// NodeStartFailedKind is the category of the reason why the node
+/// couldn't be started on the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum NodeStartFailedKind {
+    DnsResolution,
+    Connection,
+    Timeout,
+    HostKey,
+    Authentication,
+    UnsupportedPlatform,
+    NodeDownload,
+    VersionMismatch,
+    Locked,
+    Other,
+}
+
+impl Display for NodeStartFailedKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeStartFailedKind::DnsResolution => f.write_str("dns resolution"),
+            NodeStartFailedKind::Connection => f.write_str("connection"),
+            NodeStartFailedKind::Timeout => f.write_str("timeout"),
+            NodeStartFailedKind::HostKey => f.write_str("host key"),
+            NodeStartFailedKind::Authentication => f.write_str("authentication"),
+            NodeStartFailedKind::UnsupportedPlatform => f.write_str("unsupported platform"),
+            NodeStartFailedKind::NodeDownload => f.write_str("node download"),
+            NodeStartFailedKind::VersionMismatch => f.write_str("version mismatch"),
+            NodeStartFailedKind::Locked => f.write_str("locked"),
+            NodeStartFailedKind::Other => f.write_str("other"),
+        }
+    }
 }
 
 /// ActionData is the data that's being sent from core to node
@@ -46,8 +152,96 @@ pub struct ActionData {
     pub name: String,
     pub action: String,
     pub input: Vec<u8>,
+    // only check what the action would change without changing anything
+    pub dry_run: bool,
+    // limit how many hosts can run the action at the same time
+    pub throttle: Option<ActionThrottle>,
+    // the blocks the action is in, from the outermost
+    pub scope: Vec<ActionScope>,
+    // how to undo the action when the run fails, if the run rolls back on failure
+    pub rollback: Option<Rollback>,
+    // ask what to do when the action fails, with the debug strategy of the run
+    pub debug: bool,
+    // the input is the same as the last time the action succeeded on the host,
+    // so the node skips it when the run skips the unchanged actions
+    pub unchanged: bool,
+    // the params as they're written in the runbook, which are only for showing
+    // the plan, so they aren't sent to the node
+    #[serde(skip)]
+    pub params: Vec<(String, String)>,
+    // the directory of the runbook the action is written in, which the params
+    // are relative to when they're edited in the debug strategy
+    #[serde(skip)]
+    pub cwd: PathBuf,
+    // where the action block is in the runbook, for pointing at it when the action
+    // fails on the node
+    #[serde(skip)]
+    pub location: Option<ErrorLocation>,
+}
+
+/// What the node does with an action that failed in the debug strategy of the run
+#[derive(Clone, Deserialize, Serialize, strum_macros::IntoStaticStr)]
+pub enum DebugChoice {
+    Retry,
+    // retry the action with the input from the edited params
+    RetryWith(Vec<u8>),
+    // go on as if the action didn't fail
+    Skip,
+    Fail,
+}
+
+/// Rollback is how an action is undone
+#[derive(Clone, Deserialize, Serialize)]
+pub enum Rollback {
+    // the node works out the rollback right before it executes the action,
+    // if the action supports it
+    Auto,
+    // the rollback declared for the action
+    Action(ActionRollback),
+}
+
+/// ActionRollback is the action that undoes another action
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ActionRollback {
+    pub action: String,
+    pub input: Vec<u8>,
+}
+
+/// ActionScope is a block of actions that an action is in,
+/// and the section of the block the action is in
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ActionScope {
+    pub block: Uuid,
+    pub section: BlockSection,
 }
 
+/// BlockSection is a section of a block of actions
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub enum BlockSection {
+    // the actions which run until one of them fails
+    Main,
+    // the actions which run when an action in the main section fails
+    Rescue,
+    // the actions which run regardless
+    Always,
+}
+
+/// ActionThrottle limits how many hosts can run an action at the same time
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ActionThrottle {
+    // the same action on different hosts has the same key
+    pub key: String,
+    pub limit: usize,
+}
+
+/// How many messages from a node can wait for tiron to handle them, after which
+/// the node pauses until tiron catches up, so that a flood of output doesn't
+/// take up the memory of tiron
+pub const MESSAGE_BUFFER: usize = 1024;
+
+/// How many output lines of an action are kept, after which the oldest are pruned
+const MAX_OUTPUT_LINES: usize = 10_000;
+
 /// ActionOutput is the output that's returned from the node
 /// from executing the action
 #[derive(Clone, Deserialize, Serialize, Default)]
@@ -57,6 +251,54 @@ pub struct ActionOutput {
     // whether this action was succesfully or not
     // the action isn't completed if this is None
     pub success: Option<bool>,
+    // how many of the oldest lines have been pruned
+    #[serde(default)]
+    pub pruned: usize,
+}
+
+impl ActionOutput {
+    /// Add the line, and prune the oldest lines if there are too many,
+    /// with a line in their place that tells how many are gone
+    pub fn push_line(&mut self, line: ActionOutputLine) {
+        self.lines.push(line);
+        if self.lines.len() <= MAX_OUTPUT_LINES {
+            return;
+        }
+        // the lines are pruned in chunks, so that they aren't moved for every line
+        let chunk = MAX_OUTPUT_LINES / 10;
+        let start = usize::from(self.pruned > 0);
+        self.lines.drain(start..start + chunk);
+        self.pruned += chunk;
+        let line = ActionOutputLine {
+            content: format!("... {} earlier lines were pruned", self.pruned),
+            level: ActionOutputLevel::Warn,
+        };
+        if start == 0 {
+            self.lines.insert(0, line);
+        } else {
+            self.lines[0] = line;
+        }
+    }
+}
+
+/// Reset the output of the first failed action of a host that's retried, so that it
+/// runs again, with a line that tells the attempt. The TUI and the web dashboard
+/// both keep the outputs of the actions
+pub fn retry_failed_output<'a>(
+    outputs: impl IntoIterator<Item = &'a mut ActionOutput>,
+    attempt: usize,
+) {
+    if let Some(output) = outputs
+        .into_iter()
+        .find(|output| output.success == Some(false))
+    {
+        output.success = None;
+        output.started = false;
+        output.push_line(ActionOutputLine {
+            content: format!("retry attempt {attempt}"),
+            level: ActionOutputLevel::Warn,
+        });
+    }
 }
 
 /// ActionOutputLine is one line for the ActionOutput
@@ -74,3 +316,31 @@ pub enum ActionOutputLevel {
     Warn,
     Error,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prune_output_lines() {
+        let mut output = ActionOutput::default();
+        let line = |i: usize| ActionOutputLine {
+            content: i.to_string(),
+            level: ActionOutputLevel::Info,
+        };
+        for i in 0..MAX_OUTPUT_LINES * 2 {
+            output.push_line(line(i));
+            assert!(output.lines.len() <= MAX_OUTPUT_LINES + 1);
+        }
+        assert_eq!(output.pruned, MAX_OUTPUT_LINES + MAX_OUTPUT_LINES / 10);
+        assert_eq!(
+            output.lines[0].content,
+            format!("... {} earlier lines were pruned", output.pruned)
+        );
+        assert_eq!(output.lines[1].content, output.pruned.to_string());
+        assert_eq!(
+            output.lines.last().unwrap().content,
+            (MAX_OUTPUT_LINES * 2 - 1).to_string()
+        );
+    }
+}
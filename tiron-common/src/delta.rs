@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::sha256;
+
+/// The checksums of a block of the file that a delta is made against
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSignature {
+    // the rolling checksum, which is cheap to compute at every offset
+    pub weak: u32,
+    // the start of the SHA-256 of the block, for confirming a match of the weak checksum
+    pub strong: [u8; 8],
+}
+
+/// How to build the new file from the old one
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeltaOp {
+    /// Copy `count` blocks from the old file, starting at `block`
+    Copy { block: u32, count: u32 },
+    /// The bytes that aren't in the old file
+    Data(Vec<u8>),
+}
+
+/// The block size for a file of the length, which is about its square root like rsync
+pub fn block_size(len: usize) -> usize {
+    ((len as f64).sqrt() as usize & !7).clamp(704, 128 * 1024)
+}
+
+/// The signatures of the blocks of the old file, which a delta is made against
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signatures {
+    pub block_size: u32,
+    /// The length of the old file, which tells the length of its last block
+    pub len: u64,
+    pub blocks: Vec<BlockSignature>,
+}
+
+impl Signatures {
+    pub fn new(data: &[u8], block_size: usize) -> Self {
+        Self {
+            block_size: block_size as u32,
+            len: data.len() as u64,
+            blocks: data
+                .chunks(block_size)
+                .map(|block| BlockSignature {
+                    weak: weak_checksum(block),
+                    strong: strong_checksum(block),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The ops to build `data` from the old file of the signatures, which copy the
+/// blocks of the old file that are found at any offset in `data`
+pub fn delta(signatures: &Signatures, data: &[u8]) -> Vec<DeltaOp> {
+    let block_size = signatures.block_size as usize;
+    let last_len = signatures.len as usize % block_size;
+    let full_blocks = if last_len == 0 {
+        signatures.blocks.len()
+    } else {
+        signatures.blocks.len() - 1
+    };
+    let mut table: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, block) in signatures.blocks[..full_blocks].iter().enumerate() {
+        table.entry(block.weak).or_default().push(i);
+    }
+
+    let mut ops = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+    let (mut a, mut b) = if data.len() >= block_size {
+        rolling_parts(&data[..block_size])
+    } else {
+        (0, 0)
+    };
+    while i + block_size <= data.len() {
+        let matched = table.get(&(a | (b << 16))).and_then(|blocks| {
+            let strong = strong_checksum(&data[i..i + block_size]);
+            blocks
+                .iter()
+                .find(|&&block| signatures.blocks[block].strong == strong)
+        });
+        if let Some(&block) = matched {
+            push_data(&mut ops, &data[literal_start..i]);
+            push_copy(&mut ops, block as u32);
+            i += block_size;
+            literal_start = i;
+            if i + block_size <= data.len() {
+                (a, b) = rolling_parts(&data[i..i + block_size]);
+            }
+            continue;
+        }
+
+        if i + block_size < data.len() {
+            let out = data[i] as u32;
+            let new = data[i + block_size] as u32;
+            a = a.wrapping_sub(out).wrapping_add(new) & 0xffff;
+            b = b
+                .wrapping_sub((block_size as u32).wrapping_mul(out))
+                .wrapping_add(a)
+                & 0xffff;
+        }
+        i += 1;
+    }
+
+    // the last block of the old file is shorter, so it can only be at the end
+    let tail = &data[literal_start..];
+    if last_len > 0 && tail.len() >= last_len {
+        let candidate = &tail[tail.len() - last_len..];
+        let last = signatures.blocks[full_blocks];
+        if weak_checksum(candidate) == last.weak && strong_checksum(candidate) == last.strong {
+            push_data(&mut ops, &tail[..tail.len() - last_len]);
+            push_copy(&mut ops, full_blocks as u32);
+            return ops;
+        }
+    }
+    push_data(&mut ops, tail);
+    ops
+}
+
+/// Build the new file from the old file and the ops of the delta,
+/// or None if the ops don't fit the old file
+pub fn apply(old: &[u8], block_size: usize, ops: &[DeltaOp]) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    for op in ops {
+        match op {
+            DeltaOp::Copy { block, count } => {
+                let start = *block as usize * block_size;
+                let end = (start + *count as usize * block_size).min(old.len());
+                data.extend_from_slice(old.get(start..end)?);
+            }
+            DeltaOp::Data(bytes) => data.extend_from_slice(bytes),
+        }
+    }
+    Some(data)
+}
+
+fn push_data(ops: &mut Vec<DeltaOp>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        ops.push(DeltaOp::Data(bytes.to_vec()));
+    }
+}
+
+/// Copy the block, which extends the previous copy if the block follows it
+fn push_copy(ops: &mut Vec<DeltaOp>, block: u32) {
+    if let Some(DeltaOp::Copy {
+        block: start,
+        count,
+    }) = ops.last_mut()
+    {
+        if *start + *count == block {
+            *count += 1;
+            return;
+        }
+    }
+    ops.push(DeltaOp::Copy { block, count: 1 });
+}
+
+/// The two halves of the rolling checksum of rsync
+fn rolling_parts(block: &[u8]) -> (u32, u32) {
+    let len = block.len() as u32;
+    let mut a: u32 = 0;
+    let mut b: u32 = 0;
+    for (i, byte) in block.iter().enumerate() {
+        a = a.wrapping_add(*byte as u32);
+        b = b.wrapping_add((len - i as u32).wrapping_mul(*byte as u32));
+    }
+    (a & 0xffff, b & 0xffff)
+}
+
+fn weak_checksum(block: &[u8]) -> u32 {
+    let (a, b) = rolling_parts(block);
+    a | (b << 16)
+}
+
+fn strong_checksum(block: &[u8]) -> [u8; 8] {
+    let mut strong = [0u8; 8];
+    strong.copy_from_slice(&sha256(block)[..8]);
+    strong
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Bytes that don't repeat, so blocks only match where they were copied from
+    fn bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let old = bytes(100_000, 1);
+        let block_size = block_size(old.len());
+        let signatures = Signatures::new(&old, block_size);
+
+        // a change in the middle, an insert that shifts the rest, and a new end
+        let mut new = old.clone();
+        new[50_000..50_100].copy_from_slice(&bytes(100, 2));
+        new.splice(20_000..20_000, bytes(37, 3));
+        new.extend(bytes(500, 4));
+
+        let ops = delta(&signatures, &new);
+        assert_eq!(apply(&old, block_size, &ops).unwrap(), new);
+        let sent: usize = ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Data(data) => data.len(),
+                DeltaOp::Copy { .. } => 0,
+            })
+            .sum();
+        assert!(sent < 4 * block_size + 637);
+
+        // the short last block is copied when the end is the same
+        let ops = delta(&signatures, &old);
+        assert_eq!(
+            ops,
+            vec![DeltaOp::Copy {
+                block: 0,
+                count: signatures.blocks.len() as u32
+            }]
+        );
+        assert_eq!(apply(&old, block_size, &ops).unwrap(), old);
+
+        assert_eq!(
+            delta(&signatures, b"new"),
+            vec![DeltaOp::Data(b"new".to_vec())]
+        );
+    }
+}
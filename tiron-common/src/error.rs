@@ -2,6 +2,8 @@
 
 use anyhow::Result;
 
+use crate::error_code::error_code;
+
 /// The runbook file path and content
 pub struct Origin {
     pub cwd: PathBuf,
@@ -13,13 +15,51 @@ impl Origin {
     pub fn error(&self, message: impl Into<String>, span: &Option<Range<usize>>) -> Error {
         Error::new(message.into()).with_origin(self, span)
     }
+
+    /// The line and the columns of the span in the runbook
+    pub fn location(&self, span: &Option<Range<usize>>) -> Option<ErrorLocation> {
+        let span = span.as_ref()?;
+        let line_begin = self.data[..span.start]
+            .as_bytes()
+            .iter()
+            .rev()
+            .position(|&b| b == b'\n')
+            .map_or(0, |pos| span.start - pos);
+
+        let line_content = self.data[line_begin..]
+            .as_bytes()
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(&self.data[line_begin..], |pos| {
+                &self.data[line_begin..line_begin + pos]
+            });
+
+        let line = self.data[..span.start]
+            .as_bytes()
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count()
+            + 1;
+        let start_col = span.start - line_begin + 1;
+        let end_col = span.start - line_begin + span.len();
+        Some(ErrorLocation {
+            path: self.path.clone(),
+            line_content: line_content.to_string(),
+            line,
+            start_col,
+            end_col,
+        })
+    }
 }
 
 pub struct Error {
     pub message: String,
     pub location: Option<ErrorLocation>,
+    // the code of the kind of the error in `ERROR_CODES`
+    pub code: Option<&'static str>,
 }
 
+#[derive(Clone)]
 pub struct ErrorLocation {
     pub path: PathBuf,
     pub line_content: String,
@@ -33,41 +73,18 @@ pub fn new(message: impl Into<String>) -> Self {
         Self {
             message: message.into(),
             location: None,
+            code: None,
         }
     }
 
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
     pub fn with_origin(mut self, origin: &Origin, span: &Option<Range<usize>>) -> Self {
-        if let Some(span) = span {
-            let line_begin = origin.data[..span.start]
-                .as_bytes()
-                .iter()
-                .rev()
-                .position(|&b| b == b'\n')
-                .map_or(0, |pos| span.start - pos);
-
-            let line_content = origin.data[line_begin..]
-                .as_bytes()
-                .iter()
-                .position(|&b| b == b'\n')
-                .map_or(&origin.data[line_begin..], |pos| {
-                    &origin.data[line_begin..line_begin + pos]
-                });
-
-            let line = origin.data[..span.start]
-                .as_bytes()
-                .iter()
-                .filter(|&&b| b == b'\n')
-                .count()
-                + 1;
-            let start_col = span.start - line_begin + 1;
-            let end_col = span.start - line_begin + span.len();
-            self.location = Some(ErrorLocation {
-                path: origin.path.clone(),
-                line_content: line_content.to_string(),
-                line,
-                start_col,
-                end_col,
-            });
+        if let Some(location) = origin.location(span) {
+            self.location = Some(location);
         }
         self
     }
@@ -82,6 +99,7 @@ pub fn from_hcl(err: hcl_edit::parser::Error, path: PathBuf) -> Error {
                 start_col: err.location().column(),
                 end_col: err.location().column(),
             }),
+            code: Some("E0001"),
         }
     }
 
@@ -90,28 +108,52 @@ pub fn err<T>(self) -> Result<T, Error> {
     }
 
     pub fn report_stderr(&self) -> Result<()> {
-        let mut result = Vec::new();
-        result.push(Segment::from("Error: ").with_markup(Markup::Error));
+        self.report("Error", Markup::Error, &mut std::io::stderr().lock(), true)?;
+        std::process::exit(1);
+    }
+
+    /// Report the error as a warning, which doesn't stop anything
+    pub fn report_warning_stderr(&self) {
+        let _ = self.report(
+            "Warning",
+            Markup::Warning,
+            &mut std::io::stderr().lock(),
+            true,
+        );
+    }
+
+    /// Write the error with the line of the runbook it's from, like `report_stderr`
+    /// but without exiting, and without the colors if `color` is false
+    pub fn write_report(&self, out: &mut dyn Write, color: bool) -> Result<()> {
+        self.report("Error", Markup::Error, out, color)
+    }
+
+    fn report(&self, kind: &str, gutter: Markup, out: &mut dyn Write, color: bool) -> Result<()> {
+        let label = match self.code {
+            Some(code) => format!("{kind}[{code}]: "),
+            None => format!("{kind}: "),
+        };
+        let mut result = vec![Segment::from(label).with_markup(gutter)];
         result.push(self.message.clone().into());
         result.push("\n".into());
         if let Some(location) = &self.location {
             let line_len = location.line.to_string().len();
 
             result.push(" ".repeat(line_len + 1).into());
-            result.push(Segment::from("--> ").with_markup(Markup::Error));
+            result.push(Segment::from("--> ").with_markup(gutter));
             let path = location.path.to_string_lossy();
             result.push(path.as_ref().into());
             let line_col = format!(":{}:{}\n", location.line, location.start_col);
             result.push(line_col.as_str().into());
 
             result.push(" ".repeat(line_len + 2).into());
-            result.push(Segment::from("╷\n").with_markup(Markup::Error));
-            result.push(Segment::from(format!(" {} ", location.line)).with_markup(Markup::Error));
-            result.push(Segment::from("│ ").with_markup(Markup::Error));
+            result.push(Segment::from("╷\n").with_markup(gutter));
+            result.push(Segment::from(format!(" {} ", location.line)).with_markup(gutter));
+            result.push(Segment::from("│ ").with_markup(gutter));
             result.push(location.line_content.clone().into());
             result.push("\n".into());
             result.push(" ".repeat(line_len + 2).into());
-            result.push(Segment::from("╵").with_markup(Markup::Error));
+            result.push(Segment::from("╵").with_markup(gutter));
             result.push(" ".repeat(location.start_col).into());
             result.push("^".into());
             for _ in location.start_col..location.end_col {
@@ -119,19 +161,30 @@ pub fn report_stderr(&self) -> Result<()> {
             }
             result.push("\n".into());
         }
+        if let Some(code) = self.code.and_then(error_code) {
+            result.push(Segment::from("  = ").with_markup(gutter));
+            result.push(
+                format!(
+                    "help: run `tiron explain {}` or see {}\n",
+                    code.code,
+                    code.url()
+                )
+                .into(),
+            );
+        }
 
-        let stderr = std::io::stderr();
-        let mut out = stderr.lock();
         let mut markup = Markup::None;
         for seg in result {
-            if markup != seg.markup {
+            if color && markup != seg.markup {
                 markup = seg.markup;
                 out.write_all(switch_ansi(markup).as_bytes())?;
             }
             out.write_all(seg.s.as_bytes())?;
         }
-
-        std::process::exit(1);
+        if markup != Markup::None {
+            out.write_all(switch_ansi(Markup::None).as_bytes())?;
+        }
+        Ok(())
     }
 }
 
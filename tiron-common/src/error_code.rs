@@ -0,0 +1,102 @@
+/// A stable code of a kind of error, which is shown with the error and explained
+/// by `tiron explain`, so that the errors can be searched and matched in scripts
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+impl ErrorCode {
+    /// The section of the code on the errors page of the docs
+    pub fn url(&self) -> String {
+        format!(
+            "https://tiron.run/docs/getting-started/errors/#{}",
+            self.code.to_lowercase()
+        )
+    }
+}
+
+/// The codes are never reused for other kinds of errors once they're released.
+/// E00xx are about the syntax, E01xx the actions, E02xx the values,
+/// E03xx the hosts and the groups, and E04xx the versions
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "invalid syntax",
+        explanation: "The runbook isn't valid HCL, e.g. a block or a string isn't closed. \
+The error points at where the parser stopped, and the mistake is often right before it.",
+    },
+    ErrorCode {
+        code: "E0101",
+        title: "unknown action",
+        explanation: "The action of an `action` block isn't one of the actions of tiron. \
+`tiron action` lists the actions, and their names are case sensitive. \
+A job is used with `action \"job\"` and the name of the job in `job`.",
+    },
+    ErrorCode {
+        code: "E0102",
+        title: "missing required param",
+        explanation: "The action needs a param that isn't in its `params` block. \
+`tiron action <name>` shows the params of the action and which of them are required.",
+    },
+    ErrorCode {
+        code: "E0201",
+        title: "expression can't be evaluated",
+        explanation: "An expression uses a variable or a function that isn't there, \
+e.g. a var that the host doesn't have, or an Ansible filter without `ansible_filters = true`. \
+The facts of the hosts are only there in `when` and `output`.",
+    },
+    ErrorCode {
+        code: "E0202",
+        title: "invalid params",
+        explanation: "The params of the action have the right types, but the action can't \
+use them, e.g. a relative path where it needs an absolute one, or params that can't be \
+used together. The message tells what the action expects.",
+    },
+    ErrorCode {
+        code: "E0203",
+        title: "param type mismatch",
+        explanation: "A param of the action isn't of the type it should be, e.g. a number \
+for a string, or a value that isn't one of the options of the param. \
+`tiron action <name>` shows the types of the params.",
+    },
+    ErrorCode {
+        code: "E0301",
+        title: "unknown group or host",
+        explanation: "A run, or a group or `via` in it, points at a group or a host that \
+isn't in the runbook, the runbooks it uses, or the inventory. \
+The names of the hosts are matched as they're written in the `host` blocks.",
+    },
+    ErrorCode {
+        code: "E0401",
+        title: "version requirement not met",
+        explanation: "The `requires` block of the runbook needs a version of tiron or of an \
+action that this tiron isn't. Install a tiron that meets the requirement, \
+or change the requirement if the runbook works with this version.",
+    },
+];
+
+/// The code with its explanation, which is case insensitive, e.g. `e0101`
+pub fn error_code(code: &str) -> Option<&'static ErrorCode> {
+    ERROR_CODES
+        .iter()
+        .find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique() {
+        for (i, code) in ERROR_CODES.iter().enumerate() {
+            assert_eq!(code.code.len(), 5);
+            assert!(code.code.starts_with('E'));
+            assert!(ERROR_CODES[..i].iter().all(|c| c.code < code.code));
+        }
+        assert_eq!(
+            error_code("e0203").map(|c| c.title),
+            Some("param type mismatch")
+        );
+    }
+}
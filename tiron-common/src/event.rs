@@ -1 +1,110 @@
+use std::{fmt::Display, sync::Arc};
 
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::action::{ActionId, ActionMessage};
+
+/// The events of executing the runs, which are sent to the caller
+/// so that it can show the progress
+#[derive(Clone, Deserialize, Serialize)]
+pub enum Event {
+    Run(RunEvent),
+    Action {
+        run: Uuid,
+        host: Uuid,
+        msg: ActionMessage,
+    },
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub enum RunEvent {
+    RunStarted {
+        id: Uuid,
+    },
+    RunCompleted {
+        id: Uuid,
+        success: bool,
+    },
+    HostRetry {
+        run: Uuid,
+        host: Uuid,
+        attempt: usize,
+    },
+    HostPhase {
+        run: Uuid,
+        host: Uuid,
+        phase: HostPhase,
+    },
+    /// The actions of the host with their names, which are only known when the host
+    /// is connected if it has an `include_actions` that uses its facts
+    HostActions {
+        run: Uuid,
+        host: Uuid,
+        actions: Vec<(ActionId, String)>,
+    },
+}
+
+/// The phase of connecting to the host and running the actions on it
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum HostPhase {
+    Resolving,
+    Bootstrapping,
+    Connected,
+    Running,
+    // the host doesn't meet the `when` condition
+    Skipped,
+}
+
+impl Display for HostPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostPhase::Resolving => f.write_str("resolving"),
+            HostPhase::Bootstrapping => f.write_str("bootstrapping node"),
+            HostPhase::Connected => f.write_str("connected"),
+            HostPhase::Running => f.write_str("running"),
+            HostPhase::Skipped => f.write_str("skipped"),
+        }
+    }
+}
+
+/// Where the events of executing the runs go, e.g. the TUI or a log
+pub trait EventSink: Send + Sync {
+    fn send(&self, event: Event);
+}
+
+impl EventSink for Sender<Event> {
+    fn send(&self, event: Event) {
+        let _ = Sender::send(self, event);
+    }
+}
+
+/// Drops all the events, for when nothing shows the progress
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn send(&self, _event: Event) {}
+}
+
+/// Sends the events to all the sinks
+pub struct MultiSink {
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl MultiSink {
+    pub fn new(sinks: Vec<Arc<dyn EventSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl EventSink for MultiSink {
+    fn send(&self, event: Event) {
+        if let Some((last, sinks)) = self.sinks.split_last() {
+            for sink in sinks {
+                sink.send(event.clone());
+            }
+            last.send(event);
+        }
+    }
+}
@@ -1,5 +1,8 @@
 pub mod action;
+pub mod crypto;
+pub mod delta;
 pub mod error;
+pub mod error_code;
 pub mod event;
 pub mod node;
 pub mod run;
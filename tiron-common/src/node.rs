@@ -1,9 +1,109 @@
+use std::{collections::BTreeMap, fmt::Display};
+
 use serde::{Deserialize, Serialize};
 
-use crate::action::ActionData;
+use crate::action::{ActionData, DebugChoice};
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, strum_macros::IntoStaticStr)]
 pub enum NodeMessage {
     Action(ActionData),
+    /// Lock the host for the run, and take over the lock from others if `force`
+    Lock {
+        holder: LockHolder,
+        force: bool,
+    },
+    /// The signatures of the files with the block sizes, for copying only
+    /// the blocks of the files that changed
+    Signatures {
+        files: Vec<(String, u32)>,
+    },
+    /// Which of the artifacts with the hashes are in the cache of the node
+    Cached {
+        hashes: Vec<[u8; 32]>,
+    },
+    /// The states of the resources, for comparing them with the manifest of the host
+    Fingerprint {
+        resources: Vec<Resource>,
+    },
+    /// What to do with the action that failed in the debug strategy
+    Debug(DebugChoice),
     Shutdown,
 }
+
+/// Who holds the lock of a host, which is written in the lock files
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LockHolder {
+    pub user: String,
+    // when the lock was taken, e.g. 2024-05-01 08:30 UTC
+    pub since: String,
+}
+
+impl Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "locked by {} since {}", self.user, self.since)
+    }
+}
+
+/// The facts about the host that the node runs on,
+/// which the node sends when it starts
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Facts {
+    // the operating system, e.g. linux or macos
+    pub os: String,
+    // the family of the distribution, e.g. debian or rhel
+    pub os_family: String,
+    // the distribution, e.g. ubuntu or fedora
+    pub distribution: String,
+    pub distribution_version: String,
+    pub arch: String,
+    pub hostname: String,
+    pub kernel: String,
+    pub cpus: usize,
+    // the address of the host on its default route
+    pub ip: String,
+    // the local facts written by the runs in the json files of /etc/tiron/facts.d
+    // by their file names, which are kept as json text so that bincode can carry them
+    #[serde(default)]
+    pub local: BTreeMap<String, String>,
+}
+
+/// A resource on the host that's managed by the actions, e.g. a file written by `copy`,
+/// which is in the manifest of the host for the drift report
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    File { path: String },
+    Package { name: String },
+    Service { name: String },
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::File { path } => write!(f, "file {path}"),
+            Resource::Package { name } => write!(f, "package {name}"),
+            Resource::Service { name } => write!(f, "service {name}"),
+        }
+    }
+}
+
+/// The state of a resource on the host
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceState {
+    File {
+        // the SHA-256 of the file in hex, or None if it's missing
+        hash: Option<String>,
+    },
+    Package {
+        installed: bool,
+    },
+    Service {
+        active: bool,
+        enabled: bool,
+    },
+    /// The state can't be read, e.g. the host doesn't have a service manager
+    Unknown {
+        error: String,
+    },
+}
@@ -1,4 +1,4 @@
-use std::ops::Range;
+use std::{fmt::Display, ops::Range};
 
 use hcl::{
     eval::{Context, Evaluate},
@@ -124,9 +124,41 @@ pub fn from_expression(
                 let expr: hcl::Expression = expr.into();
                 let v: hcl::Value = expr
                     .evaluate(ctx)
-                    .map_err(|e| origin.error(e.to_string(), &span))?;
+                    .map_err(|e| origin.error(e.to_string(), &span).with_code("E0201"))?;
                 Ok(SpannedValue::from_value(v, span))
             }
         }
     }
 }
+
+impl Display for SpannedValue {
+    /// Format the value the way it's written in HCL
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpannedValue::Null(_) => f.write_str("null"),
+            SpannedValue::Bool(v) => write!(f, "{}", v.value()),
+            SpannedValue::Number(v) => write!(f, "{}", v.value()),
+            SpannedValue::String(v) => write!(f, "{:?}", v.value()),
+            SpannedValue::Array(v) => {
+                f.write_str("[")?;
+                for (i, item) in v.value().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            SpannedValue::Object(v) => {
+                f.write_str("{")?;
+                for (i, (key, item)) in v.value().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{key} = {item}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
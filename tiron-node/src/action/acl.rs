@@ -0,0 +1,441 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AclState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Manage an entry in the POSIX ACL of a file or directory with `setfacl`,
+/// e.g. to give a group access to a shared directory. The entries are read
+/// with `getfacl` first, so the ACL is only changed if the entry is different
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct AclAction {
+    /// The file or directory whose ACL is managed
+    path: String,
+    /// Default to `user`<br>
+    ///
+    /// The type of the entry, which is `user`, `group`, `mask` or `other`
+    etype: String,
+    /// The user or the group of the entry. Without it, the entry is for the owner
+    /// or the group of the file. `mask` and `other` don't have it
+    entity: String,
+    /// The permissions of the entry, e.g. `rwx` or `r-x`, which is required
+    /// if the state is `present`
+    permissions: String,
+    /// Manage the default ACL of the directory, which the files created in it get
+    default: bool,
+    /// Manage the entry on everything in the directory too. The default ACL
+    /// is only on the directories
+    recursive: bool,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the entry is added, or changed if it has different permissions.
+    ///
+    /// If `absent`, the entry is removed, which only works for a user or a group.
+    state: AclState,
+}
+
+impl AclAction {
+    /// The entry without the permissions, e.g. `default:user:alice`
+    fn key(&self) -> String {
+        let prefix = if self.default { "default:" } else { "" };
+        format!("{prefix}{}:{}", self.etype, self.entity)
+    }
+
+    /// The entry for setfacl, which sets the default ACL with `-d` instead of the prefix
+    fn spec(&self) -> String {
+        match self.state {
+            AclState::Present => format!("{}:{}:{}", self.etype, self.entity, self.permissions),
+            AclState::Absent => format!("{}:{}", self.etype, self.entity),
+        }
+    }
+
+    /// The paths whose ACL doesn't have the entry as it should be
+    fn outdated(&self) -> Result<Vec<PathBuf>> {
+        let mut cmd = Command::new("getfacl");
+        cmd.args(["--absolute-names"]);
+        if self.recursive {
+            cmd.arg("-R");
+        }
+        let output = cmd.arg(&self.path).output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "can't read the ACL of {}: {}",
+                self.path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let key = self.key();
+        let mut outdated = Vec::new();
+        for (path, entries) in parse_getfacl(&String::from_utf8_lossy(&output.stdout)) {
+            // only the directories have default ACLs
+            if self.default && !Path::new(&path).is_dir() {
+                continue;
+            }
+            let current = entries
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, perms)| perms.as_str());
+            let ok = match self.state {
+                AclState::Present => current == Some(self.permissions.as_str()),
+                AclState::Absent => current.is_none(),
+            };
+            if !ok {
+                outdated.push(PathBuf::from(path));
+            }
+        }
+        Ok(outdated)
+    }
+
+    fn change(&self, outdated: &[PathBuf]) -> String {
+        let action = match self.state {
+            AclState::Present => "set",
+            AclState::Absent => "remove",
+        };
+        match outdated {
+            [path] => format!("{action} acl {} on {}", self.key(), path.display()),
+            _ => format!(
+                "{action} acl {} on {} files in {}",
+                self.key(),
+                outdated.len(),
+                self.path
+            ),
+        }
+    }
+}
+
+/// The entries of every file in the output of getfacl, as the entry without
+/// the permissions to the permissions, e.g. `user:alice` to `rwx`
+fn parse_getfacl(output: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut files: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for line in output.lines() {
+        if let Some(path) = line.strip_prefix("# file: ") {
+            files.push((unescape(path), Vec::new()));
+            continue;
+        }
+        // the effective permissions are in a comment after the entry
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((key, perms)) = line.rsplit_once(':') else {
+            continue;
+        };
+        if let Some((_, entries)) = files.last_mut() {
+            entries.push((key.to_string(), perms.to_string()));
+        }
+    }
+    files
+}
+
+/// getfacl writes the special characters in the paths as octal escapes, e.g. `\040`
+fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = bytes.get(i + 1..i + 4).unwrap_or_default();
+        if bytes[i] == b'\\'
+            && digits.len() == 3
+            && digits.iter().all(|d| (b'0'..=b'7').contains(d))
+        {
+            out.push(
+                digits
+                    .iter()
+                    .fold(0u8, |b, d| b.wrapping_mul(8) + (d - b'0')),
+            );
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// The permissions as getfacl shows them, e.g. `rx` is `r-x`
+fn normalize_permissions(permissions: &str) -> Option<String> {
+    if permissions.is_empty() || !permissions.chars().all(|c| "rwx-".contains(c)) {
+        return None;
+    }
+    Some(
+        ['r', 'w', 'x']
+            .iter()
+            .map(|c| if permissions.contains(*c) { *c } else { '-' })
+            .collect(),
+    )
+}
+
+impl Action for AclAction {
+    fn name(&self) -> String {
+        "acl".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "path".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("path").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "etype".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("etype")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("user".to_string()),
+                        ActionParamBaseValue::String("group".to_string()),
+                        ActionParamBaseValue::String("mask".to_string()),
+                        ActionParamBaseValue::String("other".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "entity".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("entity")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "permissions".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("permissions")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "default".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("default")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "recursive".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("recursive")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let etype = params
+            .base(1)
+            .map(|etype| etype.expect_string())
+            .unwrap_or("user");
+        let entity = params.string(2).unwrap_or_default();
+        let state = match params.base(6).map(|state| state.expect_string()) {
+            Some("absent") => AclState::Absent,
+            _ => AclState::Present,
+        };
+
+        if matches!(etype, "mask" | "other") && !entity.is_empty() {
+            return Error::new(format!("the {etype} entry of acl doesn't have an entity"))
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        if state == AclState::Absent && entity.is_empty() {
+            return Error::new("only the entries of a user or a group can be absent")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        let permissions = match params.values[3]
+            .as_ref()
+            .map(|v| v.expect_string_with_span())
+        {
+            Some((permissions, span)) => {
+                let Some(permissions) = normalize_permissions(permissions) else {
+                    return Error::new(format!(
+                        "{permissions} isn't a permission of acl, e.g. rwx or r-x"
+                    ))
+                    .with_origin(params.origin, span)
+                    .err();
+                };
+                permissions
+            }
+            None if state == AclState::Present => {
+                return Error::new("acl needs permissions in params when the state is present")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            None => String::new(),
+        };
+
+        let input = AclAction {
+            path: params.expect_string(0).to_string(),
+            etype: etype.to_string(),
+            entity: entity.to_string(),
+            permissions,
+            default: params.bool(4).unwrap_or(false),
+            recursive: params.bool(5).unwrap_or(false),
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: AclAction = bincode::deserialize(input)?;
+        let outdated = input.outdated()?;
+        if outdated.is_empty() {
+            return Ok("".to_string());
+        }
+        let mut args = Vec::new();
+        if input.recursive {
+            args.push("-R".to_string());
+        }
+        if input.default {
+            args.push("-d".to_string());
+        }
+        args.push(
+            match input.state {
+                AclState::Present => "-m",
+                AclState::Absent => "-x",
+            }
+            .to_string(),
+        );
+        args.push(input.spec());
+        args.push(input.path.clone());
+        let status = run_command(id, tx, "setfacl", &args)?;
+        if !status.success() {
+            return Err(anyhow!("can't change the ACL of {}", input.path));
+        }
+        Ok(input.change(&outdated))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: AclAction = bincode::deserialize(input)?;
+        let outdated = input.outdated()?;
+        if outdated.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(input.change(&outdated)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_params() {
+        assert_input(
+            &AclAction::default(),
+            r#"
+                path = "/srv/shared"
+                etype = "group"
+                entity = "developers"
+                permissions = "rx"
+                default = true
+            "#,
+            &AclAction {
+                path: "/srv/shared".to_string(),
+                etype: "group".to_string(),
+                entity: "developers".to_string(),
+                permissions: "r-x".to_string(),
+                default: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            input_error(&AclAction::default(), r#"path = "/srv/shared""#),
+            "acl needs permissions in params when the state is present"
+        );
+        assert_eq!(
+            input_error(
+                &AclAction::default(),
+                r#"
+                    path = "/srv/shared"
+                    permissions = "rwz"
+                "#
+            ),
+            "rwz isn't a permission of acl, e.g. rwx or r-x"
+        );
+        assert_eq!(
+            input_error(
+                &AclAction::default(),
+                r#"
+                    path = "/srv/shared"
+                    state = "absent"
+                "#
+            ),
+            "only the entries of a user or a group can be absent"
+        );
+    }
+
+    #[test]
+    fn parse_entries() {
+        let output = "# file: /srv/shared\n\
+            # owner: root\n\
+            # group: root\n\
+            user::rwx\n\
+            user:alice:rwx\t\t#effective:r-x\n\
+            group::r-x\n\
+            mask::r-x\n\
+            other::---\n\
+            default:group:developers:r-x\n\
+            \n\
+            # file: /srv/shared/my\\040file\n\
+            user::rw-\n";
+        let files = parse_getfacl(output);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, "/srv/shared");
+        assert!(files[0]
+            .1
+            .contains(&("user:alice".to_string(), "rwx".to_string())));
+        assert!(files[0]
+            .1
+            .contains(&("default:group:developers".to_string(), "r-x".to_string())));
+        assert_eq!(files[1].0, "/srv/shared/my file");
+        assert_eq!(files[1].1, vec![("user:".to_string(), "rw-".to_string())]);
+    }
+}
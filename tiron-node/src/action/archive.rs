@@ -0,0 +1,301 @@
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    unarchive::ArchiveFormat, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue,
+    ActionParamDoc, ActionParamType, ActionParams,
+};
+
+/// Create a tar or zip archive of paths on the remote machine, e.g. to back them up,
+/// with `tar` or `zip`. The paths are in the archive by their names without
+/// their parent directories. dest is only replaced if the archive is different
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct ArchiveAction {
+    /// The files or directories on the remote machine to put in the archive
+    path: Vec<String>,
+    /// The path of the archive on the remote machine
+    dest: String,
+    /// The format of the archive, which is `gz`, `xz`, `bz2`, `tar` or `zip`.
+    /// Default to the format of the extension of dest, or `gz`
+    format: ArchiveFormat,
+    /// The patterns of the files to leave out of the archive, e.g. `*.log`
+    exclude: Vec<String>,
+}
+
+impl ArchiveAction {
+    /// Create the archive at the path
+    fn create(&self, archive: &Path) -> Result<()> {
+        let mut sources = Vec::new();
+        for path in &self.path {
+            let path = Path::new(path);
+            if !path.exists() {
+                return Err(anyhow!("can't find {}", path.display()));
+            }
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                return Err(anyhow!("{} can't be archived", path.display()));
+            };
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            sources.push((parent, name));
+        }
+
+        let flag = match self.format {
+            ArchiveFormat::TarGz => "-czf",
+            ArchiveFormat::TarXz => "-cJf",
+            ArchiveFormat::TarBz2 => "-cjf",
+            ArchiveFormat::Tar => "-cf",
+            ArchiveFormat::Zip => {
+                // zip can't change the directory for every path, so it adds them one by one
+                for (parent, name) in sources {
+                    let mut cmd = Command::new("zip");
+                    cmd.current_dir(parent)
+                        .args(["-r", "-q", "-X"])
+                        .arg(archive)
+                        .arg(name);
+                    if !self.exclude.is_empty() {
+                        cmd.arg("-x").args(&self.exclude);
+                    }
+                    check_output(cmd, "zip")?;
+                }
+                return Ok(());
+            }
+        };
+        let mut cmd = Command::new("tar");
+        cmd.arg(flag).arg(archive);
+        for exclude in &self.exclude {
+            cmd.arg(format!("--exclude={exclude}"));
+        }
+        for (parent, name) in sources {
+            cmd.arg("-C").arg(parent).arg(name);
+        }
+        check_output(cmd, "tar")
+    }
+
+    /// The new archive in a temporary file next to dest, or None if dest is the same
+    fn build(&self) -> Result<Option<tempfile::TempPath>> {
+        let dest = Path::new(&self.dest);
+        let dir = match dest.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(dir).map_err(|e| anyhow!("can't create {}: {e}", dir.display()))?;
+        let temp = tempfile::Builder::new()
+            .prefix(".tiron-archive")
+            .tempfile_in(dir)?
+            .into_temp_path();
+        // zip adds to the archive if it's there
+        std::fs::remove_file(&temp)?;
+        self.create(&temp)?;
+        if std::fs::read(dest).ok() == Some(std::fs::read(&temp)?) {
+            return Ok(None);
+        }
+        Ok(Some(temp))
+    }
+}
+
+fn check_output(mut cmd: Command, program: &str) -> Result<()> {
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+impl Action for ArchiveAction {
+    fn name(&self) -> String {
+        "archive".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "path".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("path").unwrap_or_default().to_string(),
+                    type_: vec![
+                        ActionParamType::String,
+                        ActionParamType::List(ActionParamBaseType::String),
+                    ],
+                },
+                ActionParamDoc {
+                    name: "dest".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("dest").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "format".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("format")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("gz".to_string()),
+                        ActionParamBaseValue::String("xz".to_string()),
+                        ActionParamBaseValue::String("bz2".to_string()),
+                        ActionParamBaseValue::String("tar".to_string()),
+                        ActionParamBaseValue::String("zip".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "exclude".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("exclude")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let path = params.values[0].as_ref().unwrap();
+        let path = if let Some(s) = path.string() {
+            vec![s.to_string()]
+        } else {
+            let list = path.expect_list();
+            list.iter().map(|v| v.expect_string().to_string()).collect()
+        };
+        if path.is_empty() {
+            return Error::new("archive needs at least one path")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        let dest = params.expect_string(1);
+        let format = match params.base(2).map(|format| format.expect_string()) {
+            Some("gz") => ArchiveFormat::TarGz,
+            Some("xz") => ArchiveFormat::TarXz,
+            Some("bz2") => ArchiveFormat::TarBz2,
+            Some("tar") => ArchiveFormat::Tar,
+            Some("zip") => ArchiveFormat::Zip,
+            _ => ArchiveFormat::from_path(dest).unwrap_or_default(),
+        };
+        let exclude = params
+            .list(3)
+            .map(|list| list.iter().map(|v| v.expect_string().to_string()).collect())
+            .unwrap_or_default();
+
+        let input = ArchiveAction {
+            path,
+            dest: dest.to_string(),
+            format,
+            exclude,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, _id: ActionId, input: &[u8], _tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: ArchiveAction = bincode::deserialize(input)?;
+        let Some(temp) = input.build()? else {
+            return Ok("".to_string());
+        };
+        temp.persist(&input.dest)
+            .map_err(|e| anyhow!("can't write {}: {e}", input.dest))?;
+        Ok(format!("archive to {}", input.dest))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: ArchiveAction = bincode::deserialize(input)?;
+        // the temporary archive is removed when it's dropped
+        Ok(input.build()?.map(|_| format!("archive to {}", input.dest)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::assert_input;
+
+    #[test]
+    fn input_params() {
+        assert_input(
+            &ArchiveAction::default(),
+            r#"
+                path = ["/etc/nginx", "/var/www"]
+                dest = "/backup/web.tar.xz"
+                exclude = ["*.log"]
+            "#,
+            &ArchiveAction {
+                path: vec!["/etc/nginx".to_string(), "/var/www".to_string()],
+                dest: "/backup/web.tar.xz".to_string(),
+                format: ArchiveFormat::TarXz,
+                exclude: vec!["*.log".to_string()],
+            },
+        );
+        assert_input(
+            &ArchiveAction::default(),
+            r#"
+                path = "/etc/nginx"
+                dest = "/backup/nginx"
+                format = "zip"
+            "#,
+            &ArchiveAction {
+                path: vec!["/etc/nginx".to_string()],
+                dest: "/backup/nginx".to_string(),
+                format: ArchiveFormat::Zip,
+                exclude: Vec::new(),
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn execute_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(data.join("app.conf"), "conf").unwrap();
+        std::fs::write(data.join("app.log"), "log").unwrap();
+        let dest = dir.path().join("backup/data.tar");
+        let input = bincode::serialize(&ArchiveAction {
+            path: vec![data.to_string_lossy().to_string()],
+            dest: dest.to_string_lossy().to_string(),
+            format: ArchiveFormat::Tar,
+            exclude: vec!["*.log".to_string()],
+        })
+        .unwrap();
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let execute = || {
+            ArchiveAction::default()
+                .execute(ActionId::new(), &input, &tx)
+                .unwrap()
+        };
+
+        assert_eq!(execute(), format!("archive to {}", dest.display()));
+        let output = Command::new("tar").arg("-tf").arg(&dest).output().unwrap();
+        let mut names: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["data/", "data/app.conf"]);
+        // nothing changed, so dest isn't replaced
+        assert_eq!(execute(), "");
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
-    io::{BufRead, BufReader},
-    process::{ExitStatus, Stdio},
+    io::{BufRead, BufReader, Write},
+    process::{Command, ExitStatus, Stdio},
 };
 
 use anyhow::{anyhow, Result};
@@ -13,7 +13,8 @@
 };
 
 use super::{
-    Action, ActionDoc, ActionParamBaseType, ActionParamDoc, ActionParamType, ActionParams,
+    send_value, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue, ActionParamDoc,
+    ActionParamType, ActionParams,
 };
 
 pub fn run_command(
@@ -22,39 +23,66 @@ pub fn run_command(
     program: &str,
     args: &[String],
 ) -> Result<ExitStatus> {
-    let mut cmd = std::process::Command::new(program);
+    let mut cmd = Command::new(program);
     for arg in args {
         cmd.arg(arg);
     }
+    run_command_with_stdin(id, tx, cmd, None, false).map(|(status, _)| status)
+}
+
+/// Same as `run_command` with the command that's set up, e.g. with its directory
+/// and environment, and the string written to its stdin if it's given.
+/// It also returns the whole stdout if it's captured
+pub fn run_command_with_stdin(
+    id: ActionId,
+    tx: &Sender<ActionMessage>,
+    mut cmd: Command,
+    stdin: Option<&str>,
+    capture: bool,
+) -> Result<(ExitStatus, String)> {
     let mut child = cmd
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .stdin(Stdio::null())
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
         .spawn()?;
 
+    // it's written on its own, so that the command can write its output
+    // before it reads all of the input
+    if let (Some(mut writer), Some(stdin)) = (child.stdin.take(), stdin) {
+        let stdin = stdin.to_string();
+        std::thread::spawn(move || {
+            let _ = writer.write_all(stdin.as_bytes());
+        });
+    }
+
     let stdout = child.stdout.take();
     let stderr = child.stderr.take();
 
-    if let Some(stdout) = stdout {
+    let stdout = stdout.map(|stdout| {
         let tx = tx.clone();
         std::thread::spawn(move || {
+            let mut captured = String::new();
             let mut reader = BufReader::new(stdout);
             let mut line = String::new();
             while let Ok(n) = reader.read_line(&mut line) {
                 if n > 0 {
-                    let line = line.trim_end().to_string();
-                    let _ = tx.send(ActionMessage::ActionOutputLine {
-                        id,
-                        content: line,
-                        level: ActionOutputLevel::Info,
-                    });
+                    if capture {
+                        captured.push_str(&line);
+                    }
+                    let (content, level) = output_line(line.trim_end());
+                    let _ = tx.send(ActionMessage::ActionOutputLine { id, content, level });
                 } else {
                     break;
                 }
                 line.clear();
             }
-        });
-    }
+            captured
+        })
+    });
 
     if let Some(stderr) = stderr {
         let tx = tx.clone();
@@ -63,12 +91,8 @@ pub fn run_command(
             let mut line = String::new();
             while let Ok(n) = reader.read_line(&mut line) {
                 if n > 0 {
-                    let line = line.trim_end().to_string();
-                    let _ = tx.send(ActionMessage::ActionOutputLine {
-                        id,
-                        content: line,
-                        level: ActionOutputLevel::Info,
-                    });
+                    let (content, level) = output_line(line.trim_end());
+                    let _ = tx.send(ActionMessage::ActionOutputLine { id, content, level });
                 } else {
                     break;
                 }
@@ -78,16 +102,77 @@ pub fn run_command(
     }
 
     let status = child.wait()?;
-    Ok(status)
+    // the captured stdout is only whole when all of it has been read
+    let captured = match stdout {
+        Some(stdout) if capture => stdout.join().unwrap_or_default(),
+        _ => String::new(),
+    };
+    Ok((status, captured))
+}
+
+/// How the stdout of a command is parsed into the result of the action
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum OutputFormat {
+    Json,
+}
+
+impl OutputFormat {
+    pub fn param_type() -> ActionParamType {
+        ActionParamType::Enum(vec![ActionParamBaseValue::String("json".to_string())])
+    }
+
+    pub fn from_param(value: Option<&ActionParamBaseValue>) -> Option<Self> {
+        value.map(|format| match format.expect_string() {
+            "json" => OutputFormat::Json,
+            _ => unreachable!(),
+        })
+    }
+
+    /// Parse the stdout, and send it as the result of the action
+    pub fn send(self, id: ActionId, tx: &Sender<ActionMessage>, stdout: &str) -> Result<()> {
+        match self {
+            OutputFormat::Json => {
+                let value: serde_json::Value = serde_json::from_str(stdout)
+                    .map_err(|e| anyhow!("the output isn't valid JSON: {e}"))?;
+                send_value(id, tx, &value)
+            }
+        }
+    }
+}
+
+/// The content and level of an output line of a command.
+///
+/// The line can mark its level with a prefix like `::warn::` or `::error::`,
+/// which is removed from the content. Other lines are `Info`.
+fn output_line(line: &str) -> (String, ActionOutputLevel) {
+    let prefixes = [
+        ("::error::", ActionOutputLevel::Error),
+        ("::warn::", ActionOutputLevel::Warn),
+        ("::warning::", ActionOutputLevel::Warn),
+        ("::success::", ActionOutputLevel::Success),
+        ("::info::", ActionOutputLevel::Info),
+    ];
+    for (prefix, level) in prefixes {
+        if let Some(content) = line.strip_prefix(prefix) {
+            return (content.to_string(), level);
+        }
+    }
+    (line.to_string(), ActionOutputLevel::Info)
 }
 
 /// Run the command on the remote machine
+///
+/// Output lines starting with `::warn::`, `::error::` or `::success::`
+/// are shown with that level.
 #[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
 pub struct CommandAction {
     /// The command to run
     cmd: String,
     /// The command arguments
     args: Vec<String>,
+    /// Parse the stdout of the command as `"json"`, which is kept as the result
+    /// of the action. The action fails if the output isn't valid JSON
+    parse_output: Option<OutputFormat>,
 }
 
 impl Action for CommandAction {
@@ -111,6 +196,14 @@ fn doc(&self) -> ActionDoc {
                     description: Self::get_field_docs("args").unwrap_or_default().to_string(),
                     type_: vec![ActionParamType::List(ActionParamBaseType::String)],
                 },
+                ActionParamDoc {
+                    name: "parse_output".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("parse_output")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![OutputFormat::param_type()],
+                },
             ],
         }
     }
@@ -131,6 +224,7 @@ fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
         let input = CommandAction {
             cmd: cmd.to_string(),
             args: args.unwrap_or_default(),
+            parse_output: OutputFormat::from_param(params.base(2)),
         };
         let input = bincode::serialize(&input).map_err(|e| {
             Error::new(format!("serialize action input error: {e}"))
@@ -146,11 +240,123 @@ fn execute(
         tx: &Sender<ActionMessage>,
     ) -> anyhow::Result<String> {
         let input: CommandAction = bincode::deserialize(input)?;
-        let status = run_command(id, tx, &input.cmd, &input.args)?;
-        if status.success() {
-            Ok("command".to_string())
-        } else {
-            Err(anyhow!("command failed"))
+        let mut cmd = Command::new(&input.cmd);
+        cmd.args(&input.args);
+        let (status, stdout) =
+            run_command_with_stdin(id, tx, cmd, None, input.parse_output.is_some())?;
+        if !status.success() {
+            return Err(anyhow!("command failed"));
+        }
+        if let Some(format) = input.parse_output {
+            format.send(id, tx, &stdout)?;
+        }
+        Ok("command".to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_with_args() {
+        assert_input(
+            &CommandAction::default(),
+            r#"
+                cmd = "ls"
+                args = ["-l", "/tmp"]
+            "#,
+            &CommandAction {
+                cmd: "ls".to_string(),
+                args: vec!["-l".to_string(), "/tmp".to_string()],
+                parse_output: None,
+            },
+        );
+    }
+
+    #[test]
+    fn input_without_args() {
+        assert_input(
+            &CommandAction::default(),
+            r#"cmd = "ls""#,
+            &CommandAction {
+                cmd: "ls".to_string(),
+                args: Vec::new(),
+                parse_output: None,
+            },
+        );
+    }
+
+    #[test]
+    fn input_with_parse_output() {
+        assert_input(
+            &CommandAction::default(),
+            r#"
+                cmd          = "kubectl"
+                args         = ["get", "pods", "-o", "json"]
+                parse_output = "json"
+            "#,
+            &CommandAction {
+                cmd: "kubectl".to_string(),
+                args: vec![
+                    "get".to_string(),
+                    "pods".to_string(),
+                    "-o".to_string(),
+                    "json".to_string(),
+                ],
+                parse_output: Some(OutputFormat::Json),
+            },
+        );
+    }
+
+    #[test]
+    fn parse_json_output() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let id = ActionId::new();
+        OutputFormat::Json
+            .send(id, &tx, "{\"items\": [1, 2]}\n")
+            .unwrap();
+        match rx.try_recv() {
+            Ok(ActionMessage::ActionValue { value, .. }) => {
+                assert_eq!(value, r#"{"items":[1,2]}"#)
+            }
+            _ => panic!("the value should be sent"),
         }
+        let e = OutputFormat::Json.send(id, &tx, "done\n").unwrap_err();
+        assert!(e.to_string().starts_with("the output isn't valid JSON: "));
+    }
+
+    #[test]
+    fn output_line_levels() {
+        let (content, level) = output_line("::warn::disk is almost full");
+        assert_eq!(content, "disk is almost full");
+        assert!(matches!(level, ActionOutputLevel::Warn));
+
+        let (content, level) = output_line("::error::can't connect");
+        assert_eq!(content, "can't connect");
+        assert!(matches!(level, ActionOutputLevel::Error));
+
+        let (content, level) = output_line("done ::error::");
+        assert_eq!(content, "done ::error::");
+        assert!(matches!(level, ActionOutputLevel::Info));
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(
+            input_error(&CommandAction::default(), r#"args = ["-l"]"#),
+            "can't find cmd in params, it's required"
+        );
+        assert_eq!(
+            input_error(
+                &CommandAction::default(),
+                r#"
+                    cmd = "ls"
+                    args = "-l"
+                "#
+            ),
+            "args type should be List of String"
+        );
     }
 }
@@ -5,15 +5,23 @@
 use documented::{Documented, DocumentedFields};
 use serde::{Deserialize, Serialize};
 use tiron_common::{
-    action::{ActionId, ActionMessage},
+    action::{ActionId, ActionMessage, ActionRollback},
+    crypto::{hex, sha256, unhex},
+    delta::{self, DeltaOp, Signatures},
     error::Error,
+    node::Resource,
 };
 
+use crate::{cache, managed};
+
 use super::{
-    command::run_command, Action, ActionDoc, ActionParamDoc, ActionParamType, ActionParams,
+    command::run_command, file::FileAction, Action, ActionDoc, ActionParamDoc, ActionParamType,
+    ActionParams,
 };
 
-/// Copy the file to the remote machine
+/// Copy the file to the remote machine. When dest is already there and the file
+/// is at least 64KiB, only the blocks of the file that changed are sent.
+/// Files of at least 16KiB are kept in the cache of the node, so they aren't sent again
 #[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
 pub struct CopyAction {
     /// Local path of a file to be copied
@@ -21,6 +29,149 @@ pub struct CopyAction {
     content: Vec<u8>,
     /// The path where file should be copied to on remote server
     dest: String,
+    // the blocks of content that differ from dest, which is sent instead of content
+    // when dest is mostly the same
+    delta: Option<CopyDelta>,
+    // the hash of content when it's in the cache of the node already,
+    // which is sent instead of content
+    cached: Option<[u8; 32]>,
+    /// The expected checksum of the file like `sha256:<hex>`. The copy fails if src
+    /// or the written dest doesn't match it, and it's skipped if dest matches it already
+    checksum: Option<[u8; 32]>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CopyDelta {
+    block_size: u32,
+    ops: Vec<DeltaOp>,
+    // the hash of the content, which tells if dest changed after its signatures were taken
+    hash: [u8; 32],
+}
+
+/// The content needs to be at least this big for the copy to be sent as a delta
+const DELTA_MIN_LEN: usize = 64 * 1024;
+/// The content needs to be at least this big to be kept in the cache of the node
+const CACHE_MIN_LEN: usize = 16 * 1024;
+
+/// Parse the checksum like `sha256:<hex>`
+pub(super) fn parse_checksum(checksum: &str) -> Option<[u8; 32]> {
+    let hash = checksum.strip_prefix("sha256:")?;
+    unhex(&hash.to_ascii_lowercase())?.try_into().ok()
+}
+
+/// The error message for the content that doesn't match the checksum
+pub(super) fn checksum_mismatch(what: &str, expected: &[u8; 32], content: &[u8]) -> String {
+    format!(
+        "{what} doesn't match the checksum\n  expected: sha256:{}\n  actual:   sha256:{}",
+        hex(expected),
+        hex(&sha256(content))
+    )
+}
+
+impl CopyAction {
+    pub(super) fn new(src: String, content: Vec<u8>, dest: String) -> Self {
+        Self {
+            src,
+            content,
+            dest,
+            delta: None,
+            cached: None,
+            checksum: None,
+        }
+    }
+
+    /// dest has the content of the checksum already
+    fn dest_matches(&self) -> bool {
+        match (&self.checksum, std::fs::read(&self.dest)) {
+            (Some(checksum), Ok(content)) => sha256(&content) == *checksum,
+            _ => false,
+        }
+    }
+
+    /// The hash of the content, if it's big enough to be taken from the cache of the node
+    pub fn cache_request(input: &[u8]) -> Option<[u8; 32]> {
+        let input: CopyAction = bincode::deserialize(input).ok()?;
+        if input.content.len() < CACHE_MIN_LEN {
+            return None;
+        }
+        Some(sha256(&input.content))
+    }
+
+    /// The input with the hash of the content instead of the content,
+    /// for when the node has it in the cache
+    pub fn with_cached(input: &[u8]) -> Option<Vec<u8>> {
+        let input: CopyAction = bincode::deserialize(input).ok()?;
+        bincode::serialize(&CopyAction {
+            src: input.src,
+            content: Vec::new(),
+            cached: Some(sha256(&input.content)),
+            dest: input.dest,
+            delta: None,
+            checksum: input.checksum,
+        })
+        .ok()
+    }
+
+    /// The dest of the copy and the block size for its signatures, if the content
+    /// is big enough to send only the blocks that differ from dest
+    pub fn delta_request(input: &[u8]) -> Option<(String, u32)> {
+        let input: CopyAction = bincode::deserialize(input).ok()?;
+        if input.content.len() < DELTA_MIN_LEN {
+            return None;
+        }
+        Some((input.dest, delta::block_size(input.content.len()) as u32))
+    }
+
+    /// The input with the delta against dest from its signatures instead of the content,
+    /// if that's smaller
+    pub fn with_delta(input: &[u8], signatures: &Signatures) -> Option<Vec<u8>> {
+        let input: CopyAction = bincode::deserialize(input).ok()?;
+        let ops = delta::delta(signatures, &input.content);
+        let hash = sha256(&input.content);
+        let delta_input = bincode::serialize(&CopyAction {
+            src: input.src,
+            content: Vec::new(),
+            dest: input.dest,
+            delta: Some(CopyDelta {
+                block_size: signatures.block_size,
+                ops,
+                hash,
+            }),
+            cached: None,
+            checksum: input.checksum,
+        })
+        .ok()?;
+        if delta_input.len() >= input.content.len() {
+            return None;
+        }
+        Some(delta_input)
+    }
+
+    /// The content to write to dest, which is built from dest and the delta
+    /// if the input has one, or taken from the cache
+    fn full_content(self) -> Result<Vec<u8>> {
+        if let Some(hash) = self.cached {
+            return cache::get(&hash).ok_or_else(|| {
+                anyhow!(
+                    "the content for {} is missing from the cache, run again to copy it",
+                    self.dest
+                )
+            });
+        }
+        let Some(delta) = self.delta else {
+            return Ok(self.content);
+        };
+        let old = std::fs::read(&self.dest)
+            .map_err(|e| anyhow!("can't read {} for the delta: {e}", self.dest))?;
+        delta::apply(&old, delta.block_size as usize, &delta.ops)
+            .filter(|content| sha256(content) == delta.hash)
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} changed after its checksums were taken, run again to copy it",
+                    self.dest
+                )
+            })
+    }
 }
 
 impl Action for CopyAction {
@@ -48,6 +199,14 @@ fn doc(&self) -> ActionDoc {
                         .to_string(),
                     type_: vec![ActionParamType::String],
                 },
+                ActionParamDoc {
+                    name: "checksum".to_string(),
+                    required: false,
+                    description: CopyAction::get_field_docs("checksum")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
             ],
         }
     }
@@ -68,11 +227,32 @@ fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
         })?;
 
         let dest = params.expect_string(1);
+        let checksum = match params.values[2].as_ref() {
+            Some(checksum) => {
+                let (checksum, span) = checksum.expect_string_with_span();
+                let Some(checksum) = parse_checksum(checksum) else {
+                    return Error::new("checksum should be like sha256:<hex>")
+                        .with_origin(params.origin, span)
+                        .err();
+                };
+                // the local file is checked first, so that a corrupted file is never sent
+                if sha256(&content) != checksum {
+                    return Error::new(checksum_mismatch(src, &checksum, &content))
+                        .with_origin(params.origin, src_span)
+                        .err();
+                }
+                Some(checksum)
+            }
+            None => None,
+        };
 
         let input = CopyAction {
             src: src_file.to_string_lossy().to_string(),
             content,
             dest: dest.to_string(),
+            delta: None,
+            cached: None,
+            checksum,
         };
         let input = bincode::serialize(&input).map_err(|e| {
             Error::new(format!("serialize action input error: {e}"))
@@ -84,22 +264,319 @@ fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
 
     fn execute(&self, id: ActionId, bytes: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
         let input: CopyAction = bincode::deserialize(bytes)?;
+        if input.dest_matches() {
+            return Ok("".to_string());
+        }
+        let dest = input.dest.clone();
+        let checksum = input.checksum;
+        let content = input.full_content()?;
+        if let Some(checksum) = &checksum {
+            if sha256(&content) != *checksum {
+                return Err(anyhow!(checksum_mismatch(
+                    "the content to copy",
+                    checksum,
+                    &content
+                )));
+            }
+        }
+        if content.len() >= CACHE_MIN_LEN {
+            // the copy doesn't fail because of the cache
+            let _ = cache::put(&content);
+        }
+        let created = !std::path::Path::new(&dest).exists();
         let mut temp = tempfile::NamedTempFile::new()?;
-        temp.write_all(&input.content)?;
+        temp.write_all(&content)?;
         temp.flush()?;
         let status = run_command(
             id,
             tx,
             "cp",
-            &[
-                temp.path().to_string_lossy().to_string(),
-                input.dest.clone(),
-            ],
+            &[temp.path().to_string_lossy().to_string(), dest.clone()],
         )?;
         if status.success() {
-            Ok(format!("copy to {}", input.dest))
+            if let Some(checksum) = &checksum {
+                let written =
+                    std::fs::read(&dest).map_err(|e| anyhow!("can't read {dest} back: {e}"))?;
+                if sha256(&written) != *checksum {
+                    return Err(anyhow!(checksum_mismatch(&dest, checksum, &written)));
+                }
+            }
+            if created {
+                // a file that isn't recorded is only never purged
+                let _ = managed::record(&dest);
+            }
+            Ok(format!("copy to {dest}"))
+        } else {
+            Err(anyhow!("can't copy to {dest}"))
+        }
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        bytes: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: CopyAction = bincode::deserialize(bytes)?;
+        if input.dest_matches() {
+            return Ok(None);
+        }
+        let dest = input.dest.clone();
+        let content = input.full_content()?;
+        if std::fs::read(&dest).ok().as_ref() == Some(&content) {
+            Ok(None)
         } else {
-            Err(anyhow!("can't copy to {}", input.dest))
+            Ok(Some(format!("copy to {dest}")))
         }
     }
+
+    fn rollback(&self, bytes: &[u8]) -> Result<Option<ActionRollback>> {
+        let input: CopyAction = bincode::deserialize(bytes)?;
+        let rollback = match std::fs::read(&input.dest) {
+            // copy the original content back
+            Ok(content) => ActionRollback {
+                action: self.name(),
+                input: bincode::serialize(&CopyAction {
+                    src: input.dest.clone(),
+                    content,
+                    dest: input.dest,
+                    delta: None,
+                    cached: None,
+                    checksum: None,
+                })?,
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ActionRollback {
+                action: FileAction::default().name(),
+                input: FileAction::absent(&input.dest)?,
+            },
+            Err(e) => return Err(anyhow!("can't back up {}: {e}", input.dest)),
+        };
+        Ok(Some(rollback))
+    }
+
+    fn resources(&self, bytes: &[u8]) -> Vec<Resource> {
+        match bincode::deserialize::<CopyAction>(bytes) {
+            Ok(input) => vec![Resource::File { path: input.dest }],
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::{
+        input_from_hcl,
+        testing::{assert_input_in, input_error},
+    };
+
+    #[test]
+    fn input_reads_src() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("src.txt"), "content").unwrap();
+        assert_input_in(
+            &CopyAction::default(),
+            dir.path(),
+            r#"
+                src = "src.txt"
+                dest = "/tmp/dest.txt"
+            "#,
+            &CopyAction {
+                src: dir.path().join("src.txt").to_string_lossy().to_string(),
+                content: b"content".to_vec(),
+                dest: "/tmp/dest.txt".to_string(),
+                delta: None,
+                cached: None,
+                checksum: None,
+            },
+        );
+    }
+
+    #[test]
+    fn rollback_restores_dest() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest.txt").to_string_lossy().to_string();
+        let input = bincode::serialize(&CopyAction {
+            src: "src.txt".to_string(),
+            content: b"new".to_vec(),
+            dest: dest.clone(),
+            delta: None,
+            cached: None,
+            checksum: None,
+        })
+        .unwrap();
+
+        // the dest didn't exist, so it's removed
+        let rollback = CopyAction::default().rollback(&input).unwrap().unwrap();
+        assert_eq!(rollback.action, "file");
+
+        std::fs::write(&dest, "old").unwrap();
+        let rollback = CopyAction::default().rollback(&input).unwrap().unwrap();
+        assert_eq!(rollback.action, "copy");
+        let rollback: CopyAction = bincode::deserialize(&rollback.input).unwrap();
+        assert_eq!(rollback.content, b"old");
+        assert_eq!(rollback.dest, dest);
+    }
+
+    #[test]
+    fn execute_delta() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest.bin");
+        let old: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        std::fs::write(&dest, &old).unwrap();
+
+        let mut content = old.clone();
+        content[40_000..40_010].copy_from_slice(b"0123456789");
+        let input = bincode::serialize(&CopyAction::new(
+            "src.bin".to_string(),
+            content.clone(),
+            dest.to_string_lossy().to_string(),
+        ))
+        .unwrap();
+        let (_, block_size) = CopyAction::delta_request(&input).unwrap();
+        let signatures = Signatures::new(&old, block_size as usize);
+        let delta_input = CopyAction::with_delta(&input, &signatures).unwrap();
+        assert!(delta_input.len() < 10_000);
+
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        CopyAction::default()
+            .execute(ActionId::new(), &delta_input, &tx)
+            .unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), content);
+
+        // dest changed since the signatures, so the delta can't be applied
+        std::fs::write(&dest, b"changed").unwrap();
+        assert!(CopyAction::default()
+            .execute(ActionId::new(), &delta_input, &tx)
+            .is_err());
+    }
+
+    #[test]
+    fn input_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("src.txt"), "content").unwrap();
+        let checksum = hex(&sha256(b"content"));
+        assert_input_in(
+            &CopyAction::default(),
+            dir.path(),
+            &format!(
+                r#"
+                    src      = "src.txt"
+                    dest     = "/tmp/dest.txt"
+                    checksum = "sha256:{}"
+                "#,
+                checksum.to_uppercase()
+            ),
+            &CopyAction {
+                src: dir.path().join("src.txt").to_string_lossy().to_string(),
+                content: b"content".to_vec(),
+                dest: "/tmp/dest.txt".to_string(),
+                delta: None,
+                cached: None,
+                checksum: Some(sha256(b"content")),
+            },
+        );
+
+        let e = input_from_hcl(
+            &CopyAction::default(),
+            dir.path(),
+            &format!(
+                r#"
+                    src      = "src.txt"
+                    dest     = "/tmp/dest.txt"
+                    checksum = "sha256:{}"
+                "#,
+                hex(&sha256(b"other"))
+            ),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            e.message,
+            format!(
+                "src.txt doesn't match the checksum\n  expected: sha256:{}\n  actual:   sha256:{checksum}",
+                hex(&sha256(b"other"))
+            )
+        );
+
+        let e = input_from_hcl(
+            &CopyAction::default(),
+            dir.path(),
+            r#"
+                src      = "src.txt"
+                dest     = "/tmp/dest.txt"
+                checksum = "md5:9a0364b9e99bb480dd25e1f0284c8555"
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(e.message, "checksum should be like sha256:<hex>");
+    }
+
+    #[test]
+    fn checksum_skips_matching_dest() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("dest.txt");
+        std::fs::write(&dest, "content").unwrap();
+        let input = bincode::serialize(&CopyAction {
+            src: "src.txt".to_string(),
+            content: b"content".to_vec(),
+            dest: dest.to_string_lossy().to_string(),
+            delta: None,
+            cached: None,
+            checksum: Some(sha256(b"content")),
+        })
+        .unwrap();
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        assert_eq!(
+            CopyAction::default()
+                .dry_run(ActionId::new(), &input, &tx)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            CopyAction::default()
+                .execute(ActionId::new(), &input, &tx)
+                .unwrap(),
+            ""
+        );
+
+        // the content doesn't match the checksum, so it's never written
+        let input = bincode::serialize(&CopyAction {
+            src: "src.txt".to_string(),
+            content: b"corrupted".to_vec(),
+            dest: dest.to_string_lossy().to_string(),
+            delta: None,
+            cached: None,
+            checksum: Some(sha256(b"new content")),
+        })
+        .unwrap();
+        assert!(CopyAction::default()
+            .execute(ActionId::new(), &input, &tx)
+            .is_err());
+        assert_eq!(std::fs::read(&dest).unwrap(), b"content");
+    }
+
+    #[test]
+    fn input_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let e = input_from_hcl(
+            &CopyAction::default(),
+            dir.path(),
+            r#"
+                src = "missing.txt"
+                dest = "/tmp/dest.txt"
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(e.message, "can't find src file");
+        // the error points to the src in the params
+        assert_eq!(e.location.unwrap().line, 2);
+
+        assert_eq!(
+            input_error(&CopyAction::default(), r#"src = "src.txt""#),
+            "can't find dest in params, it's required"
+        );
+    }
 }
@@ -0,0 +1,396 @@
+use std::{io::Write, process::Command};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionRollback},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CronState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Manage an entry in the crontab. The entry is marked with a `#Tiron: <name>` comment
+/// above it, so that it's updated in place instead of added again
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct CronAction {
+    /// The name of the entry, which is in the comment that marks it
+    name: String,
+    /// Minute when the job runs, e.g. `0-59`, `*` or `*/5`. Default to `*`
+    minute: String,
+    /// Hour when the job runs, e.g. `0-23`, `*` or `*/2`. Default to `*`
+    hour: String,
+    /// Day of the month when the job runs, e.g. `1-31`, `*` or `*/2`. Default to `*`
+    day: String,
+    /// Month when the job runs, e.g. `1-12` or `*`. Default to `*`
+    month: String,
+    /// Day of the week when the job runs, e.g. `0-6` for Sunday to Saturday, or `*`.
+    /// Default to `*`
+    weekday: String,
+    /// The command to run, which is required if the state is `present`
+    job: String,
+    /// The user whose crontab is changed. Default to the user the node runs as
+    user: Option<String>,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the entry is added, or updated if it's different.
+    ///
+    /// If `absent`, the entry is removed.
+    state: CronState,
+}
+
+impl CronAction {
+    /// The line of the entry in the crontab
+    fn entry(&self) -> String {
+        format!(
+            "{} {} {} {} {} {}",
+            self.minute, self.hour, self.day, self.month, self.weekday, self.job
+        )
+    }
+
+    fn marker(&self) -> String {
+        format!("#Tiron: {}", self.name)
+    }
+
+    /// The crontab with the entry updated, or None if it's up to date
+    fn update(&self, crontab: &str) -> Option<String> {
+        let marker = self.marker();
+        let entry = self.entry();
+        let mut lines: Vec<&str> = crontab.lines().collect();
+        let found = lines.iter().position(|line| line.trim_end() == marker);
+        match (self.state, found) {
+            (CronState::Present, Some(i)) => {
+                if lines.get(i + 1) == Some(&entry.as_str()) {
+                    return None;
+                }
+                if i + 1 < lines.len() {
+                    lines[i + 1] = entry.as_str();
+                } else {
+                    lines.push(entry.as_str());
+                }
+            }
+            (CronState::Present, None) => {
+                lines.push(marker.as_str());
+                lines.push(entry.as_str());
+            }
+            (CronState::Absent, Some(i)) => {
+                lines.drain(i..(i + 2).min(lines.len()));
+            }
+            (CronState::Absent, None) => return None,
+        }
+        let mut crontab = lines.join("\n");
+        crontab.push('\n');
+        Some(crontab)
+    }
+
+    /// The entry as it is in the crontab, or None if it's not there
+    fn current(&self, crontab: &str) -> Option<CronAction> {
+        let marker = self.marker();
+        let mut lines = crontab.lines();
+        lines.find(|line| line.trim_end() == marker)?;
+        let fields: Vec<&str> = lines.next()?.splitn(6, ' ').collect();
+        let [minute, hour, day, month, weekday, job] = fields[..] else {
+            return None;
+        };
+        Some(CronAction {
+            name: self.name.clone(),
+            minute: minute.to_string(),
+            hour: hour.to_string(),
+            day: day.to_string(),
+            month: month.to_string(),
+            weekday: weekday.to_string(),
+            job: job.to_string(),
+            user: self.user.clone(),
+            state: CronState::Present,
+        })
+    }
+
+    fn user_args(&self) -> Vec<String> {
+        match &self.user {
+            Some(user) => vec!["-u".to_string(), user.clone()],
+            None => Vec::new(),
+        }
+    }
+
+    /// The crontab of the user, which is empty if the user doesn't have one
+    fn read_crontab(&self) -> Result<String> {
+        let output = Command::new("crontab")
+            .args(self.user_args())
+            .arg("-l")
+            .output()?;
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("no crontab for") {
+            Ok(String::new())
+        } else {
+            Err(anyhow!("can't read the crontab: {}", stderr.trim()))
+        }
+    }
+}
+
+impl Action for CronAction {
+    fn name(&self) -> String {
+        "cron".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "minute".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("minute")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "hour".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("hour").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "day".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("day").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "month".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("month")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "weekday".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("weekday")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "job".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("job").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "user".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("user").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let state = match params.base(8).map(|state| state.expect_string()) {
+            Some("absent") => CronState::Absent,
+            _ => CronState::Present,
+        };
+        let job = params.string(6).unwrap_or_default();
+        if state == CronState::Present && job.is_empty() {
+            return Error::new("cron needs job in params")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        let time = |i: usize| -> Result<String, Error> {
+            let Some((value, span)) = params.values[i]
+                .as_ref()
+                .map(|v| v.expect_string_with_span())
+            else {
+                return Ok("*".to_string());
+            };
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                return Error::new("the time of cron can't be empty or have spaces")
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+            Ok(value.to_string())
+        };
+
+        let input = CronAction {
+            name: params.expect_string(0).to_string(),
+            minute: time(1)?,
+            hour: time(2)?,
+            day: time(3)?,
+            month: time(4)?,
+            weekday: time(5)?,
+            job: job.to_string(),
+            user: params.string(7).map(|user| user.to_string()),
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: CronAction = bincode::deserialize(input)?;
+        let Some(crontab) = input.update(&input.read_crontab()?) else {
+            return Ok(format!("cron {}", input.name));
+        };
+        let mut temp = tempfile::NamedTempFile::new()?;
+        temp.write_all(crontab.as_bytes())?;
+        temp.flush()?;
+        let mut args = input.user_args();
+        args.push(temp.path().to_string_lossy().to_string());
+        let status = run_command(id, tx, "crontab", &args)?;
+        if status.success() {
+            Ok(format!("cron {}", input.name))
+        } else {
+            Err(anyhow!("can't write the crontab"))
+        }
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: CronAction = bincode::deserialize(input)?;
+        let crontab = input.read_crontab()?;
+        if input.update(&crontab).is_none() {
+            return Ok(None);
+        }
+        let change = match (input.state, input.current(&crontab)) {
+            (CronState::Absent, _) => "remove",
+            (CronState::Present, Some(_)) => "update",
+            (CronState::Present, None) => "add",
+        };
+        Ok(Some(format!("{change} cron {}", input.name)))
+    }
+
+    fn rollback(&self, input: &[u8]) -> Result<Option<ActionRollback>> {
+        let input: CronAction = bincode::deserialize(input)?;
+        let crontab = input.read_crontab()?;
+        if input.update(&crontab).is_none() {
+            return Ok(None);
+        }
+        // the entry is put back as it is now, or removed if it's not there
+        let rollback = input.current(&crontab).unwrap_or_else(|| CronAction {
+            name: input.name.clone(),
+            user: input.user.clone(),
+            state: CronState::Absent,
+            ..Default::default()
+        });
+        Ok(Some(ActionRollback {
+            action: self.name(),
+            input: bincode::serialize(&rollback)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    fn backup() -> CronAction {
+        CronAction {
+            name: "backup".to_string(),
+            minute: "0".to_string(),
+            hour: "2".to_string(),
+            day: "*".to_string(),
+            month: "*".to_string(),
+            weekday: "*".to_string(),
+            job: "/usr/local/bin/backup --all".to_string(),
+            user: None,
+            state: CronState::Present,
+        }
+    }
+
+    #[test]
+    fn input_params() {
+        assert_input(
+            &CronAction::default(),
+            r#"
+                name = "backup"
+                minute = "0"
+                hour = "2"
+                job = "/usr/local/bin/backup --all"
+            "#,
+            &backup(),
+        );
+        assert_eq!(
+            input_error(&CronAction::default(), r#"name = "backup""#),
+            "cron needs job in params"
+        );
+        assert_eq!(
+            input_error(
+                &CronAction::default(),
+                r#"
+                    name = "backup"
+                    minute = "0 2"
+                    job = "backup"
+                "#
+            ),
+            "the time of cron can't be empty or have spaces"
+        );
+    }
+
+    #[test]
+    fn update_crontab() {
+        let cron = backup();
+        let crontab = "MAILTO=ops\n*/5 * * * * /usr/bin/check\n";
+        let added = cron.update(crontab).unwrap();
+        assert_eq!(
+            added,
+            "MAILTO=ops\n*/5 * * * * /usr/bin/check\n#Tiron: backup\n0 2 * * * /usr/local/bin/backup --all\n"
+        );
+        // the entry is already there, so it's not added again
+        assert!(cron.update(&added).is_none());
+        assert_eq!(cron.current(&added).unwrap().entry(), cron.entry());
+
+        let mut changed = backup();
+        changed.hour = "3".to_string();
+        assert_eq!(
+            changed.update(&added).unwrap(),
+            "MAILTO=ops\n*/5 * * * * /usr/bin/check\n#Tiron: backup\n0 3 * * * /usr/local/bin/backup --all\n"
+        );
+
+        let mut absent = backup();
+        absent.state = CronState::Absent;
+        assert_eq!(absent.update(&added).unwrap(), crontab);
+        assert!(absent.update(crontab).is_none());
+    }
+}
@@ -1,17 +1,52 @@
 use std::collections::HashMap;
 
 use super::{
-    command::CommandAction, copy::CopyAction, file::FileAction, git::GitAction,
-    package::PackageAction, Action,
+    acl::AclAction, archive::ArchiveAction, command::CommandAction, copy::CopyAction,
+    cron::CronAction, debug::DebugAction, docker_compose::DockerComposeAction, fetch::FetchAction,
+    file::FileAction, filesystem::FilesystemAction, find::FindAction, firewall::FirewallAction,
+    git::GitAction, kernel_module::KernelModuleAction, local_facts::LocalFactsAction,
+    mount::MountAction, mysql::MysqlDbAction, mysql::MysqlUserAction, package::PackageAction,
+    reboot::RebootAction, selinux::SelinuxAction, service::ServiceAction, shell::ShellAction,
+    snapshot::SnapshotAction, stat::StatAction, synchronize::SynchronizeAction,
+    systemd_snippet::SystemdSnippetAction, systemd_unit::SystemdUnitAction,
+    template::TemplateAction, unarchive::UnarchiveAction, user::UserAction,
+    wait_for::WaitForAction, Action,
 };
 
 pub fn all_actions() -> HashMap<String, Box<dyn Action>> {
     [
+        Box::<AclAction>::default() as Box<dyn Action>,
+        Box::<ArchiveAction>::default() as Box<dyn Action>,
         Box::<CopyAction>::default() as Box<dyn Action>,
         Box::<PackageAction>::default() as Box<dyn Action>,
         Box::<CommandAction>::default() as Box<dyn Action>,
+        Box::<CronAction>::default() as Box<dyn Action>,
+        Box::<DebugAction>::default() as Box<dyn Action>,
+        Box::<DockerComposeAction>::default() as Box<dyn Action>,
+        Box::<FetchAction>::default() as Box<dyn Action>,
         Box::<FileAction>::default() as Box<dyn Action>,
+        Box::<FilesystemAction>::default() as Box<dyn Action>,
+        Box::<FindAction>::default() as Box<dyn Action>,
+        Box::<FirewallAction>::default() as Box<dyn Action>,
         Box::<GitAction>::default() as Box<dyn Action>,
+        Box::<KernelModuleAction>::default() as Box<dyn Action>,
+        Box::<LocalFactsAction>::default() as Box<dyn Action>,
+        Box::<MountAction>::default() as Box<dyn Action>,
+        Box::<MysqlDbAction>::default() as Box<dyn Action>,
+        Box::<MysqlUserAction>::default() as Box<dyn Action>,
+        Box::<RebootAction>::default() as Box<dyn Action>,
+        Box::<SelinuxAction>::default() as Box<dyn Action>,
+        Box::<ServiceAction>::default() as Box<dyn Action>,
+        Box::<ShellAction>::default() as Box<dyn Action>,
+        Box::<SnapshotAction>::default() as Box<dyn Action>,
+        Box::<StatAction>::default() as Box<dyn Action>,
+        Box::<SynchronizeAction>::default() as Box<dyn Action>,
+        Box::<SystemdSnippetAction>::default() as Box<dyn Action>,
+        Box::<SystemdUnitAction>::default() as Box<dyn Action>,
+        Box::<TemplateAction>::default() as Box<dyn Action>,
+        Box::<UnarchiveAction>::default() as Box<dyn Action>,
+        Box::<UserAction>::default() as Box<dyn Action>,
+        Box::<WaitForAction>::default() as Box<dyn Action>,
     ]
     .into_iter()
     .map(|a| (a.name(), a))
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use hcl::eval::Context;
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionOutputLevel},
+    error::Error,
+    value::SpannedValue,
+};
+
+use super::{
+    Action, ActionDoc, ActionParamBaseType, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+/// Print a message or the values of vars on each host, e.g. to see what a var
+/// resolved to for the host. It doesn't change anything on the host
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct DebugAction {
+    /// The message to print, which can use the vars of the host,
+    /// e.g. `"db is ${db_host}:${db_port}"`
+    msg: Option<String>,
+    /// The var or the list of vars to print with their values,
+    /// which can also be expressions, e.g. `["db_host", "ports[0]"]`
+    var: Vec<(String, String)>,
+}
+
+impl DebugAction {
+    /// The lines that it prints
+    fn lines(&self) -> Vec<String> {
+        self.msg
+            .iter()
+            .cloned()
+            .chain(
+                self.var
+                    .iter()
+                    .map(|(name, value)| format!("{name} = {value}")),
+            )
+            .collect()
+    }
+
+    fn print(&self, id: ActionId, tx: &Sender<ActionMessage>) {
+        for line in self.lines() {
+            let _ = tx.send(ActionMessage::ActionOutputLine {
+                id,
+                content: line,
+                level: ActionOutputLevel::Info,
+            });
+        }
+    }
+}
+
+impl Action for DebugAction {
+    fn name(&self) -> String {
+        "debug".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "msg".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("msg").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "var".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("var").unwrap_or_default().to_string(),
+                    type_: vec![
+                        ActionParamType::String,
+                        ActionParamType::List(ActionParamBaseType::String),
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let msg = params.string(0).map(|s| s.to_string());
+        let (names, span) = match params.values[1].as_ref() {
+            Some(var) => match var.string_with_span() {
+                Some((name, span)) => (vec![name.to_string()], span),
+                None => (
+                    var.expect_list()
+                        .iter()
+                        .map(|v| v.expect_string().to_string())
+                        .collect(),
+                    &params.span,
+                ),
+            },
+            None => (Vec::new(), &params.span),
+        };
+        if msg.is_none() && names.is_empty() {
+            return Error::new("debug needs msg or var in params")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        // without the context of a host, e.g. when the params are checked on their own,
+        // there are no vars to print
+        let empty = Context::new();
+        let ctx = params.ctx.unwrap_or(&empty);
+        let mut var = Vec::new();
+        for name in names {
+            let expr = hcl_edit::parser::parse_expr(&name).map_err(|e| {
+                Error::new(format!("{name} isn't a valid expression: {e}"))
+                    .with_origin(params.origin, span)
+            })?;
+            let value = SpannedValue::from_expression(params.origin, ctx, expr).map_err(|e| {
+                Error::new(format!("can't evaluate {name}: {}", e.message))
+                    .with_origin(params.origin, span)
+                    .with_code("E0201")
+            })?;
+            var.push((name, value.to_string()));
+        }
+
+        let input = DebugAction { msg, var };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: DebugAction = bincode::deserialize(input)?;
+        input.print(id, tx);
+        Ok(String::new())
+    }
+
+    fn dry_run(
+        &self,
+        id: ActionId,
+        input: &[u8],
+        tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        // printing doesn't change anything, so it prints in the dry run as well
+        let input: DebugAction = bincode::deserialize(input)?;
+        input.print(id, tx);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_debug() {
+        assert_input(
+            &DebugAction::default(),
+            r#"
+                msg = "db is ${"db1"}"
+                var = ["[1, 2]", "true"]
+            "#,
+            &DebugAction {
+                msg: Some("db is db1".to_string()),
+                var: vec![
+                    ("[1, 2]".to_string(), "[1, 2]".to_string()),
+                    ("true".to_string(), "true".to_string()),
+                ],
+            },
+        );
+        assert!(input_error(&DebugAction::default(), "var = \"db_host\"")
+            .starts_with("can't evaluate db_host"));
+        assert_eq!(
+            input_error(&DebugAction::default(), "var = []"),
+            "debug needs msg or var in params"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+    node::Resource,
+};
+
+use crate::cache::data_dir;
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+/// The file that the inline content of the compose project is written to
+const COMPOSE_FILE: &str = "compose.yaml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum ComposeState {
+    Up,
+    Down,
+    Restart,
+}
+
+/// Bring a Docker Compose project up, down, or restart it, from a project directory
+/// on the host or from inline compose content
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct DockerComposeAction {
+    /// The directory of the project on the host, which has the compose file.
+    /// With content, the compose file is written to it as `compose.yaml`
+    project_dir: Option<String>,
+    /// The content of the compose file. Either content or project_dir is required
+    content: Option<String>,
+    /// The name of the project. It's required for content without project_dir,
+    /// whose compose file goes to the data directory of tiron on the host
+    project: Option<String>,
+    /// `up` creates and starts the containers of the project in the background,
+    /// `down` stops and removes them, and `restart` restarts them.
+    /// Default to `up`
+    state: Option<ComposeState>,
+}
+
+impl DockerComposeAction {
+    fn dir(&self) -> PathBuf {
+        match (&self.project_dir, &self.project) {
+            (Some(dir), _) => PathBuf::from(dir),
+            (None, project) => data_dir()
+                .join("compose")
+                .join(project.as_deref().unwrap_or_default()),
+        }
+    }
+
+    /// The compose file that the content is written to, if there's content
+    fn file(&self) -> Option<PathBuf> {
+        self.content.as_ref().map(|_| self.dir().join(COMPOSE_FILE))
+    }
+
+    /// Whether the content needs to be written to the compose file
+    fn content_changed(&self) -> bool {
+        match (&self.content, self.file()) {
+            (Some(content), Some(file)) => {
+                std::fs::read_to_string(file).ok().as_deref() != Some(content.as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["compose".to_string()];
+        if let Some(project) = &self.project {
+            args.extend(["-p".to_string(), project.clone()]);
+        }
+        args.extend([
+            "--project-directory".to_string(),
+            self.dir().to_string_lossy().to_string(),
+        ]);
+        if let Some(file) = self.file() {
+            args.extend(["-f".to_string(), file.to_string_lossy().to_string()]);
+        }
+        match self.state.unwrap_or(ComposeState::Up) {
+            ComposeState::Up => args.extend(["up", "-d", "--remove-orphans"].map(String::from)),
+            ComposeState::Down => args.push("down".to_string()),
+            ComposeState::Restart => args.push("restart".to_string()),
+        }
+        args
+    }
+}
+
+impl Action for DockerComposeAction {
+    fn name(&self) -> String {
+        "docker_compose".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "project_dir".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("project_dir")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "content".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("content")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "project".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("project")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("up".to_string()),
+                        ActionParamBaseValue::String("down".to_string()),
+                        ActionParamBaseValue::String("restart".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let project_dir = params.string(0).map(|s| s.to_string());
+        let content = params.string(1).map(|s| s.to_string());
+        let project = params.string(2).map(|s| s.to_string());
+        if project_dir.is_none() && content.is_none() {
+            return Error::new("docker_compose needs project_dir or content in params")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        if project_dir.is_none() && project.is_none() {
+            return Error::new("docker_compose needs project for content without project_dir")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        if let Some((project, span)) = params.values[2]
+            .as_ref()
+            .map(|v| v.expect_string_with_span())
+        {
+            if project.is_empty() || project.contains('/') {
+                return Error::new("the name of the project can't be empty or have /")
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+        }
+
+        let state = params.base(3).map(|state| match state.expect_string() {
+            "up" => ComposeState::Up,
+            "down" => ComposeState::Down,
+            "restart" => ComposeState::Restart,
+            _ => unreachable!(),
+        });
+
+        let input = DockerComposeAction {
+            project_dir,
+            content,
+            project,
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: DockerComposeAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
+        if let (Some(content), Some(file)) = (&input.content, input.file()) {
+            if input.content_changed() {
+                let dir = input.dir();
+                std::fs::create_dir_all(&dir)
+                    .map_err(|e| anyhow!("can't create {}: {e}", dir.display()))?;
+                std::fs::write(&file, content)
+                    .map_err(|e| anyhow!("can't write {}: {e}", file.display()))?;
+                changes.push(format!("write {}", file.display()));
+            }
+        }
+        let args = input.args();
+        if !run_command(id, tx, "docker", &args)?.success() {
+            return Err(anyhow!("docker {} failed", args.join(" ")));
+        }
+        changes.push(format!("docker {}", args.join(" ")));
+        Ok(changes.join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: DockerComposeAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
+        if let Some(file) = input.file().filter(|_| input.content_changed()) {
+            changes.push(format!("write {}", file.display()));
+        }
+        // compose only knows what up would change when it runs
+        changes.push(format!("docker {}", input.args().join(" ")));
+        Ok(Some(changes.join(", ")))
+    }
+
+    fn resources(&self, input: &[u8]) -> Vec<Resource> {
+        match bincode::deserialize::<DockerComposeAction>(input) {
+            Ok(input) => input
+                .file()
+                .map(|file| Resource::File {
+                    path: file.to_string_lossy().to_string(),
+                })
+                .into_iter()
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_project() {
+        assert_input(
+            &DockerComposeAction::default(),
+            r#"
+                project_dir = "/srv/app"
+                state = "restart"
+            "#,
+            &DockerComposeAction {
+                project_dir: Some("/srv/app".to_string()),
+                content: None,
+                project: None,
+                state: Some(ComposeState::Restart),
+            },
+        );
+        assert_eq!(
+            input_error(
+                &DockerComposeAction::default(),
+                r#"content = "services: {}""#
+            ),
+            "docker_compose needs project for content without project_dir"
+        );
+    }
+
+    #[test]
+    fn compose_args() {
+        let action = DockerComposeAction {
+            project_dir: Some("/srv/app".to_string()),
+            content: Some("services: {}\n".to_string()),
+            project: Some("app".to_string()),
+            state: None,
+        };
+        assert_eq!(
+            action.args().join(" "),
+            "compose -p app --project-directory /srv/app -f /srv/app/compose.yaml up -d --remove-orphans"
+        );
+    }
+}
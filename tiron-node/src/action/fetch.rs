@@ -0,0 +1,173 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{Action, ActionDoc, ActionParamDoc, ActionParamType, ActionParams};
+
+/// The file is sent back to tiron in chunks of this size
+const FETCH_CHUNK: usize = 64 * 1024;
+
+/// Fetch a file from the remote machine to a local directory, which is the opposite
+/// of `copy`. The file is written to `<dest>/<host>/<src>`,
+/// e.g. `backups/web1/etc/nginx/nginx.conf`, so that the hosts don't overwrite
+/// each other's files
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct FetchAction {
+    /// The path of the file on the remote machine
+    src: String,
+    /// The local directory to fetch the file to, which is relative to the runbook
+    dest: String,
+}
+
+impl FetchAction {
+    /// The local path that the file of the host is fetched to with the input
+    pub fn local_path(input: &[u8], host: &str) -> Option<PathBuf> {
+        let input: FetchAction = bincode::deserialize(input).ok()?;
+        // only the names in src, so that it can't get out of the directory of the host
+        let src: PathBuf = Path::new(&input.src)
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        Some(Path::new(&input.dest).join(host).join(src))
+    }
+}
+
+impl Action for FetchAction {
+    fn name(&self) -> String {
+        "fetch".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("src").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "dest".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("dest").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (src, src_span) = params.expect_string_with_span(0);
+        if !Path::new(src).is_absolute() {
+            return Error::new("src should be an absolute path")
+                .with_origin(params.origin, src_span)
+                .err();
+        }
+        let dest = params.origin.cwd.join(params.expect_string(1));
+
+        let input = FetchAction {
+            src: src.to_string(),
+            dest: dest.to_string_lossy().to_string(),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: FetchAction = bincode::deserialize(input)?;
+        let mut file =
+            File::open(&input.src).map_err(|e| anyhow!("can't open {}: {e}", input.src))?;
+        if !file.metadata()?.is_file() {
+            return Err(anyhow!("{} isn't a file", input.src));
+        }
+        // tiron writes the chunks to the local file, which is done when the action succeeds
+        let mut buf = vec![0; FETCH_CHUNK];
+        let mut total = 0;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            tx.send(ActionMessage::ActionFetch {
+                id,
+                data: buf[..n].to_vec(),
+            })?;
+            total += n;
+        }
+        Ok(format!("fetched {} ({total} bytes)", input.src))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: FetchAction = bincode::deserialize(input)?;
+        if !Path::new(&input.src).is_file() {
+            return Err(anyhow!("{} isn't a file", input.src));
+        }
+        Ok(Some(format!("fetch {}", input.src)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input_in, input_error};
+
+    #[test]
+    fn input_fetch() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_input_in(
+            &FetchAction::default(),
+            dir.path(),
+            r#"
+                src  = "/etc/nginx/nginx.conf"
+                dest = "backups"
+            "#,
+            &FetchAction {
+                src: "/etc/nginx/nginx.conf".to_string(),
+                dest: dir.path().join("backups").to_string_lossy().to_string(),
+            },
+        );
+        assert_eq!(
+            input_error(
+                &FetchAction::default(),
+                r#"
+                    src  = "nginx.conf"
+                    dest = "backups"
+                "#
+            ),
+            "src should be an absolute path"
+        );
+    }
+
+    #[test]
+    fn fetch_local_path() {
+        let input = bincode::serialize(&FetchAction {
+            src: "/etc/../etc/nginx/nginx.conf".to_string(),
+            dest: "/tmp/backups".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            FetchAction::local_path(&input, "web1"),
+            Some(PathBuf::from("/tmp/backups/web1/etc/etc/nginx/nginx.conf"))
+        );
+    }
+}
@@ -1,10 +1,15 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
 
 use documented::{Documented, DocumentedFields};
 use serde::{Deserialize, Serialize};
 use tiron_common::error::Error;
 
+use crate::managed;
+
 use super::{
+    permission::{parse_mode, Permissions},
     Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType, ActionParams,
 };
 
@@ -14,6 +19,8 @@ pub enum FileState {
     File,
     Directory,
     Absent,
+    Link,
+    Hard,
 }
 
 /// Manage files/folders and their properties
@@ -30,7 +37,136 @@ pub struct FileAction {
     ///
     /// If `absent`, directories will be recursively deleted
     /// and all its contents, and files or symlinks will be unlinked.
+    ///
+    /// If `link`, a symlink to src will be created, or changed if it points elsewhere.
+    ///
+    /// If `hard`, a hard link to src will be created, or changed if it's another file.
     state: FileState,
+    /// Remove the files in the directory that tiron created before but the runbook
+    /// doesn't write anymore, e.g. with `copy` and `template`. The other files
+    /// are left alone. It needs the state to be `directory`
+    purge_unmanaged: bool,
+    // the files that the actions of the runbook write on the host,
+    // which are kept when purge_unmanaged
+    keep: Vec<String>,
+    /// The file the link points to, which is required if the state is `link` or `hard`.
+    /// A relative src of a symlink is from the directory of the link
+    src: Option<String>,
+    /// The user that should own the file, by name or id
+    owner: Option<String>,
+    /// The group that should own the file, by name or id
+    group: Option<String>,
+    /// The permissions of the file in octal, e.g. `"0644"`
+    mode: Option<u32>,
+    /// Set the owner, the group and the mode of everything in the directory too.
+    /// It needs the state to be `directory`
+    recurse: bool,
+}
+
+impl FileAction {
+    /// The input of removing the file or folder at the path
+    pub fn absent(path: &str) -> anyhow::Result<Vec<u8>> {
+        let input = FileAction {
+            path: path.to_string(),
+            state: FileState::Absent,
+            ..Default::default()
+        };
+        Ok(bincode::serialize(&input)?)
+    }
+
+    /// The input with the files to keep, if it purges the unmanaged files
+    pub fn with_keep(input: &[u8], keep: &[String]) -> Option<Vec<u8>> {
+        let mut input: FileAction = bincode::deserialize(input).ok()?;
+        if !input.purge_unmanaged {
+            return None;
+        }
+        input.keep = keep.to_vec();
+        bincode::serialize(&input).ok()
+    }
+
+    /// The link at path points to src already
+    fn is_linked(&self, src: &str) -> bool {
+        match self.state {
+            FileState::Link => std::fs::read_link(&self.path)
+                .map(|target| target == Path::new(src))
+                .unwrap_or(false),
+            FileState::Hard => same_file(Path::new(&self.path), Path::new(src)),
+            _ => false,
+        }
+    }
+
+    /// Create the link at path, which replaces the file that's there with a rename,
+    /// so that the path is never missing
+    fn link(&self, src: &str) -> anyhow::Result<()> {
+        let path = Path::new(&self.path);
+        if let Ok(meta) = std::fs::symlink_metadata(path) {
+            if meta.is_dir() {
+                return Err(anyhow!(
+                    "{} is a directory, it can't be replaced with a link",
+                    self.path
+                ));
+            }
+        }
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} isn't a file path", self.path))?;
+        let temp = path.with_file_name(format!(".{}.tiron-link", file_name.to_string_lossy()));
+        let _ = std::fs::remove_file(&temp);
+        match self.state {
+            FileState::Hard => {
+                std::fs::hard_link(src, &temp).map_err(|e| anyhow!("can't link to {src}: {e}"))?
+            }
+            _ => symlink(src, &temp)?,
+        }
+        if let Err(e) = std::fs::rename(&temp, path) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn permissions(&self) -> Permissions {
+        Permissions {
+            owner: self.owner.clone(),
+            group: self.group.clone(),
+            mode: self.mode,
+        }
+    }
+
+    /// Set the permissions of the path, which returns the change
+    fn apply_permissions(&self, dry_run: bool) -> anyhow::Result<Option<String>> {
+        let permissions = self.permissions();
+        if permissions.is_empty() {
+            return Ok(None);
+        }
+        if std::fs::symlink_metadata(&self.path).is_err() {
+            if dry_run && !matches!(self.state, FileState::File) {
+                // it would be created first
+                return Ok(Some(format!("set the permissions of {}", self.path)));
+            }
+            return Err(anyhow!("{} doesn't exist", self.path));
+        }
+        let changed = permissions.apply(Path::new(&self.path), self.recurse, dry_run)?;
+        let change = match changed.len() {
+            0 => None,
+            1 => Some(format!(
+                "set the permissions of {}",
+                changed[0].to_string_lossy()
+            )),
+            n => Some(format!("set the permissions of {n} files in {}", self.path)),
+        };
+        Ok(change)
+    }
+
+    /// The managed files in the directory that the runbook doesn't write anymore
+    fn unmanaged(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let keep: Vec<PathBuf> = self.keep.iter().map(|k| managed::absolute(k)).collect();
+        Ok(managed::unmanaged(
+            &managed::load()?,
+            &managed::absolute(&self.path),
+            &keep,
+        ))
+    }
 }
 
 impl Action for FileAction {
@@ -58,8 +194,54 @@ fn doc(&self) -> ActionDoc {
                         ActionParamBaseValue::String("file".to_string()),
                         ActionParamBaseValue::String("absent".to_string()),
                         ActionParamBaseValue::String("directory".to_string()),
+                        ActionParamBaseValue::String("link".to_string()),
+                        ActionParamBaseValue::String("hard".to_string()),
                     ])],
                 },
+                ActionParamDoc {
+                    name: "purge_unmanaged".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("purge_unmanaged")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("src").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "owner".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("owner")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "group".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("group")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "mode".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("mode").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "recurse".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("recurse")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
             ],
         }
     }
@@ -77,11 +259,64 @@ fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
                 "file" => FileState::File,
                 "absent" => FileState::Absent,
                 "directory" => FileState::Directory,
+                "link" => FileState::Link,
+                "hard" => FileState::Hard,
                 _ => unreachable!(),
             };
             input.state = state;
         }
 
+        if params.bool(2) == Some(true) {
+            if !matches!(input.state, FileState::Directory) {
+                return Error::new("purge_unmanaged needs the state to be directory")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            input.purge_unmanaged = true;
+        }
+
+        match (&input.state, params.string(3)) {
+            (FileState::Link | FileState::Hard, Some(src)) => input.src = Some(src.to_string()),
+            (FileState::Link | FileState::Hard, None) => {
+                return Error::new("file needs src in params when the state is link or hard")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            (_, Some(_)) => {
+                return Error::new("src only works with the link and hard states")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            (_, None) => {}
+        }
+
+        input.owner = params.string(4).map(|s| s.to_string());
+        input.group = params.string(5).map(|s| s.to_string());
+        if let Some((mode, span)) = params.values[6]
+            .as_ref()
+            .map(|v| v.expect_string_with_span())
+        {
+            let Some(mode) = parse_mode(mode) else {
+                return Error::new(format!("{mode} isn't an octal mode, e.g. \"0644\""))
+                    .with_origin(params.origin, span)
+                    .err();
+            };
+            input.mode = Some(mode);
+        }
+        if matches!(input.state, FileState::Absent) && !input.permissions().is_empty() {
+            return Error::new("owner, group and mode don't work with the absent state")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        if params.bool(7) == Some(true) {
+            if !matches!(input.state, FileState::Directory) {
+                return Error::new("recurse needs the state to be directory")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            input.recurse = true;
+        }
+
         let input = bincode::serialize(&input).map_err(|e| {
             Error::new(format!("serialize action input error: {e}"))
                 .with_origin(params.origin, &params.span)
@@ -96,22 +331,311 @@ fn execute(
         _tx: &crossbeam_channel::Sender<tiron_common::action::ActionMessage>,
     ) -> anyhow::Result<String> {
         let input: FileAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
         match input.state {
             FileState::File => {}
             FileState::Directory => {
-                std::fs::create_dir_all(input.path)?;
+                std::fs::create_dir_all(&input.path)?;
+                if input.purge_unmanaged {
+                    let unmanaged = input.unmanaged()?;
+                    managed::purge(&unmanaged)?;
+                    if !unmanaged.is_empty() {
+                        changes.push(format!(
+                            "remove {} unmanaged files from {}",
+                            unmanaged.len(),
+                            input.path
+                        ));
+                    }
+                }
             }
             FileState::Absent => {
                 let path = PathBuf::from(input.path);
-                if path.exists() {
+                // symlink_metadata finds the broken symlinks too
+                if std::fs::symlink_metadata(&path).is_ok() {
                     if path.is_dir() {
                         std::fs::remove_dir_all(path)?;
                     } else {
                         std::fs::remove_file(path)?;
                     }
                 }
+                return Ok("".to_string());
+            }
+            FileState::Link | FileState::Hard => {
+                let src = input.src.as_deref().unwrap_or_default();
+                if !input.is_linked(src) {
+                    input.link(src)?;
+                    changes.push(format!("link {} to {src}", input.path));
+                }
             }
         }
-        Ok("".to_string())
+        changes.extend(input.apply_permissions(false)?);
+        Ok(changes.join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: tiron_common::action::ActionId,
+        input: &[u8],
+        _tx: &crossbeam_channel::Sender<tiron_common::action::ActionMessage>,
+    ) -> anyhow::Result<Option<String>> {
+        let input: FileAction = bincode::deserialize(input)?;
+        let path = PathBuf::from(&input.path);
+        let mut changes = Vec::new();
+        match input.state {
+            FileState::File => {}
+            FileState::Directory => {
+                if !path.is_dir() {
+                    changes.push(format!("create directory {}", input.path));
+                }
+                if input.purge_unmanaged {
+                    for file in input.unmanaged()? {
+                        if file.exists() {
+                            changes.push(format!("remove unmanaged {}", file.display()));
+                        }
+                    }
+                }
+            }
+            FileState::Absent => {
+                if std::fs::symlink_metadata(&path).is_ok() {
+                    changes.push(format!("remove {}", input.path));
+                }
+            }
+            FileState::Link | FileState::Hard => {
+                let src = input.src.as_deref().unwrap_or_default();
+                if !input.is_linked(src) {
+                    changes.push(format!("link {} to {src}", input.path));
+                }
+            }
+        }
+        changes.extend(input.apply_permissions(true)?);
+        Ok((!changes.is_empty()).then(|| changes.join(", ")))
+    }
+}
+
+#[cfg(unix)]
+fn symlink(src: &str, link: &Path) -> anyhow::Result<()> {
+    std::os::unix::fs::symlink(src, link).map_err(|e| anyhow!("can't link to {src}: {e}"))
+}
+
+#[cfg(not(unix))]
+fn symlink(_src: &str, _link: &Path) -> anyhow::Result<()> {
+    Err(anyhow!("symlinks are only supported on unix"))
+}
+
+/// The link is a hard link of the file, which isn't the case if the link is a symlink
+#[cfg(unix)]
+fn same_file(link: &Path, file: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::symlink_metadata(link), std::fs::metadata(file)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_link: &Path, _file: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_default_state() {
+        assert_input(
+            &FileAction::default(),
+            r#"path = "/tmp/file""#,
+            &FileAction {
+                path: "/tmp/file".to_string(),
+                state: FileState::File,
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn input_states() {
+        assert_input(
+            &FileAction::default(),
+            r#"
+                path = "/tmp/dir"
+                state = "directory"
+            "#,
+            &FileAction {
+                path: "/tmp/dir".to_string(),
+                state: FileState::Directory,
+                ..Default::default()
+            },
+        );
+        assert_input(
+            &FileAction::default(),
+            r#"
+                path = "/tmp/file"
+                state = "absent"
+            "#,
+            &FileAction {
+                path: "/tmp/file".to_string(),
+                state: FileState::Absent,
+                ..Default::default()
+            },
+        );
+        assert_input(
+            &FileAction::default(),
+            r#"
+                path = "/etc/nginx/conf.d"
+                state = "directory"
+                purge_unmanaged = true
+            "#,
+            &FileAction {
+                path: "/etc/nginx/conf.d".to_string(),
+                state: FileState::Directory,
+                purge_unmanaged: true,
+                ..Default::default()
+            },
+        );
+        assert_input(
+            &FileAction::default(),
+            r#"
+                path = "/etc/nginx/sites-enabled/app"
+                state = "link"
+                src = "/etc/nginx/sites-available/app"
+            "#,
+            &FileAction {
+                path: "/etc/nginx/sites-enabled/app".to_string(),
+                state: FileState::Link,
+                src: Some("/etc/nginx/sites-available/app".to_string()),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(
+            input_error(&FileAction::default(), r#"state = "file""#),
+            "can't find path in params, it's required"
+        );
+        assert_eq!(
+            input_error(
+                &FileAction::default(),
+                r#"
+                    path = "/tmp/file"
+                    state = "link"
+                "#
+            ),
+            r#"state type should be Enum of "file", "absent", "directory", "link", "hard""#
+        );
+        assert_eq!(
+            input_error(
+                &FileAction::default(),
+                r#"
+                    path = "/tmp/file"
+                    purge_unmanaged = true
+                "#
+            ),
+            "purge_unmanaged needs the state to be directory"
+        );
+        assert_eq!(
+            input_error(
+                &FileAction::default(),
+                r#"
+                    path = "/etc/nginx/sites-enabled/app"
+                    state = "link"
+                "#
+            ),
+            "file needs src in params when the state is link or hard"
+        );
+        assert_eq!(
+            input_error(
+                &FileAction::default(),
+                r#"
+                    path = "/tmp/file"
+                    mode = "0844"
+                "#
+            ),
+            r#"0844 isn't an octal mode, e.g. "0644""#
+        );
+        assert_eq!(
+            input_error(
+                &FileAction::default(),
+                r#"
+                    path = "/tmp/file"
+                    mode = "0644"
+                    recurse = true
+                "#
+            ),
+            "recurse needs the state to be directory"
+        );
+    }
+
+    #[test]
+    fn input_permissions() {
+        assert_input(
+            &FileAction::default(),
+            r#"
+                path = "/srv/app"
+                state = "directory"
+                owner = "deploy"
+                group = "www-data"
+                mode = "0750"
+                recurse = true
+            "#,
+            &FileAction {
+                path: "/srv/app".to_string(),
+                state: FileState::Directory,
+                owner: Some("deploy".to_string()),
+                group: Some("www-data".to_string()),
+                mode: Some(0o750),
+                recurse: true,
+                ..Default::default()
+            },
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn execute_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("app.conf").to_string_lossy().to_string();
+        let other = dir.path().join("other.conf").to_string_lossy().to_string();
+        std::fs::write(&src, "app").unwrap();
+        std::fs::write(&other, "other").unwrap();
+        let path = dir
+            .path()
+            .join("enabled.conf")
+            .to_string_lossy()
+            .to_string();
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let execute = |state: FileState, src: &str| {
+            let input = bincode::serialize(&FileAction {
+                path: path.clone(),
+                state,
+                src: Some(src.to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+            FileAction::default()
+                .execute(tiron_common::action::ActionId::new(), &input, &tx)
+                .unwrap()
+        };
+
+        assert_eq!(
+            execute(FileState::Link, &src),
+            format!("link {path} to {src}")
+        );
+        assert_eq!(std::fs::read_link(&path).unwrap(), PathBuf::from(&src));
+        assert_eq!(execute(FileState::Link, &src), "");
+        // the link points elsewhere, so it's changed
+        execute(FileState::Link, &other);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "other");
+
+        // the symlink is replaced with a hard link
+        execute(FileState::Hard, &src);
+        assert!(!std::fs::symlink_metadata(&path).unwrap().is_symlink());
+        assert!(same_file(Path::new(&path), Path::new(&src)));
+        assert_eq!(execute(FileState::Hard, &src), "");
     }
 }
@@ -0,0 +1,274 @@
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseType, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+/// Create a filesystem on a block device with `mkfs`. A device that already has
+/// the filesystem is left alone, and a device with another filesystem is only
+/// reformatted with `force`
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct FilesystemAction {
+    /// The path of the block device, e.g. `/dev/sdb1`
+    dev: String,
+    /// The type of the filesystem, e.g. `ext4`, `xfs`, `btrfs`, `vfat` or `swap`,
+    /// which is created with `mkfs.<fstype>`, or `mkswap` for `swap`
+    fstype: String,
+    /// Reformat the device when it has another filesystem, which destroys
+    /// the data on it. Default to `false`
+    force: bool,
+    /// The extra arguments of `mkfs`, e.g. `["-L", "data"]`
+    opts: Vec<String>,
+}
+
+impl FilesystemAction {
+    /// The type of the filesystem that's on the device already, from `blkid`
+    fn current(&self) -> Result<Option<String>> {
+        if !Path::new(&self.dev).exists() {
+            return Err(anyhow!("{} doesn't exist", self.dev));
+        }
+        let output = Command::new("blkid")
+            .args(["-o", "value", "-s", "TYPE", &self.dev])
+            .output()
+            .map_err(|e| anyhow!("can't run blkid: {e}"))?;
+        // blkid exits with 2 when it finds nothing on the device
+        match output.status.code() {
+            Some(0) => {
+                let fstype = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                Ok((!fstype.is_empty()).then_some(fstype))
+            }
+            Some(2) => Ok(None),
+            _ => Err(anyhow!(
+                "blkid can't probe {}: {}",
+                self.dev,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+        }
+    }
+
+    /// Whether the filesystem should be created on the device with what's on it,
+    /// which refuses to reformat it without `force`
+    fn should_create(&self, current: Option<&str>) -> Result<bool> {
+        match current {
+            None => Ok(true),
+            Some(current) if current == self.fstype => Ok(false),
+            Some(_) if self.force => Ok(true),
+            Some(current) => Err(anyhow!(
+                "{} already has a {current} filesystem, set force to reformat it as {}",
+                self.dev,
+                self.fstype
+            )),
+        }
+    }
+
+    /// The program and the args that create the filesystem
+    fn mkfs(&self) -> (String, Vec<String>) {
+        let program = match self.fstype.as_str() {
+            "swap" => "mkswap".to_string(),
+            fstype => format!("mkfs.{fstype}"),
+        };
+        let mut args = Vec::new();
+        // mkfs asks before it overwrites a signature on the device,
+        // which is only there if force allowed it
+        if self.force {
+            match self.fstype.as_str() {
+                "ext2" | "ext3" | "ext4" => args.push("-F".to_string()),
+                "xfs" | "btrfs" | "swap" => args.push("-f".to_string()),
+                _ => {}
+            }
+        }
+        args.extend(self.opts.iter().cloned());
+        args.push(self.dev.clone());
+        (program, args)
+    }
+}
+
+impl Action for FilesystemAction {
+    fn name(&self) -> String {
+        "filesystem".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "dev".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("dev").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "fstype".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("fstype")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "force".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("force")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "opts".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("opts").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (dev, dev_span) = params.expect_string_with_span(0);
+        if !Path::new(dev).is_absolute() {
+            return Error::new("dev should be an absolute path")
+                .with_origin(params.origin, dev_span)
+                .err();
+        }
+        let (fstype, fstype_span) = params.expect_string_with_span(1);
+        if fstype.is_empty()
+            || !fstype
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Error::new(format!("{fstype} isn't a valid filesystem type"))
+                .with_origin(params.origin, fstype_span)
+                .err();
+        }
+
+        let input = FilesystemAction {
+            dev: dev.to_string(),
+            fstype: fstype.to_string(),
+            force: params.bool(2).unwrap_or(false),
+            opts: params
+                .list(3)
+                .unwrap_or_default()
+                .iter()
+                .map(|v| v.expect_string().to_string())
+                .collect(),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: FilesystemAction = bincode::deserialize(input)?;
+        if !input.should_create(input.current()?.as_deref())? {
+            return Ok("".to_string());
+        }
+        let (program, args) = input.mkfs();
+        let status = run_command(id, tx, &program, &args)?;
+        if !status.success() {
+            return Err(anyhow!("{program} failed on {}", input.dev));
+        }
+        Ok(format!("create {} on {}", input.fstype, input.dev))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: FilesystemAction = bincode::deserialize(input)?;
+        if !input.should_create(input.current()?.as_deref())? {
+            return Ok(None);
+        }
+        Ok(Some(format!("create {} on {}", input.fstype, input.dev)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_filesystem() {
+        assert_input(
+            &FilesystemAction::default(),
+            r#"
+                dev    = "/dev/sdb1"
+                fstype = "ext4"
+                opts   = ["-L", "data"]
+            "#,
+            &FilesystemAction {
+                dev: "/dev/sdb1".to_string(),
+                fstype: "ext4".to_string(),
+                force: false,
+                opts: vec!["-L".to_string(), "data".to_string()],
+            },
+        );
+        assert_eq!(
+            input_error(
+                &FilesystemAction::default(),
+                r#"
+                    dev    = "sdb1"
+                    fstype = "ext4"
+                "#
+            ),
+            "dev should be an absolute path"
+        );
+        assert_eq!(
+            input_error(
+                &FilesystemAction::default(),
+                r#"
+                    dev    = "/dev/sdb1"
+                    fstype = "ext4 -F"
+                "#
+            ),
+            "ext4 -F isn't a valid filesystem type"
+        );
+    }
+
+    #[test]
+    fn refuse_to_reformat() {
+        let mut action = FilesystemAction {
+            dev: "/dev/sdb1".to_string(),
+            fstype: "xfs".to_string(),
+            force: false,
+            opts: Vec::new(),
+        };
+        assert!(action.should_create(None).unwrap());
+        assert!(!action.should_create(Some("xfs")).unwrap());
+        assert_eq!(
+            action.should_create(Some("ext4")).unwrap_err().to_string(),
+            "/dev/sdb1 already has a ext4 filesystem, set force to reformat it as xfs"
+        );
+        assert_eq!(
+            action.mkfs(),
+            ("mkfs.xfs".to_string(), vec!["/dev/sdb1".to_string()])
+        );
+
+        // force reformats another filesystem, but never the same one
+        action.force = true;
+        assert!(action.should_create(Some("ext4")).unwrap());
+        assert!(!action.should_create(Some("xfs")).unwrap());
+        assert_eq!(
+            action.mkfs(),
+            (
+                "mkfs.xfs".to_string(),
+                vec!["-f".to_string(), "/dev/sdb1".to_string()]
+            )
+        );
+    }
+}
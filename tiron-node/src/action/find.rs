@@ -0,0 +1,579 @@
+use std::{
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionOutputLevel},
+    error::Error,
+};
+
+use super::{
+    send_value, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue, ActionParamDoc,
+    ActionParamType, ActionParams,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FindType {
+    File,
+    Directory,
+    Any,
+}
+
+/// Find the files in directories on the remote machine by their names, age and size,
+/// e.g. the logs older than 30 days. The files that are found are kept as the result
+/// of the action in the run history, and they can be removed with `remove`
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct FindAction {
+    /// The absolute path or the list of the absolute paths of the directories to search in
+    paths: Vec<String>,
+    /// The glob patterns that the names of the files match any of, e.g. `["*.log", "*.gz"]`,
+    /// where `*` is any characters, `?` is one character and `[...]` is one of the characters
+    patterns: Vec<String>,
+    /// The regex that the names of the files match
+    regex: Option<String>,
+    /// Only the files that were modified at least this long ago, e.g. `"30d"`, with the unit
+    /// of `s`, `m`, `h`, `d` or `w`. With a `-` in front, e.g. `"-1h"`, only the files that
+    /// were modified less than this long ago
+    age: Option<i64>,
+    /// Only the files of at least this size, e.g. `"100m"`, with the unit of `b`, `k`, `m`
+    /// or `g`. With a `-` in front, e.g. `"-1k"`, only the files smaller than this
+    size: Option<i64>,
+    /// The type of what to find. Default to `file`
+    file_type: Option<FindType>,
+    /// Search in the subdirectories as well. Default to false
+    recurse: bool,
+    /// Remove the files that are found, and the directories with everything in them.
+    /// Default to false
+    remove: bool,
+}
+
+/// A file that's found, which is in the structured result of the action
+#[derive(Debug, Serialize)]
+struct FoundFile {
+    path: String,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    size: u64,
+    // unix timestamp in seconds
+    mtime: u64,
+}
+
+#[derive(Serialize)]
+struct FindResult {
+    matched: usize,
+    files: Vec<FoundFile>,
+}
+
+impl FindAction {
+    fn find(&self) -> Result<Vec<FoundFile>> {
+        let regex = self
+            .regex
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| anyhow!("regex isn't valid: {e}"))?;
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|pattern| glob_regex(pattern))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("pattern isn't valid: {e}"))?;
+        let now = SystemTime::now();
+        let mut found = Vec::new();
+        for dir in &self.paths {
+            let meta = std::fs::metadata(dir).map_err(|e| anyhow!("can't read {dir}: {e}"))?;
+            if !meta.is_dir() {
+                return Err(anyhow!("{dir} isn't a directory"));
+            }
+            let mut dirs = vec![PathBuf::from(dir)];
+            while let Some(dir) = dirs.pop() {
+                let entries = std::fs::read_dir(&dir)
+                    .map_err(|e| anyhow!("can't read {}: {e}", dir.display()))?;
+                for entry in entries {
+                    let entry = entry?;
+                    let path = entry.path();
+                    // the symlinks aren't followed, so that it stays in the directories
+                    let meta = std::fs::symlink_metadata(&path)?;
+                    if self.recurse && meta.is_dir() {
+                        dirs.push(path.clone());
+                    }
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let matched = (patterns.is_empty()
+                        || patterns.iter().any(|p| p.is_match(&name)))
+                        && regex.as_ref().map_or(true, |regex| regex.is_match(&name));
+                    if matched {
+                        if let Some(file) = self.found(&path, &meta, now) {
+                            found.push(file);
+                        }
+                    }
+                }
+            }
+        }
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(found)
+    }
+
+    /// The file if it's of the type, the age and the size to find
+    fn found(&self, path: &Path, meta: &Metadata, now: SystemTime) -> Option<FoundFile> {
+        let type_ = if meta.is_dir() {
+            "directory"
+        } else if meta.is_symlink() {
+            "symlink"
+        } else {
+            "file"
+        };
+        let type_matched = match self.file_type.unwrap_or(FindType::File) {
+            FindType::File => !meta.is_dir(),
+            FindType::Directory => meta.is_dir(),
+            FindType::Any => true,
+        };
+        if !type_matched {
+            return None;
+        }
+        let modified = meta.modified().ok()?;
+        if let Some(age) = self.age {
+            let elapsed = now.duration_since(modified).unwrap_or_default().as_secs() as i64;
+            if (age >= 0 && elapsed < age) || (age < 0 && elapsed >= -age) {
+                return None;
+            }
+        }
+        if let Some(size) = self.size {
+            let len = meta.len() as i64;
+            if (size >= 0 && len < size) || (size < 0 && len >= -size) {
+                return None;
+            }
+        }
+        Some(FoundFile {
+            path: path.to_string_lossy().to_string(),
+            type_,
+            size: meta.len(),
+            mtime: modified
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        })
+    }
+
+    /// Find the files, and send them as the result of the action
+    fn send(&self, id: ActionId, tx: &Sender<ActionMessage>) -> Result<Vec<FoundFile>> {
+        let files = self.find()?;
+        for file in &files {
+            let _ = tx.send(ActionMessage::ActionOutputLine {
+                id,
+                content: file.path.clone(),
+                level: ActionOutputLevel::Info,
+            });
+        }
+        let result = FindResult {
+            matched: files.len(),
+            files,
+        };
+        send_value(id, tx, &result)?;
+        Ok(result.files)
+    }
+}
+
+/// The regex of the glob pattern, which matches the whole name
+fn glob_regex(pattern: &str) -> Result<Regex, String> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                regex.push('[');
+                let mut first = true;
+                loop {
+                    let c = chars
+                        .next()
+                        .ok_or_else(|| format!("missing ] in {pattern}"))?;
+                    match c {
+                        '!' if first => regex.push('^'),
+                        ']' if !first => break,
+                        '\\' => regex.push_str("\\\\"),
+                        c => regex.push(c),
+                    }
+                    first = false;
+                }
+                regex.push(']');
+            }
+            '.' | '+' | '(' | ')' | '{' | '}' | '|' | '^' | '$' | '\\' => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|e| e.to_string())
+}
+
+/// Parse a number with a unit, e.g. `30d`, with a `-` in front for less than it
+fn parse_with_unit(s: &str, units: &[(char, i64)]) -> Option<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (n, multiplier) = match s.chars().last()? {
+        c if c.is_ascii_digit() => (s, 1),
+        c => {
+            let (_, multiplier) = units
+                .iter()
+                .find(|(unit, _)| *unit == c.to_ascii_lowercase())?;
+            (&s[..s.len() - c.len_utf8()], *multiplier)
+        }
+    };
+    let n = n.parse::<i64>().ok()?.checked_mul(multiplier)?;
+    Some(if negative { -n } else { n })
+}
+
+fn parse_age(s: &str) -> Option<i64> {
+    parse_with_unit(
+        s,
+        &[
+            ('s', 1),
+            ('m', 60),
+            ('h', 60 * 60),
+            ('d', 24 * 60 * 60),
+            ('w', 7 * 24 * 60 * 60),
+        ],
+    )
+}
+
+fn parse_size(s: &str) -> Option<i64> {
+    parse_with_unit(
+        s,
+        &[('b', 1), ('k', 1 << 10), ('m', 1 << 20), ('g', 1 << 30)],
+    )
+}
+
+impl Action for FindAction {
+    fn name(&self) -> String {
+        "find".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "paths".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("paths")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![
+                        ActionParamType::String,
+                        ActionParamType::List(ActionParamBaseType::String),
+                    ],
+                },
+                ActionParamDoc {
+                    name: "patterns".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("patterns")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "regex".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("regex")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "age".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("age").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "size".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("size").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "file_type".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("file_type")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("file".to_string()),
+                        ActionParamBaseValue::String("directory".to_string()),
+                        ActionParamBaseValue::String("any".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "recurse".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("recurse")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "remove".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("remove")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let paths = match params.values[0].as_ref() {
+            Some(paths) => match paths.string_with_span() {
+                Some((path, span)) => vec![(path.to_string(), span)],
+                None => paths
+                    .expect_list()
+                    .iter()
+                    .map(|v| (v.expect_string().to_string(), &params.span))
+                    .collect(),
+            },
+            None => Vec::new(),
+        };
+        if paths.is_empty() {
+            return Error::new("paths can't be empty")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        for (path, span) in &paths {
+            if !Path::new(path).is_absolute() {
+                return Error::new(format!("{path} should be an absolute path"))
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+        }
+        let patterns: Vec<String> = params
+            .list(1)
+            .map(|list| list.iter().map(|v| v.expect_string().to_string()).collect())
+            .unwrap_or_default();
+        for pattern in &patterns {
+            if let Err(e) = glob_regex(pattern) {
+                return Error::new(format!("pattern isn't valid: {e}"))
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+        }
+        let regex = match params.values[2].as_ref() {
+            Some(regex) => {
+                let (regex, span) = regex.expect_string_with_span();
+                if let Err(e) = Regex::new(regex) {
+                    return Error::new(format!("regex isn't valid: {e}"))
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+                Some(regex.to_string())
+            }
+            None => None,
+        };
+        let age = match params.values[3].as_ref() {
+            Some(age) => {
+                let (age, span) = age.expect_string_with_span();
+                let Some(age) = parse_age(age) else {
+                    return Error::new(format!("{age} isn't a valid age, e.g. \"30d\""))
+                        .with_origin(params.origin, span)
+                        .err();
+                };
+                Some(age)
+            }
+            None => None,
+        };
+        let size = match params.values[4].as_ref() {
+            Some(size) => {
+                let (size, span) = size.expect_string_with_span();
+                let Some(size) = parse_size(size) else {
+                    return Error::new(format!("{size} isn't a valid size, e.g. \"100m\""))
+                        .with_origin(params.origin, span)
+                        .err();
+                };
+                Some(size)
+            }
+            None => None,
+        };
+        let file_type = params.base(5).map(|t| match t.expect_string() {
+            "file" => FindType::File,
+            "directory" => FindType::Directory,
+            "any" => FindType::Any,
+            _ => unreachable!(),
+        });
+
+        let input = FindAction {
+            paths: paths.into_iter().map(|(path, _)| path).collect(),
+            patterns,
+            regex,
+            age,
+            size,
+            file_type,
+            recurse: params.bool(6).unwrap_or(false),
+            remove: params.bool(7).unwrap_or(false),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: FindAction = bincode::deserialize(input)?;
+        let files = input.send(id, tx)?;
+        if !input.remove {
+            return Ok(format!("found {} files", files.len()));
+        }
+        for file in &files {
+            let removed = if file.type_ == "directory" {
+                std::fs::remove_dir_all(&file.path)
+            } else {
+                std::fs::remove_file(&file.path)
+            };
+            match removed {
+                // it was in a directory that's removed before it
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow!("can't remove {}: {e}", file.path)),
+                Ok(()) => {}
+            }
+        }
+        Ok(format!("removed {} files", files.len()))
+    }
+
+    fn dry_run(
+        &self,
+        id: ActionId,
+        input: &[u8],
+        tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        // finding doesn't change anything, so it finds in the dry run as well
+        let input: FindAction = bincode::deserialize(input)?;
+        let files = input.send(id, tx)?;
+        Ok((input.remove && !files.is_empty()).then(|| format!("remove {} files", files.len())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_find() {
+        assert_input(
+            &FindAction::default(),
+            r#"
+                paths    = "/var/log/app"
+                patterns = ["*.log", "*.gz"]
+                age      = "30d"
+                size     = "-1m"
+                recurse  = true
+                remove   = true
+            "#,
+            &FindAction {
+                paths: vec!["/var/log/app".to_string()],
+                patterns: vec!["*.log".to_string(), "*.gz".to_string()],
+                regex: None,
+                age: Some(30 * 24 * 60 * 60),
+                size: Some(-(1 << 20)),
+                file_type: None,
+                recurse: true,
+                remove: true,
+            },
+        );
+        assert_eq!(
+            input_error(&FindAction::default(), r#"paths = ["log"]"#),
+            "log should be an absolute path"
+        );
+        assert_eq!(
+            input_error(
+                &FindAction::default(),
+                r#"
+                    paths = "/var/log"
+                    age   = "30 days"
+                "#
+            ),
+            "30 days isn't a valid age, e.g. \"30d\""
+        );
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let regex = glob_regex("app-?.[lg]*").unwrap();
+        assert!(regex.is_match("app-1.log"));
+        assert!(regex.is_match("app-2.gz.old"));
+        assert!(!regex.is_match("app-10.log"));
+        assert!(!regex.is_match("app-1xlog"));
+        assert!(glob_regex("[!.]*").unwrap().is_match("app.log"));
+        assert!(!glob_regex("[!.]*").unwrap().is_match(".hidden"));
+        assert!(glob_regex("*.log[").is_err());
+    }
+
+    #[test]
+    fn find_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("old")).unwrap();
+        std::fs::write(dir.path().join("app.log"), "started\n").unwrap();
+        std::fs::write(dir.path().join("app.conf"), "").unwrap();
+        std::fs::write(dir.path().join("old").join("app.1.log"), "").unwrap();
+        let action = FindAction {
+            paths: vec![dir.path().to_string_lossy().to_string()],
+            patterns: vec!["*.log".to_string()],
+            ..Default::default()
+        };
+        let found = |action: &FindAction| -> Vec<String> {
+            action
+                .find()
+                .unwrap()
+                .into_iter()
+                .map(|file| {
+                    Path::new(&file.path)
+                        .strip_prefix(dir.path())
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect()
+        };
+        assert_eq!(found(&action), ["app.log"]);
+        let action = FindAction {
+            recurse: true,
+            ..action
+        };
+        assert_eq!(found(&action), ["app.log", "old/app.1.log"]);
+        let action = FindAction {
+            size: Some(1),
+            ..action
+        };
+        assert_eq!(found(&action), ["app.log"]);
+        let action = FindAction {
+            size: None,
+            age: Some(60 * 60),
+            ..action
+        };
+        assert!(found(&action).is_empty());
+    }
+
+    #[test]
+    fn parse_ages_and_sizes() {
+        assert_eq!(parse_age("90"), Some(90));
+        assert_eq!(parse_age("2h"), Some(2 * 60 * 60));
+        assert_eq!(parse_age("-1w"), Some(-7 * 24 * 60 * 60));
+        assert_eq!(parse_age("1y"), None);
+        assert_eq!(parse_size("10K"), Some(10 * 1024));
+        assert_eq!(parse_size("-1g"), Some(-(1 << 30)));
+        assert_eq!(parse_size("m"), None);
+    }
+}
@@ -0,0 +1,29 @@
+use tiron_common::{
+    crypto::{hex, sha256},
+    node::{Resource, ResourceState},
+};
+
+use super::{package, service};
+
+/// The state of the resource on this host
+pub fn resource_state(resource: &Resource) -> ResourceState {
+    let state = match resource {
+        Resource::File { path } => match std::fs::read(path) {
+            Ok(content) => Ok(ResourceState::File {
+                hash: Some(hex(&sha256(&content))),
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(ResourceState::File { hash: None })
+            }
+            Err(e) => Err(e.into()),
+        },
+        Resource::Package { name } => {
+            package::installed(name).map(|installed| ResourceState::Package { installed })
+        }
+        Resource::Service { name } => service::service_state(name)
+            .map(|(active, enabled)| ResourceState::Service { active, enabled }),
+    };
+    state.unwrap_or_else(|e: anyhow::Error| ResourceState::Unknown {
+        error: e.to_string(),
+    })
+}
@@ -0,0 +1,288 @@
+mod provider;
+
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionRollback},
+    error::Error,
+};
+
+use self::provider::FirewallProvider;
+
+use super::{
+    Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FirewallProto {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl FirewallProto {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FirewallProto::Tcp => "tcp",
+            FirewallProto::Udp => "udp",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FirewallState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Open a port in the firewall of the remote machine, with firewalld if it's running,
+/// or with ufw otherwise
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct FirewallAction {
+    /// The port, or the range of ports, e.g. `443` or `8000-8100`
+    port: String,
+    /// Default to `tcp`
+    proto: FirewallProto,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the port is allowed.
+    ///
+    /// If `absent`, the rule that allows the port is removed.
+    state: FirewallState,
+    /// The zone of firewalld, e.g. `public`. Default to the default zone.
+    /// ufw doesn't have zones, so it's ignored for ufw
+    zone: Option<String>,
+}
+
+impl FirewallAction {
+    /// The rule for ufw, which has `:` in the ranges
+    fn ufw_rule(&self) -> String {
+        format!("{}/{}", self.port.replace('-', ":"), self.proto.as_str())
+    }
+
+    /// The args of `firewall-cmd` to query, add or remove the port
+    fn firewalld_args(&self, op: &str, permanent: bool) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(zone) = &self.zone {
+            args.push(format!("--zone={zone}"));
+        }
+        if permanent {
+            args.push("--permanent".to_string());
+        }
+        args.push(format!("{op}={}/{}", self.port, self.proto.as_str()));
+        args
+    }
+
+    /// Whether the port is already as it should be
+    fn is_current(&self, provider: &FirewallProvider) -> anyhow::Result<bool> {
+        Ok(provider.is_open(self)? == (self.state == FirewallState::Present))
+    }
+
+    fn change(&self) -> String {
+        let change = match self.state {
+            FirewallState::Present => "allow",
+            FirewallState::Absent => "remove",
+        };
+        format!("{change} {}/{}", self.port, self.proto.as_str())
+    }
+}
+
+/// The port like `443`, or the range like `8000-8100` where the first port is lower
+fn parse_port(port: &str) -> Option<String> {
+    let parse = |port: &str| port.parse::<u16>().ok().filter(|port| *port > 0);
+    match port.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            (start < end).then(|| format!("{start}-{end}"))
+        }
+        None => parse(port).map(|port| port.to_string()),
+    }
+}
+
+impl Action for FirewallAction {
+    fn name(&self) -> String {
+        "firewall".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "port".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("port").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "proto".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("proto")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("tcp".to_string()),
+                        ActionParamBaseValue::String("udp".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "zone".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("zone").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (port, port_span) = params.expect_string_with_span(0);
+        let Some(port) = parse_port(port) else {
+            return Error::new(format!(
+                "{port} isn't a valid port, it should be like 443 or 8000-8100"
+            ))
+            .with_origin(params.origin, port_span)
+            .err();
+        };
+        let proto = match params.base(1).map(|proto| proto.expect_string()) {
+            Some("udp") => FirewallProto::Udp,
+            _ => FirewallProto::Tcp,
+        };
+        let state = match params.base(2).map(|state| state.expect_string()) {
+            Some("absent") => FirewallState::Absent,
+            _ => FirewallState::Present,
+        };
+        let zone = match params.values[3].as_ref() {
+            Some(zone) => {
+                let (zone, span) = zone.expect_string_with_span();
+                if zone.is_empty() || zone.contains(char::is_whitespace) {
+                    return Error::new(format!("{zone} isn't a valid zone"))
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+                Some(zone.to_string())
+            }
+            None => None,
+        };
+
+        let input = FirewallAction {
+            port,
+            proto,
+            state,
+            zone,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(
+        &self,
+        id: ActionId,
+        input: &[u8],
+        tx: &Sender<ActionMessage>,
+    ) -> anyhow::Result<String> {
+        let input: FirewallAction = bincode::deserialize(input)?;
+        let provider = FirewallProvider::detect()?;
+        if input.is_current(&provider)? {
+            return Ok("".to_string());
+        }
+        provider.run(id, tx, &input)?;
+        Ok(input.change())
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> anyhow::Result<Option<String>> {
+        let input: FirewallAction = bincode::deserialize(input)?;
+        let provider = FirewallProvider::detect()?;
+        if input.is_current(&provider)? {
+            return Ok(None);
+        }
+        Ok(Some(input.change()))
+    }
+
+    fn rollback(&self, input: &[u8]) -> anyhow::Result<Option<ActionRollback>> {
+        let input: FirewallAction = bincode::deserialize(input)?;
+        if input.is_current(&FirewallProvider::detect()?)? {
+            return Ok(None);
+        }
+        let state = match input.state {
+            FirewallState::Present => FirewallState::Absent,
+            FirewallState::Absent => FirewallState::Present,
+        };
+        Ok(Some(ActionRollback {
+            action: self.name(),
+            input: bincode::serialize(&FirewallAction { state, ..input })?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_firewall() {
+        assert_input(
+            &FirewallAction::default(),
+            r#"
+                port  = "8000-8100"
+                proto = "udp"
+                zone  = "public"
+            "#,
+            &FirewallAction {
+                port: "8000-8100".to_string(),
+                proto: FirewallProto::Udp,
+                state: FirewallState::Present,
+                zone: Some("public".to_string()),
+            },
+        );
+        assert_eq!(
+            input_error(&FirewallAction::default(), r#"port = "8100-8000""#),
+            "8100-8000 isn't a valid port, it should be like 443 or 8000-8100"
+        );
+        assert_eq!(
+            input_error(&FirewallAction::default(), r#"port = "http""#),
+            "http isn't a valid port, it should be like 443 or 8000-8100"
+        );
+    }
+
+    #[test]
+    fn provider_rules() {
+        let rule = FirewallAction {
+            port: "8000-8100".to_string(),
+            proto: FirewallProto::Tcp,
+            state: FirewallState::Present,
+            zone: Some("public".to_string()),
+        };
+        assert_eq!(rule.ufw_rule(), "8000:8100/tcp");
+        assert_eq!(
+            rule.firewalld_args("--add-port", true),
+            vec!["--zone=public", "--permanent", "--add-port=8000-8100/tcp"]
+        );
+
+        let added = "Added user rules (see 'ufw status' for running firewall):\nufw allow 22/tcp\nufw allow 8000:8100/tcp\n";
+        assert!(provider::ufw_has_rule(added, "8000:8100/tcp"));
+        assert!(!provider::ufw_has_rule(added, "80/tcp"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use tiron_common::action::{ActionId, ActionMessage};
+
+use crate::action::command::run_command;
+
+use super::{FirewallAction, FirewallState};
+
+pub enum FirewallProvider {
+    Ufw,
+    Firewalld,
+}
+
+impl FirewallProvider {
+    /// firewalld when it's running, otherwise ufw when it's installed
+    pub fn detect() -> Result<Self> {
+        let firewalld = Command::new("firewall-cmd")
+            .arg("--state")
+            .output()
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+        if firewalld {
+            return Ok(Self::Firewalld);
+        }
+        if Command::new("ufw").arg("version").output().is_ok() {
+            return Ok(Self::Ufw);
+        }
+        Err(anyhow!("Can't find ufw or a running firewalld on the host"))
+    }
+
+    /// Whether the port is open, which for firewalld is both now and after a reload
+    pub fn is_open(&self, rule: &FirewallAction) -> Result<bool> {
+        match self {
+            FirewallProvider::Ufw => {
+                // the added rules are there even when ufw isn't enabled
+                let output = Command::new("ufw").args(["show", "added"]).output()?;
+                if !output.status.success() {
+                    return Err(anyhow!("can't get the rules of ufw"));
+                }
+                Ok(ufw_has_rule(
+                    &String::from_utf8_lossy(&output.stdout),
+                    &rule.ufw_rule(),
+                ))
+            }
+            FirewallProvider::Firewalld => {
+                for permanent in [false, true] {
+                    let output = Command::new("firewall-cmd")
+                        .args(rule.firewalld_args("--query-port", permanent))
+                        .output()?;
+                    if !output.status.success() {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    pub fn run(
+        &self,
+        id: ActionId,
+        tx: &Sender<ActionMessage>,
+        rule: &FirewallAction,
+    ) -> Result<()> {
+        match self {
+            FirewallProvider::Ufw => {
+                let mut args = Vec::new();
+                if rule.state == FirewallState::Absent {
+                    args.push("delete".to_string());
+                }
+                args.push("allow".to_string());
+                args.push(rule.ufw_rule());
+                if !run_command(id, tx, "ufw", &args)?.success() {
+                    return Err(anyhow!("ufw failed"));
+                }
+            }
+            FirewallProvider::Firewalld => {
+                let op = match rule.state {
+                    FirewallState::Present => "--add-port",
+                    FirewallState::Absent => "--remove-port",
+                };
+                // the runtime rules change now, and the permanent ones after a reload
+                for permanent in [false, true] {
+                    let args = rule.firewalld_args(op, permanent);
+                    if !run_command(id, tx, "firewall-cmd", &args)?.success() {
+                        return Err(anyhow!("firewall-cmd failed"));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the output of `ufw show added` has the allow rule
+pub fn ufw_has_rule(added: &str, rule: &str) -> bool {
+    let rule = format!("ufw allow {rule}");
+    added.lines().any(|line| line.trim() == rule)
+}
@@ -75,4 +75,50 @@ fn execute(
             Err(anyhow!("command failed"))
         }
     }
+
+    fn dry_run(
+        &self,
+        _id: tiron_common::action::ActionId,
+        input: &[u8],
+        _tx: &crossbeam_channel::Sender<tiron_common::action::ActionMessage>,
+    ) -> anyhow::Result<Option<String>> {
+        let input: GitAction = bincode::deserialize(input)?;
+        if std::path::Path::new(&input.dest).join(".git").exists() {
+            Ok(None)
+        } else {
+            Ok(Some(format!("clone {} to {}", input.repo, input.dest)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input() {
+        assert_input(
+            &GitAction::default(),
+            r#"
+                repo = "https://github.com/lapce/tiron"
+                dest = "/tmp/tiron"
+            "#,
+            &GitAction {
+                repo: "https://github.com/lapce/tiron".to_string(),
+                dest: "/tmp/tiron".to_string(),
+            },
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(
+            input_error(
+                &GitAction::default(),
+                r#"repo = "https://github.com/lapce/tiron""#
+            ),
+            "can't find dest in params, it's required"
+        );
+    }
 }
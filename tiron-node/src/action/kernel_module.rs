@@ -0,0 +1,351 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue,
+    ActionParamDoc, ActionParamType, ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModuleState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Load or unload a kernel module with `modprobe`, and keep it loaded at boot
+/// with a file in `/etc/modules-load.d`
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct KernelModuleAction {
+    /// The name of the kernel module, e.g. `br_netfilter`
+    name: String,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the module is loaded if it isn't.
+    ///
+    /// If `absent`, the module is unloaded with `modprobe -r` if it's loaded.
+    state: ModuleState,
+    /// The options of the module, e.g. `["max_loop=64"]`. They're only used
+    /// when the module is loaded, so a module that's already loaded keeps its options
+    params: Vec<String>,
+    /// Default to `true`<br>
+    ///
+    /// If `true`, the module is written to `/etc/modules-load.d/<name>.conf` so that
+    /// it's loaded at boot, and the options to `/etc/modprobe.d/<name>.conf`.
+    /// Both files are removed when the state is `absent`.
+    ///
+    /// If `false`, only the running kernel is changed.
+    persistent: bool,
+}
+
+impl KernelModuleAction {
+    fn load_path(&self) -> PathBuf {
+        PathBuf::from("/etc/modules-load.d").join(format!("{}.conf", self.name))
+    }
+
+    fn options_path(&self) -> PathBuf {
+        PathBuf::from("/etc/modprobe.d").join(format!("{}.conf", self.name))
+    }
+
+    fn options(&self) -> String {
+        format!("options {} {}\n", self.name, self.params.join(" "))
+    }
+
+    /// The module is in `/proc/modules`, where its name has `_` instead of `-`
+    fn is_loaded(&self) -> bool {
+        let name = self.name.replace('-', "_");
+        std::fs::read_to_string("/proc/modules")
+            .map(|modules| {
+                modules
+                    .lines()
+                    .any(|line| line.split_whitespace().next() == Some(name.as_str()))
+            })
+            .unwrap_or(false)
+    }
+
+    /// The files that should be written with their content, and the ones that should be removed
+    fn files(&self) -> (Vec<(PathBuf, String)>, Vec<PathBuf>) {
+        if !self.persistent {
+            return (Vec::new(), Vec::new());
+        }
+        match self.state {
+            ModuleState::Present => {
+                let mut write = vec![(self.load_path(), format!("{}\n", self.name))];
+                if !self.params.is_empty() {
+                    write.push((self.options_path(), self.options()));
+                }
+                (write, Vec::new())
+            }
+            ModuleState::Absent => (Vec::new(), vec![self.load_path(), self.options_path()]),
+        }
+    }
+
+    /// What would change on the machine, which is empty if the module is already as it should be
+    fn changes(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+        match (self.state, self.is_loaded()) {
+            (ModuleState::Present, false) => changes.push(format!("load {}", self.name)),
+            (ModuleState::Absent, true) => changes.push(format!("unload {}", self.name)),
+            _ => {}
+        }
+        let (write, remove) = self.files();
+        for (path, content) in write {
+            if std::fs::read_to_string(&path).ok().as_deref() != Some(content.as_str()) {
+                changes.push(format!("write {}", path.display()));
+            }
+        }
+        for path in remove {
+            if path.exists() {
+                changes.push(format!("remove {}", path.display()));
+            }
+        }
+        changes
+    }
+}
+
+impl Action for KernelModuleAction {
+    fn name(&self) -> String {
+        "kernel_module".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "params".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("params")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "persistent".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("persistent")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (name, name_span) = params.expect_string_with_span(0);
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Error::new(format!("{name} isn't a valid kernel module name"))
+                .with_origin(params.origin, name_span)
+                .err();
+        }
+        let state = match params.base(1).map(|state| state.expect_string()) {
+            Some("absent") => ModuleState::Absent,
+            _ => ModuleState::Present,
+        };
+        let mut options = Vec::new();
+        for value in params.list(2).unwrap_or_default() {
+            let option = value.expect_string();
+            if option.is_empty() || option.contains(char::is_whitespace) {
+                return Error::new(format!(
+                    "\"{option}\" isn't a valid module option, it should be like key=value"
+                ))
+                .with_origin(params.origin, &params.span)
+                .err();
+            }
+            options.push(option.to_string());
+        }
+
+        let input = KernelModuleAction {
+            name: name.to_string(),
+            state,
+            params: options,
+            persistent: params.bool(3).unwrap_or(true),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: KernelModuleAction = bincode::deserialize(input)?;
+        let changes = input.changes();
+        if changes.is_empty() {
+            return Ok("".to_string());
+        }
+
+        // the options are written first, so that modprobe loads the module with them
+        let (write, remove) = input.files();
+        for (path, content) in write {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| anyhow!("can't create {}: {e}", dir.display()))?;
+            }
+            std::fs::write(&path, content)
+                .map_err(|e| anyhow!("can't write {}: {e}", path.display()))?;
+        }
+
+        match (input.state, input.is_loaded()) {
+            (ModuleState::Present, false) => {
+                let mut args = vec![input.name.clone()];
+                args.extend(input.params.iter().cloned());
+                let status = run_command(id, tx, "modprobe", &args)?;
+                if !status.success() {
+                    return Err(anyhow!("modprobe can't load {}", input.name));
+                }
+            }
+            (ModuleState::Absent, true) => {
+                let status =
+                    run_command(id, tx, "modprobe", &["-r".to_string(), input.name.clone()])?;
+                if !status.success() {
+                    return Err(anyhow!("modprobe can't unload {}", input.name));
+                }
+            }
+            _ => {}
+        }
+
+        for path in remove {
+            if path.exists() {
+                std::fs::remove_file(&path)
+                    .map_err(|e| anyhow!("can't remove {}: {e}", path.display()))?;
+            }
+        }
+
+        Ok(changes.join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: KernelModuleAction = bincode::deserialize(input)?;
+        let changes = input.changes();
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(changes.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_kernel_module() {
+        assert_input(
+            &KernelModuleAction::default(),
+            r#"
+                name   = "loop"
+                params = ["max_loop=64"]
+            "#,
+            &KernelModuleAction {
+                name: "loop".to_string(),
+                state: ModuleState::Present,
+                params: vec!["max_loop=64".to_string()],
+                persistent: true,
+            },
+        );
+        assert_input(
+            &KernelModuleAction::default(),
+            r#"
+                name       = "br_netfilter"
+                state      = "absent"
+                persistent = false
+            "#,
+            &KernelModuleAction {
+                name: "br_netfilter".to_string(),
+                state: ModuleState::Absent,
+                params: Vec::new(),
+                persistent: false,
+            },
+        );
+        assert_eq!(
+            input_error(&KernelModuleAction::default(), r#"name = "../loop""#),
+            "../loop isn't a valid kernel module name"
+        );
+        assert_eq!(
+            input_error(
+                &KernelModuleAction::default(),
+                r#"
+                    name   = "loop"
+                    params = ["max_loop=64 max_part=8"]
+                "#
+            ),
+            "\"max_loop=64 max_part=8\" isn't a valid module option, it should be like key=value"
+        );
+    }
+
+    #[test]
+    fn persistent_files() {
+        let module = KernelModuleAction {
+            name: "loop".to_string(),
+            state: ModuleState::Present,
+            params: vec!["max_loop=64".to_string(), "max_part=8".to_string()],
+            persistent: true,
+        };
+        let (write, remove) = module.files();
+        assert_eq!(
+            write,
+            vec![
+                (
+                    PathBuf::from("/etc/modules-load.d/loop.conf"),
+                    "loop\n".to_string()
+                ),
+                (
+                    PathBuf::from("/etc/modprobe.d/loop.conf"),
+                    "options loop max_loop=64 max_part=8\n".to_string()
+                ),
+            ]
+        );
+        assert!(remove.is_empty());
+
+        let module = KernelModuleAction {
+            state: ModuleState::Absent,
+            ..module
+        };
+        let (write, remove) = module.files();
+        assert!(write.is_empty());
+        assert_eq!(
+            remove,
+            vec![
+                PathBuf::from("/etc/modules-load.d/loop.conf"),
+                PathBuf::from("/etc/modprobe.d/loop.conf"),
+            ]
+        );
+    }
+}
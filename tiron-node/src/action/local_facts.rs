@@ -0,0 +1,281 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use crate::facts::LOCAL_FACTS_DIR;
+
+use super::{
+    Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LocalFactsState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Write the facts to `/etc/tiron/facts.d/<name>.json` on the remote machine, which the
+/// later runs get as `facts.local.<name>`, e.g. to record on the host itself that its
+/// database schema has been migrated to v42
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct LocalFactsAction {
+    /// The name of the facts, which is the file name without `.json`
+    name: String,
+    /// The facts to write, e.g. `{ schema = "v42" }`. The other facts that are
+    /// in the file already are kept
+    facts: Vec<(String, String)>,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the facts are written.
+    ///
+    /// If `absent`, the facts are removed from the file, or the whole file
+    /// if there aren't any facts in params.
+    state: LocalFactsState,
+}
+
+impl LocalFactsAction {
+    fn path(&self) -> PathBuf {
+        PathBuf::from(LOCAL_FACTS_DIR).join(format!("{}.json", self.name))
+    }
+
+    /// The content of the file with the facts updated from the current content,
+    /// which is None if the file should be removed
+    fn updated(&self, current: Option<&str>) -> Result<Option<String>> {
+        if self.state == LocalFactsState::Absent && self.facts.is_empty() {
+            return Ok(None);
+        }
+        let mut object = match current {
+            Some(current) => match serde_json::from_str(current) {
+                Ok(serde_json::Value::Object(object)) => object,
+                _ => {
+                    return Err(anyhow!(
+                        "{} isn't a JSON object, so the facts can't be updated",
+                        self.path().display()
+                    ))
+                }
+            },
+            None => serde_json::Map::new(),
+        };
+        for (key, value) in &self.facts {
+            match self.state {
+                LocalFactsState::Present => {
+                    object.insert(key.clone(), serde_json::Value::String(value.clone()));
+                }
+                LocalFactsState::Absent => {
+                    object.remove(key);
+                }
+            }
+        }
+        let mut content = serde_json::to_string_pretty(&object)?;
+        content.push('\n');
+        Ok(Some(content))
+    }
+
+    /// What would change on the machine, or None if the facts are already as they should be
+    fn change(&self) -> Result<Option<(&'static str, Option<String>)>> {
+        let current = match std::fs::read_to_string(self.path()) {
+            Ok(current) => Some(current),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(anyhow!("can't read {}: {e}", self.path().display())),
+        };
+        let updated = self.updated(current.as_deref())?;
+        let change = match (&current, &updated) {
+            (None, None) => None,
+            (Some(_), None) => Some("remove"),
+            (None, Some(_)) if self.state == LocalFactsState::Absent => None,
+            (current, Some(updated)) if current.as_ref() == Some(updated) => None,
+            _ => Some("write"),
+        };
+        Ok(change.map(|change| (change, updated)))
+    }
+
+    /// Write the file with a rename, so that the node never reads half of it
+    fn write(&self, content: &str) -> Result<()> {
+        std::fs::create_dir_all(LOCAL_FACTS_DIR)
+            .map_err(|e| anyhow!("can't create {LOCAL_FACTS_DIR}: {e}"))?;
+        let mut temp = tempfile::NamedTempFile::new_in(LOCAL_FACTS_DIR)?;
+        temp.write_all(content.as_bytes())?;
+        temp.flush()?;
+        // the temp file is only readable by the owner
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp.as_file()
+                .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+        }
+        temp.persist(self.path())
+            .map_err(|e| anyhow!("can't write {}: {e}", self.path().display()))?;
+        Ok(())
+    }
+}
+
+impl Action for LocalFactsAction {
+    fn name(&self) -> String {
+        "local_facts".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "facts".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("facts")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Map(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (name, name_span) = params.expect_string_with_span(0);
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Error::new("the name of the facts should be a file name without /")
+                .with_origin(params.origin, name_span)
+                .err();
+        }
+        let facts = params
+            .map(1)
+            .unwrap_or_default()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.expect_string().to_string()))
+            .collect::<Vec<_>>();
+        let state = match params.base(2).map(|state| state.expect_string()) {
+            Some("absent") => LocalFactsState::Absent,
+            _ => LocalFactsState::Present,
+        };
+        if state == LocalFactsState::Present && facts.is_empty() {
+            return Error::new("local_facts needs facts in params when the state is present")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        let input = LocalFactsAction {
+            name: name.to_string(),
+            facts,
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, _id: ActionId, input: &[u8], _tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: LocalFactsAction = bincode::deserialize(input)?;
+        let Some((change, content)) = input.change()? else {
+            return Ok("".to_string());
+        };
+        match content {
+            Some(content) => input.write(&content)?,
+            None => std::fs::remove_file(input.path())?,
+        }
+        Ok(format!("{change} {}", input.path().display()))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: LocalFactsAction = bincode::deserialize(input)?;
+        Ok(input
+            .change()?
+            .map(|(change, _)| format!("{change} {}", input.path().display())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_local_facts() {
+        assert_input(
+            &LocalFactsAction::default(),
+            r#"
+                name  = "app"
+                facts = { schema = "v42" }
+            "#,
+            &LocalFactsAction {
+                name: "app".to_string(),
+                facts: vec![("schema".to_string(), "v42".to_string())],
+                state: LocalFactsState::Present,
+            },
+        );
+        assert_eq!(
+            input_error(&LocalFactsAction::default(), r#"name = "app""#),
+            "local_facts needs facts in params when the state is present"
+        );
+        assert_eq!(
+            input_error(
+                &LocalFactsAction::default(),
+                r#"
+                    name  = "../app"
+                    state = "absent"
+                "#
+            ),
+            "the name of the facts should be a file name without /"
+        );
+    }
+
+    #[test]
+    fn update_facts() {
+        let mut action = LocalFactsAction {
+            name: "app".to_string(),
+            facts: vec![("schema".to_string(), "v42".to_string())],
+            state: LocalFactsState::Present,
+        };
+        assert_eq!(
+            action
+                .updated(Some(r#"{ "schema": "v41", "owner": "ops" }"#))
+                .unwrap()
+                .unwrap(),
+            "{\n  \"owner\": \"ops\",\n  \"schema\": \"v42\"\n}\n"
+        );
+        assert!(action.updated(Some("[]")).is_err());
+
+        action.state = LocalFactsState::Absent;
+        assert_eq!(
+            action
+                .updated(Some(r#"{ "schema": "v41", "owner": "ops" }"#))
+                .unwrap()
+                .unwrap(),
+            "{\n  \"owner\": \"ops\"\n}\n"
+        );
+        action.facts.clear();
+        assert_eq!(action.updated(Some("{}")).unwrap(), None);
+    }
+}
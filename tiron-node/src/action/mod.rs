@@ -1,26 +1,73 @@
+mod acl;
+mod archive;
 mod command;
 mod copy;
+mod cron;
 pub mod data;
+mod debug;
+mod docker_compose;
+mod fetch;
 mod file;
+mod filesystem;
+mod find;
+mod fingerprint;
+mod firewall;
 mod git;
+mod kernel_module;
+mod local_facts;
+mod mount;
+mod mysql;
 mod package;
-
-use std::{collections::HashMap, fmt::Display, ops::Range};
+mod permission;
+mod reboot;
+mod selinux;
+mod service;
+mod shell;
+mod snapshot;
+mod stat;
+mod synchronize;
+mod systemd_snippet;
+mod systemd_unit;
+mod template;
+#[cfg(test)]
+mod testing;
+mod unarchive;
+mod user;
+mod wait_for;
+
+use std::{collections::HashMap, fmt::Display, ops::Range, path::Path};
 
 use crossbeam_channel::Sender;
+use hcl::eval::Context;
+use hcl_edit::structure::Structure;
 use itertools::Itertools;
+use serde::Serialize;
 use tiron_common::{
-    action::{ActionId, ActionMessage},
+    action::{ActionId, ActionMessage, ActionRollback},
     error::{Error, Origin},
+    node::Resource,
     value::SpannedValue,
 };
 
+pub use copy::CopyAction;
+pub use fetch::FetchAction;
+pub use file::FileAction;
+pub use fingerprint::resource_state;
+pub use reboot::RebootAction;
+pub use synchronize::{RsyncRemote, SynchronizeAction};
+
 pub trait Action {
     /// name of the action
     fn name(&self) -> String;
 
     fn doc(&self) -> ActionDoc;
 
+    /// The version of the action, which runbooks can require in `requires`.
+    /// The actions that come with tiron have the version of tiron
+    fn version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+
     fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error>;
 
     fn execute(
@@ -29,6 +76,85 @@ fn execute(
         input: &[u8],
         tx: &Sender<ActionMessage>,
     ) -> anyhow::Result<String>;
+
+    /// Check what executing the action would change without changing anything.
+    /// Returns the description of the change, or None if nothing would change.
+    ///
+    /// Actions that can't tell always report that they would run.
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        _input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> anyhow::Result<Option<String>> {
+        Ok(Some(format!("run {}", self.name())))
+    }
+
+    /// The action that undoes executing the action with the input, which is worked out
+    /// right before the action is executed.
+    ///
+    /// Returns None if the action can't be undone.
+    fn rollback(&self, _input: &[u8]) -> anyhow::Result<Option<ActionRollback>> {
+        Ok(None)
+    }
+
+    /// The resources on the host that the action with the input manages,
+    /// which go in the manifest of the host for the drift report
+    fn resources(&self, _input: &[u8]) -> Vec<Resource> {
+        Vec::new()
+    }
+}
+
+/// Send the structured result of the action, which is kept with its outcome
+fn send_value(
+    id: ActionId,
+    tx: &Sender<ActionMessage>,
+    value: &impl Serialize,
+) -> anyhow::Result<()> {
+    tx.send(ActionMessage::ActionValue {
+        id,
+        value: serde_json::to_string(value)?,
+    })?;
+    Ok(())
+}
+
+/// The origin for parsing the params from HCL, which is the content of a `params` block.
+/// Paths in the params are relative to `cwd`.
+pub fn params_origin(cwd: &Path, hcl: &str) -> Origin {
+    Origin {
+        cwd: cwd.to_path_buf(),
+        path: cwd.join("params.tr"),
+        data: hcl.to_string(),
+    }
+}
+
+/// Parse the params of the action from the HCL in the origin, the same way as
+/// in a runbook, so that the params can be checked without a runbook.
+pub fn params_from_hcl<'a>(
+    action: &dyn Action,
+    origin: &'a Origin,
+) -> Result<ActionParams<'a>, Error> {
+    let body = hcl_edit::parser::parse_body(&origin.data)
+        .map_err(|e| Error::from_hcl(e, origin.path.clone()))?;
+
+    let ctx = Context::new();
+    let mut attrs = HashMap::new();
+    for s in body.iter() {
+        if let Structure::Attribute(a) = s {
+            let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+            attrs.insert(a.key.to_string(), v);
+        }
+    }
+
+    action.doc().parse_attrs(origin, &attrs)
+}
+
+/// Parse the params of the action from HCL and returns the serialized input of the action.
+/// Paths in the params are relative to `cwd`.
+pub fn input_from_hcl(action: &dyn Action, cwd: &Path, hcl: &str) -> Result<Vec<u8>, Error> {
+    let origin = params_origin(cwd, hcl);
+    let params = params_from_hcl(action, &origin)?;
+    action.input(params)
 }
 
 pub enum ActionParamBaseType {
@@ -59,7 +185,11 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 pub enum ActionParamType {
     String,
     Bool,
+    // a whole number that's not negative, e.g. an id
+    Number,
     List(ActionParamBaseType),
+    // an object with the values of the type, e.g. the environment variables
+    Map(ActionParamBaseType),
     Enum(Vec<ActionParamBaseValue>),
 }
 
@@ -79,6 +209,14 @@ fn parse_attr(&self, value: &SpannedValue) -> Option<ActionParamValue> {
                     return Some(ActionParamValue::Bool(*v.value()));
                 }
             }
+            ActionParamType::Number => {
+                if let SpannedValue::Number(v) = value {
+                    return Some(ActionParamValue::Number(
+                        v.value().as_u64()?,
+                        value.span().to_owned(),
+                    ));
+                }
+            }
             ActionParamType::List(base) => {
                 if let SpannedValue::Array(v) = value {
                     let mut items = Vec::new();
@@ -89,6 +227,16 @@ fn parse_attr(&self, value: &SpannedValue) -> Option<ActionParamValue> {
                     return Some(ActionParamValue::List(items));
                 }
             }
+            ActionParamType::Map(base) => {
+                if let SpannedValue::Object(v) = value {
+                    let mut items = Vec::new();
+                    for (key, v) in v.value().iter() {
+                        let base = base.parse_value(v)?;
+                        items.push((key.clone(), base));
+                    }
+                    return Some(ActionParamValue::Map(items));
+                }
+            }
             ActionParamType::Enum(options) => {
                 for option in options {
                     if option.match_value_new(value) {
@@ -107,7 +255,9 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ActionParamType::String => f.write_str("String"),
             ActionParamType::Bool => f.write_str("Boolean"),
+            ActionParamType::Number => f.write_str("Number"),
             ActionParamType::List(t) => f.write_str(&format!("List of {t}")),
+            ActionParamType::Map(t) => f.write_str(&format!("Map of {t}")),
             ActionParamType::Enum(t) => f.write_str(&format!(
                 "Enum of {}",
                 t.iter()
@@ -149,11 +299,14 @@ fn parse_attrs(
                     ),
                     param.span(),
                 )
+                .with_code("E0203")
                 .err();
         }
 
         if self.required {
-            return Error::new(format!("can't find {} in params, it's required", self.name)).err();
+            return Error::new(format!("can't find {} in params, it's required", self.name))
+                .with_code("E0102")
+                .err();
         }
 
         Ok(None)
@@ -179,6 +332,7 @@ pub fn parse_attrs<'a>(
 
         Ok(ActionParams {
             origin,
+            ctx: None,
             span: None,
             values,
         })
@@ -187,6 +341,9 @@ pub fn parse_attrs<'a>(
 
 pub struct ActionParams<'a> {
     pub origin: &'a Origin,
+    // the context of the host the action is for, e.g. to render templates
+    // with the host vars
+    pub ctx: Option<&'a Context<'a>>,
     pub span: Option<Range<usize>>,
     pub values: Vec<Option<ActionParamValue>>,
 }
@@ -200,6 +357,10 @@ pub fn expect_string_with_span(&self, i: usize) -> (&str, &Option<Range<usize>>)
         self.values[i].as_ref().unwrap().expect_string_with_span()
     }
 
+    pub fn string(&self, i: usize) -> Option<&str> {
+        self.values[i].as_ref().map(|v| v.expect_string())
+    }
+
     pub fn base(&self, i: usize) -> Option<&ActionParamBaseValue> {
         self.values[i].as_ref().map(|v| v.expect_base())
     }
@@ -211,12 +372,26 @@ pub fn expect_base(&self, i: usize) -> &ActionParamBaseValue {
     pub fn list(&self, i: usize) -> Option<&[ActionParamBaseValue]> {
         self.values[i].as_ref().map(|v| v.expect_list())
     }
+
+    pub fn bool(&self, i: usize) -> Option<bool> {
+        self.values[i].as_ref().map(|v| v.expect_bool())
+    }
+
+    pub fn map(&self, i: usize) -> Option<&[(String, ActionParamBaseValue)]> {
+        self.values[i].as_ref().map(|v| v.expect_map())
+    }
+
+    pub fn number_with_span(&self, i: usize) -> Option<(u64, &Option<Range<usize>>)> {
+        self.values[i].as_ref().map(|v| v.expect_number_with_span())
+    }
 }
 
 pub enum ActionParamValue {
     String(String, Option<Range<usize>>),
     Bool(bool),
+    Number(u64, Option<Range<usize>>),
     List(Vec<ActionParamBaseValue>),
+    Map(Vec<(String, ActionParamBaseValue)>),
     Base(ActionParamBaseValue),
 }
 
@@ -245,6 +420,14 @@ pub fn list(&self) -> Option<&[ActionParamBaseValue]> {
         }
     }
 
+    pub fn map(&self) -> Option<&[(String, ActionParamBaseValue)]> {
+        if let ActionParamValue::Map(m) = self {
+            Some(m)
+        } else {
+            None
+        }
+    }
+
     pub fn base(&self) -> Option<&ActionParamBaseValue> {
         if let ActionParamValue::Base(v) = self {
             Some(v)
@@ -253,6 +436,22 @@ pub fn base(&self) -> Option<&ActionParamBaseValue> {
         }
     }
 
+    pub fn bool(&self) -> Option<bool> {
+        if let ActionParamValue::Bool(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    pub fn number_with_span(&self) -> Option<(u64, &Option<Range<usize>>)> {
+        if let ActionParamValue::Number(v, span) = self {
+            Some((*v, span))
+        } else {
+            None
+        }
+    }
+
     pub fn expect_string(&self) -> &str {
         self.string().unwrap()
     }
@@ -265,9 +464,21 @@ pub fn expect_list(&self) -> &[ActionParamBaseValue] {
         self.list().unwrap()
     }
 
+    pub fn expect_map(&self) -> &[(String, ActionParamBaseValue)] {
+        self.map().unwrap()
+    }
+
     pub fn expect_base(&self) -> &ActionParamBaseValue {
         self.base().unwrap()
     }
+
+    pub fn expect_bool(&self) -> bool {
+        self.bool().unwrap()
+    }
+
+    pub fn expect_number_with_span(&self) -> (u64, &Option<Range<usize>>) {
+        self.number_with_span().unwrap()
+    }
 }
 
 #[derive(Clone)]
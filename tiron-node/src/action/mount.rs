@@ -0,0 +1,380 @@
+use std::{io::Write, path::Path};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+const FSTAB: &str = "/etc/fstab";
+const MOUNTS: &str = "/proc/mounts";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum MountState {
+    Mounted,
+    Unmounted,
+    Present,
+    Absent,
+}
+
+/// Manage a mount point and its entry in `/etc/fstab`. The entry is only changed
+/// if it's different, and the mount point is remounted if the entry changed
+/// while it's mounted
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct MountAction {
+    /// The path of the mount point, e.g. `/mnt/data`
+    path: String,
+    /// The device to mount, e.g. `/dev/sdb1`, `UUID=...` or `nfs.local:/export`.
+    /// Required for `mounted` and `present`
+    src: Option<String>,
+    /// The type of the filesystem, e.g. `ext4` or `nfs`.
+    /// Required for `mounted` and `present`
+    fstype: Option<String>,
+    /// The mount options. Default to `defaults`
+    opts: String,
+    /// `mounted` adds the entry to fstab and mounts it, `present` only adds the entry,
+    /// `unmounted` unmounts it and keeps the entry, and `absent` unmounts it
+    /// and removes the entry. Default to `mounted`
+    state: Option<MountState>,
+}
+
+impl MountAction {
+    fn state(&self) -> MountState {
+        self.state.unwrap_or(MountState::Mounted)
+    }
+
+    /// The fstab line of the mount, if it should have one
+    fn entry(&self) -> Option<String> {
+        let (src, fstype) = (self.src.as_ref()?, self.fstype.as_ref()?);
+        Some(format!(
+            "{} {} {fstype} {} 0 0",
+            escape(src),
+            escape(&self.path),
+            self.opts
+        ))
+    }
+
+    /// The new content of fstab, or None if it doesn't change
+    fn new_fstab(&self, fstab: &str) -> Option<String> {
+        match self.state() {
+            MountState::Mounted | MountState::Present => {
+                set_entry(fstab, &self.path, &self.entry()?)
+            }
+            MountState::Absent => remove_entry(fstab, &self.path),
+            MountState::Unmounted => None,
+        }
+    }
+
+    fn is_mounted(&self) -> Result<bool> {
+        let mounts =
+            std::fs::read_to_string(MOUNTS).map_err(|e| anyhow!("can't read {MOUNTS}: {e}"))?;
+        Ok(find_entry(&mounts, &self.path).is_some())
+    }
+
+    /// The commands to mount or unmount the path after fstab has the new content
+    fn commands(&self, fstab_changed: bool) -> Result<Vec<Vec<String>>> {
+        let mounted = self.is_mounted()?;
+        let path = self.path.clone();
+        Ok(match self.state() {
+            MountState::Mounted if !mounted => vec![vec!["mount".to_string(), path]],
+            // the mount point is mounted again with the new entry
+            MountState::Mounted if fstab_changed => vec![
+                vec!["umount".to_string(), path.clone()],
+                vec!["mount".to_string(), path],
+            ],
+            MountState::Unmounted | MountState::Absent if mounted => {
+                vec![vec!["umount".to_string(), path]]
+            }
+            _ => Vec::new(),
+        })
+    }
+}
+
+impl Action for MountAction {
+    fn name(&self) -> String {
+        "mount".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "path".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("path").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("src").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "fstype".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("fstype")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "opts".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("opts").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("mounted".to_string()),
+                        ActionParamBaseValue::String("unmounted".to_string()),
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (path, path_span) = params.expect_string_with_span(0);
+        if !path.starts_with('/') {
+            return Error::new("the path of the mount point should be absolute")
+                .with_origin(params.origin, path_span)
+                .err();
+        }
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            path => path,
+        };
+
+        let state = params.base(4).map(|state| match state.expect_string() {
+            "mounted" => MountState::Mounted,
+            "unmounted" => MountState::Unmounted,
+            "present" => MountState::Present,
+            "absent" => MountState::Absent,
+            _ => unreachable!(),
+        });
+        let src = params.string(1).map(|s| s.to_string());
+        let fstype = params.string(2).map(|s| s.to_string());
+        let needs_entry = matches!(
+            state.unwrap_or(MountState::Mounted),
+            MountState::Mounted | MountState::Present
+        );
+        if needs_entry && (src.is_none() || fstype.is_none()) {
+            return Error::new("mount needs src and fstype in params to be mounted or present")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        let opts = match params.values[3].as_ref() {
+            Some(opts) => {
+                let (opts, span) = opts.expect_string_with_span();
+                if opts.is_empty() || opts.contains(char::is_whitespace) {
+                    return Error::new("opts can't be empty or have spaces")
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+                opts.to_string()
+            }
+            None => "defaults".to_string(),
+        };
+
+        let input = MountAction {
+            path: path.to_string(),
+            src,
+            fstype,
+            opts,
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: MountAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
+        let fstab = std::fs::read_to_string(FSTAB).unwrap_or_default();
+        let new_fstab = input.new_fstab(&fstab);
+        if let Some(new_fstab) = &new_fstab {
+            write_fstab(new_fstab)?;
+            changes.push(format!("update {FSTAB}"));
+        }
+        if input.state() == MountState::Mounted {
+            std::fs::create_dir_all(&input.path)
+                .map_err(|e| anyhow!("can't create {}: {e}", input.path))?;
+        }
+        for args in input.commands(new_fstab.is_some())? {
+            let status = run_command(id, tx, &args[0], &args[1..])?;
+            if !status.success() {
+                return Err(anyhow!("{} failed", args.join(" ")));
+            }
+            changes.push(args.join(" "));
+        }
+        Ok(changes.join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: MountAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
+        let fstab = std::fs::read_to_string(FSTAB).unwrap_or_default();
+        let fstab_changed = input.new_fstab(&fstab).is_some();
+        if fstab_changed {
+            changes.push(format!("update {FSTAB}"));
+        }
+        for args in input.commands(fstab_changed)? {
+            changes.push(args.join(" "));
+        }
+        Ok((!changes.is_empty()).then(|| changes.join(", ")))
+    }
+}
+
+/// Replace the entry of the mount point in fstab with the line, or add the line
+/// if it doesn't have one. Returns None if it already has the same entry
+fn set_entry(fstab: &str, path: &str, line: &str) -> Option<String> {
+    let mut lines: Vec<&str> = fstab.lines().collect();
+    match find_entry(fstab, path) {
+        Some(i) if same_fields(lines[i], line) => return None,
+        Some(i) => lines[i] = line,
+        None => lines.push(line),
+    }
+    Some(lines.join("\n") + "\n")
+}
+
+/// Remove the entry of the mount point from fstab, or None if it doesn't have one
+fn remove_entry(fstab: &str, path: &str) -> Option<String> {
+    let i = find_entry(fstab, path)?;
+    let mut lines: Vec<&str> = fstab.lines().collect();
+    lines.remove(i);
+    Some(lines.join("\n") + "\n")
+}
+
+/// The index of the line for the mount point in fstab or /proc/mounts
+fn find_entry(content: &str, path: &str) -> Option<usize> {
+    let path = escape(path);
+    content.lines().position(|line| {
+        let line = line.trim_start();
+        !line.starts_with('#') && line.split_whitespace().nth(1) == Some(path.as_str())
+    })
+}
+
+/// Whether the lines have the same fields, no matter how they are aligned
+fn same_fields(a: &str, b: &str) -> bool {
+    a.split_whitespace().eq(b.split_whitespace())
+}
+
+/// The spaces in the fields of fstab are written in octal
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\134")
+        .replace(' ', "\\040")
+        .replace('\t', "\\011")
+}
+
+/// Write fstab with a rename, so that it's never left half written
+fn write_fstab(content: &str) -> Result<()> {
+    let dir = Path::new(FSTAB).parent().unwrap_or(Path::new("/"));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(content.as_bytes())?;
+    temp.flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        temp.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+    }
+    temp.persist(FSTAB)
+        .map_err(|e| anyhow!("can't write {FSTAB}: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    const CONTENT: &str = "# /etc/fstab\n\
+                         UUID=1234 / ext4 errors=remount-ro 0 1\n\
+                         /dev/sdb1\t/mnt/data\text4\tdefaults\t0\t0\n";
+
+    #[test]
+    fn input_mount() {
+        assert_input(
+            &MountAction::default(),
+            r#"
+                path   = "/mnt/backup/"
+                src    = "nfs.local:/export"
+                fstype = "nfs"
+                opts   = "ro,noatime"
+            "#,
+            &MountAction {
+                path: "/mnt/backup".to_string(),
+                src: Some("nfs.local:/export".to_string()),
+                fstype: Some("nfs".to_string()),
+                opts: "ro,noatime".to_string(),
+                state: None,
+            },
+        );
+        assert_eq!(
+            input_error(&MountAction::default(), r#"path = "/mnt/backup""#),
+            "mount needs src and fstype in params to be mounted or present"
+        );
+    }
+
+    #[test]
+    fn fstab_entries() {
+        // the same entry aligned differently is left alone
+        assert_eq!(
+            set_entry(
+                CONTENT,
+                "/mnt/data",
+                "/dev/sdb1 /mnt/data ext4 defaults 0 0"
+            ),
+            None
+        );
+        assert_eq!(
+            set_entry(CONTENT, "/mnt/data", "/dev/sdc1 /mnt/data xfs defaults 0 0").as_deref(),
+            Some(
+                "# /etc/fstab\n\
+                 UUID=1234 / ext4 errors=remount-ro 0 1\n\
+                 /dev/sdc1 /mnt/data xfs defaults 0 0\n"
+            )
+        );
+        assert_eq!(
+            set_entry(
+                CONTENT,
+                "/mnt/my disk",
+                "/dev/sdd1 /mnt/my\\040disk ext4 defaults 0 0"
+            )
+            .as_deref(),
+            Some(format!("{CONTENT}/dev/sdd1 /mnt/my\\040disk ext4 defaults 0 0\n").as_str())
+        );
+        assert_eq!(
+            remove_entry(CONTENT, "/mnt/data").as_deref(),
+            Some("# /etc/fstab\nUUID=1234 / ext4 errors=remount-ro 0 1\n")
+        );
+        assert_eq!(remove_entry(CONTENT, "/mnt/other"), None);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{quote_ident, quote_string, MysqlLogin, MysqlState};
+use crate::action::{
+    Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+/// Create or drop a MySQL or MariaDB database with the `mysql` client on the remote machine
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct MysqlDbAction {
+    /// The name of the database
+    name: String,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the database is created if it doesn't exist.
+    ///
+    /// If `absent`, the database is dropped with all its tables.
+    state: MysqlState,
+    /// The character set of the database when it's created, e.g. `utf8mb4`
+    encoding: Option<String>,
+    /// The collation of the database when it's created, e.g. `utf8mb4_unicode_ci`
+    collation: Option<String>,
+    login: MysqlLogin,
+}
+
+impl MysqlDbAction {
+    fn exists(&self) -> Result<bool> {
+        let rows = self.login.query(&format!(
+            "SELECT SCHEMA_NAME FROM information_schema.SCHEMATA WHERE SCHEMA_NAME = {}",
+            quote_string(&self.name)
+        ))?;
+        Ok(!rows.trim().is_empty())
+    }
+
+    /// The statement that makes the database as it should be, or None if it already is
+    fn statement(&self, exists: bool) -> Option<String> {
+        match (self.state, exists) {
+            (MysqlState::Present, false) => {
+                let mut sql = format!("CREATE DATABASE {}", quote_ident(&self.name));
+                if let Some(encoding) = &self.encoding {
+                    sql.push_str(&format!(" CHARACTER SET {encoding}"));
+                }
+                if let Some(collation) = &self.collation {
+                    sql.push_str(&format!(" COLLATE {collation}"));
+                }
+                Some(sql)
+            }
+            (MysqlState::Absent, true) => {
+                Some(format!("DROP DATABASE {}", quote_ident(&self.name)))
+            }
+            _ => None,
+        }
+    }
+
+    fn change(&self) -> String {
+        match self.state {
+            MysqlState::Present => format!("create database {}", self.name),
+            MysqlState::Absent => format!("drop database {}", self.name),
+        }
+    }
+}
+
+impl Action for MysqlDbAction {
+    fn name(&self) -> String {
+        "mysql_db".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        let mut params = vec![
+            ActionParamDoc {
+                name: "name".to_string(),
+                required: true,
+                description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                type_: vec![ActionParamType::String],
+            },
+            ActionParamDoc {
+                name: "state".to_string(),
+                required: false,
+                description: Self::get_field_docs("state")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::Enum(vec![
+                    ActionParamBaseValue::String("present".to_string()),
+                    ActionParamBaseValue::String("absent".to_string()),
+                ])],
+            },
+            ActionParamDoc {
+                name: "encoding".to_string(),
+                required: false,
+                description: Self::get_field_docs("encoding")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::String],
+            },
+            ActionParamDoc {
+                name: "collation".to_string(),
+                required: false,
+                description: Self::get_field_docs("collation")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::String],
+            },
+        ];
+        params.extend(MysqlLogin::param_docs());
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params,
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (name, name_span) = params.expect_string_with_span(0);
+        if name.is_empty() {
+            return Error::new("the name of the database can't be empty")
+                .with_origin(params.origin, name_span)
+                .err();
+        }
+        let state = match params.base(1).map(|state| state.expect_string()) {
+            Some("absent") => MysqlState::Absent,
+            _ => MysqlState::Present,
+        };
+        // the character set and the collation can't be quoted in the statement
+        for i in [2, 3] {
+            let Some((value, span)) = params.values[i]
+                .as_ref()
+                .map(|v| v.expect_string_with_span())
+            else {
+                continue;
+            };
+            let valid =
+                !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Error::new(format!("{value} isn't a valid character set or collation"))
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+        }
+
+        let input = MysqlDbAction {
+            name: name.to_string(),
+            state,
+            encoding: params.string(2).map(|s| s.to_string()),
+            collation: params.string(3).map(|s| s.to_string()),
+            login: MysqlLogin::from_params(&params, 4),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, _id: ActionId, input: &[u8], _tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: MysqlDbAction = bincode::deserialize(input)?;
+        let Some(sql) = input.statement(input.exists()?) else {
+            return Ok("".to_string());
+        };
+        input.login.query(&sql)?;
+        Ok(input.change())
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: MysqlDbAction = bincode::deserialize(input)?;
+        Ok(input.statement(input.exists()?).map(|_| input.change()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_mysql_db() {
+        assert_input(
+            &MysqlDbAction::default(),
+            r#"
+                name       = "app"
+                encoding   = "utf8mb4"
+                login_user = "admin"
+            "#,
+            &MysqlDbAction {
+                name: "app".to_string(),
+                state: MysqlState::Present,
+                encoding: Some("utf8mb4".to_string()),
+                collation: None,
+                login: MysqlLogin {
+                    login_user: Some("admin".to_string()),
+                    login_password: None,
+                    login_host: None,
+                },
+            },
+        );
+        assert_eq!(
+            input_error(
+                &MysqlDbAction::default(),
+                r#"
+                    name      = "app"
+                    collation = "utf8mb4; DROP DATABASE app"
+                "#
+            ),
+            "utf8mb4; DROP DATABASE app isn't a valid character set or collation"
+        );
+    }
+
+    #[test]
+    fn db_statement() {
+        let mut action = MysqlDbAction {
+            name: "my`app".to_string(),
+            state: MysqlState::Present,
+            encoding: Some("utf8mb4".to_string()),
+            collation: Some("utf8mb4_unicode_ci".to_string()),
+            login: MysqlLogin::default(),
+        };
+        assert_eq!(
+            action.statement(false).unwrap(),
+            "CREATE DATABASE `my``app` CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci"
+        );
+        assert_eq!(action.statement(true), None);
+
+        action.state = MysqlState::Absent;
+        assert_eq!(action.statement(true).unwrap(), "DROP DATABASE `my``app`");
+        assert_eq!(action.statement(false), None);
+    }
+}
@@ -0,0 +1,121 @@
+mod db;
+mod user;
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use documented::DocumentedFields;
+use serde::{Deserialize, Serialize};
+
+use super::{ActionParamDoc, ActionParamType, ActionParams};
+
+pub use self::{db::MysqlDbAction, user::MysqlUserAction};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MysqlState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// How the `mysql` client on the remote machine connects to the server,
+/// which are the last params of the mysql actions
+#[derive(Default, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, DocumentedFields)]
+pub struct MysqlLogin {
+    /// The user to connect as. Default to the user of the node, which is `root`
+    /// with `become`, and connects with the unix socket auth of MySQL and MariaDB
+    login_user: Option<String>,
+    /// The password of `login_user`, which is given to `mysql` in `MYSQL_PWD`
+    /// instead of the command line
+    login_password: Option<String>,
+    /// The host of the server. Default to the local server on its unix socket
+    login_host: Option<String>,
+}
+
+impl MysqlLogin {
+    /// The docs of the login params, which come after the params of the action
+    fn param_docs() -> Vec<ActionParamDoc> {
+        ["login_user", "login_password", "login_host"]
+            .into_iter()
+            .map(|name| ActionParamDoc {
+                name: name.to_string(),
+                required: false,
+                description: Self::get_field_docs(name).unwrap_or_default().to_string(),
+                type_: vec![ActionParamType::String],
+            })
+            .collect()
+    }
+
+    /// The login from the params, which start at `i`
+    fn from_params(params: &ActionParams, i: usize) -> Self {
+        Self {
+            login_user: params.string(i).map(|s| s.to_string()),
+            login_password: params.string(i + 1).map(|s| s.to_string()),
+            login_host: params.string(i + 2).map(|s| s.to_string()),
+        }
+    }
+
+    /// Execute the SQL with the `mysql` client, and returns the rows it prints
+    /// with the columns separated by tabs. The SQL is written to stdin,
+    /// so that the passwords in it don't show up in the processes
+    fn query(&self, sql: &str) -> Result<String> {
+        let mut cmd = Command::new("mysql");
+        cmd.args(["--batch", "--skip-column-names"]);
+        if let Some(user) = &self.login_user {
+            cmd.arg(format!("--user={user}"));
+        }
+        if let Some(host) = &self.login_host {
+            cmd.arg(format!("--host={host}"));
+        }
+        if let Some(password) = &self.login_password {
+            cmd.env("MYSQL_PWD", password);
+        }
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("can't run mysql, is the mysql client installed? {e}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(sql.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "mysql failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+/// The string as a SQL literal in single quotes
+fn quote_string(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// The name of a database or a table in backticks, where `*` is kept as the wildcard
+fn quote_ident(s: &str) -> String {
+    if s == "*" {
+        return s.to_string();
+    }
+    format!("`{}`", s.replace('`', "``"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quote_sql() {
+        assert_eq!(quote_string("it's"), "'it\\'s'");
+        assert_eq!(quote_string("a\\"), "'a\\\\'");
+        assert_eq!(quote_ident("app"), "`app`");
+        assert_eq!(quote_ident("a`b"), "`a``b`");
+        assert_eq!(quote_ident("*"), "*");
+    }
+}
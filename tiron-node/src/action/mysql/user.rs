@@ -0,0 +1,483 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{quote_ident, quote_string, MysqlLogin, MysqlState};
+use crate::action::{
+    Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdatePassword {
+    #[default]
+    OnCreate,
+    Always,
+}
+
+/// The privileges of the user on a target like `app.*`
+type Grants = Vec<(String, BTreeSet<String>)>;
+
+/// Create or drop a MySQL or MariaDB user and grant it privileges,
+/// with the `mysql` client on the remote machine
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct MysqlUserAction {
+    /// The name of the user
+    name: String,
+    /// The host the user connects from, e.g. `%` for any host. Default to `localhost`
+    host: String,
+    /// The password of the user
+    password: Option<String>,
+    /// The privileges of the user on the databases and the tables,
+    /// e.g. `{ "app.*" = "ALL", "reports.daily" = "SELECT,INSERT" }`.
+    /// The user's privileges on the targets that aren't in it are revoked,
+    /// and they are left alone if it's not given
+    privileges: Option<Grants>,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the user is created if it doesn't exist, and granted the privileges.
+    ///
+    /// If `absent`, the user is dropped.
+    state: MysqlState,
+    /// Default to `on_create`<br>
+    ///
+    /// If `on_create`, the password is only set when the user is created.
+    ///
+    /// If `always`, the password is set every time, which is always a change,
+    /// as the password of an existing user can't be compared.
+    update_password: UpdatePassword,
+    login: MysqlLogin,
+}
+
+impl MysqlUserAction {
+    fn account(&self) -> String {
+        format!("{}@{}", quote_string(&self.name), quote_string(&self.host))
+    }
+
+    /// The grants of the user, or None if the user doesn't exist
+    fn current(&self) -> Result<Option<Grants>> {
+        let rows = self.login.query(&format!(
+            "SELECT User FROM mysql.user WHERE User = {} AND Host = {}",
+            quote_string(&self.name),
+            quote_string(&self.host)
+        ))?;
+        if rows.trim().is_empty() {
+            return Ok(None);
+        }
+        let rows = self
+            .login
+            .query(&format!("SHOW GRANTS FOR {}", self.account()))?;
+        Ok(Some(parse_grants(&rows)))
+    }
+
+    /// The changes with their statements that make the user as it should be
+    fn statements(&self, current: Option<&Grants>) -> Vec<(String, String)> {
+        let account = self.account();
+        let user = format!("{}@{}", self.name, self.host);
+        let identified = |sql: String| match &self.password {
+            Some(password) => format!("{sql} IDENTIFIED BY {}", quote_string(password)),
+            None => sql,
+        };
+        let mut statements = Vec::new();
+        match (self.state, current) {
+            (MysqlState::Absent, Some(_)) => {
+                statements.push((format!("drop user {user}"), format!("DROP USER {account}")));
+                return statements;
+            }
+            (MysqlState::Absent, None) => return statements,
+            (MysqlState::Present, None) => {
+                statements.push((
+                    format!("create user {user}"),
+                    identified(format!("CREATE USER {account}")),
+                ));
+            }
+            (MysqlState::Present, Some(_)) => {
+                if self.update_password == UpdatePassword::Always && self.password.is_some() {
+                    statements.push((
+                        format!("set the password of {user}"),
+                        identified(format!("ALTER USER {account}")),
+                    ));
+                }
+            }
+        }
+
+        let Some(privileges) = &self.privileges else {
+            return statements;
+        };
+        let current = current.cloned().unwrap_or_default();
+        for (target, _) in &current {
+            if !privileges.iter().any(|(t, _)| t == target) {
+                statements.push((
+                    format!("revoke the privileges of {user} on {target}"),
+                    format!(
+                        "REVOKE ALL PRIVILEGES ON {} FROM {account}",
+                        quote_target(target)
+                    ),
+                ));
+            }
+        }
+        for (target, privs) in privileges {
+            let granted = current.iter().find(|(t, _)| t == target).map(|(_, p)| p);
+            if granted == Some(privs) {
+                continue;
+            }
+            if granted.is_some() {
+                statements.push((
+                    format!("revoke the privileges of {user} on {target}"),
+                    format!(
+                        "REVOKE ALL PRIVILEGES ON {} FROM {account}",
+                        quote_target(target)
+                    ),
+                ));
+            }
+            let privs = privs.iter().cloned().collect::<Vec<_>>().join(", ");
+            statements.push((
+                format!("grant {privs} on {target} to {user}"),
+                format!("GRANT {privs} ON {} TO {account}", quote_target(target)),
+            ));
+        }
+        statements
+    }
+}
+
+/// The target like `app.*` with the names in backticks
+fn quote_target(target: &str) -> String {
+    match target.split_once('.') {
+        Some((db, table)) => format!("{}.{}", quote_ident(db), quote_ident(table)),
+        None => quote_ident(target),
+    }
+}
+
+/// The target in a grant, e.g. `` `app`.* ``, without the backticks
+fn unquote_target(target: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quoted = false;
+    let mut chars = target.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' if quoted && chars.peek() == Some(&'`') => {
+                chars.next();
+                unquoted.push('`');
+            }
+            '`' => quoted = !quoted,
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// The privileges in the list like `SELECT,INSERT`, in upper case,
+/// with `ALL PRIVILEGES` as `ALL` and without `USAGE`, which is no privilege
+fn parse_privileges(privileges: &str) -> BTreeSet<String> {
+    privileges
+        .split(',')
+        .map(|p| {
+            p.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_uppercase()
+        })
+        .map(|p| {
+            if p == "ALL PRIVILEGES" {
+                "ALL".to_string()
+            } else {
+                p
+            }
+        })
+        .filter(|p| !p.is_empty() && p != "USAGE")
+        .collect()
+}
+
+/// The privileges on the targets from the output of `SHOW GRANTS`, which is like
+/// ``GRANT SELECT, INSERT ON `app`.* TO `app`@`localhost` ``.
+/// The grants of the roles and the proxies aren't privileges on the targets
+fn parse_grants(rows: &str) -> Grants {
+    let mut grants: Grants = Vec::new();
+    for row in rows.lines() {
+        let Some((privileges, rest)) = row
+            .strip_prefix("GRANT ")
+            .and_then(|row| row.split_once(" ON "))
+        else {
+            continue;
+        };
+        let Some((target, _)) = rest.split_once(" TO ") else {
+            continue;
+        };
+        let privileges = parse_privileges(privileges);
+        if privileges.is_empty() || privileges.contains("PROXY") {
+            continue;
+        }
+        let target = unquote_target(target.trim());
+        match grants.iter_mut().find(|(t, _)| *t == target) {
+            Some((_, privs)) => privs.extend(privileges),
+            None => grants.push((target, privileges)),
+        }
+    }
+    grants
+}
+
+impl Action for MysqlUserAction {
+    fn name(&self) -> String {
+        "mysql_user".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        let mut params = vec![
+            ActionParamDoc {
+                name: "name".to_string(),
+                required: true,
+                description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                type_: vec![ActionParamType::String],
+            },
+            ActionParamDoc {
+                name: "host".to_string(),
+                required: false,
+                description: Self::get_field_docs("host").unwrap_or_default().to_string(),
+                type_: vec![ActionParamType::String],
+            },
+            ActionParamDoc {
+                name: "password".to_string(),
+                required: false,
+                description: Self::get_field_docs("password")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::String],
+            },
+            ActionParamDoc {
+                name: "privileges".to_string(),
+                required: false,
+                description: Self::get_field_docs("privileges")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::Map(ActionParamBaseType::String)],
+            },
+            ActionParamDoc {
+                name: "state".to_string(),
+                required: false,
+                description: Self::get_field_docs("state")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::Enum(vec![
+                    ActionParamBaseValue::String("present".to_string()),
+                    ActionParamBaseValue::String("absent".to_string()),
+                ])],
+            },
+            ActionParamDoc {
+                name: "update_password".to_string(),
+                required: false,
+                description: Self::get_field_docs("update_password")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::Enum(vec![
+                    ActionParamBaseValue::String("on_create".to_string()),
+                    ActionParamBaseValue::String("always".to_string()),
+                ])],
+            },
+        ];
+        params.extend(MysqlLogin::param_docs());
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params,
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (name, name_span) = params.expect_string_with_span(0);
+        if name.is_empty() {
+            return Error::new("the name of the user can't be empty")
+                .with_origin(params.origin, name_span)
+                .err();
+        }
+        let privileges = match params.map(3) {
+            Some(map) => {
+                let mut privileges = Vec::new();
+                for (target, privs) in map {
+                    let privs = privs.expect_string();
+                    // the privileges can't be quoted in the statement
+                    let valid = !privs.is_empty()
+                        && privs
+                            .chars()
+                            .all(|c| c.is_ascii_alphabetic() || c == '_' || c == ',' || c == ' ');
+                    if !target.contains('.') || !valid {
+                        return Error::new(format!(
+                            "{target} = \"{privs}\" isn't a valid privilege, it should be like \"app.*\" = \"SELECT,INSERT\""
+                        ))
+                        .with_origin(params.origin, &params.span)
+                        .err();
+                    }
+                    privileges.push((target.clone(), parse_privileges(privs)));
+                }
+                Some(privileges)
+            }
+            None => None,
+        };
+        let state = match params.base(4).map(|state| state.expect_string()) {
+            Some("absent") => MysqlState::Absent,
+            _ => MysqlState::Present,
+        };
+        let update_password = match params.base(5).map(|update| update.expect_string()) {
+            Some("always") => UpdatePassword::Always,
+            _ => UpdatePassword::OnCreate,
+        };
+
+        let input = MysqlUserAction {
+            name: name.to_string(),
+            host: params.string(1).unwrap_or("localhost").to_string(),
+            password: params.string(2).map(|s| s.to_string()),
+            privileges,
+            state,
+            update_password,
+            login: MysqlLogin::from_params(&params, 6),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, _id: ActionId, input: &[u8], _tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: MysqlUserAction = bincode::deserialize(input)?;
+        let statements = input.statements(input.current()?.as_ref());
+        if statements.is_empty() {
+            return Ok("".to_string());
+        }
+        let sql = statements
+            .iter()
+            .map(|(_, sql)| format!("{sql};"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        input.login.query(&sql)?;
+        Ok(statements
+            .into_iter()
+            .map(|(change, _)| change)
+            .collect::<Vec<_>>()
+            .join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: MysqlUserAction = bincode::deserialize(input)?;
+        let statements = input.statements(input.current()?.as_ref());
+        if statements.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            statements
+                .into_iter()
+                .map(|(change, _)| change)
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    fn privs(privs: &[&str]) -> BTreeSet<String> {
+        privs.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn input_mysql_user() {
+        assert_input(
+            &MysqlUserAction::default(),
+            r#"
+                name       = "app"
+                password   = "secret"
+                privileges = { "app.*" = "all privileges", "reports.daily" = "SELECT, insert" }
+            "#,
+            &MysqlUserAction {
+                name: "app".to_string(),
+                host: "localhost".to_string(),
+                password: Some("secret".to_string()),
+                privileges: Some(vec![
+                    ("app.*".to_string(), privs(&["ALL"])),
+                    ("reports.daily".to_string(), privs(&["INSERT", "SELECT"])),
+                ]),
+                state: MysqlState::Present,
+                update_password: UpdatePassword::OnCreate,
+                login: MysqlLogin::default(),
+            },
+        );
+        assert_eq!(
+            input_error(
+                &MysqlUserAction::default(),
+                r#"
+                    name       = "app"
+                    privileges = { "app.*" = "ALL; DROP USER root" }
+                "#
+            ),
+            "app.* = \"ALL; DROP USER root\" isn't a valid privilege, it should be like \"app.*\" = \"SELECT,INSERT\""
+        );
+    }
+
+    #[test]
+    fn grants() {
+        let rows = "GRANT USAGE ON *.* TO `app`@`localhost`\n\
+                    GRANT ALL PRIVILEGES ON `app`.* TO `app`@`localhost`\n\
+                    GRANT SELECT, INSERT ON `reports`.`daily` TO `app`@`localhost` WITH GRANT OPTION\n\
+                    GRANT PROXY ON ''@'' TO 'app'@'localhost'\n\
+                    GRANT `readers` TO `app`@`localhost`\n";
+        let current = parse_grants(rows);
+        assert_eq!(
+            current,
+            vec![
+                ("app.*".to_string(), privs(&["ALL"])),
+                ("reports.daily".to_string(), privs(&["INSERT", "SELECT"])),
+            ]
+        );
+
+        let mut action = MysqlUserAction {
+            name: "app".to_string(),
+            host: "%".to_string(),
+            password: Some("it's".to_string()),
+            privileges: Some(vec![("app.*".to_string(), privs(&["ALL"]))]),
+            state: MysqlState::Present,
+            update_password: UpdatePassword::OnCreate,
+            login: MysqlLogin::default(),
+        };
+        assert_eq!(
+            action
+                .statements(None)
+                .into_iter()
+                .map(|(_, sql)| sql)
+                .collect::<Vec<_>>(),
+            vec![
+                "CREATE USER 'app'@'%' IDENTIFIED BY 'it\\'s'",
+                "GRANT ALL ON `app`.* TO 'app'@'%'",
+            ]
+        );
+        // the grants that aren't in the privileges are revoked
+        assert_eq!(
+            action
+                .statements(Some(&current))
+                .into_iter()
+                .map(|(change, _)| change)
+                .collect::<Vec<_>>(),
+            vec!["revoke the privileges of app@% on reports.daily"]
+        );
+
+        action.privileges = None;
+        assert!(action.statements(Some(&current)).is_empty());
+        action.state = MysqlState::Absent;
+        assert_eq!(
+            action.statements(Some(&current))[0].1,
+            "DROP USER 'app'@'%'"
+        );
+    }
+}
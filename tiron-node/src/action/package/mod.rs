@@ -5,8 +5,9 @@
 use documented::{Documented, DocumentedFields};
 use serde::{Deserialize, Serialize};
 use tiron_common::{
-    action::{ActionId, ActionMessage},
+    action::{ActionId, ActionMessage, ActionRollback},
     error::Error,
+    node::Resource,
 };
 
 use self::provider::PackageProvider;
@@ -119,4 +120,100 @@ fn execute(
             Err(anyhow!("package failed"))
         }
     }
+
+    fn rollback(&self, input: &[u8]) -> anyhow::Result<Option<ActionRollback>> {
+        let input: PackageAction = bincode::deserialize(input)?;
+        // upgraded packages can't be downgraded
+        let (installed, state) = match input.state {
+            PackageState::Present => (false, PackageState::Absent),
+            PackageState::Absent => (true, PackageState::Present),
+            PackageState::Latest => return Ok(None),
+        };
+
+        let provider = PackageProvider::detect()?;
+        let mut name = Vec::new();
+        for package in input.name {
+            if provider.installed(&package)? == installed {
+                name.push(package);
+            }
+        }
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(ActionRollback {
+            action: self.name(),
+            input: bincode::serialize(&PackageAction { name, state })?,
+        }))
+    }
+
+    fn resources(&self, input: &[u8]) -> Vec<Resource> {
+        match bincode::deserialize::<PackageAction>(input) {
+            Ok(input) => input
+                .name
+                .into_iter()
+                .map(|name| Resource::Package { name })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Whether the package is installed, with the package manager of the host
+pub(crate) fn installed(package: &str) -> anyhow::Result<bool> {
+    PackageProvider::detect()?.installed(package)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_single_name() {
+        assert_input(
+            &PackageAction::default(),
+            r#"
+                name = "nginx"
+                state = "present"
+            "#,
+            &PackageAction {
+                name: vec!["nginx".to_string()],
+                state: PackageState::Present,
+            },
+        );
+    }
+
+    #[test]
+    fn input_name_list() {
+        assert_input(
+            &PackageAction::default(),
+            r#"
+                name = ["nginx", "curl"]
+                state = "latest"
+            "#,
+            &PackageAction {
+                name: vec!["nginx".to_string(), "curl".to_string()],
+                state: PackageState::Latest,
+            },
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(
+            input_error(&PackageAction::default(), r#"name = "nginx""#),
+            "can't find state in params, it's required"
+        );
+        assert_eq!(
+            input_error(
+                &PackageAction::default(),
+                r#"
+                    name = true
+                    state = "present"
+                "#
+            ),
+            "name type should be String or List of String"
+        );
+    }
 }
@@ -55,6 +55,19 @@ pub fn detect() -> Result<Self> {
         Ok(provider)
     }
 
+    /// Whether the package is installed
+    pub fn installed(&self, package: &str) -> Result<bool> {
+        let (program, args) = match self {
+            PackageProvider::Apt => ("dpkg", vec!["-s", package]),
+            PackageProvider::Dnf | PackageProvider::Zypper => ("rpm", vec!["-q", package]),
+            PackageProvider::Pacman => ("pacman", vec!["-Q", package]),
+            PackageProvider::Homebrew => ("brew", vec!["list", package]),
+            PackageProvider::Winget => ("winget", vec!["list", "--exact", "--id", package]),
+        };
+        let output = std::process::Command::new(program).args(args).output()?;
+        Ok(output.status.success())
+    }
+
     pub fn run(
         &self,
         id: ActionId,
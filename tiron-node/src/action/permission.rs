@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+/// The owner, the group and the mode that the files should have,
+/// which are left as they are if they're None
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub mode: Option<u32>,
+}
+
+impl Permissions {
+    pub fn is_empty(&self) -> bool {
+        self.owner.is_none() && self.group.is_none() && self.mode.is_none()
+    }
+
+    /// Set the permissions of the path, and of everything in it if `recurse`.
+    /// Returns the paths that are changed, or would be changed in a dry run
+    #[cfg(unix)]
+    pub fn apply(&self, path: &Path, recurse: bool, dry_run: bool) -> Result<Vec<PathBuf>> {
+        use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt};
+
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let uid = self.owner.as_deref().map(uid).transpose()?;
+        let gid = self.group.as_deref().map(gid).transpose()?;
+
+        let mut paths = vec![path.to_path_buf()];
+        if recurse {
+            walk(path, &mut paths)?;
+        }
+        let mut changed = Vec::new();
+        for path in paths {
+            let meta = std::fs::symlink_metadata(&path)
+                .map_err(|e| anyhow!("can't read {}: {e}", path.display()))?;
+            let mut change = false;
+            if uid.is_some_and(|uid| uid != meta.uid()) || gid.is_some_and(|gid| gid != meta.gid())
+            {
+                change = true;
+                if !dry_run {
+                    // the symlinks are changed instead of the files they point to
+                    lchown(&path, uid, gid).map_err(|e| {
+                        anyhow!("can't change the owner of {}: {e}", path.display())
+                    })?;
+                }
+            }
+            // symlinks don't have their own mode
+            if let Some(mode) = self.mode.filter(|_| !meta.file_type().is_symlink()) {
+                if meta.mode() & 0o7777 != mode {
+                    change = true;
+                    if !dry_run {
+                        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+                            .map_err(|e| {
+                                anyhow!("can't change the mode of {}: {e}", path.display())
+                            })?;
+                    }
+                }
+            }
+            if change {
+                changed.push(path);
+            }
+        }
+        Ok(changed)
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _path: &Path, _recurse: bool, _dry_run: bool) -> Result<Vec<PathBuf>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        Err(anyhow!("owner, group and mode are only supported on unix"))
+    }
+}
+
+/// The mode in octal, e.g. `0644` or `755`
+pub fn parse_mode(mode: &str) -> Option<u32> {
+    let mode = mode.strip_prefix("0o").unwrap_or(mode);
+    if mode.is_empty() {
+        return None;
+    }
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+}
+
+/// Add everything in the directory to the paths, without following the symlinks
+#[cfg(unix)]
+fn walk(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        paths.push(path.clone());
+        walk(&path, paths)?;
+    }
+    Ok(())
+}
+
+/// The user id of the owner, which can be the name or the id
+#[cfg(unix)]
+fn uid(owner: &str) -> Result<u32> {
+    if let Ok(uid) = owner.parse() {
+        return Ok(uid);
+    }
+    let output = std::process::Command::new("id")
+        .args(["-u", owner])
+        .output()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("can't find user {owner}"))
+}
+
+/// The group id of the group, which can be the name or the id
+#[cfg(unix)]
+fn gid(group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let gid = match std::process::Command::new("getent")
+        .args(["group", group])
+        .output()
+    {
+        // the entry is like `name:x:gid:members`
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .split(':')
+            .nth(2)
+            .and_then(|gid| gid.trim().parse().ok()),
+        // macOS doesn't have getent
+        Err(_) => std::process::Command::new("dscl")
+            .args([".", "-read", &format!("/Groups/{group}"), "PrimaryGroupID"])
+            .output()
+            .ok()
+            .and_then(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|gid| gid.parse().ok())
+            }),
+    };
+    gid.ok_or_else(|| anyhow!("can't find group {group}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_modes() {
+        assert_eq!(parse_mode("0644"), Some(0o644));
+        assert_eq!(parse_mode("755"), Some(0o755));
+        assert_eq!(parse_mode("0o1777"), Some(0o1777));
+        assert_eq!(parse_mode("0844"), None);
+        assert_eq!(parse_mode("17777"), None);
+        assert_eq!(parse_mode(""), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let permissions = Permissions {
+            mode: Some(0o755),
+            ..Default::default()
+        };
+        // only the file differs
+        assert_eq!(
+            permissions.apply(dir.path(), true, true).unwrap(),
+            vec![file.clone()]
+        );
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o600);
+
+        assert_eq!(
+            permissions.apply(dir.path(), true, false).unwrap(),
+            vec![file.clone()]
+        );
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+        assert!(permissions
+            .apply(dir.path(), true, false)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionData, ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{Action, ActionDoc, ActionParamDoc, ActionParamType, ActionParams};
+
+/// How long to wait for the host to come back by default
+const DEFAULT_TIMEOUT: u64 = 600;
+
+/// Reboot the host and wait for it to come back, e.g. after upgrading the kernel,
+/// and then the actions after it run on the host after the reboot. It's done by tiron
+/// itself rather than the node on the host, so it can't reboot the local host or a container
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct RebootAction {
+    /// How long to wait for the host to come back in seconds. Default to 600
+    timeout: Option<u64>,
+    // set by tiron for the remote hosts, which it reboots when the node gets to the action
+    #[serde(default)]
+    remote: bool,
+}
+
+impl RebootAction {
+    /// How long to wait for the host to come back after the reboot with the input
+    pub fn timeout(input: &[u8]) -> Duration {
+        let timeout = bincode::deserialize::<RebootAction>(input)
+            .ok()
+            .and_then(|input| input.timeout);
+        Duration::from_secs(timeout.unwrap_or(DEFAULT_TIMEOUT))
+    }
+
+    /// The input marked for a remote host
+    pub fn with_remote(input: &[u8]) -> Option<Vec<u8>> {
+        let mut input: RebootAction = bincode::deserialize(input).ok()?;
+        input.remote = true;
+        bincode::serialize(&input).ok()
+    }
+
+    /// Whether it's a reboot that tiron does itself when the node gets to it
+    pub fn is_remote(action: &ActionData) -> bool {
+        action.action == "reboot"
+            && !action.dry_run
+            && bincode::deserialize::<RebootAction>(&action.input).is_ok_and(|input| input.remote)
+    }
+}
+
+impl Action for RebootAction {
+    fn name(&self) -> String {
+        "reboot".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![ActionParamDoc {
+                name: "timeout".to_string(),
+                required: false,
+                description: Self::get_field_docs("timeout")
+                    .unwrap_or_default()
+                    .to_string(),
+                type_: vec![ActionParamType::Number],
+            }],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let timeout = match params.number_with_span(0) {
+            Some((0, span)) => {
+                return Error::new("timeout should be more than 0")
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+            Some((timeout, _)) => Some(timeout),
+            None => None,
+        };
+        let input = RebootAction {
+            timeout,
+            remote: false,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, _id: ActionId, input: &[u8], _tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: RebootAction = bincode::deserialize(input)?;
+        // the node can't be there across the reboot, so it only tells tiron that
+        // the actions before it are done, and tiron reboots the host
+        if !input.remote {
+            return Err(anyhow!("reboot can only be used on a remote host"));
+        }
+        Ok("rebooting the host".to_string())
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        _input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        Ok(Some("reboot the host".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::input_error;
+
+    #[test]
+    fn reboot_timeout() {
+        let input = bincode::serialize(&RebootAction {
+            timeout: Some(300),
+            remote: false,
+        })
+        .unwrap();
+        assert_eq!(RebootAction::timeout(&input), Duration::from_secs(300));
+        let input = bincode::serialize(&RebootAction::default()).unwrap();
+        assert_eq!(RebootAction::timeout(&input), Duration::from_secs(600));
+        let input = RebootAction::with_remote(&input).unwrap();
+        assert!(bincode::deserialize::<RebootAction>(&input).unwrap().remote);
+        assert_eq!(
+            input_error(&RebootAction::default(), "timeout = 0"),
+            "timeout should be more than 0"
+        );
+    }
+}
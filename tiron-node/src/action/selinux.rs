@@ -0,0 +1,391 @@
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue,
+    ActionParamDoc, ActionParamType, ActionParams,
+};
+
+const SELINUX_CONFIG: &str = "/etc/selinux/config";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelinuxMode {
+    Enforcing,
+    Permissive,
+    Disabled,
+}
+
+impl SelinuxMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SelinuxMode::Enforcing => "enforcing",
+            SelinuxMode::Permissive => "permissive",
+            SelinuxMode::Disabled => "disabled",
+        }
+    }
+}
+
+/// Set the mode and the booleans of SELinux, and restore the SELinux contexts
+/// of the files, without `setenforce`, `setsebool` or `restorecon` in `command`
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct SelinuxAction {
+    /// The mode of SELinux, which is set now with `setenforce` and at boot
+    /// in `/etc/selinux/config`. Switching between `disabled` and the other modes
+    /// only takes effect after a reboot
+    mode: Option<SelinuxMode>,
+    /// The booleans to set persistently with `setsebool -P`,
+    /// e.g. `{ httpd_can_network_connect = "on" }`
+    booleans: Vec<(String, bool)>,
+    /// The paths to restore the default SELinux contexts of recursively with `restorecon`,
+    /// e.g. after files were moved into `/var/www`
+    restorecon: Vec<String>,
+}
+
+impl SelinuxAction {
+    /// What would change on the machine, which is empty if SELinux is already as it should be
+    fn changes(&self) -> Result<Vec<String>> {
+        let mut changes = Vec::new();
+        if let Some(mode) = self.mode {
+            let config = std::fs::read_to_string(SELINUX_CONFIG).unwrap_or_default();
+            if config_mode(&config) != Some(mode) {
+                changes.push(format!("set {} in {SELINUX_CONFIG}", mode.as_str()));
+            }
+            if runtime_change(current_mode()?, mode).is_some() {
+                changes.push(format!("setenforce {}", mode.as_str()));
+            }
+        }
+        for (name, value) in &self.booleans {
+            if get_boolean(name)? != *value {
+                changes.push(format!("set {name} {}", on_off(*value)));
+            }
+        }
+        for path in &self.restorecon {
+            // -n only prints what would be relabeled
+            let output = Command::new("restorecon")
+                .args(["-R", "-n", "-v", path])
+                .output()
+                .map_err(|e| anyhow!("can't run restorecon: {e}"))?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "restorecon can't check {path}: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            if !output.stdout.is_empty() {
+                changes.push(format!("restore the contexts of {path}"));
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// The mode of SELinux now, from `getenforce`
+fn current_mode() -> Result<SelinuxMode> {
+    let output = Command::new("getenforce")
+        .output()
+        .map_err(|e| anyhow!("can't run getenforce, is SELinux installed? {e}"))?;
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "Enforcing" => Ok(SelinuxMode::Enforcing),
+        "Permissive" => Ok(SelinuxMode::Permissive),
+        "Disabled" => Ok(SelinuxMode::Disabled),
+        other => Err(anyhow!("unknown SELinux mode {other}")),
+    }
+}
+
+/// The arg of `setenforce` that changes the mode now, which can't change
+/// from or to `disabled` without a reboot
+fn runtime_change(current: SelinuxMode, mode: SelinuxMode) -> Option<&'static str> {
+    match (current, mode) {
+        (SelinuxMode::Permissive, SelinuxMode::Enforcing) => Some("1"),
+        (SelinuxMode::Enforcing, SelinuxMode::Permissive) => Some("0"),
+        _ => None,
+    }
+}
+
+/// The mode in the content of `/etc/selinux/config`
+fn config_mode(config: &str) -> Option<SelinuxMode> {
+    config
+        .lines()
+        .find_map(|line| match line.trim().strip_prefix("SELINUX=")?.trim() {
+            "enforcing" => Some(SelinuxMode::Enforcing),
+            "permissive" => Some(SelinuxMode::Permissive),
+            "disabled" => Some(SelinuxMode::Disabled),
+            _ => None,
+        })
+}
+
+/// The content of `/etc/selinux/config` with the mode, which is added if it's not there
+fn config_with_mode(config: &str, mode: SelinuxMode) -> String {
+    let line = format!("SELINUX={}", mode.as_str());
+    let mut found = false;
+    let mut lines = config
+        .lines()
+        .map(|l| {
+            if l.trim().starts_with("SELINUX=") {
+                found = true;
+                line.clone()
+            } else {
+                l.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    if !found {
+        lines.push(line);
+    }
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+fn get_boolean(name: &str) -> Result<bool> {
+    let output = Command::new("getsebool")
+        .arg(name)
+        .output()
+        .map_err(|e| anyhow!("can't run getsebool: {e}"))?;
+    parse_getsebool(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| anyhow!("can't find SELinux boolean {name}"))
+}
+
+/// The value in the output of `getsebool`, which is like `httpd_can_network_connect --> on`
+fn parse_getsebool(output: &str) -> Option<bool> {
+    match output.trim().rsplit_once("-->")?.1.trim() {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl Action for SelinuxAction {
+    fn name(&self) -> String {
+        "selinux".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "mode".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("mode").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("enforcing".to_string()),
+                        ActionParamBaseValue::String("permissive".to_string()),
+                        ActionParamBaseValue::String("disabled".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "booleans".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("booleans")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Map(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "restorecon".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("restorecon")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![
+                        ActionParamType::String,
+                        ActionParamType::List(ActionParamBaseType::String),
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let mode = params.base(0).map(|mode| match mode.expect_string() {
+            "enforcing" => SelinuxMode::Enforcing,
+            "permissive" => SelinuxMode::Permissive,
+            _ => SelinuxMode::Disabled,
+        });
+        let mut booleans = Vec::new();
+        for (name, value) in params.map(1).unwrap_or_default() {
+            let value = match value.expect_string() {
+                "on" | "true" | "1" => true,
+                "off" | "false" | "0" => false,
+                value => {
+                    return Error::new(format!(
+                        "{value} isn't a valid value of SELinux boolean {name}, it should be on or off"
+                    ))
+                    .with_origin(params.origin, &params.span)
+                    .err();
+                }
+            };
+            booleans.push((name.clone(), value));
+        }
+        let restorecon = match params.values[2].as_ref() {
+            Some(paths) => match paths.string_with_span() {
+                Some((path, span)) => vec![(path.to_string(), span)],
+                None => paths
+                    .expect_list()
+                    .iter()
+                    .map(|v| (v.expect_string().to_string(), &params.span))
+                    .collect(),
+            },
+            None => Vec::new(),
+        };
+        for (path, span) in &restorecon {
+            if !Path::new(path).is_absolute() {
+                return Error::new(format!("{path} in restorecon should be an absolute path"))
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+        }
+        let restorecon = restorecon
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        if mode.is_none() && booleans.is_empty() && restorecon.is_empty() {
+            return Error::new("selinux needs mode, booleans or restorecon in params")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        let input = SelinuxAction {
+            mode,
+            booleans,
+            restorecon,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: SelinuxAction = bincode::deserialize(input)?;
+        let changes = input.changes()?;
+        if changes.is_empty() {
+            return Ok("".to_string());
+        }
+
+        if let Some(mode) = input.mode {
+            let config = std::fs::read_to_string(SELINUX_CONFIG).unwrap_or_default();
+            if config_mode(&config) != Some(mode) {
+                std::fs::write(SELINUX_CONFIG, config_with_mode(&config, mode))
+                    .map_err(|e| anyhow!("can't write {SELINUX_CONFIG}: {e}"))?;
+            }
+            if let Some(arg) = runtime_change(current_mode()?, mode) {
+                if !run_command(id, tx, "setenforce", &[arg.to_string()])?.success() {
+                    return Err(anyhow!("setenforce can't set SELinux to {}", mode.as_str()));
+                }
+            }
+        }
+        for (name, value) in &input.booleans {
+            if get_boolean(name)? == *value {
+                continue;
+            }
+            let args = ["-P".to_string(), name.clone(), on_off(*value).to_string()];
+            if !run_command(id, tx, "setsebool", &args)?.success() {
+                return Err(anyhow!("setsebool can't set {name}"));
+            }
+        }
+        for path in &input.restorecon {
+            let args = ["-R".to_string(), "-v".to_string(), path.clone()];
+            if !run_command(id, tx, "restorecon", &args)?.success() {
+                return Err(anyhow!("restorecon can't restore the contexts of {path}"));
+            }
+        }
+
+        Ok(changes.join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: SelinuxAction = bincode::deserialize(input)?;
+        let changes = input.changes()?;
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(changes.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_selinux() {
+        assert_input(
+            &SelinuxAction::default(),
+            r#"
+                mode       = "enforcing"
+                booleans   = { httpd_can_network_connect = "on", ftpd_anon_write = "off" }
+                restorecon = "/var/www"
+            "#,
+            &SelinuxAction {
+                mode: Some(SelinuxMode::Enforcing),
+                booleans: vec![
+                    ("httpd_can_network_connect".to_string(), true),
+                    ("ftpd_anon_write".to_string(), false),
+                ],
+                restorecon: vec!["/var/www".to_string()],
+            },
+        );
+        assert_eq!(
+            input_error(&SelinuxAction::default(), r#"booleans = {}"#),
+            "selinux needs mode, booleans or restorecon in params"
+        );
+        assert_eq!(
+            input_error(
+                &SelinuxAction::default(),
+                r#"booleans = { httpd_can_network_connect = "yes" }"#
+            ),
+            "yes isn't a valid value of SELinux boolean httpd_can_network_connect, it should be on or off"
+        );
+        assert_eq!(
+            input_error(&SelinuxAction::default(), r#"restorecon = ["var/www"]"#),
+            "var/www in restorecon should be an absolute path"
+        );
+    }
+
+    #[test]
+    fn selinux_config() {
+        let config = "# SELINUX= can take one of these three values\nSELINUX=enforcing\nSELINUXTYPE=targeted\n";
+        assert_eq!(config_mode(config), Some(SelinuxMode::Enforcing));
+        assert_eq!(
+            config_with_mode(config, SelinuxMode::Permissive),
+            "# SELINUX= can take one of these three values\nSELINUX=permissive\nSELINUXTYPE=targeted\n"
+        );
+        assert_eq!(
+            config_with_mode("SELINUXTYPE=targeted\n", SelinuxMode::Disabled),
+            "SELINUXTYPE=targeted\nSELINUX=disabled\n"
+        );
+        assert_eq!(
+            runtime_change(SelinuxMode::Disabled, SelinuxMode::Enforcing),
+            None
+        );
+        assert_eq!(
+            parse_getsebool("httpd_can_network_connect --> on\n"),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionRollback},
+    error::Error,
+    node::Resource,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServiceState {
+    Started,
+    Stopped,
+    Restarted,
+    Reloaded,
+}
+
+/// Manage a system service, with systemd on Linux and launchd on macOS
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct ServiceAction {
+    /// The name of the service, which is the label of the launchd job on macOS
+    name: String,
+    /// The state of the service
+    ///
+    /// `started` and `stopped` only change the service if it isn't in the state
+    ///
+    /// `restarted` and `reloaded` always restart or reload the service
+    state: Option<ServiceState>,
+    /// Whether the service starts on boot
+    enabled: Option<bool>,
+}
+
+impl ServiceAction {
+    pub(super) fn new(name: String, state: Option<ServiceState>, enabled: Option<bool>) -> Self {
+        Self {
+            name,
+            state,
+            enabled,
+        }
+    }
+}
+
+impl Action for ServiceAction {
+    fn name(&self) -> String {
+        "service".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: ServiceAction::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: ServiceAction::get_field_docs("name")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: ServiceAction::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("started".to_string()),
+                        ActionParamBaseValue::String("stopped".to_string()),
+                        ActionParamBaseValue::String("restarted".to_string()),
+                        ActionParamBaseValue::String("reloaded".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "enabled".to_string(),
+                    required: false,
+                    description: ServiceAction::get_field_docs("enabled")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let name = params.expect_string(0);
+        let state = params.base(1).map(|state| match state.expect_string() {
+            "started" => ServiceState::Started,
+            "stopped" => ServiceState::Stopped,
+            "restarted" => ServiceState::Restarted,
+            "reloaded" => ServiceState::Reloaded,
+            _ => unreachable!(),
+        });
+        let enabled = params.bool(2);
+        if state.is_none() && enabled.is_none() {
+            return Error::new("service needs state or enabled in params")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        let input = ServiceAction {
+            name: name.to_string(),
+            state,
+            enabled,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: ServiceAction = bincode::deserialize(input)?;
+        let manager = ServiceManager::detect()?;
+        for (program, args) in manager.changes(&input)? {
+            let status = run_command(id, tx, program, &args)?;
+            if !status.success() {
+                return Err(anyhow!("{program} {} failed", args.join(" ")));
+            }
+        }
+        Ok(format!("service {}", input.name))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: ServiceAction = bincode::deserialize(input)?;
+        let manager = ServiceManager::detect()?;
+        let changes = manager.changes(&input)?;
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            changes
+                .iter()
+                .map(|(program, args)| format!("{program} {}", args.join(" ")))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+
+    fn rollback(&self, input: &[u8]) -> Result<Option<ActionRollback>> {
+        let input: ServiceAction = bincode::deserialize(input)?;
+        let manager = ServiceManager::detect()?;
+        // restarting or reloading can't be undone
+        let state = match input.state {
+            Some(ServiceState::Started) if !manager.active(&input.name)? => {
+                Some(ServiceState::Stopped)
+            }
+            Some(ServiceState::Stopped) if manager.active(&input.name)? => {
+                Some(ServiceState::Started)
+            }
+            _ => None,
+        };
+        let enabled = match input.enabled {
+            Some(enabled) if manager.enabled(&input.name)? != enabled => Some(!enabled),
+            _ => None,
+        };
+        if state.is_none() && enabled.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(ActionRollback {
+            action: self.name(),
+            input: bincode::serialize(&ServiceAction {
+                name: input.name,
+                state,
+                enabled,
+            })?,
+        }))
+    }
+
+    fn resources(&self, input: &[u8]) -> Vec<Resource> {
+        match bincode::deserialize::<ServiceAction>(input) {
+            Ok(input) => vec![Resource::Service { name: input.name }],
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Whether the service is running, and whether it starts on boot
+pub(crate) fn service_state(name: &str) -> Result<(bool, bool)> {
+    let manager = ServiceManager::detect()?;
+    Ok((manager.active(name)?, manager.enabled(name)?))
+}
+
+pub(super) enum ServiceManager {
+    Systemd,
+    Launchd,
+}
+
+impl ServiceManager {
+    fn detect() -> Result<Self> {
+        match std::env::consts::OS {
+            "linux" => Ok(Self::Systemd),
+            "macos" => Ok(Self::Launchd),
+            os => Err(anyhow!("service isn't supported on {os}")),
+        }
+    }
+
+    /// Whether the service is running
+    pub(super) fn active(&self, name: &str) -> Result<bool> {
+        let output = match self {
+            ServiceManager::Systemd => Command::new("systemctl")
+                .args(["is-active", "--quiet", name])
+                .output()?,
+            ServiceManager::Launchd => Command::new("launchctl")
+                .args(["print", &launchd_target(name)])
+                .output()?,
+        };
+        Ok(output.status.success())
+    }
+
+    /// Whether the service starts on boot
+    fn enabled(&self, name: &str) -> Result<bool> {
+        match self {
+            ServiceManager::Systemd => {
+                let output = Command::new("systemctl")
+                    .args(["is-enabled", "--quiet", name])
+                    .output()?;
+                Ok(output.status.success())
+            }
+            ServiceManager::Launchd => {
+                let output = Command::new("launchctl")
+                    .args(["print-disabled", "system"])
+                    .output()?;
+                let disabled = String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .any(|line| line.trim().starts_with(&format!("\"{name}\" => disabled")));
+                Ok(!disabled)
+            }
+        }
+    }
+
+    /// The commands to bring the service to the state of the input,
+    /// which are empty if it's in the state already
+    pub(super) fn changes(
+        &self,
+        input: &ServiceAction,
+    ) -> Result<Vec<(&'static str, Vec<String>)>> {
+        let name = input.name.clone();
+        let mut changes = Vec::new();
+        if let Some(enabled) = input.enabled {
+            if self.enabled(&name)? != enabled {
+                changes.push(match (self, enabled) {
+                    (ServiceManager::Systemd, true) => {
+                        ("systemctl", vec!["enable".to_string(), name.clone()])
+                    }
+                    (ServiceManager::Systemd, false) => {
+                        ("systemctl", vec!["disable".to_string(), name.clone()])
+                    }
+                    (ServiceManager::Launchd, true) => (
+                        "launchctl",
+                        vec!["enable".to_string(), launchd_target(&name)],
+                    ),
+                    (ServiceManager::Launchd, false) => (
+                        "launchctl",
+                        vec!["disable".to_string(), launchd_target(&name)],
+                    ),
+                });
+            }
+        }
+
+        let Some(state) = input.state else {
+            return Ok(changes);
+        };
+        let active = match state {
+            ServiceState::Started | ServiceState::Stopped => self.active(&name)?,
+            _ => false,
+        };
+        let command = match (self, state) {
+            (_, ServiceState::Started) if active => None,
+            (_, ServiceState::Stopped) if !active => None,
+            (ServiceManager::Systemd, ServiceState::Started) => Some(vec!["start"]),
+            (ServiceManager::Systemd, ServiceState::Stopped) => Some(vec!["stop"]),
+            (ServiceManager::Systemd, ServiceState::Restarted) => Some(vec!["restart"]),
+            (ServiceManager::Systemd, ServiceState::Reloaded) => Some(vec!["reload"]),
+            // the launchd job is loaded from its plist in /Library/LaunchDaemons
+            (ServiceManager::Launchd, ServiceState::Started) => {
+                changes.push((
+                    "launchctl",
+                    vec![
+                        "bootstrap".to_string(),
+                        "system".to_string(),
+                        format!("/Library/LaunchDaemons/{name}.plist"),
+                    ],
+                ));
+                return Ok(changes);
+            }
+            (ServiceManager::Launchd, ServiceState::Stopped) => Some(vec!["bootout"]),
+            (ServiceManager::Launchd, ServiceState::Restarted) => Some(vec!["kickstart", "-k"]),
+            // launchd can't reload a job, so it's sent SIGHUP like most daemons expect
+            (ServiceManager::Launchd, ServiceState::Reloaded) => Some(vec!["kill", "HUP"]),
+        };
+        if let Some(command) = command {
+            let mut args: Vec<String> = command.into_iter().map(|s| s.to_string()).collect();
+            match self {
+                ServiceManager::Systemd => {
+                    args.push(name);
+                    changes.push(("systemctl", args));
+                }
+                ServiceManager::Launchd => {
+                    args.push(launchd_target(&name));
+                    changes.push(("launchctl", args));
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// The launchd service target of the job in the system domain
+fn launchd_target(name: &str) -> String {
+    format!("system/{name}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_state_and_enabled() {
+        assert_input(
+            &ServiceAction::default(),
+            r#"
+                name = "nginx"
+                state = "started"
+                enabled = true
+            "#,
+            &ServiceAction {
+                name: "nginx".to_string(),
+                state: Some(ServiceState::Started),
+                enabled: Some(true),
+            },
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(
+            input_error(&ServiceAction::default(), r#"name = "nginx""#),
+            "service needs state or enabled in params"
+        );
+        assert_eq!(
+            input_error(
+                &ServiceAction::default(),
+                r#"
+                    name = "nginx"
+                    state = "running"
+                "#
+            ),
+            "state type should be Enum of \"started\", \"stopped\", \"restarted\", \"reloaded\""
+        );
+    }
+}
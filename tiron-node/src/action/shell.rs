@@ -0,0 +1,215 @@
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::{run_command_with_stdin, OutputFormat},
+    Action, ActionDoc, ActionParamBaseType, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+/// Run the command with a shell on the remote machine, so that it can use pipes,
+/// redirections and the other features of the shell, e.g. `"dmesg | tail -n 20 > /tmp/dmesg"`.
+/// Use `command` when it doesn't need them, so that the args don't need quoting
+///
+/// Output lines starting with `::warn::`, `::error::` or `::success::`
+/// are shown with that level.
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct ShellAction {
+    /// The command line that's run with `<shell> -c`
+    cmd: String,
+    /// The shell to run the command with. Default to `/bin/sh`
+    shell: Option<String>,
+    /// The absolute path of the directory to run the command in
+    chdir: Option<String>,
+    /// The environment variables that are set for the command,
+    /// e.g. `{ RUST_LOG = "debug" }`
+    env: Vec<(String, String)>,
+    /// The string that's written to the stdin of the command
+    stdin: Option<String>,
+    /// Parse the stdout of the command as `"json"`, which is kept as the result
+    /// of the action. The action fails if the output isn't valid JSON
+    parse_output: Option<OutputFormat>,
+}
+
+impl ShellAction {
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(self.shell.as_deref().unwrap_or("/bin/sh"));
+        cmd.arg("-c").arg(&self.cmd);
+        if let Some(chdir) = &self.chdir {
+            cmd.current_dir(chdir);
+        }
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)));
+        cmd
+    }
+}
+
+impl Action for ShellAction {
+    fn name(&self) -> String {
+        "shell".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "cmd".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("cmd").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "shell".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("shell")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "chdir".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("chdir")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "env".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("env").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::Map(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "stdin".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("stdin")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "parse_output".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("parse_output")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![OutputFormat::param_type()],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let cmd = params.expect_string(0);
+        let chdir = match params.values[2].as_ref() {
+            Some(chdir) => {
+                let (chdir, span) = chdir.expect_string_with_span();
+                if !Path::new(chdir).is_absolute() {
+                    return Error::new("chdir should be an absolute path")
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+                Some(chdir.to_string())
+            }
+            None => None,
+        };
+        let mut env = Vec::new();
+        for (key, value) in params.map(3).unwrap_or_default() {
+            if key.is_empty() || key.contains('=') {
+                return Error::new(format!("{key} isn't a valid environment variable name"))
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            env.push((key.clone(), value.expect_string().to_string()));
+        }
+
+        let input = ShellAction {
+            cmd: cmd.to_string(),
+            shell: params.string(1).map(|s| s.to_string()),
+            chdir,
+            env,
+            stdin: params.string(4).map(|s| s.to_string()),
+            parse_output: OutputFormat::from_param(params.base(5)),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: ShellAction = bincode::deserialize(input)?;
+        let (status, stdout) = run_command_with_stdin(
+            id,
+            tx,
+            input.command(),
+            input.stdin.as_deref(),
+            input.parse_output.is_some(),
+        )
+        .map_err(|e| anyhow!("can't run the shell: {e}"))?;
+        if !status.success() {
+            return Err(anyhow!("shell command failed with {status}"));
+        }
+        if let Some(format) = input.parse_output {
+            format.send(id, tx, &stdout)?;
+        }
+        Ok("shell".to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_shell() {
+        assert_input(
+            &ShellAction::default(),
+            r#"
+                cmd   = "sort | uniq > /tmp/names"
+                shell = "/bin/bash"
+                chdir = "/tmp"
+                env   = { LC_ALL = "C" }
+                stdin = "b\na\nb\n"
+            "#,
+            &ShellAction {
+                cmd: "sort | uniq > /tmp/names".to_string(),
+                shell: Some("/bin/bash".to_string()),
+                chdir: Some("/tmp".to_string()),
+                env: vec![("LC_ALL".to_string(), "C".to_string())],
+                stdin: Some("b\na\nb\n".to_string()),
+                parse_output: None,
+            },
+        );
+        assert_eq!(
+            input_error(
+                &ShellAction::default(),
+                r#"
+                    cmd   = "ls"
+                    chdir = "tmp"
+                "#
+            ),
+            "chdir should be an absolute path"
+        );
+        assert_eq!(
+            input_error(
+                &ShellAction::default(),
+                r#"
+                    cmd = "ls"
+                    env = ["LC_ALL=C"]
+                "#
+            ),
+            "env type should be Map of String"
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionRollback},
+    error::Error,
+};
+use uuid::Uuid;
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue,
+    ActionParamDoc, ActionParamType, ActionParams,
+};
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub enum SnapshotProvider {
+    #[default]
+    Lvm,
+    Zfs,
+    Command,
+}
+
+/// Create a snapshot of a volume, which is restored when the action is rolled back
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct SnapshotAction {
+    /// How to create the snapshot
+    ///
+    /// `lvm` to create a LVM snapshot of the logical volume
+    ///
+    /// `zfs` to create a ZFS snapshot of the dataset
+    ///
+    /// `command` to run the `create` and `restore` commands,
+    /// e.g. to call the API of a cloud provider
+    provider: SnapshotProvider,
+    /// The LVM logical volume, e.g. `vg0/root`, or the ZFS dataset, e.g. `tank/data`
+    target: String,
+    /// The name of the snapshot, default to a generated one
+    name: String,
+    /// The size of the LVM snapshot, default to `1G`
+    size: String,
+    /// The command and its arguments to create the snapshot
+    create: Vec<String>,
+    /// The command and its arguments to restore the snapshot
+    restore: Vec<String>,
+    // restore the snapshot instead of creating it
+    restoring: bool,
+}
+
+impl SnapshotAction {
+    fn description(&self) -> String {
+        match self.provider {
+            SnapshotProvider::Lvm | SnapshotProvider::Zfs => {
+                format!("snapshot {} of {}", self.name, self.target)
+            }
+            SnapshotProvider::Command => format!("snapshot {}", self.name),
+        }
+    }
+
+    fn command(&self) -> Result<(String, Vec<String>)> {
+        let (program, args) = match (&self.provider, self.restoring) {
+            (SnapshotProvider::Lvm, false) => (
+                "lvcreate",
+                vec![
+                    "--snapshot".to_string(),
+                    "--name".to_string(),
+                    self.name.clone(),
+                    "--size".to_string(),
+                    self.size.clone(),
+                    self.target.clone(),
+                ],
+            ),
+            (SnapshotProvider::Lvm, true) => {
+                // the snapshot is in the same volume group as the target
+                let snapshot = match self.target.rsplit_once('/') {
+                    Some((group, _)) => format!("{group}/{}", self.name),
+                    None => self.name.clone(),
+                };
+                ("lvconvert", vec!["--merge".to_string(), snapshot])
+            }
+            (SnapshotProvider::Zfs, false) => (
+                "zfs",
+                vec![
+                    "snapshot".to_string(),
+                    format!("{}@{}", self.target, self.name),
+                ],
+            ),
+            (SnapshotProvider::Zfs, true) => (
+                "zfs",
+                vec![
+                    "rollback".to_string(),
+                    "-r".to_string(),
+                    format!("{}@{}", self.target, self.name),
+                ],
+            ),
+            (SnapshotProvider::Command, restoring) => {
+                let cmd = if restoring {
+                    &self.restore
+                } else {
+                    &self.create
+                };
+                let (program, args) = cmd
+                    .split_first()
+                    .ok_or_else(|| anyhow!("snapshot command is empty"))?;
+                return Ok((program.to_string(), args.to_vec()));
+            }
+        };
+        Ok((program.to_string(), args))
+    }
+}
+
+impl Action for SnapshotAction {
+    fn name(&self) -> String {
+        "snapshot".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "provider".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("provider")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("lvm".to_string()),
+                        ActionParamBaseValue::String("zfs".to_string()),
+                        ActionParamBaseValue::String("command".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "target".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("target")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "size".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("size").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "create".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("create")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "restore".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("restore")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let provider = match params.expect_base(0).expect_string() {
+            "lvm" => SnapshotProvider::Lvm,
+            "zfs" => SnapshotProvider::Zfs,
+            "command" => SnapshotProvider::Command,
+            _ => unreachable!(),
+        };
+        let string = |i: usize| {
+            params.values[i]
+                .as_ref()
+                .map(|v| v.expect_string().to_string())
+        };
+        let list = |i: usize| {
+            params
+                .list(i)
+                .map(|list| {
+                    list.iter()
+                        .map(|v| v.expect_string().to_string())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        let input = SnapshotAction {
+            target: string(1).unwrap_or_default(),
+            name: string(2).unwrap_or_else(|| format!("tiron-{}", Uuid::new_v4().simple())),
+            size: string(3).unwrap_or_else(|| "1G".to_string()),
+            create: list(4),
+            restore: list(5),
+            restoring: false,
+            provider,
+        };
+        match input.provider {
+            SnapshotProvider::Lvm | SnapshotProvider::Zfs => {
+                if input.target.is_empty() {
+                    return Error::new("can't find target in params, it's required")
+                        .with_origin(params.origin, &params.span)
+                        .err();
+                }
+            }
+            SnapshotProvider::Command => {
+                if input.create.is_empty() || input.restore.is_empty() {
+                    return Error::new("command snapshot needs both create and restore")
+                        .with_origin(params.origin, &params.span)
+                        .err();
+                }
+            }
+        }
+
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: SnapshotAction = bincode::deserialize(input)?;
+        let (program, args) = input.command()?;
+        let status = run_command(id, tx, &program, &args)?;
+        let action = if input.restoring { "restore" } else { "create" };
+        if status.success() {
+            Ok(format!("{action} {}", input.description()))
+        } else {
+            Err(anyhow!("can't {action} {}", input.description()))
+        }
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: SnapshotAction = bincode::deserialize(input)?;
+        Ok(Some(format!("create {}", input.description())))
+    }
+
+    fn rollback(&self, input: &[u8]) -> Result<Option<ActionRollback>> {
+        let mut input: SnapshotAction = bincode::deserialize(input)?;
+        input.restoring = true;
+        Ok(Some(ActionRollback {
+            action: self.name(),
+            input: bincode::serialize(&input)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_zfs() {
+        assert_input(
+            &SnapshotAction::default(),
+            r#"
+                provider = "zfs"
+                target = "tank/data"
+                name = "before-upgrade"
+            "#,
+            &SnapshotAction {
+                provider: SnapshotProvider::Zfs,
+                target: "tank/data".to_string(),
+                name: "before-upgrade".to_string(),
+                size: "1G".to_string(),
+                create: Vec::new(),
+                restore: Vec::new(),
+                restoring: false,
+            },
+        );
+    }
+
+    #[test]
+    fn restore_commands() {
+        let lvm = SnapshotAction {
+            provider: SnapshotProvider::Lvm,
+            target: "vg0/root".to_string(),
+            name: "snap".to_string(),
+            restoring: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            lvm.command().unwrap(),
+            (
+                "lvconvert".to_string(),
+                vec!["--merge".to_string(), "vg0/snap".to_string()]
+            )
+        );
+
+        let command = SnapshotAction {
+            provider: SnapshotProvider::Command,
+            create: vec!["./snapshot.sh".to_string(), "create".to_string()],
+            restore: vec!["./snapshot.sh".to_string(), "restore".to_string()],
+            restoring: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            command.command().unwrap(),
+            ("./snapshot.sh".to_string(), vec!["restore".to_string()])
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        assert_eq!(
+            input_error(&SnapshotAction::default(), r#"provider = "lvm""#),
+            "can't find target in params, it's required"
+        );
+        assert_eq!(
+            input_error(
+                &SnapshotAction::default(),
+                r#"
+                    provider = "command"
+                    create = ["./snapshot.sh"]
+                "#
+            ),
+            "command snapshot needs both create and restore"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use std::{fs::Metadata, path::Path, time::UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionOutputLevel},
+    crypto::{hex, sha256},
+    error::Error,
+};
+
+use super::{send_value, Action, ActionDoc, ActionParamDoc, ActionParamType, ActionParams};
+
+/// Get the state of a path on the remote machine, which is whether it exists, its type,
+/// size, mode, owner, group, modification time and the sha256 checksum of a file.
+/// It doesn't change anything on the host, and the state is kept as the result of
+/// the action in the run history
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct StatAction {
+    /// The absolute path on the remote machine
+    path: String,
+    /// Get the state of what the symlink at the path points to,
+    /// rather than the symlink itself. Default to true
+    follow: bool,
+    /// Get the sha256 checksum of the file, which reads the whole file. Default to true
+    checksum: bool,
+}
+
+/// The state of the path, which is the structured result of the action
+#[derive(Debug, PartialEq, Serialize)]
+struct PathState {
+    path: String,
+    exists: bool,
+    // file, directory, symlink or other
+    #[serde(rename = "type")]
+    type_: Option<&'static str>,
+    size: Option<u64>,
+    // in octal, e.g. 0644
+    mode: Option<String>,
+    // the names, or the ids if they don't have names
+    owner: Option<String>,
+    group: Option<String>,
+    // unix timestamp in seconds
+    mtime: Option<u64>,
+    checksum: Option<String>,
+}
+
+impl PathState {
+    fn missing(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            exists: false,
+            type_: None,
+            size: None,
+            mode: None,
+            owner: None,
+            group: None,
+            mtime: None,
+            checksum: None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let Some(type_) = self.type_ else {
+            return format!("{} doesn't exist", self.path);
+        };
+        let mut line = format!("{} is a {type_}", self.path);
+        if let Some(size) = self.size {
+            line.push_str(&format!(" of {size} bytes"));
+        }
+        if let (Some(mode), Some(owner), Some(group)) = (&self.mode, &self.owner, &self.group) {
+            line.push_str(&format!(", {mode} {owner}:{group}"));
+        }
+        if let Some(checksum) = &self.checksum {
+            line.push_str(&format!(", sha256 {checksum}"));
+        }
+        line
+    }
+}
+
+impl StatAction {
+    fn state(&self) -> Result<PathState> {
+        let path = Path::new(&self.path);
+        let meta = if self.follow {
+            std::fs::metadata(path)
+        } else {
+            std::fs::symlink_metadata(path)
+        };
+        let meta = match meta {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(PathState::missing(&self.path))
+            }
+            Err(e) => return Err(anyhow!("can't stat {}: {e}", self.path)),
+        };
+        let type_ = if meta.is_file() {
+            "file"
+        } else if meta.is_dir() {
+            "directory"
+        } else if meta.is_symlink() {
+            "symlink"
+        } else {
+            "other"
+        };
+        let checksum = if self.checksum && meta.is_file() {
+            let content =
+                std::fs::read(path).map_err(|e| anyhow!("can't read {}: {e}", self.path))?;
+            Some(hex(&sha256(&content)))
+        } else {
+            None
+        };
+        let (mode, owner, group) = ownership(&meta);
+        Ok(PathState {
+            path: self.path.clone(),
+            exists: true,
+            type_: Some(type_),
+            size: Some(meta.len()),
+            mode,
+            owner,
+            group,
+            mtime: meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            checksum,
+        })
+    }
+
+    fn stat(&self, id: ActionId, tx: &Sender<ActionMessage>) -> Result<()> {
+        let state = self.state()?;
+        let _ = tx.send(ActionMessage::ActionOutputLine {
+            id,
+            content: state.describe(),
+            level: ActionOutputLevel::Info,
+        });
+        send_value(id, tx, &state)
+    }
+}
+
+/// The mode, the owner and the group of the path
+#[cfg(unix)]
+fn ownership(meta: &Metadata) -> (Option<String>, Option<String>, Option<String>) {
+    use std::os::unix::fs::MetadataExt;
+
+    let name = |file: &str, id: u32| {
+        // the entries are like `name:x:id:...`
+        std::fs::read_to_string(file)
+            .ok()
+            .and_then(|content| {
+                content.lines().find_map(|line| {
+                    let mut fields = line.split(':');
+                    let name = fields.next()?;
+                    (fields.nth(1)?.parse::<u32>().ok() == Some(id)).then(|| name.to_string())
+                })
+            })
+            .unwrap_or_else(|| id.to_string())
+    };
+    (
+        Some(format!("{:04o}", meta.mode() & 0o7777)),
+        Some(name("/etc/passwd", meta.uid())),
+        Some(name("/etc/group", meta.gid())),
+    )
+}
+
+#[cfg(not(unix))]
+fn ownership(_meta: &Metadata) -> (Option<String>, Option<String>, Option<String>) {
+    (None, None, None)
+}
+
+impl Action for StatAction {
+    fn name(&self) -> String {
+        "stat".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "path".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("path").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "follow".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("follow")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "checksum".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("checksum")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (path, path_span) = params.expect_string_with_span(0);
+        if !Path::new(path).is_absolute() {
+            return Error::new("path should be an absolute path")
+                .with_origin(params.origin, path_span)
+                .err();
+        }
+
+        let input = StatAction {
+            path: path.to_string(),
+            follow: params.bool(1).unwrap_or(true),
+            checksum: params.bool(2).unwrap_or(true),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: StatAction = bincode::deserialize(input)?;
+        input.stat(id, tx)?;
+        Ok(format!("stat {}", input.path))
+    }
+
+    fn dry_run(
+        &self,
+        id: ActionId,
+        input: &[u8],
+        tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        // it doesn't change anything, so it gets the state in the dry run as well
+        let input: StatAction = bincode::deserialize(input)?;
+        input.stat(id, tx)?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_stat() {
+        assert_input(
+            &StatAction::default(),
+            r#"
+                path     = "/var/log/app.log"
+                checksum = false
+            "#,
+            &StatAction {
+                path: "/var/log/app.log".to_string(),
+                follow: true,
+                checksum: false,
+            },
+        );
+        assert_eq!(
+            input_error(&StatAction::default(), r#"path = "app.log""#),
+            "path should be an absolute path"
+        );
+    }
+
+    #[test]
+    fn stat_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+        std::fs::write(&path, "port = 80\n").unwrap();
+        let action = StatAction {
+            path: path.to_string_lossy().to_string(),
+            follow: true,
+            checksum: true,
+        };
+        let state = action.state().unwrap();
+        assert!(state.exists);
+        assert_eq!(state.type_, Some("file"));
+        assert_eq!(state.size, Some(10));
+        assert_eq!(state.checksum, Some(hex(&sha256(b"port = 80\n"))),);
+
+        let action = StatAction {
+            path: dir.path().join("missing").to_string_lossy().to_string(),
+            ..action
+        };
+        assert_eq!(action.state().unwrap(), PathState::missing(&action.path));
+    }
+}
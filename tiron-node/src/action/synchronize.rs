@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionData, ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseType, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+/// Synchronize a local directory to the remote machine with rsync, which only sends
+/// what has changed, so it's much faster than `copy` for large trees. rsync needs to be
+/// installed on both machines. It's run by tiron itself for a remote host, through
+/// the same ssh connection as the node, so it can't synchronize to a container
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct SynchronizeAction {
+    /// The local directory, which is relative to the runbook. Like rsync, a src ending
+    /// with `/` synchronizes the content of the directory rather than the directory itself
+    src: String,
+    /// The absolute path of the directory on the remote machine
+    dest: String,
+    /// Delete the files in dest that aren't in src. Default to false
+    delete: bool,
+    /// The patterns of the files to leave out, e.g. `["*.log", ".git"]`
+    exclude: Vec<String>,
+    // set by tiron, whether tiron runs rsync to the remote host itself, or the node runs it
+    // on the same machine, which is None in a container
+    #[serde(default)]
+    remote: Option<bool>,
+}
+
+/// How tiron connects rsync to the remote host
+pub struct RsyncRemote {
+    // the ssh command for `--rsh`
+    pub rsh: String,
+    // e.g. `user@web1`
+    pub destination: String,
+    // run rsync on the host with sudo
+    pub sudo: bool,
+}
+
+impl SynchronizeAction {
+    /// The input marked for the host, which is remote if tiron runs rsync itself
+    pub fn with_remote(input: &[u8], remote: bool) -> Option<Vec<u8>> {
+        let mut input: SynchronizeAction = bincode::deserialize(input).ok()?;
+        input.remote = Some(remote);
+        bincode::serialize(&input).ok()
+    }
+
+    /// Whether it's a synchronize that tiron runs itself when the node gets to it
+    pub fn is_remote(action: &ActionData) -> bool {
+        action.action == "synchronize"
+            && !action.dry_run
+            && bincode::deserialize::<SynchronizeAction>(&action.input)
+                .is_ok_and(|input| input.remote == Some(true))
+    }
+
+    /// Run rsync with the input, to the remote host if it's given
+    pub fn rsync(
+        id: ActionId,
+        input: &[u8],
+        remote: Option<&RsyncRemote>,
+        tx: &Sender<ActionMessage>,
+    ) -> Result<String> {
+        let input: SynchronizeAction = bincode::deserialize(input)?;
+        let status = run_command(id, tx, "rsync", &input.args(remote))
+            .map_err(|e| anyhow!("can't run rsync: {e}"))?;
+        if !status.success() {
+            return Err(anyhow!("rsync failed with {status}"));
+        }
+        Ok(format!("synchronized {} to {}", input.src, input.dest))
+    }
+
+    fn args(&self, remote: Option<&RsyncRemote>) -> Vec<String> {
+        // the changed files are printed as the output of the action
+        let mut args = vec!["--archive".to_string(), "--itemize-changes".to_string()];
+        if self.delete {
+            args.push("--delete".to_string());
+        }
+        for pattern in &self.exclude {
+            args.push(format!("--exclude={pattern}"));
+        }
+        match remote {
+            Some(remote) => {
+                args.push(format!("--rsh={}", remote.rsh));
+                if remote.sudo {
+                    args.push("--rsync-path=sudo rsync".to_string());
+                }
+                args.push(self.src.clone());
+                args.push(format!("{}:{}", remote.destination, self.dest));
+            }
+            None => {
+                args.push(self.src.clone());
+                args.push(self.dest.clone());
+            }
+        }
+        args
+    }
+}
+
+impl Action for SynchronizeAction {
+    fn name(&self) -> String {
+        "synchronize".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("src").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "dest".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("dest").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "delete".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("delete")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "exclude".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("exclude")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (src, src_span) = params.expect_string_with_span(0);
+        let src_path = params.origin.cwd.join(src);
+        if !src_path.is_dir() {
+            return Error::new(format!("{} isn't a directory", src_path.display()))
+                .with_origin(params.origin, src_span)
+                .err();
+        }
+        let (dest, dest_span) = params.expect_string_with_span(1);
+        if !Path::new(dest).is_absolute() {
+            return Error::new("dest should be an absolute path")
+                .with_origin(params.origin, dest_span)
+                .err();
+        }
+        let exclude = params
+            .list(3)
+            .map(|list| list.iter().map(|v| v.expect_string().to_string()).collect())
+            .unwrap_or_default();
+
+        let input = SynchronizeAction {
+            src: src_path.to_string_lossy().to_string(),
+            dest: dest.to_string(),
+            delete: params.bool(2).unwrap_or(false),
+            exclude,
+            remote: None,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let action: SynchronizeAction = bincode::deserialize(input)?;
+        match action.remote {
+            // the node only tells tiron that the actions before it are done,
+            // and tiron runs rsync to the host
+            Some(true) => Ok(format!("got to synchronize {}", action.dest)),
+            Some(false) => Self::rsync(id, input, None, tx),
+            None => Err(anyhow!("synchronize can't be used in a container")),
+        }
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: SynchronizeAction = bincode::deserialize(input)?;
+        Ok(Some(format!("synchronize {} to {}", input.src, input.dest)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input_in, input_error};
+
+    #[test]
+    fn input_synchronize() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("site")).unwrap();
+        assert_input_in(
+            &SynchronizeAction::default(),
+            dir.path(),
+            r#"
+                src     = "site/"
+                dest    = "/var/www/site"
+                delete  = true
+                exclude = ["*.log"]
+            "#,
+            &SynchronizeAction {
+                src: dir.path().join("site/").to_string_lossy().to_string(),
+                dest: "/var/www/site".to_string(),
+                delete: true,
+                exclude: vec!["*.log".to_string()],
+                remote: None,
+            },
+        );
+        assert_eq!(
+            input_error(
+                &SynchronizeAction::default(),
+                r#"
+                    src  = "."
+                    dest = "www"
+                "#
+            ),
+            "dest should be an absolute path"
+        );
+    }
+
+    #[test]
+    fn synchronize_args() {
+        let action = SynchronizeAction {
+            src: "/srv/site/".to_string(),
+            dest: "/var/www/site".to_string(),
+            delete: true,
+            exclude: vec![".git".to_string()],
+            remote: Some(true),
+        };
+        let remote = RsyncRemote {
+            rsh: "ssh -p 2222".to_string(),
+            destination: "deploy@web1".to_string(),
+            sudo: true,
+        };
+        assert_eq!(
+            action.args(Some(&remote)),
+            [
+                "--archive",
+                "--itemize-changes",
+                "--delete",
+                "--exclude=.git",
+                "--rsh=ssh -p 2222",
+                "--rsync-path=sudo rsync",
+                "/srv/site/",
+                "deploy@web1:/var/www/site",
+            ]
+        );
+        assert_eq!(action.args(None)[4..], ["/srv/site/", "/var/www/site"]);
+    }
+}
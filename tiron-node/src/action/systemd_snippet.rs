@@ -0,0 +1,275 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType,
+    ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnippetKind {
+    #[default]
+    Sysusers,
+    Tmpfiles,
+}
+
+impl SnippetKind {
+    fn dir(&self) -> &'static str {
+        match self {
+            SnippetKind::Sysusers => "/etc/sysusers.d",
+            SnippetKind::Tmpfiles => "/etc/tmpfiles.d",
+        }
+    }
+
+    /// The generator that applies the snippet right away instead of at the next boot
+    fn generator(&self, path: &str) -> (&'static str, Vec<String>) {
+        match self {
+            SnippetKind::Sysusers => ("systemd-sysusers", vec![path.to_string()]),
+            SnippetKind::Tmpfiles => (
+                "systemd-tmpfiles",
+                vec!["--create".to_string(), path.to_string()],
+            ),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnippetState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Manage a snippet of systemd-sysusers in `/etc/sysusers.d`, or of systemd-tmpfiles
+/// in `/etc/tmpfiles.d`, which is how systemd distros declare the system users
+/// and the directories. The generator runs on the snippet when it changes
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct SystemdSnippetAction {
+    /// Default to `sysusers`<br>
+    ///
+    /// If `sysusers`, the snippet declares the users and the groups, and
+    /// `systemd-sysusers` creates them.
+    ///
+    /// If `tmpfiles`, the snippet declares the files and the directories, and
+    /// `systemd-tmpfiles --create` creates them.
+    kind: SnippetKind,
+    /// The name of the snippet, which is the file name without `.conf`
+    name: String,
+    /// The lines of the snippet, which is required if the state is `present`,
+    /// e.g. `u myapp - "My App" /var/lib/myapp` or `d /run/myapp 0755 myapp myapp -`
+    content: String,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the snippet is written, and the generator runs if it changed.
+    ///
+    /// If `absent`, the snippet is removed. What the generator created is left alone.
+    state: SnippetState,
+}
+
+impl SystemdSnippetAction {
+    fn path(&self) -> PathBuf {
+        PathBuf::from(self.kind.dir()).join(format!("{}.conf", self.name))
+    }
+
+    /// The snippet is already as it should be
+    fn is_current(&self) -> bool {
+        match self.state {
+            SnippetState::Present => {
+                std::fs::read_to_string(self.path()).ok().as_deref() == Some(self.content.as_str())
+            }
+            SnippetState::Absent => !self.path().exists(),
+        }
+    }
+
+    /// Write the snippet with a rename, so that the generator never reads half of it
+    fn write(&self) -> Result<()> {
+        let dir = self.kind.dir();
+        std::fs::create_dir_all(dir).map_err(|e| anyhow!("can't create {dir}: {e}"))?;
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        temp.write_all(self.content.as_bytes())?;
+        temp.flush()?;
+        // the temp file is only readable by the owner
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp.as_file()
+                .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+        }
+        temp.persist(self.path())
+            .map_err(|e| anyhow!("can't write {}: {e}", self.path().display()))?;
+        Ok(())
+    }
+}
+
+impl Action for SystemdSnippetAction {
+    fn name(&self) -> String {
+        "systemd_snippet".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "kind".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("kind").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("sysusers".to_string()),
+                        ActionParamBaseValue::String("tmpfiles".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "content".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("content")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let kind = match params.base(0).map(|kind| kind.expect_string()) {
+            Some("tmpfiles") => SnippetKind::Tmpfiles,
+            _ => SnippetKind::Sysusers,
+        };
+        let (name, name_span) = params.expect_string_with_span(1);
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Error::new("the name of the snippet should be a file name without /")
+                .with_origin(params.origin, name_span)
+                .err();
+        }
+        let state = match params.base(3).map(|state| state.expect_string()) {
+            Some("absent") => SnippetState::Absent,
+            _ => SnippetState::Present,
+        };
+        let mut content = params.string(2).unwrap_or_default().to_string();
+        if state == SnippetState::Present && content.trim().is_empty() {
+            return Error::new("systemd_snippet needs content in params when the state is present")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+
+        let input = SystemdSnippetAction {
+            kind,
+            name: name.to_string(),
+            content,
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: SystemdSnippetAction = bincode::deserialize(input)?;
+        if input.is_current() {
+            return Ok("".to_string());
+        }
+        let path = input.path();
+        match input.state {
+            SnippetState::Present => {
+                input.write()?;
+                let path = path.to_string_lossy();
+                let (program, args) = input.kind.generator(&path);
+                let status = run_command(id, tx, program, &args)?;
+                if !status.success() {
+                    return Err(anyhow!("{program} failed on {path}"));
+                }
+                Ok(format!("write {path}"))
+            }
+            SnippetState::Absent => {
+                std::fs::remove_file(&path)?;
+                Ok(format!("remove {}", path.display()))
+            }
+        }
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: SystemdSnippetAction = bincode::deserialize(input)?;
+        if input.is_current() {
+            return Ok(None);
+        }
+        let change = match input.state {
+            SnippetState::Present => "write",
+            SnippetState::Absent => "remove",
+        };
+        Ok(Some(format!("{change} {}", input.path().display())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_params() {
+        assert_input(
+            &SystemdSnippetAction::default(),
+            r#"
+                kind = "tmpfiles"
+                name = "myapp"
+                content = "d /run/myapp 0755 myapp myapp -"
+            "#,
+            &SystemdSnippetAction {
+                kind: SnippetKind::Tmpfiles,
+                name: "myapp".to_string(),
+                content: "d /run/myapp 0755 myapp myapp -\n".to_string(),
+                state: SnippetState::Present,
+            },
+        );
+        assert_eq!(
+            input_error(&SystemdSnippetAction::default(), r#"name = "myapp""#),
+            "systemd_snippet needs content in params when the state is present"
+        );
+        assert_eq!(
+            input_error(
+                &SystemdSnippetAction::default(),
+                r#"
+                    name = "../myapp"
+                    state = "absent"
+                "#
+            ),
+            "the name of the snippet should be a file name without /"
+        );
+    }
+}
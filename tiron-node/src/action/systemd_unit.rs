@@ -0,0 +1,310 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+    node::Resource,
+};
+
+use super::{
+    command::run_command,
+    service::{ServiceAction, ServiceManager, ServiceState},
+    template::render_template,
+    Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+/// The directory of the unit files that the admin manages
+const UNIT_DIR: &str = "/etc/systemd/system";
+
+/// Write a systemd unit file to `/etc/systemd/system`, reload systemd if it changed,
+/// and enable or start the unit, all in one action. If the unit file changed while
+/// the unit is running and the state is `started`, the unit is restarted to use it
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct SystemdUnitAction {
+    /// The name of the unit, e.g. `myapp.service` or `backup.timer`.
+    /// It's a service if it doesn't have a suffix
+    name: String,
+    /// The content of the unit file. Either content or src is required
+    content: String,
+    /// Local path of a template of the unit file, which is rendered with the vars
+    /// of the host like the `template` action
+    src: Option<String>,
+    /// Whether the unit starts on boot
+    enabled: Option<bool>,
+    /// The state of the unit, which is `started`, `stopped` or `restarted`
+    state: Option<ServiceState>,
+}
+
+impl SystemdUnitAction {
+    fn path(&self) -> PathBuf {
+        PathBuf::from(UNIT_DIR).join(&self.name)
+    }
+
+    fn is_current(&self) -> bool {
+        std::fs::read_to_string(self.path()).ok().as_deref() == Some(self.content.as_str())
+    }
+
+    /// Write the unit file with a rename, so that systemd never reads half of it
+    fn write(&self) -> Result<()> {
+        std::fs::create_dir_all(UNIT_DIR).map_err(|e| anyhow!("can't create {UNIT_DIR}: {e}"))?;
+        let mut temp = tempfile::NamedTempFile::new_in(UNIT_DIR)?;
+        temp.write_all(self.content.as_bytes())?;
+        temp.flush()?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp.as_file()
+                .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+        }
+        temp.persist(self.path())
+            .map_err(|e| anyhow!("can't write {}: {e}", self.path().display()))?;
+        Ok(())
+    }
+
+    /// The commands for the state and enabled of the unit after the unit file is written
+    fn service_changes(&self, changed: bool) -> Result<Vec<(&'static str, Vec<String>)>> {
+        let manager = ServiceManager::Systemd;
+        let state = match self.state {
+            // the running unit is restarted to use the new unit file
+            Some(ServiceState::Started) if changed && manager.active(&self.name)? => {
+                Some(ServiceState::Restarted)
+            }
+            state => state,
+        };
+        if state.is_none() && self.enabled.is_none() {
+            return Ok(Vec::new());
+        }
+        manager.changes(&ServiceAction::new(self.name.clone(), state, self.enabled))
+    }
+}
+
+impl Action for SystemdUnitAction {
+    fn name(&self) -> String {
+        "systemd_unit".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "content".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("content")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("src").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "enabled".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("enabled")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("started".to_string()),
+                        ActionParamBaseValue::String("stopped".to_string()),
+                        ActionParamBaseValue::String("restarted".to_string()),
+                    ])],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (name, name_span) = params.expect_string_with_span(0);
+        if name.is_empty() || name.contains('/') {
+            return Error::new("the name of the unit can't be empty or have /")
+                .with_origin(params.origin, name_span)
+                .err();
+        }
+        let name = if name.contains('.') {
+            name.to_string()
+        } else {
+            format!("{name}.service")
+        };
+
+        let (content, src) = match (params.string(1), params.values[2].as_ref()) {
+            (Some(content), None) => (content.to_string(), None),
+            (None, Some(src)) => {
+                let (src, src_span) = src.expect_string_with_span();
+                let (src_file, content) = render_template(&params, src, src_span)?;
+                (content, Some(src_file.to_string_lossy().to_string()))
+            }
+            (Some(_), Some(_)) => {
+                return Error::new("systemd_unit can only have one of content and src")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            (None, None) => {
+                return Error::new("systemd_unit needs content or src in params")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+        };
+        let mut content = content;
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+
+        let state = params.base(4).map(|state| match state.expect_string() {
+            "started" => ServiceState::Started,
+            "stopped" => ServiceState::Stopped,
+            "restarted" => ServiceState::Restarted,
+            _ => unreachable!(),
+        });
+
+        let input = SystemdUnitAction {
+            name,
+            content,
+            src,
+            enabled: params.bool(3),
+            state,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: SystemdUnitAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
+        let changed = !input.is_current();
+        if changed {
+            input.write()?;
+            changes.push(format!("write {}", input.path().display()));
+            let args = vec!["daemon-reload".to_string()];
+            if !run_command(id, tx, "systemctl", &args)?.success() {
+                return Err(anyhow!("systemctl daemon-reload failed"));
+            }
+        }
+        for (program, args) in input.service_changes(changed)? {
+            let status = run_command(id, tx, program, &args)?;
+            if !status.success() {
+                return Err(anyhow!("{program} {} failed", args.join(" ")));
+            }
+            changes.push(format!("{program} {}", args.join(" ")));
+        }
+        Ok(changes.join(", "))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: SystemdUnitAction = bincode::deserialize(input)?;
+        let mut changes = Vec::new();
+        let changed = !input.is_current();
+        if changed {
+            changes.push(format!("write {}", input.path().display()));
+            changes.push("systemctl daemon-reload".to_string());
+        }
+        for (program, args) in input.service_changes(changed)? {
+            changes.push(format!("{program} {}", args.join(" ")));
+        }
+        Ok((!changes.is_empty()).then(|| changes.join(", ")))
+    }
+
+    fn resources(&self, input: &[u8]) -> Vec<Resource> {
+        match bincode::deserialize::<SystemdUnitAction>(input) {
+            Ok(input) => {
+                let mut resources = vec![Resource::File {
+                    path: input.path().to_string_lossy().to_string(),
+                }];
+                if input.state.is_some() || input.enabled.is_some() {
+                    resources.push(Resource::Service { name: input.name });
+                }
+                resources
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, assert_input_in, input_error};
+
+    #[test]
+    fn input_content() {
+        assert_input(
+            &SystemdUnitAction::default(),
+            r#"
+                name = "myapp"
+                content = "[Service]\nExecStart=/usr/local/bin/myapp"
+                enabled = true
+                state = "started"
+            "#,
+            &SystemdUnitAction {
+                name: "myapp.service".to_string(),
+                content: "[Service]\nExecStart=/usr/local/bin/myapp\n".to_string(),
+                src: None,
+                enabled: Some(true),
+                state: Some(ServiceState::Started),
+            },
+        );
+        assert_eq!(
+            input_error(&SystemdUnitAction::default(), r#"name = "myapp.service""#),
+            "systemd_unit needs content or src in params"
+        );
+    }
+
+    #[test]
+    fn input_renders_src() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("backup.timer"),
+            "[Timer]\nOnCalendar=${\"daily\"}\n",
+        )
+        .unwrap();
+        assert_input_in(
+            &SystemdUnitAction::default(),
+            dir.path(),
+            r#"
+                name = "backup.timer"
+                src = "backup.timer"
+            "#,
+            &SystemdUnitAction {
+                name: "backup.timer".to_string(),
+                content: "[Timer]\nOnCalendar=daily\n".to_string(),
+                src: Some(
+                    dir.path()
+                        .join("backup.timer")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+                enabled: None,
+                state: None,
+            },
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::{ops::Range, path::PathBuf};
+
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use hcl::{
+    eval::{Context, Evaluate},
+    template::Template,
+};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionRollback},
+    error::Error,
+    node::Resource,
+};
+
+use super::{copy::CopyAction, Action, ActionDoc, ActionParamDoc, ActionParamType, ActionParams};
+
+/// Render the template file with the vars of the host, and copy the result
+/// to the remote machine. The template uses the HCL template syntax,
+/// e.g. `${port}` and `%{ for name in names }${name}%{ endfor }`
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct TemplateAction {
+    /// Local path of the template file
+    src: String,
+    /// The path where the rendered file should be written to on remote server
+    dest: String,
+}
+
+/// Render the template file at src, which is relative to the runbook, with the vars
+/// of the host. Returns the path of the file and the rendered content
+pub(super) fn render_template(
+    params: &ActionParams,
+    src: &str,
+    src_span: &Option<Range<usize>>,
+) -> Result<(PathBuf, String), Error> {
+    let src_file = params.origin.cwd.join(src);
+    let data = std::fs::read_to_string(&src_file).map_err(|e| {
+        Error::new(format!("can't read template file: {e}")).with_origin(params.origin, src_span)
+    })?;
+    let template: Template = data.parse().map_err(|e| {
+        Error::new(format!("can't parse template file: {e}")).with_origin(params.origin, src_span)
+    })?;
+    // without the context of a host, e.g. when the params are checked on their own,
+    // the template can only use what it defines itself
+    let empty = Context::new();
+    let content = template
+        .evaluate(params.ctx.unwrap_or(&empty))
+        .map_err(|e| {
+            Error::new(format!("can't render template file: {e}"))
+                .with_origin(params.origin, src_span)
+        })?;
+    Ok((src_file, content))
+}
+
+impl Action for TemplateAction {
+    fn name(&self) -> String {
+        "template".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: TemplateAction::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: true,
+                    description: TemplateAction::get_field_docs("src")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "dest".to_string(),
+                    required: true,
+                    description: TemplateAction::get_field_docs("dest")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (src, src_span) = params.expect_string_with_span(0);
+        let (src_file, content) = render_template(&params, src, src_span)?;
+
+        let dest = params.expect_string(1);
+
+        // the rendered template is copied the same way as the copy action
+        let input = CopyAction::new(
+            src_file.to_string_lossy().to_string(),
+            content.into_bytes(),
+            dest.to_string(),
+        );
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, bytes: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        CopyAction::default().execute(id, bytes, tx)
+    }
+
+    fn dry_run(
+        &self,
+        id: ActionId,
+        bytes: &[u8],
+        tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        CopyAction::default().dry_run(id, bytes, tx)
+    }
+
+    fn rollback(&self, bytes: &[u8]) -> Result<Option<ActionRollback>> {
+        CopyAction::default().rollback(bytes)
+    }
+
+    fn resources(&self, bytes: &[u8]) -> Vec<Resource> {
+        CopyAction::default().resources(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::{input_from_hcl, testing::assert_input_in};
+
+    #[test]
+    fn input_renders_template() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("hosts.tmpl"),
+            "%{ for name in [\"web1\", \"web2\"] }${name}\n%{ endfor }",
+        )
+        .unwrap();
+        assert_input_in(
+            &TemplateAction::default(),
+            dir.path(),
+            r#"
+                src = "hosts.tmpl"
+                dest = "/etc/hosts.web"
+            "#,
+            &CopyAction::new(
+                dir.path().join("hosts.tmpl").to_string_lossy().to_string(),
+                b"web1\nweb2\n".to_vec(),
+                "/etc/hosts.web".to_string(),
+            ),
+        );
+    }
+
+    #[test]
+    fn input_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("port.tmpl"), "port = ${port}").unwrap();
+        let e = input_from_hcl(
+            &TemplateAction::default(),
+            dir.path(),
+            r#"
+                src = "port.tmpl"
+                dest = "/etc/app.conf"
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert!(e.message.starts_with("can't render template file"));
+        // the error points to the src in the params
+        assert_eq!(e.location.unwrap().line, 2);
+    }
+}
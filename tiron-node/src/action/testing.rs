@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::{input_from_hcl, Action};
+
+/// Assert that the params in HCL are parsed to the expected input of the action
+pub fn assert_input<T: Serialize>(action: &dyn Action, hcl: &str, expected: &T) {
+    assert_input_in(action, Path::new("."), hcl, expected);
+}
+
+/// Same as `assert_input` with the paths in the params relative to `cwd`
+pub fn assert_input_in<T: Serialize>(action: &dyn Action, cwd: &Path, hcl: &str, expected: &T) {
+    let input = match input_from_hcl(action, cwd, hcl) {
+        Ok(input) => input,
+        Err(e) => panic!("params should be parsed, but got error: {}", e.message),
+    };
+    let expected = bincode::serialize(expected).unwrap();
+    assert!(input == expected, "input doesn't match for params:\n{hcl}");
+}
+
+/// Assert that parsing the params fails, and returns the error message
+pub fn input_error(action: &dyn Action, hcl: &str) -> String {
+    match input_from_hcl(action, Path::new("."), hcl) {
+        Ok(_) => panic!("params should fail to parse:\n{hcl}"),
+        Err(e) => e.message,
+    }
+}
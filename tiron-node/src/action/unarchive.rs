@@ -0,0 +1,401 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    TarGz,
+    TarXz,
+    TarBz2,
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format from the extension of the archive
+    pub(super) fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_lowercase();
+        let format = if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else if path.ends_with(".tar.xz") || path.ends_with(".txz") {
+            ArchiveFormat::TarXz
+        } else if path.ends_with(".tar.bz2") || path.ends_with(".tbz2") {
+            ArchiveFormat::TarBz2
+        } else if path.ends_with(".tar") {
+            ArchiveFormat::Tar
+        } else if path.ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else {
+            return None;
+        };
+        Some(format)
+    }
+
+    /// The command that extracts the archive into the directory
+    fn command(&self, archive: &Path, dir: &Path) -> (&'static str, Vec<String>) {
+        let archive = archive.to_string_lossy().to_string();
+        let dir = dir.to_string_lossy().to_string();
+        let flags = match self {
+            ArchiveFormat::TarGz => "-xzf",
+            ArchiveFormat::TarXz => "-xJf",
+            ArchiveFormat::TarBz2 => "-xjf",
+            ArchiveFormat::Tar => "-xf",
+            ArchiveFormat::Zip => {
+                return (
+                    "unzip",
+                    vec!["-q".to_string(), archive, "-d".to_string(), dir],
+                );
+            }
+        };
+        (
+            "tar",
+            vec![flags.to_string(), archive, "-C".to_string(), dir],
+        )
+    }
+}
+
+/// Extract a tar.gz, tar.xz, tar.bz2, tar or zip archive into a directory,
+/// with `tar` or `unzip` on the remote machine
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct UnarchiveAction {
+    /// The archive to extract, which is a local path, or a path on the remote machine
+    /// if `remote_src` is true. The format is from its extension
+    src: String,
+    content: Vec<u8>,
+    /// The directory on the remote machine to extract the archive into,
+    /// which is created if it's missing
+    dest: String,
+    /// The src is already on the remote machine instead of copied from the local one
+    remote_src: bool,
+    /// A path on the remote machine that the archive creates, e.g. its binary.
+    /// If it exists, the archive isn't extracted again
+    creates: Option<String>,
+    /// Remove this many leading directories from the paths in the archive,
+    /// e.g. 1 for the top directory of a release tarball
+    strip_components: u64,
+    format: ArchiveFormat,
+}
+
+impl UnarchiveAction {
+    fn is_created(&self) -> bool {
+        self.creates
+            .as_ref()
+            .map(|creates| Path::new(creates).exists())
+            .unwrap_or(false)
+    }
+
+    /// Extract the archive into a temporary directory in dest first,
+    /// so that the leading directories can be stripped before it's moved into dest
+    fn extract(&self, id: ActionId, tx: &Sender<ActionMessage>) -> Result<()> {
+        let dest = Path::new(&self.dest);
+        std::fs::create_dir_all(dest).map_err(|e| anyhow!("can't create {}: {e}", self.dest))?;
+
+        let mut local = None;
+        let archive = if self.remote_src {
+            PathBuf::from(&self.src)
+        } else {
+            let mut temp = tempfile::NamedTempFile::new()?;
+            temp.write_all(&self.content)?;
+            temp.flush()?;
+            let path = temp.path().to_path_buf();
+            local = Some(temp);
+            path
+        };
+
+        let temp = tempfile::Builder::new()
+            .prefix(".tiron-unarchive")
+            .tempdir_in(dest)?;
+        let (program, args) = self.format.command(&archive, temp.path());
+        let status = run_command(id, tx, program, &args)?;
+        drop(local);
+        if !status.success() {
+            return Err(anyhow!("can't extract {} with {program}", self.src));
+        }
+
+        for entry in stripped(temp.path(), self.strip_components)? {
+            let Some(name) = entry.file_name() else {
+                continue;
+            };
+            merge(&entry, &dest.join(name))?;
+        }
+        Ok(())
+    }
+}
+
+/// The paths in the directory after the leading directories are stripped
+fn stripped(dir: &Path, strip: u64) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![dir.to_path_buf()];
+    for _ in 0..=strip {
+        let mut next = Vec::new();
+        for path in paths {
+            // the files above the stripped level are left out, like tar does
+            if path.is_dir() && !path.is_symlink() {
+                for entry in std::fs::read_dir(&path)? {
+                    next.push(entry?.path());
+                }
+            }
+        }
+        paths = next;
+    }
+    Ok(paths)
+}
+
+/// Move the extracted path to dest, which replaces the files that are there,
+/// and merges into the directories that are there
+fn merge(src: &Path, dest: &Path) -> Result<()> {
+    let dest_meta = std::fs::symlink_metadata(dest).ok();
+    match dest_meta {
+        Some(meta) if meta.is_dir() && src.is_dir() && !src.is_symlink() => {
+            for entry in std::fs::read_dir(src)? {
+                let entry = entry?.path();
+                if let Some(name) = entry.file_name() {
+                    merge(&entry, &dest.join(name))?;
+                }
+            }
+            return Ok(());
+        }
+        Some(meta) if meta.is_dir() => std::fs::remove_dir_all(dest)?,
+        Some(_) => std::fs::remove_file(dest)?,
+        None => {}
+    }
+    std::fs::rename(src, dest).map_err(|e| anyhow!("can't move to {}: {e}", dest.display()))
+}
+
+impl Action for UnarchiveAction {
+    fn name(&self) -> String {
+        "unarchive".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "src".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("src").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "dest".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("dest").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "remote_src".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("remote_src")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Bool],
+                },
+                ActionParamDoc {
+                    name: "creates".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("creates")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "strip_components".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("strip_components")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Number],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let (src, src_span) = params.expect_string_with_span(0);
+        let Some(format) = ArchiveFormat::from_path(src) else {
+            return Error::new("src isn't a tar.gz, tar.xz, tar.bz2, tar or zip archive")
+                .with_origin(params.origin, src_span)
+                .err();
+        };
+        let remote_src = params.bool(2).unwrap_or(false);
+        let (src, content) = if remote_src {
+            (src.to_string(), Vec::new())
+        } else {
+            let src_file = params.origin.cwd.join(src);
+            if !src_file.is_file() {
+                return Error::new("can't find src file")
+                    .with_origin(params.origin, src_span)
+                    .err();
+            }
+            let content = std::fs::read(&src_file).map_err(|e| {
+                Error::new(format!("read src file error: {e}")).with_origin(params.origin, src_span)
+            })?;
+            (src_file.to_string_lossy().to_string(), content)
+        };
+
+        let input = UnarchiveAction {
+            src,
+            content,
+            dest: params.expect_string(1).to_string(),
+            remote_src,
+            creates: params.string(3).map(|creates| creates.to_string()),
+            strip_components: params
+                .number_with_span(4)
+                .map(|(n, _)| n)
+                .unwrap_or_default(),
+            format,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: UnarchiveAction = bincode::deserialize(input)?;
+        if input.is_created() {
+            return Ok("".to_string());
+        }
+        input.extract(id, tx)?;
+        Ok(format!("extract {} to {}", input.src, input.dest))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: UnarchiveAction = bincode::deserialize(input)?;
+        if input.is_created() {
+            return Ok(None);
+        }
+        if input.remote_src && !Path::new(&input.src).exists() {
+            return Err(anyhow!("can't find src {} on the host", input.src));
+        }
+        Ok(Some(format!("extract {} to {}", input.src, input.dest)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn archive_formats() {
+        assert_eq!(
+            ArchiveFormat::from_path("node-v20.11.0-linux-x64.tar.xz"),
+            Some(ArchiveFormat::TarXz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path("release.TGZ"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path("app.zip"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_path("app.gz"), None);
+    }
+
+    #[test]
+    fn input_params() {
+        assert_input(
+            &UnarchiveAction::default(),
+            r#"
+                src = "/tmp/node.tar.xz"
+                dest = "/opt/node"
+                remote_src = true
+                creates = "/opt/node/bin/node"
+                strip_components = 1
+            "#,
+            &UnarchiveAction {
+                src: "/tmp/node.tar.xz".to_string(),
+                dest: "/opt/node".to_string(),
+                remote_src: true,
+                creates: Some("/opt/node/bin/node".to_string()),
+                strip_components: 1,
+                format: ArchiveFormat::TarXz,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            input_error(
+                &UnarchiveAction::default(),
+                r#"
+                    src = "/tmp/node.rar"
+                    dest = "/opt/node"
+                    remote_src = true
+                "#
+            ),
+            "src isn't a tar.gz, tar.xz, tar.bz2, tar or zip archive"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn execute_strips_components() {
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("app-1.0");
+        std::fs::create_dir_all(release.join("bin")).unwrap();
+        std::fs::write(release.join("bin/app"), "app").unwrap();
+        let archive = dir.path().join("app.tar.gz");
+        let status = std::process::Command::new("tar")
+            .arg("-czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(dir.path())
+            .arg("app-1.0")
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let dest = dir.path().join("opt/app");
+        std::fs::create_dir_all(dest.join("bin")).unwrap();
+        std::fs::write(dest.join("bin/app"), "old").unwrap();
+        std::fs::write(dest.join("config"), "config").unwrap();
+        let input = bincode::serialize(&UnarchiveAction {
+            src: archive.to_string_lossy().to_string(),
+            dest: dest.to_string_lossy().to_string(),
+            remote_src: true,
+            creates: Some(dest.join("bin/app-1.0").to_string_lossy().to_string()),
+            strip_components: 1,
+            format: ArchiveFormat::TarGz,
+            ..Default::default()
+        })
+        .unwrap();
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        UnarchiveAction::default()
+            .execute(ActionId::new(), &input, &tx)
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dest.join("bin/app")).unwrap(),
+            "app"
+        );
+        // the files that aren't in the archive are left alone
+        assert_eq!(
+            std::fs::read_to_string(dest.join("config")).unwrap(),
+            "config"
+        );
+        let entries: Vec<_> = std::fs::read_dir(&dest).unwrap().flatten().collect();
+        assert_eq!(entries.len(), 2);
+    }
+}
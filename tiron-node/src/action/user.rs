@@ -0,0 +1,469 @@
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionRollback},
+    error::Error,
+};
+
+use super::{
+    command::run_command, Action, ActionDoc, ActionParamBaseType, ActionParamBaseValue,
+    ActionParamDoc, ActionParamType, ActionParams,
+};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UserState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// Manage a user on Linux, with useradd, usermod and userdel
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct UserAction {
+    /// The name of the user
+    name: String,
+    /// Default to `present`<br>
+    ///
+    /// If `present`, the user is created, or changed to match the params.
+    ///
+    /// If `absent`, the user is removed, and its home directory is kept.
+    state: UserState,
+    /// The user id
+    uid: Option<u32>,
+    /// The supplementary groups of the user, which replace the groups it's in
+    groups: Option<Vec<String>>,
+    /// The login shell of the user
+    shell: Option<String>,
+    /// The home directory of the user, which is created if it's missing
+    /// and moved if the user has a different one
+    home: Option<String>,
+    /// The hashed password of the user, as it's stored in /etc/shadow,
+    /// e.g. from `openssl passwd -6`
+    password_hash: Option<String>,
+}
+
+impl Action for UserAction {
+    fn name(&self) -> String {
+        "user".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "name".to_string(),
+                    required: true,
+                    description: Self::get_field_docs("name").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "uid".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("uid").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::Number],
+                },
+                ActionParamDoc {
+                    name: "groups".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("groups")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::List(ActionParamBaseType::String)],
+                },
+                ActionParamDoc {
+                    name: "shell".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("shell")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "home".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("home").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "password_hash".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("password_hash")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let name = params.expect_string(0);
+        let state = match params.base(1).map(|state| state.expect_string()) {
+            Some("absent") => UserState::Absent,
+            _ => UserState::Present,
+        };
+        let uid = match params.number_with_span(2) {
+            Some((uid, span)) => Some(u32::try_from(uid).map_err(|_| {
+                Error::new("uid is too big for a user id").with_origin(params.origin, span)
+            })?),
+            None => None,
+        };
+        let groups = params.list(3).map(|groups| {
+            groups
+                .iter()
+                .map(|group| group.expect_string().to_string())
+                .collect()
+        });
+        let input = UserAction {
+            name: name.to_string(),
+            state,
+            uid,
+            groups,
+            shell: params.string(4).map(|shell| shell.to_string()),
+            home: params.string(5).map(|home| home.to_string()),
+            password_hash: params.string(6).map(|hash| hash.to_string()),
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: UserAction = bincode::deserialize(input)?;
+        let current = UserInfo::read(&input.name)?;
+        for (program, args) in changes(&input, current.as_ref()) {
+            let status = run_command(id, tx, program, &args)?;
+            if !status.success() {
+                return Err(anyhow!("{} failed", describe(program, &args)));
+            }
+        }
+        Ok(format!("user {}", input.name))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        let input: UserAction = bincode::deserialize(input)?;
+        let current = UserInfo::read(&input.name)?;
+        let changes = changes(&input, current.as_ref());
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            changes
+                .iter()
+                .map(|(program, args)| describe(program, args))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+
+    fn rollback(&self, input: &[u8]) -> Result<Option<ActionRollback>> {
+        let input: UserAction = bincode::deserialize(input)?;
+        let current = UserInfo::read(&input.name)?;
+        if changes(&input, current.as_ref()).is_empty() {
+            return Ok(None);
+        }
+        // the user is changed back to what it is now, but the files of a removed user
+        // other than its home directory can't be brought back
+        let rollback = match current {
+            Some(current) => UserAction {
+                name: input.name,
+                state: UserState::Present,
+                uid: Some(current.uid),
+                groups: Some(current.groups),
+                shell: Some(current.shell),
+                home: Some(current.home),
+                password_hash: current.password_hash,
+            },
+            None => UserAction {
+                name: input.name,
+                state: UserState::Absent,
+                ..Default::default()
+            },
+        };
+        Ok(Some(ActionRollback {
+            action: self.name(),
+            input: bincode::serialize(&rollback)?,
+        }))
+    }
+}
+
+/// The user as it is on the host
+#[derive(Debug, PartialEq)]
+struct UserInfo {
+    uid: u32,
+    groups: Vec<String>,
+    shell: String,
+    home: String,
+    // None if the shadow file can't be read, e.g. the node isn't run as root
+    password_hash: Option<String>,
+}
+
+impl UserInfo {
+    /// Read the user, or None if the user doesn't exist
+    fn read(name: &str) -> Result<Option<Self>> {
+        let output = Command::new("getent").args(["passwd", name]).output()?;
+        // getent exits with 2 when the user isn't found
+        if output.status.code() == Some(2) {
+            return Ok(None);
+        }
+        if !output.status.success() {
+            return Err(anyhow!("can't read user {name}"));
+        }
+        let passwd = String::from_utf8_lossy(&output.stdout);
+        // name:password:uid:gid:gecos:home:shell
+        let fields: Vec<&str> = passwd.trim_end().split(':').collect();
+        let [_, _, uid, _, _, home, shell] = fields[..] else {
+            return Err(anyhow!("can't parse the passwd entry of user {name}"));
+        };
+
+        let groups = Command::new("id").args(["-Gn", name]).output()?;
+        let primary = Command::new("id").args(["-gn", name]).output()?;
+        let primary = String::from_utf8_lossy(&primary.stdout).trim().to_string();
+        let groups = String::from_utf8_lossy(&groups.stdout)
+            .split_whitespace()
+            .filter(|group| *group != primary)
+            .map(|group| group.to_string())
+            .collect();
+
+        let password_hash = Command::new("getent")
+            .args(["shadow", name])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| {
+                let shadow = String::from_utf8_lossy(&output.stdout).to_string();
+                shadow.split(':').nth(1).map(|hash| hash.to_string())
+            });
+
+        Ok(Some(UserInfo {
+            uid: uid.parse()?,
+            groups,
+            shell: shell.to_string(),
+            home: home.to_string(),
+            password_hash,
+        }))
+    }
+}
+
+/// The commands to bring the user to the state of the input,
+/// which are empty if it's in the state already
+fn changes(input: &UserAction, current: Option<&UserInfo>) -> Vec<(&'static str, Vec<String>)> {
+    let name = input.name.clone();
+    match (input.state, current) {
+        (UserState::Absent, None) => Vec::new(),
+        (UserState::Absent, Some(_)) => vec![("userdel", vec![name])],
+        (UserState::Present, None) => {
+            let mut args = Vec::new();
+            if let Some(uid) = input.uid {
+                args.extend(["-u".to_string(), uid.to_string()]);
+            }
+            if let Some(groups) = &input.groups {
+                if !groups.is_empty() {
+                    args.extend(["-G".to_string(), groups.join(",")]);
+                }
+            }
+            if let Some(shell) = &input.shell {
+                args.extend(["-s".to_string(), shell.clone()]);
+            }
+            if let Some(home) = &input.home {
+                args.extend(["-d".to_string(), home.clone()]);
+            }
+            if let Some(hash) = &input.password_hash {
+                args.extend(["-p".to_string(), hash.clone()]);
+            }
+            args.extend(["-m".to_string(), name]);
+            vec![("useradd", args)]
+        }
+        (UserState::Present, Some(current)) => {
+            let mut args = Vec::new();
+            if let Some(uid) = input.uid.filter(|uid| *uid != current.uid) {
+                args.extend(["-u".to_string(), uid.to_string()]);
+            }
+            if let Some(groups) = &input.groups {
+                let mut wanted = groups.clone();
+                let mut current = current.groups.clone();
+                wanted.sort();
+                current.sort();
+                if wanted != current {
+                    args.extend(["-G".to_string(), groups.join(",")]);
+                }
+            }
+            if let Some(shell) = input.shell.as_ref().filter(|s| **s != current.shell) {
+                args.extend(["-s".to_string(), shell.clone()]);
+            }
+            if let Some(home) = input.home.as_ref().filter(|h| **h != current.home) {
+                args.extend(["-d".to_string(), home.clone(), "-m".to_string()]);
+            }
+            if let Some(hash) = input
+                .password_hash
+                .as_ref()
+                .filter(|h| Some(*h) != current.password_hash.as_ref())
+            {
+                args.extend(["-p".to_string(), hash.clone()]);
+            }
+            if args.is_empty() {
+                return Vec::new();
+            }
+            args.push(name);
+            vec![("usermod", args)]
+        }
+    }
+}
+
+/// The command for the output, without the password hash
+fn describe(program: &str, args: &[String]) -> String {
+    let mut hide = false;
+    let args: Vec<&str> = args
+        .iter()
+        .map(|arg| {
+            let arg = if hide {
+                "<password_hash>"
+            } else {
+                arg.as_str()
+            };
+            hide = arg == "-p";
+            arg
+        })
+        .collect();
+    format!("{program} {}", args.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    fn user() -> UserAction {
+        UserAction {
+            name: "deploy".to_string(),
+            state: UserState::Present,
+            uid: Some(1001),
+            groups: Some(vec!["docker".to_string(), "sudo".to_string()]),
+            shell: Some("/bin/bash".to_string()),
+            home: None,
+            password_hash: Some("$6$salt$hash".to_string()),
+        }
+    }
+
+    #[test]
+    fn input_params() {
+        assert_input(
+            &UserAction::default(),
+            r#"
+                name = "deploy"
+                uid = 1001
+                groups = ["docker", "sudo"]
+                shell = "/bin/bash"
+                password_hash = "$6$salt$hash"
+            "#,
+            &user(),
+        );
+        assert_eq!(
+            input_error(
+                &UserAction::default(),
+                r#"
+                    name = "deploy"
+                    uid = "1001"
+                "#
+            ),
+            "uid type should be Number"
+        );
+        assert_eq!(
+            input_error(
+                &UserAction::default(),
+                r#"
+                    name = "deploy"
+                    uid = 5000000000
+                "#
+            ),
+            "uid is too big for a user id"
+        );
+    }
+
+    #[test]
+    fn changes_to_user() {
+        let input = user();
+        assert_eq!(
+            changes(&input, None),
+            vec![(
+                "useradd",
+                [
+                    "-u",
+                    "1001",
+                    "-G",
+                    "docker,sudo",
+                    "-s",
+                    "/bin/bash",
+                    "-p",
+                    "$6$salt$hash",
+                    "-m",
+                    "deploy"
+                ]
+                .map(String::from)
+                .to_vec()
+            )]
+        );
+
+        let mut current = UserInfo {
+            uid: 1001,
+            groups: vec!["sudo".to_string(), "docker".to_string()],
+            shell: "/bin/sh".to_string(),
+            home: "/home/deploy".to_string(),
+            password_hash: Some("$6$salt$hash".to_string()),
+        };
+        assert_eq!(
+            changes(&input, Some(&current)),
+            vec![(
+                "usermod",
+                ["-s", "/bin/bash", "deploy"].map(String::from).to_vec()
+            )]
+        );
+        current.shell = "/bin/bash".to_string();
+        assert!(changes(&input, Some(&current)).is_empty());
+
+        let absent = UserAction {
+            name: "deploy".to_string(),
+            state: UserState::Absent,
+            ..Default::default()
+        };
+        assert_eq!(
+            changes(&absent, Some(&current)),
+            vec![("userdel", vec!["deploy".to_string()])]
+        );
+        assert!(changes(&absent, None).is_empty());
+    }
+
+    #[test]
+    fn describe_hides_password() {
+        assert_eq!(
+            describe(
+                "usermod",
+                &["-p", "$6$salt$hash", "deploy"].map(String::from)
+            ),
+            "usermod -p <password_hash> deploy"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use documented::{Documented, DocumentedFields};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionOutputLevel},
+    error::Error,
+};
+
+use super::{
+    Action, ActionDoc, ActionParamBaseValue, ActionParamDoc, ActionParamType, ActionParams,
+};
+
+const DEFAULT_TIMEOUT: u64 = 300;
+const DEFAULT_INTERVAL: u64 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum WaitState {
+    Present,
+    Absent,
+}
+
+/// Wait for a TCP port to be open, a file to exist, or a regex to match in a file,
+/// or for them to go away, e.g. for a service that takes a while to start
+/// before the actions that use it
+#[derive(Default, Clone, Serialize, Deserialize, Documented, DocumentedFields)]
+pub struct WaitForAction {
+    /// The TCP port to wait for. Either port or path is required
+    port: Option<u16>,
+    /// The host of the port. Default to `127.0.0.1`
+    host: Option<String>,
+    /// The path of the file to wait for
+    path: Option<String>,
+    /// The regex to wait for in the content of the file at path,
+    /// e.g. `listening on port \d+`
+    regex: Option<String>,
+    /// `present` waits for the port to be open, or the file to exist and match the regex,
+    /// and `absent` waits for them to go away. Default to `present`
+    state: Option<WaitState>,
+    /// How long to wait in seconds before it fails. Default to 300
+    timeout: Option<u64>,
+    /// How often to check in seconds. Default to 1
+    interval: Option<u64>,
+}
+
+impl WaitForAction {
+    fn state(&self) -> WaitState {
+        self.state.unwrap_or(WaitState::Present)
+    }
+
+    fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or("127.0.0.1")
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.unwrap_or(DEFAULT_INTERVAL))
+    }
+
+    /// What it waits for, e.g. `port 8080 on 127.0.0.1 to be open`
+    fn description(&self) -> String {
+        let present = self.state() == WaitState::Present;
+        match (&self.port, &self.path, &self.regex) {
+            (Some(port), _, _) => format!(
+                "port {port} on {} to be {}",
+                self.host(),
+                if present { "open" } else { "closed" }
+            ),
+            (None, Some(path), Some(regex)) => format!(
+                "{regex} to {} in {path}",
+                if present { "appear" } else { "disappear" }
+            ),
+            (None, Some(path), None) => {
+                format!("{path} to {}", if present { "exist" } else { "be removed" })
+            }
+            (None, None, _) => String::new(),
+        }
+    }
+
+    /// Whether the port is open, or the file exists and matches the regex
+    fn is_present(&self, regex: Option<&Regex>) -> bool {
+        if let Some(port) = self.port {
+            let Ok(addrs) = (self.host(), port).to_socket_addrs() else {
+                return false;
+            };
+            let timeout = self.interval().max(Duration::from_secs(1));
+            return addrs
+                .into_iter()
+                .any(|addr| TcpStream::connect_timeout(&addr, timeout).is_ok());
+        }
+        let Some(path) = &self.path else {
+            return false;
+        };
+        match regex {
+            Some(regex) => std::fs::read_to_string(path)
+                .map(|content| regex.is_match(&content))
+                .unwrap_or(false),
+            None => Path::new(path).exists(),
+        }
+    }
+}
+
+impl Action for WaitForAction {
+    fn name(&self) -> String {
+        "wait_for".to_string()
+    }
+
+    fn doc(&self) -> ActionDoc {
+        ActionDoc {
+            description: Self::DOCS.to_string(),
+            params: vec![
+                ActionParamDoc {
+                    name: "port".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("port").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::Number],
+                },
+                ActionParamDoc {
+                    name: "host".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("host").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "path".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("path").unwrap_or_default().to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "regex".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("regex")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::String],
+                },
+                ActionParamDoc {
+                    name: "state".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("state")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Enum(vec![
+                        ActionParamBaseValue::String("present".to_string()),
+                        ActionParamBaseValue::String("absent".to_string()),
+                    ])],
+                },
+                ActionParamDoc {
+                    name: "timeout".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("timeout")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Number],
+                },
+                ActionParamDoc {
+                    name: "interval".to_string(),
+                    required: false,
+                    description: Self::get_field_docs("interval")
+                        .unwrap_or_default()
+                        .to_string(),
+                    type_: vec![ActionParamType::Number],
+                },
+            ],
+        }
+    }
+
+    fn input(&self, params: ActionParams) -> Result<Vec<u8>, Error> {
+        let port = match params.number_with_span(0) {
+            Some((port, span)) => match u16::try_from(port) {
+                Ok(port) if port > 0 => Some(port),
+                _ => {
+                    return Error::new("port should be between 1 and 65535")
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+            },
+            None => None,
+        };
+        let host = params.string(1).map(|s| s.to_string());
+        let path = params.string(2).map(|s| s.to_string());
+        match (&port, &path) {
+            (None, None) => {
+                return Error::new("wait_for needs port or path in params")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            (Some(_), Some(_)) => {
+                return Error::new("wait_for can wait for either port or path, but not both")
+                    .with_origin(params.origin, &params.span)
+                    .err();
+            }
+            _ => {}
+        }
+        if host.is_some() && port.is_none() {
+            return Error::new("host is only for port")
+                .with_origin(params.origin, &params.span)
+                .err();
+        }
+
+        let regex = match params.values[3].as_ref() {
+            Some(regex) => {
+                let (regex, span) = regex.expect_string_with_span();
+                if path.is_none() {
+                    return Error::new("regex is only for path")
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+                if let Err(e) = Regex::new(regex) {
+                    return Error::new(format!("regex isn't valid: {e}"))
+                        .with_origin(params.origin, span)
+                        .err();
+                }
+                Some(regex.to_string())
+            }
+            None => None,
+        };
+
+        let state = params.base(4).map(|state| match state.expect_string() {
+            "present" => WaitState::Present,
+            "absent" => WaitState::Absent,
+            _ => unreachable!(),
+        });
+        let timeout = params.number_with_span(5).map(|(timeout, _)| timeout);
+        let interval = match params.number_with_span(6) {
+            Some((0, span)) => {
+                return Error::new("interval should be more than 0")
+                    .with_origin(params.origin, span)
+                    .err();
+            }
+            Some((interval, _)) => Some(interval),
+            None => None,
+        };
+
+        let input = WaitForAction {
+            port,
+            host,
+            path,
+            regex,
+            state,
+            timeout,
+            interval,
+        };
+        let input = bincode::serialize(&input).map_err(|e| {
+            Error::new(format!("serialize action input error: {e}"))
+                .with_origin(params.origin, &params.span)
+        })?;
+        Ok(input)
+    }
+
+    fn execute(&self, id: ActionId, input: &[u8], tx: &Sender<ActionMessage>) -> Result<String> {
+        let input: WaitForAction = bincode::deserialize(input)?;
+        let regex = input
+            .regex
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| anyhow!("regex isn't valid: {e}"))?;
+        let timeout = Duration::from_secs(input.timeout.unwrap_or(DEFAULT_TIMEOUT));
+        let present = input.state() == WaitState::Present;
+        let start = Instant::now();
+        let mut waiting = false;
+        while input.is_present(regex.as_ref()) != present {
+            if start.elapsed() >= timeout {
+                return Err(anyhow!(
+                    "timed out after {}s waiting for {}",
+                    timeout.as_secs(),
+                    input.description()
+                ));
+            }
+            if !waiting {
+                waiting = true;
+                let _ = tx.send(ActionMessage::ActionOutputLine {
+                    id,
+                    content: format!("waiting for {}", input.description()),
+                    level: ActionOutputLevel::Info,
+                });
+            }
+            std::thread::sleep(input.interval());
+        }
+        Ok(format!(
+            "{} after {}s",
+            input.description(),
+            start.elapsed().as_secs()
+        ))
+    }
+
+    fn dry_run(
+        &self,
+        _id: ActionId,
+        _input: &[u8],
+        _tx: &Sender<ActionMessage>,
+    ) -> Result<Option<String>> {
+        // waiting doesn't change anything on the host
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::testing::{assert_input, input_error};
+
+    #[test]
+    fn input_wait_for() {
+        assert_input(
+            &WaitForAction::default(),
+            r#"
+                path  = "/var/log/app.log"
+                regex = "listening on port \\d+"
+                timeout = 60
+            "#,
+            &WaitForAction {
+                port: None,
+                host: None,
+                path: Some("/var/log/app.log".to_string()),
+                regex: Some(r"listening on port \d+".to_string()),
+                state: None,
+                timeout: Some(60),
+                interval: None,
+            },
+        );
+        assert_eq!(
+            input_error(&WaitForAction::default(), "port = 70000"),
+            "port should be between 1 and 65535"
+        );
+        assert_eq!(
+            input_error(
+                &WaitForAction::default(),
+                r#"
+                    port = 8080
+                    path = "/tmp/ready"
+                "#
+            ),
+            "wait_for can wait for either port or path, but not both"
+        );
+        assert_eq!(
+            input_error(
+                &WaitForAction::default(),
+                r#"
+                    port  = 8080
+                    regex = "up"
+                "#
+            ),
+            "regex is only for path"
+        );
+    }
+
+    #[test]
+    fn wait_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ready");
+        let action = WaitForAction {
+            path: Some(path.to_string_lossy().to_string()),
+            regex: Some("^ready$".to_string()),
+            ..Default::default()
+        };
+        let regex = Regex::new("^ready$").unwrap();
+        assert!(!action.is_present(Some(&regex)));
+        std::fs::write(&path, "ready").unwrap();
+        assert!(action.is_present(Some(&regex)));
+        assert!(action.is_present(None));
+        assert_eq!(
+            action.description(),
+            format!("^ready$ to appear in {}", path.to_string_lossy())
+        );
+    }
+}
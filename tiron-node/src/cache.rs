@@ -0,0 +1,96 @@
+use std::{
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use tiron_common::crypto::{hex, sha256};
+
+/// The data directory of tiron on the host, which has the node binary
+pub(crate) fn data_dir() -> PathBuf {
+    let home = std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    match std::env::consts::OS {
+        "macos" => home.join("Library/Application Support/dev.tiron.tiron"),
+        _ => std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".local/share"))
+            .join("tiron"),
+    }
+}
+
+/// The cache of the artifacts on the host, e.g. the content of the copied files,
+/// which are kept by their SHA-256 so that they don't have to be sent again.
+/// It's next to the node binary in the data directory of tiron.
+pub fn cache_dir() -> PathBuf {
+    data_dir().join("cache")
+}
+
+pub fn contains(hash: &[u8; 32]) -> bool {
+    cache_dir().join(hex(hash)).is_file()
+}
+
+/// The cached content with the hash, which is checked against the hash
+/// in case the file was changed
+pub fn get(hash: &[u8; 32]) -> Option<Vec<u8>> {
+    let content = std::fs::read(cache_dir().join(hex(hash))).ok()?;
+    (sha256(&content) == *hash).then_some(content)
+}
+
+/// Put the content in the cache, which is written to a temp file first so that
+/// a partly written file is never found by its hash
+pub fn put(content: &[u8]) -> Result<[u8; 32]> {
+    let hash = sha256(content);
+    let dir = cache_dir();
+    let path = dir.join(hex(&hash));
+    if path.is_file() {
+        return Ok(hash);
+    }
+    std::fs::create_dir_all(&dir)?;
+    let mut temp = tempfile::NamedTempFile::new_in(&dir)?;
+    temp.write_all(content)?;
+    temp.persist(&path)?;
+    Ok(hash)
+}
+
+/// The hashes and the sizes of the cached artifacts
+pub fn entries() -> Result<Vec<(String, u64)>> {
+    let dir = match std::fs::read_dir(cache_dir()) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = Vec::new();
+    for entry in dir {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() {
+            entries.push((entry.file_name().to_string_lossy().to_string(), meta.len()));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Print the cached artifacts, or remove them all if `clear`
+pub fn cache_cmd(clear: bool) -> Result<()> {
+    let entries = entries()?;
+    let total: u64 = entries.iter().map(|(_, size)| size).sum();
+    if clear {
+        for (hash, _) in &entries {
+            std::fs::remove_file(cache_dir().join(hash))?;
+        }
+        println!("removed {} artifacts, {total} bytes", entries.len());
+        return Ok(());
+    }
+    for (hash, size) in &entries {
+        println!("{hash} {size}");
+    }
+    println!(
+        "{} artifacts, {total} bytes in {}",
+        entries.len(),
+        cache_dir().display()
+    );
+    Ok(())
+}
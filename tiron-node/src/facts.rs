@@ -0,0 +1,103 @@
+use std::{collections::BTreeMap, net::UdpSocket, path::Path, process::Command};
+
+use os_info::Type;
+use tiron_common::node::Facts;
+
+/// The directory of the local facts, which are JSON objects in `<name>.json`
+pub const LOCAL_FACTS_DIR: &str = "/etc/tiron/facts.d";
+
+/// Gather the facts about the host the node runs on
+pub fn gather() -> Facts {
+    let info = os_info::get();
+    Facts {
+        os: std::env::consts::OS.to_string(),
+        os_family: os_family(info.os_type()).to_string(),
+        distribution: info.os_type().to_string().to_lowercase().replace(' ', "_"),
+        distribution_version: info.version().to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        hostname: uname("-n"),
+        kernel: uname("-r"),
+        cpus: std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+        ip: ip(),
+        local: local(Path::new(LOCAL_FACTS_DIR)),
+    }
+}
+
+fn os_family(os_type: Type) -> &'static str {
+    match os_type {
+        Type::Debian | Type::Ubuntu | Type::Mint | Type::Pop => "debian",
+        Type::Fedora | Type::Redhat | Type::RedHatEnterprise | Type::CentOS | Type::OracleLinux => {
+            "rhel"
+        }
+        Type::Arch | Type::Manjaro => "arch",
+        Type::openSUSE | Type::SUSE => "suse",
+        Type::Alpine => "alpine",
+        Type::Macos => "darwin",
+        Type::Windows => "windows",
+        _ => "unknown",
+    }
+}
+
+fn uname(arg: &str) -> String {
+    Command::new("uname")
+        .arg(arg)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The JSON objects in the directory by their file names.
+/// The files that aren't JSON objects are left out
+fn local(dir: &Path) -> BTreeMap<String, String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let name = path.file_stem()?.to_str()?.to_string();
+            let content = std::fs::read_to_string(&path).ok()?;
+            let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+            value
+                .is_object()
+                .then(|| (name, serde_json::to_string(&value).unwrap_or_default()))
+        })
+        .collect()
+}
+
+fn ip() -> String {
+    // connecting a udp socket doesn't send anything,
+    // but it picks the local address of the route to the destination
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("8.8.8.8:80")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_facts() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.json"), r#"{ "schema": "v42" }"#).unwrap();
+        std::fs::write(dir.path().join("broken.json"), "{").unwrap();
+        std::fs::write(dir.path().join("list.json"), "[1, 2]").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "{}").unwrap();
+        let local = local(dir.path());
+        assert_eq!(local.len(), 1);
+        assert_eq!(local["app"], r#"{"schema":"v42"}"#);
+        assert!(super::local(&dir.path().join("missing")).is_empty());
+    }
+}
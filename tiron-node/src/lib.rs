@@ -1,3 +1,7 @@
 pub mod action;
+pub mod cache;
+mod facts;
+pub mod lock;
+pub mod managed;
 pub mod node;
 pub mod stdio;
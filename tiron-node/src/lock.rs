@@ -0,0 +1,206 @@
+use std::{
+    fmt::Display,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+use tiron_common::node::LockHolder;
+use uuid::Uuid;
+
+/// The lock file on the host, which is removed when the node shuts down
+pub fn host_lock_path() -> PathBuf {
+    std::env::temp_dir().join("tiron.lock")
+}
+
+pub enum LockError {
+    // the lock is held by someone else
+    Held(LockHolder),
+    Io(std::io::Error),
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Held(holder) => write!(f, "{holder}"),
+            LockError::Io(e) => write!(f, "can't lock: {e}"),
+        }
+    }
+}
+
+/// What's in the lock file: the holder, the process that took the lock,
+/// and the nonce that tells it apart from the lock that took it over
+#[derive(Deserialize, Serialize)]
+struct LockContent {
+    #[serde(flatten)]
+    holder: LockHolder,
+    // the lock files from before the pid was written don't have it
+    pid: Option<u32>,
+    nonce: Option<Uuid>,
+}
+
+impl LockContent {
+    fn read(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    /// The process that took the lock is gone without removing it, e.g. it was killed
+    fn is_stale(&self) -> bool {
+        self.pid.is_some_and(|pid| !process_alive(pid))
+    }
+}
+
+/// A lock file with the holder of the lock in it, which is removed when it's dropped
+pub struct LockFile {
+    path: PathBuf,
+    nonce: Uuid,
+}
+
+impl LockFile {
+    /// Create the lock file, which fails if someone else holds the lock,
+    /// unless `force` is set to take over the lock. A stale lock is taken over
+    /// without `force`, as nobody holds it anymore
+    pub fn acquire(path: &Path, holder: &LockHolder, force: bool) -> Result<Self, LockError> {
+        let nonce = Uuid::new_v4();
+        let content = LockContent {
+            holder: holder.clone(),
+            pid: Some(std::process::id()),
+            nonce: Some(nonce),
+        };
+        let content = serde_json::to_vec(&content).map_err(|e| LockError::Io(e.into()))?;
+        let file = OpenOptions::new().write(true).create_new(true).open(path);
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let current = LockContent::read(path);
+                if !force && !current.as_ref().is_some_and(|c| c.is_stale()) {
+                    // a lock file that can't be read is from someone else all the same
+                    let current = current.map(|c| c.holder).unwrap_or_else(|| LockHolder {
+                        user: "unknown".to_string(),
+                        since: "unknown".to_string(),
+                    });
+                    return Err(LockError::Held(current));
+                }
+                OpenOptions::new()
+                    .write(true)
+                    .truncate(true)
+                    .open(path)
+                    .map_err(LockError::Io)?
+            }
+            Err(e) => return Err(LockError::Io(e)),
+        };
+        file.write_all(&content).map_err(LockError::Io)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            nonce,
+        })
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // the lock was taken over with force, so it's not ours to remove anymore
+        let ours = LockContent::read(&self.path).is_some_and(|c| c.nonce == Some(self.nonce));
+        if ours {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Whether the process is still running. It's assumed to be when it can't be told
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    if Path::new("/proc/self").exists() {
+        return Path::new(&format!("/proc/{pid}")).exists();
+    }
+    match Command::new("kill").args(["-0", &pid.to_string()]).output() {
+        // the process of another user can't be signaled, but it's running
+        Ok(output) => {
+            output.status.success()
+                || String::from_utf8_lossy(&output.stderr).contains("not permitted")
+        }
+        Err(_) => true,
+    }
+}
+
+/// Whether the process is still running. It's assumed to be when it can't be told
+#[cfg(not(unix))]
+fn process_alive(pid: u32) -> bool {
+    match Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .output()
+    {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn holder(user: &str) -> LockHolder {
+        LockHolder {
+            user: user.to_string(),
+            since: "2024-05-01 08:30 UTC".to_string(),
+        }
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiron.lock");
+
+        let lock = LockFile::acquire(&path, &holder("alice"), false)
+            .ok()
+            .unwrap();
+        match LockFile::acquire(&path, &holder("bob"), false) {
+            Err(LockError::Held(current)) => assert_eq!(current, holder("alice")),
+            _ => panic!("the lock should be held by alice"),
+        }
+
+        drop(lock);
+        assert!(!path.exists());
+        assert!(LockFile::acquire(&path, &holder("bob"), false).is_ok());
+    }
+
+    #[test]
+    fn force_takes_over_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiron.lock");
+
+        let lock = LockFile::acquire(&path, &holder("alice"), false)
+            .ok()
+            .unwrap();
+        let _forced = LockFile::acquire(&path, &holder("bob"), true).ok().unwrap();
+        let current: LockHolder = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(current, holder("bob"));
+
+        // alice's lock doesn't remove bob's when it's dropped
+        drop(lock);
+        assert!(path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_lock_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tiron.lock");
+
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        let content = LockContent {
+            holder: holder("alice"),
+            pid: Some(child.id()),
+            nonce: Some(Uuid::new_v4()),
+        };
+        std::fs::write(&path, serde_json::to_vec(&content).unwrap()).unwrap();
+        assert!(LockFile::acquire(&path, &holder("bob"), false).is_ok());
+
+        // the lock files without a pid can't be told to be stale
+        std::fs::write(&path, serde_json::to_vec(&holder("alice")).unwrap()).unwrap();
+        assert!(LockFile::acquire(&path, &holder("bob"), false).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    collections::BTreeSet,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::cache::data_dir;
+
+/// The manifest of the files that tiron created on the host, e.g. by `copy` and `template`,
+/// which are the only files that `purge_unmanaged` of `file` removes
+fn manifest_path() -> PathBuf {
+    data_dir().join("managed.json")
+}
+
+pub fn load() -> Result<BTreeSet<PathBuf>> {
+    match std::fs::read(manifest_path()) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save(files: &BTreeSet<PathBuf>) -> Result<()> {
+    let dir = data_dir();
+    std::fs::create_dir_all(&dir)?;
+    let mut temp = tempfile::NamedTempFile::new_in(&dir)?;
+    temp.write_all(&serde_json::to_vec_pretty(files)?)?;
+    temp.persist(manifest_path())?;
+    Ok(())
+}
+
+/// The path from the working directory of the node, which is how the files
+/// are kept in the manifest
+pub fn absolute(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Add the file that tiron created to the manifest
+pub fn record(path: &str) -> Result<()> {
+    let mut files = load()?;
+    if files.insert(absolute(path)) {
+        save(&files)?;
+    }
+    Ok(())
+}
+
+/// The managed files in the directory that aren't kept, i.e. the runbook
+/// doesn't produce them anymore
+pub fn unmanaged(files: &BTreeSet<PathBuf>, dir: &Path, keep: &[PathBuf]) -> Vec<PathBuf> {
+    files
+        .iter()
+        .filter(|file| file.starts_with(dir) && !keep.contains(file))
+        .cloned()
+        .collect()
+}
+
+/// Remove the files from the host and from the manifest
+pub fn purge(files: &[PathBuf]) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let mut managed = load()?;
+    for file in files {
+        match std::fs::remove_file(file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow!("can't remove {}: {e}", file.display())),
+        }
+        managed.remove(file);
+    }
+    save(&managed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unmanaged_in_dir() {
+        let files: BTreeSet<PathBuf> = [
+            "/etc/nginx/conf.d/app.conf",
+            "/etc/nginx/conf.d/old.conf",
+            "/etc/nginx/conf.d/sites/old.conf",
+            "/etc/nginx/conf.d.bak/old.conf",
+            "/etc/motd",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(
+            unmanaged(
+                &files,
+                Path::new("/etc/nginx/conf.d"),
+                &[PathBuf::from("/etc/nginx/conf.d/app.conf")]
+            ),
+            vec![
+                PathBuf::from("/etc/nginx/conf.d/old.conf"),
+                PathBuf::from("/etc/nginx/conf.d/sites/old.conf"),
+            ]
+        );
+    }
+}
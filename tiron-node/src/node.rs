@@ -1,87 +1,527 @@
 use std::{
-    collections::HashMap,
+    backtrace::Backtrace,
+    collections::{HashMap, VecDeque},
     io::{stdin, stdout, BufReader},
+    sync::Mutex,
 };
 
 use anyhow::{anyhow, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crossbeam_channel::{Receiver, Sender};
 use tiron_common::{
-    action::{ActionData, ActionMessage, ActionOutputLevel},
+    action::{
+        ActionData, ActionId, ActionMessage, ActionOutputLevel, ActionRollback, ActionScope,
+        BlockSection, DebugChoice, Rollback, MESSAGE_BUFFER,
+    },
+    delta::Signatures,
     node::NodeMessage,
 };
 
 use crate::{
-    action::{data::all_actions, Action},
-    stdio::stdio_transport,
+    action::{data::all_actions, resource_state, Action},
+    cache, facts,
+    lock::{host_lock_path, LockFile},
+    stdio::{stdio_transport, write_msg},
 };
 
+/// The action that's running, which is reported if the node panics
+static CURRENT_ACTION: Mutex<Option<ActionId>> = Mutex::new(None);
+
+/// The lock of the host, which is released when the node shuts down,
+/// and by the panic hook, which exits without dropping anything
+static HOST_LOCK: Mutex<Option<LockFile>> = Mutex::new(None);
+
 #[derive(Parser)]
 #[clap(name = "tiron-node")]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
-pub struct Cli {}
+pub struct Cli {
+    #[command(subcommand)]
+    cmd: Option<NodeCmd>,
+}
+
+#[derive(Subcommand)]
+enum NodeCmd {
+    /// Show the artifacts in the cache of the node
+    Cache {
+        /// Remove all the artifacts from the cache
+        #[arg(long)]
+        clear: bool,
+    },
+}
 
 pub fn start() -> Result<()> {
-    let _ = Cli::parse();
-    let (writer_tx, writer_rx) = crossbeam_channel::unbounded::<ActionMessage>();
+    let cli = Cli::parse();
+    if let Some(NodeCmd::Cache { clear }) = cli.cmd {
+        return cache::cache_cmd(clear);
+    }
+    install_panic_hook();
+    // the actions wait when the output can't be written as fast as they make it
+    let (writer_tx, writer_rx) = crossbeam_channel::bounded::<ActionMessage>(MESSAGE_BUFFER);
     let (reader_tx, reader_rx) = crossbeam_channel::unbounded::<NodeMessage>();
     stdio_transport(stdout(), writer_rx, BufReader::new(stdin()), reader_tx);
-    mainloop(reader_rx, writer_tx)?;
-    Ok(())
+    let result = mainloop(reader_rx, writer_tx);
+    // the mainloop can stop with an error before the shutdown released the lock
+    release_host_lock();
+    result
+}
+
+/// Send the panic to the controller, and exit the node after releasing the lock
+/// of the host, because it can't tell the state of the actions after any of
+/// its threads panicked
+fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let message = match info.location() {
+            Some(location) => format!("{message} at {location}"),
+            None => message,
+        };
+        let id = CURRENT_ACTION.lock().ok().and_then(|id| *id);
+        let msg = ActionMessage::NodePanicked {
+            id,
+            message,
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+        // the message is written in one go with stdout locked,
+        // so it can't be mixed up with the messages from the writer thread
+        let _ = write_msg(&mut stdout().lock(), msg);
+        release_host_lock();
+        std::process::exit(101);
+    }));
 }
 
 pub fn mainloop(rx: Receiver<NodeMessage>, tx: Sender<ActionMessage>) -> Result<()> {
     let all_actions = all_actions();
-    let mut had_error = false;
-    while let Ok(msg) = rx.recv() {
-        if had_error {
-            continue;
-        }
+    tx.send(ActionMessage::NodeFacts {
+        facts: facts::gather(),
+    })?;
+    let mut blocks = Blocks::default();
+    // the rollbacks of the actions that succeeded, in the order they were executed
+    let mut rollbacks: Vec<(ActionId, ActionRollback)> = Vec::new();
+    // the messages that came while waiting for the debug choice of an action
+    let mut queued = VecDeque::new();
+    while let Some(msg) = queued.pop_front().or_else(|| rx.recv().ok()) {
         match msg {
-            NodeMessage::Action(action) => match node_run_action(&all_actions, &action, &tx) {
-                Ok(result) => {
+            NodeMessage::Action(mut action) => {
+                if !blocks.enter(&action.scope) {
+                    tx.send(ActionMessage::ActionSkipped { id: action.id })?;
+                    continue;
+                }
+                if action.unchanged {
+                    // it's as if it succeeded again, which it did last time
+                    blocks.succeed();
                     tx.send(ActionMessage::ActionOutputLine {
                         id: action.id,
-                        content: format!("successfully {result}"),
-                        level: ActionOutputLevel::Success,
-                    })?;
-                    tx.send(ActionMessage::ActionResult {
-                        id: action.id,
-                        success: true,
+                        content: "unchanged since it last succeeded".to_string(),
+                        level: ActionOutputLevel::Info,
                     })?;
+                    tx.send(ActionMessage::ActionSkipped { id: action.id })?;
+                    continue;
                 }
-                Err(e) => {
+                set_current_action(Some(action.id));
+                let mut rollback = if action.dry_run {
+                    None
+                } else {
+                    node_action_rollback(&all_actions, &action, &tx)
+                };
+                let mut result = node_run_action(&all_actions, &action, &tx);
+                // with the debug strategy, the failed action waits to be retried,
+                // skipped or failed
+                while let Err(e) = &result {
+                    if !action.debug || action.dry_run {
+                        break;
+                    }
                     tx.send(ActionMessage::ActionOutputLine {
                         id: action.id,
                         content: format!("error: {e:#}"),
                         level: ActionOutputLevel::Error,
                     })?;
-                    had_error = true;
-                    tx.send(ActionMessage::ActionResult {
-                        id: action.id,
-                        success: false,
-                    })?;
-                    tx.send(ActionMessage::NodeShutdown { success: false })?;
+                    tx.send(ActionMessage::ActionDebug { id: action.id })?;
+                    match debug_choice(&rx, &mut queued) {
+                        Some(DebugChoice::Retry) => {}
+                        Some(DebugChoice::RetryWith(input)) => {
+                            action.input = input;
+                            rollback = node_action_rollback(&all_actions, &action, &tx);
+                        }
+                        Some(DebugChoice::Skip) => {
+                            result =
+                                Ok(("skipped the failure".to_string(), ActionOutputLevel::Warn));
+                            break;
+                        }
+                        Some(DebugChoice::Fail) | None => {
+                            // the error is already reported
+                            result = Err(anyhow!("failed in the debugger"));
+                            break;
+                        }
+                    }
+                    result = node_run_action(&all_actions, &action, &tx);
                 }
-            },
+                match result {
+                    Ok((content, level)) => {
+                        blocks.succeed();
+                        if let Some(rollback) = rollback {
+                            rollbacks.push((action.id, rollback));
+                        }
+                        tx.send(ActionMessage::ActionOutputLine {
+                            id: action.id,
+                            content,
+                            level,
+                        })?;
+                        tx.send(ActionMessage::ActionResult {
+                            id: action.id,
+                            success: true,
+                        })?;
+                    }
+                    Err(e) => {
+                        blocks.fail();
+                        tx.send(ActionMessage::ActionOutputLine {
+                            id: action.id,
+                            content: format!("error: {e:#}"),
+                            level: ActionOutputLevel::Error,
+                        })?;
+                        tx.send(ActionMessage::ActionResult {
+                            id: action.id,
+                            success: false,
+                        })?;
+                    }
+                }
+                set_current_action(None);
+            }
+            NodeMessage::Lock { holder, force } => {
+                let error = match LockFile::acquire(&host_lock_path(), &holder, force) {
+                    Ok(file) => {
+                        if let Ok(mut lock) = HOST_LOCK.lock() {
+                            *lock = Some(file);
+                        }
+                        None
+                    }
+                    Err(e) => Some(e.to_string()),
+                };
+                tx.send(ActionMessage::NodeLocked { error })?;
+            }
+            NodeMessage::Signatures { files } => {
+                let signatures = files
+                    .iter()
+                    .map(|(path, block_size)| {
+                        let data = std::fs::read(path).ok()?;
+                        Some(Signatures::new(&data, *block_size as usize))
+                    })
+                    .collect();
+                tx.send(ActionMessage::NodeSignatures { signatures })?;
+            }
+            NodeMessage::Cached { hashes } => {
+                let cached = hashes.iter().map(cache::contains).collect();
+                tx.send(ActionMessage::NodeCached { cached })?;
+            }
+            NodeMessage::Fingerprint { resources } => {
+                let states = resources.iter().map(resource_state).collect();
+                tx.send(ActionMessage::NodeFingerprint { states })?;
+            }
+            // there's no failed action waiting for it
+            NodeMessage::Debug(_) => {}
             NodeMessage::Shutdown => {
-                tx.send(ActionMessage::NodeShutdown { success: true })?;
+                let success = blocks.finish();
+                if !success {
+                    while let Some((id, rollback)) = rollbacks.pop() {
+                        set_current_action(Some(id));
+                        node_run_rollback(&all_actions, id, &rollback, &tx)?;
+                    }
+                    set_current_action(None);
+                }
+                release_host_lock();
+                tx.send(ActionMessage::NodeShutdown { success })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Wait for what to do with the failed action, and keep the other messages for later
+fn debug_choice(
+    rx: &Receiver<NodeMessage>,
+    queued: &mut VecDeque<NodeMessage>,
+) -> Option<DebugChoice> {
+    loop {
+        match rx.recv().ok()? {
+            NodeMessage::Debug(choice) => return Some(choice),
+            msg => queued.push_back(msg),
+        }
+    }
+}
+
+fn release_host_lock() {
+    // it's not waited for, in case the thread that panicked was holding it
+    if let Ok(mut lock) = HOST_LOCK.try_lock() {
+        drop(lock.take());
+    }
+}
+
+fn set_current_action(id: Option<ActionId>) {
+    if let Ok(mut current) = CURRENT_ACTION.lock() {
+        *current = id;
+    }
+}
+
+/// Work out how to undo the action before it's executed
+fn node_action_rollback(
+    all_actions: &HashMap<String, Box<dyn Action>>,
+    data: &ActionData,
+    tx: &Sender<ActionMessage>,
+) -> Option<ActionRollback> {
+    match data.rollback.as_ref()? {
+        Rollback::Action(rollback) => Some(rollback.clone()),
+        Rollback::Auto => {
+            let action = all_actions.get(&data.action)?;
+            match action.rollback(&data.input) {
+                Ok(rollback) => rollback,
+                Err(e) => {
+                    let _ = tx.send(ActionMessage::ActionOutputLine {
+                        id: data.id,
+                        content: format!("can't roll back: {e:#}"),
+                        level: ActionOutputLevel::Warn,
+                    });
+                    None
+                }
             }
         }
     }
+}
+
+/// Run the rollback of the action, with the output reported on the action
+fn node_run_rollback(
+    all_actions: &HashMap<String, Box<dyn Action>>,
+    id: ActionId,
+    rollback: &ActionRollback,
+    tx: &Sender<ActionMessage>,
+) -> Result<()> {
+    let result = all_actions
+        .get(&rollback.action)
+        .ok_or_else(|| anyhow!("can't find action name {}", rollback.action))
+        .and_then(|action| action.execute(id, &rollback.input, tx));
+    let (content, level) = match result {
+        Ok(_) => ("rolled back".to_string(), ActionOutputLevel::Warn),
+        Err(e) => (format!("rollback error: {e:#}"), ActionOutputLevel::Error),
+    };
+    tx.send(ActionMessage::ActionOutputLine { id, content, level })?;
     Ok(())
 }
 
+/// The state of the blocks the actions are in, which decides whether an action runs
+/// with the `rescue` and `always` sections of the blocks
+#[derive(Default)]
+struct Blocks {
+    // the blocks the current action is in, from the outermost
+    stack: Vec<(ActionScope, BlockState)>,
+    // the failure wasn't handled by any of the blocks
+    failed: bool,
+}
+
+#[derive(Default)]
+struct BlockState {
+    failed: bool,
+    rescued: bool,
+    rescue_failed: bool,
+    always_failed: bool,
+}
+
+impl BlockState {
+    fn ended_in_failure(&self) -> bool {
+        (self.failed && !self.rescued) || self.rescue_failed || self.always_failed
+    }
+}
+
+impl Blocks {
+    /// Enter the blocks of the action, and returns whether the action should run
+    fn enter(&mut self, scope: &[ActionScope]) -> bool {
+        let common = self
+            .stack
+            .iter()
+            .zip(scope)
+            .take_while(|((current, _), scope)| current.block == scope.block)
+            .count();
+        while self.stack.len() > common {
+            self.leave();
+        }
+        for ((current, _), scope) in self.stack.iter_mut().zip(scope) {
+            current.section = scope.section;
+        }
+        for scope in &scope[common..] {
+            self.stack.push((*scope, BlockState::default()));
+        }
+
+        !self.failed
+            && self.stack.iter().all(|(scope, state)| match scope.section {
+                BlockSection::Main => !state.failed,
+                BlockSection::Rescue => state.failed && !state.rescue_failed,
+                BlockSection::Always => true,
+            })
+    }
+
+    /// Leave the innermost block, and the failure in it goes to the outer block
+    fn leave(&mut self) {
+        if let Some((_, state)) = self.stack.pop() {
+            if state.ended_in_failure() {
+                self.fail();
+            } else {
+                self.succeed();
+            }
+        }
+    }
+
+    /// The current action succeeded
+    fn succeed(&mut self) {
+        if let Some((scope, state)) = self.stack.last_mut() {
+            if scope.section == BlockSection::Rescue {
+                state.rescued = true;
+            }
+        }
+    }
+
+    /// The current action failed
+    fn fail(&mut self) {
+        match self.stack.last_mut() {
+            Some((scope, state)) => match scope.section {
+                BlockSection::Main => state.failed = true,
+                BlockSection::Rescue => state.rescue_failed = true,
+                BlockSection::Always => state.always_failed = true,
+            },
+            None => self.failed = true,
+        }
+    }
+
+    /// Leave all the blocks, and returns whether the actions succeeded in the end
+    fn finish(&mut self) -> bool {
+        while !self.stack.is_empty() {
+            self.leave();
+        }
+        !self.failed
+    }
+}
+
+/// Run the action and returns the line to report its result
 fn node_run_action(
     all_actions: &HashMap<String, Box<dyn Action>>,
     data: &ActionData,
     tx: &Sender<ActionMessage>,
-) -> Result<String> {
-    let result = if let Some(action) = all_actions.get(&data.action) {
-        let _ = tx.send(ActionMessage::ActionStarted { id: data.id });
-        action.execute(data.id, &data.input, tx)?
-    } else {
+) -> Result<(String, ActionOutputLevel)> {
+    let Some(action) = all_actions.get(&data.action) else {
         return Err(anyhow!("can't find action name {}", data.action));
     };
-    Ok(result)
+    let _ = tx.send(ActionMessage::ActionStarted { id: data.id });
+
+    if data.dry_run {
+        let change = action.dry_run(data.id, &data.input, tx)?;
+        let _ = tx.send(ActionMessage::ActionDryRun {
+            id: data.id,
+            change: change.clone(),
+        });
+        Ok(match change {
+            Some(change) => (format!("would {change}"), ActionOutputLevel::Warn),
+            None => ("no change".to_string(), ActionOutputLevel::Success),
+        })
+    } else {
+        let result = action.execute(data.id, &data.input, tx)?;
+        Ok((format!("successfully {result}"), ActionOutputLevel::Success))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn scope(block: Uuid, section: BlockSection) -> ActionScope {
+        ActionScope { block, section }
+    }
+
+    #[test]
+    fn failure_skips_the_rest() {
+        let run = Uuid::new_v4();
+        let mut blocks = Blocks::default();
+        assert!(blocks.enter(&[scope(run, BlockSection::Main)]));
+        blocks.fail();
+        assert!(!blocks.enter(&[scope(run, BlockSection::Main)]));
+        assert!(!blocks.finish());
+    }
+
+    #[test]
+    fn rescue_handles_failure() {
+        let run = Uuid::new_v4();
+        let mut blocks = Blocks::default();
+        assert!(blocks.enter(&[scope(run, BlockSection::Main)]));
+        blocks.fail();
+        assert!(blocks.enter(&[scope(run, BlockSection::Rescue)]));
+        blocks.succeed();
+        assert!(blocks.enter(&[scope(run, BlockSection::Always)]));
+        blocks.succeed();
+        assert!(blocks.finish());
+    }
+
+    #[test]
+    fn rescue_is_skipped_without_failure() {
+        let run = Uuid::new_v4();
+        let mut blocks = Blocks::default();
+        assert!(blocks.enter(&[scope(run, BlockSection::Main)]));
+        blocks.succeed();
+        assert!(!blocks.enter(&[scope(run, BlockSection::Rescue)]));
+        assert!(blocks.enter(&[scope(run, BlockSection::Always)]));
+        blocks.succeed();
+        assert!(blocks.finish());
+    }
+
+    #[test]
+    fn always_runs_after_failure() {
+        let run = Uuid::new_v4();
+        let mut blocks = Blocks::default();
+        assert!(blocks.enter(&[scope(run, BlockSection::Main)]));
+        blocks.fail();
+        assert!(blocks.enter(&[scope(run, BlockSection::Always)]));
+        blocks.succeed();
+        assert!(!blocks.finish());
+    }
+
+    #[test]
+    fn job_failure_goes_to_outer_rescue() {
+        let run = Uuid::new_v4();
+        let job = Uuid::new_v4();
+        let mut blocks = Blocks::default();
+        let in_job = [
+            scope(run, BlockSection::Main),
+            scope(job, BlockSection::Main),
+        ];
+        assert!(blocks.enter(&in_job));
+        blocks.fail();
+        assert!(!blocks.enter(&in_job));
+        // the job doesn't have a rescue, so the failure goes to the run
+        assert!(!blocks.enter(&[scope(run, BlockSection::Main)]));
+        assert!(blocks.enter(&[scope(run, BlockSection::Rescue)]));
+        blocks.succeed();
+        assert!(blocks.finish());
+    }
+
+    #[test]
+    fn job_rescue_handles_its_failure() {
+        let run = Uuid::new_v4();
+        let job = Uuid::new_v4();
+        let mut blocks = Blocks::default();
+        assert!(blocks.enter(&[
+            scope(run, BlockSection::Main),
+            scope(job, BlockSection::Main),
+        ]));
+        blocks.fail();
+        assert!(blocks.enter(&[
+            scope(run, BlockSection::Main),
+            scope(job, BlockSection::Rescue),
+        ]));
+        blocks.succeed();
+        assert!(blocks.enter(&[scope(run, BlockSection::Main)]));
+        blocks.succeed();
+        assert!(!blocks.enter(&[scope(run, BlockSection::Rescue)]));
+        assert!(blocks.finish());
+    }
 }
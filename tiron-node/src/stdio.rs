@@ -43,6 +43,30 @@ pub fn write_msg<W, RpcMessage>(out: &mut W, msg: RpcMessage) -> Result<()>
     Ok(())
 }
 
+/// Write the message in chunks of `chunk_size`, and call `progress` with the bytes
+/// written so far and the total after every chunk
+pub fn write_msg_chunked<W, RpcMessage>(
+    out: &mut W,
+    msg: RpcMessage,
+    chunk_size: usize,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<()>
+where
+    W: Write,
+    RpcMessage: Serialize,
+{
+    let msg = format!("{}\n", serde_json::to_string(&msg)?);
+    let total = msg.len() as u64;
+    let mut sent = 0;
+    for chunk in msg.as_bytes().chunks(chunk_size.max(1)) {
+        out.write_all(chunk)?;
+        sent += chunk.len() as u64;
+        progress(sent, total);
+    }
+    out.flush()?;
+    Ok(())
+}
+
 pub fn read_msg<R, RpcMessage>(inp: &mut R) -> Result<Option<RpcMessage>>
 where
     R: BufRead,
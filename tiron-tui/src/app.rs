@@ -1,4 +1,8 @@
-use std::time::SystemTime;
+use std::{
+    process::Command,
+    sync::{atomic::AtomicBool, Arc},
+    time::SystemTime,
+};
 
 use anyhow::{anyhow, Result};
 use crossbeam_channel::{Receiver, Sender};
@@ -9,15 +13,22 @@
     widgets::{Block, Borders, List, ListState, Widget},
     Frame,
 };
-use tiron_common::action::ActionMessage;
+use tiron_common::action::{ActionMessage, ActionOutputLevel};
 use uuid::Uuid;
 
 use crate::{
-    event::{AppEvent, RunEvent, UserInputEvent},
-    run::RunPanel,
+    event::{
+        AppEvent, DebugKey, DebugPrompt, DebugReply, HostPhase, RestartPrompt, RunEvent,
+        UserInputEvent,
+    },
+    run::{ActionSection, RunPanel},
     tui,
 };
 
+/// How many events can wait for the TUI, after which the hosts wait for it
+/// or their output is dropped
+const EVENT_BUFFER: usize = 4096;
+
 pub struct App {
     exit: bool,
     list_state: ListState,
@@ -26,6 +37,10 @@ pub struct App {
     pub active: usize,
     pub tx: Sender<AppEvent>,
     rx: Receiver<AppEvent>,
+    // the events aren't read while the TUI is suspended for a command
+    paused: Arc<AtomicBool>,
+    // the terminal is drawn from scratch after the TUI was suspended
+    redraw: bool,
 }
 
 impl Default for App {
@@ -36,7 +51,7 @@ fn default() -> Self {
 
 impl App {
     pub fn new() -> Self {
-        let (tx, rx) = crossbeam_channel::unbounded();
+        let (tx, rx) = crossbeam_channel::bounded(EVENT_BUFFER);
         Self {
             exit: false,
             list_state: ListState::default(),
@@ -44,6 +59,8 @@ pub fn new() -> Self {
             active: 0,
             tx,
             rx,
+            paused: Arc::new(AtomicBool::new(false)),
+            redraw: false,
         }
     }
 
@@ -56,10 +73,14 @@ pub fn start(&mut self) -> Result<()> {
 
     fn run(&mut self, terminal: &mut tui::Tui) -> Result<()> {
         let tx = self.tx.clone();
+        let paused = self.paused.clone();
         std::thread::spawn(move || {
-            let _ = tui::handle_events(tx);
+            let _ = tui::handle_events(tx, paused);
         });
         while !self.exit {
+            if std::mem::take(&mut self.redraw) {
+                terminal.clear()?;
+            }
             terminal.draw(|frame| self.render_frame(frame))?;
             self.handle_events()?;
         }
@@ -82,6 +103,12 @@ fn handle_events(&mut self) -> Result<()> {
             AppEvent::Run(event) => {
                 self.handle_run_event(event)?;
             }
+            AppEvent::Debug(prompt) => {
+                self.handle_debug_prompt(prompt)?;
+            }
+            AppEvent::Restart(prompt) => {
+                self.handle_restart_prompt(prompt)?;
+            }
         };
         Ok(())
     }
@@ -168,11 +195,126 @@ fn handle_user_input(&mut self, event: UserInputEvent) -> Result<()> {
                     run.active += 1;
                 }
             }
+            UserInputEvent::PrevAction => {
+                let run = self.get_active_run()?;
+                let host = run.get_active_host_mut()?;
+                if let Some(prompt) = host.restart.as_mut() {
+                    prompt.from = prompt.from.saturating_sub(1);
+                }
+            }
+            UserInputEvent::NextAction => {
+                let run = self.get_active_run()?;
+                let host = run.get_active_host_mut()?;
+                let last = host.actions.len().saturating_sub(1);
+                if let Some(prompt) = host.restart.as_mut() {
+                    prompt.from = (prompt.from + 1).min(last);
+                }
+            }
+            UserInputEvent::Debug(key) => {
+                let paused = self.paused.clone();
+                let run = self.get_active_run()?;
+                let host = run.get_active_host_mut()?;
+                if host.debug.is_none() {
+                    if let Some(prompt) = host.restart.take() {
+                        match key {
+                            DebugKey::Retry => {
+                                host.restart_from(prompt.from);
+                                let _ = prompt.reply.send(prompt.from);
+                                run.sort_hosts();
+                            }
+                            // the host stays failed when the prompt is dropped
+                            DebugKey::Fail => host.content_height = None,
+                            _ => host.restart = Some(prompt),
+                        }
+                        return Ok(());
+                    }
+                }
+                let Some(prompt) = host.debug.take() else {
+                    return Ok(());
+                };
+                host.content_height = None;
+                let reply = match key {
+                    DebugKey::Retry => DebugReply::Retry,
+                    DebugKey::Skip => DebugReply::Skip,
+                    DebugKey::Fail => DebugReply::Fail,
+                    DebugKey::Edit => {
+                        let edited = edit_params(&paused, &prompt);
+                        self.redraw = true;
+                        match edited {
+                            Ok(params) => DebugReply::Edit(params),
+                            Err(e) => {
+                                let host = self.get_active_run()?.get_active_host_mut()?;
+                                host.get_action(prompt.action)?.output_line(
+                                    format!("can't edit the params: {e}"),
+                                    ActionOutputLevel::Warn,
+                                );
+                                host.debug = Some(prompt);
+                                return Ok(());
+                            }
+                        }
+                    }
+                    DebugKey::Shell => {
+                        let opened = open_shell(&paused, &prompt.shell);
+                        self.redraw = true;
+                        let host = self.get_active_run()?.get_active_host_mut()?;
+                        if let Err(e) = opened {
+                            host.get_action(prompt.action)?.output_line(
+                                format!("can't open a shell: {e}"),
+                                ActionOutputLevel::Warn,
+                            );
+                        }
+                        // the action still waits for the answer after the shell
+                        host.debug = Some(prompt);
+                        return Ok(());
+                    }
+                };
+                let _ = prompt.reply.send(reply);
+            }
             UserInputEvent::Quit => self.exit(),
         }
         Ok(())
     }
 
+    /// Show the prompt of the failed action on its host, which is brought to the front
+    fn handle_debug_prompt(&mut self, prompt: DebugPrompt) -> Result<()> {
+        let (i, run) = self.get_run(prompt.run)?;
+        let host = run
+            .hosts
+            .iter()
+            .position(|h| h.id == prompt.host)
+            .ok_or_else(|| anyhow!("can't find host"))?;
+        run.active = host;
+        run.hosts[host].debug = Some(prompt);
+        run.hosts[host].content_height = None;
+        self.active = i;
+        Ok(())
+    }
+
+    /// Show the prompt of the failed host, which is brought to the front
+    /// if no other host is waiting for an answer
+    fn handle_restart_prompt(&mut self, prompt: RestartPrompt) -> Result<()> {
+        let (i, run) = self.get_run(prompt.run)?;
+        let host = run
+            .hosts
+            .iter()
+            .position(|h| h.id == prompt.host)
+            .ok_or_else(|| anyhow!("can't find host"))?;
+        if !run.hosts.iter().any(|h| h.restart.is_some()) {
+            run.active = host;
+            self.active = i;
+        }
+        run.hosts[host].restart = Some(prompt);
+        run.hosts[host].content_height = None;
+        Ok(())
+    }
+
+    /// Whether the runs wait for the failed hosts to be restarted
+    pub fn prompting_restart(&self) -> bool {
+        self.runs
+            .iter()
+            .any(|run| run.hosts.iter().any(|host| host.restart.is_some()))
+    }
+
     fn handle_action_event(&mut self, run: Uuid, host: Uuid, msg: ActionMessage) -> Result<()> {
         let run = self
             .runs
@@ -188,6 +330,7 @@ fn handle_action_event(&mut self, run: Uuid, host: Uuid, msg: ActionMessage) ->
             .ok_or_else(|| anyhow!("can't find host"))?;
         match msg {
             ActionMessage::ActionStarted { id } => {
+                host.phase = Some(HostPhase::Running);
                 let action = host.get_action(id)?;
                 action.started();
             }
@@ -200,6 +343,17 @@ fn handle_action_event(&mut self, run: Uuid, host: Uuid, msg: ActionMessage) ->
                 let action = host.get_action(id)?;
                 action.success(success);
             }
+            ActionMessage::ActionDryRun { .. }
+            | ActionMessage::ActionDebug { .. }
+            | ActionMessage::ActionTransfer { .. }
+            | ActionMessage::ActionFetch { .. }
+            | ActionMessage::ActionValue { .. }
+            | ActionMessage::ActionSkipped { .. }
+            | ActionMessage::NodeFacts { .. }
+            | ActionMessage::NodeLocked { .. }
+            | ActionMessage::NodeSignatures { .. }
+            | ActionMessage::NodeCached { .. }
+            | ActionMessage::NodeFingerprint { .. } => {}
             ActionMessage::NodeShutdown { success } => {
                 host.success = Some((
                     success,
@@ -210,8 +364,27 @@ fn handle_action_event(&mut self, run: Uuid, host: Uuid, msg: ActionMessage) ->
                 ));
                 run.sort_hosts();
             }
-            ActionMessage::NodeStartFailed { reason } => {
-                host.start_failed = Some(reason);
+            ActionMessage::NodePanicked {
+                id,
+                message,
+                backtrace,
+            } => {
+                if let Some(id) = id {
+                    host.get_action(id)?.success(false);
+                }
+                host.panicked = Some((message, backtrace));
+                host.content_height = None;
+                host.success = Some((
+                    false,
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0),
+                ));
+                run.sort_hosts();
+            }
+            ActionMessage::NodeStartFailed { kind, reason } => {
+                host.start_failed = Some((kind, reason));
                 host.success = Some((
                     false,
                     SystemTime::now()
@@ -228,14 +401,42 @@ fn handle_action_event(&mut self, run: Uuid, host: Uuid, msg: ActionMessage) ->
     fn handle_run_event(&mut self, event: RunEvent) -> Result<()> {
         match event {
             RunEvent::RunStarted { id } => {
+                // it stays on the active run while it's executing, e.g. when the runbooks
+                // are executed at the same time
+                let busy = self
+                    .runs
+                    .get(self.active)
+                    .is_some_and(|run| run.started && run.success.is_none());
                 let (i, run) = self.get_run(id)?;
                 run.started = true;
-                self.active = i;
+                if !busy {
+                    self.active = i;
+                }
             }
             RunEvent::RunCompleted { id, success } => {
                 let (_, run) = self.get_run(id)?;
                 run.success = Some(success);
             }
+            RunEvent::HostPhase { run, host, phase } => {
+                let (_, run) = self.get_run(run)?;
+                let host = run.get_host_mut(host)?;
+                host.phase = Some(phase);
+            }
+            RunEvent::HostRetry { run, host, attempt } => {
+                let (_, run) = self.get_run(run)?;
+                let host = run.get_host_mut(host)?;
+                host.retry(attempt);
+                run.sort_hosts();
+            }
+            RunEvent::HostActions { run, host, actions } => {
+                let (_, run) = self.get_run(run)?;
+                let host = run.get_host_mut(host)?;
+                host.actions = actions
+                    .into_iter()
+                    .map(|(id, name)| ActionSection::new(id, name))
+                    .collect();
+                host.content_height = None;
+            }
         }
         Ok(())
     }
@@ -262,6 +463,35 @@ fn exit(&mut self) {
     }
 }
 
+/// Edit the params of the failed action in the editor of `$VISUAL` or `$EDITOR`,
+/// and returns the edited params
+fn edit_params(paused: &AtomicBool, prompt: &DebugPrompt) -> Result<String> {
+    let path = std::env::temp_dir().join(format!("tiron-debug-{}.tr", prompt.host));
+    std::fs::write(&path, &prompt.params)?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = tui::suspend(paused, Command::new(program).args(words).arg(&path));
+    let params = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let status = status?;
+    if !status.success() {
+        return Err(anyhow!("{program} exited with {status}"));
+    }
+    Ok(params?)
+}
+
+/// Open an interactive shell on the host of the failed action
+fn open_shell(paused: &AtomicBool, shell: &[String]) -> Result<()> {
+    let (program, args) = shell
+        .split_first()
+        .ok_or_else(|| anyhow!("there's no shell for the host"))?;
+    tui::suspend(paused, Command::new(program).args(args))?;
+    Ok(())
+}
+
 impl Widget for &mut App {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let layout = Layout::default()
@@ -1,4 +1,11 @@
-use tiron_common::action::ActionMessage;
+use std::{collections::HashMap, sync::Mutex};
+
+use crossbeam_channel::{Receiver, Sender};
+pub use tiron_common::event::{HostPhase, RunEvent};
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionOutputLevel},
+    event::{Event, EventSink},
+};
 use uuid::Uuid;
 
 pub enum AppEvent {
@@ -9,6 +16,182 @@ pub enum AppEvent {
         host: Uuid,
         msg: ActionMessage,
     },
+    Debug(DebugPrompt),
+    Restart(RestartPrompt),
+}
+
+/// The prompt of an action that failed in the debug strategy of the run,
+/// which waits for the user to answer it
+pub struct DebugPrompt {
+    pub run: Uuid,
+    pub host: Uuid,
+    pub action: ActionId,
+    // the params of the action as they're written in HCL, for editing them
+    pub params: String,
+    // the command of an interactive shell on the host
+    pub shell: Vec<String>,
+    pub reply: Sender<DebugReply>,
+}
+
+/// The answer to the prompt of a failed action
+pub enum DebugReply {
+    Retry,
+    // retry with the edited params
+    Edit(String),
+    Skip,
+    Fail,
+}
+
+/// The prompt of a host that failed, which can be restarted from one of its actions
+pub struct RestartPrompt {
+    pub run: Uuid,
+    pub host: Uuid,
+    // the index of the action to restart from, which is where the host failed
+    // until another one is picked
+    pub from: usize,
+    pub reply: Sender<usize>,
+}
+
+/// Asks the user in the TUI what to do with the actions that fail
+/// in the debug strategy of the run
+#[derive(Clone)]
+pub struct TuiDebugger {
+    tx: Sender<AppEvent>,
+}
+
+impl TuiDebugger {
+    pub fn new(tx: Sender<AppEvent>) -> Self {
+        Self { tx }
+    }
+
+    /// Show the prompt for the failed action, and wait for the answer,
+    /// which is to fail if the TUI is gone
+    pub fn ask(
+        &self,
+        run: Uuid,
+        host: Uuid,
+        action: ActionId,
+        params: String,
+        shell: Vec<String>,
+    ) -> DebugReply {
+        let (reply, rx) = crossbeam_channel::bounded(1);
+        let prompt = DebugPrompt {
+            run,
+            host,
+            action,
+            params,
+            shell,
+            reply,
+        };
+        if self.tx.send(AppEvent::Debug(prompt)).is_err() {
+            return DebugReply::Fail;
+        }
+        rx.recv().unwrap_or(DebugReply::Fail)
+    }
+
+    /// Show the prompt for the failed host, which is answered with the index of
+    /// the action to restart it from. The answer doesn't come if it isn't restarted
+    pub fn ask_restart(&self, run: Uuid, host: Uuid, from: usize) -> Receiver<usize> {
+        let (reply, rx) = crossbeam_channel::bounded(1);
+        let prompt = RestartPrompt {
+            run,
+            host,
+            from,
+            reply,
+        };
+        let _ = self.tx.send(AppEvent::Restart(prompt));
+        rx
+    }
+}
+
+impl From<Event> for AppEvent {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::Run(event) => AppEvent::Run(event),
+            Event::Action { run, host, msg } => AppEvent::Action { run, host, msg },
+        }
+    }
+}
+
+/// Sends the run events to the TUI. The hosts wait for the TUI when it can't keep up,
+/// but the info lines of the output are only to look at, so they're dropped instead,
+/// with a line that tells how many were dropped
+pub struct TuiSink {
+    tx: Sender<AppEvent>,
+    // how many info lines of the actions have been dropped since their last line
+    // that got through
+    dropped: Mutex<HashMap<(Uuid, Uuid, ActionId), usize>>,
+}
+
+impl TuiSink {
+    pub fn new(tx: Sender<AppEvent>) -> Self {
+        Self {
+            tx,
+            dropped: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn dropped_line(run: Uuid, host: Uuid, id: ActionId, n: usize) -> AppEvent {
+        AppEvent::Action {
+            run,
+            host,
+            msg: ActionMessage::ActionOutputLine {
+                id,
+                content: format!("... {n} lines of output were dropped"),
+                level: ActionOutputLevel::Warn,
+            },
+        }
+    }
+}
+
+impl EventSink for TuiSink {
+    fn send(&self, event: Event) {
+        let Ok(mut dropped) = self.dropped.lock() else {
+            return;
+        };
+        match &event {
+            // the progress is shown again with the next one
+            Event::Action {
+                msg: ActionMessage::ActionTransfer { .. },
+                ..
+            } => {
+                let _ = self.tx.try_send(event.into());
+            }
+            Event::Action {
+                run,
+                host,
+                msg:
+                    ActionMessage::ActionOutputLine {
+                        id,
+                        level: ActionOutputLevel::Info,
+                        ..
+                    },
+            } => {
+                let key = (*run, *host, *id);
+                if let Some(n) = dropped.get(&key).copied() {
+                    if self
+                        .tx
+                        .try_send(Self::dropped_line(*run, *host, *id, n))
+                        .is_err()
+                    {
+                        dropped.insert(key, n + 1);
+                        return;
+                    }
+                    dropped.remove(&key);
+                }
+                if self.tx.try_send(event.into()).is_err() {
+                    *dropped.entry(key).or_default() += 1;
+                }
+            }
+            _ => {
+                // the lines that were dropped are told before anything else of the actions
+                for ((run, host, id), n) in dropped.drain() {
+                    let _ = self.tx.send(Self::dropped_line(run, host, id, n));
+                }
+                let _ = self.tx.send(event.into());
+            }
+        }
+    }
 }
 
 pub enum UserInputEvent {
@@ -22,11 +205,20 @@ pub enum UserInputEvent {
     NextRun,
     PrevHost,
     NextHost,
+    // pick the action to restart the failed host from
+    PrevAction,
+    NextAction,
+    Debug(DebugKey),
     Resize,
     Quit,
 }
 
-pub enum RunEvent {
-    RunStarted { id: Uuid },
-    RunCompleted { id: Uuid, success: bool },
+/// The keys for answering the prompt of a failed action, and `Retry` and `Fail`
+/// for the prompt of a failed host as well
+pub enum DebugKey {
+    Retry,
+    Skip,
+    Edit,
+    Shell,
+    Fail,
 }
@@ -3,18 +3,24 @@
     buffer::Buffer,
     layout::{Alignment, Rect},
     style::{Color, Style, Stylize},
-    text::StyledGrapheme,
+    text::{Line, Span, StyledGrapheme, Text},
     widgets::{
-        block::Title, Block, Borders, List, ListState, Paragraph, Scrollbar, ScrollbarOrientation,
-        ScrollbarState, StatefulWidget,
+        block::Title, Block, Borders, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, StatefulWidget,
     },
 };
-use tiron_common::action::{ActionId, ActionOutput, ActionOutputLevel, ActionOutputLine};
+use tiron_common::action::{
+    retry_failed_output, ActionId, ActionOutput, ActionOutputLevel, ActionOutputLine,
+    NodeStartFailedKind,
+};
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 use uuid::Uuid;
 
-use crate::reflow::{LineComposer, WordWrapper, WrappedLine};
+use crate::{
+    event::{DebugPrompt, HostPhase, RestartPrompt},
+    reflow::{LineComposer, WordWrapper, WrappedLine},
+};
 
 pub struct HostSection {
     pub id: Uuid,
@@ -27,7 +33,18 @@ pub struct HostSection {
     pub content_height: Option<usize>,
     pub viewport_height: usize,
     pub success: Option<(bool, u64)>,
-    pub start_failed: Option<String>,
+    pub start_failed: Option<(NodeStartFailedKind, String)>,
+    // the panic message and the backtrace if the node panicked
+    pub panicked: Option<(String, String)>,
+    pub phase: Option<HostPhase>,
+    // the environment of the host, e.g. prod, which the hosts are grouped by
+    pub environment: Option<String>,
+    // the color of the host's tag in the list
+    pub color: Option<Color>,
+    // the prompt of the action that failed in the debug strategy
+    pub debug: Option<DebugPrompt>,
+    // the prompt to restart the host after it failed
+    pub restart: Option<RestartPrompt>,
 }
 
 impl HostSection {
@@ -41,6 +58,31 @@ pub fn get_action(&mut self, id: ActionId) -> Result<&mut ActionSection> {
         Ok(action)
     }
 
+    /// reset the failed state of the host so that it can be executed again
+    pub fn retry(&mut self, attempt: usize) {
+        self.success = None;
+        self.start_failed = None;
+        self.panicked = None;
+        self.phase = None;
+        retry_failed_output(self.actions.iter_mut().map(|a| &mut a.output), attempt);
+        self.content_height = None;
+    }
+
+    /// reset the host from the action it's restarted from, and the actions after it
+    pub fn restart_from(&mut self, from: usize) {
+        self.success = None;
+        self.start_failed = None;
+        self.panicked = None;
+        self.phase = None;
+        for (i, action) in self.actions.iter_mut().enumerate().skip(from) {
+            action.output = ActionOutput::default();
+            if i == from {
+                action.output_line("restarted from here".to_string(), ActionOutputLevel::Warn);
+            }
+        }
+        self.content_height = None;
+    }
+
     fn render(&mut self, area: Rect, buf: &mut Buffer) {
         let status_area = Rect::new(
             area.left() + 1,
@@ -49,7 +91,38 @@ fn render(&mut self, area: Rect, buf: &mut Buffer) {
             1,
         );
 
-        {
+        if self.debug.is_some() {
+            buf.set_style(
+                status_area,
+                Style::default().bg(Color::Yellow).fg(Color::Black),
+            );
+            ratatui::widgets::Widget::render(
+                Paragraph::new(
+                    "action failed: [r]etry  [s]kip  [e]dit params  [o]pen shell  [f]ail",
+                )
+                .alignment(Alignment::Center),
+                status_area,
+                buf,
+            );
+        } else if let Some(prompt) = &self.restart {
+            buf.set_style(
+                status_area,
+                Style::default().bg(Color::Yellow).fg(Color::Black),
+            );
+            let action = self
+                .actions
+                .get(prompt.from)
+                .map(|action| format!("{}. {}", prompt.from + 1, action.name))
+                .unwrap_or_else(|| "the end".to_string());
+            ratatui::widgets::Widget::render(
+                Paragraph::new(format!(
+                    "host failed: [r]estart from {action}  [ and ] pick the action  [f]ail"
+                ))
+                .alignment(Alignment::Center),
+                status_area,
+                buf,
+            );
+        } else {
             let width = status_area.width;
             let completed = self
                 .actions
@@ -99,19 +172,44 @@ fn render(&mut self, area: Rect, buf: &mut Buffer) {
         let mut running_bottom = 0;
 
         let stop_if_outside_area = self.content_height.is_some();
-        if let Some(reason) = &self.start_failed {
+        if let Some((kind, reason)) = &self.start_failed {
             render_line(
                 area,
                 buf,
                 &mut y,
                 self.scroll,
-                &format!("host start failed: {reason}"),
+                &format!("host start failed ({kind}): {reason}"),
                 Some(Color::Red),
                 None,
                 stop_if_outside_area,
             );
             y += 1;
         }
+        if let Some((message, backtrace)) = &self.panicked {
+            render_line(
+                area,
+                buf,
+                &mut y,
+                self.scroll,
+                &format!("node panicked: {message}"),
+                Some(Color::Red),
+                None,
+                stop_if_outside_area,
+            );
+            for line in backtrace.lines() {
+                render_line(
+                    area,
+                    buf,
+                    &mut y,
+                    self.scroll,
+                    line,
+                    Some(Color::DarkGray),
+                    None,
+                    stop_if_outside_area,
+                );
+            }
+            y += 1;
+        }
 
         for action in &self.actions {
             action.render(area, buf, &mut y, self.scroll, stop_if_outside_area);
@@ -161,7 +259,7 @@ pub fn started(&mut self) {
     }
 
     pub fn output_line(&mut self, content: String, level: ActionOutputLevel) {
-        self.output.lines.push(ActionOutputLine { content, level });
+        self.output.push_line(ActionOutputLine { content, level });
     }
 
     pub fn success(&mut self, success: bool) {
@@ -180,7 +278,9 @@ pub struct RunPanel {
 }
 
 impl RunPanel {
-    pub fn new(id: Uuid, name: Option<String>, hosts: Vec<HostSection>) -> Self {
+    pub fn new(id: Uuid, name: Option<String>, mut hosts: Vec<HostSection>) -> Self {
+        // the hosts in the same environment are next to each other
+        hosts.sort_by(|a, b| a.environment.cmp(&b.environment));
         Self {
             id,
             name,
@@ -201,6 +301,16 @@ pub fn get_active_host_mut(&mut self) -> Result<&mut HostSection> {
         Ok(host)
     }
 
+    pub fn get_host_mut(&mut self, id: Uuid) -> Result<&mut HostSection> {
+        let host = self
+            .hosts
+            .iter_mut()
+            .rev()
+            .find(|h| h.id == id)
+            .ok_or_else(|| anyhow!("can't find host"))?;
+        Ok(host)
+    }
+
     pub fn get_active_host(&self) -> Result<&HostSection> {
         let active = self.active.min(self.hosts.len().saturating_sub(1));
         let host = self.hosts.get(active).ok_or_else(|| anyhow!("no host"))?;
@@ -215,18 +325,49 @@ pub fn render(&mut self, area: Rect, buf: &mut Buffer) {
 
     pub fn render_hosts(&mut self, area: Rect, buf: &mut Buffer) {
         self.hosts_state.select(Some(self.active));
-        List::new(self.hosts.iter().map(|host| {
-            let color = if host.start_failed.is_some() {
+        let grouped = self.hosts.iter().any(|host| host.environment.is_some());
+        List::new(self.hosts.iter().enumerate().map(|(i, host)| {
+            let color = if host.start_failed.is_some() || host.panicked.is_some() {
                 Some(Color::Red)
             } else {
                 host.success
                     .map(|(success, _)| if success { Color::Green } else { Color::Red })
             };
-            if let Some(color) = color {
+            let name = if let Some(color) = color {
                 host.host.clone().fg(color)
             } else {
                 host.host.clone().into()
+            };
+
+            let phase = if let Some((success, _)) = host.success {
+                if success {
+                    "done".to_string()
+                } else {
+                    "failed".to_string()
+                }
+            } else if let Some(phase) = host.phase {
+                phase.to_string()
+            } else {
+                "waiting".to_string()
+            };
+
+            let tag_style = Style::default().fg(host.color.unwrap_or(Color::Reset));
+            let mut lines = Vec::new();
+            // the first host of each environment has its name above it
+            if grouped && (i == 0 || self.hosts[i - 1].environment != host.environment) {
+                let environment = host.environment.as_deref().unwrap_or("untagged");
+                lines.push(Line::from(Span::styled(
+                    format!("[{environment}]"),
+                    tag_style.bold(),
+                )));
             }
+            lines.push(if host.color.is_some() {
+                Line::from(vec![Span::styled("▌", tag_style), name])
+            } else {
+                Line::from(name)
+            });
+            lines.push(Line::from(format!("  {phase}").dark_gray()));
+            ListItem::new(Text::from(lines))
         }))
         .highlight_symbol(" > ")
         .block(Block::default().borders(Borders::RIGHT))
@@ -235,7 +376,8 @@ pub fn render_hosts(&mut self, area: Rect, buf: &mut Buffer) {
 
     pub fn sort_hosts(&mut self) {
         let active_id = self.get_active_host().ok().map(|h| h.id);
-        self.hosts.sort_by_key(|h| h.success);
+        self.hosts
+            .sort_by(|a, b| (&a.environment, a.success).cmp(&(&b.environment, b.success)));
         let active = if let Some(id) = active_id {
             self.hosts.iter().position(|h| h.id == id)
         } else {
@@ -268,8 +410,26 @@ pub fn new(id: Uuid, host: String, actions: Vec<ActionSection>) -> Self {
             scroll_state: ScrollbarState::default(),
             success: None,
             start_failed: None,
+            panicked: None,
+            phase: None,
+            environment: None,
+            color: None,
+            debug: None,
+            restart: None,
         }
     }
+
+    /// The host with the tags it's grouped and colored by in the list
+    pub fn with_tags(mut self, environment: Option<String>, color: Option<&str>) -> Self {
+        self.environment = environment;
+        self.color = color.and_then(parse_color);
+        self
+    }
+}
+
+/// The color of a tag, e.g. `red`, `lightblue` or `#ff8800`
+pub fn parse_color(color: &str) -> Option<Color> {
+    color.parse().ok()
 }
 
 impl ActionSection {
@@ -1,4 +1,12 @@
-use std::io::{stdout, Stdout};
+use std::{
+    io::{stdout, Stdout},
+    process::{Command, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::Result;
 use crossbeam_channel::Sender;
@@ -9,7 +17,7 @@
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 
-use crate::event::{AppEvent, UserInputEvent};
+use crate::event::{AppEvent, DebugKey, UserInputEvent};
 
 /// A type alias for the terminal type used in this application
 pub type Tui = Terminal<CrosstermBackend<Stdout>>;
@@ -29,8 +37,32 @@ pub fn restore() -> Result<()> {
     Ok(())
 }
 
-pub fn handle_events(tx: Sender<AppEvent>) -> Result<()> {
-    while let Ok(event) = crossterm::event::read() {
+/// Leave the TUI to run an interactive command, e.g. a shell on the host,
+/// and come back when it exits. The events aren't read while it runs,
+/// so that the command gets the keys
+pub fn suspend(paused: &AtomicBool, cmd: &mut Command) -> Result<ExitStatus> {
+    paused.store(true, Ordering::SeqCst);
+    restore()?;
+    let status = cmd.status();
+    execute!(stdout(), EnterAlternateScreen)?;
+    enable_raw_mode()?;
+    paused.store(false, Ordering::SeqCst);
+    Ok(status?)
+}
+
+pub fn handle_events(tx: Sender<AppEvent>, paused: Arc<AtomicBool>) -> Result<()> {
+    loop {
+        if paused.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(50));
+            continue;
+        }
+        // it's polled so that it stops reading when the TUI is suspended
+        if !crossterm::event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        let Ok(event) = crossterm::event::read() else {
+            break;
+        };
         let event = match event {
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                 match key_event.code {
@@ -53,6 +85,13 @@ pub fn handle_events(tx: Sender<AppEvent>) -> Result<()> {
                     KeyCode::Char('n') if key_event.modifiers.is_empty() => {
                         UserInputEvent::NextHost
                     }
+                    KeyCode::Char('[') => UserInputEvent::PrevAction,
+                    KeyCode::Char(']') => UserInputEvent::NextAction,
+                    KeyCode::Char('r') => UserInputEvent::Debug(DebugKey::Retry),
+                    KeyCode::Char('s') => UserInputEvent::Debug(DebugKey::Skip),
+                    KeyCode::Char('e') => UserInputEvent::Debug(DebugKey::Edit),
+                    KeyCode::Char('o') => UserInputEvent::Debug(DebugKey::Shell),
+                    KeyCode::Char('f') => UserInputEvent::Debug(DebugKey::Fail),
                     _ => continue,
                 }
             }
@@ -0,0 +1,206 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    sync::Arc,
+};
+
+use tiron_common::{
+    crypto::{constant_time_eq, hex, hmac_sha256, unhex},
+    error::Error,
+    event::{EventSink, NullSink},
+};
+
+use crate::{
+    core::execute_runs,
+    history::now,
+    run::{Run, RunResult},
+};
+
+/// The environment variable of the key shared by the approvers and the runners
+/// to sign and verify the approval tokens
+const APPROVAL_KEY: &str = "TIRON_APPROVAL_KEY";
+
+/// How long in seconds an approval token is valid after it's signed,
+/// so that it can't be replayed when the same changes come up again later
+pub const APPROVAL_VALIDITY: u64 = 24 * 60 * 60;
+
+/// Check the runs that require approval, and returns the summary of what they would change
+pub fn plan_summary(runs: &[Run]) -> Result<Option<String>, Error> {
+    let runs: Vec<Run> = runs
+        .iter()
+        .filter(|run| run.requires_approval)
+        .map(|run| run.dry_run())
+        .collect();
+    if runs.is_empty() {
+        return Ok(None);
+    }
+
+    // the check doesn't change anything, so its events aren't shown
+    let events: Arc<dyn EventSink> = Arc::new(NullSink);
+    let expected = runs.len();
+    let results = execute_runs(runs, &events).map_err(|e| Error::new(e.to_string()))?;
+    if results.len() < expected || results.iter().any(|r| !r.success) {
+        return Error::new("can't check the runs that require approval").err();
+    }
+    Ok(Some(summary(&results)))
+}
+
+fn summary(results: &[RunResult]) -> String {
+    let mut summary = String::new();
+    for (i, result) in results.iter().enumerate() {
+        let name = result
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Run {}", i + 1));
+        summary.push_str(&format!("{name}\n"));
+        for host in &result.hosts {
+            if host.skipped {
+                summary.push_str(&format!("  {}: skipped\n", host.host));
+            } else if host.pending.is_empty() {
+                summary.push_str(&format!("  {}: no changes\n", host.host));
+            } else {
+                summary.push_str(&format!("  {}:\n", host.host));
+                for change in &host.pending {
+                    summary.push_str(&format!("    {}: {}\n", change.action, change.change));
+                }
+            }
+        }
+    }
+    summary
+}
+
+/// Pause before the runs that require approval with the summary of what they would change,
+/// and only continue when it's confirmed in the terminal, or with an approval token
+/// signed for the same changes when there's no terminal
+pub fn approve(runs: &[Run], token: Option<&str>) -> Result<(), Error> {
+    let Some(summary) = plan_summary(runs)? else {
+        return Ok(());
+    };
+    eprintln!("The runs require approval, and they would make these changes:\n");
+    eprint!("{summary}");
+
+    if std::io::stdin().is_terminal() && token.is_none() {
+        eprint!("\nProceed? [y/N] ");
+        let _ = std::io::stderr().flush();
+        let mut answer = String::new();
+        std::io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|e| Error::new(e.to_string()))?;
+        if matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Ok(());
+        }
+        return Error::new("the runs weren't approved").err();
+    }
+
+    let Some(token) = token else {
+        return Error::new(
+            "the runs require approval, give an approval token from `tiron approve` \
+             with --approval-token",
+        )
+        .err();
+    };
+    verify(&approval_key()?, token, &summary, now())
+}
+
+/// Refuse to run on the hosts of the protected groups unless it's confirmed in the
+/// terminal with the hosts listed, or with --yes-i-mean-prod
+pub fn confirm_protected(runs: &[Run], yes_i_mean_prod: bool) -> Result<(), Error> {
+    let mut hosts: Vec<(&str, &str)> = Vec::new();
+    for host in runs.iter().flat_map(|run| run.protected_hosts()) {
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    if hosts.is_empty() || yes_i_mean_prod {
+        return Ok(());
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Error::new(format!(
+            "the runs target {} hosts in protected groups, use --yes-i-mean-prod to run them",
+            hosts.len()
+        ))
+        .err();
+    }
+    eprintln!("The runs target these hosts in protected groups:\n");
+    for (group, host) in &hosts {
+        eprintln!("  {host} ({group})");
+    }
+    eprint!("\nRun on these {} hosts? [y/N] ", hosts.len());
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| Error::new(e.to_string()))?;
+    if matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Ok(());
+    }
+    Error::new("the runs on the protected hosts weren't confirmed").err()
+}
+
+/// Sign the summary of the changes with the approval key, which gives the approval token
+/// that expires after [`APPROVAL_VALIDITY`]
+pub fn sign(summary: &str) -> Result<String, Error> {
+    Ok(token(&approval_key()?, summary, now() + APPROVAL_VALIDITY))
+}
+
+fn approval_key() -> Result<String, Error> {
+    std::env::var(APPROVAL_KEY)
+        .map_err(|_| Error::new(format!("{APPROVAL_KEY} isn't set for the approval")))
+}
+
+/// The token is the expiry, and the signature of the expiry with the summary,
+/// so that the expiry can't be changed
+fn token(key: &str, summary: &str, expires: u64) -> String {
+    format!("{expires}.{}", hex(&signature(key, summary, expires)))
+}
+
+fn signature(key: &str, summary: &str, expires: u64) -> [u8; 32] {
+    hmac_sha256(key.as_bytes(), format!("{expires}\n{summary}").as_bytes())
+}
+
+/// Check that the token is signed for the summary and hasn't expired at `now`
+fn verify(key: &str, token: &str, summary: &str, now: u64) -> Result<(), Error> {
+    let invalid = || Error::new("the approval token isn't valid, get one from `tiron approve`");
+    let (expires, sig) = token.trim().split_once('.').ok_or_else(invalid)?;
+    let expires: u64 = expires.parse().map_err(|_| invalid())?;
+    let sig = unhex(sig).ok_or_else(invalid)?;
+    if !constant_time_eq(&sig, &signature(key, summary, expires)) {
+        return Error::new(
+            "the approval token doesn't match the changes, they might have changed since the approval",
+        )
+        .err();
+    }
+    if expires < now {
+        return Error::new("the approval token has expired, get a new one from `tiron approve`")
+            .err();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verify_token() {
+        let token = token("secret", "web1: restart nginx\n", 1000);
+        assert!(verify("secret", &token, "web1: restart nginx\n", 999).is_ok());
+
+        let e = verify("secret", &token, "web1: restart nginx\n", 1001).unwrap_err();
+        assert_eq!(
+            e.message,
+            "the approval token has expired, get a new one from `tiron approve`"
+        );
+        let e = verify("secret", &token, "web1: stop nginx\n", 999).unwrap_err();
+        assert!(e
+            .message
+            .starts_with("the approval token doesn't match the changes"));
+        // the expiry can't be pushed back without the key
+        let forged = token.replacen("1000", "2000", 1);
+        assert!(verify("secret", &forged, "web1: restart nginx\n", 1001).is_err());
+        assert!(verify("other", &token, "web1: restart nginx\n", 999).is_err());
+        assert!(verify("secret", "3b1f", "web1: restart nginx\n", 999).is_err());
+    }
+}
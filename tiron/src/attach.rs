@@ -0,0 +1,274 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use tiron_common::{
+    error::Error,
+    event::{Event, EventSink},
+};
+use uuid::Uuid;
+
+use crate::{
+    history::history_dir,
+    replay::{RecordedLine, RecordedRun},
+    run::Run,
+};
+
+/// Serves the events of the runs on a unix socket in the run history, next to their
+/// recording, so that `tiron attach` can mirror the runs from another terminal.
+/// The clients get the lines of the recording, starting with the ones they missed
+pub struct AttachSink {
+    start: Instant,
+    path: PathBuf,
+    state: Arc<Mutex<AttachState>>,
+}
+
+#[derive(Default)]
+struct AttachState {
+    // the lines sent so far, starting with the runs
+    lines: Vec<String>,
+    // every client is written to on its own thread, so that a slow one
+    // never holds up the runs
+    clients: Vec<Sender<String>>,
+    closed: bool,
+}
+
+impl AttachSink {
+    /// Listen on `<id>.sock` in the history directory
+    pub fn listen(id: Uuid, runs: &[Run]) -> Result<Self> {
+        let dir = history_dir().ok_or_else(|| anyhow!("can't find the history directory"))?;
+        std::fs::create_dir_all(&dir)?;
+        let path = socket_path(&dir, &id.to_string());
+        let runs = RecordedLine::Runs(runs.iter().map(RecordedRun::new).collect());
+        let state = Arc::new(Mutex::new(AttachState {
+            lines: vec![serde_json::to_string(&runs)?],
+            ..Default::default()
+        }));
+        serve(&path, state.clone())?;
+        Ok(Self {
+            start: Instant::now(),
+            path,
+            state,
+        })
+    }
+}
+
+impl EventSink for AttachSink {
+    fn send(&self, event: Event) {
+        let line = RecordedLine::Event {
+            at: self.start.elapsed().as_millis() as u64,
+            event,
+        };
+        let Ok(json) = serde_json::to_string(&line) else {
+            return;
+        };
+        if let Ok(mut state) = self.state.lock() {
+            state
+                .clients
+                .retain(|client| client.send(json.clone()).is_ok());
+            state.lines.push(json);
+        }
+    }
+}
+
+impl Drop for AttachSink {
+    /// The runs are done, so the clients are disconnected
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+            state.clients.clear();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn socket_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.sock"))
+}
+
+#[cfg(unix)]
+fn serve(path: &Path, state: Arc<Mutex<AttachState>>) -> Result<()> {
+    use std::{io::Write, os::unix::net::UnixListener};
+
+    let listener = UnixListener::bind(path)?;
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let (tx, rx) = crossbeam_channel::unbounded::<String>();
+            // the lines so far are taken with the client added in one go,
+            // so that it gets every line once
+            let lines = {
+                let Ok(mut state) = state.lock() else {
+                    return;
+                };
+                if state.closed {
+                    return;
+                }
+                state.clients.push(tx);
+                state.lines.clone()
+            };
+            std::thread::spawn(move || {
+                for line in lines.into_iter().chain(rx) {
+                    if writeln!(stream, "{line}").is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve(_path: &Path, _state: Arc<Mutex<AttachState>>) -> Result<()> {
+    Err(anyhow!("attaching to the runs needs unix sockets"))
+}
+
+/// Mirror the runs in progress with the id, which can be a prefix, in a read-only TUI,
+/// or the only runs in progress if it's not given
+#[cfg(unix)]
+pub fn attach(id: Option<String>) -> Result<(), Error> {
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixStream,
+    };
+
+    use tiron_tui::{app::App, event::TuiSink};
+
+    let path = find_socket(id.as_deref())?;
+    let stream = UnixStream::connect(&path)
+        .map_err(|e| Error::new(format!("can't attach to {}: {e}", path.display())))?;
+    let mut lines = BufReader::new(stream).lines().map_while(Result::ok);
+    let runs = match lines.next().map(|line| serde_json::from_str(&line)) {
+        Some(Ok(RecordedLine::Runs(runs))) => runs,
+        _ => return Error::new("the runs finished before they could be attached to").err(),
+    };
+
+    let mut app = App::new();
+    app.runs = runs.iter().map(|run| run.to_panel()).collect();
+    let sink = TuiSink::new(app.tx.clone());
+    // the lines end when the runs finish, and the TUI is kept until it's quit
+    std::thread::spawn(move || {
+        for line in lines {
+            let Ok(RecordedLine::Event { event, .. }) = serde_json::from_str(&line) else {
+                break;
+            };
+            sink.send(event);
+        }
+    });
+    app.start().map_err(|e| Error::new(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn attach(_id: Option<String>) -> Result<(), Error> {
+    Error::new("tiron attach needs unix sockets, which this platform doesn't have").err()
+}
+
+/// The socket of the runs in progress with the id prefix, or of the only runs in progress.
+/// The sockets of the runs that were killed are removed
+#[cfg(unix)]
+fn find_socket(id: Option<&str>) -> Result<PathBuf, Error> {
+    use std::os::unix::net::UnixStream;
+
+    let dir = history_dir().ok_or_else(|| Error::new("can't find the history directory"))?;
+    let mut sockets: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    name.ends_with(".sock") && id.map_or(true, |id| name.starts_with(id))
+                })
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    sockets.retain(|path| match UnixStream::connect(path) {
+        Ok(_) => true,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::ConnectionRefused {
+                let _ = std::fs::remove_file(path);
+            }
+            false
+        }
+    });
+    sockets.sort();
+    match (id, sockets.as_slice()) {
+        (Some(id), []) => Error::new(format!("run {id} isn't in progress")).err(),
+        (None, []) => Error::new("there aren't any runs in progress to attach to").err(),
+        (Some(id), [_, _, ..]) => Error::new(format!("run {id} is ambiguous")).err(),
+        (None, [_, _, ..]) => Error::new(format!(
+            "there are {} runs in progress, attach to one of them with its id: {}",
+            sockets.len(),
+            sockets
+                .iter()
+                .filter_map(|path| path.file_stem())
+                .map(|id| id.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+        .err(),
+        (_, [path]) => Ok(path.clone()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixStream,
+    };
+
+    use tiron_common::event::RunEvent;
+
+    use super::*;
+
+    #[test]
+    fn clients_get_the_missed_lines_first() {
+        let dir = std::env::temp_dir().join(format!("tiron-attach-{}", Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = socket_path(&dir, "run");
+        let state = Arc::new(Mutex::new(AttachState {
+            lines: vec![serde_json::to_string(&RecordedLine::Runs(Vec::new())).unwrap()],
+            ..Default::default()
+        }));
+        serve(&path, state.clone()).unwrap();
+        let sink = AttachSink {
+            start: Instant::now(),
+            path: path.clone(),
+            state,
+        };
+        let id = Uuid::new_v4();
+        sink.send(Event::Run(RunEvent::RunStarted { id }));
+
+        let mut lines = BufReader::new(UnixStream::connect(&path).unwrap()).lines();
+        let mut next =
+            || -> RecordedLine { serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap() };
+        assert!(matches!(next(), RecordedLine::Runs(runs) if runs.is_empty()));
+        assert!(matches!(
+            next(),
+            RecordedLine::Event { event: Event::Run(RunEvent::RunStarted { id: started }), .. }
+                if started == id
+        ));
+
+        sink.send(Event::Run(RunEvent::RunCompleted { id, success: true }));
+        assert!(matches!(
+            next(),
+            RecordedLine::Event {
+                event: Event::Run(RunEvent::RunCompleted { success: true, .. }),
+                ..
+            }
+        ));
+
+        // the clients are disconnected when the runs are done
+        drop(sink);
+        assert!(lines.next().is_none());
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,714 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use hcl::{eval::Context, Map, Value};
+use hcl_edit::{
+    structure::{Block, BlockLabel, Structure},
+    Span,
+};
+use tiron_common::{
+    crypto::{constant_time_eq, hex, hmac_sha256},
+    error::{Error, Origin},
+    event::{EventSink, NullSink},
+    value::SpannedValue,
+};
+use uuid::Uuid;
+
+use crate::{
+    core::{execute_runs, load_runs, runbook_paths},
+    history::{now, RunRecord},
+    run::RunResult,
+    schedule::string_list,
+    web::{read_request, write_response, HttpRequest},
+};
+
+// the reactions that approve a run, in Slack names and Matrix emoji
+const APPROVE_REACTIONS: &[&str] = &["white_check_mark", "+1", "✅", "👍"];
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(600);
+// how old the requests from Slack can be in seconds, so that they can't be replayed
+const SLACK_MAX_AGE: u64 = 5 * 60;
+
+/// Something happened in the chat that the bot should handle
+enum ChatEvent {
+    Command {
+        user: String,
+        text: String,
+    },
+    Reaction {
+        user: String,
+        // the id of the message that's reacted to
+        message: String,
+        reaction: String,
+    },
+}
+
+/// The chat service the bot talks to
+trait ChatBackend: Send + Sync {
+    /// Receive the events from the chat and send them to `tx`, which blocks forever
+    fn listen(&self, tx: Sender<ChatEvent>) -> Result<()>;
+
+    /// Post a message and returns its id
+    fn post(&self, text: &str) -> Result<String>;
+}
+
+struct Bot {
+    backend: Box<dyn ChatBackend>,
+    // the users who can approve the runs that use `become`
+    approvers: Vec<String>,
+    runbooks: BTreeMap<String, Vec<PathBuf>>,
+    running: AtomicBool,
+    // the approval messages that are waiting for a reaction
+    approvals: Mutex<HashMap<String, Sender<String>>>,
+}
+
+/// Run a chat bot that starts the predefined runbooks on request
+pub fn chatops(file: String) -> Result<(), Error> {
+    let path = runbook_paths(&[file])
+        .pop()
+        .ok_or_else(|| Error::new("no chatops file"))?;
+    let cwd = path
+        .parent()
+        .ok_or_else(|| Error::new(format!("can't find parent for {}", path.to_string_lossy())))?
+        .to_path_buf();
+    let data = std::fs::read_to_string(&path).map_err(|e| {
+        Error::new(format!(
+            "can't read chatops file {} error: {e}",
+            path.to_string_lossy()
+        ))
+    })?;
+    let origin = Origin { cwd, path, data };
+
+    let body = hcl_edit::parser::parse_body(&origin.data)
+        .map_err(|e| Error::from_hcl(e, origin.path.clone()))?;
+    let mut blocks = body
+        .iter()
+        .filter_map(|s| s.as_block())
+        .filter(|b| b.ident.as_str() == "chatops");
+    let block = blocks.next().ok_or_else(|| {
+        Error::new(format!(
+            "there's no chatops in {}",
+            origin.path.to_string_lossy()
+        ))
+    })?;
+    if let Some(block) = blocks.next() {
+        return origin
+            .error("there should only be one chatops", &block.ident.span())
+            .err();
+    }
+
+    let bot = Arc::new(Bot::from_block(&origin, block)?);
+
+    // check the runbooks up front so that mistakes show up now rather than in the chat
+    for paths in bot.runbooks.values() {
+        load_runs(paths, true)?;
+    }
+
+    let (tx, rx) = crossbeam_channel::unbounded();
+    {
+        let bot = bot.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = bot.backend.listen(tx) {
+                eprintln!("chatops stopped listening: {e}");
+            }
+        });
+    }
+
+    for event in rx {
+        match event {
+            ChatEvent::Command { user, text } => bot.handle_command(user, &text),
+            ChatEvent::Reaction {
+                user,
+                message,
+                reaction,
+            } => {
+                if bot.approvers.contains(&user) && APPROVE_REACTIONS.contains(&reaction.as_str()) {
+                    if let Some(tx) = bot
+                        .approvals
+                        .lock()
+                        .ok()
+                        .and_then(|mut a| a.remove(&message))
+                    {
+                        let _ = tx.send(user);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Bot {
+    fn from_block(origin: &Origin, block: &Block) -> Result<Self, Error> {
+        let kind = match block.labels.first() {
+            Some(BlockLabel::String(s)) => s.as_str().to_string(),
+            Some(label) => {
+                return origin
+                    .error("chatops kind should be a string", &label.span())
+                    .err()
+            }
+            None => {
+                return origin
+                    .error(
+                        "chatops needs a kind, which is slack or matrix",
+                        &block.ident.span(),
+                    )
+                    .err()
+            }
+        };
+
+        // the environment variables are available as `env`,
+        // so that the tokens don't need to be in the file
+        let mut ctx = Context::new();
+        let env: Map<String, Value> = std::env::vars().map(|(k, v)| (k, Value::from(v))).collect();
+        ctx.declare_var("env", Value::Object(env));
+
+        let mut attrs = HashMap::new();
+        let mut approvers = Vec::new();
+        let mut runbooks = BTreeMap::new();
+        for structure in block.body.iter() {
+            if let Structure::Attribute(a) = structure {
+                let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+                match a.key.as_str() {
+                    "approvers" => {
+                        approvers = string_list(origin, &v, "approvers")?;
+                    }
+                    "runbooks" => {
+                        let SpannedValue::Object(o) = &v else {
+                            return origin.error("runbooks should be an object", v.span()).err();
+                        };
+                        for (name, v) in o.value() {
+                            let paths = string_list(origin, v, "runbooks")?;
+                            runbooks.insert(name.to_string(), runbook_paths(&paths));
+                        }
+                    }
+                    key => {
+                        let SpannedValue::String(s) = &v else {
+                            return origin
+                                .error(format!("{key} should be a string"), v.span())
+                                .err();
+                        };
+                        attrs.insert(key.to_string(), s.value().to_string());
+                    }
+                }
+            }
+        }
+
+        let mut attr = |name: &str| {
+            attrs
+                .remove(name)
+                .ok_or_else(|| origin.error(format!("chatops needs {name}"), &block.ident.span()))
+        };
+        let backend: Box<dyn ChatBackend> = match kind.as_str() {
+            "slack" => Box::new(Slack {
+                listen: attr("listen")?,
+                token: attr("token")?,
+                signing_secret: attr("signing_secret")?,
+                channel: attr("channel")?,
+            }),
+            "matrix" => Box::new(Matrix {
+                homeserver: attr("homeserver")?.trim_end_matches('/').to_string(),
+                token: attr("token")?,
+                room: attr("room")?,
+            }),
+            _ => {
+                return origin
+                    .error(
+                        "chatops kind should be slack or matrix",
+                        &block.labels[0].span(),
+                    )
+                    .err()
+            }
+        };
+
+        if runbooks.is_empty() {
+            return origin
+                .error("chatops doesn't have any runbooks", &block.ident.span())
+                .err();
+        }
+
+        Ok(Bot {
+            backend,
+            approvers,
+            runbooks,
+            running: AtomicBool::new(false),
+            approvals: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn post(&self, text: &str) -> Option<String> {
+        match self.backend.post(text) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                eprintln!("can't post to chat: {e}");
+                None
+            }
+        }
+    }
+
+    fn handle_command(self: &Arc<Self>, user: String, text: &str) {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["run", name] => {
+                let name = name.to_string();
+                if !self.runbooks.contains_key(&name) {
+                    self.post(&format!("there's no runbook {name}"));
+                    return;
+                }
+                if self.running.swap(true, Ordering::SeqCst) {
+                    self.post("another run is still in progress");
+                    return;
+                }
+
+                let bot = self.clone();
+                std::thread::spawn(move || {
+                    bot.run(&name, &user);
+                    bot.running.store(false, Ordering::SeqCst);
+                });
+            }
+            ["list"] => {
+                let names = self.runbooks.keys().cloned().collect::<Vec<_>>().join(", ");
+                self.post(&format!("runbooks: {names}"));
+            }
+            _ => {
+                self.post("usage: `run <runbook>` or `list`");
+            }
+        }
+    }
+
+    fn run(&self, name: &str, user: &str) {
+        let Some(paths) = self.runbooks.get(name) else {
+            return;
+        };
+
+        // there's no TUI for the chat runs, so the events are dropped
+        let events: Arc<dyn EventSink> = Arc::new(NullSink);
+        let runs = match load_runs(paths, false) {
+            Ok(runs) => runs,
+            Err(e) => {
+                self.post(&format!("can't load {name}: {}", e.message));
+                return;
+            }
+        };
+
+        // the runs that require approval and the protected hosts can't be confirmed
+        // in the chat, so they are refused before any of them starts
+        if let Some(e) = runs
+            .iter()
+            .find_map(|run| run.check_approved().and(run.check_protected()).err())
+        {
+            self.post(&format!("can't run {name}: {e}"));
+            return;
+        }
+
+        if runs.iter().any(|run| run.uses_become()) {
+            if self.approvers.is_empty() {
+                self.post(&format!(
+                    "{name} uses become, but there's no approvers to approve it"
+                ));
+                return;
+            }
+            let Some(message) = self.post(&format!(
+                "{user} wants to run {name} which uses become, an approver needs to react with ✅ in {} minutes",
+                APPROVAL_TIMEOUT.as_secs() / 60
+            )) else {
+                return;
+            };
+            let (approval_tx, approval_rx) = crossbeam_channel::bounded(1);
+            if let Ok(mut approvals) = self.approvals.lock() {
+                approvals.insert(message.clone(), approval_tx);
+            }
+            match approval_rx.recv_timeout(APPROVAL_TIMEOUT) {
+                Ok(approver) => {
+                    self.post(&format!("{name} approved by {approver}"));
+                }
+                Err(_) => {
+                    if let Ok(mut approvals) = self.approvals.lock() {
+                        approvals.remove(&message);
+                    }
+                    self.post(&format!("{name} wasn't approved in time"));
+                    return;
+                }
+            }
+        }
+
+        self.post(&format!("{user} started {name}"));
+        let mut record = RunRecord::new(paths.clone(), false);
+        let mut results = Vec::new();
+        for (i, run) in runs.into_iter().enumerate() {
+            match execute_runs(vec![run], &events) {
+                Ok(mut result) => {
+                    let Some(result) = result.pop() else {
+                        continue;
+                    };
+                    self.post(&run_summary(i, &result));
+                    let success = result.success;
+                    results.push(result);
+                    if !success {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    self.post(&format!("run {} failed: {e}", i + 1));
+                    break;
+                }
+            }
+        }
+        record.finish(results);
+        let _ = record.save();
+
+        self.post(&format!(
+            "{name} {} (run id {})",
+            if record.success {
+                "succeeded"
+            } else {
+                "failed"
+            },
+            record.id
+        ));
+    }
+}
+
+fn run_summary(i: usize, result: &RunResult) -> String {
+    let name = result
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("run {}", i + 1));
+    let failed: Vec<&str> = result
+        .hosts
+        .iter()
+        .filter(|h| !h.success)
+        .map(|h| h.host.as_str())
+        .collect();
+    let ok = result.hosts.len() - failed.len();
+    if failed.is_empty() {
+        format!("{name} succeeded on {ok} hosts")
+    } else {
+        format!(
+            "{name} failed: {ok} hosts ok, {} failed ({})",
+            failed.len(),
+            failed.join(", ")
+        )
+    }
+}
+
+/// Slack with the Events API, which sends the mentions of the bot
+/// and the reactions to the `listen` address
+struct Slack {
+    listen: String,
+    token: String,
+    // the signing secret of the app, which the requests from Slack are signed with
+    signing_secret: String,
+    channel: String,
+}
+
+impl Slack {
+    /// Check that the request is signed with the signing secret, and that it's recent
+    fn verify(&self, request: &HttpRequest, now: u64) -> bool {
+        let (Some(timestamp), Some(signature)) = (
+            request.header("X-Slack-Request-Timestamp"),
+            request.header("X-Slack-Signature"),
+        ) else {
+            return false;
+        };
+        let Ok(time) = timestamp.parse::<u64>() else {
+            return false;
+        };
+        if now.abs_diff(time) > SLACK_MAX_AGE {
+            return false;
+        }
+        let mut base = format!("v0:{timestamp}:").into_bytes();
+        base.extend_from_slice(&request.body);
+        let expected = format!(
+            "v0={}",
+            hex(&hmac_sha256(self.signing_secret.as_bytes(), &base))
+        );
+        constant_time_eq(signature.as_bytes(), expected.as_bytes())
+    }
+
+    /// Answer a request from Slack, and send its event to the bot if it has one
+    fn handle(&self, stream: TcpStream, tx: &Sender<ChatEvent>) {
+        let Ok(request) = read_request(&stream) else {
+            return;
+        };
+        if request.method != "POST" {
+            let _ = write_response(&stream, "404 Not Found", "text/plain", "not found");
+            return;
+        }
+        if !self.verify(&request, now()) {
+            let _ = write_response(&stream, "403 Forbidden", "text/plain", "forbidden");
+            return;
+        }
+        let Ok(body) = serde_json::from_slice::<serde_json::Value>(&request.body) else {
+            let _ = write_response(&stream, "400 Bad Request", "text/plain", "bad request");
+            return;
+        };
+
+        if body["type"] == "url_verification" {
+            let challenge = body["challenge"].as_str().unwrap_or_default();
+            let _ = write_response(&stream, "200 OK", "text/plain", challenge);
+            return;
+        }
+        let _ = write_response(&stream, "200 OK", "text/plain", "");
+
+        let event = &body["event"];
+        let user = event["user"].as_str().unwrap_or_default().to_string();
+        match event["type"].as_str() {
+            Some("app_mention") if event["channel"] == self.channel.as_str() => {
+                // the text starts with the mention of the bot, e.g. "<@U123> run deploy"
+                let text = event["text"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .filter(|w| !w.starts_with("<@"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let _ = tx.send(ChatEvent::Command { user, text });
+            }
+            Some("reaction_added") => {
+                let message = event["item"]["ts"].as_str().unwrap_or_default().to_string();
+                let reaction = event["reaction"].as_str().unwrap_or_default().to_string();
+                let _ = tx.send(ChatEvent::Reaction {
+                    user,
+                    message,
+                    reaction,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ChatBackend for Slack {
+    fn listen(&self, tx: Sender<ChatEvent>) -> Result<()> {
+        let listener = TcpListener::bind(&self.listen)?;
+        // each request is handled on its own thread, so a slow client
+        // doesn't hold up the ones after it
+        std::thread::scope(|scope| {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                scope.spawn(move || self.handle(stream, &tx));
+            }
+        });
+        Ok(())
+    }
+
+    fn post(&self, text: &str) -> Result<String> {
+        let body = serde_json::json!({
+            "channel": self.channel,
+            "text": text,
+        });
+        let resp = request(
+            "POST",
+            "https://slack.com/api/chat.postMessage",
+            &self.token,
+            Some(&body.to_string()),
+        )?;
+        if resp["ok"] != true {
+            return Err(anyhow!(
+                "slack error: {}",
+                resp["error"].as_str().unwrap_or("unknown")
+            ));
+        }
+        resp["ts"]
+            .as_str()
+            .map(|ts| ts.to_string())
+            .ok_or_else(|| anyhow!("slack didn't return the message ts"))
+    }
+}
+
+/// Matrix with the client-server API, which long polls `/sync` for the
+/// messages starting with `!tiron` and the reactions in the room
+struct Matrix {
+    homeserver: String,
+    token: String,
+    room: String,
+}
+
+impl ChatBackend for Matrix {
+    fn listen(&self, tx: Sender<ChatEvent>) -> Result<()> {
+        let whoami = request(
+            "GET",
+            &format!("{}/_matrix/client/v3/account/whoami", self.homeserver),
+            &self.token,
+            None,
+        )?;
+        let me = whoami["user_id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("can't get the matrix user"))?
+            .to_string();
+
+        let filter = serde_json::json!({
+            "room": { "rooms": [self.room], "timeline": { "limit": 50 } },
+        });
+        let mut since: Option<String> = None;
+        loop {
+            let mut url = format!(
+                "{}/_matrix/client/v3/sync?timeout=30000&filter={}",
+                self.homeserver,
+                url_encode(&filter.to_string())
+            );
+            if let Some(since) = &since {
+                url.push_str(&format!("&since={}", url_encode(since)));
+            }
+            let resp = match request("GET", &url, &self.token, None) {
+                Ok(resp) => resp,
+                Err(e) => {
+                    eprintln!("matrix sync failed: {e}");
+                    std::thread::sleep(Duration::from_secs(5));
+                    continue;
+                }
+            };
+
+            // the first sync only gets where we are,
+            // so that the old messages aren't handled again
+            if since.is_some() {
+                let events = resp["rooms"]["join"][&self.room]["timeline"]["events"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                for event in events {
+                    let user = event["sender"].as_str().unwrap_or_default().to_string();
+                    if user == me {
+                        continue;
+                    }
+                    let content = &event["content"];
+                    match event["type"].as_str() {
+                        Some("m.room.message") => {
+                            let body = content["body"].as_str().unwrap_or_default();
+                            if let Some(text) = body.strip_prefix("!tiron") {
+                                let text = text.trim().to_string();
+                                let _ = tx.send(ChatEvent::Command { user, text });
+                            }
+                        }
+                        Some("m.reaction") => {
+                            let relates_to = &content["m.relates_to"];
+                            let message = relates_to["event_id"]
+                                .as_str()
+                                .unwrap_or_default()
+                                .to_string();
+                            let reaction = relates_to["key"].as_str().unwrap_or_default();
+                            // strip the variation selector that some clients add to the emoji
+                            let reaction = reaction.trim_end_matches('\u{fe0f}').to_string();
+                            let _ = tx.send(ChatEvent::Reaction {
+                                user,
+                                message,
+                                reaction,
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            since = resp["next_batch"].as_str().map(|s| s.to_string());
+        }
+    }
+
+    fn post(&self, text: &str) -> Result<String> {
+        let body = serde_json::json!({
+            "msgtype": "m.text",
+            "body": text,
+        });
+        let resp = request(
+            "PUT",
+            &format!(
+                "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+                self.homeserver,
+                url_encode(&self.room),
+                Uuid::new_v4()
+            ),
+            &self.token,
+            Some(&body.to_string()),
+        )?;
+        resp["event_id"]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| {
+                anyhow!(
+                    "matrix error: {}",
+                    resp["error"].as_str().unwrap_or("unknown")
+                )
+            })
+    }
+}
+
+/// Send a request with the token and parse the response as JSON
+fn request(method: &str, url: &str, token: &str, body: Option<&str>) -> Result<serde_json::Value> {
+    let req = ureq::request(method, url).set("Authorization", &format!("Bearer {token}"));
+    let resp = match body {
+        Some(body) => req
+            .set("Content-Type", "application/json")
+            .send_string(body),
+        None => req.call(),
+    };
+    let resp = match resp {
+        Ok(resp) => resp,
+        // the errors of the APIs are in the JSON of the response
+        Err(ureq::Error::Status(_, resp)) => resp,
+        Err(e) => return Err(anyhow!("request failed: {e}")),
+    };
+    Ok(serde_json::from_reader(resp.into_reader())?)
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verify_slack_signature() {
+        let slack = Slack {
+            listen: "127.0.0.1:0".to_string(),
+            token: "xoxb".to_string(),
+            signing_secret: "secret".to_string(),
+            channel: "C0123456789".to_string(),
+        };
+        let body = br#"{"type":"event_callback"}"#.to_vec();
+        let signature = format!(
+            "v0={}",
+            hex(&hmac_sha256(
+                b"secret",
+                &[b"v0:1000:".as_slice(), &body].concat()
+            ))
+        );
+        let request = |timestamp: &str, signature: &str| HttpRequest {
+            method: "POST".to_string(),
+            path: "/".to_string(),
+            headers: vec![
+                (
+                    "X-Slack-Request-Timestamp".to_string(),
+                    timestamp.to_string(),
+                ),
+                ("X-Slack-Signature".to_string(), signature.to_string()),
+            ],
+            body: body.clone(),
+        };
+        assert!(slack.verify(&request("1000", &signature), 1010));
+        // replayed after the max age
+        assert!(!slack.verify(&request("1000", &signature), 1000 + SLACK_MAX_AGE + 1));
+        // signed for another timestamp
+        assert!(!slack.verify(&request("1001", &signature), 1010));
+        assert!(!slack.verify(&request("1000", "v0=00"), 1010));
+    }
+}
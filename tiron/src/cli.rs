@@ -7,6 +7,27 @@
 pub struct Cli {
     #[command(subcommand)]
     pub cmd: CliCmd,
+    /// Write the logs of tiron, e.g. the connections to the hosts and the messages
+    /// with the nodes, to a file at the level, which can be error, warn, info, debug
+    /// or trace. It's for finding out why a run hangs or fails.
+    #[arg(long, global = true)]
+    pub log_level: Option<String>,
+    /// The file to write the logs to, which is rotated when it's larger than 10MB.
+    /// Default to logs/tiron.log in the data directory of tiron
+    #[arg(long, global = true)]
+    pub log_file: Option<String>,
+    /// Start the node on the hosts without checking its version or installing it,
+    /// which saves a round trip to every host when it's known to be installed
+    #[arg(long, global = true, conflicts_with = "reinstall_node")]
+    pub skip_node_install: bool,
+    /// Install the node on the hosts again even if they have the same version,
+    /// e.g. when the node binary on a host is corrupted
+    #[arg(long, global = true)]
+    pub reinstall_node: bool,
+    /// Ask for the password of sudo before the runs start, for the hosts with `become`
+    /// where sudo needs a password. It can also be given with TIRON_BECOME_PASSWORD.
+    #[arg(long, global = true)]
+    pub ask_become_pass: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -17,6 +38,129 @@ pub enum CliCmd {
         ///
         /// Default to main.tr if unspecified
         runbooks: Vec<String>,
+        /// Only check what the runbooks would change without changing anything.
+        ///
+        /// A summary of the pending changes is printed as JSON,
+        /// and Tiron exits with 2 if there are any.
+        #[arg(long)]
+        dry_run: bool,
+        /// Serve a web dashboard of the runs on the port, e.g. 8080, which is on
+        /// 127.0.0.1, or on the address, e.g. 0.0.0.0:8080, so that the runs can be
+        /// watched from a browser. It needs the token in the printed url,
+        /// or the one in TIRON_WEB_TOKEN.
+        #[arg(long)]
+        web: Option<String>,
+        /// Run every host in an ephemeral container instead, in the form of
+        /// <engine>:<image>, e.g. docker:ubuntu:22.04
+        #[arg(long)]
+        simulate: Option<String>,
+        /// Write the events of the runs to the file as JSON, one event per line
+        #[arg(long)]
+        event_log: Option<String>,
+        /// Take over the locks of the hosts from another run,
+        /// e.g. when a run was killed before it could release them
+        #[arg(long)]
+        force_unlock: bool,
+        /// Skip the actions whose inputs are the same as the last time they succeeded
+        /// on the host, which makes rerunning the runbooks quick when only some of
+        /// them changed. The states of the hosts aren't checked, so what was changed
+        /// on the hosts since then isn't put back by the skipped actions
+        #[arg(long)]
+        skip_unchanged: bool,
+        /// Resume from where the last execution of the runbooks failed. The runs that
+        /// succeeded are left out, and the hosts start from the actions they failed at
+        #[arg(long)]
+        resume: bool,
+        /// Execute the runbooks at the same time instead of one after another, each
+        /// with its own runs in the TUI. The runs in a runbook are still executed one
+        /// after another, and the runbooks can't share any host
+        #[arg(long, conflicts_with = "resume")]
+        concurrent: bool,
+        /// Run the runbooks even if it's outside of the maintenance windows of the runs
+        #[arg(long)]
+        override_window: bool,
+        /// The token from `tiron approve` for the runs that require approval,
+        /// when there's no terminal to confirm them. It can also be given with
+        /// TIRON_APPROVAL_TOKEN.
+        #[arg(long)]
+        approval_token: Option<String>,
+        /// Run on the hosts of the protected groups without confirming them,
+        /// e.g. when there's no terminal
+        #[arg(long)]
+        yes_i_mean_prod: bool,
+        /// Export the traces of the runs, with a span for every run, host and action,
+        /// to the OTLP/HTTP endpoint, e.g. http://localhost:4318 for Jaeger.
+        /// It can also be given with OTEL_EXPORTER_OTLP_ENDPOINT.
+        #[arg(long)]
+        otlp_endpoint: Option<String>,
+    },
+    /// Run one action on the hosts of a group without writing a runbook for it,
+    /// e.g. `tiron exec -g web -a command cmd=uptime`
+    Exec {
+        /// The group or the host to run the action on
+        #[arg(short, long)]
+        group: String,
+        /// The name of the action
+        #[arg(short, long)]
+        action: String,
+        /// The params of the action in the form of key=value, e.g. cmd=uptime.
+        /// The value is a string unless it's an HCL literal, e.g. 30, true or ["-p"]
+        params: Vec<String>,
+        /// The runbook with the groups and the inventory.
+        ///
+        /// Default to main.tr if it exists
+        #[arg(long)]
+        runbook: Option<String>,
+        /// Run on the hosts of the protected groups without confirming them,
+        /// e.g. when there's no terminal
+        #[arg(long)]
+        yes_i_mean_prod: bool,
+    },
+    /// Open an interactive shell on a host, which connects the same way as the runs,
+    /// e.g. with the user, the port, the jump host in the ssh options and become
+    Shell {
+        /// The host, which is from the groups and the inventory of the runbook
+        host: String,
+        /// The runbook with the groups and the inventory.
+        ///
+        /// Default to main.tr if it exists
+        #[arg(long)]
+        runbook: Option<String>,
+    },
+    /// Check the runs in Tiron runbooks that require approval, and print their pending
+    /// changes with an approval token signed with TIRON_APPROVAL_KEY
+    Approve {
+        /// The runbooks to approve.
+        ///
+        /// Default to main.tr if unspecified
+        runbooks: Vec<String>,
+    },
+    /// Show the plan of Tiron runbooks, which is the actions with their params
+    /// in the order they run on every host, without connecting to the hosts
+    Plan {
+        /// The runbooks to plan.
+        ///
+        /// Default to main.tr if unspecified
+        runbooks: Vec<String>,
+    },
+    /// Snapshot the states of the resources that Tiron runbooks manage on every host,
+    /// e.g. the files written by `copy`, the packages and the services, into the
+    /// manifest of the host
+    Fingerprint {
+        /// The runbooks with the resources.
+        ///
+        /// Default to main.tr if unspecified
+        runbooks: Vec<String>,
+    },
+    /// Compare the live states of the resources on the hosts of Tiron runbooks with
+    /// the manifests from `fingerprint`, without running the runbooks.
+    ///
+    /// Tiron exits with 2 if any of the hosts drifted.
+    Drift {
+        /// The runbooks with the hosts to check.
+        ///
+        /// Default to main.tr if unspecified
+        runbooks: Vec<String>,
     },
     /// Check Tiron runbooks
     Check {
@@ -25,6 +169,76 @@ pub enum CliCmd {
         /// Default to main.tr if unspecified
         runbooks: Vec<String>,
     },
+    /// Test Tiron runbooks in disposable containers
+    Test {
+        /// The test file.
+        ///
+        /// Default to test.tr if unspecified
+        file: Option<String>,
+    },
+    /// Run Tiron runbooks on the schedules defined in a schedule file
+    Schedule {
+        /// The schedule file.
+        ///
+        /// Default to schedule.tr if unspecified
+        file: Option<String>,
+    },
+    /// Run a chat bot that starts the runbooks defined in a chatops file on request
+    Chatops {
+        /// The chatops file.
+        ///
+        /// Default to chatops.tr if unspecified
+        file: Option<String>,
+    },
+    /// Compare two runs of the same runbooks from the run history, which shows
+    /// the per-host status changes, the changed actions, and the regressions
+    DiffRuns {
+        /// The ids of the old and the new run, which can be prefixes.
+        ///
+        /// Default to the latest run and the previous run of the same runbooks
+        runs: Vec<String>,
+    },
+    /// Replay a run from the run history in the TUI as it ran, e.g. for a demo
+    /// or a postmortem
+    Replay {
+        /// The id of the run, which can be a prefix.
+        ///
+        /// Default to the latest run
+        run: Option<String>,
+        /// How many times faster than the run it's replayed, e.g. 10
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Mirror a run that's in progress in a read-only TUI, e.g. from a second terminal,
+    /// which doesn't disturb the run
+    Attach {
+        /// The id of the run, which can be a prefix.
+        ///
+        /// Default to the only run in progress
+        run: Option<String>,
+    },
+    /// Convert an Ansible playbook into a Tiron runbook, with TODO comments
+    /// for what can't be converted
+    ImportPlaybook {
+        /// The Ansible playbook, e.g. site.yml
+        playbook: String,
+        /// The runbook to write to.
+        ///
+        /// Default to the playbook with the .tr extension
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Manage the node on this machine, which runs the actions for the localhost
+    Node {
+        #[command(subcommand)]
+        cmd: NodeCmd,
+    },
+    /// Encrypt or decrypt Tiron runbooks, so that the sensitive ones can be kept
+    /// in shared repositories
+    Vault {
+        #[command(subcommand)]
+        cmd: VaultCmd,
+    },
     /// Format Tiron runbooks
     Fmt {
         /// If unspecified, Tiron will scan the current directory for *.tr files.
@@ -39,6 +253,41 @@ pub enum CliCmd {
         /// name of the action
         name: Option<String>,
     },
+    /// Explain an error code, e.g. E0203, or list all the error codes
+    Explain {
+        /// the error code
+        code: Option<String>,
+    },
     #[clap(hide = true)]
     GenerateDoc,
 }
+
+#[derive(Debug, Subcommand)]
+pub enum NodeCmd {
+    /// Show the artifacts in the cache of the node, e.g. the content of the copied files,
+    /// which are reused by their hash instead of being sent again.
+    /// On the other hosts, it's `tiron-node-<version> cache` in the data directory of tiron.
+    Cache {
+        /// Remove all the artifacts from the cache
+        #[arg(long)]
+        clear: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VaultCmd {
+    /// Encrypt the runbooks in place with the password in TIRON_VAULT_PASSWORD,
+    /// or TIRON_VAULT_PASSWORD_<ID> for another vault id
+    Encrypt {
+        /// The runbooks to encrypt
+        runbooks: Vec<String>,
+        /// The vault id, which picks the password to encrypt with
+        #[arg(long, default_value = "default")]
+        vault_id: String,
+    },
+    /// Decrypt the runbooks in place
+    Decrypt {
+        /// The runbooks to decrypt
+        runbooks: Vec<String>,
+    },
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use hcl::eval::Context;
+use hcl_edit::structure::{Block, Structure};
+use tiron_common::{
+    error::{Error, Origin},
+    value::SpannedValue,
+};
+
+use crate::hook::Hooks;
+
+/// The settings of the project in `tiron.hcl` in the current directory,
+/// which are all optional
+#[derive(Default)]
+pub struct ProjectConfig {
+    pub ssh: SshConfig,
+    // the hooks before and after all the runs of the command
+    pub hooks: Hooks,
+}
+
+#[derive(Default)]
+pub struct SshConfig {
+    // the directory of the ssh control sockets, default to /tmp/tiron-<user>
+    pub control_dir: Option<PathBuf>,
+    pub control_scope: ControlScope,
+}
+
+/// Which connections share the ssh control sockets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlScope {
+    /// The runs in the same project share the connections, which are kept for 30 minutes
+    #[default]
+    Project,
+    /// Every run has its own connections, which are closed when it finishes
+    Run,
+}
+
+impl ProjectConfig {
+    pub fn load() -> Result<Self, Error> {
+        let cwd = std::env::current_dir()
+            .map_err(|e| Error::new(format!("can't find the current directory: {e}")))?;
+        let path = cwd.join("tiron.hcl");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(&path).map_err(|e| {
+            Error::new(format!(
+                "can't read project config {} error: {e}",
+                path.to_string_lossy()
+            ))
+        })?;
+        let origin = Origin { cwd, path, data };
+        Self::parse(&origin)
+    }
+
+    fn parse(origin: &Origin) -> Result<Self, Error> {
+        let body = hcl_edit::parser::parse_body(&origin.data)
+            .map_err(|e| Error::from_hcl(e, origin.path.clone()))?;
+        let mut config = Self::default();
+        for structure in body.iter() {
+            if let Structure::Block(block) = structure {
+                if block.ident.as_str() == "ssh" {
+                    config.ssh = SshConfig::from_block(origin, block)?;
+                } else {
+                    config.hooks.add_block(origin, block)?;
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl SshConfig {
+    fn from_block(origin: &Origin, block: &Block) -> Result<Self, Error> {
+        let mut config = Self::default();
+        let ctx = Context::new();
+        for structure in block.body.iter() {
+            if let Structure::Attribute(a) = structure {
+                let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+                let SpannedValue::String(s) = &v else {
+                    return origin
+                        .error(format!("{} should be a string", a.key.as_str()), v.span())
+                        .err();
+                };
+                match a.key.as_str() {
+                    "control_dir" => {
+                        config.control_dir = Some(origin.cwd.join(s.value()));
+                    }
+                    "control_scope" => {
+                        config.control_scope = match s.value().as_str() {
+                            "project" => ControlScope::Project,
+                            "run" => ControlScope::Run,
+                            _ => {
+                                return origin
+                                    .error("control_scope should be project or run", v.span())
+                                    .err()
+                            }
+                        };
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(data: &str) -> Result<ProjectConfig, Error> {
+        ProjectConfig::parse(&Origin {
+            cwd: PathBuf::from("/srv/project"),
+            path: PathBuf::from("/srv/project/tiron.hcl"),
+            data: data.to_string(),
+        })
+    }
+
+    #[test]
+    fn parse_ssh() {
+        let config = parse(
+            r#"
+                ssh {
+                  control_dir   = "sockets"
+                  control_scope = "run"
+                }
+            "#,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            config.ssh.control_dir,
+            Some(PathBuf::from("/srv/project/sockets"))
+        );
+        assert_eq!(config.ssh.control_scope, ControlScope::Run);
+
+        let config = parse("").ok().unwrap();
+        assert_eq!(config.ssh.control_dir, None);
+        assert_eq!(config.ssh.control_scope, ControlScope::Project);
+
+        let e = parse(
+            r#"
+                ssh {
+                  control_scope = "host"
+                }
+            "#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(e.message, "control_scope should be project or run");
+    }
+
+    #[test]
+    fn parse_hooks() {
+        let config = parse(
+            r#"
+                pre_run {
+                  command = "./notify.sh started"
+                }
+                post_run {
+                  command = ["./notify.sh", "finished"]
+                }
+            "#,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(config.hooks.pre_run.len(), 1);
+        assert_eq!(config.hooks.post_run.len(), 1);
+    }
+}
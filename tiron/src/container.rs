@@ -0,0 +1,91 @@
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+/// A container that a host runs in instead of being connected to with ssh
+#[derive(Clone)]
+pub struct ContainerHost {
+    // the container engine, which is docker or podman
+    pub engine: String,
+    pub name: String,
+}
+
+impl ContainerHost {
+    /// Start a disposable container from the image, which is removed
+    /// when the returned Container is dropped
+    pub fn start(engine: &str, image: &str) -> Result<Container> {
+        if engine != "docker" && engine != "podman" {
+            return Err(anyhow!(
+                "unknown container engine {engine}, it should be docker or podman"
+            ));
+        }
+
+        let name = format!("tiron-{}", Uuid::new_v4());
+        // the container only needs to stay alive for us to exec the node in it
+        let output = Command::new(engine)
+            .args(["run", "-d", "--rm", "--name", &name, image])
+            .args(["tail", "-f", "/dev/null"])
+            .output()
+            .map_err(|e| anyhow!("can't run {engine}: {e}"))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "can't start container from {image}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(Container {
+            host: ContainerHost {
+                engine: engine.to_string(),
+                name,
+            },
+        })
+    }
+
+    /// The command to run a shell command line in the container.
+    ///
+    /// The arguments appended to it are joined with spaces and evaluated by the shell,
+    /// which is the same as how ssh runs the remote command, so the commands
+    /// for bootstrapping the node work in both.
+    pub fn command_builder(&self) -> Command {
+        let mut cmd = Command::new(&self.engine);
+        cmd.args(["exec", "-i", &self.name, "sh", "-c", "eval \"$*\"", "sh"]);
+        cmd
+    }
+
+    /// The command of an interactive shell in the container
+    pub fn shell_command(&self) -> Vec<String> {
+        [
+            self.engine.as_str(),
+            "exec",
+            "-it",
+            self.name.as_str(),
+            "sh",
+        ]
+        .map(|s| s.to_string())
+        .to_vec()
+    }
+}
+
+/// A running disposable container
+pub struct Container {
+    pub host: ContainerHost,
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        let _ = Command::new(&self.host.engine)
+            .args(["rm", "-f", &self.host.name])
+            .output();
+    }
+}
+
+/// Parse the container spec in the form of `<engine>:<image>`, e.g. `docker:ubuntu:22.04`
+pub fn parse_container_spec(spec: &str) -> Result<(String, String)> {
+    let (engine, image) = spec
+        .split_once(':')
+        .filter(|(_, image)| !image.is_empty())
+        .ok_or_else(|| anyhow!("{spec} should be in the form of <engine>:<image>"))?;
+    Ok((engine.to_string(), image.to_string()))
+}
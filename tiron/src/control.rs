@@ -0,0 +1,229 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use tiron_common::{
+    crypto::{hex, sha256},
+    error::Error,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::{ControlScope, SshConfig},
+    log,
+};
+
+/// The ssh control sockets are at most this long, because ssh adds a suffix of
+/// 17 characters to them while they're created, and unix sockets can only be
+/// 104 characters on macOS
+const MAX_SOCKET_LEN: usize = 104 - 17;
+
+static CONTROL_DIR: OnceLock<Option<ControlDir>> = OnceLock::new();
+
+/// The directory of the ssh control sockets, which is only for the project or the run,
+/// so that tiron running concurrently under the same account doesn't share them
+struct ControlDir {
+    path: PathBuf,
+    scope: ControlScope,
+}
+
+/// Closes the connections of the run when it's dropped, if the connections are
+/// only for the run
+pub struct ControlGuard;
+
+impl Drop for ControlGuard {
+    fn drop(&mut self) {
+        if let Some(Some(dir)) = CONTROL_DIR.get() {
+            if dir.scope == ControlScope::Run {
+                dir.close();
+            }
+        }
+    }
+}
+
+/// Set up the directory of the ssh control sockets from the project config,
+/// with the stale sockets removed from it
+pub fn init(config: &SshConfig) -> Result<ControlGuard, Error> {
+    // ssh on Windows doesn't have the control sockets
+    if cfg!(windows) {
+        return Ok(ControlGuard);
+    }
+    let dir = ControlDir::new(config)?;
+    log::debug(
+        "ssh",
+        "the control directory of the connections",
+        &[("path", &dir.path.display())],
+    );
+    // it's already set up if any connection was made before
+    let _ = CONTROL_DIR.set(Some(dir));
+    Ok(ControlGuard)
+}
+
+/// The ControlPath for ssh, or None if the connections aren't shared
+pub fn control_path() -> Option<String> {
+    let dir = CONTROL_DIR
+        .get_or_init(|| ControlDir::new(&SshConfig::default()).ok())
+        .as_ref()?;
+    Some(format!("{}/%C", dir.path.to_string_lossy()))
+}
+
+impl ControlDir {
+    fn new(config: &SshConfig) -> Result<Self, Error> {
+        let base = config.control_dir.clone().unwrap_or_else(|| {
+            let user = std::env::var("USER").unwrap_or_else(|_| "tiron".to_string());
+            PathBuf::from("/tmp").join(format!("tiron-{user}"))
+        });
+        let project = std::env::current_dir().unwrap_or_default();
+        let run = Uuid::new_v4().simple().to_string();
+        let path = socket_dir(&base, config.control_scope, &project, &run);
+        // %C is 40 characters
+        if path.to_string_lossy().len() + 41 > MAX_SOCKET_LEN {
+            return Error::new(format!(
+                "the ssh control directory {} is too long for the sockets, set a shorter control_dir",
+                path.to_string_lossy()
+            ))
+            .err();
+        }
+        create_private_dir(&path).map_err(|e| {
+            Error::new(format!(
+                "can't create the ssh control directory {}: {e}",
+                path.to_string_lossy()
+            ))
+        })?;
+        remove_stale_sockets(&path);
+        Ok(Self {
+            path,
+            scope: config.control_scope,
+        })
+    }
+
+    /// Close the connections with the sockets, and remove the directory
+    fn close(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.path) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let _ = Command::new("ssh")
+                .arg("-o")
+                .arg(format!("ControlPath={}", entry.path().to_string_lossy()))
+                .args(["-O", "exit", "tiron"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The directory of the sockets in base, which is named after the hash of
+/// the project directory, or the id of the run
+fn socket_dir(base: &Path, scope: ControlScope, project: &Path, run: &str) -> PathBuf {
+    let name = match scope {
+        ControlScope::Project => {
+            let hash = hex(&sha256(project.to_string_lossy().as_bytes()));
+            hash[..12].to_string()
+        }
+        ControlScope::Run => run.chars().take(12).collect(),
+    };
+    base.join(name)
+}
+
+/// Create the directory that only the user can access, because anyone who can
+/// open the sockets can use the connections
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700))?;
+    }
+    #[cfg(not(unix))]
+    std::fs::create_dir_all(path)?;
+    Ok(())
+}
+
+/// Remove the sockets that no ssh is listening on, e.g. ssh was killed,
+/// which ssh would fail to connect with
+fn remove_stale_sockets(dir: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let is_socket = entry.file_type().map(|t| t.is_socket()).unwrap_or(false);
+            if !is_socket {
+                continue;
+            }
+            if let Err(e) = UnixStream::connect(entry.path()) {
+                if e.kind() == std::io::ErrorKind::ConnectionRefused {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn socket_dir_of_scope() {
+        let base = Path::new("/tmp/tiron-deploy");
+        let project = socket_dir(
+            base,
+            ControlScope::Project,
+            Path::new("/srv/app"),
+            "0123456789abcdef",
+        );
+        assert_eq!(project.parent(), Some(base));
+        assert_eq!(project.file_name().unwrap().len(), 12);
+        // the same project has the same directory in every run
+        assert_eq!(
+            project,
+            socket_dir(base, ControlScope::Project, Path::new("/srv/app"), "other")
+        );
+        assert_ne!(
+            project,
+            socket_dir(base, ControlScope::Project, Path::new("/srv/web"), "other")
+        );
+        assert_eq!(
+            socket_dir(
+                base,
+                ControlScope::Run,
+                Path::new("/srv/app"),
+                "0123456789abcdef"
+            ),
+            base.join("0123456789ab")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remove_stale() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = std::env::temp_dir().join(format!("tiron-control-{}", Uuid::new_v4().simple()));
+        create_private_dir(&dir).unwrap();
+        let live = dir.join("live");
+        let _listener = UnixListener::bind(&live).unwrap();
+        let stale = dir.join("stale");
+        drop(UnixListener::bind(&stale).unwrap());
+
+        remove_stale_sockets(&dir);
+        assert!(live.exists());
+        assert!(!stale.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,31 +1,136 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use itertools::Itertools;
 
-use tiron_common::error::Error;
-use tiron_node::action::data::all_actions;
-use tiron_tui::event::{AppEvent, RunEvent};
+use tiron_common::{
+    error::Error,
+    error_code::{error_code, ERROR_CODES},
+    event::{Event, EventSink, MultiSink, RunEvent},
+};
+use tiron_node::{action::data::all_actions, cache};
+use tiron_tui::event::{TuiDebugger, TuiSink};
 
 use crate::{
-    cli::{Cli, CliCmd},
+    approval::{approve, confirm_protected, plan_summary, sign, APPROVAL_VALIDITY},
+    attach::{attach, AttachSink},
+    chatops::chatops,
+    cli::{Cli, CliCmd, NodeCmd, VaultCmd},
+    config::ProjectConfig,
+    container::parse_container_spec,
+    control,
+    diff::diff_runs,
     doc::generate_doc,
+    drift::{drift, fingerprint},
+    exec::exec,
     fmt::fmt,
-    run::Run,
+    history::{last_record, RunRecord},
+    hook::{start_env, summary_env, Hooks},
+    import::import_playbook,
+    log::{self, LogLevel},
+    node::ActionStatus,
+    plan::plan,
+    remote::{set_become_password, set_node_install, NodeInstall},
+    replay::{replay, RecordingSink},
+    run::{Run, RunResult},
     runbook::Runbook,
+    schedule::schedule,
+    shell::shell,
+    sink::{JsonSink, LogSink},
+    testing::test,
+    trace::TraceSink,
+    vault::{decrypt_runbooks, encrypt_runbooks},
+    web,
 };
 
-pub fn cmd() -> Result<(), Error> {
+/// The exit code of a dry run that found changes, or of drift
+const CHANGED: u8 = 2;
+
+/// Run the command, and returns the exit code of tiron. The exit code is returned
+/// instead of exiting here, so that the connections and the locks are dropped first
+pub fn cmd() -> Result<ExitCode, Error> {
     let cli = Cli::parse();
+    if cli.log_level.is_some() || cli.log_file.is_some() {
+        let level = match &cli.log_level {
+            Some(level) => level.parse().map_err(Error::new)?,
+            None => LogLevel::Info,
+        };
+        log::init(level, cli.log_file.as_ref().map(PathBuf::from))?;
+    }
+    if cli.skip_node_install {
+        set_node_install(NodeInstall::Skip);
+    } else if cli.reinstall_node {
+        set_node_install(NodeInstall::Reinstall);
+    }
+    if cli.ask_become_pass {
+        set_become_password(ask_password("BECOME password: ")?);
+    } else if let Ok(password) = std::env::var("TIRON_BECOME_PASSWORD") {
+        set_become_password(password);
+    }
+    let config = ProjectConfig::load()?;
+    // the connections of the run are closed when the command finishes
+    let _control = control::init(&config.ssh)?;
     match cli.cmd {
-        CliCmd::Run { runbooks } => {
+        CliCmd::Run {
+            runbooks,
+            dry_run,
+            web,
+            simulate,
+            event_log,
+            force_unlock,
+            skip_unchanged,
+            resume,
+            concurrent,
+            override_window,
+            approval_token,
+            yes_i_mean_prod,
+            otlp_endpoint,
+        } => {
+            let runbooks = if runbooks.is_empty() {
+                vec!["main".to_string()]
+            } else {
+                runbooks
+            };
+            let outcome = run(
+                runbooks,
+                RunOptions {
+                    check: false,
+                    dry_run,
+                    web,
+                    simulate,
+                    event_log,
+                    force_unlock,
+                    skip_unchanged,
+                    resume,
+                    concurrent,
+                    override_window,
+                    approval_token: approval_token
+                        .or_else(|| std::env::var("TIRON_APPROVAL_TOKEN").ok()),
+                    yes_i_mean_prod,
+                    otlp_endpoint: otlp_endpoint
+                        .or_else(|| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()),
+                    hooks: config.hooks.clone(),
+                },
+            )?;
+            if outcome.changed {
+                return Ok(ExitCode::from(CHANGED));
+            }
+        }
+        CliCmd::Plan { runbooks } => {
             let runbooks = if runbooks.is_empty() {
                 vec!["main".to_string()]
             } else {
                 runbooks
             };
-            run(runbooks, false)?;
+            plan(runbooks)?;
         }
         CliCmd::Check { runbooks } => {
             let runbooks = if runbooks.is_empty() {
@@ -33,26 +138,355 @@ pub fn cmd() -> Result<(), Error> {
             } else {
                 runbooks
             };
-            let runbooks = run(runbooks, true)?;
+            let outcome = run(
+                runbooks,
+                RunOptions {
+                    check: true,
+                    ..Default::default()
+                },
+            )?;
             println!("successfully checked");
-            for runbook in runbooks {
+            for runbook in outcome.runbooks {
                 println!("{}", runbook.to_string_lossy());
             }
         }
+        CliCmd::Exec {
+            group,
+            action,
+            params,
+            runbook,
+            yes_i_mean_prod,
+        } => {
+            exec(runbook, group, action, params, yes_i_mean_prod)?;
+        }
+        CliCmd::Shell { host, runbook } => {
+            return shell(runbook, host);
+        }
+        CliCmd::Approve { runbooks } => {
+            let runbooks = if runbooks.is_empty() {
+                vec!["main".to_string()]
+            } else {
+                runbooks
+            };
+            let runs = load_runs(&runbook_paths(&runbooks), false)?;
+            let Some(summary) = plan_summary(&runs)? else {
+                println!("none of the runs require approval");
+                return Ok(ExitCode::SUCCESS);
+            };
+            print!("{summary}");
+            println!(
+                "\napproval token, valid for {} hours: {}",
+                APPROVAL_VALIDITY / 3600,
+                sign(&summary)?
+            );
+        }
+        CliCmd::Test { file } => {
+            test(file.unwrap_or_else(|| "test".to_string()))?;
+        }
+        CliCmd::Schedule { file } => {
+            schedule(file.unwrap_or_else(|| "schedule".to_string()))?;
+        }
+        CliCmd::Chatops { file } => {
+            chatops(file.unwrap_or_else(|| "chatops".to_string()))?;
+        }
+        CliCmd::Fingerprint { runbooks } => {
+            let runbooks = if runbooks.is_empty() {
+                vec!["main".to_string()]
+            } else {
+                runbooks
+            };
+            fingerprint(runbooks)?;
+        }
+        CliCmd::Drift { runbooks } => {
+            let runbooks = if runbooks.is_empty() {
+                vec!["main".to_string()]
+            } else {
+                runbooks
+            };
+            if drift(runbooks)? {
+                return Ok(ExitCode::from(CHANGED));
+            }
+        }
+        CliCmd::DiffRuns { runs } => {
+            diff_runs(runs)?;
+        }
+        CliCmd::Replay { run, speed } => {
+            replay(run, speed)?;
+        }
+        CliCmd::Attach { run } => {
+            attach(run)?;
+        }
+        CliCmd::ImportPlaybook { playbook, output } => {
+            import_playbook(playbook, output)?;
+        }
+        CliCmd::Node { cmd } => match cmd {
+            NodeCmd::Cache { clear } => {
+                cache::cache_cmd(clear).map_err(|e| Error::new(e.to_string()))?
+            }
+        },
+        CliCmd::Vault { cmd } => match cmd {
+            VaultCmd::Encrypt { runbooks, vault_id } => encrypt_runbooks(runbooks, vault_id)?,
+            VaultCmd::Decrypt { runbooks } => decrypt_runbooks(runbooks)?,
+        },
         CliCmd::Fmt { targets } => {
             fmt(targets)?;
         }
         CliCmd::Action { name } => action_doc(name),
+        CliCmd::Explain { code } => explain(code)?,
         CliCmd::GenerateDoc => {
             generate_doc().map_err(|e| Error::new(e.to_string()))?;
         }
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-pub fn run(runbooks: Vec<String>, check: bool) -> Result<Vec<PathBuf>, Error> {
+/// The options of [`run`], which mostly come from the flags of `tiron run`
+#[derive(Default)]
+pub struct RunOptions {
+    /// Only parse the runbooks and check them, without running them
+    pub check: bool,
+    pub dry_run: bool,
+    /// The address of the web dashboard
+    pub web: Option<String>,
+    /// The container engine and image that stand in for the hosts
+    pub simulate: Option<String>,
+    /// The file the events are written to as JSON lines
+    pub event_log: Option<String>,
+    pub force_unlock: bool,
+    pub skip_unchanged: bool,
+    /// Resume from where the last execution of the runbooks failed
+    pub resume: bool,
+    /// Execute the runbooks at the same time
+    pub concurrent: bool,
+    pub override_window: bool,
+    pub approval_token: Option<String>,
+    pub yes_i_mean_prod: bool,
+    pub otlp_endpoint: Option<String>,
+    pub hooks: Hooks,
+}
+
+/// What the runs did, which decides the exit code
+pub struct RunOutcome {
+    /// The paths of the runbooks that were run
+    pub runbooks: Vec<PathBuf>,
+    /// Whether the dry run found anything to change
+    pub changed: bool,
+}
+
+pub fn run(runbooks: Vec<String>, options: RunOptions) -> Result<RunOutcome, Error> {
+    let RunOptions {
+        check,
+        dry_run,
+        web,
+        simulate,
+        event_log,
+        force_unlock,
+        skip_unchanged,
+        resume,
+        concurrent,
+        override_window,
+        approval_token,
+        yes_i_mean_prod,
+        otlp_endpoint,
+        hooks,
+    } = options;
     let mut app = tiron_tui::app::App::new();
-    let runbooks: Vec<PathBuf> = runbooks
+    let runbooks = runbook_paths(&runbooks);
+    let mut runs = load_runs(&runbooks, check || dry_run)?;
+
+    if !check {
+        // the results of the runs that succeeded the last time, which go into the record
+        let previous = if resume {
+            resume_runs(&runbooks, &mut runs)?
+        } else {
+            Vec::new()
+        };
+        if concurrent {
+            check_independent(&runs)?;
+        }
+        for run in runs.iter_mut() {
+            run.force_unlock = force_unlock;
+            run.skip_unchanged = skip_unchanged;
+            run.override_window |= override_window;
+        }
+
+        // the containers stand in for the hosts, and a dry run doesn't change them
+        if !dry_run && simulate.is_none() {
+            confirm_protected(&runs, yes_i_mean_prod)?;
+        }
+        // confirmed, or the dry run and the containers don't touch the protected hosts
+        for run in runs.iter_mut() {
+            run.protected_confirmed = true;
+        }
+
+        // the containers standing in for the hosts are removed when they are dropped
+        let mut containers = HashMap::new();
+        if let Some(simulate) = simulate {
+            let (engine, image) =
+                parse_container_spec(&simulate).map_err(|e| Error::new(e.to_string()))?;
+            for run in runs.iter_mut() {
+                run.use_containers(&engine, &image, &mut containers)
+                    .map_err(|e| Error::new(e.to_string()))?;
+            }
+        }
+
+        if !dry_run {
+            approve(&runs, approval_token.as_deref())?;
+            for run in runs.iter_mut() {
+                run.approved = true;
+            }
+        }
+
+        let mut record = RunRecord::new(runbooks.clone(), dry_run);
+        // a dry run doesn't change anything, so there's nothing to announce
+        let hooks = if dry_run { Hooks::default() } else { hooks };
+        let hook_env = start_env(
+            &record.id.to_string(),
+            &runbooks
+                .iter()
+                .map(|runbook| runbook.to_string_lossy().to_string())
+                .collect::<Vec<_>>(),
+            &runs
+                .iter()
+                .flat_map(|run| run.hosts())
+                .map(|host| host.host.as_str())
+                .collect::<Vec<_>>(),
+            // the labels of all the runs, like the notify command of a schedule
+            &runs.iter().flat_map(|run| run.labels().clone()).collect(),
+        );
+        hooks
+            .pre_run(&hook_env)
+            .map_err(|e| Error::new(e.to_string()))?;
+
+        // without a terminal there's no TUI, so the events are logged instead
+        // and we wait for the runs to finish
+        let headless = !std::io::stdout().is_terminal();
+        let mut sinks: Vec<Arc<dyn EventSink>> = Vec::new();
+        if headless {
+            sinks.push(Arc::new(
+                LogSink::new(std::io::stderr(), &runs).with_color(std::io::stderr().is_terminal()),
+            ));
+        } else {
+            app.runs = runs.iter().map(|run| run.to_panel()).collect();
+            sinks.push(Arc::new(TuiSink::new(app.tx.clone())));
+            for run in runs.iter_mut() {
+                run.debugger = Some(TuiDebugger::new(app.tx.clone()));
+            }
+        }
+        if let Some(addr) = web {
+            let token = std::env::var("TIRON_WEB_TOKEN").unwrap_or_else(|_| web::new_token());
+            let (url, sink) = web::serve(&addr, &token, &runs)
+                .map_err(|e| Error::new(format!("can't start the web dashboard on {addr}: {e}")))?;
+            eprintln!("web dashboard: {url}");
+            sinks.push(sink);
+        }
+        if let Some(path) = event_log {
+            let file = File::create(&path)
+                .map_err(|e| Error::new(format!("can't create event log {path}: {e}")))?;
+            sinks.push(Arc::new(JsonSink::new(file)));
+        }
+        if let Some(endpoint) = otlp_endpoint {
+            sinks.push(Arc::new(TraceSink::new(endpoint, &runs)));
+        }
+        // the run history is kept on a best effort basis, like the record itself
+        if let Ok(sink) = RecordingSink::create(record.id, &runs) {
+            sinks.push(Arc::new(sink));
+        }
+        // attaching is best effort too, e.g. it needs unix sockets
+        if let Ok(sink) = AttachSink::listen(record.id, &runs) {
+            sinks.push(Arc::new(sink));
+        }
+        let events: Arc<dyn EventSink> = Arc::new(MultiSink::new(sinks));
+
+        let handle = std::thread::spawn(move || -> Result<Vec<RunResult>> {
+            let mut results = previous;
+            if concurrent {
+                results.extend(execute_runbooks(runs, &events)?);
+            } else {
+                results.extend(execute_runs(runs, &events)?);
+            }
+            record.finish(results);
+            let _ = record.save();
+            if !hooks.post_run.is_empty() {
+                let duration = record.finished.saturating_sub(record.started);
+                hooks.post_run(&summary_env(hook_env, &record.runs, duration));
+            }
+            Ok(record.runs)
+        });
+
+        if !headless {
+            app.start().map_err(|e| Error::new(e.to_string()))?;
+            // the failed hosts aren't restarted when the TUI is gone,
+            // so the runs finish without them
+            let restarting = app.prompting_restart();
+            drop(app);
+            if !dry_run && (handle.is_finished() || restarting) {
+                if let Ok(Ok(results)) = handle.join() {
+                    print_outputs(&results);
+                    print_failures(&results);
+                    print_recap(&results);
+                    if concurrent {
+                        print_runbook_recap(&results);
+                    }
+                }
+                return Ok(RunOutcome {
+                    runbooks,
+                    changed: false,
+                });
+            }
+        }
+
+        if dry_run || headless {
+            let results = handle
+                .join()
+                .map_err(|_| Error::new("runs stopped unexpectedly"))?
+                .map_err(|e| Error::new(e.to_string()))?;
+
+            let changed = results
+                .iter()
+                .any(|r| r.hosts.iter().any(|h| !h.pending.is_empty()));
+            if dry_run {
+                let summary = serde_json::json!({
+                    "changed": changed,
+                    "runs": results,
+                });
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&summary)
+                        .map_err(|e| Error::new(e.to_string()))?
+                );
+            }
+
+            if !dry_run {
+                print_outputs(&results);
+                print_failures(&results);
+                print_recap(&results);
+                if concurrent {
+                    print_runbook_recap(&results);
+                }
+            }
+            if results.iter().any(|r| !r.success) {
+                return Error::new("run failed").err();
+            }
+            if dry_run && changed {
+                return Ok(RunOutcome {
+                    runbooks,
+                    changed: true,
+                });
+            }
+        }
+    }
+
+    Ok(RunOutcome {
+        runbooks,
+        changed: false,
+    })
+}
+
+/// The paths of the runbooks from their names, which are relative to the current directory
+/// and can omit the `.tr` extension
+pub fn runbook_paths(runbooks: &[String]) -> Vec<PathBuf> {
+    runbooks
         .iter()
         .map(|name| {
             let file_name = if !name.ends_with(".tr") {
@@ -66,39 +500,269 @@ pub fn run(runbooks: Vec<String>, check: bool) -> Result<Vec<PathBuf>, Error> {
                 Err(_) => PathBuf::from(file_name),
             }
         })
-        .collect();
+        .collect()
+}
 
+/// Parse the runbooks and returns all the runs in them.
+///
+/// In check mode, the actions only report what they would change.
+pub fn load_runs(runbooks: &[PathBuf], check_mode: bool) -> Result<Vec<Run>, Error> {
     let mut runs = Vec::new();
     for path in runbooks.iter() {
-        let mut runbook = Runbook::new(path.to_path_buf(), app.tx.clone(), 0)?;
+        let mut runbook = Runbook::new(path.to_path_buf(), 0)?;
+        runbook.check_mode = check_mode;
         runbook.parse(true)?;
         runs.push(runbook.runs);
     }
-    let runs: Vec<Run> = runs.into_iter().flatten().collect();
+    Ok(runs.into_iter().flatten().collect())
+}
 
-    if !check {
-        app.runs = runs.iter().map(|run| run.to_panel()).collect();
-
-        let tx = app.tx.clone();
-        std::thread::spawn(move || -> Result<()> {
-            for run in runs {
-                let _ = tx.send(AppEvent::Run(RunEvent::RunStarted { id: run.id }));
-                let success = run.execute()?;
-                let _ = tx.send(AppEvent::Run(RunEvent::RunCompleted {
-                    id: run.id,
-                    success,
-                }));
-                if !success {
-                    break;
-                }
+/// Resume the runs from where the last execution of the runbooks failed. The runs that
+/// succeeded then are left out, and their results are returned
+fn resume_runs(runbooks: &[PathBuf], runs: &mut Vec<Run>) -> Result<Vec<RunResult>, Error> {
+    let record = last_record(runbooks)
+        .map_err(|e| Error::new(format!("can't read the run history: {e}")))?
+        .ok_or_else(|| Error::new("the runbooks haven't been run, so there's nothing to resume"))?;
+    if record.success {
+        return Error::new("the runbooks succeeded the last time, so there's nothing to resume")
+            .err();
+    }
+    // the runs stop at the first failed one, which is the last one
+    let mut previous = record.runs;
+    let failed = previous
+        .pop()
+        .ok_or_else(|| Error::new("the last execution of the runbooks didn't run anything"))?;
+    let changed = previous.len() >= runs.len()
+        || runs
+            .iter()
+            .zip(previous.iter().chain([&failed]))
+            .any(|(run, result)| run.name() != result.name.as_deref());
+    if changed {
+        return Error::new(
+            "the runs of the runbooks changed since the last time, so they can't be resumed",
+        )
+        .err();
+    }
+    runs.drain(..previous.len());
+    runs[0].resume(&failed);
+    Ok(previous)
+}
+
+/// Execute the runs one after another, and stop at the first failed run.
+///
+/// The events of the runs are sent to `events`.
+pub fn execute_runs(runs: Vec<Run>, events: &Arc<dyn EventSink>) -> Result<Vec<RunResult>> {
+    // the runs are checked before any of them starts, so that they don't stop halfway
+    for run in &runs {
+        run.check_window()?;
+        run.check_approved()?;
+        run.check_protected()?;
+    }
+
+    let mut results = Vec::new();
+    for run in runs {
+        events.send(Event::Run(RunEvent::RunStarted { id: run.id }));
+        let result = run.execute(events)?;
+        let success = result.success;
+        events.send(Event::Run(RunEvent::RunCompleted {
+            id: run.id,
+            success,
+        }));
+        results.push(result);
+        if !success {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// The runbooks that are executed at the same time can't share any host,
+/// which they'd change at the same time
+fn check_independent(runs: &[Run]) -> Result<(), Error> {
+    let mut hosts: HashMap<&str, &Path> = HashMap::new();
+    for run in runs {
+        for host in run.hosts() {
+            let runbook = *hosts.entry(host.host.as_str()).or_insert(run.runbook());
+            if runbook != run.runbook() {
+                return Error::new(format!(
+                    "{} is in both {} and {}, so they can't run concurrently",
+                    host.host,
+                    runbook.display(),
+                    run.runbook().display()
+                ))
+                .err();
             }
-            Ok(())
-        });
+        }
+    }
+    Ok(())
+}
+
+/// Execute the runbooks at the same time, while the runs in each runbook are executed
+/// one after another like `execute_runs`. The results are in the order of the runbooks
+pub fn execute_runbooks(runs: Vec<Run>, events: &Arc<dyn EventSink>) -> Result<Vec<RunResult>> {
+    // the runs are checked before any of them starts, so that they don't stop halfway
+    for run in &runs {
+        run.check_window()?;
+        run.check_approved()?;
+        run.check_protected()?;
+    }
+
+    let mut runbooks: Vec<Vec<Run>> = Vec::new();
+    for run in runs {
+        match runbooks
+            .iter_mut()
+            .find(|runs| runs[0].runbook() == run.runbook())
+        {
+            Some(runs) => runs.push(run),
+            None => runbooks.push(vec![run]),
+        }
+    }
+    let handles = runbooks
+        .into_iter()
+        .map(|runs| {
+            let events = events.clone();
+            std::thread::spawn(move || execute_runs(runs, &events))
+        })
+        .collect::<Vec<_>>();
+    // all the runbooks finish before an error of any of them is returned
+    let finished = handles
+        .into_iter()
+        .map(|handle| {
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("runs stopped unexpectedly")))
+        })
+        .collect::<Vec<_>>();
+    let mut results = Vec::new();
+    for finished in finished {
+        results.extend(finished?);
+    }
+    Ok(results)
+}
 
-        app.start().map_err(|e| Error::new(e.to_string()))?;
+/// Print how every runbook did, when they were executed at the same time
+fn print_runbook_recap(results: &[RunResult]) {
+    let mut runbooks: Vec<(&Path, Vec<&RunResult>)> = Vec::new();
+    for result in results {
+        match runbooks
+            .iter_mut()
+            .find(|(runbook, _)| *runbook == result.runbook)
+        {
+            Some((_, results)) => results.push(result),
+            None => runbooks.push((&result.runbook, vec![result])),
+        }
+    }
+    println!("runbooks:");
+    for (runbook, results) in &runbooks {
+        let name = runbook
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| runbook.to_string_lossy());
+        match results.iter().find(|result| !result.success) {
+            Some(result) => println!(
+                "  {name}: failed in {}, {} of {} hosts failed",
+                result.name.as_deref().unwrap_or("a run"),
+                result.hosts.iter().filter(|host| !host.success).count(),
+                result.hosts.len(),
+            ),
+            None => println!(
+                "  {name}: succeeded in {} runs on {} hosts",
+                results.len(),
+                results
+                    .iter()
+                    .flat_map(|result| &result.hosts)
+                    .map(|host| host.host.as_str())
+                    .unique()
+                    .count(),
+            ),
+        }
     }
+    let failed = runbooks
+        .iter()
+        .filter(|(_, results)| results.iter().any(|result| !result.success))
+        .count();
+    println!(
+        "  total: {} runbooks, {} succeeded, {failed} failed",
+        runbooks.len(),
+        runbooks.len() - failed,
+    );
+}
 
-    Ok(runbooks)
+/// Print the actions that failed with where they are in the runbooks
+fn print_failures(results: &[RunResult]) {
+    let stderr = std::io::stderr();
+    let color = stderr.is_terminal();
+    for host in results.iter().flat_map(|result| &result.hosts) {
+        for action in &host.actions {
+            if action.status != ActionStatus::Failed {
+                continue;
+            }
+            let message = match &action.error {
+                Some(error) => format!("[{}] {} failed: {error}", host.host, action.action),
+                None => format!("[{}] {} failed", host.host, action.action),
+            };
+            let mut error = Error::new(message);
+            error.location = action.location.clone();
+            let _ = error.write_report(&mut stderr.lock(), color);
+        }
+    }
+}
+
+/// Print how the hosts did in every environment, if the hosts are tagged with
+/// their environments. A host failed if it failed in any of the runs
+fn print_recap(results: &[RunResult]) {
+    let hosts = results.iter().flat_map(|result| &result.hosts);
+    if hosts.clone().all(|host| host.environment.is_none()) {
+        return;
+    }
+    // the environments with their hosts, and whether each host succeeded
+    let mut environments: Vec<(Option<&str>, Vec<(&str, bool)>)> = Vec::new();
+    for host in hosts {
+        let environment = host.environment.as_deref();
+        let index = match environments.iter().position(|(e, _)| *e == environment) {
+            Some(index) => index,
+            None => {
+                environments.push((environment, Vec::new()));
+                environments.len() - 1
+            }
+        };
+        let hosts = &mut environments[index].1;
+        match hosts.iter_mut().find(|(name, _)| *name == host.host) {
+            Some((_, success)) => *success &= host.success,
+            None => hosts.push((host.host.as_str(), host.success)),
+        }
+    }
+    println!("recap:");
+    for (environment, hosts) in environments {
+        let failed = hosts.iter().filter(|(_, success)| !success).count();
+        println!(
+            "  {}: {} hosts, {} succeeded, {failed} failed",
+            environment.unwrap_or("untagged"),
+            hosts.len(),
+            hosts.len() - failed,
+        );
+    }
+}
+
+/// Print the output values of the runs for every host at the end of the runs
+fn print_outputs(results: &[RunResult]) {
+    for (i, result) in results.iter().enumerate() {
+        if result.hosts.iter().all(|host| host.outputs.is_empty()) {
+            continue;
+        }
+        let name = result
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Run {}", i + 1));
+        println!("{name} outputs:");
+        for host in result.hosts.iter().filter(|host| !host.outputs.is_empty()) {
+            println!("  {}:", host.host);
+            for (key, value) in &host.outputs {
+                let value = serde_json::to_string(value).unwrap_or_default();
+                println!("    {key} = {value}");
+            }
+        }
+    }
 }
 
 fn action_doc(name: Option<String>) {
@@ -137,3 +801,45 @@ fn action_doc(name: Option<String>) {
             });
     }
 }
+
+fn explain(code: Option<String>) -> Result<(), Error> {
+    let Some(code) = code else {
+        for code in ERROR_CODES {
+            println!("{}: {}", code.code, code.title);
+        }
+        return Ok(());
+    };
+    let code = error_code(&code)
+        .ok_or_else(|| Error::new(format!("{code} isn't an error code of tiron")))?;
+    println!("{}: {}\n", code.code, code.title);
+    println!("{}\n", code.explanation);
+    println!("{}", code.url());
+    Ok(())
+}
+
+/// Read a password from the terminal without echoing it, or a line from stdin
+/// if it's not a terminal
+fn ask_password(prompt: &str) -> Result<String, Error> {
+    let stdin = std::io::stdin();
+    let terminal = stdin.is_terminal();
+    if terminal {
+        eprint!("{prompt}");
+    }
+    #[cfg(unix)]
+    let echo_off = terminal
+        && std::process::Command::new("stty")
+            .arg("-echo")
+            .status()
+            .is_ok_and(|status| status.success());
+    let mut password = String::new();
+    let read = stdin.read_line(&mut password);
+    #[cfg(unix)]
+    if echo_off {
+        let _ = std::process::Command::new("stty").arg("echo").status();
+    }
+    if terminal {
+        eprintln!();
+    }
+    read.map_err(|e| Error::new(format!("can't read the password: {e}")))?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -0,0 +1,328 @@
+use std::fmt::Write;
+
+use hcl::Map;
+use itertools::Itertools;
+use tiron_common::error::Error;
+
+use crate::{
+    history::{load_records, now, RunRecord},
+    node::ActionOutcome,
+    run::{format_labels, HostResult, RunResult},
+    schedule::UtcTime,
+};
+
+/// How much slower an action or a host must be to count as a regression
+const REGRESSION_RATIO: f64 = 1.5;
+const REGRESSION_MIN_MS: u64 = 1000;
+
+/// Compare two runs from the run history. With no ids, the latest run is compared
+/// with the previous run of the same runbooks, and with one id, that run is.
+pub fn diff_runs(ids: Vec<String>) -> Result<(), Error> {
+    if ids.len() > 2 {
+        return Error::new("diff-runs compares two runs at most").err();
+    }
+    let records = load_records().map_err(|e| Error::new(e.to_string()))?;
+    let find = |prefix: &str| -> Result<usize, Error> {
+        let matches: Vec<usize> = records
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.id.to_string().starts_with(prefix))
+            .map(|(i, _)| i)
+            .collect();
+        match matches.as_slice() {
+            [i] => Ok(*i),
+            [] => Error::new(format!("can't find run {prefix} in the history")).err(),
+            _ => Error::new(format!("run {prefix} is ambiguous")).err(),
+        }
+    };
+
+    let (old, new) = match ids.as_slice() {
+        [old, new] => (find(old)?, find(new)?),
+        _ => {
+            let new = match ids.first() {
+                Some(id) => find(id)?,
+                None => records
+                    .iter()
+                    .rposition(|r| !r.dry_run)
+                    .ok_or_else(|| Error::new("there are no runs in the history"))?,
+            };
+            let old = records[..new]
+                .iter()
+                .rposition(|r| !r.dry_run && r.runbooks == records[new].runbooks)
+                .ok_or_else(|| {
+                    Error::new(format!(
+                        "there's no run of the same runbooks before {}",
+                        records[new].id
+                    ))
+                })?;
+            (old, new)
+        }
+    };
+
+    print!("{}", format_diff(&records[old], &records[new]));
+    Ok(())
+}
+
+fn format_diff(old: &RunRecord, new: &RunRecord) -> String {
+    let mut diff = String::new();
+    let _ = writeln!(diff, "old: {}", describe(old));
+    let _ = writeln!(diff, "new: {}", describe(new));
+    if old.runbooks != new.runbooks {
+        let _ = writeln!(diff, "the runs are of different runbooks");
+    }
+
+    let mut changed = false;
+    for (i, new_run) in new.runs.iter().enumerate() {
+        let old_run = match &new_run.name {
+            Some(name) => old.runs.iter().find(|r| r.name.as_ref() == Some(name)),
+            None => old.runs.get(i).filter(|r| r.name.is_none()),
+        };
+        let lines = match old_run {
+            Some(old_run) => diff_run(old_run, new_run),
+            None => vec!["  only in the new run".to_string()],
+        };
+        if !lines.is_empty() {
+            changed = true;
+            let _ = writeln!(diff, "\n{}", run_name(new_run, i));
+            for line in lines {
+                let _ = writeln!(diff, "{line}");
+            }
+        }
+    }
+    for (i, old_run) in old.runs.iter().enumerate() {
+        let in_new = match &old_run.name {
+            Some(name) => new.runs.iter().any(|r| r.name.as_ref() == Some(name)),
+            None => new.runs.get(i).is_some_and(|r| r.name.is_none()),
+        };
+        if !in_new {
+            changed = true;
+            let _ = writeln!(diff, "\n{}\n  only in the old run", run_name(old_run, i));
+        }
+    }
+
+    if !changed {
+        let _ = writeln!(diff, "\nno differences");
+    }
+    diff
+}
+
+fn describe(record: &RunRecord) -> String {
+    // a run that was killed before it finished has no finish time
+    let finished = if record.finished == 0 {
+        now()
+    } else {
+        record.finished
+    };
+    let labels: Map<String, String> = record
+        .runs
+        .iter()
+        .flat_map(|run| run.labels.clone())
+        .collect();
+    let labels = if labels.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", format_labels(&labels))
+    };
+    format!(
+        "{} at {}, {} in {}s{labels}",
+        record.id,
+        UtcTime::from_unix(record.started),
+        if record.success {
+            "succeeded"
+        } else {
+            "failed"
+        },
+        finished.saturating_sub(record.started)
+    )
+}
+
+fn run_name(run: &RunResult, i: usize) -> String {
+    match &run.name {
+        Some(name) => name.clone(),
+        None => format!("Run {}", i + 1),
+    }
+}
+
+fn diff_run(old: &RunResult, new: &RunResult) -> Vec<String> {
+    let mut lines = Vec::new();
+    for host in &new.hosts {
+        let Some(old_host) = old.hosts.iter().find(|h| h.host == host.host) else {
+            lines.push(format!(
+                "  {}: only in the new run ({})",
+                host.host,
+                host_status(host)
+            ));
+            continue;
+        };
+
+        let mut host_lines = Vec::new();
+        if host_status(old_host) != host_status(host) {
+            host_lines.push(format!(
+                "    {} -> {}",
+                host_status(old_host),
+                host_status(host)
+            ));
+        }
+        if let Some(regression) = regression(old_host.duration, host.duration) {
+            host_lines.push(format!("    took {regression}"));
+        }
+        host_lines.extend(diff_actions(&old_host.actions, &host.actions));
+        if !host_lines.is_empty() {
+            lines.push(format!("  {}", host.host));
+            lines.extend(host_lines);
+        }
+    }
+    for host in &old.hosts {
+        if !new.hosts.iter().any(|h| h.host == host.host) {
+            lines.push(format!("  {}: only in the old run", host.host));
+        }
+    }
+    lines
+}
+
+fn host_status(host: &HostResult) -> &'static str {
+    if host.skipped {
+        "skipped"
+    } else if host.success {
+        "success"
+    } else {
+        "failed"
+    }
+}
+
+/// The actions are matched by their name, and the nth action with the same
+/// name is matched with the nth one in the other run
+fn diff_actions(old: &[ActionOutcome], new: &[ActionOutcome]) -> Vec<String> {
+    let keyed = |actions: &[ActionOutcome]| -> Vec<((String, usize), ActionOutcome)> {
+        actions
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let nth = actions[..i].iter().filter(|b| b.action == a.action).count();
+                ((a.action.clone(), nth), a.clone())
+            })
+            .collect()
+    };
+    let old = keyed(old);
+    let new = keyed(new);
+
+    let mut lines = Vec::new();
+    for (key, action) in &new {
+        match old.iter().find(|(k, _)| k == key) {
+            Some((_, old_action)) => {
+                if old_action.status != action.status {
+                    lines.push(format!(
+                        "    {}: {} -> {}",
+                        action.action, old_action.status, action.status
+                    ));
+                }
+                if let Some(regression) = regression(old_action.duration, action.duration) {
+                    lines.push(format!("    {}: took {regression}", action.action));
+                }
+            }
+            None => lines.push(format!(
+                "    {}: only in the new run ({})",
+                action.action, action.status
+            )),
+        }
+    }
+    let removed = old
+        .iter()
+        .filter(|(key, _)| !new.iter().any(|(k, _)| k == key))
+        .map(|(_, action)| format!("    {}: only in the old run", action.action))
+        .collect_vec();
+    lines.extend(removed);
+    lines
+}
+
+fn regression(old: u64, new: u64) -> Option<String> {
+    if new < old + REGRESSION_MIN_MS || (new as f64) < old as f64 * REGRESSION_RATIO {
+        return None;
+    }
+    Some(format!(
+        "{:.1}s instead of {:.1}s",
+        new as f64 / 1000.0,
+        old as f64 / 1000.0
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(runs: serde_json::Value) -> RunRecord {
+        serde_json::from_value(json!({
+            "id": uuid::Uuid::new_v4(),
+            "runbooks": ["main.tr"],
+            "dry_run": false,
+            "started": 0,
+            "finished": 60,
+            "success": true,
+            "runs": runs,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn diff_two_runs() {
+        let old = record(json!([{
+            "name": "deploy",
+            "success": true,
+            "hosts": [{
+                "host": "web1",
+                "success": true,
+                "pending": [],
+                "duration": 3000,
+                "actions": [
+                    { "action": "copy", "status": "success", "duration": 100 },
+                    { "action": "build", "status": "success", "duration": 2000 },
+                    { "action": "cleanup", "status": "success", "duration": 100 },
+                ],
+            }],
+        }]));
+        let new = record(json!([{
+            "name": "deploy",
+            "success": false,
+            "hosts": [{
+                "host": "web1",
+                "success": false,
+                "pending": [],
+                "duration": 3500,
+                "actions": [
+                    { "action": "copy", "status": "failed", "duration": 100 },
+                    { "action": "build", "status": "success", "duration": 5000 },
+                    { "action": "restart", "status": "skipped", "duration": 0 },
+                ],
+            }],
+        }]));
+
+        let diff = format_diff(&old, &new);
+        let diff = diff.lines().skip(2).join("\n");
+        assert_eq!(
+            diff,
+            "
+deploy
+  web1
+    success -> failed
+    copy: success -> failed
+    build: took 5.0s instead of 2.0s
+    restart: only in the new run (skipped)
+    cleanup: only in the old run"
+        );
+
+        assert!(format_diff(&old, &old).ends_with("\nno differences\n"));
+    }
+
+    #[test]
+    fn describe_labels() {
+        let record = record(json!([{
+            "name": "deploy",
+            "success": true,
+            "labels": { "ticket": "OPS-123", "owner": "alice" },
+            "hosts": [],
+        }]));
+        assert!(describe(&record).ends_with("succeeded in 60s [ticket=OPS-123,owner=alice]"));
+    }
+}
@@ -2,6 +2,7 @@
 
 use anyhow::{anyhow, Result};
 use itertools::Itertools;
+use tiron_common::error_code::ERROR_CODES;
 use tiron_node::action::data::all_actions;
 
 pub fn generate_doc() -> Result<()> {
@@ -45,5 +46,30 @@ pub fn generate_doc() -> Result<()> {
             )?;
         }
     }
+    generate_error_codes_doc()?;
+    Ok(())
+}
+
+fn generate_error_codes_doc() -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open("docs/content/docs/getting-started/errors.md")?;
+    file.write_all(b"+++\n")?;
+    file.write_all(b"title = \"errors\"\n")?;
+    file.write_all(b"template = \"docs/section.html\"\n")?;
+    file.write_all(b"weight = 3\n")?;
+    file.write_all(b"+++\n\n")?;
+    file.write_all(b"# Error codes\n\n")?;
+    file.write_all(
+        b"The errors of Tiron have stable codes, e.g. `Error[E0203]`, \
+and `tiron explain E0203` shows the explanation of the code in the console.\n",
+    )?;
+    for code in ERROR_CODES {
+        file.write_all(format!("\n### {}\n\n", code.code).as_bytes())?;
+        file.write_all(format!("**{}**\n\n", code.title).as_bytes())?;
+        file.write_all(format!("{}\n", code.explanation).as_bytes())?;
+    }
     Ok(())
 }
@@ -0,0 +1,257 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    error::Error,
+    node::{Resource, ResourceState},
+};
+use tiron_node::action::data::all_actions;
+
+use crate::{
+    core::{load_runs, runbook_paths},
+    history::{data_dir, now},
+    node::Node,
+    schedule::UtcTime,
+};
+
+/// The states of the resources that the runbooks manage on a host, which the live
+/// states are compared with for the drift report
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    host: String,
+    // unix timestamp in seconds
+    taken: u64,
+    resources: Vec<(Resource, ResourceState)>,
+}
+
+impl Manifest {
+    fn path(host: &str) -> Result<PathBuf> {
+        let dir = data_dir().ok_or_else(|| anyhow!("can't find the data directory"))?;
+        Ok(dir.join("manifests").join(format!("{host}.json")))
+    }
+
+    fn load(host: &str) -> Result<Option<Self>> {
+        let path = Self::path(host)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path(&self.host)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Snapshot the states of the resources the runbooks manage, e.g. the files written
+/// by `copy`, into the manifest of every host
+pub fn fingerprint(runbooks: Vec<String>) -> Result<(), Error> {
+    let hosts = managed_hosts(&runbooks)?;
+    let mut failed = false;
+    for (node, resources) in hosts {
+        let result = node.fingerprint(resources.clone()).and_then(|states| {
+            Manifest {
+                host: node.host.clone(),
+                taken: now(),
+                resources: resources.into_iter().zip(states).collect(),
+            }
+            .save()
+        });
+        match result {
+            Ok(()) => println!("{}: fingerprinted", node.host),
+            Err(e) => {
+                failed = true;
+                println!("{}: {e}", node.host);
+            }
+        }
+    }
+    if failed {
+        return Error::new("some hosts can't be fingerprinted").err();
+    }
+    Ok(())
+}
+
+/// Compare the live states of the resources with the manifests of the hosts,
+/// and returns whether any of them drifted
+pub fn drift(runbooks: Vec<String>) -> Result<bool, Error> {
+    let hosts = managed_hosts(&runbooks)?;
+    let mut drifted = false;
+    for (node, _) in hosts {
+        let manifest = Manifest::load(&node.host)
+            .map_err(|e| Error::new(format!("can't read the manifest of {}: {e}", node.host)))?;
+        let Some(manifest) = manifest else {
+            println!("{}: no manifest, run `tiron fingerprint` first", node.host);
+            continue;
+        };
+        let (resources, recorded): (Vec<Resource>, Vec<ResourceState>) =
+            manifest.resources.into_iter().unzip();
+        let live = match node.fingerprint(resources.clone()) {
+            Ok(live) => live,
+            Err(e) => {
+                println!("{}: {e}", node.host);
+                continue;
+            }
+        };
+        let lines = drift_lines(&resources, &recorded, &live);
+        if lines.is_empty() {
+            println!("{}: no drift", node.host);
+        } else {
+            drifted = true;
+            println!(
+                "{}: drifted since {}",
+                node.host,
+                UtcTime::from_unix(manifest.taken)
+            );
+            for line in lines {
+                println!("  {line}");
+            }
+        }
+    }
+    Ok(drifted)
+}
+
+/// The hosts of the runbooks with the resources their actions manage,
+/// which are merged for the hosts in several runs
+fn managed_hosts(runbooks: &[String]) -> Result<Vec<(Node, Vec<Resource>)>, Error> {
+    let runs = load_runs(&runbook_paths(runbooks), false)?;
+    let all_actions = all_actions();
+    let mut hosts: Vec<(Node, Vec<Resource>)> = Vec::new();
+    for run in &runs {
+        for node in run.hosts() {
+            let index = match hosts.iter().position(|(n, _)| n.host == node.host) {
+                Some(index) => index,
+                None => {
+                    hosts.push((node.clone(), Vec::new()));
+                    hosts.len() - 1
+                }
+            };
+            let resources = &mut hosts[index].1;
+            for action in &node.actions {
+                let Some(a) = all_actions.get(&action.action) else {
+                    continue;
+                };
+                for resource in a.resources(&action.input) {
+                    if !resources.contains(&resource) {
+                        resources.push(resource);
+                    }
+                }
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+fn drift_lines(
+    resources: &[Resource],
+    recorded: &[ResourceState],
+    live: &[ResourceState],
+) -> Vec<String> {
+    resources
+        .iter()
+        .zip(recorded.iter().zip(live))
+        .filter_map(|(resource, (recorded, live))| {
+            let change = match (recorded, live) {
+                (ResourceState::Unknown { .. }, ResourceState::Unknown { .. }) => return None,
+                (_, ResourceState::Unknown { error }) => format!("can't be read: {error}"),
+                (ResourceState::File { hash: old }, ResourceState::File { hash: new }) => {
+                    match (old, new) {
+                        (Some(old), Some(new)) if old != new => "changed".to_string(),
+                        (Some(_), None) => "removed".to_string(),
+                        (None, Some(_)) => "created".to_string(),
+                        _ => return None,
+                    }
+                }
+                (
+                    ResourceState::Package { installed: old },
+                    ResourceState::Package { installed: new },
+                ) if old != new => {
+                    if *new {
+                        "installed".to_string()
+                    } else {
+                        "removed".to_string()
+                    }
+                }
+                (
+                    ResourceState::Service {
+                        active: old_active,
+                        enabled: old_enabled,
+                    },
+                    ResourceState::Service { active, enabled },
+                ) => {
+                    let mut changes = Vec::new();
+                    if old_active != active {
+                        changes.push(if *active { "started" } else { "stopped" });
+                    }
+                    if old_enabled != enabled {
+                        changes.push(if *enabled { "enabled" } else { "disabled" });
+                    }
+                    if changes.is_empty() {
+                        return None;
+                    }
+                    changes.join(", ")
+                }
+                // the recorded state couldn't be read, so there's nothing to compare with
+                _ => return None,
+            };
+            Some(format!("{resource}: {change}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drift_of_resources() {
+        let resources = vec![
+            Resource::File {
+                path: "/etc/nginx/nginx.conf".to_string(),
+            },
+            Resource::File {
+                path: "/etc/motd".to_string(),
+            },
+            Resource::Package {
+                name: "nginx".to_string(),
+            },
+            Resource::Service {
+                name: "nginx".to_string(),
+            },
+        ];
+        let file = |hash: Option<&str>| ResourceState::File {
+            hash: hash.map(|h| h.to_string()),
+        };
+        let recorded = vec![
+            file(Some("aa")),
+            file(Some("bb")),
+            ResourceState::Package { installed: true },
+            ResourceState::Service {
+                active: true,
+                enabled: true,
+            },
+        ];
+        let live = vec![
+            file(Some("cc")),
+            file(Some("bb")),
+            ResourceState::Package { installed: true },
+            ResourceState::Service {
+                active: false,
+                enabled: true,
+            },
+        ];
+        assert_eq!(
+            drift_lines(&resources, &recorded, &live),
+            vec![
+                "file /etc/nginx/nginx.conf: changed",
+                "service nginx: stopped"
+            ]
+        );
+        assert!(drift_lines(&resources, &recorded, &recorded).is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use std::{io::IsTerminal, sync::Arc};
+
+use hcl_edit::expr::Expression;
+use tiron_common::{
+    error::Error,
+    event::{Event, EventSink, RunEvent},
+};
+
+use crate::{
+    approval::confirm_protected, core::runbook_paths, run::Run, runbook::Runbook, sink::LogSink,
+};
+
+/// Run one action on the hosts of the group without a runbook for it.
+/// The groups and the inventory are from the runbook, which is main.tr by default
+pub fn exec(
+    runbook: Option<String>,
+    group: String,
+    action: String,
+    params: Vec<String>,
+    yes_i_mean_prod: bool,
+) -> Result<(), Error> {
+    let run = adhoc_run(&group, &action, &params)?;
+    let mut run = load_adhoc_run(runbook, &run)?;
+    confirm_protected(std::slice::from_ref(&run), yes_i_mean_prod)?;
+    run.protected_confirmed = true;
+
+    let events: Arc<dyn EventSink> = Arc::new(
+        LogSink::new(std::io::stderr(), std::slice::from_ref(&run))
+            .with_color(std::io::stderr().is_terminal()),
+    );
+    events.send(Event::Run(RunEvent::RunStarted { id: run.id }));
+    let result = run
+        .execute(&events)
+        .map_err(|e| Error::new(e.to_string()))?;
+    events.send(Event::Run(RunEvent::RunCompleted {
+        id: run.id,
+        success: result.success,
+    }));
+
+    let failed = result.hosts.iter().filter(|host| !host.success).count();
+    println!(
+        "{}: {} hosts, {} succeeded, {failed} failed",
+        action,
+        result.hosts.len(),
+        result.hosts.len() - failed,
+    );
+    if !result.success {
+        return Error::new("exec failed").err();
+    }
+    Ok(())
+}
+
+pub fn load_adhoc_run(runbook: Option<String>, run: &str) -> Result<Run, Error> {
+    let path = runbook_paths(&[runbook.clone().unwrap_or_else(|| "main".to_string())]).remove(0);
+    // without main.tr, there's only the localhost to run on
+    let mut runbook = if runbook.is_none() && !path.exists() {
+        Runbook::from_data(
+            path,
+            "group \"localhost\" {\n  host \"localhost\" {}\n}\n".to_string(),
+            0,
+        )?
+    } else {
+        Runbook::new(path, 0)?
+    };
+    runbook.parse_adhoc_run(run)?;
+    runbook
+        .runs
+        .pop()
+        .ok_or_else(|| Error::new("can't find the ad-hoc run"))
+}
+
+/// The run block of the action with the params in the form of key=value.
+/// The value is used as it is if it's a literal, e.g. a number or a list,
+/// and it's a string otherwise
+fn adhoc_run(group: &str, action: &str, params: &[String]) -> Result<String, Error> {
+    let mut run = format!(
+        "run {} {{\n  action {} {{\n    params {{\n",
+        hcl_string(group),
+        hcl_string(action)
+    );
+    for param in params {
+        let Some((key, value)) = param.split_once('=') else {
+            return Error::new(format!("param should be key=value, but got {param}")).err();
+        };
+        let is_ident = key
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if !is_ident {
+            return Error::new(format!("{key} isn't a valid param name")).err();
+        }
+        let value = match hcl_edit::parser::parse_expr(value) {
+            Ok(
+                Expression::Null(_)
+                | Expression::Bool(_)
+                | Expression::Number(_)
+                | Expression::String(_)
+                | Expression::Array(_)
+                | Expression::Object(_),
+            ) => value.to_string(),
+            _ => hcl_string(value),
+        };
+        run.push_str(&format!("      {key} = {value}\n"));
+    }
+    run.push_str("    }\n  }\n}\n");
+    Ok(run)
+}
+
+/// The quoted HCL string of the value, which doesn't interpolate anything
+pub fn hcl_string(value: &str) -> String {
+    let mut s = String::from("\"");
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                s.push(c);
+                s.push(c);
+            }
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adhoc_run_params() {
+        let run = adhoc_run(
+            "web",
+            "command",
+            &[
+                "cmd=uptime".to_string(),
+                "args=[\"-p\"]".to_string(),
+                "timeout=30".to_string(),
+                "note=say \"${hi}\"".to_string(),
+            ],
+        )
+        .ok();
+        assert_eq!(
+            run.as_deref(),
+            Some(
+                r#"run "web" {
+  action "command" {
+    params {
+      cmd = "uptime"
+      args = ["-p"]
+      timeout = 30
+      note = "say \"$${hi}\""
+    }
+  }
+}
+"#
+            )
+        );
+        match adhoc_run("web", "command", &["uptime".to_string()]) {
+            Ok(_) => panic!("param without = should be an error"),
+            Err(e) => assert_eq!(e.message, "param should be key=value, but got uptime"),
+        }
+    }
+}
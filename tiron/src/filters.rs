@@ -0,0 +1,330 @@
+use std::{
+    net::IpAddr,
+    path::{Component, Path},
+};
+
+use hcl::{
+    eval::{Context, FuncArgs, FuncDef, ParamType},
+    Value,
+};
+use regex::Regex;
+
+/// Declare the functions that work like the Ansible filters of the same names,
+/// e.g. `regex_replace(name, "-", "_")` for `name | regex_replace('-', '_')`,
+/// which the runbooks opt in to with `ansible_filters = true`
+pub fn declare_filters(ctx: &mut Context) {
+    ctx.declare_func(
+        "default",
+        FuncDef::builder()
+            .param(ParamType::Any)
+            .param(ParamType::Any)
+            .variadic_param(ParamType::Bool)
+            .build(default),
+    );
+    ctx.declare_func(
+        "regex_replace",
+        FuncDef::builder()
+            .param(ParamType::String)
+            .param(ParamType::String)
+            .param(ParamType::String)
+            .build(regex_replace),
+    );
+    ctx.declare_func(
+        "to_json",
+        FuncDef::builder().param(ParamType::Any).build(to_json),
+    );
+    ctx.declare_func(
+        "ipaddr",
+        FuncDef::builder()
+            .param(ParamType::Any)
+            .variadic_param(ParamType::String)
+            .build(ipaddr),
+    );
+}
+
+/// Declare the functions that every runbook has, which are `file("nginx.conf")`
+/// for `files/nginx.conf` next to the runbook the action is written in,
+/// and `concat` and `split` for building lists
+pub fn declare_functions(ctx: &mut Context) {
+    ctx.declare_func(
+        "file",
+        FuncDef::builder().param(ParamType::String).build(file),
+    );
+    ctx.declare_func(
+        "concat",
+        FuncDef::builder()
+            .variadic_param(ParamType::array_of(ParamType::Any))
+            .build(concat),
+    );
+    ctx.declare_func(
+        "split",
+        FuncDef::builder()
+            .param(ParamType::String)
+            .param(ParamType::String)
+            .build(split),
+    );
+}
+
+/// The path of the file in the `files` directory, which the actions resolve
+/// relative to the directory of their runbook like the other relative paths
+fn file(args: FuncArgs) -> Result<Value, String> {
+    let name = args[0].as_str().unwrap_or_default();
+    files_path(name).map(Value::from)
+}
+
+/// The lists joined into one, like `concat(["web01"], ["web02", "web03"])`
+fn concat(args: FuncArgs) -> Result<Value, String> {
+    Ok(Value::Array(
+        args.iter()
+            .filter_map(|list| list.as_array())
+            .flatten()
+            .cloned()
+            .collect(),
+    ))
+}
+
+/// The parts of the string between the separator, like `split(",", "web,db")`.
+/// An empty string has no parts rather than one empty part
+fn split(args: FuncArgs) -> Result<Value, String> {
+    let sep = args[0].as_str().unwrap_or_default();
+    let s = args[1].as_str().unwrap_or_default();
+    if sep.is_empty() {
+        return Err("split() needs a separator that isn't empty".to_string());
+    }
+    if s.is_empty() {
+        return Ok(Value::Array(Vec::new()));
+    }
+    Ok(Value::Array(s.split(sep).map(Value::from).collect()))
+}
+
+fn files_path(name: &str) -> Result<String, String> {
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!(
+            "file() needs a path in the files directory, but got \"{name}\""
+        ));
+    }
+    Ok(format!("files/{name}"))
+}
+
+/// The fallback when the value is null, or also when it's falsy
+/// if the third argument is true, like `default(value, true)` in Ansible
+fn default(args: FuncArgs) -> Result<Value, String> {
+    let value = &args[0];
+    let falsy = args.get(2).and_then(|v| v.as_bool()).unwrap_or(false);
+    let empty = match value {
+        Value::Null => true,
+        Value::Bool(b) => falsy && !b,
+        Value::Number(n) => falsy && n.as_f64() == Some(0.0),
+        Value::String(s) => falsy && s.is_empty(),
+        Value::Array(a) => falsy && a.is_empty(),
+        Value::Object(o) => falsy && o.is_empty(),
+    };
+    Ok(if empty {
+        args[1].clone()
+    } else {
+        value.clone()
+    })
+}
+
+fn regex_replace(args: FuncArgs) -> Result<Value, String> {
+    let (Some(text), Some(pattern), Some(replacement)) =
+        (args[0].as_str(), args[1].as_str(), args[2].as_str())
+    else {
+        return Err("regex_replace takes a string, a pattern and a replacement".to_string());
+    };
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    let replacement = replacement_syntax(replacement);
+    Ok(Value::String(
+        regex.replace_all(text, replacement.as_str()).into_owned(),
+    ))
+}
+
+/// The replacement in the syntax of the regex crate, from the Python syntax
+/// of the Ansible filter, where `\1` or `\g<1>` is the group and `$` isn't special
+fn replacement_syntax(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => result.push_str("$$"),
+            '\\' => match chars.peek() {
+                Some(d) if d.is_ascii_digit() => {
+                    let mut group = String::new();
+                    while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                        group.push(d);
+                    }
+                    result.push_str(&format!("${{{group}}}"));
+                }
+                Some('g') => {
+                    chars.next();
+                    if chars.next_if_eq(&'<').is_some() {
+                        let name: String = chars.by_ref().take_while(|c| *c != '>').collect();
+                        result.push_str(&format!("${{{name}}}"));
+                    } else {
+                        result.push_str("\\g");
+                    }
+                }
+                Some('\\') => {
+                    chars.next();
+                    result.push('\\');
+                }
+                _ => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn to_json(args: FuncArgs) -> Result<Value, String> {
+    serde_json::to_string(&args[0])
+        .map(Value::String)
+        .map_err(|e| e.to_string())
+}
+
+/// Check that the value is an IP address or a network like `10.0.0.1/24`, which gives
+/// false if it isn't. The query picks a part of it, e.g. `address`, `network`, `prefix`
+/// or `netmask`, or only keeps it if it's of the version in `ipv4` or `ipv6`.
+/// With a list, it gives the list of the ones that are valid.
+fn ipaddr(args: FuncArgs) -> Result<Value, String> {
+    let query = args.get(1).and_then(|v| v.as_str()).unwrap_or("");
+    match &args[0] {
+        Value::Array(values) => {
+            let mut result = Vec::new();
+            for value in values {
+                let v = ipaddr_query(value, query)?;
+                if v != Value::Bool(false) {
+                    result.push(v);
+                }
+            }
+            Ok(Value::Array(result))
+        }
+        value => ipaddr_query(value, query),
+    }
+}
+
+fn ipaddr_query(value: &Value, query: &str) -> Result<Value, String> {
+    let Some(s) = value.as_str() else {
+        return Ok(Value::Bool(false));
+    };
+    let Some((addr, prefix)) = parse_network(s) else {
+        return Ok(Value::Bool(false));
+    };
+    let bits = if addr.is_ipv4() { 32 } else { 128 };
+    let mask = |prefix: u32| -> u128 {
+        if prefix == 0 {
+            0
+        } else {
+            (u128::MAX << (128 - prefix)) >> (128 - bits)
+        }
+    };
+    let from_bits = |n: u128| -> IpAddr {
+        if addr.is_ipv4() {
+            IpAddr::V4((n as u32).into())
+        } else {
+            IpAddr::V6(n.into())
+        }
+    };
+    let n = match addr {
+        IpAddr::V4(a) => u32::from(a) as u128,
+        IpAddr::V6(a) => u128::from(a),
+    };
+    let prefix_len = prefix.unwrap_or(bits);
+
+    Ok(match query {
+        "" => Value::String(s.to_string()),
+        "address" => Value::String(addr.to_string()),
+        "prefix" => Value::from(prefix_len),
+        "network" => Value::String(from_bits(n & mask(prefix_len)).to_string()),
+        "netmask" => Value::String(from_bits(mask(prefix_len)).to_string()),
+        "ipv4" if addr.is_ipv4() => Value::String(s.to_string()),
+        "ipv6" if addr.is_ipv6() => Value::String(s.to_string()),
+        "ipv4" | "ipv6" => Value::Bool(false),
+        _ => return Err(format!("ipaddr doesn't support the query {query}")),
+    })
+}
+
+/// Parse an IP address with the optional prefix length of its network
+fn parse_network(s: &str) -> Option<(IpAddr, Option<u32>)> {
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (s, None),
+    };
+    let addr: IpAddr = addr.trim().parse().ok()?;
+    let bits = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => Some(prefix.trim().parse().ok().filter(|p| *p <= bits)?),
+        None => None,
+    };
+    Some((addr, prefix))
+}
+
+#[cfg(test)]
+mod test {
+    use hcl::eval::Evaluate;
+
+    use super::*;
+
+    #[test]
+    fn files_paths() {
+        assert_eq!(files_path("nginx.conf").unwrap(), "files/nginx.conf");
+        assert_eq!(
+            files_path("nginx/site.conf").unwrap(),
+            "files/nginx/site.conf"
+        );
+        assert!(files_path("../secrets").is_err());
+        assert!(files_path("/etc/passwd").is_err());
+        assert!(files_path("").is_err());
+    }
+
+    #[test]
+    fn concat_split_lists() {
+        let mut ctx = Context::new();
+        declare_functions(&mut ctx);
+        let eval = |expr: &str| {
+            let expr: hcl::Expression = hcl_edit::parser::parse_expr(expr).unwrap().into();
+            expr.evaluate(&ctx).unwrap()
+        };
+        assert_eq!(
+            eval(r#"concat(split(",", "web,db"), ["canary01"])"#),
+            Value::from(vec!["web", "db", "canary01"])
+        );
+        assert_eq!(eval(r#"split(",", "")"#), Value::Array(Vec::new()));
+        assert_eq!(eval("concat()"), Value::Array(Vec::new()));
+    }
+
+    #[test]
+    fn regex_replace_groups() {
+        let replace = |text: &str, pattern: &str, replacement: &str| {
+            Regex::new(pattern)
+                .unwrap()
+                .replace_all(text, replacement_syntax(replacement).as_str())
+                .into_owned()
+        };
+        assert_eq!(replace("web-01-prod", "-", "_"), "web_01_prod");
+        assert_eq!(
+            replace("host.example.com", r"^(\w+)\.(.*)$", r"\2/\1"),
+            "example.com/host"
+        );
+        assert_eq!(replace("a1b22c333", r"\d{2,}", "#"), "a1b#c#");
+        assert_eq!(replace("abc", "x*", "-"), "-a-b-c-");
+        assert_eq!(replace("10.0.0.1", r"(?:\d+\.){3}(\d+)", r"\g<1>"), "1");
+        assert_eq!(replace("price", "price", "$5"), "$5");
+    }
+
+    #[test]
+    fn ipaddr_queries() {
+        let query = |s: &str, q: &str| ipaddr_query(&Value::from(s), q).unwrap();
+        assert_eq!(query("10.1.2.3/24", "network"), Value::from("10.1.2.0"));
+        assert_eq!(
+            query("10.1.2.3/24", "netmask"),
+            Value::from("255.255.255.0")
+        );
+        assert_eq!(query("10.1.2.3/24", "address"), Value::from("10.1.2.3"));
+        assert_eq!(query("10.1.2.3", "prefix"), Value::from(32u32));
+        assert_eq!(query("fe80::1/64", "network"), Value::from("fe80::"));
+        assert_eq!(query("fe80::1", "ipv4"), Value::Bool(false));
+        assert_eq!(query("example.com", ""), Value::Bool(false));
+    }
+}
@@ -3,6 +3,8 @@
 use hcl::format::{Format, Formatter};
 use tiron_common::error::Error;
 
+use crate::vault::is_encrypted;
+
 pub fn fmt(targets: Vec<String>) -> Result<(), Error> {
     let targets = if targets.is_empty() {
         vec![std::env::current_dir().map_err(|e| Error::new(e.to_string()))?]
@@ -47,6 +49,10 @@ fn fmt_runbook(path: PathBuf) -> Result<(), Error> {
             path.to_string_lossy()
         ))
     })?;
+    // the encrypted runbooks can't be formatted without decrypting them
+    if is_encrypted(&data) {
+        return Ok(());
+    }
     let body = hcl::parse(&data).map_err(|e| {
         if let hcl::Error::Parse(e) = e {
             Error::from_hcl(e, path.clone())
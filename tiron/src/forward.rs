@@ -0,0 +1,167 @@
+use std::{
+    io::Read,
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    process::{Child, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use hcl::eval::Context;
+use hcl_edit::{
+    structure::{Block, Structure},
+    Span,
+};
+use tiron_common::{error::Error, value::SpannedValue};
+
+use crate::{node::Node, remote::SshHost, runbook::Runbook};
+
+/// How long the forward has to be up before the run gives up on it
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A local port forwarded to a remote address through a host for the duration
+/// of a run, e.g. to reach a database that's only accessible from the host
+pub struct PortForward {
+    // the host that the connections go through
+    via: SshHost,
+    // the port on 127.0.0.1 of this machine
+    local: u16,
+    // the address that the connections go to from the host, in the form of host:port
+    remote: String,
+}
+
+impl PortForward {
+    /// The forward goes through the host of `via` in the runbook, or the host
+    /// of the run if it only has one
+    pub fn from_block(runbook: &Runbook, block: &Block, hosts: &[Node]) -> Result<Self, Error> {
+        let origin = &runbook.origin;
+        let ctx = Context::new();
+        let mut via = None;
+        let mut local = None;
+        let mut remote = None;
+        for structure in block.body.iter() {
+            let Structure::Attribute(a) = structure else {
+                continue;
+            };
+            let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+            match a.key.as_str() {
+                "via" => {
+                    let SpannedValue::String(s) = &v else {
+                        return origin.error("via should be a string", v.span()).err();
+                    };
+                    let nodes = runbook
+                        .hosts_from_name(s.value())
+                        .map_err(|e| origin.error(e.to_string(), v.span()).with_code("E0301"))?;
+                    let [node] = nodes.as_slice() else {
+                        return origin
+                            .error("via should be a host instead of a group", v.span())
+                            .err();
+                    };
+                    via = Some(node.ssh_host());
+                }
+                "local" => {
+                    let port = match &v {
+                        SpannedValue::Number(n) => {
+                            n.value().as_u64().and_then(|n| u16::try_from(n).ok())
+                        }
+                        _ => None,
+                    };
+                    local =
+                        Some(port.filter(|port| *port > 0).ok_or_else(|| {
+                            origin.error("local should be a port number", v.span())
+                        })?);
+                }
+                "remote" => {
+                    let address = match &v {
+                        SpannedValue::String(s) => Some(s.value().to_string()),
+                        _ => None,
+                    };
+                    let is_address = address.as_ref().is_some_and(|address| {
+                        address.rsplit_once(':').is_some_and(|(host, port)| {
+                            !host.is_empty() && port.parse::<u16>().is_ok()
+                        })
+                    });
+                    if !is_address {
+                        return origin
+                            .error(
+                                "remote should be an address in the form of host:port",
+                                v.span(),
+                            )
+                            .err();
+                    }
+                    remote = address;
+                }
+                _ => {}
+            }
+        }
+
+        let via = match (via, hosts) {
+            (Some(via), _) => via,
+            (None, [host]) => host.ssh_host(),
+            (None, _) => {
+                return origin
+                    .error(
+                        "port_forward needs via for a run with more than one host",
+                        &block.ident.span(),
+                    )
+                    .err();
+            }
+        };
+        let (Some(local), Some(remote)) = (local, remote) else {
+            return origin
+                .error("port_forward needs local and remote", &block.ident.span())
+                .err();
+        };
+        Ok(PortForward { via, local, remote })
+    }
+
+    /// Start forwarding and wait until the local port accepts connections.
+    /// The forward is torn down when the returned child is dropped
+    pub fn start(&self) -> Result<ForwardChild> {
+        let description = format!(
+            "port {} to {} through {}",
+            self.local, self.remote, self.via.host
+        );
+        // ssh would fail to listen on the port anyway, but then we couldn't tell
+        // whether the port accepting connections is from the forward
+        TcpListener::bind((Ipv4Addr::LOCALHOST, self.local))
+            .map_err(|e| anyhow!("can't forward {description}: {e}"))?;
+
+        let mut child = ForwardChild(
+            self.via
+                .forward_command(self.local, &self.remote)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| anyhow!("can't start ssh to forward {description}: {e}"))?,
+        );
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, self.local));
+        let start = Instant::now();
+        loop {
+            if child.0.try_wait()?.is_some() {
+                let mut stderr = String::new();
+                if let Some(mut output) = child.0.stderr.take() {
+                    let _ = output.read_to_string(&mut stderr);
+                }
+                return Err(anyhow!("can't forward {description}: {}", stderr.trim()));
+            }
+            if TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok() {
+                return Ok(child);
+            }
+            if start.elapsed() > FORWARD_TIMEOUT {
+                return Err(anyhow!("timed out forwarding {description}"));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// The ssh process of a port forward, which is killed when it's dropped
+pub struct ForwardChild(Child);
+
+impl Drop for ForwardChild {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, net::Ipv6Addr, path::PathBuf};
 
 #[derive(Clone)]
 pub enum HostOrGroup {
@@ -10,6 +10,9 @@ pub enum HostOrGroup {
 pub struct HostOrGroupConfig {
     pub host: HostOrGroup,
     pub vars: HashMap<String, hcl::Value>,
+    // the condition for the hosts to be in the group, which is evaluated
+    // with the facts of the host when it's connected
+    pub when: Option<hcl::Expression>,
 }
 
 #[derive(Clone)]
@@ -17,4 +20,153 @@ pub struct GroupConfig {
     pub hosts: Vec<HostOrGroupConfig>,
     pub vars: HashMap<String, hcl::Value>,
     pub imported: Option<PathBuf>,
+    // the runs on the hosts of the group need confirming, e.g. for production
+    pub protected: bool,
+}
+
+/// The host of a `host` block, which can be written as `user@host:port`,
+/// `user@[2001:db8::1]:port` or `ssh://user@host:port`, with the user and the port optional
+#[derive(Debug, PartialEq, Eq)]
+pub struct HostSpec {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl HostSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let rest = match spec.split_once("://") {
+            Some(("ssh", rest)) => rest.strip_suffix('/').unwrap_or(rest),
+            Some((scheme, _)) => return Err(format!("{scheme}:// isn't supported, use ssh://")),
+            None => spec,
+        };
+        let (user, address) = match rest.rsplit_once('@') {
+            Some(("", _)) => return Err("the user of the host is empty".to_string()),
+            Some((user, address)) => (Some(user.to_string()), address),
+            None => (None, rest),
+        };
+
+        let (host, port) = if let Some(address) = address.strip_prefix('[') {
+            let Some((host, port)) = address.split_once(']') else {
+                return Err("the IPv6 address of the host is missing ]".to_string());
+            };
+            if host.parse::<Ipv6Addr>().is_err() {
+                return Err(format!("{host} isn't an IPv6 address"));
+            }
+            let port = match port {
+                "" => None,
+                port => match port.strip_prefix(':') {
+                    Some(port) => Some(port),
+                    None => return Err(format!("unexpected {port} after the IPv6 address")),
+                },
+            };
+            (host, port)
+        } else if address.matches(':').count() > 1 {
+            // an IPv6 address without the port
+            if address.parse::<Ipv6Addr>().is_err() {
+                return Err(format!(
+                    "{address} isn't an IPv6 address, write it in [] with the port"
+                ));
+            }
+            (address, None)
+        } else {
+            match address.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            }
+        };
+
+        if host.is_empty() {
+            return Err("the host is empty".to_string());
+        }
+        let port = match port {
+            Some(port) => match port.parse::<u16>() {
+                Ok(port) if port > 0 => Some(port),
+                _ => return Err(format!("{port} isn't a valid port")),
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            user,
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// The name of the host in the runs, which is the address with the port if it has one
+    pub fn name(&self) -> String {
+        match self.port {
+            Some(port) if self.host.contains(':') => format!("[{}]:{port}", self.host),
+            Some(port) => format!("{}:{port}", self.host),
+            None => self.host.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spec(user: Option<&str>, host: &str, port: Option<u16>) -> HostSpec {
+        HostSpec {
+            user: user.map(|u| u.to_string()),
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parse_host_spec() {
+        assert_eq!(HostSpec::parse("web1"), Ok(spec(None, "web1", None)));
+        assert_eq!(
+            HostSpec::parse("deploy@web1:2222"),
+            Ok(spec(Some("deploy"), "web1", Some(2222)))
+        );
+        assert_eq!(
+            HostSpec::parse("user@[2001:db8::1]:2222"),
+            Ok(spec(Some("user"), "2001:db8::1", Some(2222)))
+        );
+        assert_eq!(
+            HostSpec::parse("2001:db8::1"),
+            Ok(spec(None, "2001:db8::1", None))
+        );
+        assert_eq!(
+            HostSpec::parse("ssh://deploy@10.0.0.1:22/"),
+            Ok(spec(Some("deploy"), "10.0.0.1", Some(22)))
+        );
+        assert_eq!(
+            HostSpec::parse("user@[2001:db8::1]:2222").unwrap().name(),
+            "[2001:db8::1]:2222"
+        );
+        assert_eq!(HostSpec::parse("deploy@web1").unwrap().name(), "web1");
+    }
+
+    #[test]
+    fn parse_host_spec_errors() {
+        assert_eq!(
+            HostSpec::parse("http://web1"),
+            Err("http:// isn't supported, use ssh://".to_string())
+        );
+        assert_eq!(
+            HostSpec::parse("@web1"),
+            Err("the user of the host is empty".to_string())
+        );
+        assert_eq!(
+            HostSpec::parse("web1:ssh"),
+            Err("ssh isn't a valid port".to_string())
+        );
+        assert_eq!(
+            HostSpec::parse("[2001:db8::1:2222"),
+            Err("the IPv6 address of the host is missing ]".to_string())
+        );
+        assert_eq!(
+            HostSpec::parse("2001:db8::g:2222"),
+            Err("2001:db8::g:2222 isn't an IPv6 address, write it in [] with the port".to_string())
+        );
+        assert_eq!(
+            HostSpec::parse("user@:22"),
+            Err("the host is empty".to_string())
+        );
+    }
 }
@@ -0,0 +1,105 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::run::RunResult;
+
+/// The record of executing a set of runbooks, stored in the run history
+#[derive(Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: Uuid,
+    pub runbooks: Vec<PathBuf>,
+    pub dry_run: bool,
+    // unix timestamps in seconds
+    pub started: u64,
+    pub finished: u64,
+    pub success: bool,
+    pub runs: Vec<RunResult>,
+}
+
+impl RunRecord {
+    pub fn new(runbooks: Vec<PathBuf>, dry_run: bool) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            runbooks,
+            dry_run,
+            started: now(),
+            finished: 0,
+            success: false,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self, runs: Vec<RunResult>) {
+        self.finished = now();
+        self.success = runs.iter().all(|r| r.success);
+        self.runs = runs;
+    }
+
+    /// Save the record to the history directory as `<id>.json`
+    pub fn save(&self) -> Result<()> {
+        let dir = history_dir().ok_or_else(|| anyhow!("can't find the history directory"))?;
+        std::fs::create_dir_all(&dir)?;
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(dir.join(format!("{}.json", self.id)), content)?;
+        Ok(())
+    }
+}
+
+/// All the records in the run history, oldest first
+pub fn load_records() -> Result<Vec<RunRecord>> {
+    let dir = history_dir().ok_or_else(|| anyhow!("can't find the history directory"))?;
+    let mut records = Vec::new();
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(records);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path)?;
+        // records that can't be parsed, e.g. from an incompatible version, are skipped
+        if let Ok(record) = serde_json::from_str::<RunRecord>(&content) {
+            records.push(record);
+        }
+    }
+    records.sort_by_key(|r| r.started);
+    Ok(records)
+}
+
+/// The latest record of executing the runbooks that isn't a dry run, if there's any
+pub fn last_record(runbooks: &[PathBuf]) -> Result<Option<RunRecord>> {
+    Ok(load_records()?
+        .into_iter()
+        .rev()
+        .find(|record| !record.dry_run && record.runbooks == runbooks))
+}
+
+/// The directory where the run history is stored
+pub fn history_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("history"))
+}
+
+/// The directory where tiron keeps its data, e.g. the run history
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(data_dir.join("tiron"))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,257 @@
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Result};
+use hcl::{eval::Context, Map};
+use hcl_edit::{
+    structure::{Block, Structure},
+    Span,
+};
+use itertools::Itertools;
+use tiron_common::{
+    error::{Error, Origin},
+    value::SpannedValue,
+};
+
+use crate::{
+    log,
+    node::ActionStatus,
+    run::{format_labels, RunResult},
+    schedule::string_list,
+};
+
+/// A command executed on this machine before or after the runs, e.g. to notify
+/// a deploy channel, with the summary of the runs in the env vars
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hook {
+    // the program and its args
+    command: Vec<String>,
+}
+
+/// The `pre_run` and `post_run` hooks of the project or a run
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub pre_run: Vec<Hook>,
+    pub post_run: Vec<Hook>,
+}
+
+impl Hooks {
+    /// Add the hook of the block if it's `pre_run` or `post_run`
+    pub fn add_block(&mut self, origin: &Origin, block: &Block) -> Result<(), Error> {
+        let hooks = match block.ident.as_str() {
+            "pre_run" => &mut self.pre_run,
+            "post_run" => &mut self.post_run,
+            _ => return Ok(()),
+        };
+        hooks.push(Hook::from_block(origin, block)?);
+        Ok(())
+    }
+
+    /// Execute the `pre_run` hooks one after another, and stop at the first failed one
+    pub fn pre_run(&self, env: &[(String, String)]) -> Result<()> {
+        for hook in &self.pre_run {
+            hook.execute("pre_run", env)?;
+        }
+        Ok(())
+    }
+
+    /// Execute the `post_run` hooks. The runs have finished by then,
+    /// so the failed hooks are only logged
+    pub fn post_run(&self, env: &[(String, String)]) {
+        for hook in &self.post_run {
+            if let Err(e) = hook.execute("post_run", env) {
+                log::warn("hook", "post_run failed", &[("error", &e)]);
+            }
+        }
+    }
+}
+
+impl Hook {
+    fn from_block(origin: &Origin, block: &Block) -> Result<Self, Error> {
+        let ctx = Context::new();
+        let kind = block.ident.as_str();
+        let mut command = Vec::new();
+        for structure in block.body.iter() {
+            let Structure::Attribute(a) = structure else {
+                continue;
+            };
+            if a.key.as_str() == "command" {
+                let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+                command = string_list(origin, &v, "command")?;
+            }
+        }
+        if command.is_empty() {
+            return origin
+                .error(format!("{kind} needs a command"), &block.ident.span())
+                .err();
+        }
+        Ok(Self { command })
+    }
+
+    /// Execute the command with the env vars, and returns an error if it fails.
+    /// The output goes to the log, as the terminal belongs to the TUI
+    fn execute(&self, kind: &str, env: &[(String, String)]) -> Result<()> {
+        let command = self.command.join(" ");
+        log::info("hook", "execute", &[("hook", &kind), ("command", &command)]);
+        let output = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .envs(env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| anyhow!("can't execute {kind} hook {command}: {e}"))?;
+        log::debug(
+            "hook",
+            "output",
+            &[
+                ("hook", &kind),
+                ("stdout", &String::from_utf8_lossy(&output.stdout).trim()),
+                ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+            ],
+        );
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{kind} hook {command} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The env vars of the hooks that describe the runs before they start
+pub fn start_env(
+    id: &str,
+    runbooks: &[String],
+    hosts: &[&str],
+    labels: &Map<String, String>,
+) -> Vec<(String, String)> {
+    vec![
+        ("TIRON_RUN_ID".to_string(), id.to_string()),
+        ("TIRON_RUNBOOKS".to_string(), runbooks.join(",")),
+        ("TIRON_HOSTS".to_string(), hosts.iter().unique().join(",")),
+        ("TIRON_LABELS".to_string(), format_labels(labels)),
+    ]
+}
+
+/// The env vars of the `post_run` hooks, which add the summary of the results
+/// to the ones from [`start_env`]
+pub fn summary_env(
+    mut env: Vec<(String, String)>,
+    results: &[RunResult],
+    duration: u64,
+) -> Vec<(String, String)> {
+    let hosts = results.iter().flat_map(|result| &result.hosts);
+    let success = results.iter().all(|result| result.success);
+    env.extend([
+        ("TIRON_SUCCESS".to_string(), success.to_string()),
+        (
+            "TIRON_FAILED_HOSTS".to_string(),
+            hosts
+                .clone()
+                .filter(|host| !host.success)
+                .map(|host| host.host.as_str())
+                .unique()
+                .join(","),
+        ),
+        (
+            "TIRON_FAILED_ACTIONS".to_string(),
+            hosts
+                .flat_map(|host| &host.actions)
+                .filter(|action| action.status == ActionStatus::Failed)
+                .count()
+                .to_string(),
+        ),
+        ("TIRON_DURATION".to_string(), duration.to_string()),
+    ]);
+    env
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn parse(data: &str) -> Result<Hooks, Error> {
+        let origin = Origin {
+            cwd: PathBuf::from("/srv/project"),
+            path: PathBuf::from("/srv/project/tiron.hcl"),
+            data: data.to_string(),
+        };
+        let body = hcl_edit::parser::parse_body(data).unwrap();
+        let mut hooks = Hooks::default();
+        for block in body.iter().filter_map(|s| s.as_block()) {
+            hooks.add_block(&origin, block)?;
+        }
+        Ok(hooks)
+    }
+
+    #[test]
+    fn parse_hooks() {
+        let hooks = parse(
+            r#"
+                pre_run {
+                  command = ["./notify.sh", "deploy started"]
+                }
+                post_run {
+                  command = ["./release.sh"]
+                }
+            "#,
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            hooks.pre_run,
+            vec![Hook {
+                command: vec!["./notify.sh".to_string(), "deploy started".to_string()]
+            }]
+        );
+        assert_eq!(hooks.post_run.len(), 1);
+
+        let e = parse("pre_run {}").err().unwrap();
+        assert_eq!(e.message, "pre_run needs a command");
+        let e = parse("post_run {\n command = \"./release.sh\"\n}")
+            .err()
+            .unwrap();
+        assert_eq!(e.message, "command should be a list of strings");
+    }
+
+    #[cfg(unix)]
+    fn sh(command: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), command.to_string()]
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hooks_get_the_env() {
+        let labels = Map::from_iter([("ticket".to_string(), "OPS-123".to_string())]);
+        let env = start_env(
+            "abc",
+            &["main.tr".to_string()],
+            &["web1", "web2", "web1"],
+            &labels,
+        );
+        let env = summary_env(env, &[], 3);
+        assert!(env.contains(&("TIRON_HOSTS".to_string(), "web1,web2".to_string())));
+        assert!(env.contains(&("TIRON_SUCCESS".to_string(), "true".to_string())));
+
+        let hooks = Hooks {
+            pre_run: vec![Hook {
+                command: sh(
+                    "test \"$TIRON_RUN_ID:$TIRON_LABELS:$TIRON_DURATION\" = abc:ticket=OPS-123:3",
+                ),
+            }],
+            post_run: Vec::new(),
+        };
+        hooks.pre_run(&env).unwrap();
+        let hooks = Hooks {
+            pre_run: vec![Hook {
+                command: sh("echo broken >&2; exit 1"),
+            }],
+            post_run: Vec::new(),
+        };
+        assert_eq!(
+            hooks.pre_run(&env).unwrap_err().to_string(),
+            "pre_run hook sh -c echo broken >&2; exit 1 failed: broken"
+        );
+    }
+}
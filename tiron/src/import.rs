@@ -0,0 +1,774 @@
+use std::{fmt::Write, path::PathBuf};
+
+use tiron_common::error::Error;
+
+use crate::yaml::{parse_yaml, Yaml};
+
+// the keywords of a task that aren't the module
+const TASK_KEYWORDS: [&str; 27] = [
+    "name",
+    "when",
+    "notify",
+    "register",
+    "become",
+    "become_user",
+    "tags",
+    "loop",
+    "with_items",
+    "with_dict",
+    "loop_control",
+    "ignore_errors",
+    "changed_when",
+    "failed_when",
+    "vars",
+    "args",
+    "delegate_to",
+    "run_once",
+    "environment",
+    "no_log",
+    "retries",
+    "until",
+    "delay",
+    "check_mode",
+    "diff",
+    "listen",
+    "throttle",
+];
+
+/// Convert an Ansible playbook into a Tiron runbook, with TODO comments
+/// for what can't be converted
+pub fn import_playbook(playbook: String, output: Option<String>) -> Result<(), Error> {
+    let path = PathBuf::from(&playbook);
+    let data = std::fs::read_to_string(&path)
+        .map_err(|e| Error::new(format!("can't read playbook {playbook}: {e}")))?;
+    let yaml = parse_yaml(&data)
+        .map_err(|e| Error::new(format!("can't parse playbook {playbook}: {e}")))?;
+
+    let output = output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| path.with_extension("tr"));
+    if output.exists() {
+        return Error::new(format!(
+            "{} already exists, give another output with --output",
+            output.to_string_lossy()
+        ))
+        .err();
+    }
+
+    let mut converter = Converter::default();
+    converter.playbook(&yaml, &playbook);
+    std::fs::write(&output, &converter.out).map_err(|e| {
+        Error::new(format!(
+            "can't write runbook {}: {e}",
+            output.to_string_lossy()
+        ))
+    })?;
+
+    println!("imported {playbook} into {}", output.to_string_lossy());
+    if converter.todos > 0 {
+        println!(
+            "there are {} TODOs in it for what couldn't be converted",
+            converter.todos
+        );
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Converter {
+    out: String,
+    todos: usize,
+}
+
+impl Converter {
+    fn line(&mut self, indent: usize, line: &str) {
+        let _ = writeln!(self.out, "{}{line}", "  ".repeat(indent));
+    }
+
+    fn todo(&mut self, indent: usize, message: &str) {
+        self.line(indent, &format!("# TODO: {message}"));
+        self.todos += 1;
+    }
+
+    fn playbook(&mut self, yaml: &Yaml, name: &str) {
+        self.line(0, &format!("# imported from the Ansible playbook {name}"));
+        let Yaml::List(plays) = yaml else {
+            self.todo(0, "the playbook should be a list of plays");
+            return;
+        };
+        for play in plays {
+            self.out.push('\n');
+            self.play(play);
+        }
+    }
+
+    fn play(&mut self, play: &Yaml) {
+        let Yaml::Map(keys) = play else {
+            self.todo(0, "the play should be a mapping");
+            return;
+        };
+        if let Some(playbook) = play.get("import_playbook").or_else(|| play.get("include")) {
+            self.todo(
+                0,
+                &format!(
+                    "import {} as well, e.g. with `use`",
+                    playbook.as_str().unwrap_or("the playbook")
+                ),
+            );
+            return;
+        }
+
+        let hosts = match play.get("hosts") {
+            Some(Yaml::String(hosts)) => hosts.clone(),
+            Some(Yaml::List(hosts)) => hosts
+                .iter()
+                .filter_map(|host| host.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => String::new(),
+        };
+        let group = hosts
+            .split([',', ':'])
+            .map(|h| h.trim())
+            .find(|h| !h.is_empty())
+            .unwrap_or("localhost");
+        if hosts.contains([',', ':', '!', '&', '*']) {
+            self.todo(
+                0,
+                &format!("the hosts pattern \"{hosts}\" needs a group in Tiron"),
+            );
+        }
+
+        self.line(0, &format!("run {} {{", hcl_string(group).0));
+        if let Some(name) = play.get("name").and_then(|n| n.as_str()) {
+            self.line(1, &format!("name = {}", hcl_string(name).0));
+        }
+        if let Some(user) = play.get("remote_user").and_then(|u| u.as_str()) {
+            self.line(1, &format!("remote_user = {}", hcl_string(user).0));
+        }
+        if let Some(become_) = play.get("become").and_then(|b| b.as_bool()) {
+            self.line(1, &format!("become = {become_}"));
+        }
+        for (key, _) in keys {
+            match key.as_str() {
+                "hosts" | "name" | "remote_user" | "become" | "gather_facts" | "pre_tasks"
+                | "tasks" | "post_tasks" => {}
+                key => self.todo(1, &format!("the play's `{key}` isn't converted")),
+            }
+        }
+
+        for section in ["pre_tasks", "tasks", "post_tasks"] {
+            if let Some(Yaml::List(tasks)) = play.get(section) {
+                for task in tasks {
+                    self.out.push('\n');
+                    self.task(task);
+                }
+            }
+        }
+        self.line(0, "}");
+    }
+
+    fn task(&mut self, task: &Yaml) {
+        let Yaml::Map(keys) = task else {
+            self.todo(1, "the task should be a mapping");
+            return;
+        };
+        if let Some(Yaml::List(tasks)) = task.get("block") {
+            if task.get("rescue").is_some() || task.get("always").is_some() {
+                self.todo(
+                    1,
+                    "the rescue and always of the block aren't converted, \
+                     put them in `rescue` and `always` blocks",
+                );
+            }
+            for (i, task) in tasks.iter().enumerate() {
+                if i > 0 {
+                    self.out.push('\n');
+                }
+                self.task(task);
+            }
+            return;
+        }
+
+        let Some((module, args)) = keys
+            .iter()
+            .find(|(key, _)| !TASK_KEYWORDS.contains(&key.as_str()))
+        else {
+            self.todo(1, "the task doesn't have a module");
+            return;
+        };
+        let module = module
+            .strip_prefix("ansible.builtin.")
+            .unwrap_or(module)
+            .to_string();
+        let name = task.get("name").and_then(|n| n.as_str());
+
+        let mut todos = Vec::new();
+        for (key, _) in keys {
+            if key != "name" && TASK_KEYWORDS.contains(&key.as_str()) {
+                todos.push(format!("the task's `{key}` isn't converted"));
+            }
+        }
+
+        let actions = match module.as_str() {
+            "copy" | "template" => copy(&module, args, &mut todos),
+            "package" | "apt" | "yum" | "dnf" => package(args, &mut todos),
+            "service" | "systemd" => service(args, &mut todos),
+            "command" | "shell" => command(&module, args, &mut todos),
+            "file" => file(args, &mut todos),
+            "git" => git(args, &mut todos),
+            "user" => user(args, &mut todos),
+            "cron" => cron(args, &mut todos),
+            "acl" | "ansible.posix.acl" => acl(args, &mut todos),
+            "unarchive" => unarchive(args, &mut todos),
+            "archive" | "community.general.archive" => archive(args, &mut todos),
+            _ => {
+                todos.push(format!("the module `{module}` isn't supported"));
+                Vec::new()
+            }
+        };
+
+        for todo in &todos {
+            self.todo(1, todo);
+        }
+        if actions.is_empty() {
+            if let Some(name) = name {
+                self.line(1, &format!("# {name}"));
+            }
+            return;
+        }
+        for (i, (action, params)) in actions.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            self.line(1, &format!("action \"{action}\" {{"));
+            if let Some(name) = name {
+                let (name, _) = hcl_string(name);
+                self.line(2, &format!("name = {name}"));
+                self.out.push('\n');
+            }
+            self.line(2, "params {");
+            for (key, value) in params {
+                self.line(3, &format!("{key} = {value}"));
+            }
+            self.line(2, "}");
+            self.line(1, "}");
+        }
+    }
+}
+
+// the actions converted from a task, with their params in HCL
+type Actions = Vec<(&'static str, Vec<(&'static str, String)>)>;
+
+fn copy(module: &str, args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    let action = if module == "template" {
+        todos.push("convert the template from Jinja to the HCL template syntax".to_string());
+        "template"
+    } else {
+        "copy"
+    };
+    if args.get("content").is_some() {
+        todos.push("the content isn't supported, put it in a file for `src`".to_string());
+    }
+    unsupported_args(&args, &["src", "dest", "content"], todos);
+    let params = params(&args, &[("src", "src"), ("dest", "dest")], todos);
+    vec![(action, params)]
+}
+
+fn package(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    unsupported_args(&args, &["name", "pkg", "state"], todos);
+    let state = match args.get("state").and_then(|s| s.as_str()) {
+        None | Some("present" | "installed") => "present",
+        Some("absent" | "removed") => "absent",
+        Some("latest") => "latest",
+        Some(state) => {
+            todos.push(format!("the package state `{state}` isn't supported"));
+            "present"
+        }
+    };
+    let mut params = params(&args, &[("name", "name"), ("pkg", "name")], todos);
+    params.push(("state", hcl_string(state).0));
+    vec![("package", params)]
+}
+
+fn service(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    unsupported_args(&args, &["name", "state", "enabled"], todos);
+    if args.get("name").is_none() {
+        todos.push("the service doesn't have a name".to_string());
+        return Vec::new();
+    }
+    let mut params = params(&args, &[("name", "name")], todos);
+    if let Some(state) = args.get("state").and_then(|s| s.as_str()) {
+        match state {
+            "started" | "stopped" | "restarted" | "reloaded" => {
+                params.push(("state", hcl_string(state).0))
+            }
+            state => todos.push(format!("the service state `{state}` isn't supported")),
+        }
+    }
+    if let Some(enabled) = args.get("enabled").and_then(|e| e.as_bool()) {
+        params.push(("enabled", enabled.to_string()));
+    }
+    if params.len() == 1 {
+        return Vec::new();
+    }
+    vec![("service", params)]
+}
+
+fn command(module: &str, args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let (line, args) = match args {
+        Yaml::String(line) => (Some(line.clone()), Yaml::Map(Vec::new())),
+        args => (
+            args.get("cmd")
+                .and_then(|c| c.as_str())
+                .map(|c| c.to_string()),
+            args.clone(),
+        ),
+    };
+    unsupported_args(&args, &["cmd", "argv"], todos);
+
+    let argv: Vec<String> = if module == "shell" {
+        let Some(line) = line else {
+            todos.push("the shell doesn't have a command".to_string());
+            return Vec::new();
+        };
+        vec!["sh".to_string(), "-c".to_string(), line]
+    } else if let Some(Yaml::List(argv)) = args.get("argv") {
+        argv.iter()
+            .filter_map(|a| a.as_str().map(|a| a.to_string()))
+            .collect()
+    } else if let Some(line) = line {
+        split_command(&line)
+    } else {
+        Vec::new()
+    };
+    let Some((cmd, rest)) = argv.split_first() else {
+        todos.push("the command doesn't have a command".to_string());
+        return Vec::new();
+    };
+
+    let mut params = vec![("cmd", convert_string(cmd, todos))];
+    if !rest.is_empty() {
+        let rest: Vec<&str> = rest.iter().map(|a| a.as_str()).collect();
+        params.push(("args", hcl_list(&rest, todos)));
+    }
+    vec![("command", params)]
+}
+
+fn file(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    unsupported_args(
+        &args,
+        &[
+            "path", "dest", "name", "state", "src", "owner", "group", "mode", "recurse",
+        ],
+        todos,
+    );
+    let mut params = params(
+        &args,
+        &[
+            ("path", "path"),
+            ("dest", "path"),
+            ("name", "path"),
+            ("src", "src"),
+            ("owner", "owner"),
+            ("group", "group"),
+        ],
+        todos,
+    );
+    if let Some(mode) = args.get("mode").and_then(|m| m.as_str()) {
+        // only the octal modes, not the symbolic ones like u=rw,g=r
+        if !mode.is_empty() && mode.trim_start_matches("0o").chars().all(|c| c.is_digit(8)) {
+            params.push(("mode", hcl_string(mode).0));
+        } else {
+            todos.push(format!("the file mode `{mode}` isn't supported"));
+        }
+    }
+    if let Some(recurse) = args.get("recurse").and_then(|r| r.as_bool()) {
+        params.push(("recurse", recurse.to_string()));
+    }
+    match args.get("state").and_then(|s| s.as_str()) {
+        Some(state @ ("file" | "absent" | "directory" | "link" | "hard")) => {
+            params.push(("state", hcl_string(state).0));
+        }
+        Some(state) => todos.push(format!("the file state `{state}` isn't supported")),
+        None => {}
+    }
+    vec![("file", params)]
+}
+
+fn git(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    unsupported_args(&args, &["repo", "dest"], todos);
+    let params = params(&args, &[("repo", "repo"), ("dest", "dest")], todos);
+    vec![("git", params)]
+}
+
+fn user(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    unsupported_args(
+        &args,
+        &[
+            "name", "state", "uid", "groups", "shell", "home", "password",
+        ],
+        todos,
+    );
+    let mut params = params(
+        &args,
+        &[
+            ("name", "name"),
+            ("shell", "shell"),
+            ("home", "home"),
+            ("password", "password_hash"),
+        ],
+        todos,
+    );
+    match args.get("state").and_then(|s| s.as_str()) {
+        Some(state @ ("present" | "absent")) => params.push(("state", hcl_string(state).0)),
+        Some(state) => todos.push(format!("the user state `{state}` isn't supported")),
+        None => {}
+    }
+    if let Some(uid) = args.get("uid").and_then(|u| u.as_str()) {
+        match uid.parse::<u32>() {
+            Ok(uid) => params.push(("uid", uid.to_string())),
+            Err(_) => todos.push(format!("the uid `{uid}` isn't supported")),
+        }
+    }
+    // the groups can be a list or separated by commas
+    let groups: Vec<&str> = match args.get("groups") {
+        Some(Yaml::List(groups)) => groups.iter().filter_map(|g| g.as_str()).collect(),
+        Some(Yaml::String(groups)) => groups.split(',').map(|g| g.trim()).collect(),
+        _ => Vec::new(),
+    };
+    if !groups.is_empty() {
+        params.push(("groups", hcl_list(&groups, todos)));
+    }
+    vec![("user", params)]
+}
+
+fn cron(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    let names = [
+        ("name", "name"),
+        ("minute", "minute"),
+        ("hour", "hour"),
+        ("day", "day"),
+        ("month", "month"),
+        ("weekday", "weekday"),
+        ("job", "job"),
+        ("user", "user"),
+    ];
+    let supported: Vec<&str> = names.iter().map(|(arg, _)| *arg).chain(["state"]).collect();
+    unsupported_args(&args, &supported, todos);
+    let mut params = params(&args, &names, todos);
+    match args.get("state").and_then(|s| s.as_str()) {
+        Some(state @ ("present" | "absent")) => params.push(("state", hcl_string(state).0)),
+        Some(state) => todos.push(format!("the cron state `{state}` isn't supported")),
+        None => {}
+    }
+    vec![("cron", params)]
+}
+
+fn acl(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    let names = [
+        ("path", "path"),
+        ("name", "path"),
+        ("entity", "entity"),
+        ("permissions", "permissions"),
+    ];
+    let supported: Vec<&str> = names
+        .iter()
+        .map(|(arg, _)| *arg)
+        .chain(["etype", "state", "default", "recursive"])
+        .collect();
+    unsupported_args(&args, &supported, todos);
+    let mut params = params(&args, &names, todos);
+    match args.get("etype").and_then(|e| e.as_str()) {
+        Some(etype @ ("user" | "group" | "mask" | "other")) => {
+            params.push(("etype", hcl_string(etype).0))
+        }
+        Some(etype) => todos.push(format!("the acl etype `{etype}` isn't supported")),
+        None => {}
+    }
+    for arg in ["default", "recursive"] {
+        if let Some(value) = args.get(arg).and_then(|v| v.as_bool()) {
+            params.push((arg, value.to_string()));
+        }
+    }
+    match args.get("state").and_then(|s| s.as_str()) {
+        Some(state @ ("present" | "absent")) => params.push(("state", hcl_string(state).0)),
+        Some(state) => todos.push(format!("the acl state `{state}` isn't supported")),
+        None => {}
+    }
+    vec![("acl", params)]
+}
+
+fn unarchive(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    let names = [("src", "src"), ("dest", "dest"), ("creates", "creates")];
+    let supported: Vec<&str> = names
+        .iter()
+        .map(|(arg, _)| *arg)
+        .chain(["remote_src", "extra_opts"])
+        .collect();
+    unsupported_args(&args, &supported, todos);
+    let mut params = params(&args, &names, todos);
+    if let Some(remote_src) = args.get("remote_src").and_then(|r| r.as_bool()) {
+        params.push(("remote_src", remote_src.to_string()));
+    }
+    // only --strip-components of the tar options has a param
+    if let Some(Yaml::List(opts)) = args.get("extra_opts") {
+        for opt in opts.iter().filter_map(|o| o.as_str()) {
+            match opt
+                .strip_prefix("--strip-components=")
+                .and_then(|n| n.parse::<u64>().ok())
+            {
+                Some(n) => params.push(("strip_components", n.to_string())),
+                None => todos.push(format!("the unarchive option `{opt}` isn't supported")),
+            }
+        }
+    }
+    vec![("unarchive", params)]
+}
+
+fn archive(args: &Yaml, todos: &mut Vec<String>) -> Actions {
+    let args = module_args(args);
+    let names = [
+        ("path", "path"),
+        ("dest", "dest"),
+        ("exclusion_patterns", "exclude"),
+    ];
+    let supported: Vec<&str> = names
+        .iter()
+        .map(|(arg, _)| *arg)
+        .chain(["format"])
+        .collect();
+    unsupported_args(&args, &supported, todos);
+    let mut params = params(&args, &names, todos);
+    match args.get("format").and_then(|f| f.as_str()) {
+        Some(format @ ("gz" | "xz" | "bz2" | "tar" | "zip")) => {
+            params.push(("format", hcl_string(format).0))
+        }
+        Some(format) => todos.push(format!("the archive format `{format}` isn't supported")),
+        None => {}
+    }
+    vec![("archive", params)]
+}
+
+/// The args of a module, which can be `key=value` pairs in a string
+fn module_args(args: &Yaml) -> Yaml {
+    match args {
+        Yaml::String(line) => Yaml::Map(
+            split_command(line)
+                .into_iter()
+                .filter_map(|arg| {
+                    let (key, value) = arg.split_once('=')?;
+                    Some((key.to_string(), Yaml::String(value.to_string())))
+                })
+                .collect(),
+        ),
+        args => args.clone(),
+    }
+}
+
+fn unsupported_args(args: &Yaml, supported: &[&str], todos: &mut Vec<String>) {
+    if let Yaml::Map(args) = args {
+        for (key, _) in args {
+            if !supported.contains(&key.as_str()) {
+                todos.push(format!("the module's `{key}` isn't supported"));
+            }
+        }
+    }
+}
+
+/// The params from the args of the module, with the names of the args and the params
+fn params(
+    args: &Yaml,
+    names: &[(&str, &'static str)],
+    todos: &mut Vec<String>,
+) -> Vec<(&'static str, String)> {
+    let mut params: Vec<(&'static str, String)> = Vec::new();
+    for (arg, param) in names {
+        if params.iter().any(|(p, _)| p == param) {
+            continue;
+        }
+        let value = match args.get(arg) {
+            Some(Yaml::String(s)) => convert_string(s, todos),
+            Some(Yaml::List(list)) => {
+                let list: Vec<&str> = list.iter().filter_map(|v| v.as_str()).collect();
+                hcl_list(&list, todos)
+            }
+            _ => continue,
+        };
+        params.push((*param, value));
+    }
+    params
+}
+
+fn hcl_list(list: &[&str], todos: &mut Vec<String>) -> String {
+    let items: Vec<String> = list.iter().map(|s| convert_string(s, todos)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn convert_string(s: &str, todos: &mut Vec<String>) -> String {
+    let (s, converted) = hcl_string(s);
+    if !converted {
+        todos.push(format!("convert the Jinja expressions in {s}"));
+    }
+    s
+}
+
+/// The HCL string of the value, with the Jinja variables like `{{ name }}` turned into
+/// `${name}`, and whether all the Jinja expressions could be converted
+fn hcl_string(s: &str) -> (String, bool) {
+    let mut out = String::from("\"");
+    let mut converted = true;
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        push_escaped(&mut out, &rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let inner = rest[start + 2..start + end].trim();
+        let is_var = inner
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && inner
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if is_var {
+            let var = match inner {
+                "inventory_hostname" => "tiron.host",
+                inner => inner,
+            };
+            out.push_str(&format!("${{{var}}}"));
+        } else {
+            converted = false;
+            push_escaped(&mut out, &rest[start..start + end + 2]);
+        }
+        rest = &rest[start + end + 2..];
+    }
+    push_escaped(&mut out, rest);
+    out.push('"');
+    (out, converted)
+}
+
+fn push_escaped(out: &mut String, s: &str) {
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                out.push(c);
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+/// Split a command line into the arguments at the spaces, except in quotes
+fn split_command(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => arg.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(arg);
+    }
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_playbook() {
+        let yaml = parse_yaml(
+            r#"
+- name: web servers
+  hosts: webservers
+  become: true
+  tasks:
+    - name: install nginx
+      ansible.builtin.apt:
+        name: nginx
+        update_cache: yes
+    - name: start nginx
+      service: name=nginx state=started
+    - command: echo "{{ inventory_hostname }}" done
+      register: out
+"#,
+        )
+        .unwrap();
+        let mut converter = Converter::default();
+        converter.playbook(&yaml, "site.yml");
+        assert_eq!(converter.todos, 2);
+        assert_eq!(
+            converter.out,
+            r#"# imported from the Ansible playbook site.yml
+
+run "webservers" {
+  name = "web servers"
+  become = true
+
+  # TODO: the module's `update_cache` isn't supported
+  action "package" {
+    name = "install nginx"
+
+    params {
+      name = "nginx"
+      state = "present"
+    }
+  }
+
+  action "service" {
+    name = "start nginx"
+
+    params {
+      name = "nginx"
+      state = "started"
+    }
+  }
+
+  # TODO: the task's `register` isn't converted
+  action "command" {
+    params {
+      cmd = "echo"
+      args = ["${tiron.host}", "done"]
+    }
+  }
+}
+"#
+        );
+    }
+}
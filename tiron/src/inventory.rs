@@ -0,0 +1,249 @@
+use std::{collections::HashMap, path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+
+use crate::group::{GroupConfig, HostOrGroup, HostOrGroupConfig};
+
+/// Load the groups from an inventory source, with the attributes in the `inventory` block
+pub fn load_inventory(
+    kind: &str,
+    cwd: &Path,
+    attrs: &HashMap<String, hcl::Value>,
+) -> Result<Vec<(String, GroupConfig)>> {
+    match kind {
+        "terraform" => terraform(cwd, attrs),
+        "vagrant" => vagrant(cwd, attrs),
+        "libvirt" => libvirt(attrs),
+        _ => Err(anyhow!("unknown inventory {kind}")),
+    }
+}
+
+fn string_attr<'a>(attrs: &'a HashMap<String, hcl::Value>, name: &str) -> Result<Option<&'a str>> {
+    match attrs.get(name) {
+        Some(hcl::Value::String(s)) => Ok(Some(s.as_str())),
+        Some(_) => Err(anyhow!("{name} should be a string")),
+        None => Ok(None),
+    }
+}
+
+fn group(hosts: Vec<HostOrGroupConfig>) -> GroupConfig {
+    GroupConfig {
+        hosts,
+        vars: HashMap::new(),
+        imported: None,
+        protected: false,
+    }
+}
+
+fn host(name: String, vars: HashMap<String, hcl::Value>) -> HostOrGroupConfig {
+    HostOrGroupConfig {
+        host: HostOrGroup::Host(name),
+        vars,
+        when: None,
+    }
+}
+
+/// Groups from the outputs of Terraform, either from the `state` file
+/// or from `terraform output -json` in `dir`.
+///
+/// Every output becomes a group with the same name (plus `prefix`) if its value is
+/// - a string, which is the host
+/// - a list of strings, which are the hosts
+/// - a list of objects, which are the vars of the hosts with the host in `host`
+/// - an object of objects, which are the vars of the hosts keyed by the host
+fn terraform(
+    cwd: &Path,
+    attrs: &HashMap<String, hcl::Value>,
+) -> Result<Vec<(String, GroupConfig)>> {
+    let outputs = if let Some(state) = string_attr(attrs, "state")? {
+        let path = cwd.join(state);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("can't read {}: {e}", path.to_string_lossy()))?;
+        let state: serde_json::Value = serde_json::from_str(&content)?;
+        state["outputs"].clone()
+    } else {
+        let dir = cwd.join(string_attr(attrs, "dir")?.unwrap_or("."));
+        let output = Command::new("terraform")
+            .args(["output", "-json"])
+            .current_dir(&dir)
+            .output()
+            .map_err(|e| anyhow!("can't run terraform: {e}"))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "terraform output failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        serde_json::from_slice(&output.stdout)?
+    };
+    let prefix = string_attr(attrs, "prefix")?.unwrap_or("");
+
+    let serde_json::Value::Object(outputs) = outputs else {
+        return Err(anyhow!("terraform doesn't have any outputs"));
+    };
+
+    let mut groups = Vec::new();
+    for (name, output) in outputs {
+        if let Some(hosts) = terraform_hosts(&output["value"])? {
+            groups.push((format!("{prefix}{name}"), group(hosts)));
+        }
+    }
+    Ok(groups)
+}
+
+fn terraform_hosts(value: &serde_json::Value) -> Result<Option<Vec<HostOrGroupConfig>>> {
+    let hosts = match value {
+        serde_json::Value::String(s) => vec![host(s.to_string(), HashMap::new())],
+        serde_json::Value::Array(items) => {
+            let mut hosts = Vec::new();
+            for item in items {
+                match item {
+                    serde_json::Value::String(s) => {
+                        hosts.push(host(s.to_string(), HashMap::new()));
+                    }
+                    serde_json::Value::Object(o) => {
+                        let Some(name) = o.get("host").and_then(|h| h.as_str()) else {
+                            return Ok(None);
+                        };
+                        hosts.push(host(name.to_string(), json_vars(o)?));
+                    }
+                    _ => return Ok(None),
+                }
+            }
+            hosts
+        }
+        serde_json::Value::Object(o) => {
+            let mut hosts = Vec::new();
+            for (name, vars) in o {
+                let serde_json::Value::Object(vars) = vars else {
+                    return Ok(None);
+                };
+                hosts.push(host(name.to_string(), json_vars(vars)?));
+            }
+            hosts
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(hosts))
+}
+
+fn json_vars(
+    o: &serde_json::Map<String, serde_json::Value>,
+) -> Result<HashMap<String, hcl::Value>> {
+    let mut vars = HashMap::new();
+    for (key, value) in o {
+        if key != "host" {
+            vars.insert(key.to_string(), hcl::to_value(value)?);
+        }
+    }
+    Ok(vars)
+}
+
+fn command_output(cmd: &mut Command, name: &str) -> Result<String> {
+    let output = cmd.output().map_err(|e| anyhow!("can't run {name}: {e}"))?;
+    if !output.status.success() && output.stdout.is_empty() {
+        return Err(anyhow!(
+            "{name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The Vagrant machines in `dir` from `vagrant ssh-config`, as the group `group`
+/// which defaults to "vagrant". The ssh settings of the machines are set as the
+/// `ssh_*` vars of the hosts.
+fn vagrant(cwd: &Path, attrs: &HashMap<String, hcl::Value>) -> Result<Vec<(String, GroupConfig)>> {
+    let dir = cwd.join(string_attr(attrs, "dir")?.unwrap_or("."));
+    let group_name = string_attr(attrs, "group")?.unwrap_or("vagrant");
+    // vagrant prints the machines that are up even if some of them aren't
+    let output = command_output(
+        Command::new("vagrant").arg("ssh-config").current_dir(&dir),
+        "vagrant ssh-config",
+    )?;
+
+    let mut hosts = Vec::new();
+    let mut current: Option<(String, HashMap<String, hcl::Value>)> = None;
+    let mut options = Vec::new();
+    for line in output.lines() {
+        let Some((key, value)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        if key == "Host" {
+            if let Some((name, mut vars)) = current.take() {
+                vars.insert(
+                    "ssh_options".to_string(),
+                    std::mem::take(&mut options).into(),
+                );
+                hosts.push(host(name, vars));
+            }
+            current = Some((value.to_string(), HashMap::new()));
+            continue;
+        }
+
+        let Some((_, vars)) = current.as_mut() else {
+            continue;
+        };
+        match key {
+            "HostName" => {
+                vars.insert("ssh_host".to_string(), value.into());
+            }
+            "Port" => {
+                vars.insert("ssh_port".to_string(), value.into());
+            }
+            "User" => {
+                vars.insert("remote_user".to_string(), value.into());
+            }
+            "IdentityFile" => {
+                vars.insert("ssh_identity_file".to_string(), value.into());
+            }
+            "StrictHostKeyChecking" | "UserKnownHostsFile" | "IdentitiesOnly" => {
+                options.push(hcl::Value::from(format!("{key}={value}")));
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, mut vars)) = current.take() {
+        vars.insert("ssh_options".to_string(), options.into());
+        hosts.push(host(name, vars));
+    }
+
+    Ok(vec![(group_name.to_string(), group(hosts))])
+}
+
+/// The running libvirt domains at `uri` with their IPv4 addresses, as the group `group`
+/// which defaults to "libvirt". The addresses come from `source`, which can be
+/// "lease", "agent" or "arp" as in `virsh domifaddr`. Domains without an address are skipped.
+fn libvirt(attrs: &HashMap<String, hcl::Value>) -> Result<Vec<(String, GroupConfig)>> {
+    let uri = string_attr(attrs, "uri")?.unwrap_or("qemu:///system");
+    let source = string_attr(attrs, "source")?.unwrap_or("lease");
+    let group_name = string_attr(attrs, "group")?.unwrap_or("libvirt");
+
+    let domains = command_output(
+        Command::new("virsh").args(["-c", uri, "list", "--name"]),
+        "virsh list",
+    )?;
+
+    let mut hosts = Vec::new();
+    for domain in domains.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        let addresses = command_output(
+            Command::new("virsh").args(["-c", uri, "domifaddr", domain, "--source", source]),
+            "virsh domifaddr",
+        )?;
+        // the lines look like ` vnet0  52:54:00:6b:3c:58  ipv4  192.168.122.45/24`
+        let address = addresses.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let i = fields.iter().position(|f| *f == "ipv4")?;
+            let address = fields.get(i + 1)?;
+            Some(address.split('/').next().unwrap_or(address).to_string())
+        });
+        if let Some(address) = address {
+            let mut vars = HashMap::new();
+            vars.insert("ssh_host".to_string(), address.into());
+            hosts.push(host(domain.to_string(), vars));
+        }
+    }
+
+    Ok(vec![(group_name.to_string(), group(hosts))])
+}
@@ -1,11 +1,85 @@
+//! Tiron can be embedded in other tools to execute runbooks without the TUI.
+//!
+//! The runbooks are parsed into runs with [`load_runs`], and the runs are executed
+//! with [`execute_runs`]. The progress of the runs is sent as [`Event`]s
+//! to the [`EventSink`] provided by the caller, e.g. a channel.
+//! The runs with `requires_approval` are refused unless the caller approved them
+//! and set [`Run::approved`].
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! let (tx, rx) = crossbeam_channel::unbounded::<tiron::Event>();
+//! std::thread::spawn(move || {
+//!     for event in rx {
+//!         if let tiron::Event::Run(tiron::RunEvent::RunCompleted { id, success }) = event {
+//!             println!("run {id} completed, success: {success}");
+//!         }
+//!     }
+//! });
+//!
+//! let runs = match tiron::load_runs(&tiron::runbook_paths(&["main".to_string()]), false) {
+//!     Ok(runs) => runs,
+//!     Err(e) => {
+//!         let _ = e.report_stderr();
+//!         return;
+//!     }
+//! };
+//! let events: Arc<dyn tiron::EventSink> = Arc::new(tx);
+//! let results = tiron::execute_runs(runs, &events);
+//! ```
+
+mod approval;
+mod attach;
+mod chatops;
 pub mod cli;
+mod config;
+mod container;
+mod control;
 pub mod core;
+mod diff;
 mod doc;
+mod drift;
+mod exec;
+mod filters;
 mod fmt;
+mod forward;
 mod group;
+mod history;
+mod hook;
+mod import;
+mod inventory;
 mod job;
 mod local;
+mod lock;
+mod log;
 mod node;
+mod plan;
 mod remote;
+mod replay;
+mod requires;
+mod results;
 mod run;
 mod runbook;
+mod schedule;
+mod shell;
+mod sink;
+mod testing;
+mod trace;
+mod vault;
+mod web;
+mod window;
+mod yaml;
+
+pub use crate::{
+    container::{Container, ContainerHost},
+    core::{execute_runs, load_runs, runbook_paths},
+    node::{Node, PendingChange},
+    run::{HostResult, Run, RunResult},
+    runbook::Runbook,
+    sink::{JsonSink, LogSink},
+};
+pub use tiron_common::{
+    error::Error,
+    event::{Event, EventSink, HostPhase, MultiSink, NullSink, RunEvent},
+};
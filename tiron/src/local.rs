@@ -1,11 +1,14 @@
 use anyhow::Result;
 use crossbeam_channel::{Receiver, Sender};
-use tiron_common::{action::ActionMessage, node::NodeMessage};
+use tiron_common::{
+    action::{ActionMessage, MESSAGE_BUFFER},
+    node::NodeMessage,
+};
 use tiron_node::node;
 
 pub fn start_local() -> (Sender<NodeMessage>, Receiver<ActionMessage>) {
     let (writer_tx, writer_rx) = crossbeam_channel::unbounded::<NodeMessage>();
-    let (reader_tx, reader_rx) = crossbeam_channel::unbounded::<ActionMessage>();
+    let (reader_tx, reader_rx) = crossbeam_channel::bounded::<ActionMessage>(MESSAGE_BUFFER);
 
     std::thread::spawn(move || -> Result<()> {
         node::mainloop(writer_rx, reader_tx)?;
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use tiron_common::node::LockHolder;
+use tiron_node::lock::{LockError, LockFile};
+
+use crate::{history::now, schedule::UtcTime};
+
+/// The holder of the locks for a run started by the current user now
+pub fn current_holder() -> LockHolder {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    LockHolder {
+        user,
+        since: UtcTime::from_unix(now()).to_string(),
+    }
+}
+
+/// Lock the hosts on this machine, so that the runs from here don't run against
+/// the same host at the same time. The hosts are unlocked when the locks are dropped.
+pub fn lock_hosts<'a>(
+    hosts: impl Iterator<Item = &'a str>,
+    holder: &LockHolder,
+    force: bool,
+) -> Result<Vec<LockFile>> {
+    let dir = std::env::temp_dir().join("tiron-locks");
+    std::fs::create_dir_all(&dir)?;
+
+    let mut locks = Vec::new();
+    for host in hosts.unique() {
+        let lock =
+            LockFile::acquire(&local_lock_path(&dir, host), holder, force).map_err(
+                |e| match e {
+                    LockError::Held(holder) => {
+                        anyhow!("{host} is {holder}, use --force-unlock to take over the lock")
+                    }
+                    LockError::Io(e) => anyhow!("can't lock {host}: {e}"),
+                },
+            )?;
+        locks.push(lock);
+    }
+    Ok(locks)
+}
+
+fn local_lock_path(dir: &Path, host: &str) -> PathBuf {
+    let name: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dir.join(format!("{name}.lock"))
+}
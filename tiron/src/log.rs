@@ -0,0 +1,241 @@
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tiron_common::error::Error;
+
+use crate::{history::data_dir, schedule::UtcTime};
+
+/// The log file is rotated when it gets larger than this
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// How many of the rotated log files are kept, from tiron.log.1 to tiron.log.5
+const KEEP_LOGS: usize = 5;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// The levels of the logs, from the least verbose
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!(
+                "{s} isn't a log level, which can be error, warn, info, debug or trace"
+            )),
+        }
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogLevel::Error => f.write_str("error"),
+            LogLevel::Warn => f.write_str("warn"),
+            LogLevel::Info => f.write_str("info"),
+            LogLevel::Debug => f.write_str("debug"),
+            LogLevel::Trace => f.write_str("trace"),
+        }
+    }
+}
+
+struct Logger {
+    level: LogLevel,
+    path: PathBuf,
+    // the file and how large it is
+    file: Mutex<Option<(File, u64)>>,
+}
+
+/// Write the logs at the level or less verbose to the file, which is tiron.log
+/// in the logs directory of the data directory of tiron if it's not given.
+/// Nothing is logged if it's not initialized
+pub fn init(level: LogLevel, path: Option<PathBuf>) -> Result<(), Error> {
+    let path = match path {
+        Some(path) => path,
+        None => data_dir()
+            .ok_or_else(|| Error::new("can't find the data directory of tiron for the logs"))?
+            .join("logs")
+            .join("tiron.log"),
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            Error::new(format!(
+                "can't create the log directory {}: {e}",
+                dir.display()
+            ))
+        })?;
+    }
+    let file = open(&path)
+        .map_err(|e| Error::new(format!("can't open the log file {}: {e}", path.display())))?;
+    let _ = LOGGER.set(Logger {
+        level,
+        path,
+        file: Mutex::new(Some(file)),
+    });
+    info(
+        "tiron",
+        "start",
+        &[
+            ("version", &env!("CARGO_PKG_VERSION")),
+            ("pid", &std::process::id()),
+        ],
+    );
+    Ok(())
+}
+
+/// Whether the logs at the level are written, for skipping the work of the fields
+/// that are only for the logs
+pub fn enabled(level: LogLevel) -> bool {
+    LOGGER.get().is_some_and(|logger| level <= logger.level)
+}
+
+/// Write a line of the log with the fields in the form of key=value, e.g.
+/// `2024-05-01T08:30:12.345Z debug ssh: connect host=web1 port=22`.
+/// `target` is the part of tiron that it's from
+pub fn log(level: LogLevel, target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    let Some(logger) = LOGGER.get().filter(|logger| level <= logger.level) else {
+        return;
+    };
+    let line = line(&timestamp(), level, target, message, fields);
+    let Ok(mut file) = logger.file.lock() else {
+        return;
+    };
+    if file
+        .as_ref()
+        .is_some_and(|(_, size)| *size + line.len() as u64 > MAX_LOG_SIZE)
+    {
+        // the file is closed before it's renamed
+        file.take();
+        *file = rotate(&logger.path).ok();
+    }
+    if let Some((file, size)) = file.as_mut() {
+        if file.write_all(line.as_bytes()).is_ok() {
+            *size += line.len() as u64;
+        }
+    }
+}
+
+pub fn error(target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Error, target, message, fields);
+}
+
+pub fn warn(target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Warn, target, message, fields);
+}
+
+pub fn info(target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Info, target, message, fields);
+}
+
+pub fn debug(target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Debug, target, message, fields);
+}
+
+pub fn trace(target: &str, message: &str, fields: &[(&str, &dyn Display)]) {
+    log(LogLevel::Trace, target, message, fields);
+}
+
+fn open(path: &Path) -> std::io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Move the log file to tiron.log.1, and the rotated ones one further,
+/// and open a new log file
+fn rotate(path: &Path) -> std::io::Result<(File, u64)> {
+    let rotated = |i: usize| PathBuf::from(format!("{}.{i}", path.display()));
+    let _ = std::fs::remove_file(rotated(KEEP_LOGS));
+    for i in (1..KEEP_LOGS).rev() {
+        let _ = std::fs::rename(rotated(i), rotated(i + 1));
+    }
+    std::fs::rename(path, rotated(1))?;
+    open(path)
+}
+
+fn line(
+    timestamp: &str,
+    level: LogLevel,
+    target: &str,
+    message: &str,
+    fields: &[(&str, &dyn Display)],
+) -> String {
+    let mut line = format!("{timestamp} {level} {target}: {message}");
+    for (key, value) in fields {
+        line.push_str(&format!(" {key}={}", quote(value.to_string())));
+    }
+    line.push('\n');
+    line
+}
+
+/// The value as it is, or quoted if it can't be told apart from the other fields
+fn quote(value: String) -> String {
+    if value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '=' || c == '"')
+    {
+        format!("{value:?}")
+    } else {
+        value
+    }
+}
+
+/// The current time in UTC with milliseconds, e.g. 2024-05-01T08:30:12.345Z
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let time = UtcTime::from_unix(now.as_secs());
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time.year,
+        time.month,
+        time.day,
+        time.hour,
+        time.minute,
+        now.as_secs() % 60,
+        now.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn log_line() {
+        assert_eq!(
+            line(
+                "2024-05-01T08:30:12.345Z",
+                LogLevel::Debug,
+                "ssh",
+                "connect",
+                &[("host", &"web1"), ("port", &22), ("cmd", &"ssh -T web1")],
+            ),
+            "2024-05-01T08:30:12.345Z debug ssh: connect host=web1 port=22 cmd=\"ssh -T web1\"\n"
+        );
+        assert_eq!("DEBUG".parse::<LogLevel>(), Ok(LogLevel::Debug));
+        assert!("verbose".parse::<LogLevel>().is_err());
+        assert!(LogLevel::Warn < LogLevel::Trace);
+    }
+}
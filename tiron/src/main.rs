@@ -1,5 +1,11 @@
-fn main() {
-    if let Err(e) = tiron::core::cmd() {
-        let _ = e.report_stderr();
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match tiron::core::cmd() {
+        Ok(code) => code,
+        Err(e) => {
+            let _ = e.report_stderr();
+            ExitCode::FAILURE
+        }
     }
 }
@@ -1,35 +1,420 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use crossbeam_channel::{Receiver, Sender};
+use hcl::eval::{Context, Evaluate};
+use serde::{Deserialize, Serialize};
 use tiron_common::{
-    action::{ActionData, ActionMessage},
-    node::NodeMessage,
+    action::{
+        ActionData, ActionId, ActionMessage, ActionOutputLevel, DebugChoice, NodeStartFailedKind,
+        MESSAGE_BUFFER,
+    },
+    error::ErrorLocation,
+    event::{Event, EventSink, HostPhase, NullSink, RunEvent},
+    node::{Facts, LockHolder, NodeMessage, Resource, ResourceState},
+};
+use tiron_node::action::{
+    data::all_actions, input_from_hcl, CopyAction, FetchAction, FileAction, RebootAction,
+    RsyncRemote, SynchronizeAction,
 };
-use tiron_tui::event::AppEvent;
+use tiron_tui::event::{DebugReply, TuiDebugger};
 use uuid::Uuid;
 
 use crate::{
+    container::ContainerHost,
+    filters::declare_filters,
     local::start_local,
-    remote::{start_remote, SshHost, SshRemote},
+    log,
+    remote::{start_remote, NodeStartError, SshHost, SshRemote},
+    results::{input_hash, HostResults},
+    runbook::{is_deferred_include, resolve_include},
 };
 
+/// How often to check whether the host is back after the reboot
+const REBOOT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The outcome of executing the actions on a node
+pub struct NodeExit {
+    pub success: bool,
+    // the number of actions that completed or were skipped before the first failure,
+    // which is also the index of the action to resume from
+    pub completed: usize,
+    // the changes the actions would make in a dry run
+    pub pending: Vec<PendingChange>,
+    // the host didn't meet the `when` condition so nothing was run
+    pub skipped: bool,
+    // the output values of the run evaluated for the host
+    pub outputs: Vec<(String, hcl::Value)>,
+    // the outcomes of the actions in the order they finished
+    pub actions: Vec<ActionOutcome>,
+    // how long the host took in milliseconds, from connecting to shutting down
+    pub duration: u64,
+    // the actions of the host with the includes that use its facts resolved,
+    // and from where it started, which `completed` counts in, if they're different
+    pub resolved: Option<Vec<ActionData>>,
+}
+
+/// A change that an action would make, reported in a dry run
+#[derive(Serialize, Deserialize)]
+pub struct PendingChange {
+    pub action: String,
+    pub change: String,
+}
+
+/// The outcome of an action on a host, which is kept in the run history
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ActionOutcome {
+    pub action: String,
+    pub status: ActionStatus,
+    // how long the action took in milliseconds
+    pub duration: u64,
+    // the error from the node if the action failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // the structured result of the action, e.g. the state of the path from `stat`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<hcl::Value>,
+    // where the action is in the runbook, for pointing at it in the recap
+    #[serde(skip)]
+    pub location: Option<ErrorLocation>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionStatus {
+    Success,
+    Failed,
+    Skipped,
+}
+
+impl std::fmt::Display for ActionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionStatus::Success => f.write_str("success"),
+            ActionStatus::Failed => f.write_str("failed"),
+            ActionStatus::Skipped => f.write_str("skipped"),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Node {
     pub id: Uuid,
     pub host: String,
+    // the names of the groups this host is from
+    pub groups: Vec<String>,
+    // the protected group this host is from, which the run needs confirming for
+    pub protected: Option<String>,
     pub remote_user: Option<String>,
     pub become_: bool,
     pub vars: HashMap<String, hcl::Value>,
     pub actions: Vec<ActionData>,
-    pub tx: Sender<AppEvent>,
+    // run the node in this container instead of on the host
+    pub container: Option<ContainerHost>,
+    // the throttles of the actions, which are shared with the other hosts in the run
+    pub throttles: HashMap<ActionId, Arc<Throttle>>,
+    // the conditions for the host to run the actions, which are evaluated
+    // with the facts of the host when it's connected. The host runs if it meets
+    // all the conditions of any of the groups it's reached through
+    when: Vec<Vec<hcl::Expression>>,
+    // the built-in `tiron` variable of the run
+    pub metadata: hcl::Value,
+    // lock the host for the run with the holder
+    pub lock: Option<LockHolder>,
+    // take over the lock of the host if someone else holds it
+    pub force_unlock: bool,
+    // skip the actions whose inputs are the same as the last time they succeeded on the host
+    pub skip_unchanged: bool,
+    // the index of the action to start from, e.g. where the host failed in the run
+    // that's resumed, which counts the actions of the includes that use the facts
+    pub resume_from: usize,
+    // the output values of the run, which are evaluated with the facts of the host
+    pub outputs: Vec<(String, hcl::Expression)>,
+    // the built-in `hostvars` variable with the vars of all the hosts in the run
+    pub hostvars: hcl::Value,
+    // where the hosts in the run share their facts
+    pub shared_facts: Option<Arc<SharedFacts>>,
+    // the functions that work like the Ansible filters are available in the expressions
+    pub ansible_filters: bool,
+    // asks what to do with the failed actions in the debug strategy of the run
+    pub debugger: Option<TuiDebugger>,
+}
+
+/// Limits how many hosts run an action at the same time
+pub struct Throttle {
+    limit: usize,
+    running: Mutex<usize>,
+    cvar: Condvar,
+}
+
+impl Throttle {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            running: Mutex::new(0),
+            cvar: Condvar::new(),
+        }
+    }
+
+    /// Wait until there's a free slot and take it, which is released
+    /// when the permit is dropped
+    fn acquire(self: &Arc<Self>) -> ThrottlePermit {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.limit {
+            running = self.cvar.wait(running).unwrap();
+        }
+        *running += 1;
+        ThrottlePermit {
+            throttle: self.clone(),
+        }
+    }
+}
+
+struct ThrottlePermit {
+    throttle: Arc<Throttle>,
+}
+
+impl Drop for ThrottlePermit {
+    fn drop(&mut self) {
+        let mut running = self.throttle.running.lock().unwrap();
+        *running -= 1;
+        self.throttle.cvar.notify_one();
+    }
+}
+
+/// How long the hosts wait for the other hosts in the run to connect and share
+/// their facts, after which the ones that haven't are left out of `hostvars`
+const SHARED_FACTS_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The facts of the hosts in a run, which the hosts share with each other
+/// when they are connected
+pub struct SharedFacts {
+    state: Mutex<SharedFactsState>,
+    cvar: Condvar,
+    timeout: Duration,
+}
+
+struct SharedFactsState {
+    facts: HashMap<String, Facts>,
+    // the number of hosts that haven't connected or failed to yet
+    waiting: usize,
+    // when the hosts stop waiting for the others in the pass
+    deadline: Option<Instant>,
+}
+
+impl SharedFacts {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(SharedFactsState {
+                facts: HashMap::new(),
+                waiting: 0,
+                deadline: None,
+            }),
+            cvar: Condvar::new(),
+            timeout: SHARED_FACTS_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Start a pass of `n` hosts, which wait for each other to share the facts
+    pub fn expect(&self, n: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.waiting = n;
+        state.deadline = Some(Instant::now() + self.timeout);
+    }
+
+    /// Share the facts of the host, or nothing if it failed to connect,
+    /// and wait for the other hosts in the pass before returning the facts of all of them.
+    /// A host that's stuck connecting doesn't hold up the others past the timeout
+    fn share(&self, host: &str, facts: Option<&Facts>) -> HashMap<String, Facts> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(facts) = facts {
+            state.facts.insert(host.to_string(), facts.clone());
+        }
+        state.waiting = state.waiting.saturating_sub(1);
+        if state.waiting == 0 {
+            self.cvar.notify_all();
+        }
+        let deadline = state
+            .deadline
+            .unwrap_or_else(|| Instant::now() + self.timeout);
+        while state.waiting > 0 {
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            state = self.cvar.wait_timeout(state, timeout).unwrap().0;
+        }
+        state.facts.clone()
+    }
+}
+
+impl Default for SharedFacts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The facts as the value in the expressions, with the local facts as objects
+fn facts_value(facts: &Facts) -> hcl::Value {
+    let mut value = hcl::to_value(facts).unwrap_or(hcl::Value::Null);
+    if let hcl::Value::Object(object) = &mut value {
+        let local = facts
+            .local
+            .iter()
+            .map(|(name, json)| {
+                let value = serde_json::from_str::<serde_json::Value>(json)
+                    .ok()
+                    .and_then(|value| hcl::to_value(value).ok())
+                    .unwrap_or(hcl::Value::Null);
+                (name.to_string(), value)
+            })
+            .collect::<hcl::Map<_, _>>();
+        object.insert("local".to_string(), hcl::Value::Object(local));
+    }
+    value
+}
+
+/// What the reader tells about the actions that the node is running
+enum Progress {
+    // an action has finished
+    Finished,
+    // the node has got to the reboot, and it's for tiron to reboot the host
+    Reboot,
+}
+
+/// Wait until `n` actions have finished, and returns false if the node stopped before that
+fn wait_completed(progress: &Receiver<Progress>, completed: &mut usize, n: usize) -> bool {
+    while *completed < n {
+        match progress.recv() {
+            Ok(Progress::Finished) => *completed += 1,
+            Ok(Progress::Reboot) => {}
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+/// Wait for the reboot, which is the `n`th action, and returns whether the host should be
+/// rebooted, or None if the node stopped before that
+fn wait_reboot(progress: &Receiver<Progress>, completed: &mut usize, n: usize) -> Option<bool> {
+    loop {
+        match progress.recv().ok()? {
+            Progress::Finished => {
+                *completed += 1;
+                if *completed > n {
+                    return Some(false);
+                }
+            }
+            Progress::Reboot => return Some(true),
+        }
+    }
+}
+
+/// Save the results of the actions on the host, if they're kept for it
+fn save_results(host: &str, results: &mut Option<HostResults>) {
+    if let Some(Err(e)) = results.as_mut().map(|results| results.save()) {
+        log::warn(
+            "node",
+            "can't save the results of the host",
+            &[("host", &host), ("error", &e)],
+        );
+    }
+}
+
+/// The local file that a `fetch` action writes the chunks from the node to,
+/// which is written next to it and only moved into place when the action succeeds
+struct Fetch {
+    path: PathBuf,
+    // the partial file, or the error of writing it
+    file: Option<std::io::Result<File>>,
+}
+
+impl Fetch {
+    fn new(path: PathBuf) -> Self {
+        Self { path, file: None }
+    }
+
+    fn partial(&self) -> PathBuf {
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".part");
+        PathBuf::from(partial)
+    }
+
+    fn create(path: &Path) -> std::io::Result<File> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        File::create(path)
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        let partial = self.partial();
+        let file = self.file.get_or_insert_with(|| Self::create(&partial));
+        if let Ok(f) = file {
+            if let Err(e) = f.write_all(data) {
+                *file = Err(e);
+            }
+        }
+    }
+
+    /// Move the fetched file into place, or remove the partial file
+    /// if the action failed
+    fn finish(self, success: bool) -> std::io::Result<()> {
+        let partial = self.partial();
+        match self.file {
+            _ if !success => {
+                let _ = std::fs::remove_file(partial);
+                Ok(())
+            }
+            // the node doesn't send any chunks for an empty file
+            None => Self::create(&self.path).map(|_| ()),
+            Some(Ok(file)) => {
+                drop(file);
+                std::fs::rename(partial, &self.path)
+            }
+            Some(Err(e)) => {
+                let _ = std::fs::remove_file(partial);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Forward the messages from the node until it exits, and give back the sender
+/// so that the messages of the next node can be forwarded with it
+fn forward(
+    rx: Receiver<ActionMessage>,
+    tx: Sender<ActionMessage>,
+) -> JoinHandle<Sender<ActionMessage>> {
+    std::thread::spawn(move || {
+        for msg in rx {
+            if tx.send(msg).is_err() {
+                break;
+            }
+        }
+        tx
+    })
 }
 
 impl Node {
-    pub fn new(host: String, new_vars: HashMap<String, hcl::Value>, tx: &Sender<AppEvent>) -> Self {
+    pub fn new(host: String, new_vars: HashMap<String, hcl::Value>) -> Self {
         Self {
             id: Uuid::new_v4(),
             host,
+            groups: Vec::new(),
+            protected: None,
             remote_user: new_vars.get("remote_user").and_then(|v| {
                 if let hcl::Value::String(s) = v {
                     Some(s.to_string())
@@ -49,76 +434,1330 @@ pub fn new(host: String, new_vars: HashMap<String, hcl::Value>, tx: &Sender<AppE
                 .unwrap_or(false),
             vars: new_vars,
             actions: Vec::new(),
-            tx: tx.clone(),
+            container: None,
+            throttles: HashMap::new(),
+            when: vec![Vec::new()],
+            metadata: hcl::Value::Null,
+            lock: None,
+            force_unlock: false,
+            skip_unchanged: false,
+            resume_from: 0,
+            outputs: Vec::new(),
+            hostvars: hcl::Value::Null,
+            shared_facts: None,
+            ansible_filters: false,
+            debugger: None,
+        }
+    }
+
+    /// Merge the same host reached through another group of the run into this one.
+    /// The vars of this one take precedence, and the other one only adds the vars
+    /// this one doesn't have. Returns what was merged, or None if nothing was
+    pub fn merge(&mut self, other: Node) -> Option<String> {
+        let mut added = Vec::new();
+        let mut ignored = Vec::new();
+        let mut vars: Vec<_> = other.vars.into_iter().collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        for (key, val) in vars {
+            match self.vars.get(&key) {
+                Some(v) if *v == val => {}
+                Some(_) => ignored.push(key),
+                None => {
+                    match (key.as_str(), &val) {
+                        ("remote_user", hcl::Value::String(user)) if self.remote_user.is_none() => {
+                            self.remote_user = Some(user.to_string());
+                        }
+                        ("become", hcl::Value::Bool(b)) => self.become_ = *b,
+                        _ => {}
+                    }
+                    added.push(key.clone());
+                    self.vars.insert(key, val);
+                }
+            }
+        }
+        if self.remote_user.is_none() {
+            self.remote_user = other.remote_user;
+        }
+        if self.protected.is_none() {
+            self.protected = other.protected;
+        }
+        // the host is in the run if it meets the conditions of either of the groups
+        self.when.extend(other.when);
+        let first = self.groups.first().cloned().unwrap_or_default();
+        let second = other.groups.first().cloned().unwrap_or_default();
+        for group in other.groups {
+            if !self.groups.contains(&group) {
+                self.groups.push(group);
+            }
+        }
+
+        if added.is_empty() && ignored.is_empty() {
+            return None;
+        }
+        let mut merged = format!(
+            "host {} is in both group {first} and group {second} of the run, \
+             so it runs once with the vars from {first} taking precedence",
+            self.host
+        );
+        if !added.is_empty() {
+            merged.push_str(&format!(", {} from {second}", added.join(", ")));
+            merged.push_str(if added.len() > 1 {
+                " are added"
+            } else {
+                " is added"
+            });
+        }
+        if !ignored.is_empty() {
+            merged.push_str(&format!(", {} from {second}", ignored.join(", ")));
+            merged.push_str(if ignored.len() > 1 {
+                " are ignored"
+            } else {
+                " is ignored"
+            });
+        }
+        Some(merged)
+    }
+
+    /// The string var of the host for display, e.g. the `environment` and `color` tags
+    pub fn tag(&self, name: &str) -> Option<String> {
+        match self.vars.get(name) {
+            Some(hcl::Value::String(s)) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The context to evaluate the expressions for the host with its vars and facts,
+    /// and the facts of the other hosts in `hostvars`
+    pub fn context(&self, facts: &Facts, shared: &HashMap<String, Facts>) -> Context<'static> {
+        let mut ctx = Context::new();
+        for (name, var) in &self.vars {
+            ctx.declare_var(name.to_string(), var.to_owned());
+        }
+        ctx.declare_var("tiron", self.metadata.clone());
+        ctx.declare_var("facts", facts_value(facts));
+
+        let mut hostvars = self.hostvars.clone();
+        if let hcl::Value::Object(hosts) = &mut hostvars {
+            for (host, facts) in shared {
+                if let Some(hcl::Value::Object(host)) = hosts.get_mut(host) {
+                    host.insert("facts".to_string(), facts_value(facts));
+                }
+            }
+        }
+        ctx.declare_var("hostvars", hostvars);
+        if self.ansible_filters {
+            declare_filters(&mut ctx);
+        }
+        ctx
+    }
+
+    /// Add a `when` condition that the host needs to meet in every group it's reached through
+    pub fn add_when(&mut self, when: hcl::Expression) {
+        for conditions in self.when.iter_mut() {
+            conditions.push(when.clone());
         }
     }
 
-    pub fn execute(&self, run_id: Uuid, exit_tx: Sender<bool>) -> Result<()> {
-        let (tx, rx) = match self.start() {
-            Ok((tx, rx)) => (tx, rx),
+    /// Whether the host only runs if it meets some `when` conditions
+    pub fn has_when(&self) -> bool {
+        self.when.iter().all(|conditions| !conditions.is_empty())
+    }
+
+    /// Whether the host can use the facts of the other hosts in `hostvars`, which
+    /// it needs to wait for the other hosts to connect for. The files of the includes
+    /// that use the facts are only known when the host is connected, so they count too
+    pub fn uses_hostvars(&self) -> bool {
+        let uses = |expr: &hcl::Expression| expr.to_string().contains("hostvars");
+        self.when.iter().flatten().any(uses)
+            || self.outputs.iter().any(|(_, value)| uses(value))
+            || self.actions.iter().any(is_deferred_include)
+    }
+
+    /// Whether the host meets all the `when` conditions of any of its groups
+    fn check_when(&self, ctx: &Context) -> Result<bool> {
+        for conditions in &self.when {
+            let mut met = true;
+            for when in conditions {
+                let v = when.evaluate(ctx).map_err(|e| {
+                    anyhow!("can't evaluate when: {}", e.to_string().replace('\n', " "))
+                })?;
+                let hcl::Value::Bool(v) = v else {
+                    return Err(anyhow!("when should be a bool, but it's {v}"));
+                };
+                if !v {
+                    met = false;
+                    break;
+                }
+            }
+            if met {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Evaluate the output values of the run for the host
+    fn evaluate_outputs(&self, ctx: &Context) -> Result<Vec<(String, hcl::Value)>> {
+        self.outputs
+            .iter()
+            .map(|(name, value)| {
+                let v = value.evaluate(ctx).map_err(|e| {
+                    anyhow!(
+                        "can't evaluate output {name}: {}",
+                        e.to_string().replace('\n', " ")
+                    )
+                })?;
+                Ok((name.clone(), v))
+            })
+            .collect()
+    }
+
+    /// The actions with the `include_actions` that use the facts resolved with the context
+    /// that has the facts, or None if there aren't any
+    fn resolve_includes(&self, ctx: &Context) -> Result<Option<Vec<ActionData>>> {
+        if !self.actions.iter().any(is_deferred_include) {
+            return Ok(None);
+        }
+        let mut actions = Vec::new();
+        for action in &self.actions {
+            if !is_deferred_include(action) {
+                actions.push(action.clone());
+                continue;
+            }
+            let mut resolved = resolve_include(action, ctx).map_err(|e| match &e.location {
+                Some(location) => anyhow!(
+                    "can't include actions at {}:{}: {}",
+                    location.path.display(),
+                    location.line,
+                    e.message
+                ),
+                None => anyhow!("can't include actions: {}", e.message),
+            })?;
+            actions.append(&mut resolved);
+        }
+        Ok(Some(actions))
+    }
+
+    pub fn execute(
+        &self,
+        run_id: Uuid,
+        events: Arc<dyn EventSink>,
+        exit_tx: Sender<NodeExit>,
+    ) -> Result<()> {
+        let start_time = Instant::now();
+        let started = self.start(run_id, events.as_ref());
+        // the hosts wait for each other to connect, so that they can use the facts
+        // of the other hosts
+        let shared = match &self.shared_facts {
+            Some(shared_facts) => {
+                shared_facts.share(&self.host, started.as_ref().ok().map(|(_, _, facts)| facts))
+            }
+            None => HashMap::new(),
+        };
+        let started = started.and_then(|(tx, rx, facts)| {
+            let ctx = self.context(&facts, &shared);
+            if !self.check_when(&ctx)? {
+                return Ok((tx, rx, None));
+            }
+            let outputs = self.evaluate_outputs(&ctx)?;
+            let resolved = self.resolve_includes(&ctx)?;
+            Ok((tx, rx, Some((outputs, resolved))))
+        });
+        let (tx, rx, outputs, resolved) = match started {
+            Ok((tx, rx, Some((outputs, resolved)))) => (tx, rx, outputs, resolved),
+            Ok((tx, _, None)) => {
+                log::debug(
+                    "scheduler",
+                    "skip the host by when",
+                    &[("host", &self.host)],
+                );
+                events.send(Event::Run(RunEvent::HostPhase {
+                    run: run_id,
+                    host: self.id,
+                    phase: HostPhase::Skipped,
+                }));
+                let _ = tx.send(NodeMessage::Shutdown);
+                let _ = exit_tx.send(NodeExit {
+                    success: true,
+                    completed: self.actions.len().saturating_sub(self.resume_from),
+                    pending: Vec::new(),
+                    skipped: true,
+                    outputs: Vec::new(),
+                    actions: Vec::new(),
+                    duration: start_time.elapsed().as_millis() as u64,
+                    resolved: None,
+                });
+                return Ok(());
+            }
             Err(e) => {
-                self.tx.send(AppEvent::Action {
+                log::warn(
+                    "node",
+                    "can't start the node",
+                    &[("host", &self.host), ("error", &e)],
+                );
+                let (kind, reason) = match e.downcast_ref::<NodeStartError>() {
+                    Some(e) => (e.kind, e.reason.clone()),
+                    None => (NodeStartFailedKind::Other, e.to_string()),
+                };
+                events.send(Event::Action {
                     run: run_id,
                     host: self.id,
-                    msg: ActionMessage::NodeStartFailed {
-                        reason: e.to_string(),
-                    },
-                })?;
+                    msg: ActionMessage::NodeStartFailed { kind, reason },
+                });
+                let _ = exit_tx.send(NodeExit {
+                    success: false,
+                    completed: 0,
+                    pending: Vec::new(),
+                    skipped: false,
+                    outputs: Vec::new(),
+                    actions: Vec::new(),
+                    duration: start_time.elapsed().as_millis() as u64,
+                    resolved: None,
+                });
                 return Err(e);
             }
         };
+        // the actions of the includes that use the facts are known now that the host
+        // is connected, and the host goes on with them
+        let resolved = resolved.map(|actions| {
+            events.send(Event::Run(RunEvent::HostActions {
+                run: run_id,
+                host: self.id,
+                actions: actions
+                    .iter()
+                    .map(|action| (action.id, action.name.clone()))
+                    .collect(),
+            }));
+            Node {
+                actions,
+                ..self.clone()
+            }
+        });
+        let node = resolved.as_ref().unwrap_or(self);
+        // the actions before where it starts from have completed before,
+        // and `completed` counts from it
+        let resumed = (node.resume_from > 0).then(|| Node {
+            actions: node
+                .actions
+                .iter()
+                .skip(node.resume_from)
+                .cloned()
+                .collect(),
+            resume_from: 0,
+            ..node.clone()
+        });
+        let node = resumed.as_ref().unwrap_or(node);
+        let resolved = resolved.is_some() || resumed.is_some();
+        // the results of the actions are kept for the host itself, not for a container
+        // standing in for it, and the dry runs don't have any
+        let mut results = (node.container.is_none()
+            && node.actions.iter().any(|action| !action.dry_run))
+        .then(|| HostResults::load(&node.host));
+        let mut hashes: HashMap<ActionId, String> = match &results {
+            Some(_) => node
+                .actions
+                .iter()
+                .filter(|action| !action.dry_run)
+                .map(|action| (action.id, input_hash(action)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let unchanged = match &results {
+            Some(results) if node.skip_unchanged => Some(Node {
+                actions: node
+                    .actions
+                    .iter()
+                    .cloned()
+                    .map(|mut action| {
+                        action.unchanged = hashes
+                            .get(&action.id)
+                            .is_some_and(|hash| results.contains(hash));
+                        action
+                    })
+                    .collect(),
+                ..node.clone()
+            }),
+            _ => None,
+        };
+        let node = unchanged.as_ref().unwrap_or(node);
+        let mut tx = tx;
+        let actions = node.prepare_copies(&tx, &rx, run_id, events.as_ref());
+        let actions = node.prepare_purges(actions);
+        let actions = node.prepare_reboots(actions);
+        let actions = node.prepare_syncs(actions);
+        // the reboots that tiron does itself after the node says they can run
+        let mut reboots: HashSet<ActionId> = actions
+            .iter()
+            .filter(|action| RebootAction::is_remote(action))
+            .map(|action| action.id)
+            .collect();
+        // the local files of the fetches, which the reader writes the chunks to
+        let mut fetches: HashMap<ActionId, Fetch> = actions
+            .iter()
+            .filter(|action| action.action == "fetch" && !action.dry_run)
+            .filter_map(|action| {
+                let path = FetchAction::local_path(&action.input, &node.host)?;
+                Some((action.id, Fetch::new(path)))
+            })
+            .collect();
+        // the synchronizes that tiron runs rsync for after the node says they can run
+        let syncs: HashMap<ActionId, Vec<u8>> = actions
+            .iter()
+            .filter(|action| SynchronizeAction::is_remote(action))
+            .map(|action| (action.id, action.input.clone()))
+            .collect();
 
+        // the messages of the node go through a forwarder to the reader, so that the reader
+        // can keep reading from the node started after the host reboots
+        let (msg_tx, msg_rx) = crossbeam_channel::bounded(MESSAGE_BUFFER);
+        let mut forwarder = Some(forward(rx, msg_tx));
+        // the node that the reader sends the debug choices to
+        let node_tx = Arc::new(Mutex::new(Some(tx.clone())));
+        // the reader tells how many actions have finished, so that the throttled actions
+        // are only sent when they can run
+        let (progress_tx, progress_rx) = crossbeam_channel::unbounded();
         {
-            let node_tx = tx.clone();
-            let tx = self.tx.clone();
-            let host_id = self.id;
+            let node_tx = node_tx.clone();
+            let node = node.clone();
+            let host_id = node.id;
+            let rsync_remote = node.rsync_remote();
+            let action_names: HashMap<ActionId, String> = node
+                .actions
+                .iter()
+                .map(|action| (action.id, action.name.clone()))
+                .collect();
+            let action_locations: HashMap<ActionId, ErrorLocation> = node
+                .actions
+                .iter()
+                .filter_map(|action| Some((action.id, action.location.clone()?)))
+                .collect();
             std::thread::spawn(move || {
-                while let Ok(msg) = rx.recv() {
+                let mut completed = 0;
+                let mut failed = false;
+                let mut pending = Vec::new();
+                let mut action_started = HashMap::new();
+                let mut action_errors = HashMap::new();
+                let mut action_values = HashMap::new();
+                let mut actions = Vec::new();
+                while let Ok(msg) = msg_rx.recv() {
+                    let msg = match msg {
+                        // the chunks of the fetched files only go to the local files
+                        ActionMessage::ActionFetch { id, data } => {
+                            if let Some(fetch) = fetches.get_mut(&id) {
+                                fetch.write(&data);
+                            }
+                            continue;
+                        }
+                        ActionMessage::ActionResult { id, success: true }
+                            if syncs.contains_key(&id) =>
+                        {
+                            let success = match node.synchronize(
+                                run_id,
+                                id,
+                                &syncs[&id],
+                                &rsync_remote,
+                                events.as_ref(),
+                            ) {
+                                Ok(()) => true,
+                                Err(e) => {
+                                    action_errors.insert(id, e);
+                                    false
+                                }
+                            };
+                            ActionMessage::ActionResult { id, success }
+                        }
+                        ActionMessage::ActionResult { id, success } => match fetches.remove(&id) {
+                            Some(fetch) => {
+                                let path = fetch.path.clone();
+                                let mut success = success;
+                                let line = match fetch.finish(success) {
+                                    Ok(()) if success => Some((
+                                        format!("saved to {}", path.display()),
+                                        ActionOutputLevel::Info,
+                                    )),
+                                    Ok(()) => None,
+                                    Err(e) => {
+                                        // the node has gone on with the actions after it,
+                                        // but the host fails with it
+                                        success = false;
+                                        let error = format!("can't write {}: {e}", path.display());
+                                        action_errors.insert(id, error.clone());
+                                        Some((format!("error: {error}"), ActionOutputLevel::Error))
+                                    }
+                                };
+                                if let Some((content, level)) = line {
+                                    events.send(Event::Action {
+                                        run: run_id,
+                                        host: host_id,
+                                        msg: ActionMessage::ActionOutputLine { id, content, level },
+                                    });
+                                }
+                                ActionMessage::ActionResult { id, success }
+                            }
+                            None => ActionMessage::ActionResult { id, success },
+                        },
+                        msg => msg,
+                    };
+                    match &msg {
+                        ActionMessage::ActionStarted { id } => {
+                            action_started.insert(*id, Instant::now());
+                        }
+                        ActionMessage::ActionOutputLine {
+                            id,
+                            content,
+                            level: ActionOutputLevel::Error,
+                        } => {
+                            let error = content.strip_prefix("error: ").unwrap_or(content);
+                            action_errors.insert(*id, error.to_string());
+                        }
+                        ActionMessage::ActionValue { id, value } => {
+                            if let Ok(value) = serde_json::from_str::<hcl::Value>(value) {
+                                action_values.insert(*id, value);
+                            }
+                        }
+                        ActionMessage::ActionResult { id, success: true } if reboots.remove(id) => {
+                            // tiron reboots the host, and then gives the result
+                            let _ = progress_tx.send(Progress::Reboot);
+                            continue;
+                        }
+                        ActionMessage::ActionResult { id, success } => {
+                            if !*success {
+                                failed = true;
+                            } else if !failed {
+                                completed += 1;
+                            }
+                            if let (Some(results), Some(hash)) = (results.as_mut(), hashes.get(id))
+                            {
+                                if *success {
+                                    results.succeed(hash.clone());
+                                } else {
+                                    results.fail(hash);
+                                }
+                            }
+                            actions.push(ActionOutcome {
+                                action: action_names.get(id).cloned().unwrap_or_default(),
+                                status: if *success {
+                                    ActionStatus::Success
+                                } else {
+                                    ActionStatus::Failed
+                                },
+                                duration: action_started
+                                    .get(id)
+                                    .map(|started| started.elapsed().as_millis() as u64)
+                                    .unwrap_or(0),
+                                error: if *success {
+                                    None
+                                } else {
+                                    action_errors.remove(id)
+                                },
+                                value: action_values.remove(id),
+                                location: action_locations.get(id).cloned(),
+                            });
+                            let _ = progress_tx.send(Progress::Finished);
+                        }
+                        ActionMessage::ActionSkipped { id } => {
+                            if !failed {
+                                completed += 1;
+                            }
+                            // the unchanged actions are kept as if they succeeded again,
+                            // so that they aren't the oldest ones to be pruned
+                            if let (Some(results), Some(hash)) = (results.as_mut(), hashes.get(id))
+                            {
+                                if node.actions.iter().any(|a| a.id == *id && a.unchanged) {
+                                    results.succeed(hash.clone());
+                                }
+                            }
+                            actions.push(ActionOutcome {
+                                action: action_names.get(id).cloned().unwrap_or_default(),
+                                status: ActionStatus::Skipped,
+                                duration: 0,
+                                error: None,
+                                value: None,
+                                location: action_locations.get(id).cloned(),
+                            });
+                            let _ = progress_tx.send(Progress::Finished);
+                        }
+                        ActionMessage::NodePanicked { id, message, .. } => {
+                            failed = true;
+                            if let Some(id) = id {
+                                if let (Some(results), Some(hash)) =
+                                    (results.as_mut(), hashes.get(id))
+                                {
+                                    results.fail(hash);
+                                }
+                                actions.push(ActionOutcome {
+                                    action: action_names.get(id).cloned().unwrap_or_default(),
+                                    status: ActionStatus::Failed,
+                                    duration: action_started
+                                        .get(id)
+                                        .map(|started| started.elapsed().as_millis() as u64)
+                                        .unwrap_or(0),
+                                    error: Some(format!("node panicked: {message}")),
+                                    value: None,
+                                    location: action_locations.get(id).cloned(),
+                                });
+                            }
+                        }
+                        ActionMessage::ActionDebug { id } => {
+                            if let (Some(results), Some(hash)) = (results.as_mut(), hashes.get(id))
+                            {
+                                results.fail(hash);
+                            }
+                            let choice = node.debug(run_id, *id, events.as_ref());
+                            // it doesn't succeed with the input if it's edited or skipped
+                            if matches!(choice, DebugChoice::RetryWith(_) | DebugChoice::Skip) {
+                                hashes.remove(id);
+                            }
+                            log::debug(
+                                "node",
+                                "debug the failed action",
+                                &[("host", &node.host), ("choice", &<&str>::from(&choice))],
+                            );
+                            if let Ok(Some(node_tx)) = node_tx.lock().as_deref() {
+                                let _ = node_tx.send(NodeMessage::Debug(choice));
+                            }
+                        }
+                        ActionMessage::ActionDryRun {
+                            id,
+                            change: Some(change),
+                        } => {
+                            pending.push(PendingChange {
+                                action: action_names.get(id).cloned().unwrap_or_default(),
+                                change: change.clone(),
+                            });
+                        }
+                        _ => {}
+                    }
                     if let ActionMessage::NodeShutdown { success } = &msg {
                         let success = *success;
-                        let _ = tx.send(AppEvent::Action {
+                        log::debug(
+                            "node",
+                            "the node shut down",
+                            &[("host", &node.host), ("success", &success)],
+                        );
+                        events.send(Event::Action {
                             run: run_id,
                             host: host_id,
                             msg,
                         });
-                        let _ = exit_tx.send(success);
+                        save_results(&node.host, &mut results);
+                        let _ = exit_tx.send(NodeExit {
+                            success,
+                            completed,
+                            pending,
+                            skipped: false,
+                            outputs,
+                            actions,
+                            duration: start_time.elapsed().as_millis() as u64,
+                            resolved: resolved.then(|| node.actions.clone()),
+                        });
                         return;
                     }
-                    let _ = tx.send(AppEvent::Action {
+                    events.send(Event::Action {
                         run: run_id,
                         host: host_id,
                         msg,
                     });
                 }
-                let _ = exit_tx.send(false);
+                log::warn(
+                    "node",
+                    "the node stopped without shutting down",
+                    &[("host", &node.host), ("completed", &completed)],
+                );
+                save_results(&node.host, &mut results);
+                let _ = exit_tx.send(NodeExit {
+                    success: false,
+                    completed,
+                    pending,
+                    skipped: false,
+                    outputs,
+                    actions,
+                    duration: start_time.elapsed().as_millis() as u64,
+                    resolved: resolved.then(|| node.actions.clone()),
+                });
                 // this doens't do anything but to hold the node's tx
                 // so that it doesn't get dropped
-                node_tx.is_empty();
+                drop(node_tx);
             });
         }
 
-        for action_data in &self.actions {
+        let mut finished = 0;
+        // the slot of a throttle is held through the consecutive actions with it,
+        // e.g. the actions of a throttled job
+        let mut permit: Option<ThrottlePermit> = None;
+        for (i, action_data) in actions.iter().enumerate() {
+            let throttle = node.throttles.get(&action_data.id);
+            let same = matches!(
+                (&permit, throttle),
+                (Some(permit), Some(throttle)) if Arc::ptr_eq(&permit.throttle, throttle)
+            );
+            if !same {
+                // wait for the actions before it, so that the slot is released
+                // only when the throttled actions are done, and it's taken
+                // only when the action is about to run
+                if (permit.is_some() || throttle.is_some())
+                    && !wait_completed(&progress_rx, &mut finished, i)
+                {
+                    return Ok(());
+                }
+                drop(permit.take());
+                permit = throttle.map(|throttle| {
+                    log::debug(
+                        "scheduler",
+                        "wait for a slot of the throttle",
+                        &[("host", &node.host), ("action", &action_data.name)],
+                    );
+                    throttle.acquire()
+                });
+            }
             tx.send(NodeMessage::Action(action_data.clone()))?;
+            // the actions after the synchronize are only sent when tiron has run rsync
+            if SynchronizeAction::is_remote(action_data) {
+                if !wait_completed(&progress_rx, &mut finished, i + 1) {
+                    return Ok(());
+                }
+                continue;
+            }
+            if !RebootAction::is_remote(action_data) {
+                continue;
+            }
+            // the node says the reboot can run when the actions before it are done,
+            // or the reboot finishes on the node if it's skipped or fails
+            match wait_reboot(&progress_rx, &mut finished, i) {
+                Some(true) => {}
+                Some(false) => continue,
+                None => return Ok(()),
+            }
+            log::info("scheduler", "reboot the host", &[("host", &node.host)]);
+            // the forwarder stops when the node exits after its tx is dropped
+            drop(node_tx.lock().ok().and_then(|mut node_tx| node_tx.take()));
+            drop(tx);
+            let Some(Ok(msg_tx)) = forwarder.take().map(|forwarder| forwarder.join()) else {
+                return Ok(());
+            };
+            let id = action_data.id;
+            let started = Instant::now();
+            match node.reboot(run_id, events.as_ref(), &action_data.input) {
+                Ok((new_tx, new_rx)) => {
+                    log::info(
+                        "scheduler",
+                        "the host is back",
+                        &[("host", &node.host), ("secs", &started.elapsed().as_secs())],
+                    );
+                    let _ = msg_tx.send(ActionMessage::ActionOutputLine {
+                        id,
+                        content: format!("the host is back in {}s", started.elapsed().as_secs()),
+                        level: ActionOutputLevel::Success,
+                    });
+                    let _ = msg_tx.send(ActionMessage::ActionResult { id, success: true });
+                    if let Ok(mut node_tx) = node_tx.lock() {
+                        *node_tx = Some(new_tx.clone());
+                    }
+                    tx = new_tx;
+                    forwarder = Some(forward(new_rx, msg_tx));
+                }
+                Err(e) => {
+                    log::warn(
+                        "scheduler",
+                        "the reboot failed",
+                        &[("host", &node.host), ("error", &e)],
+                    );
+                    let _ = msg_tx.send(ActionMessage::ActionOutputLine {
+                        id,
+                        content: format!("error: {e:#}"),
+                        level: ActionOutputLevel::Error,
+                    });
+                    let _ = msg_tx.send(ActionMessage::ActionResult { id, success: false });
+                    // the reader finishes the host as failed when the messages end
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(permit) = permit {
+            wait_completed(&progress_rx, &mut finished, node.actions.len());
+            drop(permit);
         }
         tx.send(NodeMessage::Shutdown)?;
 
         Ok(())
     }
 
-    fn start(&self) -> Result<(Sender<NodeMessage>, Receiver<ActionMessage>)> {
-        if self.host == "localhost" || self.host == "127.0.0.1" {
-            Ok(start_local())
+    /// The actions with the copies of large files changed to send less of the files,
+    /// with only the hashes of the files that are in the cache of the node, and only
+    /// the blocks that differ from the files on the host for the others
+    fn prepare_copies(
+        &self,
+        tx: &Sender<NodeMessage>,
+        rx: &Receiver<ActionMessage>,
+        run_id: Uuid,
+        events: &dyn EventSink,
+    ) -> Vec<ActionData> {
+        let mut actions = self.actions.clone();
+        // the node reads the files directly on the same machine
+        if self.is_local() {
+            return actions;
+        }
+        let mut copies: Vec<usize> = actions
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| {
+                !action.dry_run
+                    && !action.unchanged
+                    && (action.action == "copy" || action.action == "template")
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let hashes: Vec<(usize, [u8; 32])> = copies
+            .iter()
+            .filter_map(|&i| Some((i, CopyAction::cache_request(&actions[i].input)?)))
+            .collect();
+        if !hashes.is_empty() {
+            let msg = NodeMessage::Cached {
+                hashes: hashes.iter().map(|(_, hash)| *hash).collect(),
+            };
+            let Some(ActionMessage::NodeCached { cached }) =
+                self.request(tx, rx, msg, run_id, events)
+            else {
+                return actions;
+            };
+            for ((i, _), cached) in hashes.into_iter().zip(cached) {
+                if !cached {
+                    continue;
+                }
+                if let Some(input) = CopyAction::with_cached(&actions[i].input) {
+                    actions[i].input = input;
+                    copies.retain(|copy| *copy != i);
+                }
+            }
+        }
+
+        let files: Vec<(usize, (String, u32))> = copies
+            .iter()
+            .filter_map(|&i| Some((i, CopyAction::delta_request(&actions[i].input)?)))
+            .collect();
+        if !files.is_empty() {
+            let msg = NodeMessage::Signatures {
+                files: files.iter().map(|(_, file)| file.clone()).collect(),
+            };
+            let Some(ActionMessage::NodeSignatures { signatures }) =
+                self.request(tx, rx, msg, run_id, events)
+            else {
+                return actions;
+            };
+            for ((i, _), signatures) in files.into_iter().zip(signatures) {
+                let Some(signatures) = signatures else {
+                    continue;
+                };
+                if let Some(input) = CopyAction::with_delta(&actions[i].input, &signatures) {
+                    actions[i].input = input;
+                }
+            }
+        }
+        actions
+    }
+
+    /// The actions with the files that the actions write on the host given to the
+    /// directories that purge the unmanaged files, so that the files are kept
+    fn prepare_purges(&self, mut actions: Vec<ActionData>) -> Vec<ActionData> {
+        if !actions.iter().any(|action| action.action == "file") {
+            return actions;
+        }
+        let all_actions = all_actions();
+        let files: Vec<String> = self
+            .actions
+            .iter()
+            .filter_map(|action| Some(all_actions.get(&action.action)?.resources(&action.input)))
+            .flatten()
+            .filter_map(|resource| match resource {
+                Resource::File { path } => Some(path),
+                _ => None,
+            })
+            .collect();
+        for action in actions.iter_mut().filter(|action| action.action == "file") {
+            if let Some(input) = FileAction::with_keep(&action.input, &files) {
+                action.input = input;
+            }
+        }
+        actions
+    }
+
+    /// The reboots with the remote hosts marked for the node, which leaves them to tiron
+    fn prepare_reboots(&self, mut actions: Vec<ActionData>) -> Vec<ActionData> {
+        if !self.can_reboot() {
+            return actions;
+        }
+        for action in actions
+            .iter_mut()
+            .filter(|action| action.action == "reboot" && !action.dry_run)
+        {
+            if let Some(input) = RebootAction::with_remote(&action.input) {
+                action.input = input;
+            }
+        }
+        actions
+    }
+
+    /// The synchronizes marked for the host, which tiron runs rsync for if it's remote,
+    /// or the node runs rsync on the same machine
+    fn prepare_syncs(&self, mut actions: Vec<ActionData>) -> Vec<ActionData> {
+        if self.container.is_some() {
+            return actions;
+        }
+        for action in actions
+            .iter_mut()
+            .filter(|action| action.action == "synchronize")
+        {
+            if let Some(input) = SynchronizeAction::with_remote(&action.input, !self.is_local()) {
+                action.input = input;
+            }
+        }
+        actions
+    }
+
+    fn rsync_remote(&self) -> RsyncRemote {
+        let ssh = self.ssh_host();
+        RsyncRemote {
+            rsh: ssh.rsh(),
+            destination: ssh.user_host(),
+            sudo: self.become_,
+        }
+    }
+
+    /// Run rsync for the synchronize that the node has got to, with its output lines
+    /// sent as the action's, and returns the error if it fails
+    fn synchronize(
+        &self,
+        run_id: Uuid,
+        id: ActionId,
+        input: &[u8],
+        remote: &RsyncRemote,
+        events: &dyn EventSink,
+    ) -> Result<(), String> {
+        log::info(
+            "scheduler",
+            "synchronize to the host",
+            &[("host", &self.host), ("rsh", &remote.rsh)],
+        );
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let result = SynchronizeAction::rsync(id, input, Some(remote), &tx);
+        drop(tx);
+        // the lines are all there when the readers of the output of rsync finish
+        let mut lines: Vec<(String, ActionOutputLevel)> = rx
+            .iter()
+            .filter_map(|msg| match msg {
+                ActionMessage::ActionOutputLine { content, level, .. } => Some((content, level)),
+                _ => None,
+            })
+            .collect();
+        let result = match result {
+            Ok(result) => {
+                lines.push((result, ActionOutputLevel::Success));
+                Ok(())
+            }
+            Err(e) => {
+                lines.push((format!("error: {e:#}"), ActionOutputLevel::Error));
+                Err(format!("{e:#}"))
+            }
+        };
+        for (content, level) in lines {
+            events.send(Event::Action {
+                run: run_id,
+                host: self.id,
+                msg: ActionMessage::ActionOutputLine { id, content, level },
+            });
+        }
+        result
+    }
+
+    /// Send the message to the node and returns its reply, which is reported like
+    /// the messages of the actions if it's not the reply, e.g. the node panicked
+    fn request(
+        &self,
+        tx: &Sender<NodeMessage>,
+        rx: &Receiver<ActionMessage>,
+        msg: NodeMessage,
+        run_id: Uuid,
+        events: &dyn EventSink,
+    ) -> Option<ActionMessage> {
+        tx.send(msg).ok()?;
+        let reply = rx.recv().ok()?;
+        match reply {
+            ActionMessage::NodeCached { .. } | ActionMessage::NodeSignatures { .. } => Some(reply),
+            msg => {
+                events.send(Event::Action {
+                    run: run_id,
+                    host: self.id,
+                    msg,
+                });
+                None
+            }
+        }
+    }
+
+    /// Ask what to do with the action that failed in the debug strategy, which is
+    /// asked again if the edited params are wrong. It fails without the TUI to ask in
+    fn debug(&self, run_id: Uuid, id: ActionId, events: &dyn EventSink) -> DebugChoice {
+        let (Some(debugger), Some(action)) = (
+            &self.debugger,
+            self.actions.iter().find(|action| action.id == id),
+        ) else {
+            return DebugChoice::Fail;
+        };
+        let mut params: String = action
+            .params
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}\n"))
+            .collect();
+        loop {
+            let reply = debugger.ask(run_id, self.id, id, params.clone(), self.shell_command());
+            let edited = match reply {
+                DebugReply::Retry => return DebugChoice::Retry,
+                DebugReply::Skip => return DebugChoice::Skip,
+                DebugReply::Fail => return DebugChoice::Fail,
+                DebugReply::Edit(edited) => edited,
+            };
+            let all_actions = all_actions();
+            let input = match all_actions.get(&action.action) {
+                Some(a) => input_from_hcl(a.as_ref(), &action.cwd, &edited).map_err(|e| e.message),
+                None => Err(format!("can't find action name {}", action.action)),
+            };
+            match input {
+                Ok(input) => return DebugChoice::RetryWith(input),
+                Err(e) => {
+                    events.send(Event::Action {
+                        run: run_id,
+                        host: self.id,
+                        msg: ActionMessage::ActionOutputLine {
+                            id,
+                            content: format!("the edited params are wrong: {e}"),
+                            level: ActionOutputLevel::Error,
+                        },
+                    });
+                    params = edited;
+                }
+            }
+        }
+    }
+
+    /// The command of an interactive shell on the host, as the user that the actions
+    /// run as
+    pub fn shell_command(&self) -> Vec<String> {
+        if let Some(container) = &self.container {
+            container.shell_command()
+        } else if self.is_local() {
+            vec![std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string())]
+        } else {
+            self.ssh_host().shell_command(self.become_)
+        }
+    }
+
+    /// Read the states of the resources on the host, without running the actions
+    pub fn fingerprint(&self, resources: Vec<Resource>) -> Result<Vec<ResourceState>> {
+        let (tx, rx, _) = self.start(Uuid::new_v4(), &NullSink)?;
+        tx.send(NodeMessage::Fingerprint { resources })?;
+        let states = match rx.recv() {
+            Ok(ActionMessage::NodeFingerprint { states }) => states,
+            Ok(ActionMessage::NodePanicked { message, .. }) => {
+                return Err(anyhow!("node panicked: {message}"))
+            }
+            _ => return Err(anyhow!("node didn't send the states of the resources")),
+        };
+        let _ = tx.send(NodeMessage::Shutdown);
+        Ok(states)
+    }
+
+    /// Reboot the host, and start the node again when the host is back
+    fn reboot(
+        &self,
+        run_id: Uuid,
+        events: &dyn EventSink,
+        input: &[u8],
+    ) -> Result<(Sender<NodeMessage>, Receiver<ActionMessage>)> {
+        let timeout = RebootAction::timeout(input);
+        let remote = SshRemote {
+            ssh: self.ssh_host(),
+            container: None,
+        };
+        let boot_id = remote
+            .boot_id()
+            .ok_or_else(|| anyhow!("can't get the boot id of the host"))?;
+        remote.reboot(self.become_)?;
+        let start = Instant::now();
+        loop {
+            // the shared connection is either gone with the reboot, or it's to the host
+            // that's going down
+            remote.close_connection();
+            std::thread::sleep(REBOOT_POLL_INTERVAL);
+            let current = remote.boot_id();
+            log::debug(
+                "ssh",
+                "wait for the host to come back",
+                &[
+                    ("host", &self.host),
+                    ("boot_id", &current.as_deref().unwrap_or_default()),
+                ],
+            );
+            if current.is_some_and(|id| id != boot_id) {
+                break;
+            }
+            if start.elapsed() > timeout {
+                return Err(anyhow!(
+                    "the host didn't come back in {}s after the reboot",
+                    timeout.as_secs()
+                ));
+            }
+        }
+        let (tx, rx, _) = self.start(run_id, events)?;
+        Ok((tx, rx))
+    }
+
+    /// The reboot action is done by tiron itself, which can only reboot the remote hosts
+    fn can_reboot(&self) -> bool {
+        self.container.is_none() && !self.is_local()
+    }
+
+    fn is_local(&self) -> bool {
+        self.container.is_none() && (self.host == "localhost" || self.host == "127.0.0.1")
+    }
+
+    fn start(
+        &self,
+        run_id: Uuid,
+        events: &dyn EventSink,
+    ) -> Result<(Sender<NodeMessage>, Receiver<ActionMessage>, Facts)> {
+        let phase = |phase: HostPhase| {
+            events.send(Event::Run(RunEvent::HostPhase {
+                run: run_id,
+                host: self.id,
+                phase,
+            }));
+        };
+
+        let (tx, rx) = if let Some(container) = &self.container {
+            // containers run as root already, and they usually don't have sudo
+            start_remote(
+                SshRemote {
+                    ssh: self.ssh_host(),
+                    container: Some(container.clone()),
+                },
+                false,
+                &phase,
+            )?
+        } else if self.is_local() {
+            start_local()
         } else {
             start_remote(
                 SshRemote {
-                    ssh: SshHost {
-                        host: self.host.clone(),
-                        port: None,
-                        user: self.remote_user.clone(),
-                    },
+                    ssh: self.ssh_host(),
+                    container: None,
                 },
                 self.become_,
+                &phase,
+            )?
+        };
+
+        // the node sends the facts of the host first
+        let facts = match rx.recv() {
+            Ok(ActionMessage::NodeFacts { facts }) => facts,
+            Ok(ActionMessage::NodePanicked { message, .. }) => {
+                return Err(anyhow!("node panicked: {message}"))
+            }
+            _ => return Err(anyhow!("node didn't send the facts of the host")),
+        };
+
+        if let Some(holder) = &self.lock {
+            tx.send(NodeMessage::Lock {
+                holder: holder.clone(),
+                force: self.force_unlock,
+            })?;
+            match rx.recv() {
+                Ok(ActionMessage::NodeLocked { error: None }) => {}
+                Ok(ActionMessage::NodeLocked { error: Some(error) }) => {
+                    let _ = tx.send(NodeMessage::Shutdown);
+                    return Err(NodeStartError::new(NodeStartFailedKind::Locked, error).into());
+                }
+                Ok(ActionMessage::NodePanicked { message, .. }) => {
+                    return Err(anyhow!("node panicked: {message}"))
+                }
+                _ => return Err(anyhow!("node didn't lock the host")),
+            }
+        }
+        phase(HostPhase::Connected);
+        Ok((tx, rx, facts))
+    }
+
+    /// How to connect to the host with ssh, which can be changed from the host name
+    /// with the vars `ssh_host`, `ssh_port`, `ssh_identity_file` and `ssh_options`
+    pub fn ssh_host(&self) -> SshHost {
+        let string_var = |name: &str| match self.vars.get(name) {
+            Some(hcl::Value::String(s)) => Some(s.to_string()),
+            _ => None,
+        };
+
+        SshHost {
+            host: string_var("ssh_host").unwrap_or_else(|| self.host.clone()),
+            port: match self.vars.get("ssh_port") {
+                Some(hcl::Value::Number(n)) => n.as_u64().map(|n| n as usize),
+                Some(hcl::Value::String(s)) => s.parse().ok(),
+                _ => None,
+            },
+            user: self.remote_user.clone(),
+            identity_file: string_var("ssh_identity_file"),
+            options: match self.vars.get("ssh_options") {
+                Some(hcl::Value::Array(options)) => options
+                    .iter()
+                    .filter_map(|o| o.as_str().map(|o| o.to_string()))
+                    .collect(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_host_from_another_group() {
+        let mut web = Node::new(
+            "app1".to_string(),
+            HashMap::from([
+                ("port".to_string(), hcl::Value::from(8080)),
+                ("role".to_string(), hcl::Value::from("web")),
+            ]),
+        );
+        web.groups.push("web".to_string());
+        let mut db = Node::new(
+            "app1".to_string(),
+            HashMap::from([
+                ("port".to_string(), hcl::Value::from(5432)),
+                ("role".to_string(), hcl::Value::from("web")),
+                ("remote_user".to_string(), hcl::Value::from("admin")),
+            ]),
+        );
+        db.groups.push("db".to_string());
+        db.protected = Some("db".to_string());
+
+        assert_eq!(
+            web.merge(db).as_deref(),
+            Some(
+                "host app1 is in both group web and group db of the run, so it runs once \
+                 with the vars from web taking precedence, remote_user from db is added, \
+                 port from db is ignored"
             )
+        );
+        assert_eq!(web.vars["port"], hcl::Value::from(8080));
+        assert_eq!(web.remote_user.as_deref(), Some("admin"));
+        assert_eq!(web.groups, ["web", "db"]);
+        assert_eq!(web.protected.as_deref(), Some("db"));
+
+        let same = Node::new("app1".to_string(), web.vars.clone());
+        assert!(web.merge(same).is_none());
+    }
+
+    #[test]
+    fn merge_when_of_both_groups() {
+        let ctx = Context::new();
+        let node = |when: &[bool]| {
+            let mut node = Node::new("app1".to_string(), HashMap::new());
+            for when in when {
+                node.add_when(hcl::Expression::Bool(*when));
+            }
+            node
+        };
+
+        // the host is in the run through db, even though it's not through web
+        let mut web = node(&[true, false]);
+        web.merge(node(&[true]));
+        assert!(web.has_when());
+        assert!(web.check_when(&ctx).unwrap());
+
+        let mut web = node(&[false]);
+        web.merge(node(&[true, false]));
+        assert!(!web.check_when(&ctx).unwrap());
+
+        // without the conditions in db, the host is always in the run
+        let mut web = node(&[false]);
+        web.merge(node(&[]));
+        assert!(!web.has_when());
+        assert!(web.check_when(&ctx).unwrap());
+
+        // the conditions added after the merge apply to both groups
+        web.add_when(hcl::Expression::Bool(false));
+        assert!(!web.check_when(&ctx).unwrap());
+    }
+
+    #[test]
+    fn hosts_wait_for_shared_facts() {
+        let shared = Arc::new(SharedFacts::new());
+        shared.expect(3);
+
+        let handles: Vec<_> = ["web1", "db1", "db2"]
+            .into_iter()
+            .map(|host| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    // db2 fails to connect, so it doesn't have facts to share
+                    let facts = (host != "db2").then(|| Facts {
+                        hostname: host.to_string(),
+                        ..Default::default()
+                    });
+                    shared.share(host, facts.as_ref())
+                })
+            })
+            .collect();
+        for handle in handles {
+            let facts = handle.join().unwrap();
+            assert_eq!(facts.len(), 2);
+            assert_eq!(facts["db1"].hostname, "db1");
+            assert!(!facts.contains_key("db2"));
         }
+
+        // db2 is stuck connecting, so web1 stops waiting for it after the timeout
+        let shared = SharedFacts::new().with_timeout(Duration::from_millis(50));
+        shared.expect(2);
+        let facts = shared.share(
+            "web1",
+            Some(&Facts {
+                hostname: "web1".to_string(),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(facts.len(), 1);
+        // and it doesn't hold up the next host that's late either
+        let facts = shared.share("db2", None);
+        assert_eq!(facts.len(), 1);
+    }
+
+    #[test]
+    fn only_wait_for_hostvars_if_used() {
+        let mut node = Node::new("web1".to_string(), HashMap::new());
+        assert!(!node.uses_hostvars());
+        node.outputs.push((
+            "db".to_string(),
+            hcl_edit::parser::parse_expr("hostvars.db1.facts.hostname")
+                .unwrap()
+                .into(),
+        ));
+        assert!(node.uses_hostvars());
+    }
+
+    #[test]
+    fn local_facts_in_context() {
+        let mut facts = Facts::default();
+        facts
+            .local
+            .insert("app".to_string(), r#"{"schema":"v42"}"#.to_string());
+        let node = Node::new("web1".to_string(), HashMap::new());
+        let ctx = node.context(&facts, &HashMap::new());
+        let expr: hcl::Expression = hcl_edit::parser::parse_expr("facts.local.app.schema")
+            .unwrap()
+            .into();
+        assert_eq!(
+            expr.evaluate(&ctx).unwrap(),
+            hcl::Value::String("v42".to_string())
+        );
     }
 }
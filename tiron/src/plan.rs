@@ -0,0 +1,89 @@
+use std::fmt::Write;
+
+use itertools::Itertools;
+use tiron_common::{action::BlockSection, error::Error};
+
+use crate::{
+    core::{load_runs, runbook_paths},
+    run::Run,
+};
+
+/// Print the plan of the runbooks, which is the actions in order with their params
+/// for every host, without connecting to the hosts
+pub fn plan(runbooks: Vec<String>) -> Result<(), Error> {
+    let runs = load_runs(&runbook_paths(&runbooks), false)?;
+    print!("{}", format_plan(&runs));
+    Ok(())
+}
+
+fn format_plan(runs: &[Run]) -> String {
+    let mut plan = String::new();
+    for (i, run) in runs.iter().enumerate() {
+        if i > 0 {
+            plan.push('\n');
+        }
+        match run.name() {
+            Some(name) => {
+                let _ = writeln!(plan, "{name}");
+            }
+            None => {
+                let _ = writeln!(plan, "Run {}", i + 1);
+            }
+        }
+        if run.requires_approval {
+            let _ = writeln!(plan, "  requires approval");
+        }
+
+        for host in run.hosts() {
+            let mut notes = Vec::new();
+            if let Some(user) = &host.remote_user {
+                notes.push(format!("as {user}"));
+            }
+            if host.become_ {
+                notes.push("become".to_string());
+            }
+            if host.has_when() {
+                // the conditions can use the facts, which need a connection to the host
+                notes.push("if the when conditions are met".to_string());
+            }
+            if notes.is_empty() {
+                let _ = writeln!(plan, "  {}", host.host);
+            } else {
+                let _ = writeln!(plan, "  {} ({})", host.host, notes.join(", "));
+            }
+
+            if host.actions.is_empty() {
+                let _ = writeln!(plan, "    no actions");
+            }
+            for (n, action) in host.actions.iter().enumerate() {
+                let section = match action.scope.last().map(|scope| scope.section) {
+                    Some(BlockSection::Rescue) => "[rescue] ",
+                    Some(BlockSection::Always) => "[always] ",
+                    _ => "",
+                };
+                let name = if action.name == action.action {
+                    action.name.clone()
+                } else {
+                    format!("{} ({})", action.name, action.action)
+                };
+                let throttle = match &action.throttle {
+                    Some(throttle) => format!(" [throttle {}]", throttle.limit),
+                    None => String::new(),
+                };
+                let _ = writeln!(plan, "    {}. {section}{name}{throttle}", n + 1);
+                if !action.params.is_empty() {
+                    let _ = writeln!(
+                        plan,
+                        "       {}",
+                        action
+                            .params
+                            .iter()
+                            .map(|(key, value)| format!("{key} = {value}"))
+                            .join(", ")
+                    );
+                }
+            }
+        }
+    }
+    plan
+}
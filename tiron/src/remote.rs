@@ -1,19 +1,36 @@
 use std::{
-    io::BufReader,
+    fmt::Display,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::{Arc, Condvar, Mutex, OnceLock},
 };
 
 use anyhow::{anyhow, Result};
 use crossbeam_channel::{Receiver, Sender};
+use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
-use tiron_common::{action::ActionMessage, node::NodeMessage};
-use tiron_node::stdio::stdio_transport;
+use tiron_common::{
+    action::{ActionMessage, NodeStartFailedKind, MESSAGE_BUFFER},
+    crypto::{hex, sha256},
+    event::HostPhase,
+    node::NodeMessage,
+};
+use tiron_node::stdio::{read_msg, write_msg, write_msg_chunked};
+
+use crate::container::ContainerHost;
+#[cfg(unix)]
+use crate::control::control_path;
+use crate::log;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct SshHost {
     pub user: Option<String>,
     pub host: String,
     pub port: Option<usize>,
+    pub identity_file: Option<String>,
+    // extra ssh options in the form of `Key=Value`
+    pub options: Vec<String>,
 }
 
 impl SshHost {
@@ -24,36 +41,222 @@ pub fn user_host(&self) -> String {
             self.host.clone()
         }
     }
+
+    /// The command of an interactive shell on the host, which connects the same way
+    /// as the node does, and becomes root with sudo if `become_`
+    pub fn shell_command(&self, become_: bool) -> Vec<String> {
+        let mut cmd = vec!["ssh".to_string(), "-t".to_string()];
+        cmd.extend(["-o".to_string(), "ConnectTimeout=15".to_string()]);
+        #[cfg(unix)]
+        if let Some(control_path) = control_path() {
+            cmd.extend(
+                [
+                    "-o",
+                    "ControlMaster=auto",
+                    "-o",
+                    &format!("ControlPath={control_path}"),
+                    "-o",
+                    "ControlPersist=30m",
+                ]
+                .map(|s| s.to_string()),
+            );
+        }
+        cmd.extend(self.connection_args());
+        if become_ {
+            cmd.extend(["sudo".to_string(), "-i".to_string()]);
+        }
+        cmd
+    }
+
+    /// The command that forwards the local port to the remote address from the host,
+    /// until it's killed
+    pub fn forward_command(&self, local: u16, remote: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(["-N", "-o", "ExitOnForwardFailure=yes"])
+            .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=15"])
+            .arg("-L")
+            .arg(format!("127.0.0.1:{local}:{remote}"))
+            .args(self.connection_args());
+        cmd
+    }
+
+    /// The ssh command for the remote shell of rsync, which connects the same way
+    /// as the node does but without the destination, e.g. `ssh -o BatchMode=yes -p 2222`
+    pub fn rsh(&self) -> String {
+        let mut cmd: Vec<String> = ["ssh", "-o", "ConnectTimeout=15", "-o", "BatchMode=yes"]
+            .map(|s| s.to_string())
+            .to_vec();
+        #[cfg(unix)]
+        if let Some(control_path) = control_path() {
+            cmd.extend(
+                [
+                    "-o",
+                    "ControlMaster=auto",
+                    "-o",
+                    &format!("ControlPath={control_path}"),
+                    "-o",
+                    "ControlPersist=30m",
+                ]
+                .map(|s| s.to_string()),
+            );
+        }
+        let mut args = self.connection_args();
+        args.pop();
+        cmd.extend(args);
+        cmd.iter()
+            .map(|arg| {
+                // rsync splits the command by the spaces that aren't quoted,
+                // and it doesn't take backslashes
+                if arg.contains('\'') {
+                    format!("\"{arg}\"")
+                } else if arg.contains(|c: char| c.is_whitespace() || c == '"') {
+                    format!("'{arg}'")
+                } else {
+                    arg.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The args of ssh for the port, the identity file and the options,
+    /// and then the destination
+    fn connection_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(port) = self.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if let Some(identity_file) = &self.identity_file {
+            args.extend(["-i".to_string(), identity_file.clone()]);
+        }
+        for option in &self.options {
+            args.extend(["-o".to_string(), option.clone()]);
+        }
+        args.push(self.user_host());
+        args
+    }
+}
+
+/// The error when the node can't be started on the remote host,
+/// with the reason classified so that it can be shown as a category
+#[derive(Debug)]
+pub struct NodeStartError {
+    pub kind: NodeStartFailedKind,
+    pub reason: String,
+}
+
+impl NodeStartError {
+    pub fn new(kind: NodeStartFailedKind, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            reason: reason.into(),
+        }
+    }
+
+    /// classify the failure from what ssh printed to stderr
+    fn from_ssh_stderr(ssh: &SshHost, stderr: &str) -> Self {
+        use NodeStartFailedKind::*;
+
+        // ssh runs in batch mode, so anything it wanted to ask the user ends up
+        // as an error in stderr. Translate those into something actionable.
+        if stderr.contains("REMOTE HOST IDENTIFICATION HAS CHANGED") {
+            Self::new(
+                HostKey,
+                format!(
+                    "host key of {} has changed, verify it and update your known_hosts",
+                    ssh.host
+                ),
+            )
+        } else if stderr.contains("Host key verification failed") {
+            Self::new(
+                HostKey,
+                format!(
+                    "host key of {} isn't trusted yet, connect to it once with ssh to verify it",
+                    ssh.host
+                ),
+            )
+        } else if stderr.contains("Permission denied")
+            || stderr.contains("Too many authentication failures")
+        {
+            Self::new(
+                Authentication,
+                format!(
+                    "authentication to {} failed, passwords and key passphrases can't be prompted, add your key to ssh-agent",
+                    ssh.user_host()
+                ),
+            )
+        } else if stderr.contains("Could not resolve hostname") {
+            Self::new(DnsResolution, format!("can't resolve host {}", ssh.host))
+        } else if stderr.contains("timed out") {
+            Self::new(Timeout, format!("connection to {} timed out", ssh.host))
+        } else if stderr.contains("Connection refused")
+            || stderr.contains("Connection closed")
+            || stderr.contains("No route to host")
+        {
+            Self::new(Connection, stderr.trim())
+        } else {
+            Self::new(Other, stderr.trim())
+        }
+    }
+}
+
+impl Display for NodeStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.reason)
+    }
 }
 
+impl std::error::Error for NodeStartError {}
+
 pub struct SshRemote {
     pub ssh: SshHost,
+    // the host runs in the container, so the commands are executed
+    // with the container engine instead of ssh
+    pub container: Option<ContainerHost>,
 }
 
 impl SshRemote {
     #[cfg(windows)]
-    const SSH_ARGS: &'static [&'static str] = &[];
+    const SSH_ARGS: &'static [&'static str] = &["-o", "BatchMode=yes"];
 
     #[cfg(unix)]
     const SSH_ARGS: &'static [&'static str] = &[
-        "-o",
-        "ControlMaster=auto",
-        "-o",
-        "ControlPath=~/.ssh/cm_%C",
-        "-o",
-        "ControlPersist=30m",
         "-o",
         "ConnectTimeout=15",
+        // ssh can't prompt for anything when the TUI owns the terminal,
+        // so we make it fail instead of waiting for an input that never comes
+        "-o",
+        "BatchMode=yes",
     ];
 
     fn command_builder(&self) -> Command {
+        if let Some(container) = &self.container {
+            return container.command_builder();
+        }
+
         let mut cmd = Self::new_command("ssh");
         cmd.args(Self::SSH_ARGS);
 
+        // the connections to the host share one ssh connection
+        #[cfg(unix)]
+        if let Some(control_path) = control_path() {
+            cmd.args(["-o", "ControlMaster=auto", "-o"])
+                .arg(format!("ControlPath={control_path}"))
+                .args(["-o", "ControlPersist=30m"]);
+        }
+
         if let Some(port) = self.ssh.port {
             cmd.arg("-p").arg(port.to_string());
         }
 
+        if let Some(identity_file) = &self.ssh.identity_file {
+            cmd.arg("-i").arg(identity_file);
+        }
+
+        for option in &self.ssh.options {
+            cmd.arg("-o").arg(option);
+        }
+
         cmd.arg(self.ssh.user_host());
 
         if !std::env::var("TIRON_DEBUG").unwrap_or_default().is_empty() {
@@ -63,6 +266,68 @@ fn command_builder(&self) -> Command {
         cmd
     }
 
+    /// The id of the current boot of the host, which changes when the host reboots
+    pub fn boot_id(&self) -> Option<String> {
+        let output = self
+            .command_builder()
+            .arg("cat /proc/sys/kernel/random/boot_id 2> /dev/null || sysctl -n kern.boottime")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !id.is_empty()).then_some(id)
+    }
+
+    /// Reboot the host a second later, so that ssh can return before the host goes down
+    pub fn reboot(&self, sudo: bool) -> Result<()> {
+        let reboot = "id -u | grep -qx 0 || { echo 'reboot needs root, e.g. with become = true' >&2; exit 1; }; \
+                      nohup sh -c 'sleep 1; shutdown -r now' > /dev/null 2>&1 &";
+        // sudo can't prompt for the password, so it's given on stdin like for the node
+        let become_password = if sudo { become_password(self)? } else { None };
+        let arg = match (sudo, &become_password) {
+            (true, Some(_)) => format!("sudo -k -S -p '' sh -c \"{reboot}\""),
+            (true, None) => format!("sudo -n sh -c \"{reboot}\""),
+            (false, _) => reboot.to_string(),
+        };
+        let mut child = self
+            .command_builder()
+            .arg(arg)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Some(password) = become_password {
+                writeln!(stdin, "{password}")?;
+            }
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "can't reboot the host: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Close the shared ssh connection to the host, e.g. when the host reboots,
+    /// so that the next commands don't wait on the connection that's gone
+    pub fn close_connection(&self) {
+        #[cfg(unix)]
+        if let Some(control_path) = control_path() {
+            let _ = Self::new_command("ssh")
+                .arg("-o")
+                .arg(format!("ControlPath={control_path}"))
+                .args(["-O", "exit"])
+                .args(self.ssh.connection_args())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+
     fn new_command(program: &str) -> Command {
         #[allow(unused_mut)]
         let mut cmd = Command::new(program);
@@ -77,27 +342,63 @@ fn new_command(program: &str) -> Command {
 pub fn start_remote(
     remote: SshRemote,
     sudo: bool,
+    phase: &dyn Fn(HostPhase),
 ) -> Result<(Sender<NodeMessage>, Receiver<ActionMessage>)> {
-    let (platform, architecture) = host_specification(&remote)?;
+    let host = remote.ssh.user_host();
+    log::info("ssh", "connect", &[("host", &host)]);
+    phase(HostPhase::Resolving);
+    let (platform, architecture, environment) = host_specification(&remote)?;
+    log::debug(
+        "ssh",
+        "the platform of the host",
+        &[
+            ("host", &host),
+            ("platform", &platform),
+            ("architecture", &architecture),
+            ("environment", &environment),
+        ],
+    );
 
     if platform == HostPlatform::UnknownOS {
-        return Err(anyhow!("Unknown OS"));
+        return Err(
+            NodeStartError::new(NodeStartFailedKind::UnsupportedPlatform, "Unknown OS").into(),
+        );
     }
 
     if architecture == HostArchitecture::UnknownArch {
-        return Err(anyhow!("Unknown architecture"));
+        return Err(NodeStartError::new(
+            NodeStartFailedKind::UnsupportedPlatform,
+            "Unknown architecture",
+        )
+        .into());
+    }
+
+    if sudo && environment == HostEnvironment::Cygwin {
+        return Err(NodeStartError::new(
+            NodeStartFailedKind::UnsupportedPlatform,
+            "cygwin and msys don't have sudo, so the host can't run with become",
+        )
+        .into());
     }
 
     // ! Below paths have to be synced with what is
     // ! returned by Config::proxy_directory()
-    let tiron_node_path = match platform {
-        HostPlatform::Windows => "%HOMEDRIVE%%HOMEPATH%\\AppData\\Local\\tiron\\tiron\\data",
-        HostPlatform::Darwin => "~/Library/Application\\ Support/dev.tiron.tiron",
+    let tiron_node_path = match (platform, environment) {
+        // the posix shell of cygwin and msys runs the windows node from its home
+        (HostPlatform::Windows, HostEnvironment::Cygwin) => "~/.local/share/tiron",
+        (HostPlatform::Windows, _) => "%HOMEDRIVE%%HOMEPATH%\\AppData\\Local\\tiron\\tiron\\data",
+        (HostPlatform::Darwin, _) => "~/Library/Application\\ Support/dev.tiron.tiron",
         _ => "~/.local/share/tiron",
     };
 
-    let tiron_node_file = match platform {
-        HostPlatform::Windows => {
+    let tiron_node_file = match (platform, environment) {
+        (HostPlatform::Windows, HostEnvironment::Cygwin) => {
+            format!(
+                "{tiron_node_path}/tiron-node-{}.exe",
+                env!("CARGO_PKG_VERSION")
+            )
+        }
+        (HostPlatform::Windows, _) => {
             format!(
                 "{tiron_node_path}\\tiron-node-{}.exe",
                 env!("CARGO_PKG_VERSION")
@@ -106,28 +407,49 @@ pub fn start_remote(
         _ => format!("{tiron_node_path}/tiron-node-{}", env!("CARGO_PKG_VERSION")),
     };
 
-    if !remote
-        .command_builder()
-        .args([&tiron_node_file, "--version"])
-        .output()
-        .map(|output| {
-            String::from_utf8_lossy(&output.stdout).trim()
-                == format!("tiron-node {}", env!("CARGO_PKG_VERSION"))
-        })
-        .unwrap_or(false)
-    {
-        download_remote(
+    phase(HostPhase::Bootstrapping);
+    let install = match node_install() {
+        // the node is trusted to be there, which saves a round trip to the host
+        NodeInstall::Skip => false,
+        NodeInstall::Reinstall => true,
+        NodeInstall::Check => !node_version_matches(&remote, &tiron_node_file),
+    };
+    if install {
+        log::info(
+            "ssh",
+            "install the node",
+            &[("host", &host), ("file", &tiron_node_file)],
+        );
+        install_node(
             &remote,
             &platform,
             &architecture,
+            environment,
             tiron_node_path,
             &tiron_node_file,
         )?;
+        if !node_version_matches(&remote, &tiron_node_file) {
+            return Err(NodeStartError::new(
+                NodeStartFailedKind::VersionMismatch,
+                format!(
+                    "{tiron_node_file} on the host isn't tiron-node {}",
+                    env!("CARGO_PKG_VERSION")
+                ),
+            )
+            .into());
+        }
+    };
+
+    // sudo can't prompt for the password, so it's given on stdin if it needs one
+    let become_password = if sudo {
+        become_password(&remote)?
+    } else {
+        None
     };
 
-    let mut child = match platform {
+    let mut child = match (platform, environment) {
         // Force cmd.exe usage to resolve %envvar% variables
-        HostPlatform::Windows => remote
+        (HostPlatform::Windows, HostEnvironment::Native) => remote
             .command_builder()
             .args(["cmd", "/c"])
             .arg(&tiron_node_file)
@@ -136,10 +458,12 @@ pub fn start_remote(
             .spawn()?,
         _ => {
             let mut cmd = remote.command_builder();
-            let arg = if sudo {
-                format!("sudo {tiron_node_file}")
-            } else {
-                tiron_node_file
+            let arg = match (sudo, &become_password) {
+                // -k ignores the cached credentials, so sudo always reads the password
+                // line, which would go to the node otherwise
+                (true, Some(_)) => format!("sudo -k -S -p '' {tiron_node_file}"),
+                (true, None) => format!("sudo {tiron_node_file}"),
+                (false, _) => tiron_node_file,
             };
             cmd.arg(&arg)
                 .stdin(Stdio::piped())
@@ -148,10 +472,13 @@ pub fn start_remote(
                 .spawn()?
         }
     };
-    let stdin = child
+    let mut stdin = child
         .stdin
         .take()
         .ok_or_else(|| anyhow!("can't find stdin"))?;
+    if let Some(password) = become_password {
+        writeln!(stdin, "{password}")?;
+    }
     let stdout = BufReader::new(
         child
             .stdout
@@ -159,26 +486,458 @@ pub fn start_remote(
             .ok_or_else(|| anyhow!("can't find stdout"))?,
     );
 
+    log::debug("ssh", "start the node", &[("host", &host), ("sudo", &sudo)]);
+
     let (writer_tx, writer_rx) = crossbeam_channel::unbounded::<NodeMessage>();
-    let (reader_tx, reader_rx) = crossbeam_channel::unbounded::<ActionMessage>();
-    stdio_transport(stdin, writer_rx, stdout, reader_tx);
+    // the node pauses when tiron can't keep up, as it can't write to ssh
+    let (reader_tx, reader_rx) = crossbeam_channel::bounded::<ActionMessage>(MESSAGE_BUFFER);
+    node_transport(host, stdin, writer_rx, stdout, reader_tx);
 
     Ok((writer_tx, reader_rx))
 }
 
-fn download_remote(
+/// The actions are sent in chunks if their inputs are at least this large,
+/// so that the progress of sending them can be shown
+const TRANSFER_PROGRESS_MIN: usize = 1024 * 1024;
+const TRANSFER_CHUNK: usize = 64 * 1024;
+
+/// Like `stdio_transport`, but the progress of sending the large actions is sent
+/// to the reader as `ActionTransfer`, as if it's from the node
+fn node_transport(
+    host: String,
+    mut writer: impl Write + Send + 'static,
+    writer_rx: Receiver<NodeMessage>,
+    mut reader: impl BufRead + Send + 'static,
+    reader_tx: Sender<ActionMessage>,
+) {
+    // the reader drops it when the node exits, so that the receiver of the messages
+    // knows the node is gone even if the writer is still waiting for messages
+    let reader_tx = Arc::new(Mutex::new(Some(reader_tx)));
+    {
+        let reader_tx = reader_tx.clone();
+        let host = host.clone();
+        std::thread::spawn(move || {
+            for msg in writer_rx {
+                log::trace(
+                    "protocol",
+                    "send",
+                    &[("host", &host), ("message", &<&str>::from(&msg))],
+                );
+                let id = match &msg {
+                    NodeMessage::Action(action) if action.input.len() >= TRANSFER_PROGRESS_MIN => {
+                        Some(action.id)
+                    }
+                    _ => None,
+                };
+                let result = match id {
+                    Some(id) => {
+                        write_msg_chunked(&mut writer, msg, TRANSFER_CHUNK, |sent, total| {
+                            if let Ok(Some(tx)) = reader_tx.lock().as_deref() {
+                                let _ = tx.send(ActionMessage::ActionTransfer { id, sent, total });
+                            }
+                        })
+                    }
+                    None => write_msg(&mut writer, msg),
+                };
+                if let Err(e) = result {
+                    log::debug(
+                        "protocol",
+                        "can't write to the node",
+                        &[("host", &host), ("error", &e)],
+                    );
+                    return;
+                }
+            }
+        });
+    }
+    std::thread::spawn(move || {
+        loop {
+            match read_msg::<_, ActionMessage>(&mut reader) {
+                Ok(Some(msg)) => {
+                    log::trace(
+                        "protocol",
+                        "receive",
+                        &[("host", &host), ("message", &<&str>::from(&msg))],
+                    );
+                    if let Ok(Some(tx)) = reader_tx.lock().as_deref() {
+                        let _ = tx.send(msg);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::debug(
+                        "protocol",
+                        "the node is gone",
+                        &[("host", &host), ("error", &e)],
+                    );
+                    break;
+                }
+            }
+        }
+        if let Ok(mut tx) = reader_tx.lock() {
+            tx.take();
+        }
+    });
+}
+
+fn node_version_matches(remote: &SshRemote, tiron_node_file: &str) -> bool {
+    remote
+        .command_builder()
+        .args([tiron_node_file, "--version"])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout).trim()
+                == format!("tiron-node {}", env!("CARGO_PKG_VERSION"))
+        })
+        .unwrap_or(false)
+}
+
+/// How the node is installed on the hosts before it's started
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeInstall {
+    // install the node if the version on the host isn't the same as tiron
+    Check,
+    // start the node on the host without checking it
+    Skip,
+    // install the node again even if the host has it, e.g. when it's corrupted
+    Reinstall,
+}
+
+static NODE_INSTALL: OnceLock<NodeInstall> = OnceLock::new();
+
+/// Set how the node is installed on the hosts for the rest of the command
+pub fn set_node_install(install: NodeInstall) {
+    let _ = NODE_INSTALL.set(install);
+}
+
+fn node_install() -> NodeInstall {
+    NODE_INSTALL.get().copied().unwrap_or(NodeInstall::Check)
+}
+
+static BECOME_PASSWORD: OnceLock<String> = OnceLock::new();
+
+/// Set the password of sudo on the hosts that can't become root without one
+pub fn set_become_password(password: String) {
+    let _ = BECOME_PASSWORD.set(password);
+}
+
+/// What `sudo -n true` on the host says about becoming root
+#[derive(Debug)]
+enum SudoProbe {
+    Passwordless,
+    NeedsPassword,
+    Failed(NodeStartError),
+}
+
+impl SudoProbe {
+    fn from_output(ssh: &SshHost, success: bool, stderr: &str) -> Self {
+        use NodeStartFailedKind::*;
+
+        if success {
+            Self::Passwordless
+        } else if stderr.contains("password is required") {
+            Self::NeedsPassword
+        } else if stderr.contains("not found") {
+            Self::Failed(NodeStartError::new(
+                UnsupportedPlatform,
+                format!(
+                    "sudo isn't installed on {}, so it can't become root",
+                    ssh.host
+                ),
+            ))
+        } else if stderr.contains("not in the sudoers")
+            || stderr.contains("not allowed")
+            || stderr.contains("may not run sudo")
+        {
+            Self::Failed(NodeStartError::new(
+                Authentication,
+                format!("{} isn't allowed to use sudo", ssh.user_host()),
+            ))
+        } else {
+            Self::Failed(NodeStartError::new(
+                Other,
+                format!("sudo failed on {}: {}", ssh.host, stderr.trim()),
+            ))
+        }
+    }
+}
+
+/// The password that sudo on the host needs to become root, or None if it
+/// doesn't need one. The password is checked before the node is started with it
+fn become_password(remote: &SshRemote) -> Result<Option<String>> {
+    let output = remote
+        .command_builder()
+        .arg("sudo -n true")
+        .stdin(Stdio::null())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match SudoProbe::from_output(&remote.ssh, output.status.success(), &stderr) {
+        SudoProbe::Passwordless => Ok(None),
+        SudoProbe::Failed(e) => Err(e.into()),
+        SudoProbe::NeedsPassword => {
+            let Some(password) = BECOME_PASSWORD.get() else {
+                return Err(NodeStartError::new(
+                    NodeStartFailedKind::Authentication,
+                    "passwordless sudo unavailable; use --ask-become-pass",
+                )
+                .into());
+            };
+            let mut child = remote
+                .command_builder()
+                .arg("sudo -k -S -p '' true")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                writeln!(stdin, "{password}")?;
+            }
+            if !child.wait()?.success() {
+                return Err(NodeStartError::new(
+                    NodeStartFailedKind::Authentication,
+                    format!(
+                        "sudo on {} doesn't take the become password",
+                        remote.ssh.host
+                    ),
+                )
+                .into());
+            }
+            Ok(Some(password.clone()))
+        }
+    }
+}
+
+/// The maximum number of hosts downloading tiron-node from GitHub at the same time,
+/// so that a large fleet behind the same NAT doesn't get rate limited
+const MAX_CONCURRENT_DOWNLOADS: usize = 4;
+
+static DOWNLOADS: Mutex<usize> = Mutex::new(0);
+static DOWNLOAD_FINISHED: Condvar = Condvar::new();
+
+// only one host populates the controller cache, the others wait and reuse it
+static CONTROLLER_CACHE: Mutex<()> = Mutex::new(());
+
+/// A slot for downloading tiron-node on the remote host,
+/// which is released when dropped
+struct DownloadSlot;
+
+impl DownloadSlot {
+    fn acquire() -> Self {
+        let mut downloads = DOWNLOADS.lock().unwrap_or_else(|e| e.into_inner());
+        while *downloads >= MAX_CONCURRENT_DOWNLOADS {
+            downloads = DOWNLOAD_FINISHED
+                .wait(downloads)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *downloads += 1;
+        DownloadSlot
+    }
+}
+
+impl Drop for DownloadSlot {
+    fn drop(&mut self) {
+        let mut downloads = DOWNLOADS.lock().unwrap_or_else(|e| e.into_inner());
+        *downloads = downloads.saturating_sub(1);
+        DOWNLOAD_FINISHED.notify_one();
+    }
+}
+
+/// Install tiron-node on the remote host. If the controller already has the binary
+/// cached, it's uploaded directly. Otherwise the host downloads it from GitHub,
+/// and if that fails, the controller downloads it to its cache and uploads it.
+fn install_node(
     remote: &SshRemote,
     platform: &HostPlatform,
     architecture: &HostArchitecture,
+    environment: HostEnvironment,
     tiron_node_path: &str,
     tiron_node_file: &str,
 ) -> Result<()> {
-    let url = format!(
+    // both the download and the upload go through a posix shell on the host
+    if (*platform, environment) == (HostPlatform::Windows, HostEnvironment::Native) {
+        return Err(NodeStartError::new(
+            NodeStartFailedKind::UnsupportedPlatform,
+            format!(
+                "tiron-node can't be installed on windows without cygwin or msys, \
+                 it needs to be put at {tiron_node_file} by hand"
+            ),
+        )
+        .into());
+    }
+
+    if let Some(cached) = controller_cache_file(platform, architecture) {
+        if cached.exists()
+            && upload_remote(remote, &cached, tiron_node_path, tiron_node_file).is_ok()
+            && node_version_matches(remote, tiron_node_file)
+        {
+            return Ok(());
+        }
+    }
+
+    let download = {
+        let _slot = DownloadSlot::acquire();
+        download_remote(
+            remote,
+            platform,
+            architecture,
+            tiron_node_path,
+            tiron_node_file,
+        )
+    };
+    if download.is_ok() && node_version_matches(remote, tiron_node_file) {
+        return Ok(());
+    }
+
+    let cached = download_controller_cache(platform, architecture).map_err(|e| {
+        let remote_error = download.err().map(|e| format!("{e}, ")).unwrap_or_default();
+        NodeStartError::new(
+            NodeStartFailedKind::NodeDownload,
+            format!("{remote_error}and the controller can't download it either: {e}"),
+        )
+    })?;
+    upload_remote(remote, &cached, tiron_node_path, tiron_node_file)
+}
+
+fn node_download_url(platform: &HostPlatform, architecture: &HostArchitecture) -> String {
+    format!(
         "https://github.com/lapce/tiron/releases/download/v{}/tiron-node-{}-{platform}-{architecture}.gz",
         env!("CARGO_PKG_VERSION"),
         env!("CARGO_PKG_VERSION")
-    );
-    remote
+    )
+}
+
+/// The path of the tiron-node binary in the cache directory of the controller
+fn controller_cache_file(
+    platform: &HostPlatform,
+    architecture: &HostArchitecture,
+) -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(cache_dir.join("tiron").join(format!(
+        "tiron-node-{}-{platform}-{architecture}",
+        env!("CARGO_PKG_VERSION")
+    )))
+}
+
+/// Download tiron-node to the cache directory of the controller
+/// if it's not there yet. The download is checked against the checksum
+/// published with the release before it's cached, as it's uploaded to the hosts
+fn download_controller_cache(
+    platform: &HostPlatform,
+    architecture: &HostArchitecture,
+) -> Result<PathBuf> {
+    let _lock = CONTROLLER_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+
+    let file = controller_cache_file(platform, architecture)
+        .ok_or_else(|| anyhow!("can't find the cache directory"))?;
+    if file.exists() {
+        return Ok(file);
+    }
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let url = node_download_url(platform, architecture);
+    let gz = http_get(&url)?;
+    let checksum = http_get(&format!("{url}.sha256"))?;
+    let expected = String::from_utf8_lossy(&checksum)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = hex(&sha256(&gz));
+    if actual != expected {
+        return Err(anyhow!(
+            "the checksum of {url} is {actual}, but the release has {expected}"
+        ));
+    }
+
+    let mut binary = Vec::new();
+    GzDecoder::new(gz.as_slice())
+        .read_to_end(&mut binary)
+        .map_err(|e| anyhow!("can't decompress {url}: {e}"))?;
+    // the binary is written next to the cache file first, so that an interrupted
+    // download doesn't leave a broken binary in the cache
+    let mut partial = file.clone().into_os_string();
+    partial.push(".partial");
+    std::fs::write(&partial, &binary)?;
+    std::fs::rename(&partial, &file)?;
+
+    Ok(file)
+}
+
+/// Download the url on the controller
+fn http_get(url: &str) -> Result<Vec<u8>> {
+    let resp = ureq::get(url)
+        .call()
+        .map_err(|e| anyhow!("can't download {url}: {e}"))?;
+    let mut body = Vec::new();
+    resp.into_reader()
+        .read_to_end(&mut body)
+        .map_err(|e| anyhow!("can't download {url}: {e}"))?;
+    Ok(body)
+}
+
+/// Upload the tiron-node binary from the controller to the remote host,
+/// which needs a posix shell on the host
+fn upload_remote(
+    remote: &SshRemote,
+    local_file: &Path,
+    tiron_node_path: &str,
+    tiron_node_file: &str,
+) -> Result<()> {
+    let content = std::fs::read(local_file)?;
+    let mut child = remote
+        .command_builder()
+        .args([
+            "mkdir",
+            "-p",
+            tiron_node_path,
+            "&&",
+            "cat",
+            ">",
+            tiron_node_file,
+            "&&",
+            "chmod",
+            "+x",
+            tiron_node_file,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("can't find stdin"))?;
+        stdin.write_all(&content)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(NodeStartError::new(
+            NodeStartFailedKind::NodeDownload,
+            format!(
+                "can't upload tiron-node to the host: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn download_remote(
+    remote: &SshRemote,
+    platform: &HostPlatform,
+    architecture: &HostArchitecture,
+    tiron_node_path: &str,
+    tiron_node_file: &str,
+) -> Result<()> {
+    let url = node_download_url(platform, architecture);
+    let output = remote
         .command_builder()
         .args([
             "mkdir",
@@ -199,14 +958,26 @@ fn download_remote(
             tiron_node_file,
         ])
         .output()?;
+    if !output.status.success() {
+        return Err(NodeStartError::new(
+            NodeStartFailedKind::NodeDownload,
+            format!(
+                "can't download {url}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        )
+        .into());
+    }
     Ok(())
 }
 
-fn host_specification(remote: &SshRemote) -> Result<(HostPlatform, HostArchitecture)> {
+fn host_specification(
+    remote: &SshRemote,
+) -> Result<(HostPlatform, HostArchitecture, HostEnvironment)> {
     use HostArchitecture::*;
     use HostPlatform::*;
 
-    let cmd = remote.command_builder().args(["uname", "-sm"]).output();
+    let cmd = remote.command_builder().args(["uname", "-srm"]).output();
 
     let spec = match cmd {
         Ok(cmd) => {
@@ -218,22 +989,22 @@ fn host_specification(remote: &SshRemote) -> Result<(HostPlatform, HostArchitect
                 "" => {
                     let (os, arch) = host_specification_try_windows(remote)?;
                     if os != UnknownOS && arch != UnknownArch {
-                        (os, arch)
+                        (os, arch, HostEnvironment::Native)
                     } else {
-                        return Err(anyhow!(String::from_utf8_lossy(&cmd.stderr).to_string()));
+                        let stderr = String::from_utf8_lossy(&cmd.stderr);
+                        return Err(NodeStartError::from_ssh_stderr(&remote.ssh, &stderr).into());
                     }
                 }
                 v => {
-                    if let Some((os, arch)) = v.split_once(' ') {
-                        let os = parse_os(os);
-                        let arch = parse_arch(arch);
-                        if os == UnknownOS || arch == UnknownArch {
-                            return Err(anyhow!(v.to_string()));
-                        }
-                        (os, arch)
-                    } else {
-                        return Err(anyhow!(v.to_string()));
+                    let (os, arch, environment) = parse_uname(v);
+                    if os == UnknownOS || arch == UnknownArch {
+                        return Err(NodeStartError::new(
+                            NodeStartFailedKind::UnsupportedPlatform,
+                            v,
+                        )
+                        .into());
                     }
+                    (os, arch, environment)
                 }
             }
         }
@@ -294,6 +1065,44 @@ fn parse_arch(arch: &str) -> HostArchitecture {
     }
 }
 
+/// The platform, the architecture and the environment from `uname -srm`,
+/// e.g. `linux 5.15.90.1-microsoft-standard-wsl2 x86_64` for WSL,
+/// or `cygwin_nt-10.0-19045 3.4.6-1.x86_64 x86_64` for cygwin
+fn parse_uname(uname: &str) -> (HostPlatform, HostArchitecture, HostEnvironment) {
+    let mut parts = uname.split_whitespace();
+    let (Some(os), Some(arch)) = (parts.next(), parts.next_back()) else {
+        return (
+            HostPlatform::UnknownOS,
+            HostArchitecture::UnknownArch,
+            HostEnvironment::Native,
+        );
+    };
+    let release = parts.next().unwrap_or_default().to_lowercase();
+    let os = os.to_lowercase();
+    // cygwin, msys and mingw are windows with a posix shell, which run the windows node
+    if ["cygwin_nt", "msys_nt", "mingw32_nt", "mingw64_nt"]
+        .iter()
+        .any(|prefix| os.starts_with(prefix))
+    {
+        return (
+            HostPlatform::Windows,
+            parse_arch(arch),
+            HostEnvironment::Cygwin,
+        );
+    }
+    let os = parse_os(&os);
+    // WSL runs the linux node from its own home like any linux,
+    // so it's only told apart in the logs
+    let environment = if os == HostPlatform::Linux
+        && (release.contains("microsoft") || release.contains("wsl"))
+    {
+        HostEnvironment::Wsl
+    } else {
+        HostEnvironment::Native
+    };
+    (os, parse_arch(arch), environment)
+}
+
 fn parse_os(os: &str) -> HostPlatform {
     use HostPlatform::*;
     match os.to_lowercase().as_str() {
@@ -319,6 +1128,20 @@ enum HostPlatform {
     Bsd,
 }
 
+/// Where the platform of the host runs, which changes how the node is installed
+/// and started on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+enum HostEnvironment {
+    #[strum(serialize = "native")]
+    Native,
+    // the windows subsystem for linux
+    #[strum(serialize = "wsl")]
+    Wsl,
+    // cygwin, msys or mingw, which are windows with a posix shell
+    #[strum(serialize = "cygwin")]
+    Cygwin,
+}
+
 /// serialise via strum to arch name that is used
 /// in CI artefacts
 #[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
@@ -336,3 +1159,89 @@ enum HostArchitecture {
     #[strum(serialize = "armhf")]
     ARM32v6,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uname_environments() {
+        assert_eq!(
+            parse_uname("linux 6.8.0-45-generic x86_64"),
+            (
+                HostPlatform::Linux,
+                HostArchitecture::AMD64,
+                HostEnvironment::Native
+            )
+        );
+        assert_eq!(
+            parse_uname("linux 5.15.90.1-microsoft-standard-wsl2 x86_64"),
+            (
+                HostPlatform::Linux,
+                HostArchitecture::AMD64,
+                HostEnvironment::Wsl
+            )
+        );
+        assert_eq!(
+            parse_uname("cygwin_nt-10.0-19045 3.4.6-1.x86_64 x86_64"),
+            (
+                HostPlatform::Windows,
+                HostArchitecture::AMD64,
+                HostEnvironment::Cygwin
+            )
+        );
+        assert_eq!(
+            parse_uname("mingw64_nt-10.0-19045 3.4.7.x86_64 x86_64"),
+            (
+                HostPlatform::Windows,
+                HostArchitecture::AMD64,
+                HostEnvironment::Cygwin
+            )
+        );
+        assert_eq!(
+            parse_uname("darwin 23.6.0 arm64"),
+            (
+                HostPlatform::Darwin,
+                HostArchitecture::ARM64,
+                HostEnvironment::Native
+            )
+        );
+        assert_eq!(parse_uname("linux").0, HostPlatform::UnknownOS);
+    }
+
+    #[test]
+    fn sudo_probe() {
+        let ssh = SshHost {
+            user: Some("deploy".to_string()),
+            host: "web1".to_string(),
+            port: None,
+            identity_file: None,
+            options: Vec::new(),
+        };
+        assert!(matches!(
+            SudoProbe::from_output(&ssh, true, ""),
+            SudoProbe::Passwordless
+        ));
+        assert!(matches!(
+            SudoProbe::from_output(&ssh, false, "sudo: a password is required\n"),
+            SudoProbe::NeedsPassword
+        ));
+        let SudoProbe::Failed(e) =
+            SudoProbe::from_output(&ssh, false, "bash: line 1: sudo: command not found\n")
+        else {
+            panic!("sudo should be missing");
+        };
+        assert_eq!(e.kind, NodeStartFailedKind::UnsupportedPlatform);
+        let SudoProbe::Failed(e) = SudoProbe::from_output(
+            &ssh,
+            false,
+            "deploy is not in the sudoers file.  This incident will be reported.\n",
+        ) else {
+            panic!("deploy shouldn't be allowed to use sudo");
+        };
+        assert_eq!(
+            e.to_string(),
+            "authentication: deploy@web1 isn't allowed to use sudo"
+        );
+    }
+}
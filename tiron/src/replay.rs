@@ -0,0 +1,206 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::ActionId,
+    error::Error,
+    event::{Event, EventSink},
+};
+use tiron_tui::{
+    app::App,
+    event::TuiSink,
+    run::{ActionSection, HostSection, RunPanel},
+};
+use uuid::Uuid;
+
+use crate::{history::history_dir, run::Run};
+
+/// What the TUI needs to show a run before its events are replayed
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RecordedRun {
+    id: Uuid,
+    name: Option<String>,
+    hosts: Vec<RecordedHost>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedHost {
+    id: Uuid,
+    host: String,
+    environment: Option<String>,
+    color: Option<String>,
+    actions: Vec<(ActionId, String)>,
+}
+
+impl RecordedRun {
+    pub(crate) fn new(run: &Run) -> Self {
+        Self {
+            id: run.id,
+            name: run.name().map(|name| name.to_string()),
+            hosts: run
+                .hosts()
+                .iter()
+                .map(|host| RecordedHost {
+                    id: host.id,
+                    host: host.host.clone(),
+                    environment: host.tag("environment"),
+                    color: host.tag("color"),
+                    actions: host
+                        .actions
+                        .iter()
+                        .map(|action| (action.id, action.name.clone()))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn to_panel(&self) -> RunPanel {
+        let hosts = self
+            .hosts
+            .iter()
+            .map(|host| {
+                HostSection::new(
+                    host.id,
+                    host.host.clone(),
+                    host.actions
+                        .iter()
+                        .map(|(id, name)| ActionSection::new(*id, name.clone()))
+                        .collect(),
+                )
+                .with_tags(host.environment.clone(), host.color.as_deref())
+            })
+            .collect();
+        RunPanel::new(self.id, self.name.clone(), hosts)
+    }
+}
+
+/// A line of the recording, which starts with the runs and then has the events
+/// with when they happened in milliseconds from the start
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RecordedLine {
+    Runs(Vec<RecordedRun>),
+    Event { at: u64, event: Event },
+}
+
+/// Records the events of the runs in the run history next to the record of the runs,
+/// so that they can be replayed in the TUI with `tiron replay`
+pub struct RecordingSink {
+    start: Instant,
+    writer: Mutex<File>,
+}
+
+impl RecordingSink {
+    /// The recording of the record with the id, which is `<id>.events.jsonl`
+    /// in the history directory
+    pub fn create(id: Uuid, runs: &[Run]) -> Result<Self> {
+        let dir = history_dir().ok_or_else(|| anyhow!("can't find the history directory"))?;
+        std::fs::create_dir_all(&dir)?;
+        let mut file = File::create(recording_path(&dir, &id.to_string()))?;
+        let runs = RecordedLine::Runs(runs.iter().map(RecordedRun::new).collect());
+        writeln!(file, "{}", serde_json::to_string(&runs)?)?;
+        Ok(Self {
+            start: Instant::now(),
+            writer: Mutex::new(file),
+        })
+    }
+}
+
+impl EventSink for RecordingSink {
+    fn send(&self, event: Event) {
+        let line = RecordedLine::Event {
+            at: self.start.elapsed().as_millis() as u64,
+            event,
+        };
+        let Ok(json) = serde_json::to_string(&line) else {
+            return;
+        };
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{json}");
+        }
+    }
+}
+
+fn recording_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.events.jsonl"))
+}
+
+/// Replay the recorded run in the TUI, whose id can be a prefix, at `speed` times
+/// the speed that it ran, or the latest recorded run if it's not given
+pub fn replay(id: Option<String>, speed: f64) -> Result<(), Error> {
+    if speed.is_nan() || speed <= 0.0 {
+        return Error::new("speed should be more than 0").err();
+    }
+    let path = find_recording(id.as_deref())?;
+    let file =
+        File::open(&path).map_err(|e| Error::new(format!("can't open {}: {e}", path.display())))?;
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    let runs = match lines.next().map(|line| serde_json::from_str(&line)) {
+        Some(Ok(RecordedLine::Runs(runs))) => runs,
+        _ => {
+            return Error::new(format!("{} isn't a recording of runs", path.display())).err();
+        }
+    };
+
+    let mut app = App::new();
+    app.runs = runs.iter().map(|run| run.to_panel()).collect();
+    let sink = TuiSink::new(app.tx.clone());
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        for line in lines {
+            // a recording of a run that was killed can end with a partial line
+            let Ok(RecordedLine::Event { at, event }) = serde_json::from_str(&line) else {
+                break;
+            };
+            let at = Duration::from_millis((at as f64 / speed) as u64);
+            if let Some(wait) = at.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+            sink.send(event);
+        }
+    });
+    app.start().map_err(|e| Error::new(e.to_string()))?;
+    Ok(())
+}
+
+/// The recording of the run with the id prefix, or the latest recording
+fn find_recording(id: Option<&str>) -> Result<PathBuf, Error> {
+    let dir = history_dir().ok_or_else(|| Error::new("can't find the history directory"))?;
+    let mut recordings: Vec<(PathBuf, SystemTime)> = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    name.ends_with(".events.jsonl") && id.is_none_or(|id| name.starts_with(id))
+                })
+                .map(|entry| {
+                    let modified = entry
+                        .metadata()
+                        .and_then(|m| m.modified())
+                        .unwrap_or(UNIX_EPOCH);
+                    (entry.path(), modified)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    recordings.sort_by_key(|(_, modified)| *modified);
+    match (id, recordings.as_slice()) {
+        (Some(id), []) => Error::new(format!(
+            "can't find the recording of run {id} in the history"
+        ))
+        .err(),
+        (None, []) => Error::new("there are no recorded runs in the history").err(),
+        (Some(id), [_, _, ..]) => Error::new(format!("run {id} is ambiguous")).err(),
+        (_, [.., (path, _)]) => Ok(path.clone()),
+    }
+}
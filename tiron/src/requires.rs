@@ -0,0 +1,180 @@
+use hcl::eval::Context;
+use hcl_edit::{
+    structure::{Block, Structure},
+    Span,
+};
+use tiron_common::{
+    error::{Error, Origin},
+    value::SpannedValue,
+};
+use tiron_node::action::data::all_actions;
+
+/// Check the versions of tiron and the actions that the `requires` block of a runbook
+/// needs, e.g. `requires { tiron = ">=0.3", actions = { copy = ">=0.1.7" } }`.
+/// The nodes always run the same version of tiron as here, so they don't need
+/// to be checked on their own
+pub fn check_requires(origin: &Origin, block: &Block) -> Result<(), Error> {
+    let ctx = Context::new();
+    for structure in block.body.iter() {
+        let Structure::Attribute(a) = structure else {
+            continue;
+        };
+        let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+        match a.key.as_str() {
+            "tiron" => {
+                let SpannedValue::String(req) = &v else {
+                    return origin
+                        .error("tiron should be a version requirement", v.span())
+                        .err();
+                };
+                check_version("tiron", req.value(), env!("CARGO_PKG_VERSION"))
+                    .map_err(|e| origin.error(e, v.span()).with_code("E0401"))?;
+            }
+            "actions" => {
+                let SpannedValue::Object(reqs) = &v else {
+                    return origin
+                        .error(
+                            "actions should be an object of action names and version requirements",
+                            v.span(),
+                        )
+                        .err();
+                };
+                let actions = all_actions();
+                for (name, req) in reqs.value() {
+                    let SpannedValue::String(req) = req else {
+                        return origin
+                            .error(
+                                format!("the requirement of action {name} should be a string"),
+                                req.span(),
+                            )
+                            .err();
+                    };
+                    let Some(action) = actions.get(name) else {
+                        return origin
+                            .error(
+                                format!(
+                                    "this runbook requires action {name}, which tiron doesn't have"
+                                ),
+                                req.span(),
+                            )
+                            .err();
+                    };
+                    check_version(&format!("action {name}"), req.value(), &action.version())
+                        .map_err(|e| origin.error(e, req.span()).with_code("E0401"))?;
+                }
+            }
+            _ => {
+                return origin
+                    .error(
+                        format!("requires doesn't have {}", a.key.as_str()),
+                        &a.key.span(),
+                    )
+                    .err();
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_version(what: &str, req: &str, version: &str) -> Result<(), String> {
+    if version_matches(req, version)? {
+        Ok(())
+    } else {
+        Err(format!(
+            "this runbook requires {what} {req}, but it's {version} here"
+        ))
+    }
+}
+
+/// Whether the version meets the requirement, which works like the ones of Cargo,
+/// e.g. `>=0.3, <0.5`, `^1.2` or `~1.2.3`, and `1.2` is the same as `^1.2`
+fn version_matches(req: &str, version: &str) -> Result<bool, String> {
+    // the pre-release and the build metadata don't count
+    let version = version
+        .split(['-', '+'])
+        .next()
+        .and_then(|v| parse_version(v).filter(|v| v.len() == 3))
+        .ok_or_else(|| format!("{version} isn't a valid version"))?;
+    let comparators: Vec<&str> = req.split(',').map(|c| c.trim()).collect();
+    if comparators.iter().any(|c| c.is_empty()) {
+        return Err(format!("{req} isn't a valid version requirement"));
+    }
+    for comparator in comparators {
+        let (op, v) = match comparator.find(|c: char| c.is_ascii_digit()) {
+            Some(i) => comparator.split_at(i),
+            None => return Err(format!("{req} isn't a valid version requirement")),
+        };
+        let v =
+            parse_version(v).ok_or_else(|| format!("{req} isn't a valid version requirement"))?;
+        // the partial requirements only compare the parts they have, e.g. >0.3 means >=0.4.0
+        let prefix = &version[..v.len()];
+        let padded = (0..3)
+            .map(|i| v.get(i).copied().unwrap_or(0))
+            .collect::<Vec<_>>();
+        let matches = match op.trim() {
+            "=" => prefix == v.as_slice(),
+            ">" => prefix > v.as_slice(),
+            ">=" => prefix >= v.as_slice(),
+            "<" => prefix < v.as_slice(),
+            "<=" => prefix <= v.as_slice(),
+            "~" => {
+                let same = v.len().min(2);
+                version[..same] == v[..same] && version >= padded
+            }
+            "^" | "" => {
+                // up to the first part that isn't 0 has to be the same
+                let same = v
+                    .iter()
+                    .position(|n| *n != 0)
+                    .map(|i| i + 1)
+                    .unwrap_or(v.len());
+                version[..same] == v[..same] && version >= padded
+            }
+            _ => return Err(format!("{req} isn't a valid version requirement")),
+        };
+        if !matches {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// The parts of a version, like `1.2.3`, or a partial one, like `1.2`
+fn parse_version(v: &str) -> Option<Vec<u64>> {
+    let parts = v
+        .trim()
+        .split('.')
+        .map(|n| n.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    Some(parts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_requirements() {
+        let matches = |req: &str, version: &str| version_matches(req, version).unwrap();
+        assert!(matches(">=0.1", "0.1.7"));
+        assert!(!matches(">=0.3", "0.1.7"));
+        assert!(matches(">=0.1, <0.2", "0.1.7"));
+        assert!(!matches(">0.1", "0.1.7"));
+        assert!(matches("<=0.1", "0.1.7"));
+        assert!(matches("=0.1", "0.1.7"));
+        assert!(!matches("=0.1.6", "0.1.7"));
+        assert!(matches("^1.2", "1.9.0"));
+        assert!(!matches("^1.2", "2.0.0"));
+        assert!(matches("0.1.8", "0.1.9-beta"));
+        assert!(!matches("0.2", "0.1.9"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+        assert!(version_matches(">=", "0.1.7").is_err());
+        assert!(version_matches("!=0.1", "0.1.7").is_err());
+        assert!(version_matches(">=0.1,", "0.1.7").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::ActionData,
+    crypto::{hex, sha256},
+};
+
+use crate::history::{data_dir, now};
+
+/// How many hashes are kept for a host, and the ones that succeeded the longest time
+/// ago are dropped after that, e.g. the inputs that were changed since
+const MAX_RESULTS: usize = 10_000;
+
+/// The hashes of the inputs of the actions that succeeded on a host, which the actions
+/// with the same inputs are skipped by with `--skip-unchanged`
+#[derive(Default, Serialize, Deserialize)]
+pub struct HostResults {
+    host: String,
+    // the hashes with when they last succeeded, as unix timestamps in seconds
+    actions: HashMap<String, u64>,
+}
+
+impl HostResults {
+    fn path(host: &str) -> Result<PathBuf> {
+        let dir = data_dir().ok_or_else(|| anyhow!("can't find the data directory"))?;
+        Ok(dir.join("results").join(format!("{host}.json")))
+    }
+
+    /// The results of the host, which are empty if there aren't any or they can't be read
+    pub fn load(host: &str) -> Self {
+        Self::path(host)
+            .and_then(|path| Ok(serde_json::from_slice(&std::fs::read(path)?)?))
+            .unwrap_or_else(|_| Self {
+                host: host.to_string(),
+                actions: HashMap::new(),
+            })
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        self.prune();
+        let path = Self::path(&self.host)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether an action with the input hash succeeded on the host
+    pub fn contains(&self, hash: &str) -> bool {
+        self.actions.contains_key(hash)
+    }
+
+    pub fn succeed(&mut self, hash: String) {
+        self.actions.insert(hash, now());
+    }
+
+    /// The action failed with the input, so what it left on the host isn't known anymore
+    pub fn fail(&mut self, hash: &str) {
+        self.actions.remove(hash);
+    }
+
+    fn prune(&mut self) {
+        if self.actions.len() <= MAX_RESULTS {
+            return;
+        }
+        let mut actions: Vec<(String, u64)> = self.actions.drain().collect();
+        actions.sort_by(|a, b| b.1.cmp(&a.1));
+        actions.truncate(MAX_RESULTS);
+        self.actions = actions.into_iter().collect();
+    }
+}
+
+/// The hash of the action with its input, which has the content of the local files
+/// for the actions like `copy`, so it changes when the files do
+pub fn input_hash(action: &ActionData) -> String {
+    let mut data = action.action.as_bytes().to_vec();
+    data.push(0);
+    data.extend_from_slice(&action.input);
+    hex(&sha256(&data))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prune_oldest_results() {
+        let mut results = HostResults::default();
+        for i in 0..MAX_RESULTS + 10 {
+            results.actions.insert(i.to_string(), i as u64);
+        }
+        results.fail("20");
+        results.prune();
+        assert_eq!(results.actions.len(), MAX_RESULTS);
+        assert!(!results.contains("8"));
+        assert!(results.contains("9"));
+        assert!(!results.contains("20"));
+    }
+}
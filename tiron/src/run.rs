@@ -1,19 +1,114 @@
-use anyhow::Result;
-use hcl::eval::Context;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use hcl::{eval::Context, Map, Value};
 use hcl_edit::{
-    structure::{Block, Structure},
+    structure::{Block, BlockLabel, Structure},
     Span,
 };
-use tiron_common::{error::Error, value::SpannedValue};
-use tiron_tui::run::{ActionSection, HostSection, RunPanel};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tiron_common::{
+    action::ActionOutputLevel,
+    error::Error,
+    event::{Event, EventSink, RunEvent},
+    value::SpannedValue,
+};
+use tiron_tui::{
+    event::TuiDebugger,
+    run::{ActionSection, HostSection, RunPanel},
+};
 use uuid::Uuid;
 
-use crate::{node::Node, runbook::Runbook};
+use crate::{
+    container::{Container, ContainerHost},
+    filters::{declare_filters, declare_functions},
+    forward::PortForward,
+    history::now,
+    hook::{start_env, summary_env, Hooks},
+    lock::{current_holder, lock_hosts},
+    log,
+    node::{ActionOutcome, Node, NodeExit, PendingChange, SharedFacts, Throttle},
+    runbook::Runbook,
+    window::Window,
+};
+
+/// The outcome of executing a run
+#[derive(Serialize, Deserialize)]
+pub struct RunResult {
+    pub name: Option<String>,
+    // the runbook the run is in
+    #[serde(default)]
+    pub runbook: PathBuf,
+    pub success: bool,
+    // the labels of the run, e.g. the ticket of the change
+    #[serde(default)]
+    pub labels: Map<String, String>,
+    pub hosts: Vec<HostResult>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HostResult {
+    pub host: String,
+    pub success: bool,
+    // the host didn't meet the `when` condition of the run
+    #[serde(default)]
+    pub skipped: bool,
+    // the changes the actions would make in a dry run
+    pub pending: Vec<PendingChange>,
+    // the output values of the run evaluated for the host
+    #[serde(default)]
+    pub outputs: Map<String, Value>,
+    // the outcomes of the actions, including the retries
+    #[serde(default)]
+    pub actions: Vec<ActionOutcome>,
+    // how long the host took in milliseconds, including the retries
+    #[serde(default)]
+    pub duration: u64,
+    // the environment tag of the host, e.g. prod
+    #[serde(default)]
+    pub environment: Option<String>,
+    // the index of the action the host can resume from, which counts the actions
+    // that completed or were skipped before the first failure
+    #[serde(default)]
+    pub resume_from: usize,
+}
 
 pub struct Run {
     pub id: Uuid,
     name: Option<String>,
+    // the path of the runbook the run is in
+    runbook: PathBuf,
     hosts: Vec<Node>,
+    // how many times the failed hosts are retried after the first pass
+    retry_failed_hosts: usize,
+    // take over the locks of the hosts if someone else holds them
+    pub force_unlock: bool,
+    // skip the actions whose inputs are the same as the last time they succeeded on the host
+    pub skip_unchanged: bool,
+    // the maintenance windows the run can only execute in
+    windows: Vec<Window>,
+    // execute the run even if it's outside of its maintenance windows
+    pub override_window: bool,
+    // the pending changes of the run need to be approved before it executes
+    pub requires_approval: bool,
+    // the pending changes were approved in the terminal or with an approval token
+    pub approved: bool,
+    // the hosts from the protected groups were confirmed, or --yes-i-mean-prod was given
+    pub protected_confirmed: bool,
+    // asks what to do with the failed actions if the strategy of the run is debug
+    pub debugger: Option<TuiDebugger>,
+    // the local ports forwarded through the hosts while the run executes
+    port_forwards: Vec<PortForward>,
+    // the labels of the run, e.g. `ticket = "OPS-123"`, which go into the run history
+    // and the logs, so that the changes can be traced to the tickets and the owners
+    labels: Map<String, String>,
+    // the hooks before and after the run
+    hooks: Hooks,
 }
 
 impl Run {
@@ -35,20 +130,221 @@ pub fn from_block(runbook: &Runbook, block: &Block, hosts: Vec<Node>) -> Result<
             None
         };
 
+        let retry_failed_hosts = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "retry_failed_hosts")
+                .map(|a| &a.value)
+        });
+        let retry_failed_hosts = if let Some(retry_failed_hosts) = retry_failed_hosts {
+            let v = SpannedValue::from_expression(
+                &runbook.origin,
+                &Context::new(),
+                retry_failed_hosts.to_owned(),
+            )?;
+            let SpannedValue::Number(n) = &v else {
+                return runbook
+                    .origin
+                    .error("retry_failed_hosts should be a number", v.span())
+                    .err();
+            };
+            n.value().as_u64().ok_or_else(|| {
+                runbook.origin.error(
+                    "retry_failed_hosts should be a non negative integer",
+                    v.span(),
+                )
+            })? as usize
+        } else {
+            0
+        };
+
+        let rollback_on_failure = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "rollback_on_failure")
+                .map(|a| &a.value)
+        });
+        let rollback_on_failure = if let Some(rollback_on_failure) = rollback_on_failure {
+            let v = SpannedValue::from_expression(
+                &runbook.origin,
+                &Context::new(),
+                rollback_on_failure.to_owned(),
+            )?;
+            let SpannedValue::Bool(b) = &v else {
+                return runbook
+                    .origin
+                    .error("rollback_on_failure should be a bool", v.span())
+                    .err();
+            };
+            *b.value()
+        } else {
+            false
+        };
+
+        let requires_approval = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "requires_approval")
+                .map(|a| &a.value)
+        });
+        let requires_approval = if let Some(requires_approval) = requires_approval {
+            let v = SpannedValue::from_expression(
+                &runbook.origin,
+                &Context::new(),
+                requires_approval.to_owned(),
+            )?;
+            let SpannedValue::Bool(b) = &v else {
+                return runbook
+                    .origin
+                    .error("requires_approval should be a bool", v.span())
+                    .err();
+            };
+            *b.value()
+        } else {
+            false
+        };
+
+        let strategy = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "strategy")
+                .map(|a| &a.value)
+        });
+        let debug = if let Some(strategy) = strategy {
+            let v = SpannedValue::from_expression(
+                &runbook.origin,
+                &Context::new(),
+                strategy.to_owned(),
+            )?;
+            match &v {
+                SpannedValue::String(s) if s.value() == "linear" => false,
+                SpannedValue::String(s) if s.value() == "debug" => true,
+                _ => {
+                    return runbook
+                        .origin
+                        .error("strategy should be \"linear\" or \"debug\"", v.span())
+                        .err();
+                }
+            }
+        } else {
+            false
+        };
+
+        let labels = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "labels")
+                .map(|a| &a.value)
+        });
+        let labels = if let Some(labels) = labels {
+            let v =
+                SpannedValue::from_expression(&runbook.origin, &Context::new(), labels.to_owned())?;
+            let SpannedValue::Object(o) = &v else {
+                return runbook
+                    .origin
+                    .error("labels should be an object", v.span())
+                    .err();
+            };
+            let mut labels = Map::new();
+            for (key, value) in o.value() {
+                let SpannedValue::String(s) = value else {
+                    return runbook
+                        .origin
+                        .error(format!("label {key} should be a string"), value.span())
+                        .err();
+                };
+                labels.insert(key.clone(), s.value().to_string());
+            }
+            labels
+        } else {
+            Map::new()
+        };
+
+        let mut windows = Vec::new();
+        let mut outputs = Vec::new();
+        let mut hooks = Hooks::default();
+        for s in block.body.iter() {
+            if let Some(b) = s.as_block() {
+                match b.ident.as_str() {
+                    "window" => windows.push(Window::from_block(&runbook.origin, b)?),
+                    "output" => outputs.push(parse_output(runbook, b)?),
+                    _ => hooks.add_block(&runbook.origin, b)?,
+                }
+            }
+        }
+
         let mut run = Run {
             id: Uuid::new_v4(),
             name,
+            runbook: runbook.origin.path.clone(),
             hosts,
+            retry_failed_hosts,
+            force_unlock: false,
+            skip_unchanged: false,
+            windows,
+            // a dry run doesn't change anything, so it can run any time
+            override_window: runbook.check_mode,
+            requires_approval,
+            // a dry run doesn't change anything, so there's nothing to approve
+            approved: runbook.check_mode,
+            protected_confirmed: runbook.check_mode,
+            debugger: None,
+            port_forwards: Vec::new(),
+            labels,
+            // a dry run doesn't change anything, so there's nothing to announce
+            hooks: if runbook.check_mode {
+                Hooks::default()
+            } else {
+                hooks
+            },
         };
 
+        // the vars of all the hosts in the run, which get the facts of the hosts
+        // when they are connected
+        let hostvars = Value::Object(
+            run.hosts
+                .iter()
+                .map(|host| {
+                    let mut vars = Map::new();
+                    vars.insert(
+                        "vars".to_string(),
+                        Value::Object(
+                            host.vars
+                                .iter()
+                                .map(|(name, var)| (name.clone(), var.clone()))
+                                .collect(),
+                        ),
+                    );
+                    (host.host.clone(), Value::Object(vars))
+                })
+                .collect(),
+        );
+
+        // the hosts share the throttle of the same action
+        let mut throttles: HashMap<String, Arc<Throttle>> = HashMap::new();
         for host in run.hosts.iter_mut() {
             let mut ctx = Context::new();
             for (name, var) in &host.vars {
                 ctx.declare_var(name.to_string(), var.to_owned());
             }
+            host.metadata = run_metadata(&run.id, &run.name, &run.labels, host, runbook.check_mode);
+            host.outputs = outputs.clone();
+            ctx.declare_var("tiron", host.metadata.clone());
+            host.hostvars = hostvars.clone();
+            ctx.declare_var("hostvars", hostvars.clone());
+            ctx.declare_var("path", runbook.path_var(false));
+            declare_functions(&mut ctx);
+            if runbook.ansible_filters {
+                declare_filters(&mut ctx);
+                host.ansible_filters = true;
+            }
 
             for s in block.body.iter() {
                 if let Structure::Attribute(a) = s {
+                    if a.key.as_str() == "when" {
+                        // it can use the facts, so it's only evaluated when the host is connected
+                        host.add_when(a.value.to_owned().into());
+                        continue;
+                    }
+                    if a.key.as_str() == "hosts" {
+                        // it's evaluated without the host when the hosts of the run are resolved
+                        continue;
+                    }
                     let v =
                         SpannedValue::from_expression(&runbook.origin, &ctx, a.value.to_owned())?;
                     match a.key.as_str() {
@@ -88,34 +384,464 @@ pub fn from_block(runbook: &Runbook, block: &Block, hosts: Vec<Node>) -> Result<
                 e
             })?;
             host.actions = actions;
+            for action in host.actions.iter_mut() {
+                action.dry_run = runbook.check_mode;
+                action.debug = debug;
+                if !rollback_on_failure {
+                    action.rollback = None;
+                }
+                if let Some(throttle) = &action.throttle {
+                    let throttle = throttles
+                        .entry(throttle.key.clone())
+                        .or_insert_with(|| Arc::new(Throttle::new(throttle.limit)));
+                    host.throttles.insert(action.id, throttle.clone());
+                }
+            }
+
+            // the snapshots are taken before the actions, and restored
+            // whether the run rolls back on failure or not
+            let mut snapshots = Vec::new();
+            for s in block.body.iter() {
+                if let Some(b) = s.as_block() {
+                    if b.ident.as_str() == "snapshot" {
+                        let mut snapshot = runbook.parse_snapshot(&ctx, b)?;
+                        snapshot.dry_run = runbook.check_mode;
+                        snapshots.push(snapshot);
+                    }
+                }
+            }
+            host.actions.splice(0..0, snapshots);
+        }
+
+        // the forwards go through the hosts with the remote_user of the run
+        for s in block.body.iter() {
+            if let Some(b) = s.as_block() {
+                if b.ident.as_str() == "port_forward" {
+                    let forward = PortForward::from_block(runbook, b, &run.hosts)?;
+                    run.port_forwards.push(forward);
+                }
+            }
         }
 
         Ok(run)
     }
 
-    pub fn execute(&self) -> Result<bool> {
+    /// Execute the run, and send the events of it to `events`
+    pub fn execute(&self, events: &Arc<dyn EventSink>) -> Result<RunResult> {
+        self.check_approved()?;
+        self.check_protected()?;
+        let started = now();
+        let result = self.execute_locked(events)?;
+        if !self.hooks.post_run.is_empty() {
+            let env = summary_env(
+                self.hook_env(),
+                std::slice::from_ref(&result),
+                now().saturating_sub(started),
+            );
+            self.hooks.post_run(&env);
+        }
+        Ok(result)
+    }
+
+    /// The env vars of the hooks of the run, with the name of the run
+    fn hook_env(&self) -> Vec<(String, String)> {
+        let mut env = start_env(
+            &self.id.to_string(),
+            &[self.runbook.to_string_lossy().to_string()],
+            &self
+                .hosts
+                .iter()
+                .map(|host| host.host.as_str())
+                .collect::<Vec<_>>(),
+            &self.labels,
+        );
+        env.push((
+            "TIRON_RUN_NAME".to_string(),
+            self.name.clone().unwrap_or_default(),
+        ));
+        env
+    }
+
+    /// Execute the run with the hosts locked
+    fn execute_locked(&self, events: &Arc<dyn EventSink>) -> Result<RunResult> {
+        let mut result = RunResult {
+            name: self.name.clone(),
+            runbook: self.runbook.clone(),
+            success: false,
+            labels: self.labels.clone(),
+            hosts: Vec::new(),
+        };
+        // the hosts are locked both here and on the hosts, so that the runs
+        // against the same host don't run at the same time
+        let holder = current_holder();
+        let _locks = lock_hosts(
+            self.hosts.iter().map(|host| host.host.as_str()),
+            &holder,
+            self.force_unlock,
+        )?;
+        // the forwards are torn down when they're dropped at the end of the run
+        let _forwards = self
+            .port_forwards
+            .iter()
+            .map(|forward| forward.start())
+            .collect::<Result<Vec<_>>>()?;
+        // the hooks can reach the hosts through the forwards too
+        self.hooks.pre_run(&self.hook_env())?;
+        // the hosts only wait for each other to share the facts if they use them
+        let shared_facts = self
+            .hosts
+            .iter()
+            .any(|host| host.uses_hostvars())
+            .then(|| Arc::new(SharedFacts::new()));
+        let mut hosts = self.hosts.clone();
+        for host in hosts.iter_mut() {
+            host.lock = Some(holder.clone());
+            host.force_unlock = self.force_unlock;
+            host.skip_unchanged = self.skip_unchanged;
+            host.shared_facts = shared_facts.clone();
+            host.debugger = self.debugger.clone();
+        }
+        log::info(
+            "scheduler",
+            "start the run",
+            &[
+                ("run", &self.id),
+                ("name", &self.name.as_deref().unwrap_or_default()),
+                ("hosts", &hosts.len()),
+                ("labels", &format_labels(&self.labels)),
+            ],
+        );
+        // the hosts are restarted from the start of them, e.g. with the includes
+        // that use the facts resolved again
+        let prepared = hosts.clone();
+        let mut attempt = 0;
+        loop {
+            if let Some(shared_facts) = &shared_facts {
+                shared_facts.expect(hosts.len());
+            }
+            let failed = self.execute_hosts(&hosts, events, &mut result.hosts);
+            if failed.is_empty() {
+                result.success = true;
+                return Ok(result);
+            }
+            if attempt >= self.retry_failed_hosts {
+                hosts = self.restart_hosts(&failed, &prepared, &mut result.hosts);
+                if hosts.is_empty() {
+                    return Ok(result);
+                }
+                log::info(
+                    "scheduler",
+                    "restart the failed hosts",
+                    &[
+                        ("run", &self.id),
+                        (
+                            "hosts",
+                            &hosts.iter().map(|host| host.host.as_str()).join(","),
+                        ),
+                    ],
+                );
+                continue;
+            }
+
+            attempt += 1;
+            log::info(
+                "scheduler",
+                "retry the failed hosts",
+                &[
+                    ("run", &self.id),
+                    ("attempt", &attempt),
+                    (
+                        "hosts",
+                        &failed.iter().map(|host| host.host.as_str()).join(","),
+                    ),
+                ],
+            );
+            for host in &failed {
+                events.send(Event::Run(RunEvent::HostRetry {
+                    run: self.id,
+                    host: host.id,
+                    attempt,
+                }));
+            }
+            hosts = failed;
+        }
+    }
+
+    /// Execute the hosts in parallel, and returns the failed hosts
+    /// with only the actions that haven't completed yet
+    fn execute_hosts(
+        &self,
+        hosts: &[Node],
+        events: &Arc<dyn EventSink>,
+        results: &mut Vec<HostResult>,
+    ) -> Vec<Node> {
         let mut receivers = Vec::new();
 
-        for host in &self.hosts {
-            let (exit_tx, exit_rx) = crossbeam_channel::bounded::<bool>(1);
-            let host = host.clone();
+        for host in hosts {
+            let (exit_tx, exit_rx) = crossbeam_channel::bounded::<NodeExit>(1);
+            let node = host.clone();
             let run_id = self.id;
+            let events = events.clone();
+            log::debug(
+                "scheduler",
+                "start the host",
+                &[
+                    ("run", &run_id),
+                    ("host", &node.host),
+                    ("actions", &node.actions.len()),
+                ],
+            );
             std::thread::spawn(move || {
-                let _ = host.execute(run_id, exit_tx);
+                let _ = node.execute(run_id, events, exit_tx);
             });
 
-            receivers.push(exit_rx)
+            receivers.push((host, exit_rx))
+        }
+
+        let mut failed = Vec::new();
+        for (host, rx) in receivers {
+            let exit = rx.recv().unwrap_or(NodeExit {
+                success: false,
+                completed: 0,
+                pending: Vec::new(),
+                skipped: false,
+                outputs: Vec::new(),
+                actions: Vec::new(),
+                duration: 0,
+                resolved: None,
+            });
+            log::debug(
+                "scheduler",
+                "the host finished",
+                &[
+                    ("run", &self.id),
+                    ("host", &host.host),
+                    ("success", &exit.success),
+                    ("completed", &exit.completed),
+                ],
+            );
+
+            match results.iter_mut().find(|r| r.host == host.host) {
+                Some(result) => {
+                    result.success = exit.success;
+                    result.resume_from += exit.completed;
+                    result.skipped = exit.skipped;
+                    result.pending.extend(exit.pending);
+                    result.outputs = exit.outputs.into_iter().collect();
+                    result.actions.extend(exit.actions);
+                    result.duration += exit.duration;
+                }
+                None => results.push(HostResult {
+                    host: host.host.clone(),
+                    success: exit.success,
+                    skipped: exit.skipped,
+                    pending: exit.pending,
+                    outputs: exit.outputs.into_iter().collect(),
+                    actions: exit.actions,
+                    duration: exit.duration,
+                    environment: host.tag("environment"),
+                    resume_from: host.resume_from + exit.completed,
+                }),
+            }
+
+            if !exit.success {
+                let mut host = host.clone();
+                let completed = match exit.resolved {
+                    Some(actions) => {
+                        host.actions = actions;
+                        exit.completed
+                    }
+                    None => host.resume_from + exit.completed,
+                };
+                host.actions = host.actions.split_off(completed.min(host.actions.len()));
+                host.resume_from = 0;
+                failed.push(host);
+            }
+        }
+
+        failed
+    }
+
+    /// Ask in the TUI whether to restart the failed hosts from one of their actions,
+    /// and returns the hosts that are restarted
+    fn restart_hosts(
+        &self,
+        failed: &[Node],
+        prepared: &[Node],
+        results: &mut [HostResult],
+    ) -> Vec<Node> {
+        let Some(debugger) = &self.debugger else {
+            return Vec::new();
+        };
+        // a dry run doesn't change anything, so it can just be run again
+        if failed
+            .iter()
+            .all(|host| host.actions.iter().all(|action| action.dry_run))
+        {
+            return Vec::new();
+        }
+        // the hosts are all asked at once, so that they can be answered in any order
+        let replies = failed
+            .iter()
+            .filter_map(|host| {
+                let result = results.iter().find(|r| r.host == host.host)?;
+                Some((
+                    host.id,
+                    debugger.ask_restart(self.id, host.id, result.resume_from),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let mut hosts = Vec::new();
+        for (id, reply) in replies {
+            // the host isn't restarted if the TUI is gone
+            let Ok(from) = reply.recv() else {
+                continue;
+            };
+            let Some(host) = prepared.iter().find(|host| host.id == id) else {
+                continue;
+            };
+            if let Some(result) = results.iter_mut().find(|r| r.host == host.host) {
+                result.resume_from = from;
+            }
+            hosts.push(Node {
+                resume_from: from,
+                ..host.clone()
+            });
+        }
+        hosts
+    }
+
+    /// Resume the hosts from where they failed in the previous execution of the run
+    pub fn resume(&mut self, previous: &RunResult) {
+        for host in self.hosts.iter_mut() {
+            if let Some(result) = previous.hosts.iter().find(|r| r.host == host.host) {
+                host.resume_from = result.resume_from;
+            }
         }
+    }
 
-        let mut errors = 0;
-        for rx in &receivers {
-            let result = rx.recv();
-            if result != Ok(true) {
-                errors += 1;
+    /// Check that it's in one of the maintenance windows of the run, if it has any
+    pub fn check_window(&self) -> Result<()> {
+        if self.override_window || self.windows.is_empty() {
+            return Ok(());
+        }
+        let now = now();
+        if self.windows.iter().any(|window| window.is_open(now)) {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "{} is outside of its maintenance window ({}), use --override-window to run it anyway",
+            self.name.as_deref().unwrap_or("run"),
+            self.windows
+                .iter()
+                .map(|window| window.description())
+                .join(" or ")
+        ))
+    }
+
+    /// Check that the run is approved if it requires approval. Only `tiron run` can
+    /// approve the runs, so they are refused everywhere else, e.g. in the schedules
+    pub fn check_approved(&self) -> Result<()> {
+        if !self.requires_approval || self.approved {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "{} requires approval, run it with `tiron run` and an approval token from `tiron approve`",
+            self.name.as_deref().unwrap_or("run")
+        ))
+    }
+
+    /// Check that the hosts from the protected groups were confirmed. Only `tiron run`
+    /// and `tiron exec` can confirm them, so they are refused everywhere else
+    pub fn check_protected(&self) -> Result<()> {
+        let hosts = self.protected_hosts();
+        if hosts.is_empty() || self.protected_confirmed {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "{} targets {} hosts in protected groups, run it with `tiron run` to confirm them or use --yes-i-mean-prod",
+            self.name.as_deref().unwrap_or("run"),
+            hosts.len()
+        ))
+    }
+
+    /// The hosts of the run that are from protected groups, with their groups
+    pub fn protected_hosts(&self) -> Vec<(&str, &str)> {
+        self.hosts
+            .iter()
+            .filter_map(|host| Some((host.protected.as_deref()?, host.host.as_str())))
+            .collect()
+    }
+
+    /// A copy of the run that only checks what it would change
+    pub fn dry_run(&self) -> Run {
+        let mut hosts = self.hosts.clone();
+        for host in hosts.iter_mut() {
+            for action in host.actions.iter_mut() {
+                action.dry_run = true;
             }
         }
+        Run {
+            id: Uuid::new_v4(),
+            name: self.name.clone(),
+            runbook: self.runbook.clone(),
+            hosts,
+            retry_failed_hosts: 0,
+            force_unlock: self.force_unlock,
+            skip_unchanged: false,
+            windows: Vec::new(),
+            override_window: true,
+            requires_approval: false,
+            approved: true,
+            protected_confirmed: true,
+            debugger: None,
+            // the dry run only checks the changes, which doesn't need the forwards
+            port_forwards: Vec::new(),
+            labels: self.labels.clone(),
+            hooks: Hooks::default(),
+        }
+    }
 
-        Ok(errors == 0)
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn runbook(&self) -> &Path {
+        &self.runbook
+    }
+
+    pub fn hosts(&self) -> &[Node] {
+        &self.hosts
+    }
+
+    pub fn labels(&self) -> &Map<String, String> {
+        &self.labels
+    }
+
+    /// Run every host in a disposable container of the image instead.
+    /// The containers are shared by the hosts with the same name across runs.
+    pub fn use_containers(
+        &mut self,
+        engine: &str,
+        image: &str,
+        containers: &mut HashMap<String, Container>,
+    ) -> Result<()> {
+        for host in self.hosts.iter_mut() {
+            let container = match containers.get(&host.host) {
+                Some(container) => container,
+                None => {
+                    let container = ContainerHost::start(engine, image)?;
+                    containers.entry(host.host.clone()).or_insert(container)
+                }
+            };
+            host.container = Some(container.host.clone());
+        }
+        Ok(())
+    }
+
+    /// Whether any host in the run uses `become` to run the actions as root
+    pub fn uses_become(&self) -> bool {
+        self.hosts.iter().any(|host| host.become_)
     }
 
     pub fn to_panel(&self) -> RunPanel {
@@ -128,11 +854,89 @@ pub fn to_panel(&self) -> RunPanel {
                     host.host.clone(),
                     host.actions
                         .iter()
-                        .map(|action| ActionSection::new(action.id, action.name.clone()))
+                        .enumerate()
+                        .map(|(i, action)| {
+                            let mut section = ActionSection::new(action.id, action.name.clone());
+                            if i < host.resume_from {
+                                section.output_line(
+                                    "completed before the run was resumed".to_string(),
+                                    ActionOutputLevel::Info,
+                                );
+                                section.success(true);
+                            }
+                            section
+                        })
                         .collect(),
                 )
+                .with_tags(host.tag("environment"), host.tag("color").as_deref())
             })
             .collect();
         RunPanel::new(self.id, self.name.clone(), hosts)
     }
 }
+
+/// Parse an `output "name" { value = ... }` block, whose value can use the facts,
+/// so it's only evaluated when the host is connected
+fn parse_output(runbook: &Runbook, block: &Block) -> Result<(String, hcl::Expression), Error> {
+    let name = match block.labels.first() {
+        Some(BlockLabel::String(name)) => name.as_str().to_string(),
+        _ => {
+            return runbook
+                .origin
+                .error("output should have a name", &block.ident.span())
+                .err();
+        }
+    };
+    let value = block.body.iter().find_map(|s| {
+        s.as_attribute()
+            .filter(|a| a.key.as_str() == "value")
+            .map(|a| a.value.to_owned().into())
+    });
+    let Some(value) = value else {
+        return runbook
+            .origin
+            .error("output should have a value", &block.ident.span())
+            .err();
+    };
+    Ok((name, value))
+}
+
+/// The built-in `tiron` variable with the metadata of the run for the host
+fn run_metadata(
+    run_id: &Uuid,
+    run_name: &Option<String>,
+    labels: &Map<String, String>,
+    host: &Node,
+    check_mode: bool,
+) -> Value {
+    let mut tiron = Map::new();
+    tiron.insert("run_id".to_string(), Value::String(run_id.to_string()));
+    tiron.insert(
+        "run_name".to_string(),
+        run_name.clone().map(Value::String).unwrap_or(Value::Null),
+    );
+    tiron.insert(
+        "labels".to_string(),
+        Value::Object(
+            labels
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+                .collect(),
+        ),
+    );
+    tiron.insert("host".to_string(), Value::String(host.host.clone()));
+    tiron.insert(
+        "group_names".to_string(),
+        Value::Array(host.groups.iter().cloned().map(Value::String).collect()),
+    );
+    tiron.insert("check_mode".to_string(), Value::Bool(check_mode));
+    Value::Object(tiron)
+}
+
+/// The labels in the form of `key=value`, separated by commas, e.g. for the logs
+pub fn format_labels(labels: &Map<String, String>) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .join(",")
+}
@@ -1,28 +1,46 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Result};
-use crossbeam_channel::Sender;
 use hcl::eval::{Context, Evaluate};
 use hcl_edit::{
-    structure::{Block, BlockLabel, Structure},
-    Span,
+    expr::Expression,
+    structure::{Attribute, Block, BlockLabel, Structure},
+    visit::Visit,
+    Decorated, Ident, Span,
 };
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use tiron_common::{
-    action::{ActionData, ActionId},
+    action::{
+        ActionData, ActionId, ActionRollback, ActionScope, ActionThrottle, BlockSection, Rollback,
+    },
     error::{Error, Origin},
-    value::SpannedValue,
+    value::{Spanned, SpannedValue},
 };
-use tiron_node::action::data::all_actions;
-use tiron_tui::event::AppEvent;
+use tiron_node::action::{data::all_actions, Action, ActionDoc};
+use tiron_tui::run::parse_color;
 use uuid::Uuid;
 
 use crate::{
-    group::{GroupConfig, HostOrGroup, HostOrGroupConfig},
+    filters::{declare_filters, declare_functions},
+    group::{GroupConfig, HostOrGroup, HostOrGroupConfig, HostSpec},
+    inventory::load_inventory,
     job::Job,
     node::Node,
+    requires::check_requires,
     run::Run,
+    vault::{decrypt, is_encrypted},
 };
 
+/// How deep the files of `include_actions` can include other files
+const MAX_INCLUDE_LEVEL: usize = 16;
+/// The action that stands for an `include_actions` until the host is connected
+const INCLUDE_ACTIONS: &str = "include_actions";
+
 pub struct Runbook {
     groups: HashMap<String, GroupConfig>,
     pub jobs: HashMap<String, Job>,
@@ -31,37 +49,58 @@ pub struct Runbook {
     pub runs: Vec<Run>,
     // the origin data of the runbook
     pub origin: Origin,
-    tx: Sender<AppEvent>,
     // the imported level of the runbook, this is to detect circular imports
     level: usize,
+    // the directory of the runbook being run, which its imported runbooks share
+    project: PathBuf,
+    // whether the runbook is only checked and not executed
+    pub check_mode: bool,
+    // the functions that work like the Ansible filters are available in the runs
+    pub ansible_filters: bool,
 }
 
 impl Runbook {
-    pub fn new(path: PathBuf, tx: Sender<AppEvent>, level: usize) -> Result<Self, Error> {
-        let cwd = path.parent().ok_or_else(|| {
-            Error::new(format!("can't find parent for {}", path.to_string_lossy()))
-        })?;
-
+    pub fn new(path: PathBuf, level: usize) -> Result<Self, Error> {
         let data = std::fs::read_to_string(&path).map_err(|e| {
             Error::new(format!(
                 "can't read runbook {} error: {e}",
                 path.to_string_lossy()
             ))
         })?;
+        let data = if is_encrypted(&data) {
+            decrypt(&data).map_err(|e| {
+                Error::new(format!(
+                    "can't decrypt runbook {}: {}",
+                    path.to_string_lossy(),
+                    e.message
+                ))
+            })?
+        } else {
+            data
+        };
+        Self::from_data(path, data, level)
+    }
 
+    /// The runbook of the path with the data, which doesn't have to be on the disk
+    pub fn from_data(path: PathBuf, data: String, level: usize) -> Result<Self, Error> {
+        let cwd = path.parent().ok_or_else(|| {
+            Error::new(format!("can't find parent for {}", path.to_string_lossy()))
+        })?;
         let origin = Origin {
             cwd: cwd.to_path_buf(),
             path,
             data,
         };
         let runbook = Self {
+            project: origin.cwd.clone(),
             origin,
             groups: HashMap::new(),
             jobs: HashMap::new(),
             imports: HashMap::new(),
             runs: Vec::new(),
-            tx,
             level,
+            check_mode: false,
+            ansible_filters: false,
         };
 
         Ok(runbook)
@@ -71,6 +110,36 @@ pub fn parse(&mut self, parse_run: bool) -> Result<(), Error> {
         let body = hcl_edit::parser::parse_body(&self.origin.data)
             .map_err(|e| Error::from_hcl(e, self.origin.path.clone()))?;
 
+        let ansible_filters = body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "ansible_filters")
+                .map(|a| &a.value)
+        });
+        if let Some(ansible_filters) = ansible_filters {
+            let v = SpannedValue::from_expression(
+                &self.origin,
+                &Context::new(),
+                ansible_filters.to_owned(),
+            )?;
+            let SpannedValue::Bool(b) = &v else {
+                return self
+                    .origin
+                    .error("ansible_filters should be a bool", v.span())
+                    .err();
+            };
+            self.ansible_filters = *b.value();
+        }
+
+        // check the versions first, so that an old tiron fails with them
+        // instead of the things in the runbook it doesn't know
+        for structure in body.iter() {
+            if let Structure::Block(block) = structure {
+                if block.ident.as_str() == "requires" {
+                    check_requires(&self.origin, block)?;
+                }
+            }
+        }
+
         for structure in body.iter() {
             if let Structure::Block(block) = structure {
                 match block.ident.as_str() {
@@ -80,6 +149,9 @@ pub fn parse(&mut self, parse_run: bool) -> Result<(), Error> {
                     "group" => {
                         self.parse_group(block)?;
                     }
+                    "inventory" => {
+                        self.parse_inventory(block)?;
+                    }
                     "job" => {
                         self.parse_job(block)?;
                     }
@@ -97,48 +169,84 @@ pub fn parse(&mut self, parse_run: bool) -> Result<(), Error> {
         Ok(())
     }
 
+    /// Parse the run block from `tiron exec` or `tiron shell` as if it's at the end
+    /// of the runbook, so that it can use the groups and the inventory of the runbook
+    pub fn parse_adhoc_run(&mut self, run: &str) -> Result<(), Error> {
+        self.parse(false)?;
+        self.origin.data.push('\n');
+        self.origin.data.push_str(run);
+        let body = hcl_edit::parser::parse_body(&self.origin.data)
+            .map_err(|e| Error::from_hcl(e, self.origin.path.clone()))?;
+        let Some(Structure::Block(block)) = body.iter().last() else {
+            return Error::new("can't find the ad-hoc run").err();
+        };
+        self.parse_run(block)
+    }
+
     fn parse_run(&mut self, block: &Block) -> Result<(), Error> {
-        let mut hosts: Vec<Node> = Vec::new();
-        if block.labels.is_empty() {
-            return self
-                .origin
-                .error("You need put group name after run", &block.ident.span())
-                .err();
-        }
-        if block.labels.len() > 1 {
-            return self
-                .origin
-                .error(
-                    "You can only have one group name to run",
-                    &block.labels[1].span(),
-                )
-                .err();
-        }
-        let BlockLabel::String(name) = &block.labels[0] else {
-            return self
-                .origin
-                .error("group name should be a string", &block.labels[0].span())
-                .err();
+        let hosts_attr = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "hosts")
+                .map(|a| &a.value)
+        });
+        let names = match (block.labels.first(), hosts_attr) {
+            (None, None) => {
+                return self
+                    .origin
+                    .error(
+                        "You need put group name after run, or hosts in it",
+                        &block.ident.span(),
+                    )
+                    .err();
+            }
+            (Some(_), Some(hosts)) => {
+                return self
+                    .origin
+                    .error(
+                        "run can have either a group name or hosts, but not both",
+                        &hosts.span(),
+                    )
+                    .err();
+            }
+            (Some(label), None) => {
+                if block.labels.len() > 1 {
+                    return self
+                        .origin
+                        .error(
+                            "You can only have one group name to run",
+                            &block.labels[1].span(),
+                        )
+                        .err();
+                }
+                let BlockLabel::String(name) = label else {
+                    return self
+                        .origin
+                        .error("group name should be a string", &label.span())
+                        .err();
+                };
+                vec![(name.to_string(), label.span())]
+            }
+            (None, Some(hosts)) => self.run_hosts(hosts)?,
         };
-        for node in self
-            .hosts_from_name(name.as_str())
-            .map_err(|e| self.origin.error(e.to_string(), &block.labels[0].span()))?
-        {
-            if !hosts.iter().any(|n| n.host == node.host) {
-                hosts.push(node);
+
+        let mut hosts: Vec<Node> = Vec::new();
+        for (name, span) in names {
+            for node in self.hosts_from_target(&name, &span)? {
+                match hosts.iter_mut().find(|n| n.host == node.host) {
+                    Some(host) => {
+                        if let Some(merged) = host.merge(node) {
+                            Error::new(merged)
+                                .with_origin(&self.origin, &span)
+                                .report_warning_stderr();
+                        }
+                    }
+                    None => hosts.push(node),
+                }
             }
         }
 
         let hosts = if hosts.is_empty() {
-            vec![Node {
-                id: Uuid::new_v4(),
-                host: "localhost".to_string(),
-                vars: HashMap::new(),
-                remote_user: None,
-                become_: false,
-                actions: Vec::new(),
-                tx: self.tx.clone(),
-            }]
+            vec![Node::new("localhost".to_string(), HashMap::new())]
         } else {
             hosts
         };
@@ -147,6 +255,52 @@ fn parse_run(&mut self, block: &Block) -> Result<(), Error> {
         Ok(())
     }
 
+    /// The names of the groups or the hosts from the `hosts` of the run, which can be
+    /// an expression with the environment variables in `env`,
+    /// e.g. `concat(split(",", env.WEB_GROUPS), ["canary01"])`
+    fn run_hosts(&self, hosts: &Expression) -> Result<Vec<(String, Option<Range<usize>>)>, Error> {
+        let mut ctx = Context::new();
+        let env: hcl::Map<String, hcl::Value> = std::env::vars()
+            .map(|(k, v)| (k, hcl::Value::from(v)))
+            .collect();
+        ctx.declare_var("env", hcl::Value::Object(env));
+        ctx.declare_var("path", self.path_var(false));
+        declare_functions(&mut ctx);
+        if self.ansible_filters {
+            declare_filters(&mut ctx);
+        }
+        let v = SpannedValue::from_expression(&self.origin, &ctx, hosts.to_owned())?;
+        let names = match &v {
+            SpannedValue::String(name) => vec![(name.value().to_string(), v.span().clone())],
+            SpannedValue::Array(values) => values
+                .value()
+                .iter()
+                .map(|value| match value {
+                    SpannedValue::String(name) => {
+                        Ok((name.value().to_string(), value.span().clone()))
+                    }
+                    _ => self
+                        .origin
+                        .error("hosts should only have group or host names", value.span())
+                        .err(),
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+            _ => {
+                return self
+                    .origin
+                    .error(
+                        "hosts should be a group or host name, or a list of them",
+                        v.span(),
+                    )
+                    .err();
+            }
+        };
+        if names.is_empty() {
+            return self.origin.error("hosts can't be empty", v.span()).err();
+        }
+        Ok(names)
+    }
+
     fn parse_group(&mut self, block: &Block) -> Result<(), Error> {
         if block.labels.is_empty() {
             return self
@@ -178,6 +332,7 @@ fn parse_group(&mut self, block: &Block) -> Result<(), Error> {
             hosts: Vec::new(),
             vars: HashMap::new(),
             imported: None,
+            protected: false,
         };
 
         let ctx = Context::new();
@@ -188,6 +343,17 @@ fn parse_group(&mut self, block: &Block) -> Result<(), Error> {
                     let v: hcl::Value = expr
                         .evaluate(&ctx)
                         .map_err(|e| Error::new(e.to_string().replace('\n', " ")))?;
+                    if a.key.as_str() == "protected" {
+                        let hcl::Value::Bool(protected) = v else {
+                            return self
+                                .origin
+                                .error("protected should be a bool", &a.value.span())
+                                .err();
+                        };
+                        group_config.protected = protected;
+                        continue;
+                    }
+                    self.check_tag(a.key.as_str(), &v, &a.value.span())?;
                     group_config.vars.insert(a.key.to_string(), v);
                 }
                 Structure::Block(block) => {
@@ -202,11 +368,66 @@ fn parse_group(&mut self, block: &Block) -> Result<(), Error> {
         Ok(())
     }
 
+    fn parse_inventory(&mut self, block: &Block) -> Result<(), Error> {
+        if block.labels.is_empty() {
+            return self
+                .origin
+                .error("inventory needs a kind", &block.ident.span())
+                .err();
+        }
+        if block.labels.len() > 1 {
+            return self
+                .origin
+                .error(
+                    "inventory should only have one kind",
+                    &block.labels[1].span(),
+                )
+                .err();
+        }
+        let BlockLabel::String(kind) = &block.labels[0] else {
+            return self
+                .origin
+                .error("inventory kind should be a string", &block.labels[0].span())
+                .err();
+        };
+
+        let mut attrs = HashMap::new();
+        let ctx = Context::new();
+        for structure in block.body.iter() {
+            if let Structure::Attribute(a) = structure {
+                let expr: hcl::Expression = a.value.to_owned().into();
+                let v: hcl::Value = expr
+                    .evaluate(&ctx)
+                    .map_err(|e| Error::new(e.to_string().replace('\n', " ")))?;
+                attrs.insert(a.key.to_string(), v);
+            }
+        }
+
+        let groups = load_inventory(kind.as_str(), &self.origin.cwd, &attrs)
+            .map_err(|e| self.origin.error(e.to_string(), &block.labels[0].span()))?;
+        for (name, group) in groups {
+            if self.groups.contains_key(&name) {
+                return self
+                    .origin
+                    .error(
+                        format!("group {name} from the inventory already exists"),
+                        &block.labels[0].span(),
+                    )
+                    .err();
+            }
+            self.groups.insert(name, group);
+        }
+
+        Ok(())
+    }
+
     fn parse_group_entry(
         &self,
         group_name: &str,
         block: &Block,
     ) -> Result<HostOrGroupConfig, Error> {
+        // the user and the port of the host in its name, which the vars in the block override
+        let mut vars = HashMap::new();
         let host_or_group = match block.ident.as_str() {
             "host" => {
                 if block.labels.is_empty() {
@@ -229,7 +450,19 @@ fn parse_group_entry(
                         .err();
                 };
 
-                HostOrGroup::Host(name.to_string())
+                let spec = HostSpec::parse(name.as_str())
+                    .map_err(|e| self.origin.error(e, &block.labels[0].span()))?;
+                let host_name = spec.name();
+                if spec.host != host_name {
+                    vars.insert("ssh_host".to_string(), hcl::Value::from(spec.host.as_str()));
+                }
+                if let Some(port) = spec.port {
+                    vars.insert("ssh_port".to_string(), hcl::Value::from(port));
+                }
+                if let Some(user) = spec.user {
+                    vars.insert("remote_user".to_string(), hcl::Value::from(user));
+                }
+                HostOrGroup::Host(host_name)
             }
             "group" => {
                 if block.labels.is_empty() {
@@ -266,6 +499,7 @@ fn parse_group_entry(
                             format!("group {} doesn't exist", name.as_str()),
                             &block.labels[0].span(),
                         )
+                        .with_code("E0301")
                         .err();
                 }
 
@@ -281,16 +515,23 @@ fn parse_group_entry(
 
         let mut host_config = HostOrGroupConfig {
             host: host_or_group,
-            vars: HashMap::new(),
+            vars,
+            when: None,
         };
 
         let ctx = Context::new();
         for structure in block.body.iter() {
             if let Structure::Attribute(a) = structure {
                 let expr: hcl::Expression = a.value.to_owned().into();
+                if a.key.as_str() == "when" {
+                    // it can use the facts, so it's only evaluated when the host is connected
+                    host_config.when = Some(expr);
+                    continue;
+                }
                 let v: hcl::Value = expr
                     .evaluate(&ctx)
                     .map_err(|e| Error::new(e.to_string().replace('\n', " ")))?;
+                self.check_tag(a.key.as_str(), &v, &a.value.span())?;
                 host_config.vars.insert(a.key.to_string(), v);
             }
         }
@@ -298,6 +539,33 @@ fn parse_group_entry(
         Ok(host_config)
     }
 
+    /// The `environment` and `color` vars tag the hosts in the TUI, so they have to be
+    /// a string and a color that it knows
+    fn check_tag(
+        &self,
+        key: &str,
+        v: &hcl::Value,
+        span: &Option<Range<usize>>,
+    ) -> Result<(), Error> {
+        match (key, v) {
+            ("environment", hcl::Value::String(_)) => Ok(()),
+            ("color", hcl::Value::String(color)) => {
+                if parse_color(color).is_none() {
+                    return self
+                        .origin
+                        .error(format!("{color} isn't a color, e.g. red or #ff0000"), span)
+                        .err();
+                }
+                Ok(())
+            }
+            ("environment" | "color", _) => self
+                .origin
+                .error(format!("{key} should be a string"), span)
+                .err(),
+            _ => Ok(()),
+        }
+    }
+
     fn parse_use(&mut self, block: &Block) -> Result<(), Error> {
         if block.labels.is_empty() {
             return self
@@ -323,7 +591,8 @@ fn parse_use(&mut self, block: &Block) -> Result<(), Error> {
 
         let path = self.origin.cwd.join(name.as_str());
 
-        let mut runbook = Runbook::new(path, self.tx.clone(), self.level + 1)?;
+        let mut runbook = Runbook::new(path, self.level + 1)?;
+        runbook.project = self.project.clone();
         runbook.parse(false).map_err(|e| {
             let mut e = e;
             if e.location.is_none() {
@@ -352,10 +621,10 @@ fn parse_use(&mut self, block: &Block) -> Result<(), Error> {
             if let Structure::Block(block) = structure {
                 match block.ident.as_str() {
                     "job" => {
-                        self.parse_use_job(&runbook, block)?;
+                        self.parse_use_job(&runbook, &path, block)?;
                     }
                     "group" => {
-                        self.parse_use_group(&runbook, block)?;
+                        self.parse_use_group(&runbook, &path, block)?;
                     }
                     _ => {}
                 }
@@ -367,7 +636,12 @@ fn parse_use(&mut self, block: &Block) -> Result<(), Error> {
         Ok(())
     }
 
-    fn parse_use_job(&mut self, imported: &Runbook, block: &Block) -> Result<(), Error> {
+    fn parse_use_job(
+        &mut self,
+        imported: &Runbook,
+        path: &Path,
+        block: &Block,
+    ) -> Result<(), Error> {
         if block.labels.is_empty() {
             return self
                 .origin
@@ -415,35 +689,198 @@ fn parse_use_job(&mut self, imported: &Runbook, block: &Block) -> Result<(), Err
                 )
             })?
             .clone();
-        job.imported = Some(imported.origin.path.clone());
+        // a job the imported runbook imported itself stays with the runbook it's from
+        if job.imported.is_none() {
+            job.imported = Some(path.to_path_buf());
+        }
 
         self.jobs.insert(imported_name.to_string(), job.to_owned());
 
         Ok(())
     }
 
-    fn hosts_from_name(&self, name: &str) -> Result<Vec<Node>> {
+    /// The `path` variable of the actions written in the runbook, with `path.job`
+    /// only if they're in a job
+    pub fn path_var(&self, in_job: bool) -> hcl::Value {
+        let dir = |path: &Path| hcl::Value::from(path.to_string_lossy().to_string());
+        let mut vars = hcl::Map::new();
+        vars.insert("project".to_string(), dir(&self.project));
+        vars.insert("runbook".to_string(), dir(&self.origin.cwd));
+        vars.insert(
+            "job".to_string(),
+            if in_job {
+                dir(&self.origin.cwd)
+            } else {
+                hcl::Value::Null
+            },
+        );
+        hcl::Value::Object(vars)
+    }
+
+    /// The imported runbook of the path, which can be imported by one of the imported runbooks
+    fn imported(&self, path: &Path) -> Option<&Runbook> {
+        self.imports.get(path).or_else(|| {
+            self.imports
+                .values()
+                .find_map(|runbook| runbook.imported(path))
+        })
+    }
+
+    /// The hosts of a target of the run, which is a group or a host name, or the names
+    /// joined by `:` like the patterns of Ansible. The hosts of the plain names are
+    /// joined, and then only the ones in every name with `&` and in none of the names
+    /// with `!` are kept, e.g. `"web:&staging"` or `"all:!windows"`
+    fn hosts_from_target(
+        &self,
+        target: &str,
+        span: &Option<Range<usize>>,
+    ) -> Result<Vec<Node>, Error> {
+        let not_found = match self.hosts_from_name(target) {
+            Ok(hosts) => return Ok(hosts),
+            Err(e) => self.origin.error(e.to_string(), span).with_code("E0301"),
+        };
+        if !target.contains([':', '&', '!']) {
+            return Err(not_found);
+        }
+
+        let mut included = Vec::new();
+        let mut required: Vec<HashSet<String>> = Vec::new();
+        let mut excluded = HashSet::new();
+        // whether it's meant to be a pattern rather than a host name with a port,
+        // which it is with `&` or `!`, or if any of the names is found
+        let mut pattern = false;
+        let mut unknown = None;
+        let mut offset = 0;
+        for term in target.split(':') {
+            let start = offset;
+            offset += term.len() + 1;
+            let (op, name) = match term.strip_prefix(['&', '!']) {
+                Some(name) => (term.chars().next(), name),
+                None => (None, term),
+            };
+            let term_span = self.target_span(target, span, start, term.len());
+            if name.is_empty() {
+                return self
+                    .origin
+                    .error(
+                        format!("{target} has an empty group or host name"),
+                        &term_span,
+                    )
+                    .err();
+            }
+            pattern |= op.is_some();
+            let hosts = match self.hosts_from_name(name) {
+                Ok(hosts) => hosts,
+                Err(e) => {
+                    if unknown.is_none() {
+                        unknown = Some(
+                            self.origin
+                                .error(e.to_string(), &term_span)
+                                .with_code("E0301"),
+                        );
+                    }
+                    continue;
+                }
+            };
+            pattern = true;
+            let names = hosts.iter().map(|host| host.host.clone());
+            match op {
+                Some('&') => required.push(names.collect()),
+                Some(_) => excluded.extend(names),
+                None => included.extend(hosts),
+            }
+        }
+        if let Some(unknown) = unknown {
+            return Err(if pattern { unknown } else { not_found });
+        }
+
+        if included.is_empty() && required.is_empty() {
+            return self
+                .origin
+                .error(
+                    format!("{target} only excludes hosts, start it with the hosts to run on, e.g. all:!windows"),
+                    span,
+                )
+                .err();
+        }
+        included.retain(|host: &Node| {
+            required.iter().all(|names| names.contains(&host.host))
+                && !excluded.contains(&host.host)
+        });
+        if included.is_empty() {
+            return self
+                .origin
+                .error(format!("there isn't any host in {target}"), span)
+                .with_code("E0301")
+                .err();
+        }
+        Ok(included)
+    }
+
+    /// The span of the part of the target at the offset, if the target is written
+    /// in the runbook as it is, or the span of the whole target otherwise
+    fn target_span(
+        &self,
+        target: &str,
+        span: &Option<Range<usize>>,
+        offset: usize,
+        len: usize,
+    ) -> Option<Range<usize>> {
+        let span = span.as_ref()?;
+        let start = match self.origin.data.get(span.clone()) {
+            Some(s) if s == target => span.start + offset,
+            Some(s) if s.len() == target.len() + 2 && &s[1..s.len() - 1] == target => {
+                span.start + 1 + offset
+            }
+            _ => return Some(span.clone()),
+        };
+        Some(start..start + len)
+    }
+
+    pub fn hosts_from_name(&self, name: &str) -> Result<Vec<Node>> {
         if self.groups.contains_key(name) {
             return self.hosts_from_group(name);
         } else {
-            for group in self.groups.values() {
+            for (group_name, group) in self.groups.iter() {
                 for host in &group.hosts {
                     if let HostOrGroup::Host(host_name) = &host.host {
                         if host_name == name {
-                            return Ok(vec![Node::new(
-                                host_name.to_string(),
-                                host.vars.clone(),
-                                &self.tx,
-                            )]);
+                            let mut node = Node::new(host_name.to_string(), host.vars.clone());
+                            node.groups.push(group_name.to_string());
+                            if group.protected {
+                                node.protected = Some(group_name.to_string());
+                            }
+                            if let Some(when) = &host.when {
+                                node.add_when(when.clone());
+                            }
+                            return Ok(vec![node]);
                         }
                     }
                 }
             }
         }
+        if name == "all" {
+            return self.all_hosts();
+        }
         Err(anyhow!("can't find host with name {name}"))
     }
 
-    fn parse_use_group(&mut self, imported: &Runbook, block: &Block) -> Result<(), Error> {
+    /// All the hosts in the groups of the runbook, for the `all` target
+    /// if there isn't a group or a host of that name
+    fn all_hosts(&self) -> Result<Vec<Node>> {
+        let mut hosts = Vec::new();
+        for name in self.groups.keys().sorted() {
+            hosts.append(&mut self.hosts_from_group(name)?);
+        }
+        Ok(hosts)
+    }
+
+    fn parse_use_group(
+        &mut self,
+        imported: &Runbook,
+        path: &Path,
+        block: &Block,
+    ) -> Result<(), Error> {
         if block.labels.is_empty() {
             return self
                 .origin
@@ -491,21 +928,23 @@ fn parse_use_group(&mut self, imported: &Runbook, block: &Block) -> Result<(), E
                 )
             })?
             .clone();
-        group.imported = Some(imported.origin.path.clone());
+        // a group the imported runbook imported itself stays with the runbook it's from
+        if group.imported.is_none() {
+            group.imported = Some(path.to_path_buf());
+        }
 
         self.groups.insert(imported_name.to_string(), group);
 
         Ok(())
     }
 
-    fn hosts_from_group(&self, group: &str) -> Result<Vec<Node>> {
-        let Some(group) = self.groups.get(group) else {
-            return Err(anyhow!("hosts doesn't have group {group}"));
+    fn hosts_from_group(&self, group_name: &str) -> Result<Vec<Node>> {
+        let Some(group) = self.groups.get(group_name) else {
+            return Err(anyhow!("hosts doesn't have group {group_name}"));
         };
 
         let runbook = if let Some(imported) = &group.imported {
-            self.imports
-                .get(imported)
+            self.imported(imported)
                 .ok_or_else(|| anyhow!("can't find imported"))?
         } else {
             self
@@ -515,11 +954,7 @@ fn hosts_from_group(&self, group: &str) -> Result<Vec<Node>> {
         for host_or_group in &group.hosts {
             let mut local_hosts = match &host_or_group.host {
                 HostOrGroup::Host(name) => {
-                    vec![Node::new(
-                        name.to_string(),
-                        host_or_group.vars.clone(),
-                        &self.tx,
-                    )]
+                    vec![Node::new(name.to_string(), host_or_group.vars.clone())]
                 }
                 HostOrGroup::Group(group) => {
                     let mut local_hosts = runbook.hosts_from_group(group)?;
@@ -554,6 +989,15 @@ fn hosts_from_group(&self, group: &str) -> Result<Vec<Node>> {
                     }
                 }
             }
+            for host in local_hosts.iter_mut() {
+                host.groups.push(group_name.to_string());
+                if group.protected && host.protected.is_none() {
+                    host.protected = Some(group_name.to_string());
+                }
+                if let Some(when) = &host_or_group.when {
+                    host.add_when(when.clone());
+                }
+            }
             hosts.append(&mut local_hosts);
         }
         Ok(hosts)
@@ -585,131 +1029,774 @@ fn parse_job(&mut self, block: &Block) -> Result<(), Error> {
         Ok(())
     }
 
+    /// Parse the actions in the block, followed by the actions in its `rescue` block
+    /// which run when an action fails, and the actions in its `always` block
+    /// which run regardless
     pub fn parse_actions(&self, ctx: &Context, block: &Block) -> Result<Vec<ActionData>, Error> {
         let all_actions = all_actions();
 
-        let mut actions = Vec::new();
+        let mut rescue = None;
+        let mut always = None;
         for s in block.body.iter() {
-            if let Structure::Block(block) = s {
-                if block.ident.as_str() == "action" {
-                    if block.labels.is_empty() {
-                        return self
-                            .origin
-                            .error("No action name", &block.ident.span())
-                            .err();
-                    }
-                    if block.labels.len() > 1 {
-                        return self
-                            .origin
-                            .error("You can only have one action name", &block.labels[1].span())
-                            .err();
+            if let Structure::Block(b) = s {
+                let section = match b.ident.as_str() {
+                    "rescue" => &mut rescue,
+                    "always" => &mut always,
+                    _ => continue,
+                };
+                if section.is_some() {
+                    return self
+                        .origin
+                        .error(
+                            format!("there can only be one {} block", b.ident.as_str()),
+                            &b.ident.span(),
+                        )
+                        .err();
+                }
+                *section = Some(b);
+            }
+        }
+
+        let scope = Uuid::new_v4();
+        let mut actions = Vec::new();
+        for (section, block) in [
+            (BlockSection::Main, Some(block)),
+            (BlockSection::Rescue, rescue),
+            (BlockSection::Always, always),
+        ] {
+            let Some(block) = block else {
+                continue;
+            };
+            let mut entries = Vec::new();
+            for s in block.body.iter() {
+                match s {
+                    Structure::Block(block) if block.ident.as_str() == "action" => {
+                        entries.push(self.parse_action(&all_actions, ctx, block)?);
                     }
-                    let BlockLabel::String(action_name) = &block.labels[0] else {
-                        return self
-                            .origin
-                            .error("action name should be a string", &block.labels[0].span())
-                            .err();
-                    };
-
-                    let params = block.body.iter().find_map(|s| {
-                        s.as_block()
-                            .filter(|&block| block.ident.as_str() == "params")
-                    });
-
-                    let name = block.body.iter().find_map(|s| {
-                        s.as_attribute()
-                            .filter(|a| a.key.as_str() == "name")
-                            .map(|a| &a.value)
-                    });
-                    let name = if let Some(name) = name {
-                        let name =
-                            SpannedValue::from_expression(&self.origin, ctx, name.to_owned())?;
-                        let SpannedValue::String(s) = name else {
-                            return self
-                                .origin
-                                .error("name should be a string", name.span())
-                                .err();
-                        };
-                        Some(s.value().to_string())
-                    } else {
-                        None
-                    };
-
-                    let params = params.ok_or_else(|| {
-                        self.origin
-                            .error("action doesn't have params", &block.ident.span())
-                    })?;
-
-                    let mut attrs = HashMap::new();
-                    for s in params.body.iter() {
-                        if let Some(a) = s.as_attribute() {
-                            let v = SpannedValue::from_expression(
-                                &self.origin,
-                                ctx,
-                                a.value.to_owned(),
-                            )?;
-                            attrs.insert(a.key.to_string(), v);
-                        }
+                    Structure::Attribute(a) if a.key.as_str() == "include_actions" => {
+                        entries.append(&mut self.include_actions(ctx, a)?);
                     }
+                    _ => {}
+                }
+            }
+            let mut section_actions = self.sort_actions(entries)?;
+            for action in section_actions.iter_mut() {
+                action.scope.insert(
+                    0,
+                    ActionScope {
+                        block: scope,
+                        section,
+                    },
+                );
+            }
+            actions.append(&mut section_actions);
+        }
 
-                    if action_name.as_str() == "job" {
-                        let job_name = attrs.get("name").ok_or_else(|| {
-                            self.origin
-                                .error("job doesn't have name in params", &params.ident.span())
-                        })?;
-                        let SpannedValue::String(job_name) = job_name else {
-                            return self
-                                .origin
-                                .error("job name should be a string", job_name.span())
-                                .err();
-                        };
-                        let job = self.jobs.get(job_name.value()).ok_or_else(|| {
-                            self.origin.error("can't find job name", job_name.span())
-                        })?;
-
-                        let runbook = if let Some(imported) = &job.imported {
-                            self.imports.get(imported).ok_or_else(|| {
-                                self.origin
-                                    .error("can't find imported job", job_name.span())
-                            })?
-                        } else {
-                            self
-                        };
+        Ok(actions)
+    }
 
-                        actions.append(&mut runbook.parse_actions(ctx, &job.block)?);
-                    } else {
-                        let Some(action) = all_actions.get(action_name.as_str()) else {
-                            return self
-                                .origin
-                                .error(
-                                    format!("action {} can't be found", action_name.as_str()),
-                                    &block.labels[0].span(),
-                                )
-                                .err();
-                        };
+    /// The actions in the files of `include_actions`, which is evaluated with the vars
+    /// of the host, e.g. `include_actions = "tasks/${role}.tr"`. The files only have
+    /// action blocks, which work as if they're written where they're included
+    fn include_actions(&self, ctx: &Context, attr: &Attribute) -> Result<Vec<ActionEntry>, Error> {
+        if uses_facts(&attr.value) {
+            return Ok(vec![self.defer_include(ctx, attr)?]);
+        }
+        let value = SpannedValue::from_expression(&self.origin, ctx, attr.value.to_owned())?;
+        let mut entries = Vec::new();
+        for path in self.include_paths(&value)? {
+            let actions = self.included_actions(ctx, &path, value.span())?;
+            entries.push(ActionEntry {
+                name: path,
+                after: Vec::new(),
+                actions,
+            });
+        }
+        Ok(entries)
+    }
 
-                        let params =
-                            action
-                                .doc()
-                                .parse_attrs(&self.origin, &attrs)
-                                .map_err(|e| {
-                                    let mut e = e;
-                                    if e.location.is_none() {
-                                        e = e.with_origin(&self.origin, &params.ident.span());
-                                    }
-                                    e
-                                })?;
-                        let input = action.input(params)?;
-                        actions.push(ActionData {
-                            id: ActionId::new(),
-                            name: name.unwrap_or_else(|| action_name.to_string()),
-                            action: action_name.to_string(),
-                            input,
-                        });
-                    }
+    /// The `include_actions` that uses the facts of the host, which are only there when
+    /// the host is connected, stands in the actions as an action of its own until then
+    fn defer_include(&self, ctx: &Context, attr: &Attribute) -> Result<ActionEntry, Error> {
+        let span = attr.value.span();
+        let expr = span
+            .as_ref()
+            .and_then(|span| self.origin.data.get(span.clone()))
+            .unwrap_or_default()
+            .to_string();
+        // the rest of the context is the same when the host is connected,
+        // but the path of the runbook
+        let path: hcl::Expression = hcl_edit::parser::parse_expr("path")
+            .map_err(|e| Error::new(e.to_string()))?
+            .into();
+        let path = path
+            .evaluate(ctx)
+            .map_err(|e| self.origin.error(e.to_string(), &span))?;
+        let include = DeferredInclude {
+            expr: expr.clone(),
+            span: span.clone(),
+            path: self.origin.path.clone(),
+            cwd: self.origin.cwd.clone(),
+            data: self.origin.data.clone(),
+            project: self.project.clone(),
+            level: self.level,
+            path_var: serde_json::to_string(&path).unwrap_or_default(),
+        };
+        let input = bincode::serialize(&include).map_err(|e| {
+            self.origin
+                .error(format!("serialize include_actions error: {e}"), &span)
+        })?;
+        Ok(ActionEntry {
+            name: expr.clone(),
+            after: Vec::new(),
+            actions: vec![ActionData {
+                id: ActionId::new(),
+                name: format!("include_actions {expr}"),
+                action: INCLUDE_ACTIONS.to_string(),
+                input,
+                dry_run: false,
+                throttle: None,
+                scope: Vec::new(),
+                rollback: Some(Rollback::Auto),
+                debug: false,
+                unchanged: false,
+                params: Vec::new(),
+                cwd: self.origin.cwd.clone(),
+                location: self.origin.location(&span),
+            }],
+        })
+    }
+
+    fn include_paths(&self, value: &SpannedValue) -> Result<Vec<String>, Error> {
+        match value {
+            SpannedValue::String(s) => Ok(vec![s.value().to_string()]),
+            SpannedValue::Array(list) => list
+                .value()
+                .iter()
+                .map(|item| match item {
+                    SpannedValue::String(s) => Ok(s.value().to_string()),
+                    _ => self
+                        .origin
+                        .error(
+                            "include_actions should be a path or a list of paths",
+                            item.span(),
+                        )
+                        .err(),
+                })
+                .collect(),
+            _ => self
+                .origin
+                .error(
+                    "include_actions should be a path or a list of paths",
+                    value.span(),
+                )
+                .err(),
+        }
+    }
+
+    /// The actions in the file of the path from `include_actions`
+    fn included_actions(
+        &self,
+        ctx: &Context,
+        path: &str,
+        span: &Option<Range<usize>>,
+    ) -> Result<Vec<ActionData>, Error> {
+        if self.level >= MAX_INCLUDE_LEVEL {
+            return self
+                .origin
+                .error(
+                    format!(
+                        "include_actions is nested more than {MAX_INCLUDE_LEVEL} levels, \
+                         which can be a file that includes itself"
+                    ),
+                    span,
+                )
+                .err();
+        }
+        let mut included =
+            Runbook::new(self.origin.cwd.join(path), self.level + 1).map_err(|e| {
+                let mut e = e;
+                if e.location.is_none() {
+                    e = e.with_origin(&self.origin, span);
+                }
+                e
+            })?;
+        // the actions are as if they're written in the runbook, and so are
+        // the relative paths in their params
+        included.origin.cwd = self.origin.cwd.clone();
+        included.project = self.project.clone();
+
+        let body = hcl_edit::parser::parse_body(&included.origin.data)
+            .map_err(|e| Error::from_hcl(e, included.origin.path.clone()))?;
+        for s in body.iter() {
+            let span = match s {
+                Structure::Block(b)
+                    if matches!(b.ident.as_str(), "action" | "rescue" | "always") =>
+                {
+                    continue;
+                }
+                Structure::Attribute(a) if a.key.as_str() == "include_actions" => continue,
+                Structure::Block(b) => b.ident.span(),
+                Structure::Attribute(a) => a.key.span(),
+            };
+            return included
+                .origin
+                .error(
+                    "a file of include_actions can only have action blocks",
+                    &span,
+                )
+                .err();
+        }
+        let mut block = Block::new(Ident::new("actions"));
+        block.body = body;
+        included.parse_actions(ctx, &block)
+    }
+
+    fn parse_action(
+        &self,
+        all_actions: &HashMap<String, Box<dyn Action>>,
+        ctx: &Context,
+        block: &Block,
+    ) -> Result<ActionEntry, Error> {
+        if block.labels.is_empty() {
+            return self
+                .origin
+                .error("No action name", &block.ident.span())
+                .err();
+        }
+        if block.labels.len() > 1 {
+            return self
+                .origin
+                .error("You can only have one action name", &block.labels[1].span())
+                .err();
+        }
+        let BlockLabel::String(action_name) = &block.labels[0] else {
+            return self
+                .origin
+                .error("action name should be a string", &block.labels[0].span())
+                .err();
+        };
+
+        let params = block.body.iter().find_map(|s| {
+            s.as_block()
+                .filter(|&block| block.ident.as_str() == "params")
+        });
+
+        let name = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "name")
+                .map(|a| &a.value)
+        });
+        let name = if let Some(name) = name {
+            let name = SpannedValue::from_expression(&self.origin, ctx, name.to_owned())?;
+            let SpannedValue::String(s) = name else {
+                return self
+                    .origin
+                    .error("name should be a string", name.span())
+                    .err();
+            };
+            Some(s.value().to_string())
+        } else {
+            None
+        };
+        let name = name.unwrap_or_else(|| action_name.to_string());
+
+        let after = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "after")
+                .map(|a| &a.value)
+        });
+        let after = if let Some(after) = after {
+            let after = SpannedValue::from_expression(&self.origin, ctx, after.to_owned())?;
+            let SpannedValue::Array(list) = &after else {
+                return self
+                    .origin
+                    .error("after should be a list of action names", after.span())
+                    .err();
+            };
+            let mut names = Vec::new();
+            for item in list.value() {
+                let SpannedValue::String(s) = item else {
+                    return self
+                        .origin
+                        .error("after should be a list of action names", item.span())
+                        .err();
+                };
+                names.push((s.value().to_string(), item.span().to_owned()));
+            }
+            names
+        } else {
+            Vec::new()
+        };
+
+        let throttle = block.body.iter().find_map(|s| {
+            s.as_attribute()
+                .filter(|a| a.key.as_str() == "throttle")
+                .map(|a| &a.value)
+        });
+        let throttle = if let Some(throttle) = throttle {
+            let v = SpannedValue::from_expression(&self.origin, ctx, throttle.to_owned())?;
+            let limit = match &v {
+                SpannedValue::Number(n) => n.value().as_u64().filter(|n| *n > 0),
+                _ => None,
+            };
+            let limit = limit.ok_or_else(|| {
+                self.origin
+                    .error("throttle should be a positive integer", v.span())
+            })?;
+            // the action block is parsed for every host, so its position
+            // identifies it across the hosts
+            Some(ActionThrottle {
+                key: format!(
+                    "{}:{}",
+                    self.origin.path.to_string_lossy(),
+                    block.span().map(|s| s.start).unwrap_or_default()
+                ),
+                limit: limit as usize,
+            })
+        } else {
+            None
+        };
+
+        let rollback = block.body.iter().find_map(|s| {
+            s.as_block()
+                .filter(|&block| block.ident.as_str() == "rollback")
+        });
+        let rollback = if let Some(rollback) = rollback {
+            Some(Rollback::Action(self.parse_rollback(
+                all_actions,
+                ctx,
+                rollback,
+            )?))
+        } else {
+            Some(Rollback::Auto)
+        };
+
+        let params = params.ok_or_else(|| {
+            self.origin
+                .error("action doesn't have params", &block.ident.span())
+        })?;
+
+        let mut attrs = HashMap::new();
+        for s in params.body.iter() {
+            if let Some(a) = s.as_attribute() {
+                let v = SpannedValue::from_expression(&self.origin, ctx, a.value.to_owned())?;
+                attrs.insert(a.key.to_string(), v);
+            }
+        }
+
+        if action_name.as_str() == "job" {
+            if let Some(Rollback::Action(_)) = rollback {
+                return self
+                    .origin
+                    .error(
+                        "job can't have a rollback, give its actions the rollback instead",
+                        &block.labels[0].span(),
+                    )
+                    .err();
+            }
+            let job_name = attrs.get("name").ok_or_else(|| {
+                self.origin
+                    .error("job doesn't have name in params", &params.ident.span())
+            })?;
+            let SpannedValue::String(job_name) = job_name else {
+                return self
+                    .origin
+                    .error("job name should be a string", job_name.span())
+                    .err();
+            };
+            let job = self
+                .jobs
+                .get(job_name.value())
+                .ok_or_else(|| self.origin.error("can't find job name", job_name.span()))?;
+
+            let runbook = if let Some(imported) = &job.imported {
+                self.imported(imported).ok_or_else(|| {
+                    self.origin
+                        .error("can't find imported job", job_name.span())
+                })?
+            } else {
+                self
+            };
+
+            // the actions in the job have the paths of the runbook the job is from
+            let mut ctx = ctx.clone();
+            ctx.declare_var("path", runbook.path_var(true));
+            let mut actions = runbook.parse_actions(&ctx, &job.block)?;
+            // the actions in a throttled job are throttled together
+            if throttle.is_some() {
+                for action in actions.iter_mut() {
+                    action.throttle = throttle.clone();
                 }
             }
+
+            Ok(ActionEntry {
+                name,
+                after,
+                actions,
+            })
+        } else {
+            let Some(action) = all_actions.get(action_name.as_str()) else {
+                return self
+                    .origin
+                    .error(
+                        format!("action {} can't be found", action_name.as_str()),
+                        &block.labels[0].span(),
+                    )
+                    .with_code("E0101")
+                    .err();
+            };
+
+            let doc = action.doc();
+            let shown_params = params_summary(&doc, &attrs);
+            let mut params = doc.parse_attrs(&self.origin, &attrs).map_err(|e| {
+                let mut e = e;
+                if e.location.is_none() {
+                    e = e.with_origin(&self.origin, &params.ident.span());
+                }
+                e
+            })?;
+            params.ctx = Some(ctx);
+            let input = action.input(params).map_err(|e| e.with_code("E0202"))?;
+            Ok(ActionEntry {
+                name: name.clone(),
+                after,
+                actions: vec![ActionData {
+                    id: ActionId::new(),
+                    name,
+                    action: action_name.to_string(),
+                    input,
+                    dry_run: false,
+                    throttle,
+                    scope: Vec::new(),
+                    rollback,
+                    debug: false,
+                    unchanged: false,
+                    params: shown_params,
+                    cwd: self.origin.cwd.clone(),
+                    location: self.origin.location(&block_span(block)),
+                }],
+            })
+        }
+    }
+
+    /// Parse a `snapshot` block of a run, which is a snapshot action with the provider
+    /// in the label. The snapshot is restored when the host fails.
+    pub fn parse_snapshot(&self, ctx: &Context, block: &Block) -> Result<ActionData, Error> {
+        let [BlockLabel::String(provider)] = &block.labels[..] else {
+            return self
+                .origin
+                .error(
+                    "snapshot should have one provider, e.g. \"lvm\"",
+                    &block.ident.span(),
+                )
+                .err();
+        };
+
+        let mut attrs = HashMap::new();
+        attrs.insert(
+            "provider".to_string(),
+            SpannedValue::String(Spanned::new(provider.to_string())),
+        );
+        for s in block.body.iter() {
+            if let Some(a) = s.as_attribute() {
+                let v = SpannedValue::from_expression(&self.origin, ctx, a.value.to_owned())?;
+                attrs.insert(a.key.to_string(), v);
+            }
+        }
+
+        let all_actions = all_actions();
+        let action = all_actions.get("snapshot").ok_or_else(|| {
+            self.origin
+                .error("can't find snapshot action", &block.ident.span())
+        })?;
+        let doc = action.doc();
+        let shown_params = params_summary(&doc, &attrs);
+        let params = doc.parse_attrs(&self.origin, &attrs).map_err(|e| {
+            let mut e = e;
+            if e.location.is_none() {
+                e = e.with_origin(&self.origin, &block.ident.span());
+            }
+            e
+        })?;
+        let input = action.input(params).map_err(|e| {
+            let mut e = e.with_code("E0202");
+            if e.location.is_none() {
+                e = e.with_origin(&self.origin, &block.ident.span());
+            }
+            e
+        })?;
+
+        Ok(ActionData {
+            id: ActionId::new(),
+            name: format!("snapshot {provider}"),
+            action: action.name(),
+            input,
+            dry_run: false,
+            throttle: None,
+            scope: Vec::new(),
+            rollback: Some(Rollback::Auto),
+            debug: false,
+            unchanged: false,
+            params: shown_params,
+            cwd: self.origin.cwd.clone(),
+            location: self.origin.location(&block_span(block)),
+        })
+    }
+
+    /// Parse the action in the `rollback` block of an action, which undoes the action
+    fn parse_rollback(
+        &self,
+        all_actions: &HashMap<String, Box<dyn Action>>,
+        ctx: &Context,
+        block: &Block,
+    ) -> Result<ActionRollback, Error> {
+        let mut actions = block.body.iter().filter_map(|s| {
+            s.as_block()
+                .filter(|&block| block.ident.as_str() == "action")
+        });
+        let Some(action) = actions.next() else {
+            return self
+                .origin
+                .error("rollback doesn't have an action", &block.ident.span())
+                .err();
+        };
+        if let Some(action) = actions.next() {
+            return self
+                .origin
+                .error("rollback can only have one action", &action.ident.span())
+                .err();
+        }
+        if let Some(BlockLabel::String(name)) = action.labels.first() {
+            if name.as_str() == "job" {
+                return self
+                    .origin
+                    .error("rollback can't be a job", &action.labels[0].span())
+                    .err();
+            }
+        }
+
+        let mut entry = self.parse_action(all_actions, ctx, action)?;
+        let action = entry.actions.remove(0);
+        Ok(ActionRollback {
+            action: action.action,
+            input: action.input,
+        })
+    }
+
+    /// Order the actions so that every action comes after the actions
+    /// it declares in `after`, and otherwise keep the order they are written in.
+    fn sort_actions(&self, entries: Vec<ActionEntry>) -> Result<Vec<ActionData>, Error> {
+        let mut deps = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let mut entry_deps = Vec::new();
+            for (name, span) in &entry.after {
+                let len = entry_deps.len();
+                entry_deps.extend(
+                    entries
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, e)| *j != i && &e.name == name)
+                        .map(|(j, _)| j),
+                );
+                if entry_deps.len() == len {
+                    return self
+                        .origin
+                        .error(format!("can't find action {name} to be after"), span)
+                        .err();
+                }
+            }
+            deps.push(entry_deps);
+        }
+
+        let mut done = vec![false; entries.len()];
+        let mut order = Vec::new();
+        while order.len() < entries.len() {
+            let next =
+                (0..entries.len()).find(|&i| !done[i] && deps[i].iter().all(|&dep| done[dep]));
+            let Some(next) = next else {
+                let span = entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !done[*i])
+                    .find_map(|(_, e)| e.after.first())
+                    .and_then(|(_, span)| span.to_owned());
+                return self
+                    .origin
+                    .error(
+                        "actions in after can't depend on each other in a cycle",
+                        &span,
+                    )
+                    .err();
+            };
+            done[next] = true;
+            order.push(next);
+        }
+
+        let mut entries: Vec<Option<ActionEntry>> = entries.into_iter().map(Some).collect();
+        let mut actions = Vec::new();
+        for i in order {
+            if let Some(mut entry) = entries[i].take() {
+                actions.append(&mut entry.actions);
+            }
         }
         Ok(actions)
     }
 }
+
+/// The `include_actions` that uses the facts of the host, with what's needed to resolve it
+/// when the host is connected
+#[derive(Serialize, Deserialize)]
+struct DeferredInclude {
+    // the expression of the paths as it's written, e.g. `"tasks/${facts.os_family}.tr"`
+    expr: String,
+    span: Option<Range<usize>>,
+    // the runbook it's written in
+    path: PathBuf,
+    cwd: PathBuf,
+    data: String,
+    project: PathBuf,
+    level: usize,
+    // the `path` variable where it's written, in JSON
+    path_var: String,
+}
+
+/// Whether the action stands for an `include_actions` that uses the facts of the host
+pub fn is_deferred_include(action: &ActionData) -> bool {
+    action.action == INCLUDE_ACTIONS
+}
+
+/// The actions of the `include_actions` that the action stands for, with the context
+/// of the host that has its facts. The actions are in the same blocks as the action
+pub fn resolve_include(action: &ActionData, ctx: &Context) -> Result<Vec<ActionData>, Error> {
+    let include: DeferredInclude = bincode::deserialize(&action.input)
+        .map_err(|e| Error::new(format!("can't read include_actions: {e}")))?;
+    let mut runbook = Runbook::from_data(include.path, include.data, include.level)?;
+    runbook.origin.cwd = include.cwd;
+    runbook.project = include.project;
+
+    let mut ctx = ctx.clone();
+    declare_functions(&mut ctx);
+    if let Ok(path) = serde_json::from_str::<hcl::Value>(&include.path_var) {
+        ctx.declare_var("path", path);
+    }
+    let expr: hcl::Expression = hcl_edit::parser::parse_expr(&include.expr)
+        .map_err(|e| runbook.origin.error(e.to_string(), &include.span))?
+        .into();
+    let value = expr.evaluate(&ctx).map_err(|e| {
+        runbook
+            .origin
+            .error(e.to_string(), &include.span)
+            .with_code("E0201")
+    })?;
+    let value = SpannedValue::from_value(value, include.span.clone());
+
+    let mut actions = Vec::new();
+    for path in runbook.include_paths(&value)? {
+        actions.append(&mut runbook.included_actions(&ctx, &path, &include.span)?);
+    }
+    for resolved in actions.iter_mut() {
+        resolved.scope.splice(0..0, action.scope.iter().cloned());
+        resolved.dry_run = action.dry_run;
+        resolved.debug = action.debug;
+        if action.rollback.is_none() {
+            resolved.rollback = None;
+        }
+    }
+    Ok(actions)
+}
+
+/// Whether the expression uses the facts of the host
+fn uses_facts(expr: &Expression) -> bool {
+    struct Facts(bool);
+    impl Visit for Facts {
+        fn visit_variable(&mut self, node: &Decorated<Ident>) {
+            if node.as_str() == "facts" {
+                self.0 = true;
+            }
+        }
+    }
+    let mut facts = Facts(false);
+    facts.visit_expr(expr);
+    facts.0
+}
+
+/// The actions parsed from one action block, which is more than one
+/// if the action is a job
+struct ActionEntry {
+    name: String,
+    // the names of the actions this needs to be after, with the span
+    after: Vec<(String, Option<Range<usize>>)>,
+    actions: Vec<ActionData>,
+}
+
+/// The params of the action in the order of its doc, formatted as they're written
+fn params_summary(doc: &ActionDoc, attrs: &HashMap<String, SpannedValue>) -> Vec<(String, String)> {
+    doc.params
+        .iter()
+        .filter_map(|param| {
+            let value = attrs.get(&param.name)?;
+            Some((param.name.clone(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The span of the block from its name to its last label, e.g. `action "copy"`
+fn block_span(block: &Block) -> Option<Range<usize>> {
+    let start = block.ident.span()?.start;
+    let end = match block.labels.last() {
+        Some(label) => label.span()?.end,
+        None => block.ident.span()?.end,
+    };
+    Some(start..end)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_hosts(target: &str) -> Result<Vec<String>, Error> {
+        let data = format!(
+            r#"
+            group "web" {{
+                host "app1" {{}}
+                host "app2" {{}}
+            }}
+            group "staging" {{
+                host "app2" {{}}
+                host "db1" {{}}
+            }}
+            group "windows" {{
+                host "win1" {{}}
+            }}
+            run "{target}" {{}}
+            "#
+        );
+        let mut runbook = Runbook::from_data(PathBuf::from("/tmp/main.tr"), data, 0)?;
+        runbook.parse(true)?;
+        Ok(runbook.runs[0]
+            .hosts()
+            .iter()
+            .map(|host| host.host.clone())
+            .collect())
+    }
+
+    #[test]
+    fn run_target_patterns() {
+        assert_eq!(run_hosts("web:&staging").unwrap(), ["app2"]);
+        assert_eq!(run_hosts("web:staging:!app2").unwrap(), ["app1", "db1"]);
+        assert_eq!(run_hosts("all:!windows").unwrap(), ["app2", "db1", "app1"]);
+        assert_eq!(
+            run_hosts("web:&stagin").unwrap_err().message,
+            "can't find host with name stagin"
+        );
+        assert_eq!(
+            run_hosts("windows:&web").unwrap_err().message,
+            "there isn't any host in windows:&web"
+        );
+        assert_eq!(
+            run_hosts("!windows").unwrap_err().message,
+            "!windows only excludes hosts, start it with the hosts to run on, e.g. all:!windows"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use hcl::{eval::Context, Map};
+use hcl_edit::{
+    structure::{Block, BlockLabel, Structure},
+    Span,
+};
+use tiron_common::{
+    error::{Error, Origin},
+    event::{EventSink, NullSink},
+    value::SpannedValue,
+};
+
+use crate::{
+    core::{execute_runs, load_runs, runbook_paths},
+    history::{now, RunRecord},
+    run::format_labels,
+};
+
+/// A set of runbooks that's run on a cron schedule
+struct Schedule {
+    name: String,
+    cron: Cron,
+    runbooks: Vec<PathBuf>,
+    // the command to run after each run, for sending notifications
+    notify: Vec<String>,
+    // whether the previous run is still running, so that runs don't overlap
+    running: AtomicBool,
+}
+
+impl Schedule {
+    fn from_block(origin: &Origin, block: &Block) -> Result<Self, Error> {
+        if block.labels.is_empty() {
+            return origin
+                .error("schedule needs a name", &block.ident.span())
+                .err();
+        }
+        if block.labels.len() > 1 {
+            return origin
+                .error(
+                    "schedule should only have one name",
+                    &block.labels[1].span(),
+                )
+                .err();
+        }
+        let BlockLabel::String(name) = &block.labels[0] else {
+            return origin
+                .error("schedule name should be a string", &block.labels[0].span())
+                .err();
+        };
+
+        let mut cron = None;
+        let mut runbooks = Vec::new();
+        let mut notify = Vec::new();
+        let ctx = Context::new();
+        for structure in block.body.iter() {
+            if let Structure::Attribute(a) = structure {
+                let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+                match a.key.as_str() {
+                    "cron" => {
+                        let SpannedValue::String(s) = &v else {
+                            return origin.error("cron should be a string", v.span()).err();
+                        };
+                        cron = Some(Cron::parse(s.value()).map_err(|e| origin.error(e, v.span()))?);
+                    }
+                    "runbooks" => {
+                        runbooks = string_list(origin, &v, "runbooks")?;
+                    }
+                    "notify" => {
+                        notify = string_list(origin, &v, "notify")?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let cron =
+            cron.ok_or_else(|| origin.error("schedule doesn't have cron", &block.ident.span()))?;
+        if runbooks.is_empty() {
+            runbooks.push("main".to_string());
+        }
+
+        Ok(Schedule {
+            name: name.as_str().to_string(),
+            cron,
+            runbooks: runbook_paths(&runbooks),
+            notify,
+            running: AtomicBool::new(false),
+        })
+    }
+
+    fn trigger(self: &Arc<Self>) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            log(
+                &self.name,
+                "skipped because the previous run is still running",
+            );
+            return;
+        }
+
+        let schedule = self.clone();
+        std::thread::spawn(move || {
+            schedule.execute();
+            schedule.running.store(false, Ordering::SeqCst);
+        });
+    }
+
+    fn execute(&self) {
+        log(&self.name, "started");
+
+        // there's no TUI for the schedules, so the events are dropped
+        let events: Arc<dyn EventSink> = Arc::new(NullSink);
+        let mut record = RunRecord::new(self.runbooks.clone(), false);
+        let result = load_runs(&self.runbooks, false)
+            .map_err(|e| e.message)
+            .and_then(|runs| execute_runs(runs, &events).map_err(|e| e.to_string()));
+        let error = match result {
+            Ok(results) => {
+                record.finish(results);
+                None
+            }
+            Err(e) => {
+                record.finish(Vec::new());
+                record.success = false;
+                Some(e)
+            }
+        };
+        if let Err(e) = record.save() {
+            log(&self.name, &format!("can't save the run history: {e}"));
+        }
+
+        match &error {
+            Some(e) => log(&self.name, &format!("failed: {e}")),
+            None if record.success => log(&self.name, "succeeded"),
+            None => log(&self.name, "failed"),
+        }
+
+        if let Some((program, args)) = self.notify.split_first() {
+            // the labels of all the runs, e.g. for the ticket in the notification
+            let labels: Map<String, String> = record
+                .runs
+                .iter()
+                .flat_map(|run| run.labels.clone())
+                .collect();
+            let status = Command::new(program)
+                .args(args)
+                .env("TIRON_SCHEDULE", &self.name)
+                .env("TIRON_RUN_ID", record.id.to_string())
+                .env("TIRON_SUCCESS", record.success.to_string())
+                .env("TIRON_ERROR", error.unwrap_or_default())
+                .env("TIRON_LABELS", format_labels(&labels))
+                .status();
+            if let Err(e) = status {
+                log(&self.name, &format!("can't run notify command: {e}"));
+            }
+        }
+    }
+}
+
+pub fn string_list(origin: &Origin, v: &SpannedValue, name: &str) -> Result<Vec<String>, Error> {
+    let SpannedValue::Array(list) = v else {
+        return origin
+            .error(format!("{name} should be a list of strings"), v.span())
+            .err();
+    };
+    let mut items = Vec::new();
+    for item in list.value() {
+        let SpannedValue::String(s) = item else {
+            return origin
+                .error(format!("{name} should be a list of strings"), item.span())
+                .err();
+        };
+        items.push(s.value().to_string());
+    }
+    Ok(items)
+}
+
+fn log(name: &str, message: &str) {
+    let time = UtcTime::from_unix(now());
+    println!(
+        "{:02}-{:02} {:02}:{:02} UTC [{name}] {message}",
+        time.month, time.day, time.hour, time.minute
+    );
+}
+
+/// Run the schedules in the schedule file until the process is stopped
+pub fn schedule(file: String) -> Result<(), Error> {
+    let path = runbook_paths(&[file])
+        .pop()
+        .ok_or_else(|| Error::new("no schedule file"))?;
+    let cwd = path
+        .parent()
+        .ok_or_else(|| Error::new(format!("can't find parent for {}", path.to_string_lossy())))?
+        .to_path_buf();
+    let data = std::fs::read_to_string(&path).map_err(|e| {
+        Error::new(format!(
+            "can't read schedule file {} error: {e}",
+            path.to_string_lossy()
+        ))
+    })?;
+    let origin = Origin { cwd, path, data };
+
+    let body = hcl_edit::parser::parse_body(&origin.data)
+        .map_err(|e| Error::from_hcl(e, origin.path.clone()))?;
+    let mut schedules = Vec::new();
+    for structure in body.iter() {
+        if let Structure::Block(block) = structure {
+            if block.ident.as_str() == "schedule" {
+                schedules.push(Arc::new(Schedule::from_block(&origin, block)?));
+            }
+        }
+    }
+    if schedules.is_empty() {
+        return Error::new(format!(
+            "there's no schedule in {}",
+            origin.path.to_string_lossy()
+        ))
+        .err();
+    }
+
+    // check the runbooks up front so that mistakes show up now rather than at the first run
+    for schedule in &schedules {
+        load_runs(&schedule.runbooks, true)?;
+        log(&schedule.name, "scheduled");
+    }
+
+    loop {
+        let now = now();
+        let next_minute = (now / 60 + 1) * 60;
+        std::thread::sleep(Duration::from_secs(next_minute - now));
+
+        let time = UtcTime::from_unix(next_minute);
+        for schedule in &schedules {
+            if schedule.cron.matches(&time) {
+                schedule.trigger();
+            }
+        }
+    }
+}
+
+/// A cron expression with the five fields minute, hour, day of month, month
+/// and day of week, which are all in UTC
+pub(crate) struct Cron {
+    minute: u64,
+    hour: u64,
+    day: u64,
+    month: u64,
+    weekday: u64,
+    // cron matches either day or weekday if both of them are restricted
+    day_restricted: bool,
+    weekday_restricted: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "cron should have 5 fields: minute hour day month weekday, but it has {}",
+                fields.len()
+            ));
+        }
+
+        let weekday = parse_cron_field(fields[4], 0, 7)?;
+        // both 0 and 7 are Sunday
+        let weekday = if weekday & (1 << 7) != 0 {
+            (weekday | 1) & !(1 << 7)
+        } else {
+            weekday
+        };
+
+        Ok(Cron {
+            minute: parse_cron_field(fields[0], 0, 59)?,
+            hour: parse_cron_field(fields[1], 0, 23)?,
+            day: parse_cron_field(fields[2], 1, 31)?,
+            month: parse_cron_field(fields[3], 1, 12)?,
+            weekday,
+            day_restricted: fields[2] != "*",
+            weekday_restricted: fields[4] != "*",
+        })
+    }
+
+    pub fn matches(&self, time: &UtcTime) -> bool {
+        let day = self.day & (1 << time.day) != 0;
+        let weekday = self.weekday & (1 << time.weekday) != 0;
+        let day = if self.day_restricted && self.weekday_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        };
+
+        self.minute & (1 << time.minute) != 0
+            && self.hour & (1 << time.hour) != 0
+            && self.month & (1 << time.month) != 0
+            && day
+    }
+}
+
+/// Parse a cron field into a bit set of the values it matches.
+/// It supports `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n`, and lists separated by `,`
+fn parse_cron_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u64 = step
+                    .parse()
+                    .map_err(|_| format!("invalid step {step} in cron field {field}"))?;
+                if step == 0 {
+                    return Err(format!("step can't be 0 in cron field {field}"));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let parse_value = |v: &str| -> Result<u64, String> {
+            let v: u64 = v
+                .parse()
+                .map_err(|_| format!("invalid value {v} in cron field {field}"))?;
+            if v < min || v > max {
+                return Err(format!(
+                    "value {v} in cron field {field} should be between {min} and {max}"
+                ));
+            }
+            Ok(v)
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start)?, parse_value(end)?)
+        } else {
+            let v = parse_value(range)?;
+            (v, if step > 1 { max } else { v })
+        };
+        if start > end {
+            return Err(format!("invalid range {range} in cron field {field}"));
+        }
+
+        let mut v = start;
+        while v <= end {
+            bits |= 1 << v;
+            v += step;
+        }
+    }
+    Ok(bits)
+}
+
+/// The calendar fields of a UTC time that cron needs
+pub(crate) struct UtcTime {
+    pub minute: u64,
+    pub hour: u64,
+    pub day: u64,
+    pub month: u64,
+    pub year: u64,
+    // 0 is Sunday
+    pub weekday: u64,
+}
+
+impl UtcTime {
+    pub fn from_unix(secs: u64) -> Self {
+        let days = secs / 86400;
+        let secs_of_day = secs % 86400;
+
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4) % 7;
+
+        // the days to civil date algorithm from
+        // http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z % 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = era * 400 + yoe + if month <= 2 { 1 } else { 0 };
+
+        UtcTime {
+            minute: secs_of_day % 3600 / 60,
+            hour: secs_of_day / 3600,
+            day,
+            month,
+            year,
+            weekday,
+        }
+    }
+}
+
+impl Display for UtcTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{:02}-{:02} {:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute
+        )
+    }
+}
@@ -0,0 +1,35 @@
+use std::process::{Command, ExitCode};
+
+use itertools::Itertools;
+use tiron_common::error::Error;
+
+use crate::exec::{hcl_string, load_adhoc_run};
+
+/// Open an interactive shell on the host, which connects with the same settings
+/// as the runs, e.g. the user, the port, the ssh options and become.
+/// The host is from the groups and the inventory of the runbook.
+/// Returns the exit code of the shell
+pub fn shell(runbook: Option<String>, host: String) -> Result<ExitCode, Error> {
+    let run = load_adhoc_run(runbook, &format!("run {} {{}}\n", hcl_string(&host)))?;
+    let node = match run.hosts() {
+        [node] => node,
+        nodes => {
+            return Error::new(format!(
+                "{host} is a group of {} hosts, pick one of them: {}",
+                nodes.len(),
+                nodes.iter().map(|node| node.host.as_str()).join(", ")
+            ))
+            .err();
+        }
+    };
+
+    let cmd = node.shell_command();
+    let status = Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .status()
+        .map_err(|e| Error::new(format!("can't start {}: {e}", cmd[0])))?;
+    Ok(match status.code() {
+        Some(code) => ExitCode::from(code as u8),
+        None => ExitCode::FAILURE,
+    })
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tiron_common::{
+    action::{ActionId, ActionMessage, ActionOutputLevel},
+    error::{Error, ErrorLocation},
+    event::{Event, EventSink, RunEvent},
+};
+use uuid::Uuid;
+
+use crate::run::Run;
+
+/// How often the progress of sending a large action is logged
+const TRANSFER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Writes the events as plain log lines, for when there's no TUI
+pub struct LogSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+    runs: HashMap<Uuid, String>,
+    hosts: HashMap<Uuid, String>,
+    // the names of the actions, which can change when the host is connected
+    actions: Mutex<HashMap<ActionId, String>>,
+    // where the actions are in the runbooks, for pointing at them when they fail
+    locations: HashMap<ActionId, ErrorLocation>,
+    // the errors from the nodes of the actions that haven't finished
+    errors: Mutex<HashMap<ActionId, String>>,
+    // when the actions started to be sent, and when their progress was last logged
+    transfers: Mutex<HashMap<ActionId, (Instant, Instant)>>,
+    color: bool,
+}
+
+impl LogSink {
+    /// The names of the runs, hosts and actions in the log lines are from `runs`
+    pub fn new(writer: impl Write + Send + 'static, runs: &[Run]) -> Self {
+        let mut sink = Self {
+            writer: Mutex::new(Box::new(writer)),
+            runs: HashMap::new(),
+            hosts: HashMap::new(),
+            actions: Mutex::new(HashMap::new()),
+            locations: HashMap::new(),
+            errors: Mutex::new(HashMap::new()),
+            transfers: Mutex::new(HashMap::new()),
+            color: false,
+        };
+        let mut actions = HashMap::new();
+        for (i, run) in runs.iter().enumerate() {
+            let name = run
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("Run {}", i + 1));
+            sink.runs.insert(run.id, name);
+            for host in run.hosts() {
+                sink.hosts.insert(host.id, host.host.clone());
+                for action in &host.actions {
+                    actions.insert(action.id, action.name.clone());
+                    if let Some(location) = &action.location {
+                        sink.locations.insert(action.id, location.clone());
+                    }
+                }
+            }
+        }
+        sink.actions = Mutex::new(actions);
+        sink
+    }
+
+    /// Color the errors of the failed actions, e.g. when the writer is a terminal
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    fn line(&self, event: &Event) -> Option<String> {
+        let run = |id: &Uuid| self.runs.get(id).map(|s| s.as_str()).unwrap_or("");
+        let host = |id: &Uuid| self.hosts.get(id).map(|s| s.as_str()).unwrap_or("");
+        let action = |id: &ActionId| {
+            self.actions
+                .lock()
+                .ok()
+                .and_then(|actions| actions.get(id).cloned())
+                .unwrap_or_default()
+        };
+
+        let line = match event {
+            Event::Run(event) => match event {
+                RunEvent::RunStarted { id } => format!("{} started", run(id)),
+                RunEvent::RunCompleted { id, success } => format!(
+                    "{} {}",
+                    run(id),
+                    if *success { "succeeded" } else { "failed" }
+                ),
+                RunEvent::HostRetry {
+                    host: id, attempt, ..
+                } => format!("[{}] retrying, attempt {attempt}", host(id)),
+                RunEvent::HostPhase {
+                    host: id, phase, ..
+                } => format!("[{}] {phase}", host(id)),
+                RunEvent::HostActions { actions, .. } => {
+                    if let Ok(mut names) = self.actions.lock() {
+                        names.extend(actions.iter().cloned());
+                    }
+                    return None;
+                }
+            },
+            Event::Action { host: id, msg, .. } => {
+                let host = host(id);
+                match msg {
+                    ActionMessage::NodeStartFailed { reason, .. } => {
+                        format!("[{host}] can't start node: {reason}")
+                    }
+                    ActionMessage::ActionStarted { id } => {
+                        format!("[{host}] {} started", action(id))
+                    }
+                    ActionMessage::ActionOutputLine { id, content, level } => {
+                        if matches!(level, ActionOutputLevel::Error) {
+                            if let Ok(mut errors) = self.errors.lock() {
+                                let error = content.strip_prefix("error: ").unwrap_or(content);
+                                errors.insert(*id, error.to_string());
+                            }
+                        }
+                        format!("[{host}] {content}")
+                    }
+                    ActionMessage::ActionResult { id, success } => {
+                        let error = self.errors.lock().ok().and_then(|mut e| e.remove(id));
+                        match (success, self.locations.get(id)) {
+                            (false, Some(location)) => {
+                                self.failure(&format!("[{host}] {}", action(id)), error, location)
+                            }
+                            _ => format!(
+                                "[{host}] {} {}",
+                                action(id),
+                                if *success { "succeeded" } else { "failed" }
+                            ),
+                        }
+                    }
+                    ActionMessage::ActionSkipped { id } => {
+                        format!("[{host}] {} skipped", action(id))
+                    }
+                    ActionMessage::ActionDryRun { id, change } => match change {
+                        Some(change) => format!("[{host}] {} would {change}", action(id)),
+                        None => format!("[{host}] {} wouldn't change anything", action(id)),
+                    },
+                    ActionMessage::ActionTransfer { id, sent, total } => {
+                        let progress = self.transfer_progress(*id, *sent, *total)?;
+                        format!("[{host}] {} {progress}", action(id))
+                    }
+                    ActionMessage::NodePanicked {
+                        message, backtrace, ..
+                    } => {
+                        format!("[{host}] node panicked: {message}\n{backtrace}")
+                    }
+                    ActionMessage::NodeShutdown { .. }
+                    | ActionMessage::ActionDebug { .. }
+                    | ActionMessage::ActionFetch { .. }
+                    | ActionMessage::ActionValue { .. }
+                    | ActionMessage::NodeFacts { .. }
+                    | ActionMessage::NodeLocked { .. }
+                    | ActionMessage::NodeSignatures { .. }
+                    | ActionMessage::NodeCached { .. }
+                    | ActionMessage::NodeFingerprint { .. } => return None,
+                }
+            }
+        };
+        Some(line)
+    }
+
+    /// The failure of the action with the block of the action in the runbook
+    fn failure(&self, action: &str, error: Option<String>, location: &ErrorLocation) -> String {
+        let message = match error {
+            Some(error) => format!("{action} failed: {error}"),
+            None => format!("{action} failed"),
+        };
+        let mut error = Error::new(message);
+        error.location = Some(location.clone());
+        let mut out = Vec::new();
+        let _ = error.write_report(&mut out, self.color);
+        String::from_utf8_lossy(&out).trim_end().to_string()
+    }
+
+    /// The progress of sending the action every `TRANSFER_INTERVAL`, and when it's sent
+    /// if that took longer than the interval, so that a slow transfer doesn't look stuck
+    fn transfer_progress(&self, id: ActionId, sent: u64, total: u64) -> Option<String> {
+        let mut transfers = self.transfers.lock().ok()?;
+        let now = Instant::now();
+        let (started, logged) = transfers.entry(id).or_insert((now, now));
+        let elapsed = now.duration_since(*started);
+        if sent >= total {
+            transfers.remove(&id);
+            return (elapsed >= TRANSFER_INTERVAL)
+                .then(|| format!("sent {} in {}", format_bytes(total), format_secs(elapsed)));
+        }
+        if now.duration_since(*logged) < TRANSFER_INTERVAL {
+            return None;
+        }
+        *logged = now;
+        Some(format_transfer(sent, total, elapsed))
+    }
+}
+
+/// The percentage, the rate and the estimated time left of a transfer
+fn format_transfer(sent: u64, total: u64, elapsed: Duration) -> String {
+    let rate = sent as f64 / elapsed.as_secs_f64().max(0.001);
+    let left = Duration::from_secs_f64((total - sent) as f64 / rate.max(1.0));
+    format!(
+        "sending {}% ({} of {}) at {}/s, {} left",
+        sent * 100 / total.max(1),
+        format_bytes(sent),
+        format_bytes(total),
+        format_bytes(rate as u64),
+        format_secs(left),
+    )
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+fn format_secs(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{secs}s")
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+impl EventSink for LogSink {
+    fn send(&self, event: Event) {
+        if let Some(line) = self.line(&event) {
+            if let Ok(mut writer) = self.writer.lock() {
+                let _ = writeln!(writer, "{line}");
+            }
+        }
+    }
+}
+
+/// Writes the events as JSON, one event per line
+pub struct JsonSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonSink {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+}
+
+impl EventSink for JsonSink {
+    fn send(&self, event: Event) {
+        let Ok(json) = serde_json::to_string(&event) else {
+            return;
+        };
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{json}");
+            let _ = writer.flush();
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, sync::Arc};
+
+use hcl::eval::Context;
+use hcl_edit::{
+    structure::{Block, BlockLabel, Structure},
+    Span,
+};
+use tiron_common::{
+    error::Error,
+    event::{EventSink, NullSink},
+    value::SpannedValue,
+};
+
+use crate::{
+    core::{execute_runs, load_runs, runbook_paths},
+    node::Node,
+    run::{Run, RunResult},
+    runbook::Runbook,
+    schedule::string_list,
+};
+
+/// A test of runbooks in disposable containers, which converges the containers
+/// with the runbooks and then runs the verify actions on them
+struct TestCase<'a> {
+    name: String,
+    engine: String,
+    images: Vec<String>,
+    runbooks: Vec<String>,
+    verify: Option<&'a Block>,
+}
+
+impl<'a> TestCase<'a> {
+    fn from_block(runbook: &Runbook, block: &'a Block) -> Result<Self, Error> {
+        let origin = &runbook.origin;
+        if block.labels.len() != 1 {
+            return origin
+                .error("test should have one name", &block.ident.span())
+                .err();
+        }
+        let BlockLabel::String(name) = &block.labels[0] else {
+            return origin
+                .error("test name should be a string", &block.labels[0].span())
+                .err();
+        };
+
+        let mut test = TestCase {
+            name: name.as_str().to_string(),
+            engine: "docker".to_string(),
+            images: Vec::new(),
+            runbooks: vec!["main".to_string()],
+            verify: None,
+        };
+
+        let ctx = Context::new();
+        for structure in block.body.iter() {
+            match structure {
+                Structure::Attribute(a) => {
+                    let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+                    match a.key.as_str() {
+                        "engine" => {
+                            let SpannedValue::String(s) = &v else {
+                                return origin.error("engine should be a string", v.span()).err();
+                            };
+                            test.engine = s.value().to_string();
+                        }
+                        "images" => {
+                            test.images = string_list(origin, &v, "images")?;
+                        }
+                        "runbooks" => {
+                            test.runbooks = string_list(origin, &v, "runbooks")?;
+                        }
+                        _ => {}
+                    }
+                }
+                Structure::Block(b) => {
+                    if b.ident.as_str() == "verify" {
+                        test.verify = Some(b);
+                    }
+                }
+            }
+        }
+
+        if test.images.is_empty() {
+            return origin
+                .error("test doesn't have any images", &block.ident.span())
+                .err();
+        }
+
+        Ok(test)
+    }
+
+    /// Run the test in containers of the image, and returns whether it passed
+    fn execute(
+        &self,
+        runbook: &Runbook,
+        image: &str,
+        events: &Arc<dyn EventSink>,
+    ) -> Result<bool, Error> {
+        let mut runs = load_runs(&runbook_paths(&self.runbooks), false)?;
+
+        // the containers are removed when they are dropped at the end of the test
+        let mut containers = HashMap::new();
+        for run in runs.iter_mut() {
+            run.use_containers(&self.engine, image, &mut containers)
+                .map_err(|e| Error::new(e.to_string()))?;
+            // the changes only go to the containers, so there's nothing to approve
+            // and the protected hosts aren't touched
+            run.approved = true;
+            run.protected_confirmed = true;
+        }
+
+        let mut hosts: Vec<Node> = Vec::new();
+        for run in &runs {
+            for host in run.hosts() {
+                if !hosts.iter().any(|h| h.host == host.host) {
+                    hosts.push(host.clone());
+                }
+            }
+        }
+
+        let results = execute_runs(runs, events).map_err(|e| Error::new(e.to_string()))?;
+        if !report("converge", &results) {
+            return Ok(false);
+        }
+
+        if let Some(verify) = self.verify {
+            let run = Run::from_block(runbook, verify, hosts)?;
+            let results = execute_runs(vec![run], events).map_err(|e| Error::new(e.to_string()))?;
+            if !report("verify", &results) {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+fn report(stage: &str, results: &[RunResult]) -> bool {
+    let failed: Vec<&str> = results
+        .iter()
+        .flat_map(|r| r.hosts.iter())
+        .filter(|h| !h.success)
+        .map(|h| h.host.as_str())
+        .collect();
+    if results.iter().all(|r| r.success) {
+        println!("  {stage}: ok");
+        true
+    } else {
+        println!("  {stage}: failed on {}", failed.join(", "));
+        false
+    }
+}
+
+/// Run the tests in the test file
+pub fn test(file: String) -> Result<(), Error> {
+    let path = runbook_paths(&[file])
+        .pop()
+        .ok_or_else(|| Error::new("no test file"))?;
+
+    // there's no TUI for the tests, so the events are dropped
+    let events: Arc<dyn EventSink> = Arc::new(NullSink);
+    let mut runbook = Runbook::new(path, 0)?;
+    // the test file can have its own groups and jobs for the verify actions
+    runbook.parse(false)?;
+
+    let body = hcl_edit::parser::parse_body(&runbook.origin.data)
+        .map_err(|e| Error::from_hcl(e, runbook.origin.path.clone()))?;
+    let mut tests = Vec::new();
+    for structure in body.iter() {
+        if let Structure::Block(block) = structure {
+            if block.ident.as_str() == "test" {
+                tests.push(TestCase::from_block(&runbook, block)?);
+            }
+        }
+    }
+    if tests.is_empty() {
+        return Error::new(format!(
+            "there's no test in {}",
+            runbook.origin.path.to_string_lossy()
+        ))
+        .err();
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for test in &tests {
+        for image in &test.images {
+            println!("test {} on {image}", test.name);
+            match test.execute(&runbook, image, &events) {
+                Ok(true) => passed += 1,
+                Ok(false) => failed += 1,
+                Err(e) => {
+                    println!("  error: {}", e.message);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    println!("\n{passed} passed, {failed} failed");
+    if failed > 0 {
+        return Error::new("tests failed").err();
+    }
+    Ok(())
+}
@@ -0,0 +1,466 @@
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Write},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use tiron_common::{
+    action::{ActionId, ActionMessage},
+    crypto::hex,
+    event::{Event, EventSink, HostPhase, RunEvent},
+};
+use uuid::Uuid;
+
+use crate::run::Run;
+
+/// Records the runs as traces from the events, with a span for every run, host,
+/// phase of connecting to the host, e.g. bootstrapping the node, and action.
+/// The trace of a run is exported to the OTLP/HTTP endpoint when the run completes.
+pub struct TraceSink {
+    endpoint: String,
+    tracer: Mutex<Tracer>,
+}
+
+impl TraceSink {
+    /// The names of the runs, hosts and actions in the spans are from `runs`
+    pub fn new(endpoint: String, runs: &[Run]) -> Self {
+        let mut tracer = Tracer::default();
+        for (i, run) in runs.iter().enumerate() {
+            let name = run
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("Run {}", i + 1));
+            tracer.names.insert(run.id, name);
+            for host in run.hosts() {
+                tracer.names.insert(host.id, host.host.clone());
+                for action in &host.actions {
+                    tracer.action_names.insert(action.id, action.name.clone());
+                }
+            }
+        }
+        Self {
+            endpoint,
+            tracer: Mutex::new(tracer),
+        }
+    }
+}
+
+impl EventSink for TraceSink {
+    fn send(&self, event: Event) {
+        let Ok(mut tracer) = self.tracer.lock() else {
+            return;
+        };
+        if let Some(trace) = tracer.handle(&event, now_nanos()) {
+            drop(tracer);
+            // the trace is only for analyzing the runs later, so failing to export it
+            // doesn't fail the run, and it's only reported when there's no TUI
+            if let Err(e) = export(&self.endpoint, &trace) {
+                if !std::io::stdout().is_terminal() {
+                    eprintln!("can't export the trace: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Span {
+    id: [u8; 8],
+    parent: Option<[u8; 8]>,
+    name: String,
+    start: u128,
+    end: Option<u128>,
+    attributes: Vec<(&'static str, Value)>,
+    error: bool,
+}
+
+impl Span {
+    fn new(parent: Option<[u8; 8]>, name: String, start: u128) -> Self {
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+        Self {
+            id,
+            parent,
+            name,
+            start,
+            end: None,
+            attributes: Vec::new(),
+            error: false,
+        }
+    }
+
+    fn end(&mut self, time: u128, error: bool) {
+        if self.end.is_none() {
+            self.end = Some(time);
+            self.error = error;
+        }
+    }
+}
+
+/// The spans of a run that's in progress
+struct RunTrace {
+    trace_id: [u8; 16],
+    run: Span,
+    hosts: HashMap<Uuid, Span>,
+    // the current phase of the hosts
+    phases: HashMap<Uuid, Span>,
+    actions: HashMap<ActionId, Span>,
+    finished: Vec<Span>,
+}
+
+#[derive(Default)]
+struct Tracer {
+    names: HashMap<Uuid, String>,
+    action_names: HashMap<ActionId, String>,
+    runs: HashMap<Uuid, RunTrace>,
+}
+
+impl Tracer {
+    /// Record the event, and returns the trace of the run in OTLP JSON
+    /// when the run completes
+    fn handle(&mut self, event: &Event, time: u128) -> Option<Value> {
+        match event {
+            Event::Run(RunEvent::RunStarted { id }) => {
+                let name = self
+                    .names
+                    .get(id)
+                    .cloned()
+                    .unwrap_or_else(|| id.to_string());
+                let mut run = Span::new(None, name, time);
+                run.attributes.push(("tiron.run_id", json!(id.to_string())));
+                self.runs.insert(
+                    *id,
+                    RunTrace {
+                        trace_id: *Uuid::new_v4().as_bytes(),
+                        run,
+                        hosts: HashMap::new(),
+                        phases: HashMap::new(),
+                        actions: HashMap::new(),
+                        finished: Vec::new(),
+                    },
+                );
+                None
+            }
+            Event::Run(RunEvent::RunCompleted { id, success }) => {
+                let mut trace = self.runs.remove(id)?;
+                let unfinished = trace
+                    .actions
+                    .drain()
+                    .map(|(_, span)| span)
+                    .chain(trace.phases.drain().map(|(_, span)| span))
+                    .chain(trace.hosts.drain().map(|(_, span)| span));
+                for mut span in unfinished.collect::<Vec<_>>() {
+                    span.end(time, !success);
+                    trace.finished.push(span);
+                }
+                trace.run.end(time, !success);
+                trace.finished.push(trace.run.clone());
+                Some(otlp_json(&trace))
+            }
+            Event::Run(RunEvent::HostPhase { run, host, phase }) => {
+                let name = self.host_name(host);
+                let trace = self.runs.get_mut(run)?;
+                let host_span = host_span(trace, *host, name, time);
+                if let Some(mut span) = trace.phases.remove(host) {
+                    span.end(time, false);
+                    trace.finished.push(span);
+                }
+                // running is covered by the action spans
+                if !matches!(phase, HostPhase::Running | HostPhase::Skipped) {
+                    let span = Span::new(Some(host_span), phase.to_string(), time);
+                    trace.phases.insert(*host, span);
+                }
+                if *phase == HostPhase::Skipped {
+                    if let Some(span) = trace.hosts.get_mut(host) {
+                        span.attributes.push(("tiron.host.skipped", json!(true)));
+                    }
+                    end_host(trace, *host, time, false, None);
+                }
+                None
+            }
+            Event::Run(RunEvent::HostRetry { run, host, attempt }) => {
+                let name = self.host_name(host);
+                let trace = self.runs.get_mut(run)?;
+                if let Some(mut span) = trace.phases.remove(host) {
+                    span.end(time, true);
+                    trace.finished.push(span);
+                }
+                // the retry is a new span of the host
+                if let Some(mut span) = trace.hosts.remove(host) {
+                    span.end(time, true);
+                    trace.finished.push(span);
+                }
+                host_span(trace, *host, name, time);
+                if let Some(span) = trace.hosts.get_mut(host) {
+                    span.attributes.push(("tiron.host.attempt", json!(attempt)));
+                }
+                None
+            }
+            Event::Run(RunEvent::HostActions { actions, .. }) => {
+                self.action_names.extend(actions.iter().cloned());
+                None
+            }
+            Event::Action { run, host, msg } => {
+                let name = self.host_name(host);
+                let action_name = |id: &ActionId| {
+                    self.action_names
+                        .get(id)
+                        .cloned()
+                        .unwrap_or_else(|| "action".to_string())
+                };
+                let trace = self.runs.get_mut(run)?;
+                let host_id = *host;
+                let parent = host_span(trace, host_id, name, time);
+                match msg {
+                    ActionMessage::ActionStarted { id } => {
+                        if let Some(mut span) = trace.phases.remove(&host_id) {
+                            span.end(time, false);
+                            trace.finished.push(span);
+                        }
+                        let span = Span::new(Some(parent), action_name(id), time);
+                        trace.actions.insert(*id, span);
+                    }
+                    ActionMessage::ActionResult { id, success } => {
+                        if let Some(mut span) = trace.actions.remove(id) {
+                            span.end(time, !success);
+                            trace.finished.push(span);
+                        }
+                    }
+                    ActionMessage::ActionSkipped { id } => {
+                        let mut span = Span::new(Some(parent), action_name(id), time);
+                        span.attributes.push(("tiron.action.skipped", json!(true)));
+                        span.end(time, false);
+                        trace.finished.push(span);
+                    }
+                    ActionMessage::NodeShutdown { success } => {
+                        end_host(trace, host_id, time, !success, None);
+                    }
+                    ActionMessage::NodeStartFailed { reason, .. } => {
+                        end_host(trace, host_id, time, true, Some(reason));
+                    }
+                    ActionMessage::NodePanicked { message, .. } => {
+                        end_host(trace, host_id, time, true, Some(message));
+                    }
+                    _ => {}
+                }
+                None
+            }
+        }
+    }
+
+    fn host_name(&self, host: &Uuid) -> String {
+        self.names
+            .get(host)
+            .cloned()
+            .unwrap_or_else(|| host.to_string())
+    }
+}
+
+/// The span of the host, which is started by the first event of the host
+fn host_span(trace: &mut RunTrace, host: Uuid, name: String, time: u128) -> [u8; 8] {
+    let parent = trace.run.id;
+    trace
+        .hosts
+        .entry(host)
+        .or_insert_with(|| {
+            let mut span = Span::new(Some(parent), name.clone(), time);
+            span.attributes.push(("tiron.host", json!(name)));
+            span
+        })
+        .id
+}
+
+/// End the host with the spans in it
+fn end_host(trace: &mut RunTrace, host: Uuid, time: u128, error: bool, reason: Option<&String>) {
+    if let Some(mut span) = trace.phases.remove(&host) {
+        span.end(time, error);
+        trace.finished.push(span);
+    }
+    if let Some(mut span) = trace.hosts.remove(&host) {
+        let host_span = span.id;
+        let actions: Vec<ActionId> = trace
+            .actions
+            .iter()
+            .filter(|(_, action)| action.parent == Some(host_span))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in actions {
+            if let Some(mut action) = trace.actions.remove(&id) {
+                action.end(time, error);
+                trace.finished.push(action);
+            }
+        }
+        if let Some(reason) = reason {
+            span.attributes.push(("tiron.error", json!(reason)));
+        }
+        span.end(time, error);
+        trace.finished.push(span);
+    }
+}
+
+/// The trace of the run in the JSON encoding of OTLP
+fn otlp_json(trace: &RunTrace) -> Value {
+    let spans: Vec<Value> = trace
+        .finished
+        .iter()
+        .map(|span| {
+            let attributes: Vec<Value> = span
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": otlp_value(value) }))
+                .collect();
+            let mut value = json!({
+                "traceId": hex(&trace.trace_id),
+                "spanId": hex(&span.id),
+                "name": span.name,
+                // internal
+                "kind": 1,
+                "startTimeUnixNano": span.start.to_string(),
+                "endTimeUnixNano": span.end.unwrap_or(span.start).to_string(),
+                "attributes": attributes,
+                // error or ok
+                "status": { "code": if span.error { 2 } else { 1 } },
+            });
+            if let Some(parent) = &span.parent {
+                value["parentSpanId"] = json!(hex(parent));
+            }
+            value
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "tiron" } },
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "tiron", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn otlp_value(value: &Value) -> Value {
+    match value {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) => json!({ "intValue": n.to_string() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        v => json!({ "stringValue": v.to_string() }),
+    }
+}
+
+/// Post the trace to the traces endpoint of the OTLP/HTTP collector
+fn export(endpoint: &str, trace: &Value) -> Result<()> {
+    let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+    let mut child = Command::new("curl")
+        .args([
+            "-sS",
+            "-f",
+            "--max-time",
+            "10",
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+            &url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(trace.to_string().as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_of_run() {
+        let run = Uuid::new_v4();
+        let host = Uuid::new_v4();
+        let action = ActionId::new();
+        let mut tracer = Tracer::default();
+        tracer.names.insert(run, "deploy".to_string());
+        tracer.names.insert(host, "web1".to_string());
+        tracer.action_names.insert(action, "copy".to_string());
+
+        let events = vec![
+            Event::Run(RunEvent::RunStarted { id: run }),
+            Event::Run(RunEvent::HostPhase {
+                run,
+                host,
+                phase: HostPhase::Bootstrapping,
+            }),
+            Event::Action {
+                run,
+                host,
+                msg: ActionMessage::ActionStarted { id: action },
+            },
+            Event::Action {
+                run,
+                host,
+                msg: ActionMessage::ActionResult {
+                    id: action,
+                    success: false,
+                },
+            },
+            Event::Action {
+                run,
+                host,
+                msg: ActionMessage::NodeShutdown { success: false },
+            },
+        ];
+        for (i, event) in events.iter().enumerate() {
+            assert!(tracer.handle(event, i as u128).is_none());
+        }
+        let trace = tracer
+            .handle(
+                &Event::Run(RunEvent::RunCompleted {
+                    id: run,
+                    success: false,
+                }),
+                10,
+            )
+            .unwrap();
+
+        let spans = trace["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        let span = |name: &str| spans.iter().find(|s| s["name"] == name).unwrap();
+        assert_eq!(spans.len(), 4);
+        assert!(span("deploy").get("parentSpanId").is_none());
+        assert_eq!(span("web1")["parentSpanId"], span("deploy")["spanId"]);
+        assert_eq!(
+            span("bootstrapping node")["parentSpanId"],
+            span("web1")["spanId"]
+        );
+        assert_eq!(span("bootstrapping node")["endTimeUnixNano"], "2");
+        assert_eq!(span("copy")["parentSpanId"], span("web1")["spanId"]);
+        assert_eq!(span("copy")["status"]["code"], 2);
+        assert_eq!(span("copy")["endTimeUnixNano"], "3");
+    }
+}
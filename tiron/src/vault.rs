@@ -0,0 +1,284 @@
+use std::{path::Path, sync::Mutex};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+};
+use tiron_common::{
+    crypto::{hex, unhex},
+    error::Error,
+};
+
+use crate::core::runbook_paths;
+
+/// The first line of an encrypted runbook, followed by the version of the format
+/// and the vault id, e.g. `$TIRON_VAULT;2.0;prod`
+const VAULT_HEADER: &str = "$TIRON_VAULT;";
+/// The version of the format, which decides the key derivation and the cipher
+const VAULT_VERSION: &str = "2.0";
+const DEFAULT_VAULT_ID: &str = "default";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The vault ids that the passwords were read for with their env vars,
+/// to warn about the ids that share an env var
+static VAULT_VARS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Whether the runbook is encrypted, which is decrypted with [`decrypt`]
+pub fn is_encrypted(data: &str) -> bool {
+    data.starts_with(VAULT_HEADER)
+}
+
+/// The vault id goes into the header and the name of the env var of its password,
+/// so it can only have ASCII letters, digits, `_` and `-`
+fn check_vault_id(vault_id: &str) -> Result<(), Error> {
+    let valid = !vault_id.is_empty()
+        && vault_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Error::new(format!(
+            "the vault id {vault_id:?} should only have ASCII letters, digits, _ and -"
+        ))
+        .err();
+    }
+    Ok(())
+}
+
+/// The password of the vault id from the environment, which is `TIRON_VAULT_PASSWORD`
+/// for the default vault id, or e.g. `TIRON_VAULT_PASSWORD_PROD` for the vault id `prod`
+fn vault_password(vault_id: &str) -> Result<String, Error> {
+    check_vault_id(vault_id)?;
+    let var = vault_var(vault_id);
+    if let Ok(mut vars) = VAULT_VARS.lock() {
+        let shared = vars
+            .iter()
+            .find(|(id, v)| *v == var && id != vault_id)
+            .map(|(id, _)| id.clone());
+        match shared {
+            Some(id) => eprintln!(
+                "warning: the vault ids {id} and {vault_id} both use {var}, so they share the password"
+            ),
+            None => vars.push((vault_id.to_string(), var.clone())),
+        }
+    }
+    std::env::var(&var)
+        .map_err(|_| Error::new(format!("{var} isn't set for the vault id {vault_id}")))
+}
+
+/// The env var of the password of the vault id, where the ids that only differ
+/// in the case or in `_` and `-` share the same one
+fn vault_var(vault_id: &str) -> String {
+    if vault_id == DEFAULT_VAULT_ID {
+        "TIRON_VAULT_PASSWORD".to_string()
+    } else {
+        let id: String = vault_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("TIRON_VAULT_PASSWORD_{id}")
+    }
+}
+
+/// The key of the cipher derived from the password with Argon2id
+fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32], Error> {
+    // the parameters recommended by OWASP, which are fixed by the version
+    let params = Params::new(19 * 1024, 2, 1, Some(32))
+        .map_err(|e| Error::new(format!("can't derive the key: {e}")))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::new(format!("can't derive the key: {e}")))?;
+    Ok(key)
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], Error> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| Error::new(format!("can't get random bytes: {e}")))?;
+    Ok(bytes)
+}
+
+/// Encrypt the runbook with ChaCha20-Poly1305, with the key derived
+/// from the password of the vault id
+pub fn encrypt(data: &str, vault_id: &str, password: &str) -> Result<String, Error> {
+    check_vault_id(vault_id)?;
+    let salt = random_bytes::<SALT_LEN>()?;
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let key = derive_key(password, &salt)?;
+
+    let header = format!("{VAULT_HEADER}{VAULT_VERSION};{vault_id}");
+    // the header is authenticated too, so that the vault id can't be changed
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: data.as_bytes(),
+                aad: header.as_bytes(),
+            },
+        )
+        .map_err(|_| Error::new("can't encrypt the runbook"))?;
+    let mut payload = salt.to_vec();
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&ciphertext);
+
+    let body = hex(&payload);
+    let mut encrypted = header;
+    encrypted.push('\n');
+    for line in body.as_bytes().chunks(80) {
+        encrypted.push_str(&String::from_utf8_lossy(line));
+        encrypted.push('\n');
+    }
+    Ok(encrypted)
+}
+
+/// Decrypt the encrypted runbook with the password of its vault id
+pub fn decrypt(data: &str) -> Result<String, Error> {
+    let (header, body) = data.split_once('\n').unwrap_or((data, ""));
+    let header = header.trim_end();
+    let (version, vault_id) = header
+        .strip_prefix(VAULT_HEADER)
+        .and_then(|rest| rest.split_once(';'))
+        .ok_or_else(|| Error::new("it isn't encrypted"))?;
+    if version != VAULT_VERSION {
+        return Error::new(format!(
+            "it's encrypted with version {version} of the vault, which isn't supported"
+        ))
+        .err();
+    }
+    let password = vault_password(vault_id)?;
+    decrypt_with(header, body, &password)
+}
+
+fn decrypt_with(header: &str, body: &str, password: &str) -> Result<String, Error> {
+    let body: String = body.split_whitespace().collect();
+    let payload = unhex(&body).ok_or_else(|| Error::new("the encrypted data is corrupted"))?;
+    if payload.len() < SALT_LEN + NONCE_LEN + TAG_LEN {
+        return Error::new("the encrypted data is corrupted").err();
+    }
+    let (salt, rest) = payload.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(password, salt)?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header.as_bytes(),
+            },
+        )
+        .map_err(|_| Error::new("the password is wrong, or the encrypted data was changed"))?;
+    String::from_utf8(plaintext).map_err(|_| Error::new("the decrypted runbook isn't UTF-8"))
+}
+
+/// Encrypt the runbooks in place
+pub fn encrypt_runbooks(runbooks: Vec<String>, vault_id: String) -> Result<(), Error> {
+    let password = vault_password(&vault_id)?;
+    for path in runbook_paths(&runbooks) {
+        let data = read(&path)?;
+        if is_encrypted(&data) {
+            return Error::new(format!("{} is encrypted already", path.to_string_lossy())).err();
+        }
+        write(&path, &encrypt(&data, &vault_id, &password)?)?;
+        println!("encrypted {}", path.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Decrypt the runbooks in place
+pub fn decrypt_runbooks(runbooks: Vec<String>) -> Result<(), Error> {
+    for path in runbook_paths(&runbooks) {
+        let data = read(&path)?;
+        if !is_encrypted(&data) {
+            return Error::new(format!("{} isn't encrypted", path.to_string_lossy())).err();
+        }
+        let data = decrypt(&data).map_err(|e| {
+            Error::new(format!(
+                "can't decrypt {}: {}",
+                path.to_string_lossy(),
+                e.message
+            ))
+        })?;
+        write(&path, &data)?;
+        println!("decrypted {}", path.to_string_lossy());
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|e| {
+        Error::new(format!(
+            "can't read runbook {} error: {e}",
+            path.to_string_lossy()
+        ))
+    })
+}
+
+fn write(path: &Path, data: &str) -> Result<(), Error> {
+    std::fs::write(path, data).map_err(|e| {
+        Error::new(format!(
+            "can't write runbook {} error: {e}",
+            path.to_string_lossy()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let runbook = "run \"production\" {\n  become = true\n}\n";
+        let encrypted = encrypt(runbook, "prod", "secret").ok().unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("production"));
+
+        let (header, body) = encrypted.split_once('\n').unwrap();
+        assert_eq!(header, "$TIRON_VAULT;2.0;prod");
+        assert_eq!(
+            decrypt_with(header, body, "secret").ok().as_deref(),
+            Some(runbook)
+        );
+        assert!(decrypt_with(header, body, "wrong").is_err());
+        // the vault id is signed with the rest
+        assert!(decrypt_with("$TIRON_VAULT;2.0;dev", body, "secret").is_err());
+
+        let e = decrypt(&encrypted.replacen("2.0", "1.0", 1)).err().unwrap();
+        assert_eq!(
+            e.message,
+            "it's encrypted with version 1.0 of the vault, which isn't supported"
+        );
+    }
+
+    #[test]
+    fn reject_vault_ids() {
+        for vault_id in ["", "prod;dev", "prod\n", "prod eu", "prød"] {
+            let e = encrypt("run {}", vault_id, "secret").err().unwrap();
+            assert_eq!(
+                e.message,
+                format!(
+                    "the vault id {vault_id:?} should only have ASCII letters, digits, _ and -"
+                )
+            );
+        }
+        // a header that was written by hand can't pick the env var either
+        let encrypted = encrypt("run {}", "prod", "secret").ok().unwrap();
+        let e = decrypt(&encrypted.replacen(";prod", ";prod;dev", 1))
+            .err()
+            .unwrap();
+        assert!(e.message.starts_with("the vault id \"prod;dev\""));
+
+        assert_eq!(vault_var("prod-eu"), "TIRON_VAULT_PASSWORD_PROD_EU");
+        assert_eq!(vault_var("default"), "TIRON_VAULT_PASSWORD");
+    }
+}
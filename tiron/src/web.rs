@@ -0,0 +1,460 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tiron_common::{
+    action::{retry_failed_output, ActionId, ActionMessage, ActionOutput, ActionOutputLine},
+    crypto::constant_time_eq,
+    event::{Event, EventSink, HostPhase, RunEvent},
+};
+use uuid::Uuid;
+
+use crate::run::Run;
+
+const INDEX_HTML: &str = include_str!("web.html");
+// the largest body that's read, which is checked before the token or the
+// signature, so the requests can't make us allocate whatever they ask for
+const MAX_BODY: usize = 1024 * 1024;
+// the most of the request line and the headers that's read
+const MAX_HEAD: u64 = 64 * 1024;
+// how long a client can take to send each part of its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The state of the runs shown in the web dashboard,
+/// which mirrors the run panels in the TUI
+#[derive(Serialize)]
+struct Dashboard {
+    runs: Vec<DashboardRun>,
+}
+
+#[derive(Serialize)]
+struct DashboardRun {
+    id: Uuid,
+    name: Option<String>,
+    started: bool,
+    success: Option<bool>,
+    hosts: Vec<DashboardHost>,
+}
+
+#[derive(Serialize)]
+struct DashboardHost {
+    id: Uuid,
+    host: String,
+    phase: Option<String>,
+    success: Option<bool>,
+    start_failed: Option<String>,
+    // the panic message and the backtrace if the node panicked
+    panicked: Option<(String, String)>,
+    actions: Vec<DashboardAction>,
+}
+
+#[derive(Serialize)]
+struct DashboardAction {
+    id: ActionId,
+    name: String,
+    output: ActionOutput,
+}
+
+impl Dashboard {
+    fn new(runs: &[Run]) -> Self {
+        let runs = runs
+            .iter()
+            .map(|run| {
+                let panel = run.to_panel();
+                DashboardRun {
+                    id: panel.id,
+                    name: panel.name,
+                    started: false,
+                    success: None,
+                    hosts: panel
+                        .hosts
+                        .into_iter()
+                        .map(|host| DashboardHost {
+                            id: host.id,
+                            host: host.host,
+                            phase: None,
+                            success: None,
+                            start_failed: None,
+                            panicked: None,
+                            actions: host
+                                .actions
+                                .into_iter()
+                                .map(|action| DashboardAction {
+                                    id: action.id,
+                                    name: action.name,
+                                    output: ActionOutput::default(),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        Self { runs }
+    }
+
+    fn get_host(&mut self, run: Uuid, host: Uuid) -> Option<(&mut DashboardRun, usize)> {
+        let run = self.runs.iter_mut().rev().find(|r| r.id == run)?;
+        let i = run.hosts.iter().rposition(|h| h.id == host)?;
+        Some((run, i))
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::Run(event) => self.handle_run_event(event),
+            Event::Action { run, host, msg } => {
+                if let Some((run, i)) = self.get_host(*run, *host) {
+                    let host = &mut run.hosts[i];
+                    host.handle_action_message(msg);
+                }
+            }
+        }
+    }
+
+    fn handle_run_event(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::RunStarted { id } => {
+                if let Some(run) = self.runs.iter_mut().rev().find(|r| r.id == *id) {
+                    run.started = true;
+                }
+            }
+            RunEvent::RunCompleted { id, success } => {
+                if let Some(run) = self.runs.iter_mut().rev().find(|r| r.id == *id) {
+                    run.success = Some(*success);
+                }
+            }
+            RunEvent::HostPhase { run, host, phase } => {
+                if let Some((run, i)) = self.get_host(*run, *host) {
+                    run.hosts[i].phase = Some(phase.to_string());
+                }
+            }
+            RunEvent::HostRetry { run, host, attempt } => {
+                if let Some((run, i)) = self.get_host(*run, *host) {
+                    run.hosts[i].retry(*attempt);
+                }
+            }
+            RunEvent::HostActions { run, host, actions } => {
+                if let Some((run, i)) = self.get_host(*run, *host) {
+                    run.hosts[i].actions = actions
+                        .iter()
+                        .map(|(id, name)| DashboardAction {
+                            id: *id,
+                            name: name.clone(),
+                            output: ActionOutput::default(),
+                        })
+                        .collect();
+                }
+            }
+        }
+    }
+}
+
+impl DashboardHost {
+    fn get_action(&mut self, id: ActionId) -> Option<&mut DashboardAction> {
+        self.actions.iter_mut().rev().find(|a| a.id == id)
+    }
+
+    fn handle_action_message(&mut self, msg: &ActionMessage) {
+        match msg {
+            ActionMessage::ActionStarted { id } => {
+                self.phase = Some(HostPhase::Running.to_string());
+                if let Some(action) = self.get_action(*id) {
+                    action.output.started = true;
+                }
+            }
+            ActionMessage::ActionOutputLine { id, content, level } => {
+                if let Some(action) = self.get_action(*id) {
+                    action.output.push_line(ActionOutputLine {
+                        content: content.clone(),
+                        level: level.clone(),
+                    });
+                }
+            }
+            ActionMessage::ActionResult { id, success } => {
+                if let Some(action) = self.get_action(*id) {
+                    action.output.success = Some(*success);
+                }
+            }
+            ActionMessage::ActionDryRun { .. }
+            | ActionMessage::ActionDebug { .. }
+            | ActionMessage::ActionTransfer { .. }
+            | ActionMessage::ActionFetch { .. }
+            | ActionMessage::ActionValue { .. }
+            | ActionMessage::ActionSkipped { .. }
+            | ActionMessage::NodeFacts { .. }
+            | ActionMessage::NodeLocked { .. }
+            | ActionMessage::NodeSignatures { .. }
+            | ActionMessage::NodeCached { .. }
+            | ActionMessage::NodeFingerprint { .. } => {}
+            ActionMessage::NodeShutdown { success } => {
+                self.success = Some(*success);
+            }
+            ActionMessage::NodeStartFailed { kind, reason } => {
+                self.start_failed = Some(format!("{kind}: {reason}"));
+                self.success = Some(false);
+            }
+            ActionMessage::NodePanicked {
+                id,
+                message,
+                backtrace,
+            } => {
+                if let Some(action) = id.and_then(|id| self.get_action(id)) {
+                    action.output.success = Some(false);
+                }
+                self.panicked = Some((message.clone(), backtrace.clone()));
+                self.success = Some(false);
+            }
+        }
+    }
+
+    fn retry(&mut self, attempt: usize) {
+        self.success = None;
+        self.start_failed = None;
+        self.panicked = None;
+        self.phase = None;
+        retry_failed_output(self.actions.iter_mut().map(|a| &mut a.output), attempt);
+    }
+}
+
+/// Updates the web dashboard with the run events
+struct DashboardSink {
+    dashboard: Arc<Mutex<Dashboard>>,
+}
+
+impl EventSink for DashboardSink {
+    fn send(&self, event: Event) {
+        if let Ok(mut dashboard) = self.dashboard.lock() {
+            dashboard.handle_event(&event);
+        }
+    }
+}
+
+/// A random token for the web dashboard, when it's not given in `TIRON_WEB_TOKEN`
+pub fn new_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// The address to bind the dashboard on. A port alone, e.g. `8080`, is on the
+/// loopback interface, so that the dashboard is only exposed when the address says so
+fn bind_addr(addr: &str) -> String {
+    let port = addr.strip_prefix(':').unwrap_or(addr);
+    if port.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{port}")
+    } else {
+        addr.to_string()
+    }
+}
+
+/// Start serving the web dashboard for the runs on the address, which only
+/// answers the requests with the token. Returns the url of the dashboard
+/// with the token, and the sink that the run events should be sent to.
+pub fn serve(addr: &str, token: &str, runs: &[Run]) -> Result<(String, Arc<dyn EventSink>)> {
+    let listener = TcpListener::bind(bind_addr(addr))?;
+    let url = format!("http://{}/?token={token}", listener.local_addr()?);
+    let dashboard = Arc::new(Mutex::new(Dashboard::new(runs)));
+
+    {
+        let dashboard = dashboard.clone();
+        let token = token.to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let dashboard = dashboard.clone();
+                let token = token.clone();
+                std::thread::spawn(move || {
+                    let _ = handle_request(stream, &dashboard, &token);
+                });
+            }
+        });
+    }
+
+    Ok((url, Arc::new(DashboardSink { dashboard })))
+}
+
+/// The token is in the query of the url, or in the `Authorization` header
+fn authorized(request: &HttpRequest, token: &str) -> bool {
+    let given = request.query("token").or_else(|| {
+        request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+    });
+    given.is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+fn handle_request(stream: TcpStream, dashboard: &Mutex<Dashboard>, token: &str) -> Result<()> {
+    let request = read_request(&stream)?;
+    if !authorized(&request, token) {
+        return write_response(&stream, "401 Unauthorized", "text/plain", "unauthorized");
+    }
+    let (status, content_type, body) = match request.route() {
+        "/" => ("200 OK", "text/html; charset=utf-8", INDEX_HTML.to_string()),
+        "/api/runs" => {
+            let body = {
+                let dashboard = dashboard
+                    .lock()
+                    .map_err(|_| anyhow!("dashboard lock poisoned"))?;
+                serde_json::to_string(&*dashboard)?
+            };
+            ("200 OK", "application/json", body)
+        }
+        _ => ("404 Not Found", "text/plain", "not found".to_string()),
+    };
+    write_response(&stream, status, content_type, &body)
+}
+
+/// A minimal HTTP request, which is all we need for the dashboard and the chat webhooks
+pub struct HttpRequest {
+    pub method: String,
+    // the path with the query
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// The value of the header, where the name isn't case sensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The path without the query
+    pub fn route(&self) -> &str {
+        self.path
+            .split_once('?')
+            .map_or(&self.path, |(path, _)| path)
+    }
+
+    /// The value of the parameter in the query, which isn't decoded
+    pub fn query(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.path.split_once('?')?;
+        query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+}
+
+/// Read the request from the stream. The request is rejected with 413 if its
+/// body is too large, and a client that stalls times out instead of holding
+/// the connection forever.
+pub fn read_request(stream: &TcpStream) -> Result<HttpRequest> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.take(MAX_HEAD));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut content_length = 0;
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse()?;
+            }
+            headers.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    if content_length > MAX_BODY {
+        let _ = write_response(stream, "413 Payload Too Large", "text/plain", "too large");
+        return Err(anyhow!(
+            "request body of {content_length} bytes is too large"
+        ));
+    }
+    // the buffered bytes after the headers are the start of the body
+    reader.get_mut().set_limit(content_length as u64);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    Ok(HttpRequest {
+        method: parts.next().unwrap_or("GET").to_string(),
+        path: parts.next().unwrap_or("/").to_string(),
+        headers,
+        body,
+    })
+}
+
+pub fn write_response(
+    mut stream: &TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dashboard_needs_token() {
+        assert_eq!(bind_addr("8080"), "127.0.0.1:8080");
+        assert_eq!(bind_addr(":8080"), "127.0.0.1:8080");
+        assert_eq!(bind_addr("0.0.0.0:8080"), "0.0.0.0:8080");
+
+        let req = request("/api/runs?token=abc", &[]);
+        assert_eq!(req.route(), "/api/runs");
+        assert!(authorized(&req, "abc"));
+        assert!(!authorized(&req, "abcd"));
+        assert!(!authorized(&request("/api/runs", &[]), "abc"));
+        assert!(authorized(
+            &request("/api/runs", &[("Authorization", "Bearer abc")]),
+            "abc"
+        ));
+    }
+
+    fn send(raw: &str) -> Result<HttpRequest> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(raw.as_bytes()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        read_request(&stream)
+    }
+
+    #[test]
+    fn request_body_is_limited() {
+        let req = send("POST /hook HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(req.route(), "/hook");
+        assert_eq!(req.body, b"hello");
+
+        let e = send("POST /hook HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.to_string(),
+            "request body of 99999999999 bytes is too large"
+        );
+    }
+}
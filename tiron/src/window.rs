@@ -0,0 +1,274 @@
+use hcl::eval::Context;
+use hcl_edit::{
+    structure::{Block, Structure},
+    Span,
+};
+use tiron_common::{
+    error::{Error, Origin},
+    value::SpannedValue,
+};
+
+use crate::schedule::{Cron, UtcTime};
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A maintenance window of a run, outside of which the run refuses to execute
+pub struct Window {
+    when: WindowTime,
+    // the offset of the timezone of the window from UTC in minutes
+    offset: i64,
+    // how the window was written, for the error
+    description: String,
+}
+
+enum WindowTime {
+    // the window is open in the minutes that the cron expression matches
+    Cron(Cron),
+    // the window is open from `start` to `end` in minutes of the day,
+    // which can go past midnight, on the days in the bit set with 0 as Sunday
+    Range { start: u64, end: u64, days: u64 },
+}
+
+impl Window {
+    pub fn from_block(origin: &Origin, block: &Block) -> Result<Self, Error> {
+        let ctx = Context::new();
+        let mut cron = None;
+        let mut start = None;
+        let mut end = None;
+        let mut days = None;
+        let mut timezone = None;
+        for structure in block.body.iter() {
+            let Structure::Attribute(a) = structure else {
+                continue;
+            };
+            let v = SpannedValue::from_expression(origin, &ctx, a.value.to_owned())?;
+            match a.key.as_str() {
+                "cron" => cron = Some(string(origin, &v, "cron")?),
+                "start" => start = Some(time_of_day(origin, &v, "start")?),
+                "end" => end = Some(time_of_day(origin, &v, "end")?),
+                "days" => days = Some(weekdays(origin, &v)?),
+                "timezone" => {
+                    let tz = string(origin, &v, "timezone")?;
+                    let offset = parse_offset(&tz).ok_or_else(|| {
+                        origin.error(
+                            "timezone should be UTC or an offset from it, e.g. +02:00",
+                            v.span(),
+                        )
+                    })?;
+                    timezone = Some((tz, offset));
+                }
+                _ => {}
+            }
+        }
+
+        let (when, description) = match (cron, start, end) {
+            (Some(expr), None, None) => {
+                let cron = Cron::parse(&expr).map_err(|e| origin.error(e, &block.ident.span()))?;
+                if days.is_some() {
+                    return origin
+                        .error(
+                            "days can't be used with cron, put them in the cron expression",
+                            &block.ident.span(),
+                        )
+                        .err();
+                }
+                (WindowTime::Cron(cron), format!("cron \"{expr}\""))
+            }
+            (None, Some(start), Some(end)) => {
+                let days_description = match &days {
+                    Some(days) => format!(" on {}", days.1.join(", ")),
+                    None => String::new(),
+                };
+                (
+                    WindowTime::Range {
+                        start: start.0,
+                        end: end.0,
+                        days: days.map(|days| days.0).unwrap_or(0b111_1111),
+                    },
+                    format!("{} to {}{days_description}", start.1, end.1),
+                )
+            }
+            _ => {
+                return origin
+                    .error(
+                        "window should have either cron, or start and end",
+                        &block.ident.span(),
+                    )
+                    .err();
+            }
+        };
+
+        let (tz, offset) = timezone.unwrap_or_else(|| ("UTC".to_string(), 0));
+        Ok(Window {
+            when,
+            offset,
+            description: format!("{description} {tz}"),
+        })
+    }
+
+    /// Whether the window is open at the unix timestamp in seconds
+    pub fn is_open(&self, now: u64) -> bool {
+        let local = (now as i64 + self.offset * 60).max(0) as u64;
+        let time = UtcTime::from_unix(local);
+        match &self.when {
+            WindowTime::Cron(cron) => cron.matches(&time),
+            WindowTime::Range { start, end, days } => {
+                let minute = time.hour * 60 + time.minute;
+                if start <= end {
+                    days & (1 << time.weekday) != 0 && *start <= minute && minute < *end
+                } else if minute >= *start {
+                    days & (1 << time.weekday) != 0
+                } else if minute < *end {
+                    // it's the part after midnight of the window opened the day before
+                    days & (1 << ((time.weekday + 6) % 7)) != 0
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+fn string(origin: &Origin, v: &SpannedValue, name: &str) -> Result<String, Error> {
+    let SpannedValue::String(s) = v else {
+        return origin
+            .error(format!("{name} should be a string"), v.span())
+            .err();
+    };
+    Ok(s.value().to_string())
+}
+
+/// Parse a time of the day like `22:30` into the minutes of the day
+fn time_of_day(origin: &Origin, v: &SpannedValue, name: &str) -> Result<(u64, String), Error> {
+    let s = string(origin, v, name)?;
+    let minutes = s.split_once(':').and_then(|(hour, minute)| {
+        let hour: u64 = hour.parse().ok().filter(|h| *h < 24)?;
+        let minute: u64 = minute.parse().ok().filter(|m| *m < 60)?;
+        Some(hour * 60 + minute)
+    });
+    let minutes = minutes.ok_or_else(|| {
+        origin.error(
+            format!("{name} should be a time of the day, e.g. 22:30"),
+            v.span(),
+        )
+    })?;
+    Ok((minutes, s))
+}
+
+/// Parse a list of weekdays like `["sat", "sun"]` into a bit set with 0 as Sunday
+fn weekdays(origin: &Origin, v: &SpannedValue) -> Result<(u64, Vec<String>), Error> {
+    let error = || {
+        origin.error(
+            "days should be a list of weekdays, e.g. [\"sat\", \"sun\"]",
+            v.span(),
+        )
+    };
+    let SpannedValue::Array(list) = v else {
+        return Err(error());
+    };
+    let mut days = 0;
+    let mut names = Vec::new();
+    for item in list.value() {
+        let SpannedValue::String(s) = item else {
+            return Err(error());
+        };
+        let name = s.value().to_lowercase();
+        let day = WEEKDAYS
+            .iter()
+            .position(|d| name.starts_with(d))
+            .ok_or_else(error)?;
+        days |= 1 << day;
+        names.push(s.value().to_string());
+    }
+    Ok((days, names))
+}
+
+/// Parse a timezone of `UTC` or an offset from it like `+02:00` or `-0530` into minutes
+fn parse_offset(tz: &str) -> Option<i64> {
+    let tz = tz.trim();
+    if tz.eq_ignore_ascii_case("utc") || tz.eq_ignore_ascii_case("z") {
+        return Some(0);
+    }
+    let tz = tz
+        .strip_prefix("UTC")
+        .or_else(|| tz.strip_prefix("utc"))
+        .unwrap_or(tz);
+    let (sign, rest) = if let Some(rest) = tz.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = tz.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        return None;
+    };
+    let (hour, minute) = match rest.split_once(':') {
+        Some((hour, minute)) => (hour, minute),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hour: i64 = hour.parse().ok().filter(|h| *h <= 14)?;
+    let minute: i64 = minute.parse().ok().filter(|m| *m < 60)?;
+    Some(sign * (hour * 60 + minute))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn window(hcl: &str) -> Window {
+        let origin = Origin {
+            cwd: std::path::PathBuf::from("."),
+            path: std::path::PathBuf::from("main.tr"),
+            data: hcl.to_string(),
+        };
+        let body = hcl_edit::parser::parse_body(hcl).unwrap();
+        let block = body.iter().find_map(|s| s.as_block()).unwrap();
+        match Window::from_block(&origin, block) {
+            Ok(window) => window,
+            Err(e) => panic!("window should be parsed, but got error: {}", e.message),
+        }
+    }
+
+    // 2024-05-04 is a Saturday
+    const SATURDAY: u64 = 1714780800;
+
+    #[test]
+    fn range_past_midnight() {
+        let window = window(
+            r#"
+            window {
+                start = "22:00"
+                end   = "04:00"
+                days  = ["sat"]
+            }
+            "#,
+        );
+        assert!(!window.is_open(SATURDAY + 21 * 3600));
+        assert!(window.is_open(SATURDAY + 23 * 3600));
+        // early Sunday is still in the window opened on Saturday
+        assert!(window.is_open(SATURDAY + 26 * 3600));
+        assert!(!window.is_open(SATURDAY + 28 * 3600));
+        // early Saturday is from the window of Friday
+        assert!(!window.is_open(SATURDAY + 3600));
+    }
+
+    #[test]
+    fn timezone_offset() {
+        let window = window(
+            r#"
+            window {
+                cron     = "* 2-3 * * *"
+                timezone = "+02:00"
+            }
+            "#,
+        );
+        assert!(window.is_open(SATURDAY));
+        assert!(!window.is_open(SATURDAY + 2 * 3600));
+        assert_eq!(parse_offset("-0530"), Some(-330));
+        assert_eq!(parse_offset("UTC+8"), Some(480));
+        assert_eq!(parse_offset("CET"), None);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use yaml_rust2::{
+    parser::{Event, EventReceiver, Parser},
+    scanner::TScalarStyle,
+};
+
+/// A YAML value, with the scalars kept as strings
+#[derive(Debug, Clone, PartialEq)]
+pub enum Yaml {
+    Null,
+    String(String),
+    List(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Yaml::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.as_str()? {
+            "true" | "True" | "TRUE" | "yes" | "Yes" | "YES" | "on" | "On" => Some(true),
+            "false" | "False" | "FALSE" | "no" | "No" | "NO" | "off" | "Off" => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(map) => map.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the first document of the YAML. The scalars are kept as they're written,
+/// e.g. the mode `0644`, which the numbers and booleans in YAML would lose
+pub fn parse_yaml(data: &str) -> Result<Yaml, String> {
+    let mut builder = Builder::default();
+    Parser::new_from_str(data)
+        .load(&mut builder, false)
+        .map_err(|e| e.to_string())?;
+    if let Some(error) = builder.error {
+        return Err(error);
+    }
+    Ok(builder.doc.unwrap_or(Yaml::Null))
+}
+
+enum Collection {
+    List(Vec<Yaml>),
+    // the entries, and the key that's waiting for its value
+    Map(Vec<(String, Yaml)>, Option<String>),
+}
+
+/// Builds the value from the events of the YAML parser
+#[derive(Default)]
+struct Builder {
+    // the collections that are open, with their anchors
+    stack: Vec<(Collection, usize)>,
+    anchors: HashMap<usize, Yaml>,
+    doc: Option<Yaml>,
+    error: Option<String>,
+}
+
+impl Builder {
+    /// Put the finished value into the collection it's in
+    fn insert(&mut self, value: Yaml, anchor: usize) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        match self.stack.last_mut() {
+            None => self.doc = Some(value),
+            Some((Collection::List(items), _)) => items.push(value),
+            Some((Collection::Map(entries, key), _)) => match key.take() {
+                Some(key) => entries.push((key, value)),
+                None => match value {
+                    Yaml::String(s) => *key = Some(s),
+                    Yaml::Null => *key = Some(String::new()),
+                    _ => {
+                        self.error
+                            .get_or_insert_with(|| "complex keys aren't supported".to_string());
+                        *key = Some(String::new());
+                    }
+                },
+            },
+        }
+    }
+}
+
+impl EventReceiver for Builder {
+    fn on_event(&mut self, ev: Event) {
+        match ev {
+            Event::Scalar(value, style, anchor, ..) => {
+                let null = style == TScalarStyle::Plain
+                    && matches!(value.as_str(), "" | "~" | "null" | "Null" | "NULL");
+                let value = if null {
+                    Yaml::Null
+                } else {
+                    Yaml::String(value)
+                };
+                self.insert(value, anchor);
+            }
+            Event::SequenceStart(anchor, ..) => {
+                self.stack.push((Collection::List(Vec::new()), anchor));
+            }
+            Event::MappingStart(anchor, ..) => {
+                self.stack.push((Collection::Map(Vec::new(), None), anchor));
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some((collection, anchor)) = self.stack.pop() {
+                    let value = match collection {
+                        Collection::List(items) => Yaml::List(items),
+                        Collection::Map(entries, _) => Yaml::Map(entries),
+                    };
+                    self.insert(value, anchor);
+                }
+            }
+            Event::Alias(anchor) => {
+                let value = self.anchors.get(&anchor).cloned().unwrap_or(Yaml::Null);
+                self.insert(value, 0);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn string(s: &str) -> Yaml {
+        Yaml::String(s.to_string())
+    }
+
+    #[test]
+    fn parse_playbook() {
+        let yaml = parse_yaml(
+            r#"
+---
+- hosts: webservers   # the web tier
+  become: yes
+  tasks:
+  - name: install nginx
+    apt:
+      name: [nginx, "curl"]
+      state: present
+  - name: write motd
+    copy:
+      dest: /etc/motd
+      content: |
+        hello
+        world
+"#,
+        )
+        .unwrap();
+        let play = match &yaml {
+            Yaml::List(plays) => &plays[0],
+            _ => panic!("playbook should be a list"),
+        };
+        assert_eq!(play.get("hosts"), Some(&string("webservers")));
+        assert_eq!(play.get("become").and_then(|v| v.as_bool()), Some(true));
+        let Some(Yaml::List(tasks)) = play.get("tasks") else {
+            panic!("tasks should be a list");
+        };
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(
+            tasks[0].get("apt").and_then(|apt| apt.get("name")),
+            Some(&Yaml::List(vec![string("nginx"), string("curl")]))
+        );
+        assert_eq!(
+            tasks[1].get("copy").and_then(|copy| copy.get("content")),
+            Some(&string("hello\nworld\n"))
+        );
+    }
+
+    #[test]
+    fn parse_scalars_and_anchors() {
+        let yaml = parse_yaml(
+            r#"
+defaults: &defaults
+  mode: 0644
+  owner:
+files:
+  - name: motd
+  - *defaults
+"#,
+        )
+        .unwrap();
+        let defaults = yaml.get("defaults").unwrap();
+        assert_eq!(defaults.get("mode"), Some(&string("0644")));
+        assert_eq!(defaults.get("owner"), Some(&Yaml::Null));
+        let Some(Yaml::List(files)) = yaml.get("files") else {
+            panic!("files should be a list");
+        };
+        assert_eq!(&files[1], defaults);
+        assert!(parse_yaml("key: [unclosed").is_err());
+    }
+}